@@ -1,17 +1,29 @@
 use std::sync::{Arc, RwLock};
 
 use crate::channel::reactive_receiver;
+use crate::navigation::NavigationScheme;
 use crate::orbit_controls::CameraController;
 use crate::panels::SettingsPanel;
-use crate::panels::{DatasetPanel, PresetsPanel, ScenePanel, StatsPanel, TracingPanel};
+use crate::panels::{
+    DatasetPanel, EditPanel, FloatersPanel, ModelsPanel, PresetsPanel, ScenePanel, SplatStatsPanel,
+    StatsPanel, TracingPanel, TrainControlsPanel,
+};
 use brush_dataset::Dataset;
 use brush_process::data_source::DataSource;
 use brush_process::process_loop::{
     start_process, ControlMessage, ProcessArgs, ProcessMessage, RunningProcess,
 };
-use brush_render::camera::Camera;
-use brush_train::scene::SceneView;
-use burn_wgpu::WgpuDevice;
+use brush_render::{
+    camera::Camera,
+    color_lut::ColorLut,
+    crop::{CropBox, CropVolume},
+    gaussian_splats::Splats,
+    render::SolidRender,
+    tone_map::ToneMapping,
+};
+use brush_train::{image::is_hdr, scene::SceneView, train::TrainTweaks};
+use burn::tensor::{Bool, Tensor};
+use burn_wgpu::{Wgpu, WgpuDevice};
 use eframe::egui;
 use egui_tiles::SimplificationOptions;
 use egui_tiles::{Container, Tile, TileId, Tiles};
@@ -98,10 +110,18 @@ fn parse_search(search: &str) -> HashMap<String, String> {
     params
 }
 
+// How often the splats of a training run are saved to the browser session.
+#[cfg(target_family = "wasm")]
+const SESSION_SAVE_INTERVAL: web_time::Duration = web_time::Duration::from_secs(60);
+
 pub struct App {
     tree: egui_tiles::Tree<PaneType>,
     datasets: Option<TileId>,
     tree_ctx: AppTree,
+    #[cfg(target_family = "wasm")]
+    imports: tokio::sync::mpsc::UnboundedReceiver<crate::web_session::Import>,
+    #[cfg(target_family = "wasm")]
+    last_session_save: web_time::Instant,
 }
 
 // TODO: Bit too much random shared state here.
@@ -112,13 +132,39 @@ pub struct AppContext {
     pub controls: CameraController,
     pub model_local_to_world: Affine3A,
     pub device: WgpuDevice,
+    /// Splats hidden in the viewer only, eg. to preview a cleanup.
+    pub preview_hidden: Option<Tensor<Wgpu, 1, Bool>>,
+    /// Splats hidden in the viewer, and removed when exporting.
+    pub removed: Option<Tensor<Wgpu, 1, Bool>>,
+    /// Color grading applied to the rendered view.
+    pub color_lut: Option<ColorLut>,
+    /// Tone mapping applied to the rendered view, before any color grading. Turned on when
+    /// loading a dataset with HDR images.
+    pub tone_mapping: Option<ToneMapping>,
+    /// Box the splats are cropped to in the viewer and when exporting.
+    pub crop_box: Option<CropBox>,
+    /// Render the splats as solid surfaces in the viewer.
+    pub solid: Option<SolidRender>,
+    /// Splats selected for editing, highlighted in the viewer.
+    pub selection: Option<Tensor<Wgpu, 1, Bool>>,
+    /// Splats shown instead of the loaded splats, eg. when composing several models.
+    pub scene_splats: Option<Splats<Wgpu>>,
+    /// Whether dragging in the viewer draws a lasso to select splats, instead of moving the camera.
+    pub lasso_select: bool,
 
     loading: bool,
     training: bool,
+    train_paused: bool,
 
     ctx: egui::Context,
     running_process: Option<RunningProcess>,
     cam_settings: CameraSettings,
+    // Messages sent by the panels themselves, eg. when splats are edited.
+    local_messages: Vec<ProcessMessage>,
+    view_generation: u32,
+    // Whether the running process belongs to the saved browser session, so its splats are saved.
+    #[cfg(target_family = "wasm")]
+    saves_session: bool,
 }
 
 #[derive(Clone)]
@@ -131,7 +177,7 @@ impl AppContext {
     fn new(device: WgpuDevice, ctx: egui::Context, cam_settings: &CameraSettings) -> Self {
         let model_transform = Affine3A::IDENTITY;
 
-        let controls = CameraController::new(cam_settings.radius);
+        let controls = CameraController::new(cam_settings.radius, NavigationScheme::load(&ctx));
 
         // Camera position will be controlled by the orbit controls.
         let camera = Camera::new(
@@ -148,12 +194,26 @@ impl AppContext {
             model_local_to_world: model_transform,
             device,
             ctx,
+            preview_hidden: None,
+            removed: None,
+            color_lut: None,
+            tone_mapping: None,
+            crop_box: None,
+            solid: None,
+            selection: None,
+            scene_splats: None,
+            lasso_select: false,
             view_aspect: None,
             loading: false,
             training: false,
+            train_paused: false,
             dataset: Dataset::empty(),
             running_process: None,
             cam_settings: cam_settings.clone(),
+            local_messages: vec![],
+            view_generation: 0,
+            #[cfg(target_family = "wasm")]
+            saves_session: false,
         }
     }
 
@@ -189,8 +249,21 @@ impl AppContext {
     }
 
     pub fn connect_to(&mut self, process: RunningProcess) {
-        // reset context & view.
+        // reset context & view. The color grading is a viewer setting, so keep it.
+        let color_lut = self.color_lut.take();
         *self = Self::new(self.device.clone(), self.ctx.clone(), &self.cam_settings);
+        self.color_lut = color_lut;
+
+        #[cfg(not(target_family = "wasm"))]
+        if let Some(path) = process.start_args.process_config.color_lut.as_ref() {
+            match std::fs::read_to_string(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|cube| ColorLut::from_cube(&cube))
+            {
+                Ok(lut) => self.color_lut = Some(lut),
+                Err(e) => log::error!("Failed to load color LUT {path}: {e}"),
+            }
+        }
 
         // Convert the receiver to a "reactive" receiver that wakes up the UI.
         self.running_process = Some(RunningProcess {
@@ -199,19 +272,92 @@ impl AppContext {
         });
     }
 
-    pub(crate) fn control_message(&self, msg: ControlMessage) {
+    pub(crate) fn control_message(&mut self, msg: ControlMessage) {
+        if let ControlMessage::Paused(paused) = msg {
+            self.train_paused = paused;
+        }
         if let Some(process) = self.running_process.as_ref() {
             let _ = process.control.send(msg);
         }
     }
 
+    /// Send a message to all panels, as if it came from the running process.
+    pub(crate) fn send_local(&mut self, message: ProcessMessage) {
+        self.local_messages.push(message);
+    }
+
+    /// Render the view again, eg. after changing how splats are shown.
+    pub fn refresh_view(&mut self) {
+        self.view_generation = self.view_generation.wrapping_add(1);
+    }
+
+    pub(crate) fn view_generation(&self) -> u32 {
+        self.view_generation
+    }
+
     pub fn training(&self) -> bool {
         self.training
     }
 
+    pub fn train_paused(&self) -> bool {
+        self.train_paused
+    }
+
     pub fn loading(&self) -> bool {
         self.loading
     }
+
+    /// The volume splats are cropped to, as configured for the running process.
+    pub fn crop_volume(&self) -> CropVolume {
+        self.running_process
+            .as_ref()
+            .map(|p| CropVolume::new(p.start_args.process_config.crop.clone()))
+            .unwrap_or_default()
+    }
+
+    /// The splats removed from `splats`, if the removed splats still match it.
+    pub fn removed_splats(&self, splats: &Splats<Wgpu>) -> Option<Tensor<Wgpu, 1, Bool>> {
+        self.removed
+            .clone()
+            .filter(|r| r.dims()[0] == splats.num_splats())
+    }
+
+    /// Hide the splats that shouldn't be shown in the viewer.
+    pub fn filter_view_splats(&self, splats: &Splats<Wgpu>) -> Splats<Wgpu> {
+        let mut splats = splats
+            .with_crop(&self.crop_volume())
+            .with_crop_box(self.crop_box)
+            .with_solid(self.solid);
+        let hidden = [self.removed.clone(), self.preview_hidden.clone()];
+        // Masks are stale when the splats changed since, eg. during training.
+        for hidden in hidden.into_iter().flatten() {
+            if hidden.dims()[0] == splats.num_splats() {
+                splats = splats.with_hidden(hidden);
+            }
+        }
+        if let Some(selection) = self
+            .selection
+            .clone()
+            .filter(|s| s.dims()[0] == splats.num_splats())
+        {
+            splats = splats.with_highlighted(selection);
+        }
+        splats
+    }
+
+    /// The learning rates and loss weights the running process started training with.
+    pub fn start_tweaks(&self) -> Option<TrainTweaks> {
+        self.running_process
+            .as_ref()
+            .map(|p| p.start_args.train_config.tweaks())
+    }
+
+    /// The SH degree exported splats are converted to, if any.
+    pub fn export_sh_degree(&self) -> Option<u32> {
+        self.running_process
+            .as_ref()
+            .and_then(|p| p.start_args.process_config.export_sh_degree)
+    }
 }
 
 pub struct AppCreateCb {
@@ -262,6 +408,31 @@ impl App {
             }
         }
 
+        // Without tracy, still install the sync layer, so the render stats can time the kernels.
+        #[cfg(all(not(feature = "tracy"), not(target_family = "wasm")))]
+        {
+            use tracing_subscriber::layer::SubscriberExt;
+
+            tracing::subscriber::set_global_default(
+                tracing_subscriber::registry().with(sync_span::SyncLayer::<
+                    burn_jit::JitBackend<burn_wgpu::WgpuRuntime, f32, i32, u32>,
+                >::new(device.clone())),
+            )
+            .expect("Failed to set tracing subscriber");
+        }
+
+        // Watch the kernel sources for changes and swap in the new kernels live.
+        #[cfg(all(feature = "shader-hot-reload", not(target_family = "wasm")))]
+        {
+            let ctx = cc.egui_ctx.clone();
+            brush_kernel::hot_reload::watch_shaders(
+                concat!(env!("CARGO_MANIFEST_DIR"), "/.."),
+                move || {
+                    ctx.request_repaint();
+                },
+            );
+        }
+
         #[cfg(target_family = "wasm")]
         let start_uri = web_sys::window().and_then(|w| w.location().search().ok());
         #[cfg(not(target_family = "wasm"))]
@@ -303,14 +474,21 @@ impl App {
             ];
             let loading_pane = tiles.insert_tab_tile(loading_subs);
 
-            #[allow(unused_mut)]
-            let mut sides = vec![
-                loading_pane,
+            let stats_subs = vec![
                 tiles.insert_pane(Box::new(StatsPanel::new(
                     device.clone(),
                     state.adapter.get_info(),
                 ))),
+                tiles.insert_pane(Box::new(TrainControlsPanel::new())),
+                tiles.insert_pane(Box::new(SplatStatsPanel::new())),
+                tiles.insert_pane(Box::new(FloatersPanel::new())),
+                tiles.insert_pane(Box::new(EditPanel::new())),
+                tiles.insert_pane(Box::new(ModelsPanel::new())),
             ];
+            let stats_pane = tiles.insert_tab_tile(stats_subs);
+
+            #[allow(unused_mut)]
+            let mut sides = vec![loading_pane, stats_pane];
 
             if cfg!(feature = "tracing") {
                 sides.push(tiles.insert_pane(Box::new(TracingPanel::default())));
@@ -351,10 +529,36 @@ impl App {
                 .connect_to(running);
         }
 
+        #[cfg(target_family = "wasm")]
+        let imports = {
+            let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+            crate::web_session::listen_for_drops(cc.egui_ctx.clone(), sender.clone());
+
+            // Pick up where the last session left off, unless asked to load something else.
+            if url.is_none() {
+                let ctx = cc.egui_ctx.clone();
+                tokio_with_wasm::alias::task::spawn(async move {
+                    match crate::web_session::load_session().await {
+                        Ok(Some(files)) => {
+                            let _ = sender.send(crate::web_session::Import { files, save: false });
+                            ctx.request_repaint();
+                        }
+                        Ok(None) => {}
+                        Err(e) => log::warn!("Failed to restore the last session: {e:#}"),
+                    }
+                });
+            }
+            receiver
+        };
+
         Self {
             tree,
             tree_ctx,
             datasets: None,
+            #[cfg(target_family = "wasm")]
+            imports,
+            #[cfg(target_family = "wasm")]
+            last_session_save: web_time::Instant::now(),
         }
     }
 }
@@ -364,18 +568,43 @@ impl App {
     fn receive_messages(&mut self) {
         let mut context = self.tree_ctx.context.write().expect("Lock poisoned");
 
-        let Some(process) = context.running_process.as_mut() else {
-            return;
-        };
+        #[cfg(target_family = "wasm")]
+        while let Ok(import) = self.imports.try_recv() {
+            if import.save {
+                let files = import.files.clone();
+                tokio_with_wasm::alias::task::spawn(async move {
+                    if let Err(e) = crate::web_session::save_import(files).await {
+                        log::warn!("Failed to save the session: {e:#}");
+                    }
+                });
+            }
+            let device = context.device.clone();
+            context.connect_to(start_process(
+                DataSource::Files(import.files),
+                ProcessArgs::default(),
+                device,
+            ));
+            context.saves_session = true;
+            self.last_session_save = web_time::Instant::now();
+        }
 
-        let mut messages = vec![];
-        while let Ok(message) = process.messages.try_recv() {
-            messages.push(message);
+        let mut messages = std::mem::take(&mut context.local_messages);
+        if let Some(process) = context.running_process.as_mut() {
+            while let Ok(message) = process.messages.try_recv() {
+                messages.push(message);
+            }
         }
 
         for message in messages {
             match message {
-                ProcessMessage::Dataset { data: _ } => {
+                ProcessMessage::Dataset { ref data } => {
+                    // Splats trained on HDR images have linear colors, which need tone mapping.
+                    if context.tone_mapping.is_none()
+                        && data.train.views.first().is_some_and(|v| is_hdr(&v.image))
+                    {
+                        context.tone_mapping = Some(ToneMapping::default());
+                    }
+
                     // Show the dataset panel if we've loaded one.
                     if self.datasets.is_none() {
                         let pane_id = self.tree.tiles.insert_pane(Box::new(DatasetPanel::new()));
@@ -396,6 +625,25 @@ impl App {
                 ProcessMessage::DoneLoading { training: _ } => {
                     context.loading = false;
                 }
+                #[cfg(target_family = "wasm")]
+                ProcessMessage::TrainStep { ref splats, .. } => {
+                    if context.saves_session
+                        && self.last_session_save.elapsed() > SESSION_SAVE_INTERVAL
+                    {
+                        self.last_session_save = web_time::Instant::now();
+                        let splats = *splats.clone();
+                        tokio_with_wasm::alias::task::spawn(async move {
+                            let saved =
+                                match brush_dataset::splat_export::splat_to_ply(splats).await {
+                                    Ok(ply) => crate::web_session::save_splats(ply).await,
+                                    Err(e) => Err(e),
+                                };
+                            if let Err(e) = saved {
+                                log::warn!("Failed to save trained splats: {e:#}");
+                            }
+                        });
+                    }
+                }
                 _ => (),
             }
 
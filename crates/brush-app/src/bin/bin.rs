@@ -28,7 +28,52 @@ fn main() -> Result<(), clap::Error> {
         runtime.block_on(async {
             env_logger::init();
 
-            if args.with_viewer {
+            if let Some(brush_cli::Command::Train(train)) = args.command {
+                let mut process_args = train.process;
+                std::fs::create_dir_all(&train.out).expect("Failed to create output directory");
+                process_args.process_config.export_path = Some(train.out);
+
+                let device = brush_render::burn_init_setup().await;
+                let mut process = start_process(train.source, process_args, device);
+                if let Some(addr) = train.serve {
+                    let interval = std::time::Duration::from_secs(train.serve_every);
+                    process = brush_cli::remote::serve_remote(process, addr, interval)
+                        .await
+                        .expect("Failed to start remote viewer server");
+                    log::info!("Serving training to remote viewers on ws://{addr}");
+                }
+                if train.json {
+                    brush_cli::json_log::process_json(process, train.log_every).await;
+                } else {
+                    brush_cli::ui::process_ui(process).await;
+                }
+            } else if let Some(brush_cli::Command::BenchTrain(config)) = args.command {
+                let device = brush_render::burn_init_setup().await;
+                let report = brush_process::bench::bench_train(&config, &device).await;
+                let json = report
+                    .to_json()
+                    .expect("Failed to serialize benchmark report");
+                let written = config.bench_output.as_ref().map(|path| {
+                    std::fs::write(path, &json).map_err(|e| {
+                        log::error!("Failed to write benchmark report to {path}: {e}");
+                    })
+                });
+                // Print the report when it isn't written to a file, so a run isn't lost when
+                // the write fails.
+                if !matches!(written, Some(Ok(()))) {
+                    println!("{json}");
+                }
+            } else if let Some(brush_cli::Command::RenderPath(render)) = args.command {
+                let device = brush_render::burn_init_setup().await;
+                brush_cli::render_path::render_path_cli(&render, device)
+                    .await
+                    .expect("Failed to render camera path");
+            } else if let Some(brush_cli::Command::ExportColmap(export)) = args.command {
+                let device = brush_render::burn_init_setup().await;
+                brush_cli::export_colmap::export_colmap_cli(export, device)
+                    .await
+                    .expect("Failed to export COLMAP model");
+            } else if args.with_viewer {
                 let icon = eframe::icon_data::from_png_bytes(
                     &include_bytes!("../../assets/icon-256.png")[..],
                 )
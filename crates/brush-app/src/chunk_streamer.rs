@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use brush_dataset::{
+    brush_vfs::BrushVfs,
+    splat_chunks::{ChunkIndex, ChunkResidency},
+    splat_import,
+};
+use brush_render::{camera::Camera, gaussian_splats::Splats};
+use burn_wgpu::{Wgpu, WgpuDevice};
+use tokio::sync::oneshot::{self, Receiver};
+use tokio_stream::StreamExt;
+use tokio_with_wasm::alias as tokio_wasm;
+
+// Load a few chunks at a time, so the closest chunks are loaded first when the camera moves.
+const MAX_LOADING: usize = 2;
+
+async fn load_chunk(
+    mut vfs: BrushVfs,
+    path: PathBuf,
+    device: WgpuDevice,
+) -> anyhow::Result<Splats<Wgpu>> {
+    let reader = vfs.open_path(&path).await?;
+    let stream = splat_import::load_splat_from_ply(reader, None, device);
+    let mut stream = std::pin::pin!(stream);
+    let mut splats = None;
+    while let Some(message) = stream.next().await {
+        splats = Some(message?.splats);
+    }
+    splats.with_context(|| format!("No splats in chunk {path:?}"))
+}
+
+/// Streams the chunks of a chunked scene in and out around the camera.
+pub(crate) struct ChunkStreamer {
+    residency: ChunkResidency<Wgpu>,
+    vfs: BrushVfs,
+    device: WgpuDevice,
+    queued: Vec<usize>,
+    pending: Vec<(usize, Receiver<anyhow::Result<Splats<Wgpu>>>)>,
+}
+
+impl ChunkStreamer {
+    pub(crate) fn new(index: ChunkIndex, vfs: BrushVfs, budget: usize, device: WgpuDevice) -> Self {
+        Self {
+            residency: ChunkResidency::new(index, budget),
+            vfs,
+            device,
+            queued: vec![],
+            pending: vec![],
+        }
+    }
+
+    pub(crate) fn is_loading(&self) -> bool {
+        !self.pending.is_empty() || !self.queued.is_empty()
+    }
+
+    pub(crate) fn num_resident_splats(&self) -> usize {
+        self.residency.num_resident_splats()
+    }
+
+    /// Update which chunks are loaded for the camera. Returns the new splats to show when the
+    /// loaded chunks changed.
+    pub(crate) fn update(&mut self, camera: &Camera) -> Option<Option<Splats<Wgpu>>> {
+        // Chunks that were queued but aren't wanted anymore are handed back unloaded, so
+        // they can be requested again later.
+        for chunk in self.queued.drain(..) {
+            self.residency.finish_load(chunk, None);
+        }
+        self.queued = self.residency.update(camera);
+
+        self.pending
+            .retain_mut(|(chunk, rec)| match rec.try_recv() {
+                Ok(result) => {
+                    let splats = result
+                        .inspect_err(|e| log::error!("Failed to load chunk: {e}"))
+                        .ok();
+                    self.residency.finish_load(*chunk, splats);
+                    false
+                }
+                Err(oneshot::error::TryRecvError::Empty) => true,
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.residency.finish_load(*chunk, None);
+                    false
+                }
+            });
+
+        while self.pending.len() < MAX_LOADING && !self.queued.is_empty() {
+            let chunk = self.queued.remove(0);
+            let path = PathBuf::from(&self.residency.index().chunks[chunk].path);
+            let (send, rec) = oneshot::channel();
+            let vfs = self.vfs.clone();
+            let device = self.device.clone();
+            tokio_wasm::task::spawn(async move {
+                let _ = send.send(load_chunk(vfs, path, device).await);
+            });
+            self.pending.push((chunk, rec));
+        }
+
+        self.residency.is_dirty().then(|| self.residency.splats())
+    }
+}
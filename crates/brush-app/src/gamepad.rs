@@ -0,0 +1,70 @@
+//! Gamepad input for the viewer camera, on desktop.
+
+use glam::Vec2;
+
+// Stick positions below this are noise from worn sticks.
+const DEADZONE: f32 = 0.15;
+
+/// The sticks and triggers of a gamepad, each in -1..1.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct GamepadState {
+    /// Left stick, moves the camera. +y is pushed forward.
+    pub(crate) move_stick: Vec2,
+    /// Right stick, looks or orbits. +y is pushed forward.
+    pub(crate) look_stick: Vec2,
+    /// Right trigger minus left trigger, moves up or down.
+    pub(crate) rise: f32,
+}
+
+impl GamepadState {
+    pub(crate) fn is_idle(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn deadzone(value: f32) -> f32 {
+    if value.abs() < DEADZONE {
+        0.0
+    } else {
+        // Start from zero at the edge of the deadzone.
+        value.signum() * (value.abs() - DEADZONE) / (1.0 - DEADZONE)
+    }
+}
+
+pub(crate) struct Gamepads {
+    gilrs: gilrs::Gilrs,
+}
+
+impl Gamepads {
+    /// Connect to the gamepads of the system, if there's support for them.
+    pub(crate) fn new() -> Option<Self> {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(Self { gilrs }),
+            Err(e) => {
+                log::warn!("Gamepads aren't supported: {e}");
+                None
+            }
+        }
+    }
+
+    /// The state of the first connected gamepad, if any.
+    pub(crate) fn poll(&mut self) -> Option<GamepadState> {
+        // Events have to be drained for the gamepad state to update.
+        while self.gilrs.next_event().is_some() {}
+
+        let (_, pad) = self.gilrs.gamepads().next()?;
+        let stick = |x, y| {
+            let stick = Vec2::new(pad.value(x), pad.value(y));
+            Vec2::new(deadzone(stick.x), deadzone(stick.y))
+        };
+        let trigger = |button| pad.button_data(button).map_or(0.0, |b| b.value());
+
+        Some(GamepadState {
+            move_stick: stick(gilrs::Axis::LeftStickX, gilrs::Axis::LeftStickY),
+            look_stick: stick(gilrs::Axis::RightStickX, gilrs::Axis::RightStickY),
+            rise: deadzone(
+                trigger(gilrs::Button::RightTrigger2) - trigger(gilrs::Button::LeftTrigger2),
+            ),
+        })
+    }
+}
@@ -1,10 +1,16 @@
 #![recursion_limit = "256"]
 
+#[cfg(not(target_family = "wasm"))]
+mod gamepad;
+mod navigation;
 mod orbit_controls;
 mod panels;
 
 mod app;
 mod channel;
+mod chunk_streamer;
+#[cfg(target_family = "wasm")]
+mod web_session;
 
 pub use app::*;
 use burn::backend::Autodiff;
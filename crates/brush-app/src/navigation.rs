@@ -0,0 +1,224 @@
+//! How the viewer camera is navigated, with the mouse, keyboard, touch or a gamepad.
+//!
+//! The scheme is a viewer setting, saved with the rest of the UI state so it's the same the next
+//! time the app starts.
+
+use egui::Key;
+use serde::{Deserialize, Serialize};
+
+/// What dragging in the viewer does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum NavigationMode {
+    /// Drag to orbit around the focus point, the keys fly along the view.
+    #[default]
+    Orbit,
+    /// Drag to look around, the keys fly along the view.
+    Fly,
+    /// Drag to look around, the keys walk level with the horizon, and up and down move
+    /// straight up and down.
+    Fps,
+}
+
+impl NavigationMode {
+    pub(crate) const ALL: [Self; 3] = [Self::Orbit, Self::Fly, Self::Fps];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Orbit => "Orbit",
+            Self::Fly => "Fly",
+            Self::Fps => "First person",
+        }
+    }
+}
+
+/// A camera action bound to a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum NavAction {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+    RollLeft,
+    RollRight,
+    ResetRoll,
+}
+
+impl NavAction {
+    pub(crate) const ALL: [Self; 9] = [
+        Self::Forward,
+        Self::Back,
+        Self::Left,
+        Self::Right,
+        Self::Up,
+        Self::Down,
+        Self::RollLeft,
+        Self::RollRight,
+        Self::ResetRoll,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Forward => "Forward",
+            Self::Back => "Back",
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::RollLeft => "Roll left",
+            Self::RollRight => "Roll right",
+            Self::ResetRoll => "Reset roll",
+        }
+    }
+
+    // The arrow keys always move, whatever the movement keys are bound to.
+    fn arrow_key(&self) -> Option<Key> {
+        match self {
+            Self::Forward => Some(Key::ArrowUp),
+            Self::Back => Some(Key::ArrowDown),
+            Self::Left => Some(Key::ArrowLeft),
+            Self::Right => Some(Key::ArrowRight),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct NavigationScheme {
+    pub(crate) mode: NavigationMode,
+    /// The key of every action, in the order of [`NavAction::ALL`].
+    keys: [Key; 9],
+    /// Scales how fast dragging and the sticks turn the camera.
+    pub(crate) look_sensitivity: f32,
+    /// Scales how fast the keys and sticks move the camera.
+    pub(crate) move_speed: f32,
+    /// Dragging or pushing the stick up looks down.
+    pub(crate) invert_y: bool,
+}
+
+impl Default for NavigationScheme {
+    fn default() -> Self {
+        Self {
+            mode: NavigationMode::Orbit,
+            keys: [
+                Key::W,
+                Key::S,
+                Key::A,
+                Key::D,
+                Key::E,
+                Key::Q,
+                Key::Z,
+                Key::C,
+                Key::X,
+            ],
+            look_sensitivity: 1.0,
+            move_speed: 1.0,
+            invert_y: false,
+        }
+    }
+}
+
+fn scheme_id() -> egui::Id {
+    egui::Id::new("navigation_scheme")
+}
+
+impl NavigationScheme {
+    /// The scheme saved with the UI state, or the default scheme.
+    pub(crate) fn load(ctx: &egui::Context) -> Self {
+        ctx.data_mut(|d| d.get_persisted(scheme_id()))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn save(&self, ctx: &egui::Context) {
+        ctx.data_mut(|d| d.insert_persisted(scheme_id(), self.clone()));
+    }
+
+    pub(crate) fn key(&self, action: NavAction) -> Key {
+        self.keys[action as usize]
+    }
+
+    pub(crate) fn set_key(&mut self, action: NavAction, key: Key) {
+        self.keys[action as usize] = key;
+    }
+
+    /// Whether the key of an action is held down.
+    pub(crate) fn is_down(&self, action: NavAction, input: &egui::InputState) -> bool {
+        input.key_down(self.key(action)) || action.arrow_key().is_some_and(|k| input.key_down(k))
+    }
+}
+
+/// Edits a navigation scheme, and saves it when it changes. Click the key of an action, then
+/// press the new key to rebind it.
+pub(crate) struct NavigationEditor {
+    rebinding: Option<NavAction>,
+}
+
+impl NavigationEditor {
+    pub(crate) fn new() -> Self {
+        Self { rebinding: None }
+    }
+
+    pub(crate) fn ui(&mut self, ui: &mut egui::Ui, scheme: &mut NavigationScheme) {
+        let before = scheme.clone();
+
+        ui.horizontal(|ui| {
+            for mode in NavigationMode::ALL {
+                ui.selectable_value(&mut scheme.mode, mode, mode.name());
+            }
+        });
+        ui.add(egui::Slider::new(&mut scheme.look_sensitivity, 0.1..=4.0).text("Look speed"));
+        ui.add(
+            egui::Slider::new(&mut scheme.move_speed, 0.1..=10.0)
+                .logarithmic(true)
+                .text("Move speed"),
+        );
+        ui.checkbox(&mut scheme.invert_y, "Invert vertical look");
+
+        ui.separator();
+        egui::Grid::new("navigation_keys")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for action in NavAction::ALL {
+                    ui.label(action.name());
+                    let label = if self.rebinding == Some(action) {
+                        "Press a key…"
+                    } else {
+                        scheme.key(action).name()
+                    };
+                    if ui.button(label).clicked() {
+                        self.rebinding = Some(action);
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if let Some(action) = self.rebinding {
+            let pressed = ui.input(|r| {
+                r.events.iter().find_map(|e| match e {
+                    egui::Event::Key {
+                        key, pressed: true, ..
+                    } => Some(*key),
+                    _ => None,
+                })
+            });
+            match pressed {
+                Some(Key::Escape) => self.rebinding = None,
+                Some(key) => {
+                    scheme.set_key(action, key);
+                    self.rebinding = None;
+                }
+                None => {}
+            }
+        }
+
+        if ui.button("Reset to defaults").clicked() {
+            *scheme = NavigationScheme::default();
+        }
+
+        if *scheme != before {
+            scheme.save(ui.ctx());
+        }
+    }
+}
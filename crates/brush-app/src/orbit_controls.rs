@@ -3,10 +3,15 @@ use core::f32;
 use egui::Response;
 use glam::{Quat, Vec2, Vec3};
 
+#[cfg(not(target_family = "wasm"))]
+use crate::gamepad::GamepadState;
+use crate::navigation::{NavAction, NavigationMode, NavigationScheme};
+
 pub struct CameraController {
     pub position: Vec3,
     pub rotation: Quat,
     pub focus_distance: f32,
+    pub(crate) scheme: NavigationScheme,
     roll: Quat,
     fly_velocity: Vec3,
     orbit_velocity: Vec2,
@@ -47,10 +52,11 @@ fn exp_lerp3(a: Vec3, b: Vec3, dt: f32, lambda: f32) -> Vec3 {
 }
 
 impl CameraController {
-    pub fn new(start_focus_distance: f32) -> Self {
+    pub(crate) fn new(start_focus_distance: f32, scheme: NavigationScheme) -> Self {
         Self {
             position: -Vec3::Z * start_focus_distance,
             rotation: Quat::IDENTITY,
+            scheme,
             roll: Quat::IDENTITY,
             focus_distance: start_focus_distance,
             fly_velocity: Vec3::ZERO,
@@ -58,27 +64,69 @@ impl CameraController {
         }
     }
 
+    // Rotate the camera around itself.
+    fn look(&mut self, yaw: f32, pitch: f32) {
+        let yaw = Quat::from_axis_angle(self.roll * Vec3::NEG_Y, -yaw);
+        let pitch = Quat::from_rotation_x(-pitch);
+        self.rotation = yaw * self.rotation * pitch;
+    }
+
+    // Move sideways and up, by a distance in pixels of a viewport of `size`.
+    fn pan(&mut self, delta: egui::Vec2, size: egui::Vec2) {
+        let drag_mult = self.focus_distance / size.x.max(size.y);
+        self.position -= self.rotation * Vec3::X * delta.x * drag_mult;
+        self.position += self.rotation * Vec3::NEG_Y * delta.y * drag_mult;
+    }
+
+    // Move towards the focus point, scaling the distance to it by `factor`.
+    fn zoom(&mut self, factor: f32) {
+        let forward = self.rotation * Vec3::Z;
+        let pivot = self.position + forward * self.focus_distance;
+        self.focus_distance = (self.focus_distance * factor).max(0.01);
+        self.position = pivot - forward * self.focus_distance;
+    }
+
+    // Speed up the camera towards `dir` in camera space.
+    fn accelerate(&mut self, dir: Vec3, delta_time: f32) {
+        let move_speed = 30.0 * self.scheme.move_speed;
+        self.fly_velocity = exp_lerp3(self.fly_velocity, dir * move_speed, delta_time, 0.8);
+    }
+
     pub fn tick(&mut self, response: &Response, ui: &egui::Ui) {
         let delta_time = ui.input(|r| r.predicted_dt);
+        let mode = self.scheme.mode;
+
+        // Two finger gestures: pinch to zoom, and drag to pan. The first finger also drags the
+        // pointer, so don't look around at the same time.
+        let multi_touch = ui
+            .input(|r| r.multi_touch())
+            .filter(|t| t.num_touches >= 2 && response.hovered());
+        if let Some(touch) = &multi_touch {
+            self.pan(touch.translation_delta, response.rect.size());
+            if touch.zoom_delta > 0.0 {
+                self.zoom(1.0 / touch.zoom_delta);
+            }
+        }
+        let dragging = multi_touch.is_none();
 
-        let lmb = response.dragged_by(egui::PointerButton::Primary);
-        let rmb = response.dragged_by(egui::PointerButton::Secondary);
-        let mmb = response.dragged_by(egui::PointerButton::Middle);
+        let lmb = dragging && response.dragged_by(egui::PointerButton::Primary);
+        let rmb = dragging && response.dragged_by(egui::PointerButton::Secondary);
+        let mmb = dragging && response.dragged_by(egui::PointerButton::Middle);
 
         let look_pan = mmb || lmb && ui.input(|r| r.modifiers.ctrl);
-        let look_fps = rmb || lmb && ui.input(|r| r.key_down(egui::Key::Space));
+        let look_fps = rmb
+            || lmb && (mode != NavigationMode::Orbit || ui.input(|r| r.key_down(egui::Key::Space)));
         let look_orbit = lmb;
 
-        let mouselook_speed = 0.002;
+        let mouselook_speed = 0.002 * self.scheme.look_sensitivity;
+        let invert = if self.scheme.invert_y { -1.0 } else { 1.0 };
 
-        let right = self.rotation * Vec3::X;
-        let up = self.rotation * Vec3::NEG_Y;
         let forward = self.rotation * Vec3::Z;
 
         if response.hovered() {
             if ui.input(|r| r.modifiers.ctrl) {
                 ui.ctx().set_cursor_icon(egui::CursorIcon::Move);
-            } else if ui.input(|r| r.key_down(egui::Key::Space)) {
+            } else if mode != NavigationMode::Orbit || ui.input(|r| r.key_down(egui::Key::Space)) {
                 ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
             } else {
                 ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
@@ -86,138 +134,158 @@ impl CameraController {
         }
 
         if look_pan {
-            let drag_mult = self.focus_distance / response.rect.width().max(response.rect.height());
-            self.position -= right * response.drag_delta().x * drag_mult;
-            self.position += up * response.drag_delta().y * drag_mult;
-
+            self.pan(response.drag_delta(), response.rect.size());
             ui.ctx().set_cursor_icon(egui::CursorIcon::Move);
         } else if look_fps {
             let axis = response.drag_delta();
-            let yaw = Quat::from_axis_angle(self.roll * Vec3::NEG_Y, -axis.x * mouselook_speed);
-            let pitch = Quat::from_rotation_x(-axis.y * mouselook_speed);
-            self.rotation = yaw * self.rotation * pitch;
+            self.look(axis.x * mouselook_speed, axis.y * mouselook_speed * invert);
             ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
         } else if look_orbit {
             let delta_yaw = response.drag_delta().x * mouselook_speed;
-            let delta_pitch = response.drag_delta().y * mouselook_speed;
+            let delta_pitch = response.drag_delta().y * mouselook_speed * invert;
 
             self.orbit_velocity = glam::vec2(delta_yaw, delta_pitch);
             ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
         }
 
-        (self.position, self.rotation) = smooth_orbit(
-            self.position,
-            self.rotation,
-            self.roll,
-            self.orbit_velocity.x,
-            self.orbit_velocity.y,
-            self.focus_distance,
-        );
-
-        let fly_moment_lambda = 0.8;
-
-        let move_speed = 30.0
-            * if ui.input(|r| r.modifiers.shift) {
-                4.0
-            } else {
-                1.0
-            };
-
-        if ui.input(|r| r.key_down(egui::Key::W) || r.key_down(egui::Key::ArrowUp)) {
-            self.fly_velocity = exp_lerp3(
-                self.fly_velocity,
-                Vec3::Z * move_speed,
-                delta_time,
-                fly_moment_lambda,
-            );
-        }
-        if ui.input(|r| r.key_down(egui::Key::A) || r.key_down(egui::Key::ArrowLeft)) {
-            self.fly_velocity = exp_lerp3(
-                self.fly_velocity,
-                -Vec3::X * move_speed,
-                delta_time,
-                fly_moment_lambda,
-            );
-        }
-        if ui.input(|r| r.key_down(egui::Key::S) || r.key_down(egui::Key::ArrowDown)) {
-            self.fly_velocity = exp_lerp3(
-                self.fly_velocity,
-                -Vec3::Z * move_speed,
-                delta_time,
-                fly_moment_lambda,
-            );
-        }
-        if ui.input(|r| r.key_down(egui::Key::D) || r.key_down(egui::Key::ArrowRight)) {
-            self.fly_velocity = exp_lerp3(
-                self.fly_velocity,
-                Vec3::X * move_speed,
-                delta_time,
-                fly_moment_lambda,
+        if self.orbit_velocity != Vec2::ZERO {
+            (self.position, self.rotation) = smooth_orbit(
+                self.position,
+                self.rotation,
+                self.roll,
+                self.orbit_velocity.x,
+                self.orbit_velocity.y,
+                self.focus_distance,
             );
         }
 
-        if ui.input(|r| r.modifiers.alt) {
+        let boost = if ui.input(|r| r.modifiers.shift) {
+            4.0
         } else {
-            // Move _down_ with Q
-            if ui.input(|r| r.key_down(egui::Key::Q)) {
-                self.fly_velocity = exp_lerp3(
-                    self.fly_velocity,
-                    -Vec3::Y * move_speed,
-                    delta_time,
-                    fly_moment_lambda,
-                );
+            1.0
+        };
+
+        let scheme = self.scheme.clone();
+        let is_down = |action| ui.input(|r| scheme.is_down(action, r));
+        let moves = [
+            (NavAction::Forward, Vec3::Z),
+            (NavAction::Left, -Vec3::X),
+            (NavAction::Back, -Vec3::Z),
+            (NavAction::Right, Vec3::X),
+        ];
+        for (action, dir) in moves {
+            if is_down(action) {
+                self.accelerate(dir * boost, delta_time);
             }
-            // Move up with E
-            if ui.input(|r| r.key_down(egui::Key::E)) {
-                self.fly_velocity = exp_lerp3(
-                    self.fly_velocity,
-                    Vec3::Y * move_speed,
-                    delta_time,
-                    fly_moment_lambda,
-                );
+        }
+
+        // Alt + Q & E are left for other shortcuts.
+        if !ui.input(|r| r.modifiers.alt) {
+            if is_down(NavAction::Down) {
+                self.accelerate(-Vec3::Y * boost, delta_time);
+            }
+            if is_down(NavAction::Up) {
+                self.accelerate(Vec3::Y * boost, delta_time);
             }
         }
 
-        // Roll with alt + Q&E.
-        if ui.input(|r| r.key_down(egui::Key::Z)) {
-            let roll = Quat::from_axis_angle(forward, move_speed * 0.025 * delta_time);
+        let roll_speed = 30.0 * self.scheme.move_speed * boost * 0.025;
+        if is_down(NavAction::RollLeft) {
+            let roll = Quat::from_axis_angle(forward, roll_speed * delta_time);
             self.rotation = roll * self.rotation;
             self.roll = roll * self.roll;
         }
-        if ui.input(|r| r.key_down(egui::Key::X)) {
+        if is_down(NavAction::ResetRoll) {
             self.rotation = self.roll.inverse() * self.rotation;
             self.roll = Quat::IDENTITY;
         }
-        if ui.input(|r| r.key_down(egui::Key::C)) {
-            let roll = Quat::from_axis_angle(forward, -move_speed * 0.025 * delta_time);
+        if is_down(NavAction::RollRight) {
+            let roll = Quat::from_axis_angle(forward, -roll_speed * delta_time);
             self.rotation = roll * self.rotation;
             self.roll = roll * self.roll;
         }
 
         let delta = self.fly_velocity * delta_time;
+        let (right, up, forward) = if mode == NavigationMode::Fps {
+            // Walk level with the horizon, and move straight up and down.
+            let up = self.roll * Vec3::NEG_Y;
+            let forward = self.rotation * Vec3::Z;
+            let level = (forward - up * forward.dot(up)).normalize_or_zero();
+            (self.rotation * Vec3::X, up, level)
+        } else {
+            (
+                self.rotation * Vec3::X,
+                self.rotation * Vec3::NEG_Y,
+                self.rotation * Vec3::Z,
+            )
+        };
         self.position += delta.x * right + delta.y * up + delta.z * forward;
 
-        // Damp velocities towards zero.
+        // Damp velocities towards zero. Stop completely once the motion is invisible, so the
+        // camera comes to rest and the view isn't rendered again every frame.
         self.orbit_velocity = exp_lerp2(self.orbit_velocity, Vec2::ZERO, delta_time, 8.0);
         self.fly_velocity = exp_lerp3(self.fly_velocity, Vec3::ZERO, delta_time, 7.0);
+        if self.orbit_velocity.length() < 1e-6 {
+            self.orbit_velocity = Vec2::ZERO;
+        }
+        if self.fly_velocity.length() < 1e-4 {
+            self.fly_velocity = Vec3::ZERO;
+        }
 
         // Handle scroll wheel: move back, and adjust focus distance.
         let scrolled = ui.input(|r| r.smooth_scroll_delta.y);
         let scroll_speed = 0.001;
 
-        let old_pivot = self.position + self.rotation * Vec3::Z * self.focus_distance;
-
         // Scroll speed depends on how far zoomed out we are.
-        self.focus_distance -= scrolled * scroll_speed * self.focus_distance;
-        self.focus_distance = self.focus_distance.max(0.01);
+        if scrolled != 0.0 {
+            self.zoom(1.0 - scrolled * scroll_speed);
+        }
+    }
+
+    /// Move and look with a gamepad. The left stick and triggers move like the keys, the right
+    /// stick orbits or looks around depending on the navigation mode.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) fn apply_gamepad(&mut self, pad: &GamepadState, delta_time: f32) {
+        // Radians per second at full tilt.
+        let look_speed = 2.0 * self.scheme.look_sensitivity * delta_time;
+        let invert = if self.scheme.invert_y { -1.0 } else { 1.0 };
+        // Pushing the stick forward looks up, like dragging up.
+        let look = Vec2::new(pad.look_stick.x, -pad.look_stick.y * invert) * look_speed;
+
+        if look != Vec2::ZERO {
+            if self.scheme.mode == NavigationMode::Orbit {
+                (self.position, self.rotation) = smooth_orbit(
+                    self.position,
+                    self.rotation,
+                    self.roll,
+                    look.x,
+                    look.y,
+                    self.focus_distance,
+                );
+            } else {
+                self.look(look.x, look.y);
+            }
+        }
 
-        self.position = old_pivot - (self.rotation * Vec3::Z * self.focus_distance);
+        let dir = Vec3::new(pad.move_stick.x, pad.rise, pad.move_stick.y);
+        if dir != Vec3::ZERO {
+            self.accelerate(dir, delta_time);
+        }
     }
 
     pub fn local_to_world(&self) -> glam::Affine3A {
         glam::Affine3A::from_rotation_translation(self.rotation, self.position)
     }
 
+    /// Orbit around `point` from now on. The camera moves sideways so the point is in the
+    /// center of the view, keeping its distance along the view direction.
+    pub(crate) fn focus_on(&mut self, point: Vec3) {
+        let forward = self.rotation * Vec3::Z;
+        self.focus_distance = (point - self.position).dot(forward).max(0.01);
+        self.position = point - forward * self.focus_distance;
+        self.stop_movement();
+    }
+
     pub(crate) fn stop_movement(&mut self) {
         self.orbit_velocity = Vec2::ZERO;
         self.fly_velocity = Vec3::ZERO;
@@ -1,7 +1,24 @@
+use std::collections::HashMap;
+
 use crate::app::{AppContext, AppPanel};
 use brush_process::process_loop::ProcessMessage;
+use brush_render::gaussian_splats::Splats;
+use brush_render::tone_map::ToneMapping;
+use brush_train::image::{is_hdr, view_to_sample};
 use brush_train::scene::{Scene, SceneView, ViewImageType, ViewType};
-use egui::{pos2, Slider, TextureHandle, TextureOptions};
+use burn_wgpu::Wgpu;
+use egui::{pos2, vec2, Color32, ColorImage, Slider, TextureHandle, TextureOptions};
+use tokio::sync::oneshot::{self, Receiver};
+use tokio_with_wasm::alias as tokio_wasm;
+use web_time::{Duration, Instant};
+
+const THUMBNAIL_SIZE: f32 = 96.0;
+// Thumbnails are created as they scroll into view, a few per frame to keep the UI responsive.
+const THUMBNAILS_PER_FRAME: usize = 4;
+// Mean absolute error at which the heatmap is fully saturated.
+const HEATMAP_MAX_ERROR: f32 = 0.25;
+// How often the heatmap follows the splats while training.
+const HEATMAP_REFRESH: Duration = Duration::from_secs(1);
 
 struct SelectedView {
     index: usize,
@@ -26,9 +43,100 @@ impl SelectedView {
     }
 }
 
+struct RenderedHeatmap {
+    view_type: ViewType,
+    index: usize,
+    image: ColorImage,
+    psnr: f32,
+}
+
+struct Heatmap {
+    view_type: ViewType,
+    index: usize,
+    texture_handle: TextureHandle,
+    psnr: f32,
+}
+
+fn view_color_image(
+    view: &SceneView,
+    max_size: Option<u32>,
+    tone_mapping: Option<ToneMapping>,
+) -> ColorImage {
+    let thumbnail;
+    let image = if let Some(max_size) = max_size {
+        thumbnail = view.image.thumbnail(max_size, max_size);
+        &thumbnail
+    } else {
+        &*view.image
+    };
+    let img_size = [image.width() as usize, image.height() as usize];
+    if let Some(tone_mapping) = tone_mapping.filter(|_| is_hdr(image)) {
+        let data: Vec<u8> = image
+            .to_rgba32f()
+            .pixels()
+            .flat_map(|p| {
+                let [r, g, b] = tone_mapping.map(glam::vec3(p[0], p[1], p[2])).to_array();
+                [r, g, b, p[3]].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            })
+            .collect();
+        ColorImage::from_rgba_unmultiplied(img_size, &data)
+    } else if image.color().has_alpha() {
+        let data = image.to_rgba8().into_vec();
+        ColorImage::from_rgba_unmultiplied(img_size, &data)
+    } else {
+        ColorImage::from_rgb(img_size, &image.to_rgb8().into_vec())
+    }
+}
+
+// Render the view, and color every pixel by how far the render is off from the ground truth.
+async fn render_heatmap(
+    splats: Splats<Wgpu>,
+    view: SceneView,
+    view_type: ViewType,
+    index: usize,
+) -> RenderedHeatmap {
+    let (w, h) = (view.image.width() as usize, view.image.height() as usize);
+    let device = splats.means.device();
+
+    let gt_rgb = view_to_sample::<Wgpu>(&view, &device).slice([0..h, 0..w, 0..3]);
+    let (rendered, _) = splats.render(&view.camera, glam::uvec2(w as u32, h as u32), false);
+    let diff = rendered.slice([0..h, 0..w, 0..3]) - gt_rgb;
+
+    let mse = diff.clone().powf_scalar(2.0).mean();
+    let psnr = mse.recip().log() * 10.0 / std::f32::consts::LN_10;
+    let psnr = psnr.into_scalar_async().await;
+    let error = diff.abs().mean_dim(2).into_data_async().await;
+
+    let rgba: Vec<u8> = error
+        .iter::<f32>()
+        .flat_map(|e| {
+            let t = (e / HEATMAP_MAX_ERROR).clamp(0.0, 1.0);
+            // Small errors are transparent, large errors go from red to yellow.
+            [255, (t * 255.0) as u8, 0, (t * 255.0) as u8]
+        })
+        .collect();
+
+    RenderedHeatmap {
+        view_type,
+        index,
+        image: ColorImage::from_rgba_unmultiplied([w, h], &rgba),
+        psnr,
+    }
+}
+
 pub(crate) struct DatasetPanel {
     view_type: ViewType,
     selected_view: Option<SelectedView>,
+    thumbnails: HashMap<usize, TextureHandle>,
+    thumbnails_type: ViewType,
+    splats: Option<Splats<Wgpu>>,
+    splats_changed: bool,
+    show_heatmap: bool,
+    heatmap: Option<Heatmap>,
+    pending_heatmap: Option<Receiver<RenderedHeatmap>>,
+    last_heatmap: Option<Instant>,
+    // The tone mapping the view textures were made with.
+    tone_mapping: Option<ToneMapping>,
 }
 
 impl DatasetPanel {
@@ -36,8 +144,163 @@ impl DatasetPanel {
         Self {
             view_type: ViewType::Train,
             selected_view: None,
+            thumbnails: HashMap::new(),
+            thumbnails_type: ViewType::Train,
+            splats: None,
+            splats_changed: false,
+            show_heatmap: false,
+            heatmap: None,
+            pending_heatmap: None,
+            last_heatmap: None,
+            tone_mapping: None,
+        }
+    }
+
+    fn update_heatmap(&mut self, ui: &egui::Ui, scene: &Scene) {
+        if let Some(pending) = self.pending_heatmap.as_mut() {
+            match pending.try_recv() {
+                Ok(rendered) => {
+                    self.heatmap = Some(Heatmap {
+                        view_type: rendered.view_type,
+                        index: rendered.index,
+                        texture_handle: ui.ctx().load_texture(
+                            "view_heatmap_tex",
+                            rendered.image,
+                            TextureOptions::default(),
+                        ),
+                        psnr: rendered.psnr,
+                    });
+                    self.pending_heatmap = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending_heatmap = None;
+                }
+            }
+        }
+
+        let (Some(selected), Some(splats)) = (self.selected_view.as_ref(), self.splats.as_ref())
+        else {
+            return;
+        };
+        if !self.show_heatmap || self.pending_heatmap.is_some() {
+            return;
+        }
+
+        let other_view = self
+            .heatmap
+            .as_ref()
+            .is_none_or(|h| h.view_type != selected.view_type || h.index != selected.index);
+        let outdated = self.splats_changed
+            && self
+                .last_heatmap
+                .is_none_or(|t| t.elapsed() > HEATMAP_REFRESH);
+
+        if other_view || outdated {
+            let (send, rec) = oneshot::channel();
+            let splats = splats.clone();
+            let view = scene.views[selected.index].clone();
+            let (view_type, index) = (selected.view_type, selected.index);
+            tokio_wasm::task::spawn(async move {
+                let _ = send.send(render_heatmap(splats, view, view_type, index).await);
+            });
+            self.pending_heatmap = Some(rec);
+            self.splats_changed = false;
+            self.last_heatmap = Some(Instant::now());
         }
     }
+
+    // A strip with a thumbnail of every view. Returns the view that was clicked, if any.
+    fn thumbnail_strip(&mut self, ui: &mut egui::Ui, scene: &Scene) -> Option<usize> {
+        if self.thumbnails_type != self.view_type {
+            self.thumbnails.clear();
+            self.thumbnails_type = self.view_type;
+        }
+
+        let selected = self.selected_view.as_ref().map(|s| s.index);
+        let view_count = scene.views.len();
+        let mut clicked = None;
+
+        egui::ScrollArea::horizontal()
+            .id_salt("view_thumbnails")
+            .show_viewport(ui, |ui, viewport| {
+                let (strip, _) = ui.allocate_exact_size(
+                    vec2(THUMBNAIL_SIZE * view_count as f32, THUMBNAIL_SIZE),
+                    egui::Sense::hover(),
+                );
+
+                let first = (viewport.min.x / THUMBNAIL_SIZE).floor().max(0.0) as usize;
+                let last = ((viewport.max.x / THUMBNAIL_SIZE).ceil() as usize).min(view_count);
+                let mut created = 0;
+
+                for index in first..last {
+                    let cell = egui::Rect::from_min_size(
+                        strip.min + vec2(index as f32 * THUMBNAIL_SIZE, 0.0),
+                        vec2(THUMBNAIL_SIZE, THUMBNAIL_SIZE),
+                    )
+                    .shrink(2.0);
+                    let view = &scene.views[index];
+
+                    if !self.thumbnails.contains_key(&index) {
+                        if created == THUMBNAILS_PER_FRAME {
+                            ui.ctx().request_repaint();
+                            continue;
+                        }
+                        let image =
+                            view_color_image(view, Some(THUMBNAIL_SIZE as u32), self.tone_mapping);
+                        let handle = ui.ctx().load_texture(
+                            format!("view_thumbnail_{index}"),
+                            image,
+                            TextureOptions::default(),
+                        );
+                        self.thumbnails.insert(index, handle);
+                        created += 1;
+                    }
+                    let texture = &self.thumbnails[&index];
+
+                    let [w, h] = texture.size();
+                    let scale = cell.width() / w.max(h) as f32;
+                    let image_rect = egui::Rect::from_center_size(
+                        cell.center(),
+                        vec2(w as f32 * scale, h as f32 * scale),
+                    );
+                    ui.painter().image(
+                        texture.id(),
+                        image_rect,
+                        egui::Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                        Color32::WHITE,
+                    );
+
+                    let response = ui
+                        .interact(
+                            cell,
+                            ui.id().with(("view_thumbnail", index)),
+                            egui::Sense::click(),
+                        )
+                        .on_hover_text(view.path.as_str());
+                    if selected == Some(index) || response.hovered() {
+                        let color = if selected == Some(index) {
+                            ui.visuals().selection.stroke.color
+                        } else {
+                            ui.visuals().widgets.hovered.fg_stroke.color
+                        };
+                        ui.painter().rect_stroke(
+                            image_rect,
+                            0.0,
+                            egui::Stroke::new(2.0, color),
+                            egui::StrokeKind::Outside,
+                        );
+                    }
+                    if response.clicked() {
+                        clicked = Some(index);
+                    }
+                }
+            });
+
+        clicked
+    }
 }
 
 impl AppPanel for DatasetPanel {
@@ -56,12 +319,25 @@ impl AppPanel for DatasetPanel {
                     context.focus_view(view);
                 }
                 context.dataset = d.clone();
+                // Views might have been added, thumbnails are created again as needed.
+                self.thumbnails.clear();
+            }
+            ProcessMessage::ViewSplats { splats, .. }
+            | ProcessMessage::TrainStep { splats, .. } => {
+                self.splats = Some(*splats.clone());
+                self.splats_changed = true;
             }
             _ => {}
         }
     }
 
     fn ui(&mut self, ui: &mut egui::Ui, context: &mut AppContext) {
+        if self.tone_mapping != context.tone_mapping {
+            self.tone_mapping = context.tone_mapping;
+            self.thumbnails.clear();
+            self.selected_view = None;
+        }
+
         let pick_scene = selected_scene(self.view_type, context).clone();
 
         let mut nearest_view_ind = pick_scene.get_nearest_view(context.camera.local_to_world());
@@ -78,15 +354,8 @@ impl AppPanel for DatasetPanel {
             }
 
             if dirty {
-                let view = &pick_scene.views[*nearest];
-                let image = &view.image;
-                let img_size = [image.width() as usize, image.height() as usize];
-                let color_img = if image.color().has_alpha() {
-                    let data = image.to_rgba8().into_vec();
-                    egui::ColorImage::from_rgba_unmultiplied(img_size, &data)
-                } else {
-                    egui::ColorImage::from_rgb(img_size, &image.to_rgb8().into_vec())
-                };
+                let color_img =
+                    view_color_image(&pick_scene.views[*nearest], None, self.tone_mapping);
 
                 self.selected_view = Some(SelectedView {
                     index: *nearest,
@@ -99,6 +368,9 @@ impl AppPanel for DatasetPanel {
                 });
             }
 
+            // Same as above, the heatmap texture has to be updated before drawing.
+            self.update_heatmap(ui, &pick_scene);
+
             let view_count = pick_scene.views.len();
 
             if let Some(selected) = self.selected_view.as_ref() {
@@ -133,6 +405,20 @@ impl AppPanel for DatasetPanel {
                     egui::Color32::WHITE,
                 );
 
+                let heatmap = self.heatmap.as_ref().filter(|h| {
+                    self.show_heatmap
+                        && h.view_type == selected.view_type
+                        && h.index == selected.index
+                });
+                if let Some(heatmap) = heatmap {
+                    ui.painter().image(
+                        heatmap.texture_handle.id(),
+                        rect,
+                        egui::Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                        egui::Color32::WHITE,
+                    );
+                }
+
                 ui.allocate_rect(rect, egui::Sense::click());
 
                 ui.horizontal(|ui| {
@@ -196,8 +482,26 @@ impl AppPanel for DatasetPanel {
                         mask_info
                     );
                     ui.label(info);
+
+                    ui.add_space(10.0);
+
+                    ui.add_enabled(
+                        self.splats.is_some(),
+                        egui::Checkbox::new(&mut self.show_heatmap, "Error heatmap"),
+                    )
+                    .on_hover_text("Overlay the error between the current render and this view");
+                    if let Some(heatmap) = heatmap {
+                        ui.label(format!("PSNR {:.2}", heatmap.psnr));
+                    } else if self.show_heatmap && self.pending_heatmap.is_some() {
+                        ui.spinner();
+                    }
                 });
             }
+
+            if let Some(index) = self.thumbnail_strip(ui, &pick_scene) {
+                *nearest = index;
+                context.focus_view(&pick_scene.views[index]);
+            }
         }
 
         if context.loading() && context.training() {
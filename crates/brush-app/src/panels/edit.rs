@@ -0,0 +1,231 @@
+use crate::app::{AppContext, AppPanel};
+use brush_process::process_loop::ProcessMessage;
+use brush_render::{gaussian_splats::Splats, selection::transform_around};
+use burn::tensor::{Bool, Tensor};
+use burn_wgpu::Wgpu;
+use glam::{EulerRot, Quat, Vec3};
+use std::future::Future;
+use tokio::sync::oneshot::{self, Receiver};
+use tokio_with_wasm::alias as tokio_wasm;
+
+struct Edited {
+    splats: Splats<Wgpu>,
+    selection: Option<Tensor<Wgpu, 1, Bool>>,
+}
+
+pub(crate) struct EditPanel {
+    splats: Option<Splats<Wgpu>>,
+    pending: Option<Receiver<Edited>>,
+    translation: Vec3,
+    rotation_degrees: Vec3,
+    scale: f32,
+}
+
+impl EditPanel {
+    pub(crate) fn new() -> Self {
+        Self {
+            splats: None,
+            pending: None,
+            translation: Vec3::ZERO,
+            rotation_degrees: Vec3::ZERO,
+            scale: 1.0,
+        }
+    }
+
+    fn edit(&mut self, edit: impl Future<Output = Edited> + Send + 'static) {
+        let (send, rec) = oneshot::channel();
+        tokio_wasm::task::spawn(async move {
+            let _ = send.send(edit.await);
+        });
+        self.pending = Some(rec);
+    }
+}
+
+fn set_selection(context: &mut AppContext, selection: Option<Tensor<Wgpu, 1, Bool>>) {
+    context.selection = selection;
+    context.refresh_view();
+}
+
+impl AppPanel for EditPanel {
+    fn title(&self) -> String {
+        "Edit".to_owned()
+    }
+
+    fn on_message(&mut self, message: &ProcessMessage, context: &mut AppContext) {
+        match message {
+            ProcessMessage::NewSource => {
+                *self = Self::new();
+                context.selection = None;
+                context.lasso_select = false;
+            }
+            ProcessMessage::ViewSplats { splats, .. }
+            | ProcessMessage::TrainStep { splats, .. } => {
+                self.splats = Some(*splats.clone());
+            }
+            _ => {}
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, context: &mut AppContext) {
+        if let Some(pending) = self.pending.as_mut() {
+            match pending.try_recv() {
+                Ok(edited) => {
+                    self.pending = None;
+                    set_selection(context, edited.selection);
+                    // Show the edited splats everywhere, and export them.
+                    context.send_local(ProcessMessage::ViewSplats {
+                        up_axis: None,
+                        splats: Box::new(edited.splats),
+                        frame: 0,
+                        total_frames: 1,
+                    });
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending = None;
+                }
+            }
+        }
+
+        let Some(splats) = self.splats.clone() else {
+            ui.label("Load a scene to edit it.");
+            return;
+        };
+        if context.training() {
+            ui.label("Splats can be edited once training is done.");
+            return;
+        }
+
+        let n = splats.num_splats();
+        let device = context.device.clone();
+        let selection = context.selection.clone().filter(|s| s.dims()[0] == n);
+
+        ui.heading("Select");
+        ui.horizontal(|ui| {
+            if ui
+                .toggle_value(&mut context.lasso_select, "Lasso")
+                .on_hover_text(
+                    "Drag in the viewer to select splats, hold shift to add to the selection",
+                )
+                .changed()
+            {
+                context.refresh_view();
+            }
+            let crop_box = context.crop_box;
+            let button = ui
+                .add_enabled(crop_box.is_some(), egui::Button::new("In crop box"))
+                .on_disabled_hover_text("Enable the crop box in the viewer first");
+            if let Some(crop_box) = crop_box.filter(|_| button.clicked()) {
+                set_selection(context, Some(splats.select_in_box(&crop_box)));
+            }
+            if ui.button("All").clicked() {
+                set_selection(context, Some(Tensor::ones([n], &device).greater_elem(0.5)));
+            }
+            if let Some(selection) = selection.clone() {
+                if ui.button("Invert").clicked() {
+                    set_selection(context, Some(selection.bool_not()));
+                }
+                if ui.button("Clear").clicked() {
+                    set_selection(context, None);
+                }
+            }
+        });
+
+        let Some(selection) = selection else {
+            ui.label("Nothing selected.");
+            return;
+        };
+
+        ui.add_space(8.0);
+        ui.heading("Edit selection");
+        ui.add_enabled_ui(self.pending.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Delete").clicked() {
+                    let (splats, selection) = (splats.clone(), selection.clone());
+                    self.edit(async move {
+                        Edited {
+                            splats: splats.deleted(selection).await,
+                            selection: None,
+                        }
+                    });
+                }
+                if ui.button("Duplicate").clicked() {
+                    // Select the copies, so they can be moved away from the originals.
+                    let (splats, selection) = (splats.clone(), selection.clone());
+                    let device = device.clone();
+                    self.edit(async move {
+                        let splats = splats.duplicated(selection).await;
+                        let copies = splats.num_splats() - n;
+                        let selection = Tensor::cat(
+                            vec![
+                                Tensor::<Wgpu, 1>::zeros([n], &device),
+                                Tensor::ones([copies], &device),
+                            ],
+                            0,
+                        )
+                        .greater_elem(0.5);
+                        Edited {
+                            splats,
+                            selection: Some(selection),
+                        }
+                    });
+                }
+                if self.pending.is_some() {
+                    ui.spinner();
+                }
+            });
+
+            ui.add_space(4.0);
+            egui::Grid::new("edit_transform").show(ui, |ui| {
+                ui.label("Move");
+                for v in self.translation.as_mut() {
+                    ui.add(egui::DragValue::new(v).speed(0.01));
+                }
+                ui.end_row();
+                ui.label("Rotate");
+                for v in self.rotation_degrees.as_mut() {
+                    ui.add(egui::DragValue::new(v).speed(1.0).suffix("°"));
+                }
+                ui.end_row();
+                ui.label("Scale");
+                ui.add(
+                    egui::DragValue::new(&mut self.scale)
+                        .speed(0.01)
+                        .range(0.01..=100.0),
+                );
+                ui.end_row();
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("Apply").clicked() {
+                    let (translation, scale) = (self.translation, self.scale);
+                    let rotation = self.rotation_degrees * (std::f32::consts::PI / 180.0);
+                    let rotation =
+                        Quat::from_euler(EulerRot::XYZ, rotation.x, rotation.y, rotation.z);
+                    // Rotate and scale around the center of the selection.
+                    let (splats, selection) = (splats.clone(), selection.clone());
+                    self.edit(async move {
+                        let Some(center) = splats.selection_center(selection.clone()).await else {
+                            return Edited {
+                                splats,
+                                selection: Some(selection),
+                            };
+                        };
+                        let transform = transform_around(center, translation, rotation, scale);
+                        Edited {
+                            splats: splats.transformed_selection(selection.clone(), transform),
+                            selection: Some(selection),
+                        }
+                    });
+                }
+                if ui.button("Reset").clicked() {
+                    self.translation = Vec3::ZERO;
+                    self.rotation_degrees = Vec3::ZERO;
+                    self.scale = 1.0;
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,178 @@
+use crate::app::{AppContext, AppPanel};
+use brush_dataset::splat_floaters::{detect_floaters, FloaterConfig};
+use brush_process::process_loop::ProcessMessage;
+use brush_render::gaussian_splats::Splats;
+use burn::tensor::{Bool, Tensor};
+use burn_wgpu::Wgpu;
+use egui::Color32;
+use tokio::sync::oneshot::{self, Receiver};
+use tokio_with_wasm::alias as tokio_wasm;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Preview {
+    Off,
+    HideFloaters,
+    OnlyFloaters,
+}
+
+struct Detected {
+    floaters: Tensor<Wgpu, 1, Bool>,
+    count: usize,
+}
+
+pub(crate) struct FloatersPanel {
+    splats: Option<Splats<Wgpu>>,
+    config: FloaterConfig,
+    detected: Option<Detected>,
+    pending: Option<Receiver<anyhow::Result<Detected>>>,
+    preview: Preview,
+    err: Option<String>,
+}
+
+impl FloatersPanel {
+    pub(crate) fn new() -> Self {
+        Self {
+            splats: None,
+            config: FloaterConfig::new(),
+            detected: None,
+            pending: None,
+            preview: Preview::HideFloaters,
+            err: None,
+        }
+    }
+
+    fn update_preview(&self, context: &mut AppContext) {
+        context.preview_hidden = self.detected.as_ref().and_then(|d| match self.preview {
+            Preview::Off => None,
+            Preview::HideFloaters => Some(d.floaters.clone()),
+            Preview::OnlyFloaters => Some(d.floaters.clone().bool_not()),
+        });
+    }
+}
+
+impl AppPanel for FloatersPanel {
+    fn title(&self) -> String {
+        "Floaters".to_owned()
+    }
+
+    fn on_message(&mut self, message: &ProcessMessage, context: &mut AppContext) {
+        match message {
+            ProcessMessage::NewSource => {
+                *self = Self::new();
+                context.preview_hidden = None;
+            }
+            ProcessMessage::ViewSplats { splats, .. }
+            | ProcessMessage::TrainStep { splats, .. } => {
+                self.splats = Some(*splats.clone());
+            }
+            _ => {}
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, context: &mut AppContext) {
+        if let Some(pending) = self.pending.as_mut() {
+            match pending.try_recv() {
+                Ok(Ok(detected)) => {
+                    self.detected = Some(detected);
+                    self.err = None;
+                    self.pending = None;
+                    self.update_preview(context);
+                }
+                Ok(Err(e)) => {
+                    self.err = Some(e.to_string());
+                    self.pending = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending = None;
+                }
+            }
+        }
+
+        let Some(splats) = self.splats.clone() else {
+            ui.label("Load a scene to find floaters.");
+            return;
+        };
+
+        ui.add(egui::Slider::new(&mut self.config.floater_neighbours, 1..=32).text("Neighbours"));
+        ui.add(
+            egui::Slider::new(&mut self.config.floater_isolation, 0.5..=10.0)
+                .text("Isolation (std. dev.)"),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.config.floater_min_opacity, 0.0..=0.5).text("Min opacity"),
+        );
+        ui.add(egui::Slider::new(&mut self.config.floater_min_views, 0..=10).text("Min views"));
+        ui.add(
+            egui::Slider::new(&mut self.config.floater_min_contribution, 0.0..=10.0)
+                .text("Min pixels seen"),
+        );
+
+        ui.horizontal(|ui| {
+            let button = ui.add_enabled(self.pending.is_none(), egui::Button::new("Detect"));
+            if button.clicked() {
+                let (send, rec) = oneshot::channel();
+                let cameras: Vec<_> = context
+                    .dataset
+                    .train
+                    .views
+                    .iter()
+                    .map(|v| v.camera.clone())
+                    .collect();
+                let config = self.config.clone();
+                let splats = splats.clone();
+                tokio_wasm::task::spawn(async move {
+                    let detected = async {
+                        let floaters = detect_floaters(&splats, &cameras, &config).await?;
+                        let count = floaters.clone().int().sum().into_scalar_async().await;
+                        anyhow::Ok(Detected {
+                            floaters,
+                            count: count as usize,
+                        })
+                    };
+                    let _ = send.send(detected.await);
+                });
+                self.pending = Some(rec);
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+            if context.removed.is_some() && ui.button("Restore removed").clicked() {
+                context.removed = None;
+            }
+        });
+
+        if let Some(err) = &self.err {
+            ui.colored_label(Color32::LIGHT_RED, err);
+        }
+
+        let Some(detected) = &self.detected else {
+            return;
+        };
+        ui.label(format!("Found {} floaters", detected.count));
+        let floaters = detected.floaters.clone();
+
+        let preview = self.preview;
+        ui.horizontal(|ui| {
+            ui.label("Preview");
+            ui.selectable_value(&mut self.preview, Preview::Off, "Off");
+            ui.selectable_value(&mut self.preview, Preview::HideFloaters, "Hide floaters");
+            ui.selectable_value(&mut self.preview, Preview::OnlyFloaters, "Only floaters");
+        });
+        if self.preview != preview {
+            self.update_preview(context);
+        }
+
+        if ui.button("Remove floaters").clicked() {
+            // Also keep the splats removed earlier.
+            context.removed = Some(match context.removed_splats(&splats) {
+                Some(removed) => (removed.int() + floaters.int()).greater_elem(0),
+                None => floaters,
+            });
+            self.detected = None;
+            context.preview_hidden = None;
+        }
+    }
+}
@@ -1,15 +1,25 @@
 mod datasets;
+mod edit;
+mod floaters;
+mod models;
 mod settings;
 
 mod presets;
 mod scene;
+mod splat_stats;
 mod stats;
 mod tracing_debug;
+mod train_controls;
 
 pub(crate) use datasets::*;
+pub(crate) use edit::*;
+pub(crate) use floaters::*;
+pub(crate) use models::*;
 pub(crate) use presets::*;
 pub(crate) use scene::*;
 pub(crate) use settings::*;
+pub(crate) use splat_stats::*;
 pub(crate) use stats::*;
 #[allow(unused)]
 pub(crate) use tracing_debug::*;
+pub(crate) use train_controls::*;
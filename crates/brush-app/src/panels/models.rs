@@ -0,0 +1,278 @@
+use crate::app::{AppContext, AppPanel};
+use brush_dataset::splat_import::load_splat_from_ply;
+use brush_process::process_loop::ProcessMessage;
+use brush_render::{gaussian_splats::Splats, splat_scene::SplatScene};
+use burn::tensor::Tensor;
+use burn_wgpu::Wgpu;
+use egui::Color32;
+use glam::{Affine3A, EulerRot, Quat, Vec3};
+use std::io::Cursor;
+use tokio::sync::oneshot::{self, Receiver};
+use tokio_stream::StreamExt;
+use tokio_with_wasm::alias as tokio_wasm;
+
+// Transform of a model as shown in the panel. Kept separately from the affine transform, so the
+// angles don't jump around while dragging them.
+#[derive(Clone, Copy)]
+struct ModelTransform {
+    translation: Vec3,
+    rotation_degrees: Vec3,
+    scale: f32,
+}
+
+impl ModelTransform {
+    const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation_degrees: Vec3::ZERO,
+        scale: 1.0,
+    };
+
+    fn affine(&self) -> Affine3A {
+        let r = self.rotation_degrees * (std::f32::consts::PI / 180.0);
+        Affine3A::from_scale_rotation_translation(
+            Vec3::splat(self.scale),
+            Quat::from_euler(EulerRot::XYZ, r.x, r.y, r.z),
+            self.translation,
+        )
+    }
+}
+
+/// Compose the loaded scene with more splat models, each with its own transform.
+pub(crate) struct ModelsPanel {
+    // The first model is the scene of the running process.
+    scene: SplatScene<Wgpu>,
+    transforms: Vec<ModelTransform>,
+    // The visible models besides the loaded scene, merged in world space. The loaded scene
+    // changes every training step, while the other models only change when they're edited, so
+    // they're only merged again when `others_changed` is set.
+    merged_others: Option<Splats<Wgpu>>,
+    others_changed: bool,
+    pending: Option<Receiver<anyhow::Result<(String, Splats<Wgpu>)>>>,
+    err: Option<String>,
+}
+
+impl ModelsPanel {
+    pub(crate) fn new() -> Self {
+        Self {
+            scene: SplatScene::new(),
+            transforms: vec![],
+            merged_others: None,
+            others_changed: true,
+            pending: None,
+            err: None,
+        }
+    }
+
+    fn add_model(&mut self, name: String, splats: Splats<Wgpu>) {
+        self.scene.add_model(name, splats);
+        self.transforms.push(ModelTransform::IDENTITY);
+        self.others_changed = true;
+    }
+
+    // Like `SplatScene::combined`, but reuses the merged models besides the loaded scene.
+    fn combined(&mut self) -> Option<Splats<Wgpu>> {
+        let models = self.scene.models();
+        if self.others_changed {
+            let others: Vec<_> = models
+                .iter()
+                .skip(1)
+                .filter(|m| m.visible)
+                .map(|m| (&m.splats, m.transform))
+                .collect();
+            self.merged_others = (!others.is_empty()).then(|| Splats::merge_transformed(others));
+            self.others_changed = false;
+        }
+
+        match (models.first().filter(|m| m.visible), &self.merged_others) {
+            (Some(scene), Some(others)) => Some(Splats::merge_transformed([
+                (&scene.splats, scene.transform),
+                (others, Affine3A::IDENTITY),
+            ])),
+            (Some(scene), None) if scene.transform == Affine3A::IDENTITY => {
+                Some(scene.splats.clone())
+            }
+            (Some(scene), None) => Some(scene.splats.transformed(scene.transform)),
+            (None, others) => others.clone(),
+        }
+    }
+
+    // Show the combined models in the viewer, or just the loaded scene if there's nothing to
+    // combine.
+    fn update_scene(&mut self, context: &mut AppContext) {
+        let models = self.scene.models();
+        let composed = models.len() > 1
+            || models
+                .iter()
+                .any(|m| !m.visible || m.transform != Affine3A::IDENTITY);
+
+        context.scene_splats = if composed {
+            self.combined().or_else(|| {
+                // Nothing is visible, hide all splats of the loaded scene.
+                let splats = &self.scene.models().first()?.splats;
+                let n = splats.num_splats();
+                let all = Tensor::ones([n], &splats.means.device()).greater_elem(0.5);
+                Some(splats.with_hidden(all))
+            })
+        } else {
+            None
+        };
+        context.refresh_view();
+    }
+}
+
+impl AppPanel for ModelsPanel {
+    fn title(&self) -> String {
+        "Models".to_owned()
+    }
+
+    fn on_message(&mut self, message: &ProcessMessage, context: &mut AppContext) {
+        match message {
+            ProcessMessage::NewSource => {
+                *self = Self::new();
+                context.scene_splats = None;
+            }
+            // Animations aren't composed, only a single set of splats.
+            ProcessMessage::ViewSplats {
+                splats,
+                total_frames: 1,
+                ..
+            }
+            | ProcessMessage::TrainStep { splats, .. } => {
+                match self.scene.model_mut(0) {
+                    Some(model) => model.splats = *splats.clone(),
+                    None => self.add_model("Scene".to_owned(), *splats.clone()),
+                }
+                if self.scene.models().len() > 1 {
+                    self.update_scene(context);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, context: &mut AppContext) {
+        if let Some(pending) = self.pending.as_mut() {
+            match pending.try_recv() {
+                Ok(Ok((name, splats))) => {
+                    self.add_model(name, splats);
+                    self.update_scene(context);
+                    self.err = None;
+                    self.pending = None;
+                }
+                Ok(Err(e)) => {
+                    self.err = Some(e.to_string());
+                    self.pending = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending = None;
+                }
+            }
+        }
+
+        if self.scene.is_empty() {
+            ui.label("Load a scene to add more models to it.");
+            return;
+        }
+
+        let mut changed = false;
+        let mut remove = None;
+
+        for (i, transform) in self.transforms.iter_mut().enumerate() {
+            let Some(model) = self.scene.model_mut(i) else {
+                continue;
+            };
+
+            let mut edited = false;
+            ui.push_id(i, |ui| {
+                ui.horizontal(|ui| {
+                    edited |= ui.checkbox(&mut model.visible, "").changed();
+                    ui.text_edit_singleline(&mut model.name);
+                    // The loaded scene stays, load another source to replace it.
+                    if i > 0 && ui.button("🗑").on_hover_text("Remove model").clicked() {
+                        remove = Some(i);
+                    }
+                });
+                ui.label(format!("{} splats", model.splats.num_splats()));
+
+                egui::Grid::new("model_transform").show(ui, |ui| {
+                    ui.label("Position");
+                    for v in transform.translation.as_mut() {
+                        edited |= ui.add(egui::DragValue::new(v).speed(0.01)).changed();
+                    }
+                    ui.end_row();
+                    ui.label("Rotation");
+                    for v in transform.rotation_degrees.as_mut() {
+                        edited |= ui
+                            .add(egui::DragValue::new(v).speed(1.0).suffix("°"))
+                            .changed();
+                    }
+                    ui.end_row();
+                    ui.label("Scale");
+                    edited |= ui
+                        .add(
+                            egui::DragValue::new(&mut transform.scale)
+                                .speed(0.01)
+                                .range(0.01..=100.0),
+                        )
+                        .changed();
+                    ui.end_row();
+                });
+                if edited {
+                    model.transform = transform.affine();
+                }
+            });
+            changed |= edited;
+            ui.separator();
+        }
+
+        if let Some(i) = remove {
+            self.scene.remove_model(i);
+            self.transforms.remove(i);
+            changed = true;
+        }
+        if changed {
+            self.others_changed = true;
+            self.update_scene(context);
+        }
+
+        ui.horizontal(|ui| {
+            let button = ui.add_enabled(self.pending.is_none(), egui::Button::new("Add model"));
+            if button
+                .on_hover_text("Load a .ply file into the scene")
+                .clicked()
+            {
+                let device = context.device.clone();
+                let (send, rec) = oneshot::channel();
+                tokio_wasm::task::spawn(async move {
+                    let model = async {
+                        let file = rrfd::pick_file().await?;
+                        let name = file.name().unwrap_or_else(|| "Model".to_owned());
+                        let data = file.read().await;
+                        let stream = load_splat_from_ply(Cursor::new(data), None, device);
+                        let mut stream = std::pin::pin!(stream);
+                        // The loader sends progressively more splats, only keep the full set.
+                        let mut splats = None;
+                        while let Some(message) = stream.next().await {
+                            splats = Some(message?.splats);
+                        }
+                        let splats =
+                            splats.ok_or_else(|| anyhow::anyhow!("No splats in {name}"))?;
+                        anyhow::Ok((name, splats))
+                    };
+                    let _ = send.send(model.await);
+                });
+                self.pending = Some(rec);
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+        });
+
+        if let Some(err) = &self.err {
+            ui.colored_label(Color32::LIGHT_RED, err);
+        }
+    }
+}
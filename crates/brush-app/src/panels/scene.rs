@@ -1,24 +1,44 @@
-use brush_dataset::splat_export;
+use brush_dataset::splat_export::ExportFormat;
 use brush_process::process_loop::{ControlMessage, ProcessMessage};
-use brush_train::scene::ViewImageType;
-use brush_ui::burn_texture::BurnTexture;
+use brush_train::{image::load_rgb_tensor, scene::ViewImageType};
+use brush_ui::burn_texture::{self, BurnTexture};
+use burn::tensor::Tensor;
 use burn_wgpu::Wgpu;
 use core::f32;
 use egui::epaint::mutex::RwLock as EguiRwLock;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
 use brush_render::{
-    camera::{focal_to_fov, fov_to_focal},
+    background::Background,
+    camera::{focal_to_fov, fov_to_focal, Camera, Projection},
+    camera_path::CameraKeyframe,
+    color_lut::ColorLut,
+    crop::CropBox,
     gaussian_splats::Splats,
+    lod::{LodConfig, SplatLod},
+    raycast::{pick, PickResult},
+    render::{RenderOptions, SolidRender},
+    render_cache::{RenderCache, RenderKey},
+    stereo::StereoCameras,
+    tone_map::{ToneMapOperator, ToneMapping},
+    RenderAux, RenderStats,
 };
 use eframe::egui_wgpu::Renderer;
 use egui::{Color32, Rect};
-use glam::{Quat, UVec2, Vec3};
+use glam::{Quat, UVec2, Vec2, Vec3};
+use tokio::sync::oneshot::{self, Receiver};
 use tokio_with_wasm::alias as tokio_wasm;
 use tracing::trace_span;
 use web_time::Instant;
 
-use crate::app::{AppContext, AppPanel};
+#[cfg(not(target_family = "wasm"))]
+use crate::gamepad::Gamepads;
+use crate::{
+    app::{AppContext, AppPanel},
+    chunk_streamer::ChunkStreamer,
+    navigation::NavigationEditor,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 struct RenderState {
@@ -27,6 +47,42 @@ struct RenderState {
     cam_rot: Quat,
 
     frame: f32,
+    shader_generation: u32,
+    view_generation: u32,
+}
+
+// Settings to render a video of the scene, along keyframes set in the viewer or as a turntable.
+struct VideoSettings {
+    keyframes: Vec<CameraKeyframe>,
+    // Time between keyframes, or of the whole turntable.
+    seconds: f32,
+    fps: f32,
+    size: UVec2,
+    pending: Option<Receiver<anyhow::Result<()>>>,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            keyframes: vec![],
+            seconds: 8.0,
+            fps: 30.0,
+            size: glam::uvec2(1920, 1080),
+            pending: None,
+        }
+    }
+}
+
+// A splat clicked in the viewer.
+struct PickedSplat {
+    hit: PickResult,
+    opacity: f32,
+    // Where it was clicked, and the view at the time.
+    pixel: Vec2,
+    cam_pos: Vec3,
+    cam_rot: Quat,
+    // Whether to orbit around the hit, rather than to inspect the splat.
+    focus: bool,
 }
 
 struct ErrorDisplay {
@@ -40,16 +96,51 @@ pub(crate) struct ScenePanel {
 
     view_splats: Vec<Splats<Wgpu>>,
     frame_count: usize,
+    chunks: Option<ChunkStreamer>,
     frame: f32,
+    // Start and end time of splats that move, in seconds.
+    time_range: Option<(f32, f32)>,
+    pending_time_range: Option<Receiver<(f32, f32)>>,
 
     // Ui state.
     live_update: bool,
     paused: bool,
+    interpolate_frames: bool,
     err: Option<ErrorDisplay>,
     zen: bool,
+    pending_lut: Option<Receiver<anyhow::Result<ColorLut>>>,
+    show_render_stats: bool,
+    render_stats: Option<RenderStats>,
+    pending_stats: Option<Receiver<RenderStats>>,
+    // Reading back the intersection count of a frame to size the buffers of later frames.
+    #[cfg(target_family = "wasm")]
+    pending_counts: Option<Receiver<()>>,
+    #[cfg(target_family = "wasm")]
+    intersection_budget: Arc<std::sync::Mutex<brush_render::render::IntersectionBudget>>,
+    occlusion_culling: bool,
+    orthographic: bool,
+    // Render the left and right eye next to each other, for side-by-side 3D displays.
+    stereo: bool,
+    stereo_ipd: f32,
+    background: Background<Tensor<Wgpu, 3>>,
+    pending_background: Option<Receiver<anyhow::Result<Background<Tensor<Wgpu, 3>>>>>,
+    lod_enabled: bool,
+    lod_pixel_error: f32,
+    lod: Option<SplatLod<Wgpu>>,
+    pending_lod: Option<Receiver<anyhow::Result<SplatLod<Wgpu>>>>,
+    // Points of the lasso being drawn, in pixels.
+    lasso: Vec<Vec2>,
+    video: VideoSettings,
+    pending_pick: Option<Receiver<anyhow::Result<Option<PickedSplat>>>>,
+    picked: Option<PickedSplat>,
+
+    navigation_editor: NavigationEditor,
+    #[cfg(not(target_family = "wasm"))]
+    gamepads: Option<Gamepads>,
 
     // Keep track of what was last rendered.
     last_state: Option<RenderState>,
+    render_cache: RenderCache<Wgpu>,
 }
 
 impl ScenePanel {
@@ -64,15 +155,68 @@ impl ScenePanel {
             last_draw: None,
             err: None,
             view_splats: vec![],
+            chunks: None,
             live_update: true,
             paused: false,
+            interpolate_frames: true,
             last_state: None,
+            render_cache: RenderCache::default(),
             zen,
+            pending_lut: None,
+            show_render_stats: false,
+            render_stats: None,
+            pending_stats: None,
+            #[cfg(target_family = "wasm")]
+            pending_counts: None,
+            #[cfg(target_family = "wasm")]
+            intersection_budget: Arc::default(),
+            occlusion_culling: false,
+            orthographic: false,
+            stereo: false,
+            stereo_ipd: 0.064,
+            background: Background::default(),
+            pending_background: None,
+            lod_enabled: false,
+            lod_pixel_error: 2.0,
+            lod: None,
+            pending_lod: None,
+            lasso: vec![],
+            video: VideoSettings::default(),
+            pending_pick: None,
+            picked: None,
+            navigation_editor: NavigationEditor::new(),
+            #[cfg(not(target_family = "wasm"))]
+            gamepads: Gamepads::new(),
             frame_count: 0,
             frame: 0.0,
+            time_range: None,
+            pending_time_range: None,
         }
     }
 
+    // Move the camera with the first connected gamepad.
+    #[cfg(not(target_family = "wasm"))]
+    fn tick_gamepad(&mut self, ui: &egui::Ui, context: &mut AppContext) {
+        let Some(pad) = self.gamepads.as_mut().and_then(|g| g.poll()) else {
+            return;
+        };
+        if pad.is_idle() {
+            // Gamepad input doesn't wake up the UI, so check for it a few times a second.
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(100));
+        } else {
+            context
+                .controls
+                .apply_gamepad(&pad, ui.input(|r| r.predicted_dt));
+            ui.ctx().request_repaint();
+        }
+    }
+
+    fn clear_lod(&mut self) {
+        self.lod = None;
+        self.pending_lod = None;
+    }
+
     pub(crate) fn draw_splats(
         &mut self,
         ui: &mut egui::Ui,
@@ -101,10 +245,15 @@ impl ScenePanel {
 
         let (rect, response) = ui.allocate_exact_size(
             egui::Vec2::new(size.x as f32, size.y as f32),
-            egui::Sense::drag(),
+            egui::Sense::click_and_drag(),
         );
 
-        context.controls.tick(&response, ui);
+        // Dragging draws the lasso instead of moving the camera.
+        if !context.lasso_select {
+            #[cfg(not(target_family = "wasm"))]
+            self.tick_gamepad(ui, context);
+            context.controls.tick(&response, ui);
+        }
 
         let camera = &mut context.camera;
 
@@ -114,15 +263,34 @@ impl ScenePanel {
         camera.position = total_transform.translation.into();
         camera.rotation = Quat::from_mat3a(&total_transform.matrix3);
 
+        // The orthographic view covers the same area at the focus distance as the perspective
+        // view, so zooming still works.
+        camera.projection = if self.orthographic {
+            let height = 2.0 * context.controls.focus_distance * (camera.fov_y as f32 * 0.5).tan();
+            Projection::Orthographic {
+                size: glam::vec2(height * size.x as f32 / size.y as f32, height),
+            }
+        } else {
+            Projection::Perspective
+        };
+
         let state = RenderState {
             size,
             cam_pos: camera.position,
             cam_rot: camera.rotation,
             frame: self.frame,
+            shader_generation: brush_kernel::hot_reload::generation(),
+            view_generation: context.view_generation(),
         };
 
         let dirty = self.last_state != Some(state);
 
+        // Settings that aren't part of the state reset it to render again, so the cached render
+        // is stale too.
+        if self.last_state.is_none() {
+            self.render_cache.invalidate();
+        }
+
         if dirty {
             self.last_state = Some(state);
 
@@ -130,17 +298,168 @@ impl ScenePanel {
             ui.ctx().request_repaint();
         }
 
+        // The texture still shows the last render when the camera only moved by float noise, eg.
+        // while the camera controls come to a halt.
+        let key = RenderKey {
+            camera: context.camera.clone(),
+            img_size: size,
+            generation: {
+                let mut hasher = std::hash::DefaultHasher::new();
+                (
+                    state.frame.to_bits(),
+                    state.shader_generation,
+                    state.view_generation,
+                )
+                    .hash(&mut hasher);
+                hasher.finish()
+            },
+            render_u32_buffer: context.color_lut.is_none() && context.tone_mapping.is_none(),
+        };
+        let cached = self.render_cache.get(&key).is_some();
+
         // If this viewport is re-rendering.
-        if size.x > 0 && size.y > 0 && dirty {
+        if size.x > 0 && size.y > 0 && dirty && !cached {
             let _span = trace_span!("Render splats").entered();
-            let (img, _) = splats.render(&context.camera, size, true);
-            self.backbuffer.update_texture(img);
+
+            // Render a slightly wider image, so rows are aligned and the render buffer can be
+            // copied straight to the texture. Keep the focal length and principal point in pixels
+            // the same, so the visible part of the image doesn't change.
+            let render_size = glam::uvec2(burn_texture::aligned_width(size.x), size.y);
+            let mut camera = context.camera.clone();
+            camera.fov_x = focal_to_fov(fov_to_focal(camera.fov_x, size.x), render_size.x);
+            camera.center_uv.x *= size.x as f32 / render_size.x as f32;
+            if let Projection::Orthographic { size: ortho_size } = &mut camera.projection {
+                ortho_size.x *= render_size.x as f32 / size.x as f32;
+            }
+
+            // The tree is built from the loaded splats, not from composed models.
+            let lod = self
+                .lod
+                .as_ref()
+                .filter(|_| self.lod_enabled && context.scene_splats.is_none());
+            let splats = match lod {
+                Some(lod) => lod.splats_for_view(&camera, render_size, self.lod_pixel_error),
+                None => splats.clone(),
+            };
+            let splats = context.filter_view_splats(&splats);
+
+            // Each eye gets half of the view, with the same focal length in pixels.
+            let eye_size = glam::uvec2(size.x / 2, size.y);
+            let stereo = self.stereo && eye_size.x > 0;
+            let (render_size, texture_size) = if stereo {
+                (eye_size, glam::uvec2(eye_size.x * 2, size.y))
+            } else {
+                (render_size, size)
+            };
+
+            #[allow(unused_mut)]
+            let mut options = RenderOptions {
+                occlusion_culling: self.occlusion_culling,
+                ..Default::default()
+            };
+            #[cfg(target_family = "wasm")]
+            {
+                options = self
+                    .intersection_budget
+                    .lock()
+                    .expect("Budget lock poisoned")
+                    .apply(options, render_size);
+            }
+            let splats = splats.with_render_options(options);
+            let render = |render_u32_buffer: bool| -> (Tensor<Wgpu, 3>, RenderAux<Wgpu>) {
+                if stereo {
+                    let mut eye = context.camera.clone();
+                    eye.fov_x = focal_to_fov(fov_to_focal(eye.fov_x, size.x), eye_size.x);
+                    if let Projection::Orthographic { size: ortho_size } = &mut eye.projection {
+                        ortho_size.x *= eye_size.x as f32 / size.x as f32;
+                    }
+                    let eyes = StereoCameras::from_center(&eye, self.stereo_ipd);
+                    let (img, [aux, _]) = splats.render_side_by_side(
+                        &eyes,
+                        eye_size,
+                        render_u32_buffer,
+                        self.background.clone(),
+                    );
+                    (img, aux)
+                } else {
+                    splats.render_with_background(
+                        &camera,
+                        render_size,
+                        render_u32_buffer,
+                        self.background.clone(),
+                    )
+                }
+            };
+
+            let (img, aux) = if context.color_lut.is_some() || context.tone_mapping.is_some() {
+                // Tone mapping and grading need the float colors, so can't use the packed render
+                // buffer.
+                let (img, aux) = render(false);
+                let mut display = img.clone();
+                if let Some(tone_mapping) = context.tone_mapping.as_ref() {
+                    display = tone_mapping.apply(display);
+                }
+                if let Some(lut) = context.color_lut.as_ref() {
+                    display = lut.apply(display);
+                }
+                self.backbuffer
+                    .update_texture_rgba_cropped(display, texture_size);
+                (img, aux)
+            } else {
+                let (img, aux) = render(true);
+                self.backbuffer
+                    .update_texture_cropped(img.clone(), texture_size);
+                (img, aux)
+            };
+            self.render_cache.insert(key, img, aux.clone());
+
+            // Memory is tight in the browser, so size the intersection buffers from the counts
+            // of earlier frames rather than for the worst case.
+            #[cfg(target_family = "wasm")]
+            {
+                let counting = self.pending_counts.as_mut().is_some_and(|p| {
+                    matches!(p.try_recv(), Err(oneshot::error::TryRecvError::Empty))
+                });
+                if !counting {
+                    let (send, rec) = oneshot::channel();
+                    let (aux, num_splats) = (aux.clone(), splats.num_splats());
+                    let budget = self.intersection_budget.clone();
+                    tokio_wasm::task::spawn(async move {
+                        let counts = aux.read_counts_async().await;
+                        budget.lock().expect("Budget lock poisoned").update(
+                            &counts,
+                            num_splats,
+                            render_size,
+                            &options,
+                        );
+                        let _ = send.send(());
+                    });
+                    self.pending_counts = Some(rec);
+                }
+            }
+
+            // Only read back the stats of one frame at a time.
+            if self.show_render_stats && self.pending_stats.is_none() {
+                let (send, rec) = oneshot::channel();
+                tokio_wasm::task::spawn(async move {
+                    let _ = send.send(aux.read_stats_async().await);
+                });
+                self.pending_stats = Some(rec);
+            }
         }
 
         if let Some(id) = self.backbuffer.id() {
+            let has_background =
+                self.background.texture().is_some() || self.background.color() != Vec3::ZERO;
             ui.scope(|ui| {
                 let mut background = false;
-                if let Some(view) = context.dataset.train.views.first() {
+                if let Some(view) = context
+                    .dataset
+                    .train
+                    .views
+                    .first()
+                    .filter(|_| !has_background)
+                {
                     if view.image.color().has_alpha() && view.img_type == ViewImageType::Alpha {
                         background = true;
                         // if training views have alpha, show a background checker. Masked images
@@ -165,7 +484,447 @@ impl ScenePanel {
                 );
             });
         }
+
+        // Registered after the image, so the handles take priority over the camera controls.
+        if let Some(crop_box) = context.crop_box {
+            if let Some(crop_box) = crop_box_gizmo(ui, rect, size, &context.camera, crop_box) {
+                context.crop_box = Some(crop_box);
+                self.last_state = None;
+            }
+        }
+
+        if context.lasso_select {
+            self.lasso_select(ui, &response, rect, size, context, splats);
+        } else {
+            self.pick_splat(ui, &response, rect, size, context, splats);
+        }
+
+        if self.show_render_stats {
+            if let Some(stats) = self.render_stats.as_ref() {
+                let culling = self.occlusion_culling;
+                draw_render_stats(ui, rect, stats, &mut self.occlusion_culling);
+                if culling != self.occlusion_culling {
+                    self.last_state = None;
+                }
+            }
+        }
+    }
+}
+
+impl ScenePanel {
+    #[cfg(not(target_family = "wasm"))]
+    fn video_menu(&mut self, ui: &mut egui::Ui, context: &AppContext, splats: &Splats<Wgpu>) {
+        use brush_render::camera_path::{CameraPath, Interpolation};
+
+        let video = &mut self.video;
+        if let Some(pending) = video.pending.as_mut() {
+            match pending.try_recv() {
+                Ok(Ok(())) => video.pending = None,
+                Ok(Err(e)) => {
+                    log::error!("Failed to render video: {e}");
+                    video.pending = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => ui.ctx().request_repaint(),
+                Err(oneshot::error::TryRecvError::Closed) => video.pending = None,
+            }
+        }
+
+        ui.menu_button("🎬 Video", |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Add keyframe")
+                    .on_hover_text("Add the current view to the camera path")
+                    .clicked()
+                {
+                    video.keyframes.push(CameraKeyframe {
+                        time: video.keyframes.len() as f32 * video.seconds,
+                        camera: context.camera.clone(),
+                    });
+                }
+                if !video.keyframes.is_empty() && ui.button("Clear").clicked() {
+                    video.keyframes.clear();
+                }
+            });
+            if video.keyframes.len() < 2 {
+                ui.label("Without keyframes, the video circles around the view");
+            } else {
+                ui.label(format!("{} keyframes", video.keyframes.len()));
+            }
+
+            egui::Grid::new("video_settings").show(ui, |ui| {
+                ui.label(if video.keyframes.len() < 2 {
+                    "Length"
+                } else {
+                    "Between keyframes"
+                });
+                ui.add(
+                    egui::DragValue::new(&mut video.seconds)
+                        .range(0.1..=600.0)
+                        .suffix(" s"),
+                );
+                ui.end_row();
+                ui.label("Frame rate");
+                ui.add(egui::DragValue::new(&mut video.fps).range(1.0..=120.0));
+                ui.end_row();
+                ui.label("Size");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut video.size.x).range(16..=8192));
+                    ui.label("×");
+                    ui.add(egui::DragValue::new(&mut video.size.y).range(16..=8192));
+                });
+                ui.end_row();
+            });
+
+            let button = ui
+                .add_enabled(video.pending.is_none(), egui::Button::new("Render…"))
+                .on_hover_text("Pick a folder to render render.mp4 to, this needs ffmpeg");
+            if button.clicked() {
+                ui.close_menu();
+                let path = if video.keyframes.len() < 2 {
+                    // Circle around the point the camera orbits around.
+                    let camera = &context.camera;
+                    let center = camera.position
+                        + camera.rotation * Vec3::Z * context.controls.focus_distance;
+                    let up = camera.rotation * Vec3::NEG_Y;
+                    CameraPath::orbit(center, up, camera.position, camera.fov_y, video.seconds)
+                } else {
+                    // Keyframes were added with the spacing at that time, space them evenly.
+                    let keyframes = video
+                        .keyframes
+                        .iter()
+                        .enumerate()
+                        .map(|(i, k)| CameraKeyframe {
+                            time: i as f32 * video.seconds,
+                            camera: k.camera.clone(),
+                        })
+                        .collect();
+                    CameraPath::new(Interpolation::Smooth, keyframes)
+                };
+                let splats = context.filter_view_splats(splats);
+                let (size, fps) = (video.size, video.fps);
+
+                let (send, rec) = oneshot::channel();
+                tokio_wasm::task::spawn(async move {
+                    let render = async {
+                        let dir = rrfd::pick_directory().await?;
+                        let output = dir.join("render.mp4");
+                        brush_process::render_path::render_path(&splats, &path, size, fps, &output)
+                            .await?;
+                        log::info!("Rendered video to {}", output.display());
+                        anyhow::Ok(())
+                    };
+                    let _ = send.send(render.await);
+                });
+                video.pending = Some(rec);
+            }
+        });
+        if video.pending.is_some() {
+            ui.spinner();
+        }
+    }
+
+    // Click to inspect the splat under the cursor, double click to orbit around it.
+    fn pick_splat(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        rect: Rect,
+        size: UVec2,
+        context: &mut AppContext,
+        splats: &Splats<Wgpu>,
+    ) {
+        if let Some(pending) = self.pending_pick.as_mut() {
+            match pending.try_recv() {
+                Ok(Ok(picked)) => {
+                    if let Some(picked) = picked.as_ref().filter(|p| p.focus) {
+                        // The controls are in the space of the model.
+                        let point = context
+                            .model_local_to_world
+                            .inverse()
+                            .transform_point3(picked.hit.world_pos);
+                        context.controls.focus_on(point);
+                    }
+                    self.picked = picked.filter(|p| !p.focus);
+                    self.pending_pick = None;
+                }
+                Ok(Err(e)) => {
+                    log::warn!("Failed to pick splat: {e}");
+                    self.pending_pick = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending_pick = None;
+                }
+            }
+        }
+
+        let focus = response.double_clicked();
+        if let Some(pos) = response
+            .interact_pointer_pos()
+            .filter(|_| focus || response.clicked())
+        {
+            let pos = pos - rect.min;
+            let pixel = glam::vec2(pos.x, pos.y);
+            let camera = context.camera.clone();
+            let splats = context.filter_view_splats(splats);
+            let (send, rec) = oneshot::channel();
+            tokio_wasm::task::spawn(async move {
+                let picked = async {
+                    let Some(hit) = pick(&splats, &camera, size, pixel).await? else {
+                        return Ok(None);
+                    };
+                    let id = hit.splat_id as usize;
+                    let opacity: Vec<f32> = splats
+                        .opacity()
+                        .slice([id..id + 1])
+                        .into_data_async()
+                        .await
+                        .to_vec()
+                        .map_err(|e| anyhow::anyhow!("Failed to read opacity {e:?}"))?;
+                    anyhow::Ok(Some(PickedSplat {
+                        hit,
+                        opacity: opacity[0],
+                        pixel,
+                        cam_pos: camera.position,
+                        cam_rot: camera.rotation,
+                        focus,
+                    }))
+                };
+                let _ = send.send(picked.await);
+            });
+            self.pending_pick = Some(rec);
+        }
+
+        // Only show the clicked splat while the view stays the same.
+        let camera = &context.camera;
+        let Some(picked) = self
+            .picked
+            .as_ref()
+            .filter(|p| p.cam_pos == camera.position && p.cam_rot == camera.rotation)
+        else {
+            self.picked = None;
+            return;
+        };
+        let pos = rect.min + egui::vec2(picked.pixel.x, picked.pixel.y);
+        ui.painter_at(rect)
+            .circle_stroke(pos, 4.0, (1.5, Color32::from_rgb(255, 140, 0)));
+        egui::Area::new(egui::Id::new("picked_splat"))
+            .fixed_pos(pos + egui::vec2(10.0, 10.0))
+            .show(ui.ctx(), |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    egui::Grid::new("picked_splat_grid")
+                        .num_columns(2)
+                        .spacing([20.0, 2.0])
+                        .show(ui, |ui| {
+                            let hit = &picked.hit;
+                            ui.label("Splat");
+                            ui.label(format!("#{}", hit.splat_id));
+                            ui.end_row();
+
+                            ui.label("Position");
+                            let p = hit.world_pos;
+                            ui.label(format!("{:.3}, {:.3}, {:.3}", p.x, p.y, p.z));
+                            ui.end_row();
+
+                            ui.label("Depth");
+                            ui.label(format!("{:.3}", hit.depth));
+                            ui.end_row();
+
+                            ui.label("Opacity");
+                            ui.label(format!("{:.2}", picked.opacity));
+                            ui.end_row();
+                        });
+                });
+            });
+    }
+
+    fn lasso_select(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        rect: Rect,
+        size: UVec2,
+        context: &mut AppContext,
+        splats: &Splats<Wgpu>,
+    ) {
+        if let Some(pos) = response
+            .interact_pointer_pos()
+            .filter(|_| response.dragged())
+        {
+            let pos = pos - rect.min;
+            self.lasso.push(glam::vec2(pos.x, pos.y));
+        }
+
+        if response.drag_stopped() {
+            let lasso = std::mem::take(&mut self.lasso);
+            if lasso.len() < 3 {
+                return;
+            }
+            let selected = splats.select_in_lasso(&context.camera, size, &lasso);
+            // Hold shift to add to the selection.
+            let previous = context
+                .selection
+                .clone()
+                .filter(|s| ui.input(|i| i.modifiers.shift) && s.dims() == selected.dims());
+            context.selection = Some(match previous {
+                Some(previous) => (previous.int() + selected.int()).greater_elem(0),
+                None => selected,
+            });
+            context.refresh_view();
+            return;
+        }
+
+        let points: Vec<_> = self
+            .lasso
+            .iter()
+            .map(|p| rect.min + egui::vec2(p.x, p.y))
+            .collect();
+        if points.len() > 1 {
+            let color = Color32::from_rgb(255, 140, 0);
+            let painter = ui.painter_at(rect);
+            painter.add(egui::Shape::line(points.clone(), (1.5, color)));
+            // Close the lasso, as it will be when selecting.
+            painter.line_segment(
+                [points[points.len() - 1], points[0]],
+                (1.0, color.gamma_multiply(0.5)),
+            );
+        }
+    }
+}
+
+// Draw the outline of the crop box, with a handle on every face to drag that face along its
+// axis. Returns the new box when a handle was dragged.
+fn crop_box_gizmo(
+    ui: &egui::Ui,
+    rect: Rect,
+    size: UVec2,
+    camera: &Camera,
+    crop_box: CropBox,
+) -> Option<CropBox> {
+    let to_screen = |p: Vec3| {
+        camera
+            .world_to_pixel(size, p)
+            .map(|p| rect.min + egui::vec2(p.x, p.y))
+    };
+    let painter = ui.painter_at(rect);
+    let color = Color32::from_rgb(255, 190, 40);
+
+    // Corners that share an edge differ in a single bit.
+    let corners = crop_box.corners().map(to_screen);
+    for (i, a) in corners.iter().enumerate() {
+        for bit in 0..3 {
+            let j = i | (1 << bit);
+            if j == i {
+                continue;
+            }
+            if let (Some(a), Some(b)) = (a, corners[j]) {
+                painter.line_segment([*a, b], egui::Stroke::new(1.5, color));
+            }
+        }
+    }
+
+    let mut changed = None;
+    for (axis_index, axis) in crop_box.axes().into_iter().enumerate() {
+        for side in [-1.0, 1.0] {
+            let outward = axis * side;
+            let face = crop_box.center + outward;
+            let (Some(face_pos), Some(outward_pos)) = (to_screen(face), to_screen(face + outward))
+            else {
+                continue;
+            };
+
+            let id = ui.id().with(("crop_box_handle", axis_index, side > 0.0));
+            let response = ui.interact(
+                Rect::from_center_size(face_pos, egui::Vec2::splat(14.0)),
+                id,
+                egui::Sense::drag(),
+            );
+            let radius = if response.hovered() || response.dragged() {
+                7.0
+            } else {
+                5.0
+            };
+            painter.circle_filled(face_pos, radius, color);
+
+            // How far the face moved, in multiples of the extent, by projecting the drag
+            // onto the direction the face moves on screen.
+            let screen_dir = outward_pos - face_pos;
+            let drag = response.drag_delta();
+            if drag != egui::Vec2::ZERO && screen_dir.length_sq() > 1e-3 {
+                let moved = drag.dot(screen_dir) / screen_dir.length_sq();
+                let mut new_box = crop_box;
+                let extent = crop_box.extent[axis_index];
+                let new_extent = (extent * (1.0 + 0.5 * moved)).max(1e-3);
+                new_box.extent[axis_index] = new_extent;
+                // Keep the opposite face in place.
+                new_box.center += outward * ((new_extent - extent) / extent);
+                changed = Some(new_box);
+            }
+        }
     }
+    changed
+}
+
+fn draw_render_stats(ui: &egui::Ui, rect: Rect, stats: &RenderStats, occlusion_culling: &mut bool) {
+    egui::Area::new(egui::Id::new("render_stats"))
+        .fixed_pos(rect.min + egui::vec2(8.0, 8.0))
+        .show(ui.ctx(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                egui::Grid::new("render_stats_grid")
+                    .num_columns(2)
+                    .spacing([20.0, 2.0])
+                    .show(ui, |ui| {
+                        ui.label("Visible splats");
+                        ui.label(format!("{}", stats.num_visible));
+                        ui.end_row();
+
+                        ui.label("Intersections");
+                        ui.label(format!("{}", stats.num_intersections));
+                        ui.end_row();
+
+                        if stats.intersection_capacity > 0 {
+                            ui.label("Intersection buffer");
+                            if stats.overflowed() {
+                                ui.colored_label(
+                                    Color32::LIGHT_RED,
+                                    format!("{} (full)", stats.intersection_capacity),
+                                );
+                            } else {
+                                ui.label(format!("{}", stats.intersection_capacity));
+                            }
+                            ui.end_row();
+                        }
+
+                        if let Some(tiles) = stats.tile_intersections {
+                            ui.label("Per tile (min/mean/max)");
+                            ui.label(format!("{} / {:.1} / {}", tiles.min, tiles.mean, tiles.max));
+                            ui.end_row();
+                        }
+
+                        if *occlusion_culling {
+                            ui.label("Occluded intersections");
+                            ui.label(format!("{}", stats.culled_intersections));
+                            ui.end_row();
+                        }
+
+                        for (name, time) in &stats.kernel_timings {
+                            ui.label(*name);
+                            ui.label(format!("{:.2} ms", time.as_secs_f64() * 1000.0));
+                            ui.end_row();
+                        }
+                    });
+
+                let mut time_kernels = sync_span::is_enabled();
+                ui.checkbox(&mut time_kernels, "Time kernels")
+                    .on_hover_text("Wait for the GPU after every kernel to time it. This slows down rendering and training.");
+                sync_span::set_enabled(time_kernels);
+
+                ui.checkbox(occlusion_culling, "Occlusion culling")
+                    .on_hover_text("Skip rasterizing splats behind tiles that are already opaque.");
+            });
+        });
 }
 
 impl AppPanel for ScenePanel {
@@ -178,11 +937,15 @@ impl AppPanel for ScenePanel {
             ProcessMessage::NewSource => {
                 self.view_splats = vec![];
                 self.frame_count = 0;
+                self.chunks = None;
                 self.live_update = true;
                 self.paused = false;
                 self.err = None;
                 self.last_state = None;
                 self.frame = 0.0;
+                self.time_range = None;
+                self.pending_time_range = None;
+                self.clear_lod();
             }
             ProcessMessage::ViewSplats {
                 up_axis,
@@ -197,10 +960,23 @@ impl AppPanel for ScenePanel {
                 if self.live_update {
                     self.view_splats.truncate(*frame);
                     self.view_splats.push(*splats.clone());
+                    self.time_range = None;
+                    self.pending_time_range = None;
+                    self.clear_lod();
                 }
                 self.frame_count = *total_frames;
                 self.last_state = None;
             }
+            ProcessMessage::ViewChunks { index, vfs, budget } => {
+                self.chunks = Some(ChunkStreamer::new(
+                    index.clone(),
+                    vfs.clone(),
+                    *budget,
+                    context.device.clone(),
+                ));
+                self.frame_count = 1;
+                self.last_state = None;
+            }
             ProcessMessage::TrainStep {
                 splats,
                 stats: _,
@@ -213,6 +989,7 @@ impl AppPanel for ScenePanel {
 
                 if self.live_update {
                     self.view_splats = vec![splats];
+                    self.clear_lod();
                 }
             }
             ProcessMessage::Error(e) => {
@@ -227,6 +1004,137 @@ impl AppPanel for ScenePanel {
     fn ui(&mut self, ui: &mut egui::Ui, context: &mut AppContext) {
         let cur_time = Instant::now();
 
+        if let Some(chunks) = self.chunks.as_mut() {
+            if let Some(splats) = chunks.update(&context.camera) {
+                self.view_splats = splats.into_iter().collect();
+                self.last_state = None;
+                self.clear_lod();
+            }
+            if chunks.is_loading() {
+                ui.ctx().request_repaint();
+            }
+        }
+
+        if let Some(pending) = self.pending_background.as_mut() {
+            match pending.try_recv() {
+                Ok(Ok(background)) => {
+                    self.background = background;
+                    self.last_state = None;
+                    self.pending_background = None;
+                }
+                Ok(Err(e)) => {
+                    log::error!("Failed to load background: {e}");
+                    self.pending_background = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending_background = None;
+                }
+            }
+        }
+
+        if let Some(pending) = self.pending_lod.as_mut() {
+            match pending.try_recv() {
+                Ok(Ok(lod)) => {
+                    self.lod = Some(lod);
+                    self.last_state = None;
+                    self.pending_lod = None;
+                }
+                Ok(Err(e)) => {
+                    log::error!("Failed to build level of detail: {e}");
+                    self.lod_enabled = false;
+                    self.pending_lod = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending_lod = None;
+                }
+            }
+        }
+
+        if let Some(pending) = self.pending_time_range.as_mut() {
+            match pending.try_recv() {
+                Ok(range) => {
+                    self.time_range = Some(range);
+                    self.last_state = None;
+                    self.pending_time_range = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending_time_range = None;
+                }
+            }
+        }
+
+        // Splats that move are played back over the time they span.
+        let temporal = self.view_splats.first().and_then(|s| s.temporal.clone());
+        if let Some(temporal) = temporal {
+            if self.view_splats.len() == 1
+                && self.time_range.is_none()
+                && self.pending_time_range.is_none()
+            {
+                let (send, rec) = oneshot::channel();
+                tokio_wasm::task::spawn(async move {
+                    let _ = send.send(temporal.time_range().await);
+                });
+                self.pending_time_range = Some(rec);
+            }
+        }
+
+        // Only static scenes get a level of detail tree, it's too slow to rebuild every step.
+        let lod_available = !context.training()
+            && self.view_splats.len() == 1
+            && self.view_splats[0].temporal.is_none();
+        if self.lod_enabled && lod_available && self.lod.is_none() && self.pending_lod.is_none() {
+            let splats = self.view_splats[0].clone();
+            let (send, rec) = oneshot::channel();
+            tokio_wasm::task::spawn(async move {
+                let _ = send.send(SplatLod::new(&splats, &LodConfig::new()).await);
+            });
+            self.pending_lod = Some(rec);
+        }
+
+        if let Some(pending) = self.pending_lut.as_mut() {
+            match pending.try_recv() {
+                Ok(Ok(lut)) => {
+                    context.color_lut = Some(lut);
+                    self.last_state = None;
+                    self.pending_lut = None;
+                }
+                Ok(Err(e)) => {
+                    log::error!("Failed to load color LUT: {e}");
+                    self.pending_lut = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending_lut = None;
+                }
+            }
+        }
+
+        if let Some(pending) = self.pending_stats.as_mut() {
+            match pending.try_recv() {
+                Ok(stats) => {
+                    self.render_stats = Some(stats);
+                    self.pending_stats = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending_stats = None;
+                }
+            }
+        }
+
         self.last_draw = Some(cur_time);
 
         // Empty scene, nothing to show.
@@ -291,10 +1199,34 @@ For bigger training runs consider using the native app."#,
                 self.frame = self.frame.min(max_t);
             }
 
-            let frame = (self.frame * FPS)
-                .rem_euclid(self.frame_count as f32)
-                .floor() as usize;
-            let splats = self.view_splats[frame].clone();
+            let frame_pos = (self.frame * FPS).rem_euclid(self.frame_count as f32);
+            let frame = frame_pos.floor() as usize;
+            let next = (frame + 1) % self.frame_count;
+
+            // Blend to the next frame, so playback is smooth on displays with a higher rate.
+            let splats =
+                if self.interpolate_frames && self.frame_count > 1 && next < self.view_splats.len()
+                {
+                    Splats::interpolate(
+                        &self.view_splats[frame],
+                        &self.view_splats[next],
+                        frame_pos.fract(),
+                    )
+                } else {
+                    self.view_splats[frame].clone()
+                };
+            // Splats that move are rendered as they are at the current time.
+            let time_range = self.time_range.filter(|_| self.frame_count <= 1);
+            let splats = if let Some((start, end)) = time_range {
+                splats.with_time(Some(start + self.frame.rem_euclid((end - start).max(1e-3))))
+            } else {
+                splats
+            };
+            // Show the composed models instead, if any.
+            let splats = match context.scene_splats.clone() {
+                Some(scene) if self.frame_count <= 1 => scene,
+                _ => splats,
+            };
 
             self.draw_splats(ui, context, &splats);
 
@@ -308,6 +1240,37 @@ For bigger training runs consider using the native app."#,
                 if ui.selectable_label(!self.paused, label).clicked() {
                     self.paused = !self.paused;
                 }
+
+                if ui
+                    .selectable_label(self.interpolate_frames, "Interpolate frames")
+                    .clicked()
+                {
+                    self.interpolate_frames = !self.interpolate_frames;
+                    self.last_state = None;
+                }
+            }
+
+            if let Some((start, end)) = time_range {
+                ui.horizontal(|ui| {
+                    let label = if self.paused {
+                        "⏸ paused"
+                    } else {
+                        "⏵ playing"
+                    };
+                    if ui.selectable_label(!self.paused, label).clicked() {
+                        self.paused = !self.paused;
+                    }
+
+                    let duration = (end - start).max(1e-3);
+                    let mut time = self.frame.rem_euclid(duration);
+                    if ui
+                        .add(egui::Slider::new(&mut time, 0.0..=duration).suffix(" s"))
+                        .changed()
+                    {
+                        self.frame = time;
+                        self.paused = true;
+                    }
+                });
             }
 
             ui.horizontal(|ui| {
@@ -318,18 +1281,25 @@ For bigger training runs consider using the native app."#,
                     });
                 }
 
+                if let Some(chunks) = self.chunks.as_ref() {
+                    ui.label(format!("{} splats loaded", chunks.num_resident_splats()));
+                    if chunks.is_loading() {
+                        ui.spinner();
+                    }
+                }
+
                 if context.training() {
                     ui.add_space(15.0);
 
-                    let label = if self.paused {
+                    let paused = context.train_paused();
+                    let label = if paused {
                         "⏸ paused"
                     } else {
                         "⏵ training"
                     };
 
-                    if ui.selectable_label(!self.paused, label).clicked() {
-                        self.paused = !self.paused;
-                        context.control_message(ControlMessage::Paused(self.paused));
+                    if ui.selectable_label(!paused, label).clicked() {
+                        context.control_message(ControlMessage::Paused(!paused));
                     }
 
                     ui.add_space(15.0);
@@ -346,39 +1316,277 @@ For bigger training runs consider using the native app."#,
 
                     ui.add_space(15.0);
 
-                    if ui.button("⬆ Export").clicked() {
-                        let splats = splats.clone();
-
-                        let fut = async move {
-                            let file = rrfd::save_file("export.ply").await;
-
-                            // Not sure where/how to show this error if any.
-                            match file {
-                                Err(e) => {
-                                    log::error!("Failed to save file: {e}");
-                                }
-                                Ok(file) => {
-                                    let data = splat_export::splat_to_ply(splats).await;
-
-                                    let data = match data {
-                                        Ok(data) => data,
+                    ui.menu_button("⬆ Export", |ui| {
+                        for format in ExportFormat::ALL {
+                            if ui.button(format.label()).clicked() {
+                                ui.close_menu();
+                                let splats = splats.clone();
+                                let crop = context.crop_volume();
+                                let crop_box = context.crop_box;
+                                let removed = context.removed_splats(&splats);
+                                let sh_degree = context.export_sh_degree();
+
+                                let fut = async move {
+                                    let file =
+                                        rrfd::save_file(&format!("export.{}", format.extension()))
+                                            .await;
+
+                                    // Not sure where/how to show this error if any.
+                                    match file {
                                         Err(e) => {
-                                            log::error!("Failed to serialize file: {e}");
-                                            return;
+                                            log::error!("Failed to save file: {e}");
+                                        }
+                                        Ok(file) => {
+                                            let splats = match removed {
+                                                Some(removed) => {
+                                                    splats.retained(removed.bool_not()).await
+                                                }
+                                                None => splats,
+                                            };
+                                            let mut splats =
+                                                splats.with_crop_box(crop_box).cropped(&crop).await;
+                                            if let Some(sh_degree) = sh_degree {
+                                                splats = splats.with_sh_degree(sh_degree);
+                                            }
+                                            let data = format.export(splats).await;
+
+                                            let data = match data {
+                                                Ok(data) => data,
+                                                Err(e) => {
+                                                    log::error!("Failed to serialize file: {e}");
+                                                    return;
+                                                }
+                                            };
+
+                                            if let Err(e) = file.write(&data).await {
+                                                log::error!("Failed to write file: {e}");
+                                            }
                                         }
-                                    };
-
-                                    if let Err(e) = file.write(&data).await {
-                                        log::error!("Failed to write file: {e}");
                                     }
-                                }
+                                };
+
+                                tokio_wasm::task::spawn(fut);
                             }
+                        }
+                    });
+                }
+
+                #[cfg(not(target_family = "wasm"))]
+                self.video_menu(ui, context, &splats);
+
+                if context.color_lut.is_some() {
+                    if ui.button("✖ Remove LUT").clicked() {
+                        context.color_lut = None;
+                        self.last_state = None;
+                    }
+                } else if ui
+                    .add_enabled(self.pending_lut.is_none(), egui::Button::new("🎨 Load LUT"))
+                    .on_hover_text("Color grade the view with a .cube 3D LUT")
+                    .clicked()
+                {
+                    let (send, rec) = oneshot::channel();
+                    tokio_wasm::task::spawn(async move {
+                        let lut = async {
+                            let file = rrfd::pick_file().await?;
+                            let cube = String::from_utf8(file.read().await)?;
+                            ColorLut::from_cube(&cube)
+                        };
+                        let _ = send.send(lut.await);
+                    });
+                    self.pending_lut = Some(rec);
+                }
+
+                ui.menu_button("🎮 Controls", |ui| {
+                    self.navigation_editor
+                        .ui(ui, &mut context.controls.scheme);
+                });
+
+                ui.menu_button("☀ Tone mapping", |ui| {
+                    let mut enabled = context.tone_mapping.is_some();
+                    if ui
+                        .checkbox(&mut enabled, "Enabled")
+                        .on_hover_text("Map the linear colors of splats trained on HDR images to the display")
+                        .changed()
+                    {
+                        context.tone_mapping = enabled.then(ToneMapping::default);
+                        self.last_state = None;
+                    }
+                    if let Some(tone_mapping) = context.tone_mapping.as_mut() {
+                        let before = *tone_mapping;
+                        for operator in ToneMapOperator::ALL {
+                            ui.radio_value(&mut tone_mapping.operator, operator, operator.name());
+                        }
+                        ui.add(
+                            egui::Slider::new(&mut tone_mapping.exposure, -8.0..=8.0)
+                                .text("Exposure")
+                                .suffix(" stops"),
+                        );
+                        if *tone_mapping != before {
+                            self.last_state = None;
+                        }
+                    }
+                });
+
+                ui.menu_button("🖼 Background", |ui| {
+                    ui.horizontal(|ui| {
+                        let mut rgb = match &self.background {
+                            Background::Color(color) => color.to_array(),
+                            _ => [0.0; 3],
                         };
+                        if ui.color_edit_button_rgb(&mut rgb).changed() {
+                            self.background = Background::Color(Vec3::from(rgb));
+                            self.last_state = None;
+                        }
+                        ui.label("Color");
+                    });
+
+                    let loaders = [
+                        ("Image", "Stretch an image over the view", false),
+                        (
+                            "Environment map",
+                            "An equirectangular panorama around the scene",
+                            true,
+                        ),
+                    ];
+                    for (label, hover, environment) in loaders {
+                        if ui
+                            .add_enabled(self.pending_background.is_none(), egui::Button::new(label))
+                            .on_hover_text(hover)
+                            .clicked()
+                        {
+                            ui.close_menu();
+                            let device = context.device.clone();
+                            let (send, rec) = oneshot::channel();
+                            tokio_wasm::task::spawn(async move {
+                                let background = async {
+                                    let file = rrfd::pick_file().await?;
+                                    let texture =
+                                        load_rgb_tensor::<Wgpu>(&file.read().await, &device)?;
+                                    anyhow::Ok(if environment {
+                                        Background::Environment(texture)
+                                    } else {
+                                        Background::Image(texture)
+                                    })
+                                };
+                                let _ = send.send(background.await);
+                            });
+                            self.pending_background = Some(rec);
+                        }
+                    }
 
-                        tokio_wasm::task::spawn(fut);
+                    if ui.button("Reset").clicked() {
+                        ui.close_menu();
+                        self.background = Background::default();
+                        self.last_state = None;
                     }
+                });
+
+                if ui
+                    .selectable_label(self.show_render_stats, "📊 Render stats")
+                    .on_hover_text(
+                        "Show the visible splats, intersections and kernel timings of every frame",
+                    )
+                    .clicked()
+                {
+                    self.show_render_stats = !self.show_render_stats;
+                    // Render again to get the stats.
+                    self.last_state = None;
+                }
+
+                if ui
+                    .selectable_label(context.crop_box.is_some(), "Crop box")
+                    .on_hover_text(
+                        "Only show splats inside of a box, drag the handles on its faces to resize it. Exports are cropped to the box.",
+                    )
+                    .clicked()
+                {
+                    context.crop_box = if context.crop_box.is_some() {
+                        None
+                    } else {
+                        // Start with a box around the point the camera orbits.
+                        let camera = &context.camera;
+                        let focus_distance = context.controls.focus_distance;
+                        let focus = camera.position + camera.rotation * Vec3::Z * focus_distance;
+                        Some(CropBox::new(focus, Vec3::splat(focus_distance * 0.5)))
+                    };
+                    self.last_state = None;
+                }
+
+                if ui
+                    .selectable_label(context.solid.is_some(), "Solid")
+                    .on_hover_text(
+                        "Show opaque splats as hard edged surfaces, which works best with splats trained with the flatten loss",
+                    )
+                    .clicked()
+                {
+                    context.solid = match context.solid {
+                        Some(_) => None,
+                        None => Some(SolidRender::default()),
+                    };
+                    self.last_state = None;
+                }
+
+                if lod_available {
+                    ui.menu_button("🌲 Level of detail", |ui| {
+                        if ui
+                            .checkbox(&mut self.lod_enabled, "Enabled")
+                            .on_hover_text(
+                                "Draw far away groups of splats as a single splat, for huge scenes",
+                            )
+                            .changed()
+                        {
+                            self.last_state = None;
+                        }
+                        if ui
+                            .add(
+                                egui::Slider::new(&mut self.lod_pixel_error, 0.5..=32.0)
+                                    .logarithmic(true)
+                                    .text("Pixel error"),
+                            )
+                            .on_hover_text("Groups smaller than this many pixels are merged")
+                            .changed()
+                        {
+                            self.last_state = None;
+                        }
+                        if self.pending_lod.is_some() {
+                            ui.horizontal(|ui| {
+                                ui.label("Building tree...");
+                                ui.spinner();
+                            });
+                        }
+                    });
+                }
+
+                if ui
+                    .selectable_label(self.orthographic, "Orthographic")
+                    .on_hover_text("Render without perspective, eg. for CAD models or turntables")
+                    .clicked()
+                {
+                    self.orthographic = !self.orthographic;
+                    self.last_state = None;
                 }
 
+                ui.menu_button("👓 Stereo", |ui| {
+                    if ui
+                        .checkbox(&mut self.stereo, "Side by side")
+                        .on_hover_text("Render the left and right eye next to each other, for 3D displays")
+                        .changed()
+                    {
+                        self.last_state = None;
+                    }
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.stereo_ipd, 0.001..=1.0)
+                                .logarithmic(true)
+                                .text("Eye distance"),
+                        )
+                        .on_hover_text("Distance between the eyes, in scene units")
+                        .changed()
+                    {
+                        self.last_state = None;
+                    }
+                });
+
                 ui.selectable_label(false, "Controls")
                     .on_hover_ui_at_pointer(|ui| {
                         ui.heading("Controls");
@@ -389,6 +1597,8 @@ For bigger training runs consider using the native app."#,
                         );
                         ui.label("• Middle click, or left click + control, and drag to pan");
                         ui.label("• Scroll to zoom");
+                        ui.label("• Double click to orbit around a point");
+                        ui.label("• Click to inspect a splat");
                         ui.label("• WASD to fly, Q&E to move up & down.");
                         ui.label("• Z&C to roll, X to reset roll");
                         ui.label("• Shift to move faster");
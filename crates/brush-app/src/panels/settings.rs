@@ -4,12 +4,13 @@ use brush_process::{
     data_source::DataSource,
     process_loop::{start_process, ProcessArgs, ProcessConfig, RerunConfig},
 };
-use brush_train::train::TrainConfig;
+use brush_train::train::{DensificationStrategy, DensifySignal, TrainConfig};
 use egui::Slider;
 
 pub(crate) struct SettingsPanel {
     args: ProcessArgs,
     url: String,
+    checkpoint: String,
 }
 
 impl SettingsPanel {
@@ -24,6 +25,7 @@ impl SettingsPanel {
                 RerunConfig::new(),
             ),
             url: "splat.com/example.ply".to_owned(),
+            checkpoint: "checkpoint.safetensors".to_owned(),
         }
     }
 }
@@ -72,6 +74,18 @@ impl AppPanel for SettingsPanel {
                 );
             }
 
+            ui.checkbox(
+                &mut self.args.load_config.alpha_as_mask,
+                "Use image alpha as mask",
+            )
+            .on_hover_text("Ignore transparent pixels instead of training them to be transparent");
+
+            ui.checkbox(
+                &mut self.args.load_config.sparse_depth,
+                "Use COLMAP points as depth",
+            )
+            .on_hover_text("Supervise the depth of views without a depth map in a depths folder");
+
             ui.heading("Training Settings");
 
             ui.horizontal(|ui| {
@@ -84,6 +98,70 @@ impl AppPanel for SettingsPanel {
                 );
             });
 
+            ui.horizontal(|ui| {
+                ui.label("Depth loss weight");
+                ui.add(egui::Slider::new(
+                    &mut self.args.train_config.depth_loss_weight,
+                    0.0..=1.0,
+                ))
+                .on_hover_text("Only used for views with a known depth");
+            });
+
+            ui.horizontal(|ui| {
+                let config = &mut self.args.train_config;
+                ui.label("Densification");
+                ui.selectable_value(
+                    &mut config.densification,
+                    DensificationStrategy::Classic,
+                    "Clone & split",
+                );
+                ui.selectable_value(&mut config.densification, DensificationStrategy::Mcmc, "MCMC")
+                    .on_hover_text("Better quality for a fixed number of splats");
+            });
+            if self.args.train_config.densification == DensificationStrategy::Classic {
+                ui.horizontal(|ui| {
+                    let config = &mut self.args.train_config;
+                    ui.label("Split on");
+                    ui.selectable_value(&mut config.densify_signal, DensifySignal::Grad, "Gradient");
+                    ui.selectable_value(
+                        &mut config.densify_signal,
+                        DensifySignal::AbsGrad,
+                        "Absolute gradient",
+                    )
+                    .on_hover_text("Also splits big blurry splats");
+                });
+
+                let mut use_budget = self.args.train_config.splat_budget.is_some();
+                if ui
+                    .checkbox(&mut use_budget, "Limit splats")
+                    .on_hover_text("The least important splats are removed to stay under this")
+                    .clicked()
+                {
+                    self.args.train_config.splat_budget = use_budget.then_some(1000000);
+                }
+                if let Some(budget) = self.args.train_config.splat_budget.as_mut() {
+                    ui.add(egui::Slider::new(budget, 10000..=10000000).logarithmic(true));
+                }
+            }
+            if self.args.train_config.densification == DensificationStrategy::Mcmc {
+                ui.horizontal(|ui| {
+                    ui.label("Max splats");
+                    ui.add(
+                        egui::Slider::new(
+                            &mut self.args.train_config.max_splats,
+                            10000..=10000000,
+                        )
+                        .logarithmic(true),
+                    );
+                });
+            }
+
+            ui.checkbox(
+                &mut self.args.train_config.appearance_model,
+                "Learn exposure per image",
+            )
+            .on_hover_text("Correct exposure and white balance differences between photos");
+
             ui.heading("Process Settings");
 
             ui.horizontal(|ui| {
@@ -107,6 +185,18 @@ impl AppPanel for SettingsPanel {
                             .suffix(" steps"),
                     );
                 });
+
+                // Train on the first discrete GPUs, besides the default GPU.
+                let mut num_gpus = self.args.process_config.train_gpus.len() + 1;
+                ui.horizontal(|ui| {
+                    ui.label("Train on");
+                    if ui
+                        .add(egui::Slider::new(&mut num_gpus, 1..=8).suffix(" GPUs"))
+                        .changed()
+                    {
+                        self.args.process_config.train_gpus = (1..num_gpus).collect();
+                    }
+                });
             }
 
             #[cfg(all(not(target_family = "wasm"), not(target_os = "android")))]
@@ -172,19 +262,40 @@ impl AppPanel for SettingsPanel {
 
             ui.add_space(10.0);
 
-            if file || dir || url {
+            #[cfg(target_family = "wasm")]
+            {
+                ui.label("Or drop a file or folder anywhere. Dropped data and the splats trained on it are kept in the browser, and loaded again next time.");
+                if ui.button("Clear saved session").clicked() {
+                    tokio_with_wasm::alias::task::spawn(async {
+                        if let Err(e) = crate::web_session::clear_session().await {
+                            log::warn!("Failed to clear the saved session: {e:#}");
+                        }
+                    });
+                }
+                ui.add_space(10.0);
+            }
+
+            let mut resume = false;
+            if can_pick_dir {
+                ui.label("Resume training from a checkpoint, on the dataset directory it was trained on.");
+                ui.text_edit_singleline(&mut self.checkpoint);
+                resume = ui.button("Resume").clicked();
+                ui.add_space(10.0);
+            }
+
+            if file || dir || url || resume {
                 let source = if file {
                     DataSource::PickFile
-                } else if dir {
+                } else if dir || resume {
                     DataSource::PickDirectory
                 } else {
                     DataSource::Url(self.url.clone())
                 };
-                context.connect_to(start_process(
-                    source,
-                    self.args.clone(),
-                    context.device.clone(),
-                ));
+                let mut args = self.args.clone();
+                if resume {
+                    args.process_config.resume = Some(self.checkpoint.clone());
+                }
+                context.connect_to(start_process(source, args, context.device.clone()));
             }
 
             ui.add_space(10.0);
@@ -0,0 +1,224 @@
+use crate::app::{AppContext, AppPanel};
+use brush_process::process_loop::ProcessMessage;
+use brush_render::{
+    gaussian_splats::Splats,
+    splat_stats::{Histogram, SplatStats},
+};
+use burn::tensor::Tensor;
+use burn_wgpu::Wgpu;
+use egui::{Color32, Rect, Sense};
+use tokio::sync::oneshot::{self, Receiver};
+use tokio_with_wasm::alias as tokio_wasm;
+
+pub(crate) struct SplatStatsPanel {
+    splats: Option<Splats<Wgpu>>,
+    stats: Option<SplatStats>,
+    pending: Option<Receiver<anyhow::Result<SplatStats>>>,
+    err: Option<String>,
+    // The histogram and bin whose splats are selected.
+    selected: Option<(&'static str, usize)>,
+}
+
+impl SplatStatsPanel {
+    pub(crate) fn new() -> Self {
+        Self {
+            splats: None,
+            stats: None,
+            pending: None,
+            err: None,
+            selected: None,
+        }
+    }
+
+    fn histogram(
+        &mut self,
+        ui: &mut egui::Ui,
+        context: &mut AppContext,
+        label: &'static str,
+        hist: &Histogram,
+    ) {
+        let selected = self.selected.filter(|s| s.0 == label).map(|s| s.1);
+        let Some(bin) = draw_histogram(ui, label, hist, selected) else {
+            return;
+        };
+
+        // Clicking the selected bin again clears the selection.
+        if selected == Some(bin) {
+            self.selected = None;
+            context.selection = None;
+        } else {
+            let mask: Vec<f32> = hist
+                .splats_in_bin(bin)
+                .into_iter()
+                .map(|s| if s { 1.0 } else { 0.0 })
+                .collect();
+            let mask = Tensor::<Wgpu, 1>::from_floats(mask.as_slice(), &context.device);
+            self.selected = Some((label, bin));
+            context.selection = Some(mask.greater_elem(0.5));
+        }
+        context.refresh_view();
+    }
+}
+
+// Draw a histogram, and return the bin that was clicked, if any.
+fn draw_histogram(
+    ui: &mut egui::Ui,
+    label: &str,
+    hist: &Histogram,
+    selected: Option<usize>,
+) -> Option<usize> {
+    ui.label(label);
+
+    let size = egui::vec2(ui.available_width(), 60.0);
+    let (rect, response) = ui.allocate_exact_size(size, Sense::click());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    let max_count = hist.counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let bin_width = rect.width() / hist.counts.len() as f32;
+    for (i, &count) in hist.counts.iter().enumerate() {
+        let height = rect.height() * count as f32 / max_count;
+        let min = egui::pos2(rect.left() + i as f32 * bin_width, rect.bottom() - height);
+        let max = egui::pos2(min.x + bin_width - 1.0, rect.bottom());
+        let color = if selected == Some(i) {
+            Color32::GOLD
+        } else {
+            Color32::LIGHT_BLUE
+        };
+        painter.rect_filled(Rect::from_min_max(min, max), 0.0, color);
+    }
+
+    let mut clicked = None;
+    if let Some(pos) = response.hover_pos() {
+        let bin = ((pos.x - rect.left()) / bin_width) as usize;
+        if let Some(count) = hist.counts.get(bin) {
+            if response.clicked() {
+                clicked = Some(bin);
+            }
+            let (start, end) = hist.bin_range(bin);
+            response.on_hover_text(format!(
+                "{start:.4} - {end:.4}: {count} splats\nClick to highlight these splats"
+            ));
+        }
+    }
+
+    ui.label(format!("{:.4} - {:.4}", hist.min, hist.max));
+    ui.add_space(6.0);
+    clicked
+}
+
+impl AppPanel for SplatStatsPanel {
+    fn title(&self) -> String {
+        "Splat Stats".to_owned()
+    }
+
+    fn on_message(&mut self, message: &ProcessMessage, _: &mut AppContext) {
+        match message {
+            ProcessMessage::NewSource => {
+                *self = Self::new();
+            }
+            ProcessMessage::ViewSplats { splats, .. }
+            | ProcessMessage::TrainStep { splats, .. } => {
+                self.splats = Some(*splats.clone());
+            }
+            _ => {}
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, context: &mut AppContext) {
+        if let Some(pending) = self.pending.as_mut() {
+            match pending.try_recv() {
+                Ok(Ok(stats)) => {
+                    self.stats = Some(stats);
+                    self.err = None;
+                    self.pending = None;
+                }
+                Ok(Err(e)) => {
+                    self.err = Some(e.to_string());
+                    self.pending = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {
+                    ui.ctx().request_repaint();
+                }
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    self.pending = None;
+                }
+            }
+        }
+
+        let Some(splats) = self.splats.clone() else {
+            ui.label("Load a scene to see statistics about its splats.");
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            let button = ui.add_enabled(self.pending.is_none(), egui::Button::new("Calculate"));
+            if button.clicked() {
+                let (send, rec) = oneshot::channel();
+                let camera = context.camera.clone();
+                if self.selected.take().is_some() {
+                    context.selection = None;
+                    context.refresh_view();
+                }
+                tokio_wasm::task::spawn(async move {
+                    let _ = send.send(splats.stats(Some(&camera)).await);
+                });
+                self.pending = Some(rec);
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+        });
+
+        if let Some(err) = &self.err {
+            ui.colored_label(Color32::LIGHT_RED, err);
+        }
+
+        // Taken out while drawing, as the histograms can change the selection.
+        let Some(stats) = self.stats.take() else {
+            return;
+        };
+
+        egui::Grid::new("splat_stats_grid")
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Splats");
+                ui.label(format!("{}", stats.num_splats));
+                ui.end_row();
+
+                let (min, max) = (stats.bounds.min(), stats.bounds.max());
+                ui.label("Bounds min");
+                ui.label(format!("{:.2} {:.2} {:.2}", min.x, min.y, min.z));
+                ui.end_row();
+
+                ui.label("Bounds max");
+                ui.label(format!("{:.2} {:.2} {:.2}", max.x, max.y, max.z));
+                ui.end_row();
+
+                for (band, energy) in stats.sh_band_energy.iter().enumerate() {
+                    ui.label(format!("SH band {band} energy"));
+                    ui.label(format!("{energy:.5}"));
+                    ui.end_row();
+                }
+            });
+
+        ui.add_space(10.0);
+        ui.label("Click a bar to highlight its splats in the viewer.");
+        self.histogram(ui, context, "Opacity", &stats.opacity);
+        self.histogram(ui, context, "Max scale (log)", &stats.scale);
+        if let Some(sh_energy) = &stats.sh_energy {
+            self.histogram(ui, context, "View dependent SH energy (log)", sh_energy);
+        }
+        if let Some(screen_size) = &stats.screen_size {
+            self.histogram(
+                ui,
+                context,
+                "Screen size, fraction of height (log)",
+                screen_size,
+            );
+        }
+        self.stats = Some(stats);
+    }
+}
@@ -13,6 +13,8 @@ pub(crate) struct StatsPanel {
     train_iter_per_s: f32,
     last_eval: Option<String>,
     cur_sh_degree: u32,
+    // Learning rates of the means, rotations, scales, coefficients and opacities.
+    learning_rates: Option<[f64; 5]>,
 
     training_started: bool,
     num_splats: usize,
@@ -33,6 +35,7 @@ impl StatsPanel {
             num_splats: 0,
             frames: 0,
             cur_sh_degree: 0,
+            learning_rates: None,
             start_load_time: Instant::now(),
             adapter_info,
         }
@@ -76,6 +79,7 @@ impl AppPanel for StatsPanel {
                 self.num_splats = 0;
                 self.cur_sh_degree = 0;
                 self.last_eval = None;
+                self.learning_rates = None;
                 self.training_started = *training;
             }
             ProcessMessage::ViewSplats {
@@ -90,10 +94,17 @@ impl AppPanel for StatsPanel {
             }
             ProcessMessage::TrainStep {
                 splats,
-                stats: _,
+                stats,
                 iter,
                 timestamp,
             } => {
+                self.learning_rates = Some([
+                    stats.lr_mean,
+                    stats.lr_rotation,
+                    stats.lr_scale,
+                    stats.lr_coeffs,
+                    stats.lr_opac,
+                ]);
                 self.cur_sh_degree = splats.sh_degree();
                 self.num_splats = splats.num_splats();
                 let current_iter_per_s = (iter - self.last_train_step.1) as f32
@@ -102,11 +113,14 @@ impl AppPanel for StatsPanel {
                 self.last_train_step = (*timestamp, *iter);
             }
             ProcessMessage::EvalResult {
-                iter: _,
+                iter,
                 avg_psnr,
                 avg_ssim,
+                avg_lpips,
             } => {
-                self.last_eval = Some(format!("{avg_psnr:.2} PSNR, {avg_ssim:.3} SSIM"));
+                self.last_eval = Some(format!(
+                    "{avg_psnr:.2} PSNR, {avg_ssim:.3} SSIM, {avg_lpips:.3} LPIPS-lite (step {iter})"
+                ));
             }
             _ => {}
         }
@@ -154,6 +168,18 @@ impl AppPanel for StatsPanel {
                     let elapsed = Duration::from_secs(self.start_load_time.elapsed().as_secs());
                     ui.label(format!("{}", humantime::Duration::from(elapsed)));
                     ui.end_row();
+
+                    if let Some(learning_rates) = self.learning_rates {
+                        ui.label("Learning rates");
+                        ui.end_row();
+
+                        let names = ["Means", "Rotations", "Scales", "Coefficients", "Opacity"];
+                        for (name, lr) in names.into_iter().zip(learning_rates) {
+                            ui.label(name);
+                            ui.label(format!("{lr:.2e}"));
+                            ui.end_row();
+                        }
+                    }
                 }
 
                 let client = WgpuRuntime::client(&self.device);
@@ -0,0 +1,124 @@
+use crate::app::{AppContext, AppPanel};
+use brush_process::process_loop::{ControlMessage, ProcessMessage};
+use brush_train::train::TrainTweaks;
+use egui::emath::Numeric;
+use std::ops::RangeInclusive;
+
+pub(crate) struct TrainControlsPanel {
+    tweaks: Option<TrainTweaks>,
+    iter: u32,
+}
+
+impl TrainControlsPanel {
+    pub(crate) fn new() -> Self {
+        Self {
+            tweaks: None,
+            iter: 0,
+        }
+    }
+}
+
+fn tweak_slider<T: Numeric>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut T,
+    range: RangeInclusive<T>,
+    logarithmic: bool,
+) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::Slider::new(value, range).logarithmic(logarithmic))
+            .changed()
+    })
+    .inner
+}
+
+impl AppPanel for TrainControlsPanel {
+    fn title(&self) -> String {
+        "Training".to_owned()
+    }
+
+    fn on_message(&mut self, message: &ProcessMessage, context: &mut AppContext) {
+        match message {
+            ProcessMessage::NewSource => {
+                *self = Self::new();
+            }
+            ProcessMessage::StartLoading { training: true } => {
+                self.tweaks = context.start_tweaks();
+            }
+            ProcessMessage::TrainStep { iter, .. } => {
+                self.iter = *iter;
+            }
+            _ => {}
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, context: &mut AppContext) {
+        if !context.training() {
+            ui.label("Start training to control it.");
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            let paused = context.train_paused();
+            let label = if paused { "⏵ Resume" } else { "⏸ Pause" };
+            if ui.button(label).clicked() {
+                context.control_message(ControlMessage::Paused(!paused));
+            }
+            if ui
+                .add_enabled(paused, egui::Button::new("⏭ Step"))
+                .on_hover_text("Train a single step")
+                .clicked()
+            {
+                context.control_message(ControlMessage::Step);
+            }
+            ui.label(format!("Step {}", self.iter));
+        });
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("Densify now")
+                .on_hover_text("Refine the splats at the next step")
+                .clicked()
+            {
+                context.control_message(ControlMessage::Refine);
+            }
+            if ui
+                .button("Prune now")
+                .on_hover_text("Remove the transparent splats at the next step")
+                .clicked()
+            {
+                context.control_message(ControlMessage::Prune);
+            }
+        });
+
+        let Some(tweaks) = self.tweaks.as_mut() else {
+            return;
+        };
+
+        ui.heading("Learning rates");
+        let mut changed = false;
+        changed |= tweak_slider(ui, "Means", &mut tweaks.lr_mean, 1e-7..=1e-2, true);
+        changed |= tweak_slider(ui, "Colors", &mut tweaks.lr_coeffs_dc, 1e-5..=1e-1, true);
+        changed |= tweak_slider(ui, "Opacity", &mut tweaks.lr_opac, 1e-4..=1.0, true);
+        changed |= tweak_slider(ui, "Scales", &mut tweaks.lr_scale, 1e-5..=1e-1, true);
+        changed |= tweak_slider(ui, "Rotations", &mut tweaks.lr_rotation, 1e-5..=1e-1, true);
+
+        ui.heading("Loss weights");
+        changed |= tweak_slider(ui, "L1", &mut tweaks.l1_weight, 0.0..=1.0, false);
+        changed |= tweak_slider(ui, "L2", &mut tweaks.l2_weight, 0.0..=1.0, false);
+        changed |= tweak_slider(ui, "D-SSIM", &mut tweaks.dssim_weight, 0.0..=1.0, false);
+        changed |= tweak_slider(
+            ui,
+            "Opacity",
+            &mut tweaks.opac_loss_weight,
+            0.0..=0.1,
+            false,
+        );
+        changed |= tweak_slider(ui, "Depth", &mut tweaks.depth_loss_weight, 0.0..=1.0, false);
+
+        if changed {
+            context.control_message(ControlMessage::Tweak(*tweaks));
+        }
+    }
+}
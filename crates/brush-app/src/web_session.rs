@@ -0,0 +1,308 @@
+//! Browser only: importing files and folders dropped on the page, and keeping the last session
+//! in the origin private file system (OPFS), so it can be restored after a reload.
+//!
+//! A session is the files of the last import, and the latest splats trained on them, if any.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use brush_process::data_source::MemoryFiles;
+use eframe::egui;
+use js_sys::{Function, Promise, Reflect, Uint8Array};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_with_wasm::alias as tokio_wasm;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    DataTransferItem, DragEvent, File, FileSystemDirectoryHandle, FileSystemFileHandle,
+    FileSystemGetDirectoryOptions, FileSystemGetFileOptions, FileSystemHandle,
+    FileSystemHandleKind, FileSystemRemoveOptions, FileSystemWritableFileStream,
+};
+
+const SESSION_DIR: &str = "brush_session";
+// Paths of the imported files, one per line. File `i` is stored as `file_{i}`.
+const MANIFEST_FILE: &str = "manifest.txt";
+const SPLATS_FILE: &str = "splats.ply";
+
+/// Files to load in the app.
+pub(crate) struct Import {
+    pub(crate) files: MemoryFiles,
+    /// Whether to save the files as the new session.
+    pub(crate) save: bool,
+}
+
+fn js_err(e: JsValue) -> anyhow::Error {
+    anyhow::anyhow!("{e:?}")
+}
+
+async fn resolve<T: JsCast>(promise: Promise) -> Result<T> {
+    let value = JsFuture::from(promise).await.map_err(js_err)?;
+    value.dyn_into().map_err(js_err)
+}
+
+async fn file_bytes(file: &File) -> Result<Arc<[u8]>> {
+    let buffer: js_sys::ArrayBuffer = resolve(file.array_buffer()).await?;
+    Ok(Uint8Array::new(&buffer).to_vec().into())
+}
+
+async fn opfs_root() -> Result<FileSystemDirectoryHandle> {
+    let window = web_sys::window().context("No window")?;
+    resolve(window.navigator().storage().get_directory()).await
+}
+
+async fn session_dir(create: bool) -> Result<FileSystemDirectoryHandle> {
+    let options = FileSystemGetDirectoryOptions::new();
+    options.set_create(create);
+    resolve(
+        opfs_root()
+            .await?
+            .get_directory_handle_with_options(SESSION_DIR, &options),
+    )
+    .await
+}
+
+async fn write_file(dir: &FileSystemDirectoryHandle, name: &str, data: &[u8]) -> Result<()> {
+    let options = FileSystemGetFileOptions::new();
+    options.set_create(true);
+    let file: FileSystemFileHandle =
+        resolve(dir.get_file_handle_with_options(name, &options)).await?;
+    let writable: FileSystemWritableFileStream = resolve(file.create_writable()).await?;
+    resolve::<JsValue>(writable.write_with_u8_array(data).map_err(js_err)?).await?;
+    // Nothing is written until the stream is closed.
+    resolve::<JsValue>(writable.close()).await?;
+    Ok(())
+}
+
+async fn read_file(dir: &FileSystemDirectoryHandle, name: &str) -> Result<Arc<[u8]>> {
+    let handle: FileSystemFileHandle = resolve(dir.get_file_handle(name)).await?;
+    let file: File = resolve(handle.get_file()).await?;
+    file_bytes(&file).await
+}
+
+/// Remove the saved session.
+pub(crate) async fn clear_session() -> Result<()> {
+    let options = FileSystemRemoveOptions::new();
+    options.set_recursive(true);
+    // Fails when there is no session, which is fine.
+    let _ = resolve::<JsValue>(
+        opfs_root()
+            .await?
+            .remove_entry_with_options(SESSION_DIR, &options),
+    )
+    .await;
+    Ok(())
+}
+
+/// Replace the saved session with newly imported files.
+pub(crate) async fn save_import(files: MemoryFiles) -> Result<()> {
+    clear_session().await?;
+    let dir = session_dir(true).await?;
+    for (i, (_, data)) in files.files.iter().enumerate() {
+        write_file(&dir, &format!("file_{i}"), data).await?;
+    }
+    // Written last, so a session that was only partially saved isn't restored.
+    let manifest: Vec<_> = files
+        .files
+        .iter()
+        .map(|(path, _)| path.to_string_lossy())
+        .collect();
+    write_file(&dir, MANIFEST_FILE, manifest.join("\n").as_bytes()).await
+}
+
+/// Save splats trained in this session, replacing any saved earlier.
+pub(crate) async fn save_splats(ply: Vec<u8>) -> Result<()> {
+    write_file(&session_dir(true).await?, SPLATS_FILE, &ply).await
+}
+
+/// The files to load to restore the saved session, if there is one. When splats were trained,
+/// these are restored for viewing instead of training again from the dataset.
+pub(crate) async fn load_session() -> Result<Option<MemoryFiles>> {
+    let Ok(dir) = session_dir(false).await else {
+        return Ok(None);
+    };
+
+    if let Ok(splats) = read_file(&dir, SPLATS_FILE).await {
+        return Ok(Some(MemoryFiles {
+            files: vec![(PathBuf::from(SPLATS_FILE), splats)],
+        }));
+    }
+
+    let Ok(manifest) = read_file(&dir, MANIFEST_FILE).await else {
+        return Ok(None);
+    };
+    let manifest = String::from_utf8(manifest.to_vec())?;
+    let mut files = vec![];
+    for (i, path) in manifest.lines().enumerate() {
+        let data = read_file(&dir, &format!("file_{i}"))
+            .await
+            .with_context(|| format!("Missing saved file {path}"))?;
+        files.push((PathBuf::from(path), data));
+    }
+    Ok(Some(MemoryFiles { files }))
+}
+
+async fn read_directory(
+    dir: FileSystemDirectoryHandle,
+    path: PathBuf,
+    files: &mut Vec<(PathBuf, Arc<[u8]>)>,
+) -> Result<()> {
+    // Keep a stack of directories to read, as an async fn can't recurse without boxing.
+    let mut pending = vec![(dir, path)];
+
+    while let Some((dir, path)) = pending.pop() {
+        // Directory handles are async iterables, which web-sys has no bindings for.
+        let values: Function = Reflect::get(&dir, &JsValue::from_str("values"))
+            .map_err(js_err)?
+            .dyn_into()
+            .map_err(js_err)?;
+        let entries = values.call0(&dir).map_err(js_err)?;
+        let next: Function = Reflect::get(&entries, &JsValue::from_str("next"))
+            .map_err(js_err)?
+            .dyn_into()
+            .map_err(js_err)?;
+
+        loop {
+            let entry: Promise = next
+                .call0(&entries)
+                .map_err(js_err)?
+                .dyn_into()
+                .map_err(js_err)?;
+            let entry = JsFuture::from(entry).await.map_err(js_err)?;
+            let done = Reflect::get(&entry, &JsValue::from_str("done")).map_err(js_err)?;
+            if done.as_bool().unwrap_or(true) {
+                break;
+            }
+            let handle: FileSystemHandle = Reflect::get(&entry, &JsValue::from_str("value"))
+                .map_err(js_err)?
+                .dyn_into()
+                .map_err(js_err)?;
+            let entry_path = path.join(handle.name());
+
+            match handle.kind() {
+                FileSystemHandleKind::Directory => {
+                    pending.push((handle.unchecked_into(), entry_path));
+                }
+                _ => {
+                    let handle: FileSystemFileHandle = handle.unchecked_into();
+                    let file: File = resolve(handle.get_file()).await?;
+                    files.push((entry_path, file_bytes(&file).await?));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+enum Dropped {
+    Handle(Promise),
+    File(File),
+}
+
+fn dropped_item(item: &DataTransferItem) -> Option<Dropped> {
+    if item.kind() != "file" {
+        return None;
+    }
+    // Only Chromium can get the handle of a dropped folder, other browsers get the files.
+    let handle = Reflect::get(item, &JsValue::from_str("getAsFileSystemHandle"))
+        .ok()
+        .and_then(|f| f.dyn_into::<Function>().ok())
+        .and_then(|f| f.call0(item).ok())
+        .and_then(|p| p.dyn_into::<Promise>().ok());
+
+    match handle {
+        Some(handle) => Some(Dropped::Handle(handle)),
+        None => item.get_as_file().ok().flatten().map(Dropped::File),
+    }
+}
+
+async fn read_dropped(dropped: Vec<Dropped>) -> Result<MemoryFiles> {
+    let mut files = vec![];
+    for dropped in dropped {
+        match dropped {
+            Dropped::Handle(handle) => {
+                let Some(handle) = JsFuture::from(handle)
+                    .await
+                    .map_err(js_err)?
+                    .dyn_into::<FileSystemHandle>()
+                    .ok()
+                else {
+                    continue;
+                };
+                let path = PathBuf::from(handle.name());
+                match handle.kind() {
+                    FileSystemHandleKind::Directory => {
+                        read_directory(handle.unchecked_into(), path, &mut files).await?;
+                    }
+                    _ => {
+                        let handle: FileSystemFileHandle = handle.unchecked_into();
+                        let file: File = resolve(handle.get_file()).await?;
+                        files.push((path, file_bytes(&file).await?));
+                    }
+                }
+            }
+            Dropped::File(file) => {
+                files.push((PathBuf::from(file.name()), file_bytes(&file).await?));
+            }
+        }
+    }
+    Ok(MemoryFiles { files })
+}
+
+/// Import the files and folders dropped anywhere on the page.
+pub(crate) fn listen_for_drops(ctx: egui::Context, imports: UnboundedSender<Import>) {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+
+    let on_drop = Closure::<dyn Fn(DragEvent)>::new(move |event: DragEvent| {
+        let Some(transfer) = event.data_transfer() else {
+            return;
+        };
+        let items = transfer.items();
+        // The items can only be read while handling the event.
+        let dropped: Vec<_> = (0..items.length())
+            .filter_map(|i| items.get(i))
+            .filter_map(|item| dropped_item(&item))
+            .collect();
+        if dropped.is_empty() {
+            return;
+        }
+        event.prevent_default();
+        event.stop_propagation();
+
+        let ctx = ctx.clone();
+        let imports = imports.clone();
+        tokio_wasm::task::spawn(async move {
+            match read_dropped(dropped).await {
+                Ok(files) if !files.files.is_empty() => {
+                    let _ = imports.send(Import { files, save: true });
+                    ctx.request_repaint();
+                }
+                Ok(_) => log::warn!("Dropped folder is empty"),
+                Err(e) => log::error!("Failed to read dropped files: {e:#}"),
+            }
+        });
+    });
+
+    // Without this the browser opens dropped files itself.
+    let on_drag_over = Closure::<dyn Fn(DragEvent)>::new(|event: DragEvent| {
+        event.prevent_default();
+    });
+
+    // Listen in the capture phase, before the canvas sees the drop.
+    let _ = window.add_event_listener_with_callback_and_bool(
+        "drop",
+        on_drop.as_ref().unchecked_ref(),
+        true,
+    );
+    let _ = window.add_event_listener_with_callback_and_bool(
+        "dragover",
+        on_drag_over.as_ref().unchecked_ref(),
+        true,
+    );
+
+    // The listeners stay for the lifetime of the page.
+    on_drop.forget();
+    on_drag_over.forget();
+}
@@ -0,0 +1,77 @@
+//! Render a ball of splats next to a Bevy mesh, occluding each other.
+//!
+//! Run with `cargo run -p brush-bevy --example splats`.
+use bevy::prelude::*;
+use brush_bevy::{BrushDevice, BrushSplatPlugin, SplatModel};
+use brush_render::{gaussian_splats::Splats, render::rgb_to_sh};
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, BrushSplatPlugin))
+        .add_systems(Startup, setup)
+        .add_systems(Update, spin)
+        .run();
+}
+
+// Splats spread evenly over a sphere, colored by their direction.
+fn splat_ball(device: &BrushDevice, count: usize) -> Splats<burn::backend::Wgpu> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    let means: Vec<_> = (0..count)
+        .map(|i| {
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+            let r = (1.0 - y * y).sqrt();
+            let theta = golden_angle * i as f32;
+            glam::vec3(r * theta.cos(), y, r * theta.sin())
+        })
+        .collect();
+    let colors: Vec<f32> = means
+        .iter()
+        .flat_map(|m| (*m * 0.5 + 0.5).to_array().map(rgb_to_sh))
+        .collect();
+    let log_scales = vec![glam::Vec3::splat(0.03f32.ln()); count];
+    Splats::from_raw(
+        &means,
+        None,
+        Some(&log_scales),
+        Some(&colors),
+        None,
+        &device.0,
+    )
+}
+
+#[derive(Component)]
+struct Spin;
+
+fn setup(
+    mut commands: Commands,
+    device: Res<BrushDevice>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_xyz(0.0, 1.0, 4.0).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+    commands.spawn((
+        DirectionalLight::default(),
+        Transform::from_xyz(1.0, 2.0, 1.0).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+
+    // The cube pokes through the ball, to show the splats are depth tested against the scene.
+    commands.spawn((
+        Mesh3d(meshes.add(Cuboid::new(0.8, 0.8, 0.8))),
+        MeshMaterial3d(materials.add(Color::srgb(0.8, 0.7, 0.6))),
+        Transform::from_xyz(0.9, 0.0, 0.0),
+    ));
+    commands.spawn((
+        SplatModel(splat_ball(&device, 20000)),
+        Transform::default(),
+        Spin,
+    ));
+}
+
+fn spin(time: Res<Time>, mut models: Query<&mut Transform, With<Spin>>) {
+    for mut transform in &mut models {
+        transform.rotate_y(time.delta_secs() * 0.5);
+    }
+}
@@ -0,0 +1,42 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+struct Params {
+    // Near plane of the view. Bevy uses a reversed infinite projection, so the view space
+    // depth of a depth buffer value d is near / d.
+    near: f32,
+    pad0: f32,
+    pad1: f32,
+    pad2: f32,
+}
+
+@group(0) @binding(0) var splat_color: texture_2d<f32>;
+@group(0) @binding(1) var splat_depth: texture_2d<f32>;
+@group(0) @binding(2) var scene_depth: texture_depth_2d;
+@group(0) @binding(3) var<uniform> params: Params;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let coord = vec2i(in.position.xy);
+    let color = textureLoad(splat_color, coord, 0);
+
+    if color.a < 1.0 / 255.0 {
+        discard;
+    }
+
+    let depth = textureLoad(splat_depth, coord, 0).r;
+    let scene = textureLoad(scene_depth, coord, 0);
+
+    if scene > 0.0 && params.near / scene < depth {
+        discard;
+    }
+
+    // Splat colors are in sRGB, while Bevy expects linear colors. Colors are premultiplied by
+    // alpha, so convert the straight color.
+    let rgb = color.rgb / color.a;
+    let linear = select(
+        pow((rgb + 0.055) / 1.055, vec3f(2.4)),
+        rgb / 12.92,
+        rgb <= vec3f(0.04045),
+    );
+    return vec4f(linear * color.a, color.a);
+}
@@ -0,0 +1,404 @@
+//! Render Brush splats in Bevy.
+//!
+//! Add [`BrushSplatPlugin`] to an app, load splats on the [`BrushDevice`] and spawn them as a
+//! [`SplatModel`]. The splats are rendered with the Brush renderer on Bevy's wgpu device,
+//! after the transparent pass of every 3D camera, and are occluded by the scene's depth buffer.
+//!
+//! Compositing reads the depth buffer of the camera, so the plugin turns off MSAA and enables
+//! texture binding for the depth texture of every 3D camera.
+use bevy::{
+    asset::{load_internal_asset, weak_handle},
+    core_pipeline::{
+        core_3d::graph::{Core3d, Node3d},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::query::QueryItem,
+    image::BevyDefault,
+    prelude::*,
+    render::{
+        camera::ExtractedCamera,
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+        },
+        render_resource::{
+            binding_types::{texture_2d, texture_depth_2d, uniform_buffer_sized},
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, BlendState,
+            CachedRenderPipelineId, ColorTargetState, ColorWrites, FragmentState, PipelineCache,
+            RenderPassDescriptor, RenderPipelineDescriptor, ShaderStages, TextureFormat,
+            TextureSampleType, TextureUsages,
+        },
+        renderer::{RenderAdapter, RenderContext, RenderDevice, RenderQueue},
+        view::{ExtractedView, ViewDepthTexture, ViewTarget},
+        Extract, ExtractSchedule, RenderApp,
+    },
+};
+use brush_render::{
+    camera::{focal_to_fov, fov_to_focal, Camera},
+    gaussian_splats::Splats,
+};
+use burn::{
+    backend::{
+        wgpu::{JitBackend, WgpuRuntime},
+        Wgpu,
+    },
+    tensor::{Tensor, TensorPrimitive},
+};
+use burn_fusion::client::FusionClient;
+use burn_wgpu::WgpuDevice;
+use wgpu::util::DeviceExt;
+
+type InnerWgpu = JitBackend<WgpuRuntime, f32, i32, u32>;
+
+const COMPOSITE_SHADER: Handle<Shader> = weak_handle!("5e4a2a0c-7b0e-4f3c-9d59-2f5a0b8c1e11");
+
+/// The rows of textures copied from buffers need to be aligned to 256 bytes. Rendering at a
+/// width that's a multiple of this satisfies that for both the color and depth images.
+const ROW_ALIGN: u32 = 64;
+
+/// The Burn device that shares Bevy's wgpu device. Splats need to be created on this device to
+/// be rendered.
+#[derive(Resource, Clone)]
+pub struct BrushDevice(pub WgpuDevice);
+
+/// A splat model to render. The model is placed with its [`Transform`].
+#[derive(Component, Clone)]
+#[require(Transform, Visibility)]
+pub struct SplatModel(pub Splats<Wgpu>);
+
+pub struct BrushSplatPlugin;
+
+impl Plugin for BrushSplatPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, COMPOSITE_SHADER, "composite.wgsl", Shader::from_wgsl);
+
+        app.add_systems(Update, configure_cameras);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<ExtractedSplats>()
+            .add_systems(ExtractSchedule, extract_splats)
+            .add_render_graph_node::<ViewNodeRunner<SplatNode>>(Core3d, SplatLabel)
+            .add_render_graph_edges(
+                Core3d,
+                (Node3d::MainTransparentPass, SplatLabel, Node3d::EndMainPass),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let world = app.world();
+        let adapter: &wgpu::Adapter = world.resource::<RenderAdapter>();
+        let queue: &wgpu::Queue = world.resource::<RenderQueue>();
+        let device = world.resource::<RenderDevice>().wgpu_device();
+
+        let brush_device =
+            brush_render::burn_init_device(adapter.clone(), device.clone(), queue.clone());
+        app.insert_resource(BrushDevice(brush_device));
+
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.init_resource::<CompositePipeline>();
+        }
+    }
+}
+
+fn configure_cameras(
+    mut commands: Commands,
+    mut cameras: Query<(Entity, &mut Camera3d), Added<Camera3d>>,
+) {
+    for (entity, mut camera) in &mut cameras {
+        camera.depth_texture_usages =
+            (TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING).into();
+        commands.entity(entity).insert(Msaa::Off);
+    }
+}
+
+// Bevy uses a newer glam, so convert through plain arrays.
+fn to_brush_affine(transform: &GlobalTransform) -> glam::Affine3A {
+    glam::Affine3A::from_cols_array(&transform.affine().to_cols_array())
+}
+
+#[derive(Resource, Default)]
+struct ExtractedSplats {
+    splats: Option<Splats<Wgpu>>,
+    // The models and transforms the splats were merged from. Merging copies all splats, so
+    // this is only done again when a model or its transform changes.
+    merged_from: Vec<(Entity, glam::Affine3A)>,
+}
+
+fn extract_splats(
+    mut extracted: ResMut<ExtractedSplats>,
+    models: Extract<
+        Query<(
+            Entity,
+            Ref<SplatModel>,
+            &GlobalTransform,
+            &InheritedVisibility,
+        )>,
+    >,
+) {
+    let visible: Vec<_> = models
+        .iter()
+        .filter(|(_, _, _, visibility)| visibility.get())
+        .collect();
+
+    let merged_from: Vec<_> = visible
+        .iter()
+        .map(|(entity, _, transform, _)| (*entity, to_brush_affine(transform)))
+        .collect();
+    let models_changed = visible.iter().any(|(_, model, _, _)| model.is_changed());
+    if !models_changed && merged_from == extracted.merged_from {
+        return;
+    }
+
+    let visible: Vec<_> = visible
+        .iter()
+        .zip(&merged_from)
+        .map(|((_, model, _, _), (_, transform))| (&model.0, *transform))
+        .collect();
+
+    // All models are merged, so splats of different models are sorted together.
+    extracted.splats = match visible.as_slice() {
+        [] => None,
+        [(splats, transform)] if *transform == glam::Affine3A::IDENTITY => Some((*splats).clone()),
+        _ => Some(Splats::merge_transformed(visible.iter().copied())),
+    };
+    extracted.merged_from = merged_from;
+}
+
+#[derive(Resource)]
+struct CompositePipeline {
+    layout: BindGroupLayout,
+    ldr: CachedRenderPipelineId,
+    hdr: CachedRenderPipelineId,
+}
+
+impl FromWorld for CompositePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(
+            "brush_splat_composite_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    texture_depth_2d(),
+                    uniform_buffer_sized(false, None),
+                ),
+            ),
+        );
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let queue_pipeline = |format: TextureFormat| {
+            pipeline_cache.queue_render_pipeline(RenderPipelineDescriptor {
+                label: Some("brush_splat_composite_pipeline".into()),
+                layout: vec![layout.clone()],
+                push_constant_ranges: vec![],
+                vertex: fullscreen_shader_vertex_state(),
+                fragment: Some(FragmentState {
+                    shader: COMPOSITE_SHADER,
+                    shader_defs: vec![],
+                    entry_point: "fragment".into(),
+                    targets: vec![Some(ColorTargetState {
+                        format,
+                        blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: default(),
+                depth_stencil: None,
+                multisample: default(),
+                zero_initialize_workgroup_memory: false,
+            })
+        };
+
+        let ldr = queue_pipeline(TextureFormat::bevy_default());
+        let hdr = queue_pipeline(ViewTarget::TEXTURE_FORMAT_HDR);
+        Self { layout, ldr, hdr }
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct SplatLabel;
+
+#[derive(Default)]
+struct SplatNode;
+
+// Convert a Bevy view to a Brush camera, rendering an image of `render_size` which contains the
+// view in its top left `size` pixels.
+fn brush_camera(view: &ExtractedView, size: glam::UVec2, render_size: glam::UVec2) -> Camera {
+    let (_, rotation, position) = view.world_from_view.to_scale_rotation_translation();
+
+    // Bevy cameras look along -z with y up, Brush cameras look along +z with y down.
+    let rotation = glam::Quat::from_array(rotation.to_array())
+        * glam::Quat::from_rotation_x(std::f32::consts::PI);
+    let position = glam::Vec3::from_array(position.to_array());
+
+    let proj = view.clip_from_view;
+    let fov_x = 2.0 * (1.0 / proj.x_axis.x as f64).atan();
+    let fov_y = 2.0 * (1.0 / proj.y_axis.y as f64).atan();
+
+    // Keep the focal length and principal point in pixels the same for the wider image.
+    let fov_x = focal_to_fov(fov_to_focal(fov_x, size.x), render_size.x);
+    let center_uv = glam::vec2(0.5 * size.x as f32 / render_size.x as f32, 0.5);
+
+    Camera::new(position, rotation, fov_x, fov_y, center_uv)
+}
+
+// Render the premultiplied color and the view space depth of the splats.
+fn render_color_depth(
+    splats: &Splats<Wgpu>,
+    camera: &Camera,
+    img_size: glam::UVec2,
+) -> (Tensor<Wgpu, 3>, Tensor<Wgpu, 3>) {
+    let (h, w) = (img_size.y as usize, img_size.x as usize);
+    let (color, aux) = splats.render_with_depth(camera, img_size);
+    let depth = aux
+        .depth
+        .expect("Depth is rendered with render_with_depth")
+        .reshape([h, w, 1]);
+    (color, depth)
+}
+
+// Copy the top left `size` pixels of an image to a new texture.
+fn copy_to_texture(
+    encoder: &mut wgpu::CommandEncoder,
+    device: &wgpu::Device,
+    img: Tensor<Wgpu, 3>,
+    format: wgpu::TextureFormat,
+    size: glam::UVec2,
+) -> wgpu::Texture {
+    let [_, width, channels] = img.dims();
+
+    let img_prim = img.into_primitive().tensor();
+    let fusion_client = img_prim.client.clone();
+    let img = fusion_client.resolve_tensor_float::<InnerWgpu>(img_prim);
+
+    let client = &img.client;
+    let resource = client.get_resource(img.handle.clone().binding());
+    // Make sure the image is fully rendered before copying it.
+    client.flush();
+
+    let extent = wgpu::Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Brush splat image"),
+        size: extent,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    encoder.copy_buffer_to_texture(
+        wgpu::TexelCopyBufferInfo {
+            buffer: &resource.resource().buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: resource.resource().offset(),
+                bytes_per_row: Some((width * channels * 4) as u32),
+                rows_per_image: None,
+            },
+        },
+        wgpu::TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        extent,
+    );
+
+    texture
+}
+
+impl ViewNode for SplatNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ViewDepthTexture,
+        &'static ExtractedView,
+        &'static ExtractedCamera,
+    );
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (target, scene_depth, view, camera): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let Some(splats) = world.resource::<ExtractedSplats>().splats.as_ref() else {
+            return Ok(());
+        };
+        let Some(size) = camera.physical_viewport_size else {
+            return Ok(());
+        };
+        let composite = world.resource::<CompositePipeline>();
+        let pipeline_id = if view.hdr {
+            composite.hdr
+        } else {
+            composite.ldr
+        };
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(pipeline_id)
+        else {
+            return Ok(());
+        };
+
+        let size = glam::uvec2(size.x, size.y);
+        let render_size = glam::uvec2(size.x.next_multiple_of(ROW_ALIGN), size.y);
+        let brush_cam = brush_camera(view, size, render_size);
+        let (color, depth) = render_color_depth(splats, &brush_cam, render_size);
+
+        let device = render_context.render_device().wgpu_device().clone();
+        let encoder = render_context.command_encoder();
+        let color = copy_to_texture(
+            encoder,
+            &device,
+            color,
+            wgpu::TextureFormat::Rgba32Float,
+            size,
+        );
+        let depth = copy_to_texture(encoder, &device, depth, wgpu::TextureFormat::R32Float, size);
+
+        let near = view.clip_from_view.w_axis.z;
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Brush composite params"),
+            contents: bytemuck::cast_slice(&[near, 0.0, 0.0, 0.0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let color_view = color.create_view(&Default::default());
+        let depth_view = depth.create_view(&Default::default());
+        let bind_group = render_context.render_device().create_bind_group(
+            "brush_splat_composite_bind_group",
+            &composite.layout,
+            &BindGroupEntries::sequential((
+                wgpu::BindingResource::TextureView(&color_view),
+                wgpu::BindingResource::TextureView(&depth_view),
+                wgpu::BindingResource::TextureView(scene_depth.view()),
+                params.as_entire_binding(),
+            )),
+        );
+
+        let mut pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("brush_splat_composite_pass"),
+            color_attachments: &[Some(target.get_color_attachment())],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_render_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Context;
+use brush_dataset::{colmap_export::views_to_colmap, load_dataset};
+use brush_train::checkpoint::load_pose_refinement;
+use burn_wgpu::{Wgpu, WgpuDevice};
+use tokio_stream::StreamExt;
+
+use crate::ExportColmapArgs;
+
+/// Load a dataset, and write its cameras as a COLMAP text model, see [`ExportColmapArgs`].
+pub async fn export_colmap_cli(args: ExportColmapArgs, device: WgpuDevice) -> anyhow::Result<()> {
+    let vfs = args.source.into_vfs().await?;
+    let (_, mut data_stream) = load_dataset::<Wgpu>(vfs, &args.load_config, &device).await?;
+
+    // The loader sends progressively more views, only export the full dataset.
+    let mut dataset = None;
+    while let Some(d) = data_stream.next().await {
+        dataset = Some(d?);
+    }
+    let dataset = dataset.context("No views in dataset")?;
+
+    let mut views = dataset.train.views.to_vec();
+    if let Some(path) = &args.checkpoint {
+        let data = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Failed to read checkpoint {path:?}"))?;
+        if let Some(pose) = load_pose_refinement(&data, &device)? {
+            let cameras: Vec<_> = views.iter().map(|v| v.camera.clone()).collect();
+            let corrected = pose.corrected_cameras(&cameras).await;
+            for (view, camera) in views.iter_mut().zip(corrected) {
+                view.camera = camera;
+            }
+        } else {
+            log::warn!("Checkpoint has no pose corrections, exporting the dataset poses");
+        }
+    }
+    if let Some(eval) = &dataset.eval {
+        views.extend(eval.views.iter().cloned());
+    }
+
+    let (cameras, images, points) = views_to_colmap(&views);
+    tokio::fs::create_dir_all(&args.out).await?;
+    tokio::fs::write(args.out.join("cameras.txt"), cameras).await?;
+    tokio::fs::write(args.out.join("images.txt"), images).await?;
+    tokio::fs::write(args.out.join("points3D.txt"), points).await?;
+    log::info!("Exported {} views to {}", views.len(), args.out.display());
+    Ok(())
+}
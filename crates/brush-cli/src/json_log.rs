@@ -0,0 +1,97 @@
+use std::time::Instant;
+
+use brush_process::process_loop::{ProcessMessage, RunningProcess};
+use serde_json::json;
+
+fn emit(event: &serde_json::Value) {
+    println!("{event}");
+}
+
+/// Report the progress of a process as JSON lines on stdout, one object per event. This is
+/// meant for batch jobs, where the output is parsed or collected by other tools.
+///
+/// Training steps are reported every `log_every` steps, as reading back the loss stalls
+/// training a little.
+pub async fn process_json(process: RunningProcess, log_every: u32) {
+    let mut process = process;
+    let start = Instant::now();
+    let log_every = log_every.max(1);
+    let total_steps = process.start_args.train_config.total_steps;
+
+    while let Some(msg) = process.messages.recv().await {
+        match msg {
+            ProcessMessage::NewSource | ProcessMessage::DoneLoading { .. } => {}
+            ProcessMessage::StartLoading { training } => {
+                if !training {
+                    emit(&json!({
+                        "event": "error",
+                        "message": "Only datasets can be trained on, not splats",
+                    }));
+                    break;
+                }
+            }
+            ProcessMessage::Error(error) => {
+                emit(&json!({ "event": "error", "message": format!("{error:?}") }));
+                break;
+            }
+            ProcessMessage::ViewSplats { .. } | ProcessMessage::ViewChunks { .. } => {}
+            ProcessMessage::Dataset { data } => {
+                emit(&json!({
+                    "event": "dataset",
+                    "train_views": data.train.views.len(),
+                    "eval_views": data.eval.as_ref().map_or(0, |v| v.views.len()),
+                }));
+            }
+            ProcessMessage::TrainStep {
+                splats,
+                stats,
+                iter,
+                timestamp: _,
+            } => {
+                if iter % log_every != 0 && iter + 1 != total_steps {
+                    continue;
+                }
+                let loss = stats
+                    .loss
+                    .into_data_async()
+                    .await
+                    .to_vec::<f32>()
+                    .ok()
+                    .and_then(|l| l.first().copied());
+                emit(&json!({
+                    "event": "train_step",
+                    "iter": iter,
+                    "total_steps": total_steps,
+                    "loss": loss,
+                    "num_splats": splats.num_splats(),
+                    "elapsed_secs": start.elapsed().as_secs_f64(),
+                }));
+            }
+            ProcessMessage::RefineStep { stats, iter } => {
+                emit(&json!({
+                    "event": "refine",
+                    "iter": iter,
+                    "num_split": stats.num_split,
+                    "num_cloned": stats.num_cloned,
+                    "num_transparent_pruned": stats.num_transparent_pruned,
+                    "num_scale_pruned": stats.num_scale_pruned,
+                    "num_budget_pruned": stats.num_budget_pruned,
+                }));
+            }
+            ProcessMessage::EvalResult {
+                iter,
+                avg_psnr,
+                avg_ssim,
+                avg_lpips,
+            } => {
+                emit(&json!({
+                    "event": "eval",
+                    "iter": iter,
+                    "psnr": avg_psnr,
+                    "ssim": avg_ssim,
+                    "lpips": avg_lpips,
+                }));
+            }
+        }
+    }
+}
@@ -1,18 +1,125 @@
 #![recursion_limit = "256"]
 
+pub mod export_colmap;
+pub mod json_log;
+pub mod remote;
+pub mod render_path;
 pub mod ui;
 
-use brush_process::{data_source::DataSource, process_loop::ProcessArgs};
-use clap::{builder::ArgPredicate, error::ErrorKind, Error, Parser};
+use std::path::PathBuf;
+
+use brush_dataset::LoadDataseConfig;
+use brush_process::{bench::BenchConfig, data_source::DataSource, process_loop::ProcessArgs};
+use clap::{builder::ArgPredicate, error::ErrorKind, Args, Error, Parser, Subcommand};
+
+#[derive(Args)]
+pub struct TrainArgs {
+    /// Dataset to train on (path or URL).
+    #[arg(value_name = "PATH_OR_URL")]
+    pub source: DataSource,
+
+    /// Directory to write checkpoints and exports to. Overrides --export-path.
+    #[arg(long)]
+    pub out: String,
+
+    /// Report progress as JSON lines on stdout, instead of progress bars.
+    #[arg(long, default_value = "false")]
+    pub json: bool,
+
+    /// Report training steps every this many steps when using --json.
+    #[arg(long, default_value = "100")]
+    pub log_every: u32,
+
+    /// Serve the training to remote viewers over WebSocket on this address, eg. 0.0.0.0:7878.
+    /// Load ws://<host>:7878 in the viewer to watch.
+    #[arg(long)]
+    pub serve: Option<std::net::SocketAddr>,
+
+    /// Send the splats to remote viewers every this many seconds when using --serve.
+    #[arg(long, default_value = "10")]
+    pub serve_every: u64,
+
+    #[clap(flatten)]
+    pub process: ProcessArgs,
+}
+
+#[derive(Args)]
+pub struct RenderPathArgs {
+    /// Splats to render, a .ply file.
+    #[arg(value_name = "PLY")]
+    pub splats: PathBuf,
+
+    /// Where to write the render: a video file (.mp4, .mov, .mkv or .webm), or otherwise a
+    /// directory for a PNG sequence. Videos are encoded with ffmpeg, which needs to be installed.
+    #[arg(long)]
+    pub out: PathBuf,
+
+    /// Camera path JSON file to follow. Renders a turntable around the splats if not set.
+    #[arg(long)]
+    pub path: Option<PathBuf>,
+
+    /// Length of the turntable in seconds, when no camera path is set.
+    #[arg(long, default_value = "8")]
+    pub duration: f32,
+
+    /// Width of the frames in pixels.
+    #[arg(long, default_value = "1920")]
+    pub width: u32,
+
+    /// Height of the frames in pixels.
+    #[arg(long, default_value = "1080")]
+    pub height: u32,
+
+    /// Frames per second.
+    #[arg(long, default_value = "30")]
+    pub fps: f32,
+}
+
+#[derive(Args)]
+pub struct ExportColmapArgs {
+    /// Dataset to export the cameras of (path or URL).
+    #[arg(value_name = "PATH_OR_URL")]
+    pub source: DataSource,
+
+    /// Directory to write the cameras.txt, images.txt and points3D.txt to.
+    #[arg(long)]
+    pub out: PathBuf,
+
+    /// Checkpoint of a training run with pose refinement, to export the train views with their
+    /// corrected camera poses. The dataset has to be loaded with the same options as for training.
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub load_config: LoadDataseConfig,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Train on a dataset without opening a window. Checkpoints are exported to the output
+    /// directory every --export-every steps.
+    Train(TrainArgs),
+    /// Benchmark training on a synthetic scene, and report the timings as JSON.
+    BenchTrain(BenchConfig),
+    /// Render a video of splats along a camera path, or a turntable around them.
+    RenderPath(RenderPathArgs),
+    /// Write the camera poses and intrinsics of a dataset as a COLMAP text model. The images of
+    /// camera rigs are written as individual images.
+    ExportColmap(ExportColmapArgs),
+}
 
 #[derive(Parser)]
 #[command(
     author,
     version,
     arg_required_else_help = false,
+    args_conflicts_with_subcommands = true,
     about = "Brush - universal splats"
 )]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Source to load from (path or URL).
     #[arg(value_name = "PATH_OR_URL")]
     pub source: Option<DataSource>,
@@ -31,7 +138,7 @@ pub struct Cli {
 
 impl Cli {
     pub fn validate(self) -> Result<Self, Error> {
-        if !self.with_viewer && self.source.is_none() {
+        if self.command.is_none() && !self.with_viewer && self.source.is_none() {
             return Err(Error::raw(
                 ErrorKind::MissingRequiredArgument,
                 "When --with-viewer is false, --source must be provided",
@@ -0,0 +1,165 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use brush_dataset::splat_export::ExportFormat;
+use brush_process::{
+    process_loop::{ProcessMessage, RunningProcess},
+    remote::RemoteEvent,
+};
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc::channel, watch},
+};
+use tokio_tungstenite::tungstenite::Message;
+
+type Snapshot = Option<Arc<Vec<u8>>>;
+
+/// Serve a process to remote viewers over WebSocket, see [`brush_process::remote`]. Viewers
+/// connect by loading `ws://<addr>`.
+///
+/// Returns the process with the same messages, so it can still be shown locally. The splats
+/// are exported on their own task, so the process doesn't wait on them, and sent at most every
+/// `interval`. Splats that arrive while an export is still running are skipped.
+pub async fn serve_remote(
+    process: RunningProcess,
+    addr: SocketAddr,
+    interval: Duration,
+) -> anyhow::Result<RunningProcess> {
+    let listener = TcpListener::bind(addr).await?;
+
+    let (snapshot_send, snapshot_rec) = watch::channel::<Snapshot>(None);
+    let snapshot_send = Arc::new(snapshot_send);
+    let (event_send, _) = broadcast::channel::<String>(64);
+
+    let events = event_send.clone();
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log::error!("Failed to accept remote viewer: {e}");
+                    continue;
+                }
+            };
+            let (snapshots, events) = (snapshot_rec.clone(), events.subscribe());
+            tokio::spawn(async move {
+                if let Err(e) = serve_viewer(stream, snapshots, events).await {
+                    log::warn!("Remote viewer disconnected: {e}");
+                }
+            });
+        }
+    });
+
+    let (sender, receiver) = channel(1);
+    let mut messages = process.messages;
+    tokio::spawn(async move {
+        let mut last_snapshot: Option<Instant> = None;
+        let exporting = Arc::new(AtomicBool::new(false));
+
+        while let Some(message) = messages.recv().await {
+            let splats = match &message {
+                ProcessMessage::TrainStep { splats, .. }
+                    if last_snapshot.is_none_or(|t| t.elapsed() >= interval) =>
+                {
+                    Some(splats)
+                }
+                ProcessMessage::ViewSplats { splats, .. } => Some(splats),
+                _ => None,
+            };
+            if let Some(splats) = splats {
+                if !exporting.swap(true, Ordering::AcqRel) {
+                    last_snapshot = Some(Instant::now());
+                    let splats = *splats.clone();
+                    let (exporting, snapshot_send) = (exporting.clone(), snapshot_send.clone());
+                    tokio::spawn(async move {
+                        match ExportFormat::Ply.export(splats).await {
+                            Ok(data) => {
+                                let _ = snapshot_send.send(Some(Arc::new(data)));
+                            }
+                            Err(e) => {
+                                log::error!("Failed to export splats for remote viewers: {e}");
+                            }
+                        }
+                        exporting.store(false, Ordering::Release);
+                    });
+                }
+            }
+
+            let event = match &message {
+                ProcessMessage::EvalResult {
+                    iter,
+                    avg_psnr,
+                    avg_ssim,
+                    avg_lpips,
+                } => Some(RemoteEvent::Eval {
+                    iter: *iter,
+                    avg_psnr: *avg_psnr,
+                    avg_ssim: *avg_ssim,
+                    avg_lpips: *avg_lpips,
+                }),
+                ProcessMessage::Error(e) => Some(RemoteEvent::Error {
+                    message: format!("{e:?}"),
+                }),
+                _ => None,
+            };
+            if let Some(event) = event {
+                if let Ok(json) = serde_json::to_string(&event) {
+                    // Fails when no viewers are connected, which is fine.
+                    let _ = event_send.send(json);
+                }
+            }
+
+            if sender.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(RunningProcess {
+        messages: receiver,
+        ..process
+    })
+}
+
+async fn serve_viewer(
+    stream: TcpStream,
+    mut snapshots: watch::Receiver<Snapshot>,
+    mut events: broadcast::Receiver<String>,
+) -> anyhow::Result<()> {
+    let mut socket = tokio_tungstenite::accept_async(stream).await?;
+
+    // Send the latest splats right away, then whenever they change.
+    snapshots.mark_changed();
+    loop {
+        tokio::select! {
+            changed = snapshots.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+                let snapshot = snapshots.borrow_and_update().clone();
+                if let Some(data) = snapshot {
+                    socket.send(Message::binary(data.to_vec())).await?;
+                }
+            }
+            // Viewers don't send anything, but this notices when they disconnect.
+            message = socket.next() => match message {
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Err(e)) => return Err(e.into()),
+                Some(Ok(_)) => {}
+            },
+            event = events.recv() => match event {
+                Ok(json) => socket.send(Message::text(json)).await?,
+                // A slow viewer misses some events.
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use std::io::Cursor;
+
+use anyhow::Context;
+use brush_dataset::splat_import::load_splat_from_ply;
+use brush_process::render_path::{orbit_path, render_path};
+use brush_render::camera_path::CameraPath;
+use burn_wgpu::WgpuDevice;
+use glam::{uvec2, Vec3};
+use tokio_stream::StreamExt;
+
+use crate::RenderPathArgs;
+
+/// Render the splats of a ply file along a camera path, see [`RenderPathArgs`].
+pub async fn render_path_cli(args: &RenderPathArgs, device: WgpuDevice) -> anyhow::Result<()> {
+    let data = tokio::fs::read(&args.splats)
+        .await
+        .with_context(|| format!("Failed to read {}", args.splats.display()))?;
+    let stream = load_splat_from_ply(Cursor::new(data), None, device);
+    let mut stream = std::pin::pin!(stream);
+
+    // The loader sends progressively more splats, only render the full set.
+    let mut last = None;
+    while let Some(message) = stream.next().await {
+        last = Some(message?);
+    }
+    let message = last.context("No splats in file")?;
+
+    let path = match &args.path {
+        Some(path) => CameraPath::from_json(&tokio::fs::read_to_string(path).await?)?,
+        // Without an up axis, splats are shown with -y up, like in the viewer.
+        None => {
+            let up = message.meta.up_axis.unwrap_or(Vec3::NEG_Y);
+            orbit_path(&message.splats, up, args.duration).await?
+        }
+    };
+
+    let size = uvec2(args.width, args.height);
+    render_path(&message.splats, &path, size, args.fps, &args.out).await?;
+    log::info!("Rendered {}", args.out.display());
+    Ok(())
+}
@@ -83,7 +83,7 @@ pub async fn process_ui(process: RunningProcess) {
                 let _ = sp.println(format!("❌ Error: {error:?}"));
                 break;
             }
-            ProcessMessage::ViewSplats { .. } => {
+            ProcessMessage::ViewSplats { .. } | ProcessMessage::ViewChunks { .. } => {
                 // I guess we're already showing a warning.
             }
             ProcessMessage::Dataset { data } => {
@@ -121,9 +121,10 @@ pub async fn process_ui(process: RunningProcess) {
                 iter,
                 avg_psnr,
                 avg_ssim,
+                avg_lpips,
             } => {
                 eval_spinner.set_message(format!(
-                    "Eval iter {iter}: PSNR {avg_psnr}, ssim {avg_ssim}"
+                    "Eval iter {iter}: PSNR {avg_psnr}, ssim {avg_ssim}, lpips-lite {avg_lpips}"
                 ));
                 // Show eval results.
             }
@@ -4,9 +4,12 @@
 // [1] really we want to just read directories.
 // The reason is that picking directories isn't supported on
 // rfd on wasm, nor is drag-and-dropping folders in egui.
+//
+// Zip (including ZIP64) and tar archives are supported, optionally gzipped. An archive that only
+// contains another archive, as happens when zipping an archive again, is opened as that archive.
 use std::{
     collections::HashMap,
-    io::{Cursor, Read},
+    io::{Cursor, Read, Seek, SeekFrom},
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -42,6 +45,101 @@ impl AsRef<[u8]> for ZipData {
     }
 }
 
+/// A file that can be read by several clones at once, each with its own position. Lets large
+/// zip archives be read without loading the whole archive into memory.
+#[cfg(not(target_family = "wasm"))]
+#[derive(Clone)]
+pub struct SharedFile {
+    file: Arc<std::sync::Mutex<std::fs::File>>,
+    len: u64,
+    pos: u64,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl SharedFile {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            file: Arc::new(std::sync::Mutex::new(file)),
+            len,
+            pos: 0,
+        })
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Read for SharedFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut file = self.file.lock().expect("Archive file lock poisoned");
+        file.seek(SeekFrom::Start(self.pos))?;
+        let read = file.read(buf)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Seek for SharedFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or(std::io::ErrorKind::InvalidInput)?;
+        Ok(self.pos)
+    }
+}
+
+/// The data of a zip archive, either in memory or in a file on disk.
+#[derive(Clone)]
+pub enum ZipSource {
+    Memory(Cursor<ZipData>),
+    #[cfg(not(target_family = "wasm"))]
+    File(SharedFile),
+}
+
+impl Read for ZipSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Memory(cursor) => Read::read(cursor, buf),
+            #[cfg(not(target_family = "wasm"))]
+            Self::File(file) => file.read(buf),
+        }
+    }
+}
+
+impl Seek for ZipSource {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Self::Memory(cursor) => cursor.seek(pos),
+            #[cfg(not(target_family = "wasm"))]
+            Self::File(file) => file.seek(pos),
+        }
+    }
+}
+
+fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+fn is_tar(data: &[u8]) -> bool {
+    data.get(257..262) == Some(b"ustar".as_slice())
+}
+
+/// Whether some data is an archive the VFS can open.
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(b"PK") || is_gzip(data) || is_tar(data)
+}
+
+fn is_archive_name(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    [".zip", ".tar", ".tar.gz", ".tgz"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
 #[derive(Clone, Default)]
 pub struct PathReader {
     paths: HashMap<PathBuf, SharedRead>,
@@ -68,7 +166,9 @@ impl PathReader {
 
 #[derive(Clone)]
 pub enum BrushVfs {
-    Zip(ZipArchive<Cursor<ZipData>>),
+    Zip(ZipArchive<ZipSource>),
+    /// Files read into memory, eg. from a tar archive.
+    Files(Arc<HashMap<PathBuf, ZipData>>),
     Manual(PathReader),
     #[cfg(not(target_family = "wasm"))]
     Directory(PathBuf, Vec<PathBuf>),
@@ -84,10 +184,100 @@ impl BrushVfs {
         let zip_data = ZipData {
             data: Arc::new(bytes),
         };
-        let archive = ZipArchive::new(Cursor::new(zip_data))?;
+        let archive = ZipArchive::new(ZipSource::Memory(Cursor::new(zip_data)))?;
+        Ok(Self::Zip(archive))
+    }
+
+    /// Open a zip archive on disk, without reading all of it into memory.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn from_zip_file(path: &Path) -> ZipResult<Self> {
+        let archive = ZipArchive::new(ZipSource::File(SharedFile::open(path)?))?;
         Ok(Self::Zip(archive))
     }
 
+    /// Read a tar archive, optionally gzipped, into memory.
+    pub async fn from_tar_reader(reader: impl AsyncRead + Unpin) -> anyhow::Result<Self> {
+        let mut bytes = vec![];
+        let mut reader = reader;
+        reader.read_to_end(&mut bytes).await?;
+        Self::from_tar_bytes(&bytes)
+    }
+
+    fn from_tar_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let reader: Box<dyn Read + '_> = if is_gzip(bytes) {
+            Box::new(flate2::read::GzDecoder::new(bytes))
+        } else {
+            Box::new(bytes)
+        };
+
+        let mut files = HashMap::new();
+        for entry in tar::Archive::new(reader).entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?.clean();
+            let mut data = vec![];
+            entry.read_to_end(&mut data)?;
+            files.insert(
+                path,
+                ZipData {
+                    data: Arc::new(data),
+                },
+            );
+        }
+        anyhow::ensure!(!files.is_empty(), "No files in tar archive");
+        Ok(Self::Files(Arc::new(files)))
+    }
+
+    /// Open an archive from memory, detecting the format from its contents.
+    pub fn from_archive_bytes(bytes: Vec<u8>) -> anyhow::Result<Self> {
+        if bytes.starts_with(b"PK") {
+            let zip_data = ZipData {
+                data: Arc::new(bytes),
+            };
+            Ok(Self::Zip(ZipArchive::new(ZipSource::Memory(Cursor::new(
+                zip_data,
+            )))?))
+        } else if is_gzip(&bytes) || is_tar(&bytes) {
+            Self::from_tar_bytes(&bytes)
+        } else {
+            anyhow::bail!("Unknown archive format")
+        }
+    }
+
+    /// Read an archive, detecting the format from its contents.
+    pub async fn from_archive_reader(reader: impl AsyncRead + Unpin) -> anyhow::Result<Self> {
+        let mut bytes = vec![];
+        let mut reader = reader;
+        reader.read_to_end(&mut bytes).await?;
+        Self::from_archive_bytes(bytes)?.open_nested().await
+    }
+
+    // If this is an archive with just another archive in it, open that archive instead.
+    async fn open_nested(mut self) -> anyhow::Result<Self> {
+        loop {
+            if !matches!(self, Self::Zip(_) | Self::Files(_)) {
+                return Ok(self);
+            }
+            let names: Vec<_> = self.file_names().collect();
+            let mut archives = names.iter().filter(|p| is_archive_name(p));
+            let (Some(name), None) = (archives.next(), archives.next()) else {
+                return Ok(self);
+            };
+            // Anything else has to be one of the folders the archive is in.
+            if !names.iter().all(|p| p == name || name.starts_with(p)) {
+                return Ok(self);
+            }
+            let name = name.clone();
+
+            log::info!("Opening nested archive {name:?}");
+            let mut bytes = vec![];
+            self.open_path(&name).await?.read_to_end(&mut bytes).await?;
+            self = Self::from_archive_bytes(bytes)?;
+        }
+    }
+
     pub fn from_paths(paths: PathReader) -> Self {
         Self::Manual(paths)
     }
@@ -96,10 +286,13 @@ impl BrushVfs {
         #[cfg(not(target_family = "wasm"))]
         {
             if dir.is_file() {
-                let file = tokio::fs::File::open(dir).await?;
                 if dir.extension().is_some_and(|e| e == "zip") {
-                    Ok(Self::from_zip_reader(file).await?)
+                    Self::from_zip_file(dir)?.open_nested().await
+                } else if is_archive_name(dir) {
+                    let file = tokio::fs::File::open(dir).await?;
+                    Self::from_archive_reader(file).await
                 } else {
+                    let file = tokio::fs::File::open(dir).await?;
                     // Make a VFS with just this file.
                     let mut paths = PathReader::default();
                     paths.add(dir, file);
@@ -107,30 +300,6 @@ impl BrushVfs {
                 }
             } else {
                 // Make a VFS with all files contained in the directory.
-                async fn walk_dir(dir: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
-                    let dir = PathBuf::from(dir.as_ref());
-
-                    let mut paths = Vec::new();
-                    let mut stack = vec![dir.clone()];
-
-                    while let Some(path) = stack.pop() {
-                        let mut read_dir = tokio::fs::read_dir(&path).await?;
-
-                        while let Some(entry) = read_dir.next_entry().await? {
-                            let path = entry.path();
-                            if path.is_dir() {
-                                stack.push(path.clone());
-                            }
-                            paths.push(
-                                path.strip_prefix(dir.clone())
-                                    .map_err(|_e| std::io::ErrorKind::InvalidInput)?
-                                    .to_path_buf(),
-                            );
-                        }
-                    }
-                    Ok(paths)
-                }
-
                 Ok(Self::Directory(dir.to_path_buf(), walk_dir(dir).await?))
             }
         }
@@ -142,9 +311,19 @@ impl BrushVfs {
         }
     }
 
+    /// The directory this VFS reads from, if it's a directory on disk.
+    pub fn directory(&self) -> Option<&Path> {
+        match self {
+            #[cfg(not(target_family = "wasm"))]
+            Self::Directory(dir, _) => Some(dir),
+            _ => None,
+        }
+    }
+
     pub fn file_names(&self) -> impl Iterator<Item = PathBuf> + '_ {
         let iterator: Box<dyn Iterator<Item = &Path>> = match self {
             Self::Zip(archive) => Box::new(archive.file_names().map(Path::new)),
+            Self::Files(files) => Box::new(files.keys().map(|p| p.as_path())),
             Self::Manual(map) => Box::new(map.paths().map(|p| p.as_path())),
             #[cfg(not(target_family = "wasm"))]
             Self::Directory(_, paths) => Box::new(paths.iter().map(|p| p.as_path())),
@@ -171,6 +350,10 @@ impl BrushVfs {
                 archive.by_name(&name)?.read_to_end(&mut buffer)?;
                 Ok(Box::new(Cursor::new(buffer)))
             }
+            Self::Files(files) => {
+                let data = files.get(path).context("File not found")?;
+                Ok(Box::new(Cursor::new(data.clone())))
+            }
             Self::Manual(map) => map.open(path).await,
             #[cfg(not(target_family = "wasm"))]
             Self::Directory(dir, _) => {
@@ -182,3 +365,95 @@ impl BrushVfs {
         }
     }
 }
+
+#[cfg(not(target_family = "wasm"))]
+async fn walk_dir(dir: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+    let dir = PathBuf::from(dir.as_ref());
+
+    let mut paths = Vec::new();
+    let mut stack = vec![dir.clone()];
+
+    while let Some(path) = stack.pop() {
+        let mut read_dir = tokio::fs::read_dir(&path).await?;
+
+        while let Some(entry) = read_dir.next_entry().await? {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path.clone());
+            }
+            paths.push(
+                path.strip_prefix(dir.clone())
+                    .map_err(|_e| std::io::ErrorKind::InvalidInput)?
+                    .to_path_buf(),
+            );
+        }
+    }
+    Ok(paths)
+}
+
+// The size and modification time of all files in a directory.
+#[cfg(not(target_family = "wasm"))]
+async fn directory_snapshot(
+    dir: &Path,
+) -> std::io::Result<HashMap<PathBuf, (u64, std::time::SystemTime)>> {
+    let mut snapshot = HashMap::new();
+    for path in walk_dir(dir).await? {
+        // Files can be removed while walking the directory, skip those.
+        if let Ok(meta) = tokio::fs::metadata(dir.join(&path)).await {
+            if let Ok(modified) = meta.modified() {
+                snapshot.insert(path, (meta.len(), modified));
+            }
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Poll a directory for new or changed files, eg. to pick up the images of a capture that's still
+/// going on. Yields a VFS of the directory whenever its files changed. Changes are only picked up
+/// once the files are the same for two polls in a row, to skip files that are still being written.
+#[cfg(not(target_family = "wasm"))]
+pub fn watch_directory(
+    dir: PathBuf,
+    interval: std::time::Duration,
+) -> impl tokio_stream::Stream<Item = anyhow::Result<BrushVfs>> {
+    async_fn_stream::try_fn_stream(|emitter| async move {
+        let mut seen = directory_snapshot(&dir).await?;
+        let mut last = seen.clone();
+        loop {
+            tokio::time::sleep(interval).await;
+            let current = directory_snapshot(&dir).await?;
+            if current == last && current != seen {
+                log::info!("Files in {dir:?} changed");
+                emitter.emit(BrushVfs::from_directory(&dir).await?).await;
+                seen = current.clone();
+            }
+            last = current;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_gzipped_tar() {
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "./images/a.jpg", b"abc".as_slice())
+            .expect("Failed to write tar");
+        let tar = builder.into_inner().expect("Failed to write tar");
+
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        std::io::Write::write_all(&mut gz, &tar).expect("Failed to compress tar");
+        let data = gz.finish().expect("Failed to compress tar");
+
+        assert!(is_archive(&data));
+        let vfs = BrushVfs::from_archive_bytes(data).expect("Failed to read archive");
+        let names: Vec<_> = vfs.file_names().collect();
+        assert_eq!(names, vec![PathBuf::from("images/a.jpg")]);
+    }
+}
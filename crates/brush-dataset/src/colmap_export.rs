@@ -0,0 +1,132 @@
+//! Export the cameras of a dataset as a COLMAP text model.
+//!
+//! Views with the same intrinsics share a camera. The intrinsics are of the images on disk, not
+//! of the images clamped to the max resolution. The model has no sparse points, and the poses
+//! are of the individual images, so the images of a camera rig are written without their rig.
+
+use std::collections::HashMap;
+
+use brush_render::camera::{fov_to_focal, CameraModel};
+use brush_train::scene::SceneView;
+
+/// The `cameras.txt`, `images.txt` and (empty) `points3D.txt` of the views.
+pub fn views_to_colmap(views: &[SceneView]) -> (String, String, String) {
+    let mut cameras = HashMap::new();
+    let mut camera_ids = HashMap::new();
+    let mut images = HashMap::new();
+
+    for (i, view) in views.iter().enumerate() {
+        let camera = &view.camera;
+        let (width, height) = view.original_size;
+        let focal_x = fov_to_focal(camera.fov_x, width);
+        let focal_y = fov_to_focal(camera.fov_y, height);
+        let center_x = camera.center_uv.x as f64 * width as f64;
+        let center_y = camera.center_uv.y as f64 * height as f64;
+
+        let intrinsics = [focal_x, focal_y, center_x, center_y];
+        let (model, params) = match camera.model {
+            CameraModel::Pinhole => (colmap_reader::CameraModel::Pinhole, vec![]),
+            CameraModel::OpenCv(p) => (colmap_reader::CameraModel::OpenCV, p.to_vec()),
+            CameraModel::Fisheye(p) => (colmap_reader::CameraModel::OpenCvFishEye, p.to_vec()),
+        };
+        let params: Vec<f64> = intrinsics
+            .into_iter()
+            .chain(params.into_iter().map(|p| p as f64))
+            .collect();
+
+        // Share cameras between views with the exact same intrinsics.
+        let key = format!("{model:?} {width} {height} {params:?}");
+        let next_id = camera_ids.len() as i32 + 1;
+        let camera_id = *camera_ids.entry(key).or_insert_with(|| {
+            cameras.insert(
+                next_id,
+                colmap_reader::Camera {
+                    id: next_id,
+                    model,
+                    width: width as u64,
+                    height: height as u64,
+                    params,
+                },
+            );
+            next_id
+        });
+
+        // COLMAP stores the world to camera transform.
+        let (_, quat, tvec) = camera.world_to_local().to_scale_rotation_translation();
+        images.insert(
+            i as i32 + 1,
+            colmap_reader::Image {
+                tvec,
+                quat,
+                camera_id,
+                name: view.path.clone(),
+                xys: vec![],
+                point3d_ids: vec![],
+            },
+        );
+    }
+
+    let points = "# 3D point list with one line of data per point:\n".to_owned();
+    (
+        colmap_reader::write_cameras_text(&cameras),
+        colmap_reader::write_images_text(&images),
+        points,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use brush_render::camera::{fov_to_focal, Camera};
+    use brush_train::scene::{SceneView, ViewImageType};
+
+    use super::views_to_colmap;
+
+    #[tokio::test]
+    async fn exported_views_read_back() {
+        let camera = Camera::new(
+            glam::vec3(0.5, -1.0, 2.0),
+            glam::Quat::from_euler(glam::EulerRot::XYZ, 0.3, -0.4, 0.2),
+            0.8,
+            0.6,
+            glam::vec2(0.5, 0.4),
+        );
+        // The image was clamped to half its size when loading.
+        let view = SceneView {
+            path: "images/a.png".to_owned(),
+            camera: camera.clone(),
+            image: Arc::new(image::DynamicImage::new_rgb8(32, 16)),
+            img_type: ViewImageType::Alpha,
+            original_size: (64, 32),
+            depth: None,
+        };
+
+        let (cameras, images, _) = views_to_colmap(&[view]);
+        let cameras = colmap_reader::read_cameras(cameras.as_bytes(), false)
+            .await
+            .expect("Failed to read cameras");
+        let images = colmap_reader::read_images(images.as_bytes(), false)
+            .await
+            .expect("Failed to read images");
+
+        let cam = &cameras[&1];
+        assert_eq!((cam.width, cam.height), (64, 32));
+        let (focal_x, focal_y) = cam.focal();
+        assert!((focal_x - fov_to_focal(0.8, 64)).abs() < 1e-4);
+        assert!((focal_y - fov_to_focal(0.6, 32)).abs() < 1e-4);
+        assert!((cam.principal_point() - glam::vec2(32.0, 12.8)).length() < 1e-4);
+
+        let img = &images[&1];
+        assert_eq!(img.name, "images/a.png");
+        assert_eq!(img.camera_id, 1);
+        let local_to_world =
+            glam::Affine3A::from_rotation_translation(img.quat, img.tvec).inverse();
+        let (_, rotation, position) = local_to_world.to_scale_rotation_translation();
+        assert!((position - camera.position).length() < 1e-4);
+        assert!(
+            rotation.abs_diff_eq(camera.rotation, 1e-4)
+                || rotation.abs_diff_eq(-camera.rotation, 1e-4)
+        );
+    }
+}
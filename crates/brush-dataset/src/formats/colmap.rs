@@ -7,23 +7,46 @@ use std::{
 use super::DataStream;
 use crate::{
     brush_vfs::BrushVfs,
-    formats::{clamp_img_to_max_size, find_mask_path, load_image},
+    formats::{find_mask_and_img, load_depth, load_image},
     splat_import::SplatMessage,
     stream_fut_parallel, Dataset, LoadDataseConfig,
 };
 use anyhow::{Context, Result};
 use async_fn_stream::try_fn_stream;
 use brush_render::{
-    camera::{self, Camera},
+    camera::{self, Camera, CameraModel},
     gaussian_splats::Splats,
     render::rgb_to_sh,
     Backend,
 };
-use brush_train::scene::SceneView;
-use glam::Vec3;
+use brush_train::scene::{SceneView, ViewDepth};
+use glam::{Vec2, Vec3};
 use std::collections::HashMap;
 use tokio_stream::StreamExt;
 
+// Convert a COLMAP camera model to the lens distortion of the renderer. Models that can't be
+// represented are treated as pinhole cameras.
+fn camera_model(cam: &colmap_reader::Camera) -> CameraModel {
+    use colmap_reader::CameraModel as Colmap;
+
+    let p: Vec<f32> = cam.distortion_params().iter().map(|&p| p as f32).collect();
+    let get = |i: usize| p.get(i).copied().unwrap_or(0.0);
+
+    match cam.model {
+        Colmap::SimplePinhole | Colmap::Pinhole => CameraModel::Pinhole,
+        Colmap::SimpleRadial | Colmap::Radial => CameraModel::OpenCv([get(0), get(1), 0.0, 0.0]),
+        Colmap::OpenCV => CameraModel::OpenCv([get(0), get(1), get(2), get(3)]),
+        Colmap::OpenCvFishEye => CameraModel::Fisheye([get(0), get(1), get(2), get(3)]),
+        Colmap::SimpleRadialFisheye | Colmap::RadialFisheye => {
+            CameraModel::Fisheye([get(0), get(1), 0.0, 0.0])
+        }
+        ref model => {
+            log::warn!("Camera model {model:?} isn't supported, ignoring its distortion");
+            CameraModel::Pinhole
+        }
+    }
+}
+
 fn find_base_path(archive: &BrushVfs, search_path: &str) -> Option<PathBuf> {
     for path in archive.file_names() {
         if let Some(str) = path.to_str() {
@@ -38,35 +61,13 @@ fn find_base_path(archive: &BrushVfs, search_path: &str) -> Option<PathBuf> {
     None
 }
 
-fn find_mask_and_img(vfs: &BrushVfs, paths: &[PathBuf]) -> Result<(PathBuf, Option<PathBuf>)> {
-    let mut path_masks = HashMap::new();
-    let mut masks = vec![];
-
-    // First pass: collect images & masks.
-    for path in paths {
-        let mask = find_mask_path(vfs, path);
-        path_masks.insert(path.clone(), mask.clone());
-        if let Some(mask_path) = mask {
-            masks.push(mask_path);
-        }
-    }
-
-    // Remove masks from candidates - shouldn't count as an input image.
-    for mask in masks {
-        path_masks.remove(&mask);
-    }
-
-    // Sort and return the first candidate (alphabetically).
-    path_masks
-        .into_iter()
-        .min_by_key(|kv| kv.0.clone())
-        .context("No candidates found")
-}
-
+// Read the views of the dataset, with the index of the frame every view belongs to. Images that
+// were taken at the same time by the cameras of a rig are one frame, other images are a frame of
+// their own.
 async fn read_views(
     vfs: BrushVfs,
     load_args: &LoadDataseConfig,
-) -> Result<Vec<impl Future<Output = Result<SceneView>>>> {
+) -> Result<Vec<(usize, impl Future<Output = Result<SceneView>>)>> {
     log::info!("Loading colmap dataset");
     let mut vfs = vfs;
 
@@ -88,6 +89,11 @@ async fn read_views(
         let mut cam_file = vfs.open_path(&cam_path).await?;
         colmap_reader::read_cameras(&mut cam_file, is_binary).await?
     };
+    // Look up the distortion once per camera, rather than once per image.
+    let cam_models: HashMap<_, _> = cam_model_data
+        .iter()
+        .map(|(&id, cam)| (id, camera_model(cam)))
+        .collect();
 
     let img_infos = {
         let img_file = vfs.open_path(&img_path).await?;
@@ -95,6 +101,48 @@ async fn read_views(
         colmap_reader::read_images(&mut buf_reader, is_binary).await?
     };
 
+    // The sparse points, to find the depth of the points seen by each view.
+    let points = if load_args.sparse_depth {
+        let points_name = if is_binary {
+            "points3d.bin"
+        } else {
+            "points3d.txt"
+        };
+        let points_path = vfs
+            .file_names()
+            .find(|p| {
+                p.to_str()
+                    .is_some_and(|p| p.to_lowercase().ends_with(points_name))
+            })
+            .context("No COLMAP points file for sparse depth")?;
+        let mut points_file = vfs
+            .open_path(&points_path)
+            .await
+            .context("Failed to read COLMAP points file for sparse depth")?;
+        let points = colmap_reader::read_points3d(&mut points_file, is_binary).await?;
+        Some(Arc::new(points))
+    } else {
+        None
+    };
+
+    // Frames of a camera rig, if the dataset has any.
+    let frames_path = base_path.join(if is_binary {
+        "frames.bin"
+    } else {
+        "frames.txt"
+    });
+    let frame_of_image: HashMap<i32, u32> = if vfs.file_names().any(|p| p == frames_path) {
+        let mut frames_file = vfs.open_path(&frames_path).await?;
+        let frames = colmap_reader::read_frames(&mut frames_file, is_binary).await?;
+        log::info!("Loading {} frames of camera rigs", frames.len());
+        frames
+            .into_iter()
+            .flat_map(|(id, frame)| frame.image_ids.into_iter().map(move |img| (img, id)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+
     let mut img_info_list = img_infos.into_iter().collect::<Vec<_>>();
 
     log::info!("Loading colmap dataset with {} images", img_info_list.len());
@@ -102,16 +150,29 @@ async fn read_views(
     // Sort by image name. This is important to match the exact eval images mipnerf uses.
     img_info_list.sort_by_key(|key_img| key_img.1.name.clone());
 
+    // Number the frames in the order of their first image.
+    let mut frame_indices = HashMap::new();
+    let img_info_list: Vec<_> = img_info_list
+        .into_iter()
+        .map(|(id, img_info)| {
+            let key = frame_of_image.get(&id).map_or(Err(id), |&frame| Ok(frame));
+            let next = frame_indices.len();
+            (*frame_indices.entry(key).or_insert(next), img_info)
+        })
+        .collect();
+
     let handles = img_info_list
         .into_iter()
-        .take(load_args.max_frames.unwrap_or(usize::MAX))
-        .map(move |(_, img_info)| {
+        .filter(|(frame, _)| *frame < load_args.max_frames.unwrap_or(usize::MAX))
+        .map(move |(frame, img_info)| {
             let cam_data = cam_model_data[&img_info.camera_id].clone();
+            let cam_model = cam_models[&img_info.camera_id];
             let load_args = load_args.clone();
             let mut vfs = vfs.clone();
+            let points = points.clone();
 
             // Create a future to handle loading the image.
-            async move {
+            let future = async move {
                 let focal = cam_data.focal();
 
                 let fovx = camera::focal_to_fov(focal.0, cam_data.width as u32);
@@ -126,32 +187,61 @@ async fn read_views(
                     .file_names()
                     .filter(|p| p.ends_with(&img_info.name))
                     .collect();
+                // The cameras of a rig usually have an image with the same file name, which
+                // can only be told apart by the folder of the camera in the image name.
+                if img_paths.len() > 1 && Path::new(&img_info.name).parent() == Some(Path::new(""))
+                {
+                    log::warn!(
+                        "Multiple images are named {}, include the folder in the COLMAP image name",
+                        img_info.name
+                    );
+                }
 
                 let (path, mask_path) = find_mask_and_img(&vfs, &img_paths)
                     .with_context(|| format!("Failed to find image {}", img_info.name))?;
 
-                let (image, img_type) = load_image(&mut vfs, &path, mask_path.as_deref())
+                let view_image = load_image(&mut vfs, &path, mask_path.as_deref(), &load_args)
                     .await
                     .with_context(|| format!("Failed to load image {}", img_info.name))?;
 
-                let image = clamp_img_to_max_size(Arc::new(image), load_args.max_resolution);
-
                 // Convert w2c to c2w.
                 let world_to_cam =
                     glam::Affine3A::from_rotation_translation(img_info.quat, img_info.tvec);
                 let cam_to_world = world_to_cam.inverse();
                 let (_, quat, translation) = cam_to_world.to_scale_rotation_translation();
 
-                let camera = Camera::new(translation, quat, fovx, fovy, center_uv);
+                let camera =
+                    Camera::new(translation, quat, fovx, fovy, center_uv).with_model(cam_model);
+
+                let mut depth = load_depth(&mut vfs, &path, load_args.max_resolution)
+                    .await
+                    .with_context(|| format!("Failed to load depth of {}", img_info.name))?;
+                if let (None, Some(points)) = (&depth, points) {
+                    let size = glam::vec2(cam_data.width as f32, cam_data.height as f32);
+                    let seen: Vec<(Vec2, f32)> = img_info
+                        .xys
+                        .iter()
+                        .zip(&img_info.point3d_ids)
+                        .filter_map(|(&xy, id)| {
+                            let point = points.get(id)?;
+                            let z = world_to_cam.transform_point3(point.xyz).z;
+                            (z > 0.0).then_some((xy / size, z))
+                        })
+                        .collect();
+                    depth = (!seen.is_empty()).then(|| ViewDepth::Points(Arc::new(seen)));
+                }
 
                 let view = SceneView {
                     path: path.to_string_lossy().to_string(),
                     camera,
-                    image,
-                    img_type,
+                    image: view_image.image,
+                    img_type: view_image.img_type,
+                    original_size: view_image.original_size,
+                    depth,
                 };
                 Ok(view)
-            }
+            };
+            (frame, future)
         })
         .collect();
 
@@ -165,9 +255,11 @@ pub(crate) async fn load_dataset<B: Backend>(
 ) -> Result<(DataStream<SplatMessage<B>>, DataStream<Dataset>)> {
     let mut handles = read_views(vfs.clone(), load_args).await?;
 
+    // Split by frame, so the images of the cameras of a rig stay together.
     if let Some(subsample) = load_args.subsample_frames {
-        handles = handles.into_iter().step_by(subsample as usize).collect();
+        handles.retain(|(frame, _)| frame % subsample as usize == 0);
     }
+    let (frames, handles): (Vec<_>, Vec<_>) = handles.into_iter().unzip();
 
     let mut train_views = vec![];
     let mut eval_views = vec![];
@@ -180,7 +272,7 @@ pub(crate) async fn load_dataset<B: Backend>(
         let view = view.context("Failed to load COLMAP view")?;
 
         if let Some(eval_period) = load_args.eval_split_every {
-            if i % eval_period == 0 {
+            if frames[i] % eval_period == 0 {
                 eval_views.push(view);
             } else {
                 train_views.push(view);
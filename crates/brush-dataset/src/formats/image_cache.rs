@@ -0,0 +1,164 @@
+//! A disk cache of decoded and resized dataset images.
+//!
+//! Decoding and resizing the images of a large dataset takes a while. Cached images are stored
+//! uncompressed, keyed by a hash of the source files and the load settings, so loading the same
+//! dataset again only has to read them back.
+
+use std::path::{Path, PathBuf};
+
+use brush_train::scene::ViewImageType;
+use image::{DynamicImage, Rgb32FImage, RgbImage, Rgba32FImage, RgbaImage};
+
+const MAGIC: &[u8; 8] = b"BRSHIMG1";
+const HEADER_LEN: usize = MAGIC.len() + 4 * 4 + 2;
+
+/// A cached image, with the type and size of the image it was loaded from.
+pub(crate) struct CachedImage {
+    pub image: DynamicImage,
+    pub img_type: ViewImageType,
+    pub original_size: (u32, u32),
+}
+
+// FNV-1a, which unlike the std hasher is stable between runs and versions.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Key of an image loaded from `sources` (the image and mask file), with the given settings.
+pub(crate) fn cache_key(sources: &[&[u8]], max_resolution: u32, alpha_as_mask: bool) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325;
+    for source in sources {
+        // Include the length, so the boundary between the files is part of the key.
+        hash = fnv1a(hash, &(source.len() as u64).to_le_bytes());
+        hash = fnv1a(hash, source);
+    }
+    hash = fnv1a(hash, &max_resolution.to_le_bytes());
+    fnv1a(hash, &[alpha_as_mask as u8])
+}
+
+fn cache_path(dir: &Path, key: u64) -> PathBuf {
+    dir.join(format!("{key:016x}.img"))
+}
+
+fn encode(cached: &CachedImage) -> Vec<u8> {
+    let image = &cached.image;
+    let (format, pixels) = match (image.color().has_alpha(), image.color().bytes_per_pixel()) {
+        // 8 bit images are stored as is, anything else as floats to not lose precision.
+        (false, 1 | 3) => (0u8, image.to_rgb8().into_raw()),
+        (true, 2 | 4) => (1, image.to_rgba8().into_raw()),
+        (false, _) => (2, floats_to_bytes(&image.to_rgb32f().into_raw())),
+        (true, _) => (3, floats_to_bytes(&image.to_rgba32f().into_raw())),
+    };
+
+    let mut data = Vec::with_capacity(HEADER_LEN + pixels.len());
+    data.extend_from_slice(MAGIC);
+    for v in [
+        image.width(),
+        image.height(),
+        cached.original_size.0,
+        cached.original_size.1,
+    ] {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    data.push(format);
+    data.push(match cached.img_type {
+        ViewImageType::Alpha => 0,
+        ViewImageType::Masked => 1,
+    });
+    data.extend_from_slice(&pixels);
+    data
+}
+
+fn floats_to_bytes(floats: &[f32]) -> Vec<u8> {
+    floats.iter().flat_map(|f| f.to_le_bytes()).collect()
+}
+
+fn bytes_to_floats(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn decode(data: &[u8]) -> Option<CachedImage> {
+    let header = data.get(..HEADER_LEN)?;
+    if &header[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+    let read_u32 = |i: usize| {
+        let start = MAGIC.len() + i * 4;
+        u32::from_le_bytes(header[start..start + 4].try_into().expect("4 bytes"))
+    };
+    let (width, height) = (read_u32(0), read_u32(1));
+    let original_size = (read_u32(2), read_u32(3));
+    let pixels = &data[HEADER_LEN..];
+
+    let image = match header[HEADER_LEN - 2] {
+        0 => RgbImage::from_raw(width, height, pixels.to_vec())?.into(),
+        1 => RgbaImage::from_raw(width, height, pixels.to_vec())?.into(),
+        2 => Rgb32FImage::from_raw(width, height, bytes_to_floats(pixels))?.into(),
+        3 => Rgba32FImage::from_raw(width, height, bytes_to_floats(pixels))?.into(),
+        _ => return None,
+    };
+    let img_type = match header[HEADER_LEN - 1] {
+        0 => ViewImageType::Alpha,
+        1 => ViewImageType::Masked,
+        _ => return None,
+    };
+    Some(CachedImage {
+        image,
+        img_type,
+        original_size,
+    })
+}
+
+/// Read a cached image, or None if it isn't cached (or the cached file is invalid).
+pub(crate) async fn read_cached(dir: &Path, key: u64) -> Option<CachedImage> {
+    let data = tokio::fs::read(cache_path(dir, key)).await.ok()?;
+    decode(&data)
+}
+
+/// Write an image to the cache.
+pub(crate) async fn write_cached(dir: &Path, key: u64, cached: &CachedImage) -> anyhow::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    let path = cache_path(dir, key);
+    // Write to a temporary file first, so an interrupted write never leaves a partial image.
+    let tmp_path = path.with_extension(format!("tmp{:08x}", rand::random::<u32>()));
+    tokio::fs::write(&tmp_path, encode(cached)).await?;
+    tokio::fs::rename(&tmp_path, &path).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_image_round_trips() {
+        let image = RgbaImage::from_fn(5, 3, |x, y| image::Rgba([x as u8, y as u8, 7, 200]));
+        let cached = CachedImage {
+            image: image.clone().into(),
+            img_type: ViewImageType::Masked,
+            original_size: (50, 30),
+        };
+        let decoded = decode(&encode(&cached)).expect("Failed to decode cached image");
+        assert_eq!(decoded.image.to_rgba8(), image);
+        assert_eq!(decoded.img_type, ViewImageType::Masked);
+        assert_eq!(decoded.original_size, (50, 30));
+
+        // A truncated file isn't a valid image.
+        let data = encode(&cached);
+        assert!(decode(&data[..data.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn key_depends_on_settings() {
+        let key = cache_key(&[b"image", b"mask"], 1920, false);
+        assert_eq!(key, cache_key(&[b"image", b"mask"], 1920, false));
+        assert_ne!(key, cache_key(&[b"image", b"mask"], 1080, false));
+        assert_ne!(key, cache_key(&[b"image", b"mask"], 1920, true));
+        assert_ne!(key, cache_key(&[b"imagem", b"ask"], 1920, false));
+    }
+}
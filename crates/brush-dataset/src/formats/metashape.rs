@@ -0,0 +1,354 @@
+//! Cameras exported by Agisoft Metashape, with "File > Export > Export Cameras" as Agisoft XML.
+//!
+//! The sensors have the calibration in pixels, and each aligned camera has a camera to world
+//! transform. Cameras are kept in the coordinates of the chunk rather than the georeferenced
+//! coordinates of the chunk transform, which are usually too large for f32 precision.
+
+use super::photogrammetry::{ExportedView, Intrinsics};
+use anyhow::{Context, Result};
+use brush_render::camera::CameraModel;
+use glam::{Affine3A, DMat3, DMat4, DVec3};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+struct Document {
+    chunk: Chunk,
+}
+
+#[derive(Deserialize)]
+struct Chunk {
+    #[serde(default)]
+    sensors: Sensors,
+    #[serde(default)]
+    components: Components,
+    cameras: Cameras,
+}
+
+#[derive(Deserialize, Default)]
+struct Sensors {
+    #[serde(default)]
+    sensor: Vec<Sensor>,
+}
+
+#[derive(Deserialize)]
+struct Sensor {
+    #[serde(rename = "@id")]
+    id: u32,
+    #[serde(rename = "@type", default)]
+    kind: Option<String>,
+    resolution: Option<Resolution>,
+    #[serde(default)]
+    calibration: Vec<Calibration>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct Resolution {
+    #[serde(rename = "@width")]
+    width: u32,
+    #[serde(rename = "@height")]
+    height: u32,
+}
+
+#[derive(Deserialize)]
+struct Calibration {
+    #[serde(rename = "@class", default)]
+    class: Option<String>,
+    resolution: Option<Resolution>,
+    f: f32,
+    // Offset of the principal point from the image center.
+    #[serde(default)]
+    cx: f32,
+    #[serde(default)]
+    cy: f32,
+    // Aspect ratio of the pixels, fy = f + b1.
+    #[serde(default)]
+    b1: f32,
+    #[serde(default)]
+    k1: f32,
+    #[serde(default)]
+    k2: f32,
+    #[serde(default)]
+    k3: f32,
+    #[serde(default)]
+    k4: f32,
+    #[serde(default)]
+    p1: f32,
+    #[serde(default)]
+    p2: f32,
+}
+
+#[derive(Deserialize, Default)]
+struct Components {
+    #[serde(default)]
+    component: Vec<Component>,
+}
+
+#[derive(Deserialize)]
+struct Component {
+    #[serde(rename = "@id")]
+    id: u32,
+    transform: Option<ComponentTransform>,
+}
+
+#[derive(Deserialize)]
+struct ComponentTransform {
+    rotation: Option<Text>,
+    translation: Option<Text>,
+    scale: Option<Text>,
+}
+
+#[derive(Deserialize)]
+struct Cameras {
+    #[serde(default)]
+    camera: Vec<MetashapeCamera>,
+    // Cameras can be organized in folders.
+    #[serde(default)]
+    group: Vec<Cameras>,
+}
+
+#[derive(Deserialize)]
+struct MetashapeCamera {
+    #[serde(rename = "@label")]
+    label: String,
+    #[serde(rename = "@sensor_id")]
+    sensor_id: u32,
+    #[serde(rename = "@component_id", default)]
+    component_id: Option<u32>,
+    // Only aligned cameras have a transform.
+    transform: Option<Text>,
+}
+
+// Elements with a list of numbers, which can have attributes like `locked`.
+#[derive(Deserialize)]
+struct Text {
+    #[serde(rename = "$text")]
+    text: String,
+}
+
+impl Text {
+    fn numbers<const N: usize>(&self) -> Result<[f64; N]> {
+        let numbers = self
+            .text
+            .split_whitespace()
+            .map(|n| n.parse())
+            .collect::<Result<Vec<f64>, _>>()
+            .with_context(|| format!("Invalid numbers '{}'", self.text))?;
+        numbers
+            .try_into()
+            .map_err(|n: Vec<_>| anyhow::anyhow!("Expected {N} numbers, got {}", n.len()))
+    }
+}
+
+impl Cameras {
+    fn all(&self) -> Box<dyn Iterator<Item = &MetashapeCamera> + '_> {
+        Box::new(
+            self.camera
+                .iter()
+                .chain(self.group.iter().flat_map(|g| g.all())),
+        )
+    }
+}
+
+/// Whether an xml file looks like a Metashape camera export.
+pub fn is_camera_export(text: &str) -> bool {
+    text.contains("<document") && text.contains("<sensors") && text.contains("<cameras")
+}
+
+// The component transform, from the coordinates of a component to the coordinates of the chunk.
+fn component_transform(component: &Component) -> Result<DMat4> {
+    let Some(transform) = &component.transform else {
+        return Ok(DMat4::IDENTITY);
+    };
+    let rotation = transform
+        .rotation
+        .as_ref()
+        .map(|r| r.numbers::<9>())
+        .transpose()?
+        .map_or(DMat3::IDENTITY, |r| DMat3::from_cols_array(&r).transpose());
+    let translation = transform
+        .translation
+        .as_ref()
+        .map(|t| t.numbers::<3>())
+        .transpose()?
+        .map_or(DVec3::ZERO, DVec3::from_array);
+    let scale = transform
+        .scale
+        .as_ref()
+        .map(|s| s.numbers::<1>())
+        .transpose()?
+        .map_or(1.0, |[s]| s);
+    Ok(DMat4::from_translation(translation) * DMat4::from_mat3(rotation * scale))
+}
+
+fn sensor_view(sensor: &Sensor) -> Result<Option<(Intrinsics, CameraModel)>> {
+    // Prefer the calibration that was adjusted during alignment.
+    let Some(calib) = sensor
+        .calibration
+        .iter()
+        .find(|c| c.class.as_deref() == Some("adjusted"))
+        .or(sensor.calibration.first())
+    else {
+        return Ok(None);
+    };
+    let size = calib
+        .resolution
+        .or(sensor.resolution)
+        .context("Sensor without a resolution")?;
+    let size = glam::uvec2(size.width, size.height);
+
+    let model = match sensor.kind.as_deref() {
+        None | Some("frame") => {
+            if calib.k3 != 0.0 {
+                log::warn!("Ignoring the k3 distortion of sensor {}", sensor.id);
+            }
+            // Metashape swaps the tangential coefficients compared to OpenCV.
+            let params = [calib.k1, calib.k2, calib.p2, calib.p1];
+            if params == [0.0; 4] {
+                CameraModel::Pinhole
+            } else {
+                CameraModel::OpenCv(params)
+            }
+        }
+        Some("fisheye") => CameraModel::Fisheye([calib.k1, calib.k2, calib.k3, calib.k4]),
+        Some(kind) => {
+            log::warn!("Sensor type {kind} isn't supported, skipping its cameras");
+            return Ok(None);
+        }
+    };
+
+    let intrinsics = Intrinsics::Pixels {
+        focal: glam::vec2(calib.f, calib.f + calib.b1),
+        center: size.as_vec2() / 2.0 + glam::vec2(calib.cx, calib.cy),
+        size,
+    };
+    Ok(Some((intrinsics, model)))
+}
+
+/// Parse the aligned cameras of a Metashape export.
+pub fn parse_cameras(text: &str) -> Result<Vec<ExportedView>> {
+    let document: Document = quick_xml::de::from_str(text)?;
+    let chunk = document.chunk;
+
+    let sensors = chunk
+        .sensors
+        .sensor
+        .iter()
+        .map(|s| Ok((s.id, sensor_view(s)?)))
+        .collect::<Result<HashMap<_, _>>>()?;
+    let components = chunk
+        .components
+        .component
+        .iter()
+        .map(|c| Ok((c.id, component_transform(c)?)))
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let mut views = vec![];
+    let mut unaligned = 0;
+    for camera in chunk.cameras.all() {
+        let Some(transform) = &camera.transform else {
+            unaligned += 1;
+            continue;
+        };
+        let Some(&(intrinsics, model)) = sensors
+            .get(&camera.sensor_id)
+            .with_context(|| format!("Unknown sensor {}", camera.sensor_id))?
+            .as_ref()
+        else {
+            continue;
+        };
+
+        // Camera to world, in row major order.
+        let transform = DMat4::from_cols_array(&transform.numbers::<16>()?).transpose();
+        let component = camera
+            .component_id
+            .and_then(|id| components.get(&id))
+            .copied()
+            .unwrap_or(DMat4::IDENTITY);
+        let (_, rotation, translation) = (component * transform).to_scale_rotation_translation();
+
+        views.push(ExportedView {
+            name: camera.label.clone(),
+            cam_to_world: Affine3A::from_rotation_translation(
+                rotation.as_quat(),
+                translation.as_vec3(),
+            ),
+            intrinsics,
+            model,
+        });
+    }
+    if unaligned > 0 {
+        log::info!("Skipping {unaligned} cameras that weren't aligned");
+    }
+    Ok(views)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    const EXPORT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<document version="1.5.0">
+  <chunk label="Chunk 1" enabled="true">
+    <sensors next_id="1">
+      <sensor id="0" label="Camera" type="frame">
+        <resolution width="400" height="300"/>
+        <property name="fixed" value="false"/>
+        <calibration type="frame" class="adjusted">
+          <resolution width="400" height="300"/>
+          <f>200</f>
+          <cx>4</cx>
+          <cy>-3</cy>
+          <k1>-0.1</k1>
+          <p1>0.002</p1>
+        </calibration>
+      </sensor>
+    </sensors>
+    <components next_id="1" active_id="0">
+      <component id="0" label="Component 1">
+        <transform>
+          <rotation locked="true">0 -1 0 1 0 0 0 0 1</rotation>
+          <translation locked="true">1 2 3</translation>
+          <scale locked="true">2</scale>
+        </transform>
+      </component>
+    </components>
+    <cameras next_id="3" next_group_id="1">
+      <camera id="0" sensor_id="0" component_id="0" label="IMG_0001">
+        <transform>1 0 0 0.5 0 1 0 0 0 0 1 0 0 0 0 1</transform>
+      </camera>
+      <group id="0" label="Folder" type="folder">
+        <camera id="1" sensor_id="0" component_id="0" label="IMG_0002"/>
+        <camera id="2" sensor_id="0" label="IMG_0003">
+          <transform>1 0 0 0 0 1 0 0 0 0 1 0 0 0 0 1</transform>
+        </camera>
+      </group>
+    </cameras>
+  </chunk>
+</document>
+"#;
+
+    #[test]
+    fn parses_cameras() {
+        assert!(is_camera_export(EXPORT));
+
+        let views = parse_cameras(EXPORT).expect("Valid export");
+        let names: Vec<_> = views.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, ["IMG_0001", "IMG_0003"]);
+
+        // The component rotates a quarter turn around z, doubles the scale and then translates.
+        let first = &views[0];
+        let position = Vec3::from(first.cam_to_world.translation);
+        assert!(position.abs_diff_eq(glam::vec3(1.0, 3.0, 3.0), 1e-5));
+        let right = first.cam_to_world.transform_vector3(Vec3::X);
+        assert!(right.abs_diff_eq(Vec3::Y, 1e-5));
+        assert_eq!(first.model, CameraModel::OpenCv([-0.1, 0.0, 0.0, 0.002]));
+
+        let (fov_x, _, center) = first.intrinsics.fov_and_center(glam::uvec2(800, 600));
+        assert!((fov_x - std::f64::consts::FRAC_PI_2).abs() < 1e-5);
+        assert!(center.abs_diff_eq(glam::vec2(0.51, 0.49), 1e-5));
+
+        assert_eq!(views[1].cam_to_world, Affine3A::IDENTITY);
+    }
+}
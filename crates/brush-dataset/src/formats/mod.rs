@@ -3,11 +3,14 @@ use crate::{
     splat_import::{load_splat_from_ply, SplatMessage},
     Dataset, LoadDataseConfig, WasmNotSend,
 };
+use anyhow::Context;
 use brush_render::Backend;
-use brush_train::scene::ViewImageType;
-use image::DynamicImage;
+use brush_train::image::is_hdr;
+use brush_train::scene::{ViewDepth, ViewImageType};
+use image::{DynamicImage, GenericImageView};
 use path_clean::PathClean;
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
@@ -16,7 +19,14 @@ use tokio::io::AsyncReadExt;
 use tokio_stream::Stream;
 
 pub mod colmap;
+#[cfg(not(target_family = "wasm"))]
+mod image_cache;
+pub mod metashape;
 pub mod nerfstudio;
+pub mod photogrammetry;
+pub mod reality_capture;
+#[cfg(not(target_family = "wasm"))]
+pub mod video;
 
 pub trait DynStream<Item>: Stream<Item = Item> + WasmNotSend {}
 impl<Item, T: Stream<Item = Item> + WasmNotSend> DynStream<Item> for T {}
@@ -27,6 +37,13 @@ pub async fn load_dataset<B: Backend>(
     load_args: &LoadDataseConfig,
     device: &B::Device,
 ) -> anyhow::Result<(DataStream<SplatMessage<B>>, DataStream<Dataset>)> {
+    #[cfg(not(target_family = "wasm"))]
+    if vfs.file_names().any(|p| video::is_video(&p)) {
+        return video::load_dataset::<B>(vfs, load_args, device)
+            .await
+            .map_err(|e| e.context("Failed to load video."));
+    }
+
     let mut err_context = anyhow::anyhow!("Attempting to load dataset.");
 
     let stream = nerfstudio::read_dataset(vfs.clone(), load_args, device).await;
@@ -49,6 +66,17 @@ pub async fn load_dataset<B: Backend>(
                 .context(e)
                 .context("Failed to load as COLMAP format.");
 
+            photogrammetry::load_dataset::<B>(vfs.clone(), load_args).await
+        }
+    };
+
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            err_context = err_context
+                .context(e)
+                .context("Failed to load as RealityCapture or Metashape format.");
+
             Err(err_context.context("Failed to load dataset as any format."))?
         }
     };
@@ -96,6 +124,68 @@ fn find_mask_path(vfs: &BrushVfs, path: &Path) -> Option<PathBuf> {
     })
 }
 
+// Pick the image out of candidate paths, skipping the masks and depth maps among them.
+pub(crate) fn find_mask_and_img(
+    vfs: &BrushVfs,
+    paths: &[PathBuf],
+) -> anyhow::Result<(PathBuf, Option<PathBuf>)> {
+    let mut path_masks = HashMap::new();
+    let mut masks = vec![];
+
+    // First pass: collect images & masks.
+    for path in paths {
+        let mask = find_mask_path(vfs, path);
+        path_masks.insert(path.clone(), mask.clone());
+        if let Some(mask_path) = mask {
+            masks.push(mask_path);
+        }
+        masks.extend(find_depth_path(vfs, path));
+    }
+
+    // Remove masks and depth maps from candidates - shouldn't count as an input image.
+    for mask in masks {
+        path_masks.remove(&mask);
+    }
+
+    // Sort and return the first candidate (alphabetically).
+    path_masks
+        .into_iter()
+        .min_by_key(|kv| kv.0.clone())
+        .context("No candidates found")
+}
+
+// Depth maps are stored in a `depths` folder next to the images, with the same name as the image.
+pub(crate) fn find_depth_path(vfs: &BrushVfs, path: &Path) -> Option<PathBuf> {
+    let file_stem = path.file_stem()?.to_str()?;
+    let depths_dir = path.parent()?.clean().parent()?.join("depths").clean();
+
+    vfs.file_names().find(|file| {
+        file.parent().is_some_and(|p| p == depths_dir)
+            && file.file_stem().and_then(|s| s.to_str()) == Some(file_stem)
+    })
+}
+
+/// Load the depth map of an image, if there is one. See [`ViewDepth::Map`].
+pub(crate) async fn load_depth(
+    vfs: &mut BrushVfs,
+    img_path: &Path,
+    max_size: u32,
+) -> anyhow::Result<Option<ViewDepth>> {
+    let Some(depth_path) = find_depth_path(vfs, img_path) else {
+        return Ok(None);
+    };
+    let mut bytes = vec![];
+    vfs.open_path(&depth_path)
+        .await?
+        .read_to_end(&mut bytes)
+        .await?;
+    let depth = image::load_from_memory(&bytes)?;
+    Ok(Some(ViewDepth::Map(clamp_img_to_max_size(
+        Arc::new(depth),
+        max_size,
+    ))))
+}
+
 pub fn clamp_img_to_max_size(image: Arc<DynamicImage>, max_size: u32) -> Arc<DynamicImage> {
     if image.width() <= max_size && image.height() <= max_size {
         return image;
@@ -103,46 +193,121 @@ pub fn clamp_img_to_max_size(image: Arc<DynamicImage>, max_size: u32) -> Arc<Dyn
     Arc::new(image.resize(max_size, max_size, image::imageops::FilterType::Lanczos3))
 }
 
+async fn read_file(vfs: &mut BrushVfs, path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    vfs.open_path(path).await?.read_to_end(&mut bytes).await?;
+    Ok(bytes)
+}
+
+/// An image of a view, clamped to the max resolution.
+pub(crate) struct ViewImage {
+    pub image: Arc<DynamicImage>,
+    pub img_type: ViewImageType,
+    /// Size of the image before it was clamped to the max resolution.
+    pub original_size: (u32, u32),
+}
+
+/// Load the image of a view, with its mask if there is one. With an image cache directory set,
+/// the decoded image is read from or written to the cache.
 pub(crate) async fn load_image(
     vfs: &mut BrushVfs,
     img_path: &Path,
     mask_path: Option<&Path>,
-) -> anyhow::Result<(DynamicImage, ViewImageType)> {
-    let mut img_bytes = vec![];
+    load_args: &LoadDataseConfig,
+) -> anyhow::Result<ViewImage> {
+    let img_bytes = read_file(vfs, img_path).await?;
+    let mask_bytes = match mask_path {
+        Some(mask_path) => Some(read_file(vfs, mask_path).await?),
+        None => None,
+    };
 
-    vfs.open_path(img_path)
-        .await?
-        .read_to_end(&mut img_bytes)
-        .await?;
-    let mut img = image::load_from_memory(&img_bytes)?;
+    #[cfg(not(target_family = "wasm"))]
+    if let Some(dir) = &load_args.image_cache {
+        let dir = Path::new(dir);
+        let sources = [&img_bytes[..], mask_bytes.as_deref().unwrap_or_default()];
+        let key =
+            image_cache::cache_key(&sources, load_args.max_resolution, load_args.alpha_as_mask);
+        if let Some(cached) = image_cache::read_cached(dir, key).await {
+            return Ok(ViewImage {
+                image: Arc::new(cached.image),
+                img_type: cached.img_type,
+                original_size: cached.original_size,
+            });
+        }
 
-    // Copy over mask
-    if let Some(mask_path) = mask_path {
-        let mut mask_bytes = vec![];
+        let view_image = decode_image(&img_bytes, mask_bytes.as_deref(), load_args)?;
+        let cached = image_cache::CachedImage {
+            image: (*view_image.image).clone(),
+            img_type: view_image.img_type.clone(),
+            original_size: view_image.original_size,
+        };
+        // Failing to cache isn't fatal, the image just has to be decoded again next time.
+        if let Err(e) = image_cache::write_cached(dir, key, &cached).await {
+            log::warn!("Failed to cache image {img_path:?}: {e}");
+        }
+        return Ok(view_image);
+    }
 
-        vfs.open_path(mask_path)
-            .await?
-            .read_to_end(&mut mask_bytes)
-            .await?;
+    decode_image(&img_bytes, mask_bytes.as_deref(), load_args)
+}
+
+fn decode_image(
+    img_bytes: &[u8],
+    mask_bytes: Option<&[u8]>,
+    load_args: &LoadDataseConfig,
+) -> anyhow::Result<ViewImage> {
+    let (img, img_type) = decode_masked_image(img_bytes, mask_bytes, load_args.alpha_as_mask)?;
+    let original_size = img.dimensions();
+    Ok(ViewImage {
+        image: clamp_img_to_max_size(Arc::new(img), load_args.max_resolution),
+        img_type,
+        original_size,
+    })
+}
 
-        let mask_img = image::load_from_memory(&mask_bytes)?;
+fn decode_masked_image(
+    img_bytes: &[u8],
+    mask_bytes: Option<&[u8]>,
+    alpha_as_mask: bool,
+) -> anyhow::Result<(DynamicImage, ViewImageType)> {
+    let mut img = image::load_from_memory(img_bytes)?;
 
-        let mut img_masked = img.to_rgba8();
+    // Copy over mask
+    if let Some(mask_bytes) = mask_bytes {
+        let mut mask_img = image::load_from_memory(mask_bytes)?;
 
-        if mask_img.color().has_alpha() {
-            let mask_img = mask_img.to_rgba8();
+        // Masks are sometimes stored at a lower resolution.
+        if mask_img.dimensions() != img.dimensions() {
+            mask_img = mask_img.resize_exact(
+                img.width(),
+                img.height(),
+                image::imageops::FilterType::Triangle,
+            );
+        }
+
+        let mask_img = if mask_img.color().has_alpha() {
+            mask_img.to_rgba8()
+        } else {
+            mask_img.grayscale().to_rgba8()
+        };
+
+        // Keep the linear colors of HDR images, rather than clipping them to 8 bits.
+        if is_hdr(&img) {
+            let mut img_masked = img.to_rgba32f();
             for (buf, mask) in img_masked.pixels_mut().zip(mask_img.pixels()) {
-                buf[3] = mask[0];
+                buf[3] = mask[0] as f32 / 255.0;
             }
+            img = img_masked.into();
         } else {
-            let mask_img = mask_img.grayscale().to_rgb8();
+            let mut img_masked = img.to_rgba8();
             for (buf, mask) in img_masked.pixels_mut().zip(mask_img.pixels()) {
                 buf[3] = mask[0];
             }
+            img = img_masked.into();
         }
 
-        img = img_masked.into();
-
+        Ok((img, ViewImageType::Masked))
+    } else if alpha_as_mask && img.color().has_alpha() {
         Ok((img, ViewImageType::Masked))
     } else {
         Ok((img, ViewImageType::Alpha))
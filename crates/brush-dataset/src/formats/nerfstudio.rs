@@ -1,5 +1,5 @@
-use super::clamp_img_to_max_size;
 use super::find_mask_path;
+use super::load_depth;
 use super::load_image;
 use super::DataStream;
 use crate::brush_vfs::BrushVfs;
@@ -12,17 +12,15 @@ use anyhow::Context;
 use anyhow::Result;
 use async_fn_stream::try_fn_stream;
 use brush_render::camera::fov_to_focal;
-use brush_render::camera::{focal_to_fov, Camera};
+use brush_render::camera::{focal_to_fov, Camera, CameraModel};
 use brush_render::Backend;
 use brush_train::scene::SceneView;
 use std::future::Future;
 use std::path::Path;
-use std::sync::Arc;
 use tokio::io::AsyncReadExt;
 use tokio_stream::StreamExt;
 
 #[derive(serde::Deserialize, Clone)]
-#[allow(unused)] // not reading camera distortions yet.
 struct JsonScene {
     // Horizontal FOV.
     camera_angle_x: Option<f64>,
@@ -34,7 +32,7 @@ struct JsonScene {
     /// Focal length y
     fl_y: Option<f64>,
 
-    // Not really used atm.
+    /// One of `OPENCV` (the default), `OPENCV_FISHEYE` or `EQUIRECTANGULAR`.
     camera_model: Option<String>,
     // Nerfstudio doesn't mention this in their format? But fine to include really.
     ply_file_path: Option<String>,
@@ -65,7 +63,6 @@ struct JsonScene {
 }
 
 #[derive(serde::Deserialize, Clone)]
-#[allow(unused)] // not reading camera distortions yet.
 struct FrameData {
     // Horizontal FOV.
     camera_angle_x: Option<f64>,
@@ -86,7 +83,6 @@ struct FrameData {
     /// Image height. Should be an integer but read as float, fine to truncate.
     h: Option<f64>,
 
-    // TODO: These are unused currently.
     /// First radial distortion parameter used by [`OPENCV`, `OPENCV_FISHEYE`]
     k1: Option<f64>,
     /// Second radial distortion parameter used by [`OPENCV`, `OPENCV_FISHEYE`]
@@ -104,20 +100,52 @@ struct FrameData {
     file_path: String,
 }
 
+// The lens distortion of a frame. Frames can override the distortion of the scene.
+fn camera_model(scene: &JsonScene, frame: &FrameData) -> CameraModel {
+    let param = |frame: Option<f64>, scene: Option<f64>| frame.or(scene).unwrap_or(0.0) as f32;
+    let k1 = param(frame.k1, scene.k1);
+    let k2 = param(frame.k2, scene.k2);
+
+    match scene.camera_model.as_deref() {
+        Some("OPENCV_FISHEYE") => {
+            CameraModel::Fisheye([k1, k2, param(frame.k3, scene.k3), param(frame.k4, scene.k4)])
+        }
+        None | Some("OPENCV") => {
+            let params = [k1, k2, param(frame.p1, scene.p1), param(frame.p2, scene.p2)];
+            if params == [0.0; 4] {
+                CameraModel::Pinhole
+            } else {
+                CameraModel::OpenCv(params)
+            }
+        }
+        _ => CameraModel::Pinhole,
+    }
+}
+
 fn read_transforms_file(
-    scene: JsonScene,
+    mut scene: JsonScene,
     transforms_path: &Path,
     vfs: BrushVfs,
     load_args: &LoadDataseConfig,
 ) -> Vec<impl Future<Output = anyhow::Result<SceneView>>> {
-    let iter = scene
-        .frames
+    if let Some(model) = scene.camera_model.as_deref().filter(|m| {
+        !matches!(
+            *m,
+            "OPENCV" | "OPENCV_FISHEYE" | "PINHOLE" | "SIMPLE_PINHOLE"
+        )
+    }) {
+        log::warn!("Camera model {model} isn't supported, treating cameras as pinhole cameras");
+    }
+
+    let frames = std::mem::take(&mut scene.frames);
+    let iter = frames
         .into_iter()
         .take(load_args.max_frames.unwrap_or(usize::MAX))
         .map(move |frame| {
             let mut archive = vfs.clone();
             let load_args = load_args.clone();
             let transforms_path = transforms_path.to_path_buf();
+            let model = camera_model(&scene, &frame);
 
             async move {
                 // NeRF 'transform_matrix' is a camera-to-world transform
@@ -142,16 +170,16 @@ fn read_transforms_file(
                 }
 
                 let mask_path = find_mask_path(&archive, &path);
-                let (image, img_type) = load_image(&mut archive, &path, mask_path.as_deref())
+                let view_image = load_image(&mut archive, &path, mask_path.as_deref(), &load_args)
                     .await
                     .with_context(|| format!("Failed to load image {}", frame.file_path))?;
 
-                let image = Arc::new(image);
-
-                let w = frame.w.or(scene.w).unwrap_or(image.width() as f64) as u32;
-                let h = frame.h.or(scene.h).unwrap_or(image.height() as f64) as u32;
-
-                let image = clamp_img_to_max_size(image, load_args.max_resolution);
+                let (img_w, img_h) = view_image.original_size;
+                let w = frame.w.or(scene.w).unwrap_or(img_w as f64) as u32;
+                let h = frame.h.or(scene.h).unwrap_or(img_h as f64) as u32;
+                let depth = load_depth(&mut archive, &path, load_args.max_resolution)
+                    .await
+                    .with_context(|| format!("Failed to load depth of {}", frame.file_path))?;
 
                 let fovx = frame
                     .camera_angle_x
@@ -185,9 +213,11 @@ fn read_transforms_file(
 
                 let view = SceneView {
                     path: frame.file_path.clone(),
-                    camera: Camera::new(translation, rotation, fovx, fovy, cuv),
-                    image,
-                    img_type,
+                    camera: Camera::new(translation, rotation, fovx, fovy, cuv).with_model(model),
+                    image: view_image.image,
+                    img_type: view_image.img_type,
+                    original_size: view_image.original_size,
+                    depth,
                 };
                 anyhow::Result::<SceneView>::Ok(view)
             }
@@ -210,6 +240,11 @@ pub async fn read_dataset<B: Backend>(
 
     let transforms_path = if json_files.len() == 1 {
         json_files.first().cloned().expect("Must have 1 json file")
+    } else if let Some(transforms) = json_files
+        .iter()
+        .find(|x| x.file_name().is_some_and(|p| p == "transforms.json"))
+    {
+        transforms.clone()
     } else {
         let train = json_files.iter().find(|x| {
             x.file_name()
@@ -0,0 +1,186 @@
+//! Datasets with the cameras exported by photogrammetry tools, see [`reality_capture`] and
+//! [`metashape`]. These exports only have the cameras, an initial point cloud can be included
+//! as a ply file next to the images.
+
+use super::{find_mask_and_img, load_depth, load_image, metashape, reality_capture, DataStream};
+use crate::{
+    brush_vfs::BrushVfs, splat_import::SplatMessage, stream_fut_parallel, Dataset, LoadDataseConfig,
+};
+use anyhow::{Context, Result};
+use brush_render::{
+    camera::{focal_to_fov, Camera, CameraModel},
+    Backend,
+};
+use brush_train::scene::SceneView;
+use glam::{Affine3A, UVec2, Vec2};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncReadExt;
+use tokio_stream::StreamExt;
+
+/// The focal length and principal point of an exported camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Intrinsics {
+    /// In pixels of an image of `size`. Images of another resolution are scaled to match.
+    Pixels {
+        focal: Vec2,
+        center: Vec2,
+        size: UVec2,
+    },
+    /// The focal length of a 35mm film camera with the same field of view over the largest side
+    /// of the image. The offset of the principal point from the image center is in the same
+    /// units.
+    Equivalent35mm { focal: f32, offset: Vec2 },
+}
+
+impl Intrinsics {
+    /// The horizontal and vertical fov, and the principal point in uv, for an image of `size`.
+    pub fn fov_and_center(&self, size: UVec2) -> (f64, f64, Vec2) {
+        let (focal, center, size) = match *self {
+            Self::Pixels {
+                focal,
+                center,
+                size,
+            } => (focal, center, size),
+            Self::Equivalent35mm { focal, offset } => {
+                let pixels_per_mm = size.max_element() as f32 / 36.0;
+                let center = size.as_vec2() / 2.0 + offset * pixels_per_mm;
+                (Vec2::splat(focal * pixels_per_mm), center, size)
+            }
+        };
+        let fov_x = focal_to_fov(focal.x as f64, size.x);
+        let fov_y = focal_to_fov(focal.y as f64, size.y);
+        (fov_x, fov_y, center / size.as_vec2())
+    }
+}
+
+/// A camera of a photogrammetry export.
+#[derive(Debug, Clone)]
+pub struct ExportedView {
+    /// The name of the image, which might include its folder or leave out its extension.
+    pub name: String,
+    /// Camera to world, with the camera looking down +z and y down.
+    pub cam_to_world: Affine3A,
+    pub intrinsics: Intrinsics,
+    pub model: CameraModel,
+}
+
+// Exports often name images without their extension, so match on the file stem as well.
+fn image_candidates(vfs: &BrushVfs, name: &str) -> Vec<PathBuf> {
+    let name = name.replace('\\', "/");
+    let with_name: Vec<_> = vfs.file_names().filter(|p| p.ends_with(&name)).collect();
+    if !with_name.is_empty() {
+        return with_name;
+    }
+    let stem = Path::new(&name).file_stem().and_then(|s| s.to_str());
+    vfs.file_names()
+        .filter(|p| p.file_stem().and_then(|s| s.to_str()) == stem)
+        .collect()
+}
+
+async fn read_text(vfs: &mut BrushVfs, path: &Path) -> Result<String> {
+    let mut text = String::new();
+    vfs.open_path(path).await?.read_to_string(&mut text).await?;
+    Ok(text)
+}
+
+// Find and parse the camera export, either a RealityCapture csv or a Metashape xml.
+async fn read_export(vfs: &mut BrushVfs) -> Result<Vec<ExportedView>> {
+    let with_ext = |ext: &str| -> Vec<PathBuf> {
+        vfs.file_names()
+            .filter(|p| {
+                p.extension()
+                    .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case(ext))
+            })
+            .collect()
+    };
+    let (csv_files, xml_files) = (with_ext("csv"), with_ext("xml"));
+
+    for path in csv_files {
+        let text = read_text(vfs, &path).await?;
+        if reality_capture::is_camera_export(&text) {
+            log::info!("Loading RealityCapture cameras from {path:?}");
+            return reality_capture::parse_cameras(&text)
+                .with_context(|| format!("Failed to parse RealityCapture cameras {path:?}"));
+        }
+    }
+    for path in xml_files {
+        let text = read_text(vfs, &path).await?;
+        if metashape::is_camera_export(&text) {
+            log::info!("Loading Metashape cameras from {path:?}");
+            return metashape::parse_cameras(&text)
+                .with_context(|| format!("Failed to parse Metashape cameras {path:?}"));
+        }
+    }
+    anyhow::bail!("No RealityCapture csv or Metashape xml camera export found.")
+}
+
+pub(crate) async fn load_dataset<B: Backend>(
+    mut vfs: BrushVfs,
+    load_args: &LoadDataseConfig,
+) -> Result<(DataStream<SplatMessage<B>>, DataStream<Dataset>)> {
+    let mut views = read_export(&mut vfs).await?;
+    anyhow::ensure!(!views.is_empty(), "Camera export has no aligned cameras");
+
+    // Sort by name, for a stable eval split.
+    views.sort_by(|a, b| a.name.cmp(&b.name));
+    views.truncate(load_args.max_frames.unwrap_or(usize::MAX));
+    if let Some(subsample) = load_args.subsample_frames {
+        views = views.into_iter().step_by(subsample as usize).collect();
+    }
+    log::info!("Loading dataset with {} cameras", views.len());
+
+    let handles: Vec<_> = views
+        .into_iter()
+        .map(|view| {
+            let load_args = load_args.clone();
+            let mut vfs = vfs.clone();
+
+            async move {
+                let img_paths = image_candidates(&vfs, &view.name);
+                let (path, mask_path) = find_mask_and_img(&vfs, &img_paths)
+                    .with_context(|| format!("Failed to find image {}", view.name))?;
+                let view_image = load_image(&mut vfs, &path, mask_path.as_deref(), &load_args)
+                    .await
+                    .with_context(|| format!("Failed to load image {}", view.name))?;
+                let depth = load_depth(&mut vfs, &path, load_args.max_resolution)
+                    .await
+                    .with_context(|| format!("Failed to load depth of {}", view.name))?;
+
+                let (width, height) = view_image.original_size;
+                let (fov_x, fov_y, center_uv) =
+                    view.intrinsics.fov_and_center(glam::uvec2(width, height));
+                let (_, rotation, translation) = view.cam_to_world.to_scale_rotation_translation();
+                let camera = Camera::new(translation, rotation, fov_x, fov_y, center_uv)
+                    .with_model(view.model);
+
+                anyhow::Result::<SceneView>::Ok(SceneView {
+                    path: path.to_string_lossy().to_string(),
+                    camera,
+                    image: view_image.image,
+                    img_type: view_image.img_type,
+                    original_size: view_image.original_size,
+                    depth,
+                })
+            }
+        })
+        .collect();
+
+    let mut train_views = vec![];
+    let mut eval_views = vec![];
+    let eval_split_every = load_args.eval_split_every;
+
+    let mut i = 0;
+    let stream = stream_fut_parallel(handles).map(move |view| {
+        let view = view.context("Failed to load view")?;
+
+        match eval_split_every {
+            Some(eval_period) if i % eval_period == 0 => eval_views.push(view),
+            _ => train_views.push(view),
+        }
+
+        i += 1;
+        Ok(Dataset::from_views(train_views.clone(), eval_views.clone()))
+    });
+
+    Ok((Box::pin(tokio_stream::empty()), Box::pin(stream)))
+}
@@ -0,0 +1,137 @@
+//! Cameras exported by RealityCapture, with "Export > Registration > Internal/External camera
+//! parameters". This is a csv file with a line per image:
+//!
+//! `#name,x,y,alt,heading,pitch,roll,f,px,py,k1,k2,k3,k4,t1,t2`
+//!
+//! The focal length and principal point are 35mm equivalent, the angles are in degrees.
+
+use super::photogrammetry::{ExportedView, Intrinsics};
+use anyhow::{Context, Result};
+use brush_render::camera::CameraModel;
+use glam::{Affine3A, Mat3, Vec3};
+
+const COLUMNS: [&str; 16] = [
+    "name", "x", "y", "alt", "heading", "pitch", "roll", "f", "px", "py", "k1", "k2", "k3", "k4",
+    "t1", "t2",
+];
+
+fn header_columns(line: &str) -> Vec<String> {
+    line.trim_start_matches('#')
+        .split(',')
+        .map(|c| c.trim().to_lowercase())
+        .collect()
+}
+
+/// Whether a csv file looks like a RealityCapture camera export.
+pub fn is_camera_export(text: &str) -> bool {
+    text.lines().next().is_some_and(|header| {
+        let columns = header_columns(header);
+        ["name", "heading", "pitch", "roll", "f"]
+            .iter()
+            .all(|c| columns.iter().any(|h| h == c))
+    })
+}
+
+// The rotation of a camera with a heading, pitch and roll. Without any rotation, a camera
+// looks straight down with its top towards +y, a pitch of 90 degrees looks at the horizon.
+fn camera_rotation(heading: f32, pitch: f32, roll: f32) -> Mat3 {
+    let rotation = Mat3::from_rotation_z(-heading.to_radians())
+        * Mat3::from_rotation_x(pitch.to_radians())
+        * Mat3::from_rotation_y(roll.to_radians());
+    // The angles rotate a camera that looks down -z with y up, flip it to look down +z with y down.
+    rotation * Mat3::from_diagonal(glam::vec3(1.0, -1.0, -1.0))
+}
+
+/// Parse the cameras of a RealityCapture export.
+pub fn parse_cameras(text: &str) -> Result<Vec<ExportedView>> {
+    let mut lines = text.lines();
+    let header = header_columns(lines.next().context("Empty camera export")?);
+    let indices = COLUMNS.map(|name| header.iter().position(|h| h == name));
+
+    lines
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<_> = line.split(',').map(|f| f.trim()).collect();
+            let field = |column: usize| indices[column].and_then(|i| fields.get(i).copied());
+            let num = |column: usize| -> Result<f32> {
+                field(column).map_or(Ok(0.0), |f| {
+                    f.parse()
+                        .with_context(|| format!("Invalid {} '{f}'", COLUMNS[column]))
+                })
+            };
+
+            let name = field(0)
+                .context("Camera without an image name")?
+                .trim_matches('"')
+                .to_owned();
+            let [x, y, alt, heading, pitch, roll, f, px, py, k1, k2, k3, k4, t1, t2] =
+                std::array::from_fn(|i| num(i + 1));
+            let (k3, k4) = (k3?, k4?);
+            if k3 != 0.0 || k4 != 0.0 {
+                log::warn!("Ignoring the k3 and k4 distortion of {name}");
+            }
+
+            let params = [k1?, k2?, t1?, t2?];
+            let model = if params == [0.0; 4] {
+                CameraModel::Pinhole
+            } else {
+                CameraModel::OpenCv(params)
+            };
+
+            Ok(ExportedView {
+                cam_to_world: Affine3A::from_mat3_translation(
+                    camera_rotation(heading?, pitch?, roll?),
+                    Vec3::new(x?, y?, alt?),
+                ),
+                intrinsics: Intrinsics::Equivalent35mm {
+                    focal: f?,
+                    offset: glam::vec2(px?, py?),
+                },
+                model,
+                name,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = "#name,x,y,alt,heading,pitch,roll,f,px,py,k1,k2,k3,k4,t1,t2
+IMG_0001.JPG,1.5,-2.0,10.0,0.0,0.0,0.0,18.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0
+IMG_0002.JPG,0.0,0.0,1.0,90.0,90.0,0.0,24.0,0.36,0.0,-0.1,0.02,0.0,0.0,0.001,0.0
+";
+
+    #[test]
+    fn parses_cameras() {
+        assert!(is_camera_export(EXPORT));
+        assert!(!is_camera_export("x,y,z\n1,2,3\n"));
+
+        let views = parse_cameras(EXPORT).expect("Valid export");
+        assert_eq!(views.len(), 2);
+
+        // Without rotation, the camera looks straight down.
+        let nadir = &views[0];
+        assert_eq!(nadir.name, "IMG_0001.JPG");
+        assert_eq!(nadir.cam_to_world.translation, glam::vec3a(1.5, -2.0, 10.0));
+        let forward = nadir.cam_to_world.transform_vector3(Vec3::Z);
+        assert!(forward.abs_diff_eq(-Vec3::Z, 1e-5));
+        assert_eq!(nadir.model, CameraModel::Pinhole);
+
+        // An 18mm focal length sees 90 degrees over the largest side of the image.
+        let (fov_x, _, center) = nadir.intrinsics.fov_and_center(glam::uvec2(400, 300));
+        assert!((fov_x - std::f64::consts::FRAC_PI_2).abs() < 1e-5);
+        assert_eq!(center, glam::vec2(0.5, 0.5));
+
+        // Facing east at the horizon, with the top of the image up.
+        let east = &views[1];
+        let forward = east.cam_to_world.transform_vector3(Vec3::Z);
+        let up = east.cam_to_world.transform_vector3(-Vec3::Y);
+        assert!(forward.abs_diff_eq(Vec3::X, 1e-5));
+        assert!(up.abs_diff_eq(Vec3::Z, 1e-5));
+        assert_eq!(east.model, CameraModel::OpenCv([-0.1, 0.02, 0.001, 0.0]));
+        let (_, _, center) = east.intrinsics.fov_and_center(glam::uvec2(400, 300));
+        assert!((center.x - 0.51).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,238 @@
+//! Load a video file as a dataset.
+//!
+//! Frames are extracted with ffmpeg, which needs to be installed. Brush can't estimate the camera
+//! poses of a video itself yet, so the video needs a `trajectory.txt` next to it. This has a pose
+//! per line in the TUM format, `timestamp tx ty tz qx qy qz qw`, with the timestamp in seconds
+//! from the start of the video. Poses are camera to world transforms, in the same convention as
+//! brush cameras (+x right, +y down, +z forward). Frames in between poses get an interpolated
+//! pose, frames outside of the trajectory are skipped.
+
+use std::path::{Path, PathBuf};
+
+use super::{load_image, DataStream};
+use crate::{brush_vfs::BrushVfs, splat_import::SplatMessage, Dataset, LoadDataseConfig};
+use anyhow::Context;
+use async_fn_stream::try_fn_stream;
+use brush_render::{
+    camera::{focal_to_fov, fov_to_focal, Camera},
+    Backend,
+};
+use brush_train::scene::SceneView;
+use glam::{Quat, Vec3};
+use tokio::io::AsyncReadExt;
+
+const VIDEO_EXTENSIONS: [&str; 4] = ["mp4", "mov", "mkv", "webm"];
+const TRAJECTORY_NAME: &str = "trajectory.txt";
+
+pub(crate) fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pose {
+    time: f64,
+    translation: Vec3,
+    rotation: Quat,
+}
+
+fn parse_trajectory(text: &str) -> anyhow::Result<Vec<Pose>> {
+    let mut poses = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values = line
+            .split_whitespace()
+            .map(str::parse::<f64>)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid number on line {} of trajectory", i + 1))?;
+        let [time, tx, ty, tz, qx, qy, qz, qw] = values[..] else {
+            anyhow::bail!("Expected 8 values on line {} of trajectory", i + 1);
+        };
+
+        poses.push(Pose {
+            time,
+            translation: Vec3::new(tx as f32, ty as f32, tz as f32),
+            rotation: Quat::from_xyzw(qx as f32, qy as f32, qz as f32, qw as f32).normalize(),
+        });
+    }
+
+    poses.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(poses)
+}
+
+// Interpolate the pose at a time, or None if the time is outside of the trajectory.
+fn pose_at(poses: &[Pose], time: f64) -> Option<(Vec3, Quat)> {
+    let next = poses.partition_point(|p| p.time < time);
+    let after = poses.get(next)?;
+
+    if after.time == time {
+        return Some((after.translation, after.rotation));
+    }
+
+    let before = poses.get(next.checked_sub(1)?)?;
+    let t = ((time - before.time) / (after.time - before.time)) as f32;
+    Some((
+        before.translation.lerp(after.translation, t),
+        before.rotation.slerp(after.rotation, t),
+    ))
+}
+
+// Extract frames from the video with ffmpeg, to a new temporary directory.
+async fn extract_frames(
+    vfs: &mut BrushVfs,
+    video_path: &Path,
+    fps: f32,
+) -> anyhow::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("brush_video_{:016x}", rand::random::<u64>()));
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let mut data = vec![];
+    vfs.open_path(video_path)
+        .await?
+        .read_to_end(&mut data)
+        .await?;
+    let ext = video_path.extension().unwrap_or_default();
+    let input = dir.join("input").with_extension(ext);
+    tokio::fs::write(&input, data).await?;
+
+    let output = tokio::process::Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-i"])
+        .arg(&input)
+        .args(["-vf", &format!("fps={fps}"), "-q:v", "2"])
+        .arg(dir.join("frame_%05d.jpg"))
+        .output()
+        .await
+        .context("Failed to run ffmpeg. Make sure ffmpeg is installed to load videos.")?;
+
+    tokio::fs::remove_file(&input).await?;
+
+    if !output.status.success() {
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        anyhow::bail!(
+            "ffmpeg failed to extract frames: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(dir)
+}
+
+pub(crate) async fn load_dataset<B: Backend>(
+    mut vfs: BrushVfs,
+    load_args: &LoadDataseConfig,
+    _device: &B::Device,
+) -> anyhow::Result<(DataStream<SplatMessage<B>>, DataStream<Dataset>)> {
+    let videos: Vec<_> = vfs.file_names().filter(|p| is_video(p)).collect();
+    let [video_path] = videos.as_slice() else {
+        anyhow::bail!("Expected a single video file, found {}", videos.len());
+    };
+
+    let trajectory_path = vfs
+        .file_names()
+        .find(|p| p.file_name().is_some_and(|name| name == TRAJECTORY_NAME))
+        .with_context(|| {
+            format!(
+                "Can't estimate camera poses for videos. Add a {TRAJECTORY_NAME} with the camera poses next to the video."
+            )
+        })?;
+    let mut trajectory = String::new();
+    vfs.open_path(&trajectory_path)
+        .await?
+        .read_to_string(&mut trajectory)
+        .await?;
+    let poses = parse_trajectory(&trajectory)?;
+    anyhow::ensure!(!poses.is_empty(), "Trajectory has no poses");
+
+    anyhow::ensure!(load_args.video_fps > 0.0, "Video fps must be positive");
+    let frames_dir = extract_frames(&mut vfs, video_path, load_args.video_fps).await?;
+    let mut frames_vfs = BrushVfs::from_directory(&frames_dir).await?;
+    let mut frame_paths: Vec<_> = frames_vfs.file_names().collect();
+    frame_paths.sort();
+
+    log::info!(
+        "Extracted {} frames from video {video_path:?}",
+        frame_paths.len()
+    );
+
+    let load_args = load_args.clone();
+    let dataset_stream = try_fn_stream(|emitter| async move {
+        let mut train_views = vec![];
+        let mut eval_views = vec![];
+
+        let subsample = load_args.subsample_frames.unwrap_or(1) as usize;
+        let max_frames = load_args.max_frames.unwrap_or(usize::MAX);
+        let fov_x = load_args.video_fov.to_radians();
+
+        let mut i = 0;
+        for (frame, path) in frame_paths.iter().enumerate().step_by(subsample) {
+            if i >= max_frames {
+                break;
+            }
+
+            let time = frame as f64 / load_args.video_fps as f64;
+            let Some((translation, rotation)) = pose_at(&poses, time) else {
+                continue;
+            };
+
+            let view_image = load_image(&mut frames_vfs, path, None, &load_args).await?;
+            let (width, height) = view_image.original_size;
+            let fov_y = focal_to_fov(fov_to_focal(fov_x, width), height);
+
+            let view = SceneView {
+                path: path.to_string_lossy().to_string(),
+                camera: Camera::new(translation, rotation, fov_x, fov_y, glam::vec2(0.5, 0.5)),
+                image: view_image.image,
+                img_type: view_image.img_type,
+                original_size: view_image.original_size,
+                depth: None,
+            };
+
+            if load_args
+                .eval_split_every
+                .is_some_and(|every| i % every == 0)
+            {
+                eval_views.push(view);
+            } else {
+                train_views.push(view);
+            }
+            i += 1;
+
+            emitter
+                .emit(Dataset::from_views(train_views.clone(), eval_views.clone()))
+                .await;
+        }
+
+        // Frames are all loaded in memory now.
+        let _ = tokio::fs::remove_dir_all(&frames_dir).await;
+        Ok(())
+    });
+
+    let init_stream = tokio_stream::empty::<anyhow::Result<SplatMessage<B>>>();
+    Ok((Box::pin(init_stream), Box::pin(dataset_stream)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolate_trajectory() {
+        let poses =
+            parse_trajectory("# t x y z qx qy qz qw\n1.0 2 0 0 0 0 0 1\n0.0 0 0 0 0 0 0 1\n")
+                .expect("Failed to parse trajectory");
+        assert_eq!(poses.len(), 2);
+        assert_eq!(poses[0].time, 0.0);
+
+        let (translation, _) = pose_at(&poses, 0.25).expect("Time is in trajectory");
+        assert!((translation.x - 0.5).abs() < 1e-6);
+        assert!(pose_at(&poses, 1.0).is_some());
+        assert!(pose_at(&poses, -0.5).is_none());
+        assert!(pose_at(&poses, 1.5).is_none());
+    }
+}
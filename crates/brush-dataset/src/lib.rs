@@ -1,8 +1,24 @@
 pub mod brush_vfs;
+pub mod colmap_export;
 mod formats;
 pub mod scene_loader;
+pub mod splat_align;
+pub mod splat_chunks;
+pub mod splat_compress;
 pub mod splat_export;
+pub mod splat_floaters;
+pub mod splat_gltf;
+pub mod splat_holes;
 pub mod splat_import;
+pub mod splat_ksplat;
+pub mod splat_mesh;
+pub mod splat_normals;
+#[cfg(not(target_family = "wasm"))]
+pub mod splat_ply_mmap;
+pub mod splat_simplify;
+pub mod splat_spz;
+pub mod splat_usdz;
+pub mod synthetic;
 
 use burn::config::Config;
 pub use formats::clamp_img_to_max_size;
@@ -14,7 +30,7 @@ use core::f32;
 use std::future::Future;
 
 use clap::Args;
-use glam::{Mat3, Mat4, Vec3};
+use glam::{Affine3A, Mat3, Mat4, Quat, Vec3};
 use tokio_stream::Stream;
 use tokio_with_wasm::alias as tokio_wasm;
 
@@ -36,6 +52,28 @@ pub struct LoadDataseConfig {
     /// Load only every nth point from the initial sfm data
     #[arg(long, help_heading = "Dataset Options")]
     pub subsample_points: Option<u32>,
+    /// Use the alpha channel of images as a mask instead of as transparency. Masked out pixels
+    /// are ignored during training, rather than trained to be transparent.
+    #[arg(long, help_heading = "Dataset Options", default_value = "false")]
+    #[config(default = false)]
+    pub alpha_as_mask: bool,
+    /// For COLMAP datasets, use the depth of the sparse points seen by each view to supervise
+    /// the depth while training, for views without a depth map in a `depths` folder.
+    #[arg(long, help_heading = "Dataset Options", default_value = "false")]
+    #[config(default = false)]
+    pub sparse_depth: bool,
+    /// Nr. of frames per second to extract when loading a video.
+    #[arg(long, help_heading = "Dataset Options", default_value = "2")]
+    #[config(default = 2.0)]
+    pub video_fps: f32,
+    /// Horizontal field of view of the camera of a video, in degrees.
+    #[arg(long, help_heading = "Dataset Options", default_value = "60")]
+    #[config(default = 60.0)]
+    pub video_fov: f64,
+    /// Directory to cache decoded and resized images in, eg. `.brush-cache`. Loading the same
+    /// images with the same max resolution again reads them from the cache instead.
+    #[arg(long, help_heading = "Dataset Options")]
+    pub image_cache: Option<String>,
 }
 
 #[derive(Config, Debug, Args)]
@@ -215,6 +253,29 @@ impl Dataset {
 
         Vec3::new(-transform.col(0).z, -transform.col(1).z, transform.col(2).z)
     }
+
+    /// Transform all cameras of this dataset. The transform can't have a scale.
+    pub fn transformed(&self, transform: Affine3A) -> Self {
+        let transform_scene = |scene: &Scene| {
+            let views = scene
+                .views
+                .iter()
+                .map(|view| {
+                    let mut view = view.clone();
+                    let local_to_world = transform * view.camera.local_to_world();
+                    view.camera.position = local_to_world.translation.into();
+                    view.camera.rotation = Quat::from_mat3a(&local_to_world.matrix3);
+                    view
+                })
+                .collect();
+            Scene::new(views)
+        };
+
+        Self {
+            train: transform_scene(&self.train),
+            eval: self.eval.as_ref().map(transform_scene),
+        }
+    }
 }
 
 pub(crate) fn stream_fut_parallel<T: Send + 'static>(
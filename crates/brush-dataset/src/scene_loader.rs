@@ -1,46 +1,77 @@
 use brush_render::Backend;
-use brush_train::image::view_to_sample;
+use brush_train::image::{view_to_depth, view_to_sample};
 use brush_train::scene::Scene;
 use brush_train::train::SceneBatch;
-use rand::{seq::SliceRandom, SeedableRng};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
 use tokio::sync::mpsc;
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
 use tokio_with_wasm::alias as tokio_wasm;
 
+// Views added to the scene while training make up this share of the batches...
+const NEW_VIEW_SHARE: f64 = 0.5;
+// ...until each of them has been picked this many times.
+const NEW_VIEW_PICKS: u32 = 4;
+
 pub struct SceneLoader<B: Backend> {
     receiver: Receiver<SceneBatch<B>>,
+    scenes: UnboundedSender<Scene>,
 }
 
 impl<B: Backend> SceneLoader<B> {
     pub fn new(scene: &Scene, seed: u64, device: &B::Device) -> Self {
-        let scene = scene.clone();
+        let mut scene = scene.clone();
         // The bounded size == number of batches to prefetch.
         let (tx, rx) = mpsc::channel(5);
+        let (scenes, mut scene_updates) = mpsc::unbounded_channel::<Scene>();
         let device = device.clone();
 
-        let scene_extent = scene.estimate_extent().unwrap_or(1.0);
+        let mut scene_extent = scene.estimate_extent().unwrap_or(1.0);
 
         let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
 
         let fut = async move {
             let mut shuf_indices = vec![];
+            // How many more times each view is picked as a new view.
+            let mut new_picks = vec![0; scene.views.len()];
 
             loop {
-                let (gt_image, gt_view) = {
-                    let index = shuf_indices.pop().unwrap_or_else(|| {
-                        shuf_indices = (0..scene.views.len()).collect();
-                        shuf_indices.shuffle(&mut rng);
-                        shuf_indices
-                            .pop()
-                            .expect("Need at least one view in dataset")
-                    });
+                while let Ok(updated) = scene_updates.try_recv() {
+                    new_picks.resize(updated.views.len(), NEW_VIEW_PICKS);
+                    scene_extent = updated.estimate_extent().unwrap_or(1.0);
+                    scene = updated;
+                }
+
+                let (gt_image, gt_depth, gt_view, view_index) = {
+                    let new_views: Vec<_> =
+                        (0..new_picks.len()).filter(|&i| new_picks[i] > 0).collect();
+
+                    let index = if !new_views.is_empty() && rng.gen_bool(NEW_VIEW_SHARE) {
+                        let index = *new_views.choose(&mut rng).expect("Checked non-empty");
+                        new_picks[index] -= 1;
+                        index
+                    } else {
+                        shuf_indices.pop().unwrap_or_else(|| {
+                            shuf_indices = (0..scene.views.len()).collect();
+                            shuf_indices.shuffle(&mut rng);
+                            shuf_indices
+                                .pop()
+                                .expect("Need at least one view in dataset")
+                        })
+                    };
                     let view = scene.views[index].clone();
-                    (view_to_sample(&view, &device), view)
+                    (
+                        view_to_sample(&view, &device),
+                        view_to_depth(&view, &device),
+                        view,
+                        index,
+                    )
                 };
 
                 let scene_batch = SceneBatch {
                     gt_image,
+                    gt_depth,
                     gt_view,
+                    view_index,
                     scene_extent,
                 };
 
@@ -51,7 +82,17 @@ impl<B: Backend> SceneLoader<B> {
         };
 
         tokio_wasm::spawn(fut);
-        Self { receiver: rx }
+        Self {
+            receiver: rx,
+            scenes,
+        }
+    }
+
+    /// Load views of an updated scene from now on, which has the views of the current scene
+    /// and possibly new views after them. New views are picked more often until they've been
+    /// trained on a few times.
+    pub fn update_scene(&self, scene: &Scene) {
+        let _ = self.scenes.send(scene.clone());
     }
 
     pub async fn next_batch(&mut self) -> SceneBatch<B> {
@@ -0,0 +1,234 @@
+use anyhow::anyhow;
+use brush_render::{gaussian_splats::Splats, Backend};
+use glam::{Affine3A, Quat, Vec3};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+
+// Max nr. of points considered when fitting the ground plane.
+const MAX_POINTS: usize = 20000;
+const RANSAC_ITERS: usize = 512;
+
+fn percentile(values: &mut [f32], p: f32) -> f32 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let i = ((values.len() - 1) as f32 * p).round() as usize;
+    values[i]
+}
+
+/// Estimate a transform that levels the ground plane of a scene and centers it at the origin.
+///
+/// The ground is the plane with the most points on it, that is within 30 degrees of
+/// `up_hint`. After the transform the up direction is -y, as splats use a y-down convention,
+/// the ground is at y = 0, and the median of the points is at x = z = 0.
+pub fn estimate_alignment(points: &[Vec3], up_hint: Vec3) -> Affine3A {
+    let mut up = up_hint.try_normalize().unwrap_or(Vec3::NEG_Y);
+    if points.is_empty() {
+        return Affine3A::from_quat(Quat::from_rotation_arc(up, Vec3::NEG_Y));
+    }
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let points: Vec<Vec3> = if points.len() > MAX_POINTS {
+        points
+            .choose_multiple(&mut rng, MAX_POINTS)
+            .copied()
+            .collect()
+    } else {
+        points.to_vec()
+    };
+
+    let (min, max) = points.iter().fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(min, max), &p| (min.min(p), max.max(p)),
+    );
+    let threshold = (max - min).length() * 0.005;
+
+    let mut best: Option<(usize, Vec3, f32)> = None;
+    if points.len() >= 3 {
+        for _ in 0..RANSAC_ITERS {
+            let [a, b, c] = [0; 3].map(|_| points[rng.gen_range(0..points.len())]);
+            let Some(mut normal) = (b - a).cross(c - a).try_normalize() else {
+                continue;
+            };
+            if normal.dot(up) < 0.0 {
+                normal = -normal;
+            }
+            if normal.dot(up) < 30f32.to_radians().cos() {
+                continue;
+            }
+            let offset = normal.dot(a);
+            let inliers = points
+                .iter()
+                .filter(|p| (normal.dot(**p) - offset).abs() < threshold)
+                .count();
+            if inliers > best.map_or(0, |(count, _, _)| count) {
+                best = Some((inliers, normal, offset));
+            }
+        }
+    }
+
+    let ground = if let Some((_, normal, offset)) = best {
+        up = normal;
+        offset
+    } else {
+        // No plane found, put the ground just below the lowest points.
+        let mut heights: Vec<f32> = points.iter().map(|p| p.dot(up)).collect();
+        percentile(&mut heights, 0.02)
+    };
+
+    let rotation = Quat::from_rotation_arc(up, Vec3::NEG_Y);
+
+    // Center the scene on the median of the points along the ground.
+    let rotated: Vec<Vec3> = points.iter().map(|p| rotation * *p).collect();
+    let mut xs: Vec<f32> = rotated.iter().map(|p| p.x).collect();
+    let mut zs: Vec<f32> = rotated.iter().map(|p| p.z).collect();
+    let center = Vec3::new(percentile(&mut xs, 0.5), -ground, percentile(&mut zs, 0.5));
+
+    Affine3A::from_rotation_translation(rotation, -center)
+}
+
+/// Estimate a transform that only levels and centers a scene, without looking for a ground
+/// plane. `up_hint` is rotated to -y and the median of the points is moved to the origin.
+///
+/// This is meant for points that don't lie on the scene's surfaces, like camera positions.
+pub fn estimate_centering(points: &[Vec3], up_hint: Vec3) -> Affine3A {
+    let up = up_hint.try_normalize().unwrap_or(Vec3::NEG_Y);
+    let rotation = Quat::from_rotation_arc(up, Vec3::NEG_Y);
+    if points.is_empty() {
+        return Affine3A::from_quat(rotation);
+    }
+
+    let rotated: Vec<Vec3> = points.iter().map(|p| rotation * *p).collect();
+    let mut xs: Vec<f32> = rotated.iter().map(|p| p.x).collect();
+    let mut ys: Vec<f32> = rotated.iter().map(|p| p.y).collect();
+    let mut zs: Vec<f32> = rotated.iter().map(|p| p.z).collect();
+    let center = Vec3::new(
+        percentile(&mut xs, 0.5),
+        percentile(&mut ys, 0.5),
+        percentile(&mut zs, 0.5),
+    );
+
+    Affine3A::from_rotation_translation(rotation, -center)
+}
+
+/// Estimate the alignment of splats, see [`estimate_alignment`]. Only the opaque splats are
+/// taken into account, so floaters don't affect the ground plane.
+pub async fn estimate_splat_alignment<B: Backend>(
+    splats: &Splats<B>,
+    up_hint: Vec3,
+) -> anyhow::Result<Affine3A> {
+    let read_err = |e| anyhow!("Failed to read data from splat {e:?}");
+    let means: Vec<f32> = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let opacities: Vec<f32> = splats
+        .opacity()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+
+    let points: Vec<Vec3> = means
+        .chunks(3)
+        .zip(&opacities)
+        .filter(|(_, &opac)| opac > 0.5)
+        .map(|(m, _)| Vec3::from_slice(m))
+        .collect();
+
+    Ok(estimate_alignment(&points, up_hint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A flat grid of ground points at height `y`, centered on (cx, cz), with a few
+    // points above it.
+    fn ground_scene(cx: f32, y: f32, cz: f32) -> (Vec<Vec3>, Vec<Vec3>) {
+        let ground: Vec<Vec3> = (0..21)
+            .flat_map(|i| (0..21).map(move |j| (i, j)))
+            .map(|(i, j)| Vec3::new(cx + i as f32 * 0.1 - 1.0, y, cz + j as f32 * 0.1 - 1.0))
+            .collect();
+        // Above is -y, as splats are y-down.
+        let above = (0..40)
+            .map(|i| {
+                let t = i as f32 / 40.0;
+                Vec3::new(cx + t - 0.5, y - 0.2 - t, cz + 0.5 - t)
+            })
+            .collect();
+        (ground, above)
+    }
+
+    #[test]
+    fn percentile_picks_sorted_value() {
+        let mut values = vec![5.0, 1.0, 3.0, 2.0, 4.0];
+        assert_eq!(percentile(&mut values, 0.0), 1.0);
+        assert_eq!(percentile(&mut values, 0.5), 3.0);
+        assert_eq!(percentile(&mut values, 1.0), 5.0);
+    }
+
+    #[test]
+    fn ground_moves_to_origin() {
+        let (ground, above) = ground_scene(3.0, 2.0, -1.0);
+        let points: Vec<Vec3> = ground.iter().chain(&above).copied().collect();
+        let transform = estimate_alignment(&points, Vec3::NEG_Y);
+
+        for p in &ground {
+            assert!(transform.transform_point3(*p).y.abs() < 1e-3);
+        }
+        for p in &above {
+            assert!(transform.transform_point3(*p).y < 0.0);
+        }
+        let center = transform.transform_point3(Vec3::new(3.0, 2.0, -1.0));
+        assert!(center.length() < 0.1, "{center}");
+    }
+
+    #[test]
+    fn tilted_ground_is_leveled() {
+        let (ground, above) = ground_scene(0.0, 1.0, 0.0);
+        let tilt = Quat::from_rotation_x(20f32.to_radians());
+        let ground: Vec<Vec3> = ground.iter().map(|p| tilt * *p).collect();
+        let above: Vec<Vec3> = above.iter().map(|p| tilt * *p).collect();
+        let points: Vec<Vec3> = ground.iter().chain(&above).copied().collect();
+
+        // The hint is off by the tilt, but within 30 degrees of the ground normal.
+        let transform = estimate_alignment(&points, Vec3::NEG_Y);
+        for p in &ground {
+            assert!(transform.transform_point3(*p).y.abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn no_points_only_rotates() {
+        let transform = estimate_alignment(&[], Vec3::Z);
+        assert!(transform.translation.length() < 1e-6);
+        assert!(transform.transform_vector3(Vec3::Z).distance(Vec3::NEG_Y) < 1e-5);
+    }
+
+    #[test]
+    fn centering_ignores_planes() {
+        // Cameras on a ring above the scene all lie on a plane, which must not be
+        // treated as the ground.
+        let cameras: Vec<Vec3> = (0..16)
+            .map(|i| {
+                let a = i as f32 / 16.0 * std::f32::consts::TAU;
+                Vec3::new(2.0 + a.cos() * 4.0, 5.0, 1.0 + a.sin() * 4.0)
+            })
+            .collect();
+        let transform = estimate_centering(&cameras, Vec3::NEG_Y);
+
+        let center = transform.transform_point3(Vec3::new(2.0, 5.0, 1.0));
+        assert!(center.length() < 1.0, "{center}");
+        assert!(
+            transform
+                .transform_vector3(Vec3::NEG_Y)
+                .distance(Vec3::NEG_Y)
+                < 1e-5
+        );
+        // The cameras stay at the same height as the center.
+        for c in &cameras {
+            assert!(transform.transform_point3(*c).y.abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Spatially partitioned splat scenes, to view scenes that don't fit in memory at once.
+//!
+//! A chunked scene is a directory with a [`CHUNK_INDEX_NAME`] index, and a ply file for every
+//! chunk. The index lists the bounds of every chunk, so the viewer only has to load the chunks
+//! near the camera, see [`ChunkResidency`].
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+use brush_render::{camera::Camera, gaussian_splats::Splats, Backend};
+use burn::tensor::{Int, Tensor, TensorData};
+use glam::Vec3;
+
+pub const CHUNK_INDEX_NAME: &str = "chunks.json";
+pub const CHUNK_INDEX_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ChunkInfo {
+    /// Path of the chunk's ply file, relative to the index.
+    pub path: String,
+    /// Bounds of the splat centers in the chunk.
+    pub min: Vec3,
+    pub max: Vec3,
+    pub num_splats: usize,
+}
+
+impl ChunkInfo {
+    fn distance_to(&self, pos: Vec3) -> f32 {
+        (self.min - pos)
+            .max(pos - self.max)
+            .max(Vec3::ZERO)
+            .length()
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ChunkIndex {
+    pub version: u32,
+    pub chunks: Vec<ChunkInfo>,
+}
+
+impl ChunkIndex {
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let index: Self = serde_json::from_str(json)?;
+        anyhow::ensure!(
+            index.version <= CHUNK_INDEX_VERSION,
+            "Unsupported chunk index version {}",
+            index.version
+        );
+        Ok(index)
+    }
+
+    pub fn total_splats(&self) -> usize {
+        self.chunks.iter().map(|c| c.num_splats).sum()
+    }
+
+    /// The chunks to keep loaded for a camera, most important first, with at most `budget`
+    /// splats in total. The closest chunk is always included.
+    ///
+    /// Chunks are ranked by their distance to the camera, where chunks behind the camera count
+    /// as further away.
+    pub fn wanted(&self, camera: &Camera, budget: usize) -> Vec<usize> {
+        let world_to_local = camera.world_to_local();
+        let priority = |chunk: &ChunkInfo| {
+            let dist = chunk.distance_to(camera.position);
+            let center = world_to_local.transform_point3((chunk.min + chunk.max) / 2.0);
+            let radius = (chunk.max - chunk.min).length() / 2.0;
+            if center.z < -radius {
+                dist * 4.0
+            } else {
+                dist
+            }
+        };
+
+        let mut order: Vec<(usize, f32)> = self
+            .chunks
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (i, priority(c)))
+            .collect();
+        order.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut total = 0;
+        let mut wanted = vec![];
+        for (i, _) in order {
+            let n = self.chunks[i].num_splats;
+            if !wanted.is_empty() && total + n > budget {
+                break;
+            }
+            total += n;
+            wanted.push(i);
+        }
+        wanted
+    }
+}
+
+// Split the splats in two at the median of the longest axis, until every part is small enough.
+fn partition(means: &[Vec3], mut indices: Vec<usize>, max_splats: usize) -> Vec<Vec<usize>> {
+    if indices.len() <= max_splats {
+        return vec![indices];
+    }
+    let (min, max) = indices.iter().fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(min, max), &i| (min.min(means[i]), max.max(means[i])),
+    );
+    let size = max - min;
+    let axis = if size.x >= size.y && size.x >= size.z {
+        0
+    } else if size.y >= size.z {
+        1
+    } else {
+        2
+    };
+    let mid = indices.len() / 2;
+    indices.select_nth_unstable_by(mid, |&a, &b| means[a][axis].total_cmp(&means[b][axis]));
+    let upper = indices.split_off(mid);
+
+    let mut parts = partition(means, indices, max_splats);
+    parts.extend(partition(means, upper, max_splats));
+    parts
+}
+
+// The chunks of the splats, with the indices of the splats in every chunk.
+async fn partition_indices<B: Backend>(
+    splats: &Splats<B>,
+    max_chunk_splats: usize,
+) -> anyhow::Result<Vec<(ChunkInfo, Vec<usize>)>> {
+    let means: Vec<f32> = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(|e| anyhow!("Failed to read data from splat {e:?}"))?;
+    let means: Vec<Vec3> = means.chunks(3).map(Vec3::from_slice).collect();
+    let n = means.len();
+
+    let parts = partition(&means, (0..n).collect(), max_chunk_splats.max(1));
+    Ok(parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let (min, max) = part.iter().fold(
+                (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+                |(min, max), &i| (min.min(means[i]), max.max(means[i])),
+            );
+            let info = ChunkInfo {
+                path: format!("chunk_{i:04}.ply"),
+                min,
+                max,
+                num_splats: part.len(),
+            };
+            (info, part)
+        })
+        .collect())
+}
+
+// The splats of one chunk, gathered with a single select.
+fn chunk_splats<B: Backend>(splats: &Splats<B>, part: &[usize]) -> Splats<B> {
+    let inds: Vec<i32> = part.iter().map(|&i| i as i32).collect();
+    let inds = Tensor::<B, 1, Int>::from_data(
+        TensorData::new(inds, [part.len()]),
+        &splats.means.val().device(),
+    );
+    splats.selected(inds)
+}
+
+/// Split splats into spatially compact chunks of at most `max_chunk_splats` splats.
+///
+/// Returns the chunks with their bounds. The paths in the returned [`ChunkInfo`]s are
+/// `chunk_{i}.ply`. This keeps all chunks in memory at once, see [`write_chunked`] to write
+/// them out one by one.
+pub async fn partition_splats<B: Backend>(
+    splats: &Splats<B>,
+    max_chunk_splats: usize,
+) -> anyhow::Result<Vec<(ChunkInfo, Splats<B>)>> {
+    Ok(partition_indices(splats, max_chunk_splats)
+        .await?
+        .into_iter()
+        .map(|(info, part)| {
+            let chunk = chunk_splats(splats, &part);
+            (info, chunk)
+        })
+        .collect())
+}
+
+/// Write splats as a chunked scene to a directory, see [`partition_splats`]. Chunks are
+/// gathered and written one at a time, so only one chunk is copied at once.
+#[cfg(not(target_family = "wasm"))]
+pub async fn write_chunked<B: Backend>(
+    splats: &Splats<B>,
+    dir: &std::path::Path,
+    max_chunk_splats: usize,
+) -> anyhow::Result<ChunkIndex> {
+    tokio::fs::create_dir_all(dir).await?;
+
+    let mut index = ChunkIndex {
+        version: CHUNK_INDEX_VERSION,
+        chunks: vec![],
+    };
+    for (info, part) in partition_indices(splats, max_chunk_splats).await? {
+        let data = crate::splat_export::splat_to_ply(chunk_splats(splats, &part)).await?;
+        tokio::fs::write(dir.join(&info.path), data).await?;
+        index.chunks.push(info);
+    }
+
+    // Write the index last, so it only exists once all chunks are there.
+    tokio::fs::write(dir.join(CHUNK_INDEX_NAME), serde_json::to_string(&index)?).await?;
+    Ok(index)
+}
+
+/// Keeps track of which chunks of a chunked scene are loaded.
+///
+/// Loading chunks is up to the caller: [`Self::update`] returns the chunks to load for a
+/// camera, which are handed back with [`Self::finish_load`]. Chunks that are no longer
+/// wanted are dropped.
+pub struct ChunkResidency<B: Backend> {
+    index: ChunkIndex,
+    /// Max nr. of splats to keep loaded.
+    pub budget: usize,
+    wanted: HashSet<usize>,
+    resident: HashMap<usize, Splats<B>>,
+    loading: HashSet<usize>,
+    merged: Option<Splats<B>>,
+    dirty: bool,
+}
+
+impl<B: Backend> ChunkResidency<B> {
+    pub fn new(index: ChunkIndex, budget: usize) -> Self {
+        Self {
+            index,
+            budget,
+            wanted: HashSet::new(),
+            resident: HashMap::new(),
+            loading: HashSet::new(),
+            merged: None,
+            dirty: false,
+        }
+    }
+
+    pub fn index(&self) -> &ChunkIndex {
+        &self.index
+    }
+
+    pub fn num_resident_splats(&self) -> usize {
+        self.resident.values().map(|s| s.num_splats()).sum()
+    }
+
+    /// Whether the loaded chunks changed since the last call to [`Self::splats`].
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Drop the chunks that aren't needed for this camera anymore. Returns the chunks that
+    /// should start loading, in order of importance.
+    pub fn update(&mut self, camera: &Camera) -> Vec<usize> {
+        let wanted = self.index.wanted(camera, self.budget);
+        self.wanted = wanted.iter().copied().collect();
+
+        let before = self.resident.len();
+        self.resident.retain(|i, _| self.wanted.contains(i));
+        self.dirty |= self.resident.len() != before;
+
+        let load: Vec<usize> = wanted
+            .into_iter()
+            .filter(|i| !self.resident.contains_key(i) && !self.loading.contains(i))
+            .collect();
+        self.loading.extend(&load);
+        load
+    }
+
+    /// Hand back a chunk returned by [`Self::update`], or None if it failed to load.
+    pub fn finish_load(&mut self, chunk: usize, splats: Option<Splats<B>>) {
+        self.loading.remove(&chunk);
+        if let Some(splats) = splats {
+            // The camera might have moved on while loading.
+            if self.wanted.contains(&chunk) {
+                self.resident.insert(chunk, splats);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// All loaded chunks, merged into one set of splats.
+    pub fn splats(&mut self) -> Option<Splats<B>> {
+        if self.dirty {
+            let mut chunks: Vec<_> = self.resident.iter().collect();
+            chunks.sort_by_key(|(i, _)| **i);
+            let chunks: Vec<_> = chunks.into_iter().map(|(_, s)| s.clone()).collect();
+            self.merged = (!chunks.is_empty()).then(|| Splats::merge(&chunks));
+            self.dirty = false;
+        }
+        self.merged.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::backend::{wgpu::WgpuDevice, Wgpu};
+    use glam::{Quat, Vec2};
+
+    fn chunk(x: f32, num_splats: usize) -> ChunkInfo {
+        ChunkInfo {
+            path: String::new(),
+            min: Vec3::new(x, 0.0, 0.0),
+            max: Vec3::new(x + 1.0, 1.0, 1.0),
+            num_splats,
+        }
+    }
+
+    #[test]
+    fn wanted_chunks() {
+        let index = ChunkIndex {
+            version: CHUNK_INDEX_VERSION,
+            chunks: vec![chunk(10.0, 100), chunk(0.0, 100), chunk(5.0, 100)],
+        };
+        // Inside the second chunk, looking along +x.
+        let camera = Camera::new(
+            Vec3::new(0.5, 0.5, 0.5),
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            1.0,
+            1.0,
+            Vec2::splat(0.5),
+        );
+        assert_eq!(index.wanted(&camera, 300), vec![1, 2, 0]);
+        assert_eq!(index.wanted(&camera, 250), vec![1, 2]);
+        // The closest chunk is always wanted.
+        assert_eq!(index.wanted(&camera, 10), vec![1]);
+    }
+
+    #[test]
+    fn partition_splits_in_half() {
+        let means: Vec<Vec3> = (0..10).map(|i| Vec3::new(i as f32, 0.0, 0.0)).collect();
+        let parts = partition(&means, (0..10).collect(), 3);
+        assert!(parts.iter().all(|p| p.len() <= 3));
+        assert_eq!(parts.iter().map(|p| p.len()).sum::<usize>(), 10);
+        // Parts are spatially separated along x.
+        let first_max = parts[0].iter().map(|&i| means[i].x).fold(0.0, f32::max);
+        assert!(parts[1].iter().all(|&i| means[i].x > first_max));
+    }
+
+    #[tokio::test]
+    async fn chunks_keep_every_splat() {
+        let device = WgpuDevice::DefaultDevice;
+        let means: Vec<Vec3> = (0..100)
+            .map(|i| Vec3::new(i as f32, (i % 7) as f32, 0.0))
+            .collect();
+        let splats = Splats::<Wgpu>::from_raw(&means, None, None, None, None, &device);
+
+        let chunks = partition_splats(&splats, 30)
+            .await
+            .expect("Failed to partition");
+        assert_eq!(
+            chunks.iter().map(|(_, c)| c.num_splats()).sum::<usize>(),
+            100
+        );
+        for (info, chunk) in chunks {
+            assert_eq!(info.num_splats, chunk.num_splats());
+            let chunk_means: Vec<f32> = chunk.means.val().into_data().to_vec().expect("Wrong type");
+            for m in chunk_means.chunks(3).map(Vec3::from_slice) {
+                assert!(m.cmpge(info.min).all() && m.cmple(info.max).all());
+            }
+        }
+    }
+}
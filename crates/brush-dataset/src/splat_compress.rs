@@ -0,0 +1,328 @@
+use anyhow::anyhow;
+use brush_render::{gaussian_splats::Splats, Backend};
+use burn::{config::Config, tensor::Tensor};
+use clap::Args;
+use ply_rs::ply::PropertyAccess;
+
+use crate::splat_export::{read_splat_data, splat_property_names};
+
+#[derive(Config, Debug, Args)]
+pub struct CompressConfig {
+    /// Export quantized ply files instead of full precision ones.
+    #[arg(long, help_heading = "Compression Options", default_value = "false")]
+    #[config(default = false)]
+    pub compress: bool,
+    /// Bits used to store splat positions.
+    #[arg(long, help_heading = "Compression Options", default_value = "16")]
+    #[config(default = 16)]
+    pub means_bits: u32,
+    /// Bits used to store splat scales.
+    #[arg(long, help_heading = "Compression Options", default_value = "8")]
+    #[config(default = 8)]
+    pub scales_bits: u32,
+    /// Bits used to store splat rotations.
+    #[arg(long, help_heading = "Compression Options", default_value = "8")]
+    #[config(default = 8)]
+    pub rotation_bits: u32,
+    /// Bits used to store splat opacities.
+    #[arg(long, help_heading = "Compression Options", default_value = "8")]
+    #[config(default = 8)]
+    pub opacity_bits: u32,
+    /// Bits used to store the base color.
+    #[arg(long, help_heading = "Compression Options", default_value = "8")]
+    #[config(default = 8)]
+    pub sh_dc_bits: u32,
+    /// Bits used to store the higher order SH coefficients.
+    #[arg(long, help_heading = "Compression Options", default_value = "4")]
+    #[config(default = 4)]
+    pub sh_rest_bits: u32,
+    /// Fine-tune the quantized splats for this many steps before the final export, to recover
+    /// some of the quality lost to quantization.
+    #[arg(long, help_heading = "Compression Options", default_value = "0")]
+    #[config(default = 0)]
+    pub finetune_steps: u32,
+}
+
+impl CompressConfig {
+    fn bits_for_property(&self, name: &str) -> u32 {
+        match name {
+            "x" | "y" | "z" => self.means_bits,
+            "opacity" => self.opacity_bits,
+            _ if name.starts_with("scale_") => self.scales_bits,
+            _ if name.starts_with("rot_") => self.rotation_bits,
+            _ if name.starts_with("f_dc_") => self.sh_dc_bits,
+            _ => self.sh_rest_bits,
+        }
+    }
+}
+
+// Snap values to a uniform grid with 2^bits levels, spanning the range of every channel.
+fn quantize_tensor<B: Backend, const D: usize>(tensor: Tensor<B, D>, bits: u32) -> Tensor<B, D> {
+    if bits >= 24 {
+        // Beyond this float precision is the limiting factor anyway.
+        return tensor;
+    }
+
+    let levels = ((1u32 << bits) - 1) as f32;
+    let min = tensor.clone().min_dim(0);
+    let range = (tensor.clone().max_dim(0) - min.clone()).clamp_min(1e-12);
+    let normed = (tensor - min.clone()) / range.clone();
+    (normed * levels).round() / levels * range + min
+}
+
+/// Quantize all splat attributes to the bit depths in the config. The splats keep their
+/// float representation, but only contain values that can be stored exactly.
+///
+/// Parameter ids are preserved, so this can be applied during training to make the model
+/// robust to quantization.
+pub fn quantize_splats<B: Backend>(splats: Splats<B>, config: &CompressConfig) -> Splats<B> {
+    let mut splats = splats;
+    splats.norm_rotations();
+
+    let n_coeffs = splats.sh_coeffs.dims()[1];
+
+    Splats::map_param(&mut splats.means, |m| quantize_tensor(m, config.means_bits));
+    Splats::map_param(&mut splats.log_scales, |s| {
+        quantize_tensor(s, config.scales_bits)
+    });
+    Splats::map_param(&mut splats.rotation, |r| {
+        quantize_tensor(r, config.rotation_bits)
+    });
+    Splats::map_param(&mut splats.raw_opacity, |o| {
+        quantize_tensor(o, config.opacity_bits)
+    });
+    Splats::map_param(&mut splats.sh_coeffs, |c| {
+        let [n, _, _] = c.dims();
+        let dc = quantize_tensor(c.clone().slice([0..n, 0..1]), config.sh_dc_bits);
+
+        if n_coeffs > 1 {
+            let rest = quantize_tensor(c.slice([0..n, 1..n_coeffs]), config.sh_rest_bits);
+            Tensor::cat(vec![dc, rest], 1)
+        } else {
+            dc
+        }
+    });
+
+    splats
+}
+
+enum Storage {
+    // Two values of at most 4 bits, packed in one byte.
+    Nibbles,
+    UChar,
+    UShort,
+    Float,
+}
+
+impl Storage {
+    fn for_bits(bits: u32) -> Self {
+        match bits {
+            0..=8 => Self::UChar,
+            9..=16 => Self::UShort,
+            _ => Self::Float,
+        }
+    }
+
+    fn ply_name(&self) -> &'static str {
+        match self {
+            Self::Nibbles | Self::UChar => "uchar",
+            Self::UShort => "ushort",
+            Self::Float => "float",
+        }
+    }
+
+    // Write values normalized to 0-1. Nibbles take two values, the others one.
+    fn write(&self, values: &[f32], out: &mut Vec<u8>) {
+        match self {
+            Self::Nibbles => {
+                let nibble = |v: f32| (v * 15.0).round() as u8;
+                out.push(nibble(values[0]) | (nibble(values[1]) << 4));
+            }
+            Self::UChar => out.push((values[0] * u8::MAX as f32).round() as u8),
+            Self::UShort => {
+                out.extend(((values[0] * u16::MAX as f32).round() as u16).to_le_bytes());
+            }
+            Self::Float => out.extend(values[0].to_le_bytes()),
+        }
+    }
+}
+
+// A property of the vertex element, holding one or two of the splat properties.
+struct Field {
+    name: String,
+    storage: Storage,
+    columns: Vec<usize>,
+}
+
+// Pick the storage of every property. Higher order SH coefficients of at most 4 bits are
+// packed in pairs, as they make up most of the file.
+fn vertex_fields(names: &[String], config: &CompressConfig) -> Vec<Field> {
+    let mut fields = vec![];
+    let mut i = 0;
+    while i < names.len() {
+        let bits = config.bits_for_property(&names[i]);
+        let packed = bits <= 4
+            && names[i].starts_with("f_rest_")
+            && names.get(i + 1).is_some_and(|n| n.starts_with("f_rest_"));
+        if packed {
+            fields.push(Field {
+                name: format!("packed_{}", names[i]),
+                storage: Storage::Nibbles,
+                columns: vec![i, i + 1],
+            });
+            i += 2;
+        } else {
+            fields.push(Field {
+                name: names[i].clone(),
+                storage: Storage::for_bits(bits),
+                columns: vec![i],
+            });
+            i += 1;
+        }
+    }
+    fields
+}
+
+/// Export splats as a quantized ply file.
+///
+/// Every property is normalized to 0-1 and stored in the smallest integer type that fits the
+/// configured bits. The range of each property is stored in the `meta_quant_min` and
+/// `meta_quant_max` elements, which come before the vertices.
+///
+/// Higher order SH coefficients of at most 4 bits are stored two per byte: the
+/// `packed_f_rest_{i}` property holds `f_rest_{i}` in its low and `f_rest_{i+1}` in its high
+/// 4 bits.
+pub async fn splat_to_compressed_ply<B: Backend>(
+    splats: Splats<B>,
+    config: &CompressConfig,
+) -> anyhow::Result<Vec<u8>> {
+    let splats = quantize_splats(splats, config);
+    let names = splat_property_names(splats.sh_coeffs.dims()[1]);
+
+    let data = read_splat_data(splats)
+        .await
+        .map_err(|e| anyhow!("Failed to read data from splat {e:?}"))?;
+
+    let columns: Vec<Vec<f32>> = names
+        .iter()
+        .map(|name| {
+            data.iter()
+                .map(|d| d.get_float(name).unwrap_or(0.0))
+                .collect()
+        })
+        .collect();
+
+    let ranges: Vec<(f32, f32)> = columns
+        .iter()
+        .map(|col| {
+            col.iter().fold((f32::MAX, f32::MIN), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            })
+        })
+        .map(|(min, max)| if min > max { (0.0, 1.0) } else { (min, max) })
+        .collect();
+    let fields = vertex_fields(&names, config);
+
+    let mut header = String::from("ply\nformat binary_little_endian 1.0\n");
+    header += "comment Exported from Brush\n";
+    header += "comment Vertical axis: y\n";
+    for meta in ["meta_quant_min", "meta_quant_max"] {
+        header += &format!("element {meta} 1\n");
+        for name in &names {
+            header += &format!("property float {name}\n");
+        }
+    }
+    header += &format!("element vertex {}\n", data.len());
+    for field in &fields {
+        header += &format!("property {} {}\n", field.storage.ply_name(), field.name);
+    }
+    header += "end_header\n";
+
+    let mut buf = header.into_bytes();
+    buf.extend(ranges.iter().flat_map(|r| r.0.to_le_bytes()));
+    buf.extend(ranges.iter().flat_map(|r| r.1.to_le_bytes()));
+
+    let normed = |c: usize, i: usize| {
+        let (min, max) = ranges[c];
+        let range = (max - min).max(1e-12);
+        ((columns[c][i] - min) / range).clamp(0.0, 1.0)
+    };
+    for i in 0..data.len() {
+        for field in &fields {
+            let values: Vec<f32> = field.columns.iter().map(|&c| normed(c, i)).collect();
+            field.storage.write(&values, &mut buf);
+        }
+    }
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use brush_render::gaussian_splats::Splats;
+    use burn::backend::{wgpu::WgpuDevice, Wgpu};
+    use glam::{Quat, Vec3};
+    use rand::{Rng, SeedableRng};
+    use tokio_stream::StreamExt;
+
+    use super::{quantize_splats, splat_to_compressed_ply, CompressConfig};
+    use crate::splat_import::load_splat_from_ply;
+
+    #[tokio::test]
+    async fn packed_sh_round_trips() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let n = 64;
+        let means: Vec<Vec3> = (0..n)
+            .map(|_| Vec3::new(rng.gen(), rng.gen(), rng.gen()))
+            .collect();
+        let rotations = vec![Quat::IDENTITY; n];
+        let log_scales = vec![Vec3::splat(-3.0); n];
+        // Degree 3 has 45 higher order coefficients, so the last one isn't packed.
+        let sh_coeffs: Vec<f32> = (0..n * 16 * 3).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let opacities = vec![0.0; n];
+        let splats = Splats::<Wgpu>::from_raw(
+            &means,
+            Some(&rotations),
+            Some(&log_scales),
+            Some(&sh_coeffs),
+            Some(&opacities),
+            &device,
+        );
+
+        let config = CompressConfig::new();
+        let expected: Vec<f32> = quantize_splats(splats.clone(), &config)
+            .sh_coeffs
+            .val()
+            .into_data()
+            .to_vec()
+            .expect("Wrong type");
+
+        let ply = splat_to_compressed_ply(splats, &config)
+            .await
+            .expect("Failed to export");
+        let header = String::from_utf8_lossy(&ply);
+        assert!(header.contains("property uchar packed_f_rest_0\n"));
+        assert!(header.contains("property uchar packed_f_rest_42\n"));
+        assert!(header.contains("property uchar f_rest_44\n"));
+
+        let stream = load_splat_from_ply(std::io::Cursor::new(ply), None, device);
+        let mut stream = std::pin::pin!(stream);
+        let mut last = None;
+        while let Some(message) = stream.next().await {
+            last = Some(message.expect("Failed to import").splats);
+        }
+        let imported: Splats<Wgpu> = last.expect("No splats imported");
+        let imported: Vec<f32> = imported
+            .sh_coeffs
+            .val()
+            .into_data()
+            .to_vec()
+            .expect("Wrong type");
+
+        assert_eq!(imported.len(), expected.len());
+        for (a, b) in imported.iter().zip(&expected) {
+            assert!((a - b).abs() < 1e-4, "{a} != {b}");
+        }
+    }
+}
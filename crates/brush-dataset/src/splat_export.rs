@@ -1,15 +1,21 @@
 use anyhow::anyhow;
-use brush_render::{gaussian_splats::Splats, Backend};
+use brush_render::{
+    gaussian_splats::{Splats, TemporalAttributes},
+    Backend,
+};
 use burn::tensor::DataError;
 use glam::{Quat, Vec3};
 use ply_rs::{
-    ply::{self, Ply, PropertyDef, PropertyType, ScalarType},
+    ply::{self, Ply, PropertyAccess, PropertyDef, PropertyType, ScalarType},
     writer::Writer,
 };
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use crate::splat_import::GaussianData;
 
-async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Result<Vec<GaussianData>, DataError> {
+pub(crate) async fn read_splat_data<B: Backend>(
+    splats: Splats<B>,
+) -> Result<Vec<GaussianData>, DataError> {
     let means = splats.means.val().into_data_async().await.to_vec()?;
     let log_scales = splats.log_scales.val().into_data_async().await.to_vec()?;
     let rotations = splats.rotation.val().into_data_async().await.to_vec()?;
@@ -25,6 +31,35 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Result<Vec<GaussianDa
 
     let sh_coeffs_num = splats.sh_coeffs.dims()[1];
 
+    let labels: Option<Vec<i32>> = match splats.labels.clone() {
+        Some(labels) => Some(labels.into_data_async().await.to_vec()?),
+        None => None,
+    };
+
+    let temporal = match splats.temporal.clone() {
+        Some(temporal) => Some((
+            temporal
+                .velocities
+                .val()
+                .into_data_async()
+                .await
+                .to_vec::<f32>()?,
+            temporal
+                .times
+                .val()
+                .into_data_async()
+                .await
+                .to_vec::<f32>()?,
+            temporal
+                .log_durations
+                .val()
+                .into_data_async()
+                .await
+                .to_vec::<f32>()?,
+        )),
+        None => None,
+    };
+
     let splats = (0..splats.num_splats())
         .map(|i| {
             // Read SH data from [coeffs, channel] format to
@@ -58,6 +93,13 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Result<Vec<GaussianDa
                 ),
                 sh_dc,
                 sh_coeffs_rest,
+                normal: Vec3::ZERO,
+                label: labels.as_ref().map_or(0, |l| l[i].max(0) as u32),
+                velocity: temporal.as_ref().map_or(Vec3::ZERO, |(v, _, _)| {
+                    Vec3::new(v[i * 3], v[i * 3 + 1], v[i * 3 + 2])
+                }),
+                time: temporal.as_ref().map_or(0.0, |(_, t, _)| t[i]),
+                log_duration: temporal.as_ref().map_or(0.0, |(_, _, d)| d[i]),
             }
         })
         .collect();
@@ -65,30 +107,124 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Result<Vec<GaussianDa
     Ok(splats)
 }
 
+/// Names of the ply properties of a splat with `sh_coeffs_num` SH coefficients per channel.
+pub(crate) fn splat_property_names(sh_coeffs_num: usize) -> Vec<String> {
+    let mut names: Vec<String> = [
+        "x", "y", "z", "scale_0", "scale_1", "scale_2", "opacity", "rot_0", "rot_1", "rot_2",
+        "rot_3", "f_dc_0", "f_dc_1", "f_dc_2",
+    ]
+    .into_iter()
+    .map(str::to_owned)
+    .collect();
+
+    let sh_coeffs_rest = (sh_coeffs_num - 1) * 3;
+    names.extend((0..sh_coeffs_rest).map(|i| format!("f_rest_{i}")));
+    names
+}
+
+/// Names of the ply properties of the motion of dynamic splats.
+const TEMPORAL_PROPERTY_NAMES: [&str; 5] = [
+    "velocity_0",
+    "velocity_1",
+    "velocity_2",
+    "time",
+    "log_duration",
+];
+
+/// File formats splats can be exported to from the viewer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Ply,
+    /// Compressed, see [`crate::splat_spz`].
+    Spz,
+    /// Compressed, see [`crate::splat_ksplat`].
+    Ksplat,
+    /// Points with colors for other tools, see [`crate::splat_gltf`].
+    Glb,
+    /// Points with colors for other tools, see [`crate::splat_usdz`].
+    Usdz,
+}
+
+impl ExportFormat {
+    pub const ALL: [Self; 5] = [Self::Ply, Self::Spz, Self::Ksplat, Self::Glb, Self::Usdz];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Ply => "ply",
+            Self::Spz => "spz",
+            Self::Ksplat => "ksplat",
+            Self::Glb => "glb",
+            Self::Usdz => "usdz",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ply => "ply",
+            Self::Spz => "spz (compressed)",
+            Self::Ksplat => "ksplat (compressed)",
+            Self::Glb => "glb (glTF)",
+            Self::Usdz => "usdz",
+        }
+    }
+
+    pub async fn export<B: Backend>(self, splats: Splats<B>) -> anyhow::Result<Vec<u8>> {
+        let splats = splats.with_full_precision();
+        match self {
+            Self::Ply => splat_to_ply(splats).await,
+            Self::Spz => crate::splat_spz::splat_to_spz(splats).await,
+            Self::Ksplat => crate::splat_ksplat::splat_to_ksplat(splats).await,
+            Self::Glb => crate::splat_gltf::splat_to_glb(splats, None).await,
+            Self::Usdz => crate::splat_usdz::splat_to_usdz(splats, None).await,
+        }
+    }
+}
+
 pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> anyhow::Result<Vec<u8>> {
+    splat_to_ply_with_normals(splats, None).await
+}
+
+/// Export splats to a ply file, including a normal per splat (see
+/// [`crate::splat_normals::estimate_normals`]) as the `nx`, `ny` and `nz` properties.
+pub async fn splat_to_ply_with_normals<B: Backend>(
+    splats: Splats<B>,
+    normals: Option<&[Vec3]>,
+) -> anyhow::Result<Vec<u8>> {
     let mut splats = splats;
     splats.norm_rotations();
 
-    let data = read_splat_data(splats.clone())
+    let mut data = read_splat_data(splats.clone())
         .await
         .map_err(|e| anyhow!("Failed to read data from splat {e:?}"))?;
 
-    let property_names = vec![
-        "x", "y", "z", "scale_0", "scale_1", "scale_2", "opacity", "rot_0", "rot_1", "rot_2",
-        "rot_3", "f_dc_0", "f_dc_1", "f_dc_2",
-    ];
+    let mut property_names = splat_property_names(splats.sh_coeffs.dims()[1]);
+
+    if let Some(normals) = normals {
+        anyhow::ensure!(
+            normals.len() == data.len(),
+            "Expected {} normals, got {}",
+            data.len(),
+            normals.len()
+        );
+        for (splat, &normal) in data.iter_mut().zip(normals) {
+            splat.normal = normal;
+        }
+        property_names.extend(["nx", "ny", "nz"].map(str::to_owned));
+    }
+
+    if splats.temporal.is_some() {
+        property_names.extend(TEMPORAL_PROPERTY_NAMES.map(str::to_owned));
+    }
 
     let mut properties: Vec<PropertyDef> = property_names
-        .into_iter()
+        .iter()
         .map(|name| PropertyDef::new(name, PropertyType::Scalar(ScalarType::Float)))
         .collect();
 
-    let sh_coeffs_rest = (splats.sh_coeffs.dims()[1] - 1) * 3;
-
-    for i in 0..sh_coeffs_rest {
+    if splats.labels.is_some() {
         properties.push(PropertyDef::new(
-            &format!("f_rest_{i}"),
-            PropertyType::Scalar(ScalarType::Float),
+            "label",
+            PropertyType::Scalar(ScalarType::UInt),
         ));
     }
 
@@ -108,3 +244,201 @@ pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> anyhow::Result<Vec<u
     writer.write_ply(&mut buf, &mut ply)?;
     Ok(buf)
 }
+
+/// Write splats as a binary ply file, in chunks of `chunk_size` splats.
+///
+/// Only one chunk is read back from the GPU at a time, so this needs a lot less memory than
+/// [`splat_to_ply`] for big models. The output is the same.
+pub async fn write_ply_chunked<B: Backend>(
+    splats: Splats<B>,
+    writer: &mut (impl AsyncWrite + Unpin),
+    chunk_size: usize,
+) -> anyhow::Result<()> {
+    let mut splats = splats;
+    splats.norm_rotations();
+
+    let n = splats.num_splats();
+    let mut property_names = splat_property_names(splats.sh_coeffs.dims()[1]);
+    if splats.temporal.is_some() {
+        property_names.extend(TEMPORAL_PROPERTY_NAMES.map(str::to_owned));
+    }
+    let has_labels = splats.labels.is_some();
+
+    let mut header = String::from("ply\nformat binary_little_endian 1.0\n");
+    header.push_str("comment Exported from Brush\ncomment Vertical axis: y\n");
+    header.push_str(&format!("element vertex {n}\n"));
+    for name in &property_names {
+        header.push_str(&format!("property float {name}\n"));
+    }
+    if has_labels {
+        header.push_str("property uint label\n");
+    }
+    header.push_str("end_header\n");
+    writer.write_all(header.as_bytes()).await?;
+
+    for start in (0..n).step_by(chunk_size.max(1)) {
+        let end = (start + chunk_size.max(1)).min(n);
+        let mut chunk = Splats::from_tensor_data(
+            splats.means.val().slice([start..end]),
+            splats.rotation.val().slice([start..end]),
+            splats.log_scales.val().slice([start..end]),
+            splats.sh_coeffs.val().slice([start..end]),
+            splats.raw_opacity.val().slice([start..end]),
+        );
+        chunk.labels = splats.labels.clone().map(|l| l.slice([start..end]));
+        chunk.temporal = splats.temporal.as_ref().map(|t| {
+            TemporalAttributes::new(
+                t.velocities.val().slice([start..end]),
+                t.times.val().slice([start..end]),
+                t.log_durations.val().slice([start..end]),
+            )
+        });
+
+        let data = read_splat_data(chunk)
+            .await
+            .map_err(|e| anyhow!("Failed to read data from splat {e:?}"))?;
+
+        let mut bytes = Vec::with_capacity(data.len() * (property_names.len() + 1) * 4);
+        for splat in &data {
+            for name in &property_names {
+                bytes.extend(splat.get_float(name).unwrap_or(0.0).to_le_bytes());
+            }
+            if has_labels {
+                bytes.extend(splat.label.to_le_bytes());
+            }
+        }
+        writer.write_all(&bytes).await?;
+    }
+
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use brush_render::{
+        bounding_box::BoundingBox,
+        camera::Camera,
+        gaussian_splats::{RandomSplatsConfig, Splats, TemporalAttributes},
+    };
+    use brush_train::{
+        scene::{SceneView, ViewImageType},
+        train::{SceneBatch, SplatTrainer, TrainConfig},
+    };
+    use burn::{
+        backend::{wgpu::WgpuDevice, Autodiff, Wgpu},
+        tensor::{Int, Tensor},
+    };
+    use glam::Vec3;
+    use rand::SeedableRng;
+    use tokio_stream::StreamExt;
+
+    use super::{splat_to_ply, write_ply_chunked};
+    use crate::splat_import::load_splat_from_ply;
+
+    type DiffBack = Autodiff<Wgpu>;
+
+    #[tokio::test]
+    async fn densified_attributes_export() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let bounds = BoundingBox::from_min_max(Vec3::splat(-1.0), Vec3::splat(1.0));
+        let splats = Splats::<DiffBack>::from_random_config(
+            &RandomSplatsConfig::new(),
+            bounds,
+            &mut rng,
+            &device,
+        );
+        let labels = Tensor::<DiffBack, 1, Int>::full([splats.num_splats()], 7, &device);
+        let temporal = TemporalAttributes::stationary(splats.num_splats(), &device);
+        let splats = splats.with_labels(labels).with_temporal(temporal);
+
+        let batch = SceneBatch {
+            gt_image: Tensor::zeros([16, 16, 3], &device),
+            gt_depth: None,
+            gt_view: SceneView {
+                path: "test".to_owned(),
+                camera: Camera::new(
+                    glam::vec3(0.0, 0.0, -3.0),
+                    glam::Quat::IDENTITY,
+                    0.8,
+                    0.8,
+                    glam::vec2(0.5, 0.5),
+                ),
+                image: Arc::new(image::DynamicImage::new_rgb8(16, 16)),
+                img_type: ViewImageType::Alpha,
+                original_size: (16, 16),
+                depth: None,
+            },
+            view_index: 0,
+            scene_extent: 1.0,
+        };
+
+        // Densify every splat that was seen.
+        let config = TrainConfig::new()
+            .with_densify_grad_thresh(0.0)
+            .with_densify_radius_threshold(0.0);
+        let mut trainer = SplatTrainer::new(&splats, &config, &device);
+        let (splats, _) = trainer.step(0, batch, splats);
+        let (splats, stats) = trainer.refine(1, splats, 1.0).await;
+        assert!(
+            stats.num_split + stats.num_cloned > 0,
+            "Nothing was densified"
+        );
+
+        let labels = splats.labels.clone().expect("Labels were dropped");
+        assert_eq!(labels.dims()[0], splats.num_splats());
+        let temporal = splats
+            .temporal
+            .as_ref()
+            .expect("Temporal attributes were dropped");
+        assert_eq!(temporal.velocities.dims()[0], splats.num_splats());
+        assert_eq!(temporal.times.dims()[0], splats.num_splats());
+        assert_eq!(temporal.log_durations.dims()[0], splats.num_splats());
+
+        let ply = splat_to_ply(splats).await.expect("Failed to export");
+        let stream = load_splat_from_ply(std::io::Cursor::new(ply), None, device);
+        let mut stream = std::pin::pin!(stream);
+        let mut last = None;
+        while let Some(message) = stream.next().await {
+            last = Some(message.expect("Failed to import").splats);
+        }
+        let imported: Splats<Wgpu> = last.expect("No splats imported");
+        let labels: Vec<i32> = imported
+            .labels
+            .expect("Labels weren't exported")
+            .into_data()
+            .to_vec()
+            .expect("Wrong type");
+        // New splats are labelled like the splats they're densified from.
+        assert!(labels.iter().all(|&l| l == 7));
+    }
+
+    #[tokio::test]
+    async fn chunked_export_matches_ply() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let bounds = BoundingBox::from_min_max(Vec3::splat(-1.0), Vec3::splat(1.0));
+        let splats = Splats::<Wgpu>::from_random_config(
+            &RandomSplatsConfig::new().with_init_count(100),
+            bounds,
+            &mut rng,
+            &device,
+        );
+        let labels = Tensor::<Wgpu, 1, Int>::arange(0..splats.num_splats() as i64, &device);
+        let temporal = TemporalAttributes::stationary(splats.num_splats(), &device);
+        let splats = splats.with_labels(labels).with_temporal(temporal);
+
+        let ply = splat_to_ply(splats.clone())
+            .await
+            .expect("Failed to export");
+        // Chunks that don't divide the splats evenly.
+        let mut chunked = vec![];
+        write_ply_chunked(splats, &mut chunked, 7)
+            .await
+            .expect("Failed to export");
+        assert!(ply == chunked, "Chunked export differs from the ply export");
+    }
+}
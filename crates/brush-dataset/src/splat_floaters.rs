@@ -0,0 +1,214 @@
+use anyhow::anyhow;
+use ball_tree::BallTree;
+use brush_render::{camera::Camera, gaussian_splats::Splats, Backend};
+use burn::{
+    config::Config,
+    tensor::{Bool, Tensor, TensorData},
+};
+use clap::Args;
+use tokio_with_wasm::alias as tokio_wasm;
+
+#[derive(Config, Debug, Args)]
+pub struct FloaterConfig {
+    /// Remove floaters from the splats before exporting.
+    #[arg(long, help_heading = "Floater Options", default_value = "false")]
+    #[config(default = false)]
+    pub remove_floaters: bool,
+    /// Number of nearest neighbours used to measure how isolated a splat is.
+    #[arg(long, help_heading = "Floater Options", default_value = "8")]
+    #[config(default = 8)]
+    pub floater_neighbours: usize,
+    /// A splat is isolated when the distance to its neighbours is this many standard
+    /// deviations above the average.
+    #[arg(long, help_heading = "Floater Options", default_value = "3.0")]
+    #[config(default = 3.0)]
+    pub floater_isolation: f32,
+    /// Splats below this opacity are floaters when they're further from their neighbours
+    /// than average.
+    #[arg(long, help_heading = "Floater Options", default_value = "0.05")]
+    #[config(default = 0.05)]
+    pub floater_min_opacity: f32,
+    /// Splats seen by fewer training views than this are floaters.
+    #[arg(long, help_heading = "Floater Options", default_value = "2")]
+    #[config(default = 2)]
+    pub floater_min_views: usize,
+    /// A view only sees a splat when the splat covers at least this many pixels of a render of
+    /// the view, weighted by its alpha and the transmittance in front of it. Views are rendered
+    /// at 256 pixels along their longest side.
+    #[arg(long, help_heading = "Floater Options", default_value = "0.1")]
+    #[config(default = 0.1)]
+    pub floater_min_contribution: f32,
+}
+
+// Size of the longest side of the renders to find which views see a splat.
+const VIEW_RENDER_SIZE: f32 = 256.0;
+
+// Render size of a camera, with the aspect ratio of its field of view.
+fn view_render_size(camera: &Camera) -> glam::UVec2 {
+    let aspect = ((camera.fov_x / 2.0).tan() / (camera.fov_y / 2.0).tan()) as f32;
+    let size = if aspect >= 1.0 {
+        glam::vec2(VIEW_RENDER_SIZE, VIEW_RENDER_SIZE / aspect)
+    } else {
+        glam::vec2(VIEW_RENDER_SIZE * aspect, VIEW_RENDER_SIZE)
+    };
+    size.round().as_uvec2().max(glam::UVec2::ONE)
+}
+
+/// Find the splats that are likely floaters. Returns a mask that is true for every floater.
+///
+/// A splat is a floater when it is:
+/// - seen by fewer than `floater_min_views` of the cameras, where a camera sees a splat when
+///   the splat visibly contributes to a render from the camera, see [`Splats::contributions`].
+///   Splats that are always hidden behind other splats aren't seen, or
+/// - isolated, ie. the distance to its nearest neighbours is a statistical outlier, or
+/// - nearly transparent, and further from its neighbours than average.
+///
+/// When there are no cameras, eg. for a loaded ply file, the views aren't taken into account.
+pub async fn detect_floaters<B: Backend>(
+    splats: &Splats<B>,
+    cameras: &[Camera],
+    config: &FloaterConfig,
+) -> anyhow::Result<Tensor<B, 1, Bool>> {
+    let read_err = |e| anyhow!("Failed to read data from splat {e:?}");
+    let device = splats.means.val().device();
+    let n = splats.num_splats();
+
+    let means: Vec<f32> = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let opacities: Vec<f32> = splats
+        .opacity()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+
+    let tree_pos: Vec<[f64; 3]> = means
+        .chunks(3)
+        .map(|m| [m[0] as f64, m[1] as f64, m[2] as f64])
+        .collect();
+    let indices: Vec<usize> = (0..n).collect();
+    let tree = BallTree::new(tree_pos.clone(), indices);
+
+    // Average distance to the nearest neighbours of every splat.
+    let mut knn_dist = Vec::with_capacity(n);
+    for (i, pos) in tree_pos.iter().enumerate() {
+        if i % 10000 == 0 {
+            tokio_wasm::task::yield_now().await;
+        }
+        let (sum, count) = tree
+            .query()
+            .nn(pos)
+            .take(config.floater_neighbours + 1)
+            .filter(|(_, _, j)| **j != i)
+            .fold((0.0, 0), |(sum, count), (_, dist, _)| {
+                (sum + dist, count + 1)
+            });
+        knn_dist.push(if count > 0 { sum / count as f64 } else { 0.0 });
+    }
+
+    let mean_dist = knn_dist.iter().sum::<f64>() / n.max(1) as f64;
+    let std_dist = (knn_dist
+        .iter()
+        .map(|d| (d - mean_dist).powi(2))
+        .sum::<f64>()
+        / n.max(1) as f64)
+        .sqrt();
+    let isolated_dist = mean_dist + config.floater_isolation as f64 * std_dist;
+
+    // Count the views every splat visibly contributes to.
+    let min_views = config.floater_min_views.min(cameras.len());
+    let mut views = Tensor::<B, 1>::zeros([n], &device);
+    if min_views > 0 {
+        for camera in cameras {
+            tokio_wasm::task::yield_now().await;
+            let seen = splats
+                .contributions(camera, view_render_size(camera))
+                .greater_equal_elem(config.floater_min_contribution);
+            views = views + seen.float();
+        }
+    }
+    let views: Vec<f32> = views.into_data_async().await.to_vec().map_err(read_err)?;
+
+    let floaters: Vec<bool> = (0..n)
+        .map(|i| {
+            (views[i] as usize) < min_views
+                || knn_dist[i] > isolated_dist
+                || (opacities[i] < config.floater_min_opacity && knn_dist[i] > mean_dist)
+        })
+        .collect();
+
+    Ok(Tensor::from_data(TensorData::new(floaters, [n]), &device))
+}
+
+/// Remove all splats found by [`detect_floaters`].
+pub async fn remove_floaters<B: Backend>(
+    splats: Splats<B>,
+    cameras: &[Camera],
+    config: &FloaterConfig,
+) -> anyhow::Result<Splats<B>> {
+    let floaters = detect_floaters(&splats, cameras, config).await?;
+    Ok(splats.retained(floaters.bool_not()).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::backend::{wgpu::WgpuDevice, Wgpu};
+    use glam::{Quat, Vec2, Vec3};
+
+    #[test]
+    fn view_render_size_keeps_aspect() {
+        let camera = |fov_x: f64, fov_y: f64| {
+            Camera::new(Vec3::ZERO, Quat::IDENTITY, fov_x, fov_y, Vec2::splat(0.5))
+        };
+        assert_eq!(view_render_size(&camera(1.0, 1.0)), glam::uvec2(256, 256));
+        let wide = view_render_size(&camera(1.2, 0.6));
+        assert_eq!(wide.x, 256);
+        assert!(wide.y < 128);
+        assert_eq!(view_render_size(&camera(0.6, 1.2)).y, 256);
+    }
+
+    #[tokio::test]
+    async fn hidden_splats_are_floaters() {
+        let device = WgpuDevice::DefaultDevice;
+        // An opaque wall of splats, with a smaller wall hidden right behind it.
+        let grid = |size: i32, z: f32| {
+            (0..size * size).map(move |i| {
+                let (x, y) = ((i % size - size / 2) as f32, (i / size - size / 2) as f32);
+                Vec3::new(x * 0.05, y * 0.05, z)
+            })
+        };
+        let means: Vec<Vec3> = grid(20, 5.0).chain(grid(6, 5.2)).collect();
+        let n = means.len();
+        let log_scales = vec![Vec3::splat(0.05f32.ln()); n];
+        let raw_opacities = vec![8.0; n];
+        let splats = Splats::<Wgpu>::from_raw(
+            &means,
+            None,
+            Some(&log_scales),
+            None,
+            Some(&raw_opacities),
+            &device,
+        );
+
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 0.5, 0.5, Vec2::splat(0.5));
+        let config = FloaterConfig::new()
+            .with_floater_isolation(100.0)
+            .with_floater_min_opacity(0.0)
+            .with_floater_min_views(1);
+        let floaters: Vec<bool> = detect_floaters(&splats, &[camera], &config)
+            .await
+            .expect("Failed to detect floaters")
+            .into_data()
+            .to_vec()
+            .expect("Wrong type");
+
+        assert!(floaters[..400].iter().all(|f| !f), "Visible splats removed");
+        assert!(floaters[400..].iter().all(|f| *f), "Hidden splats kept");
+    }
+}
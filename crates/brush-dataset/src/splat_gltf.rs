@@ -0,0 +1,375 @@
+use anyhow::anyhow;
+use brush_render::{gaussian_splats::Splats, render::SH_C0, Backend};
+use serde_json::{json, Value};
+
+use crate::splat_mesh::Mesh;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+const GL_UNSIGNED_INT: u32 = 5125;
+const GL_FLOAT: u32 = 5126;
+const GL_ARRAY_BUFFER: u32 = 34962;
+const GL_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+const MODE_POINTS: u32 = 0;
+const MODE_TRIANGLES: u32 = 4;
+
+const EXTENSION: &str = "KHR_gaussian_splatting";
+
+#[derive(Default)]
+struct GltfBuffer {
+    bin: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl GltfBuffer {
+    // Add a float accessor with its own buffer view, returning the accessor index.
+    fn add_accessor(
+        &mut self,
+        data: &[f32],
+        accessor_type: &str,
+        components: usize,
+        bounds: bool,
+    ) -> usize {
+        let count = data.len() / components;
+
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": data.len() * 4,
+            "target": GL_ARRAY_BUFFER,
+        }));
+        self.bin.extend(data.iter().flat_map(|v| v.to_le_bytes()));
+
+        let mut accessor = json!({
+            "bufferView": self.views.len() - 1,
+            "componentType": GL_FLOAT,
+            "count": count,
+            "type": accessor_type,
+        });
+
+        if bounds {
+            let mut min = vec![f32::MAX; components];
+            let mut max = vec![f32::MIN; components];
+            for chunk in data.chunks(components) {
+                for (i, &v) in chunk.iter().enumerate() {
+                    min[i] = min[i].min(v);
+                    max[i] = max[i].max(v);
+                }
+            }
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    // Add an index accessor for triangles, returning the accessor index.
+    fn add_indices(&mut self, indices: &[u32]) -> usize {
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": indices.len() * 4,
+            "target": GL_ELEMENT_ARRAY_BUFFER,
+        }));
+        self.bin
+            .extend(indices.iter().flat_map(|v| v.to_le_bytes()));
+
+        self.accessors.push(json!({
+            "bufferView": self.views.len() - 1,
+            "componentType": GL_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+// A triangle primitive of the mesh, with vertex colors.
+fn mesh_primitive(buffer: &mut GltfBuffer, mesh: &Mesh) -> Value {
+    let positions: Vec<f32> = mesh.positions.iter().flat_map(|p| p.to_array()).collect();
+    let colors: Vec<f32> = mesh.colors.iter().flat_map(|c| c.to_array()).collect();
+
+    json!({
+        "mode": MODE_TRIANGLES,
+        "attributes": {
+            "POSITION": buffer.add_accessor(&positions, "VEC3", 3, true),
+            "COLOR_0": buffer.add_accessor(&colors, "VEC3", 3, false),
+        },
+        "indices": buffer.add_indices(&mesh.indices),
+    })
+}
+
+fn pad_to_4(buf: &mut Vec<u8>, pad: u8) {
+    while buf.len() % 4 != 0 {
+        buf.push(pad);
+    }
+}
+
+/// Export splats as a binary glTF (.glb) file.
+///
+/// The splats are stored as a point primitive using the `KHR_gaussian_splatting` extension.
+/// The extension is optional, so importers that don't support it load the splats as a colored
+/// point cloud instead. When a mesh is given (see [`crate::splat_mesh::extract_mesh`]) it's
+/// included as a second node, for tools that only import meshes.
+pub async fn splat_to_glb<B: Backend>(
+    splats: Splats<B>,
+    mesh: Option<&Mesh>,
+) -> anyhow::Result<Vec<u8>> {
+    let read_err = |e| anyhow!("Failed to read data from splat {e:?}");
+    let n = splats.num_splats();
+    let n_coeffs = splats.sh_coeffs.dims()[1];
+
+    let means: Vec<f32> = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let scales: Vec<f32> = splats
+        .scales()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let rotations_wxyz: Vec<f32> = splats
+        .rotations_normed()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let opacities: Vec<f32> = splats
+        .opacity()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let sh_coeffs: Vec<f32> = splats
+        .sh_coeffs
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+
+    // glTF quaternions are stored as xyzw.
+    let rotations: Vec<f32> = rotations_wxyz
+        .chunks(4)
+        .flat_map(|q| [q[1], q[2], q[3], q[0]])
+        .collect();
+
+    // Base color with opacity, for the point cloud fallback.
+    let colors: Vec<f32> = (0..n)
+        .flat_map(|i| {
+            let dc = &sh_coeffs[i * n_coeffs * 3..i * n_coeffs * 3 + 3];
+            let rgb = dc.iter().map(|c| (c * SH_C0 + 0.5).clamp(0.0, 1.0));
+            rgb.chain([opacities[i]]).collect::<Vec<_>>()
+        })
+        .collect();
+
+    let mut buffer = GltfBuffer::default();
+    let mut attributes = serde_json::Map::new();
+    attributes.insert(
+        "POSITION".into(),
+        buffer.add_accessor(&means, "VEC3", 3, true).into(),
+    );
+    attributes.insert(
+        "COLOR_0".into(),
+        buffer.add_accessor(&colors, "VEC4", 4, false).into(),
+    );
+    attributes.insert(
+        format!("{EXTENSION}:SCALE"),
+        buffer.add_accessor(&scales, "VEC3", 3, false).into(),
+    );
+    attributes.insert(
+        format!("{EXTENSION}:ROTATION"),
+        buffer.add_accessor(&rotations, "VEC4", 4, false).into(),
+    );
+    attributes.insert(
+        format!("{EXTENSION}:OPACITY"),
+        buffer.add_accessor(&opacities, "SCALAR", 1, false).into(),
+    );
+
+    let degree = brush_render::render::sh_degree_from_coeffs(n_coeffs as u32) as usize;
+    for l in 0..=degree {
+        for m in 0..2 * l + 1 {
+            let coeff = l * l + m;
+            let data: Vec<f32> = (0..n)
+                .flat_map(|i| {
+                    let start = (i * n_coeffs + coeff) * 3;
+                    sh_coeffs[start..start + 3].to_vec()
+                })
+                .collect();
+            attributes.insert(
+                format!("{EXTENSION}:SH_DEGREE_{l}_COEF_{m}"),
+                buffer.add_accessor(&data, "VEC3", 3, false).into(),
+            );
+        }
+    }
+
+    let mut meshes = vec![json!({
+        "name": "Splats",
+        "primitives": [{
+            "mode": MODE_POINTS,
+            "attributes": attributes,
+            "extensions": { "KHR_gaussian_splatting": {} },
+        }],
+    })];
+    if let Some(mesh) = mesh {
+        meshes.push(json!({
+            "name": "Mesh",
+            "primitives": [mesh_primitive(&mut buffer, mesh)],
+        }));
+    }
+
+    pad_to_4(&mut buffer.bin, 0);
+
+    // Splats use a y-down convention while glTF is y-up, so flip the models around x.
+    let nodes: Vec<Value> = (0..meshes.len())
+        .map(|i| json!({ "mesh": i, "rotation": [1.0, 0.0, 0.0, 0.0] }))
+        .collect();
+
+    let gltf = json!({
+        "asset": { "version": "2.0", "generator": "Brush" },
+        "extensionsUsed": [EXTENSION],
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "buffers": [{ "byteLength": buffer.bin.len() }],
+        "bufferViews": buffer.views,
+        "accessors": buffer.accessors,
+    });
+
+    let mut json_chunk = serde_json::to_vec(&gltf)?;
+    pad_to_4(&mut json_chunk, b' ');
+
+    let total_len = 12 + 8 + json_chunk.len() + 8 + buffer.bin.len();
+    let mut glb = Vec::with_capacity(total_len);
+    glb.extend(GLB_MAGIC.to_le_bytes());
+    glb.extend(2u32.to_le_bytes());
+    glb.extend((total_len as u32).to_le_bytes());
+    glb.extend((json_chunk.len() as u32).to_le_bytes());
+    glb.extend(CHUNK_JSON.to_le_bytes());
+    glb.extend(json_chunk);
+    glb.extend((buffer.bin.len() as u32).to_le_bytes());
+    glb.extend(CHUNK_BIN.to_le_bytes());
+    glb.extend(buffer.bin);
+
+    Ok(glb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::backend::{wgpu::WgpuDevice, Wgpu};
+    use glam::{Quat, Vec3};
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().expect("Truncated glb"))
+    }
+
+    // Split a glb into its json and binary chunks.
+    fn parse_glb(glb: &[u8]) -> (Value, &[u8]) {
+        assert_eq!(read_u32(glb, 0), GLB_MAGIC);
+        assert_eq!(read_u32(glb, 4), 2);
+        assert_eq!(read_u32(glb, 8) as usize, glb.len());
+
+        let json_len = read_u32(glb, 12) as usize;
+        assert_eq!(read_u32(glb, 16), CHUNK_JSON);
+        let json = serde_json::from_slice(&glb[20..20 + json_len]).expect("Invalid json");
+
+        let bin_start = 20 + json_len;
+        let bin_len = read_u32(glb, bin_start) as usize;
+        assert_eq!(read_u32(glb, bin_start + 4), CHUNK_BIN);
+        (json, &glb[bin_start + 8..bin_start + 8 + bin_len])
+    }
+
+    fn read_accessor(gltf: &Value, bin: &[u8], accessor: &Value) -> Vec<f32> {
+        let accessor = &gltf["accessors"][accessor.as_u64().expect("Invalid index") as usize];
+        assert_eq!(accessor["componentType"], GL_FLOAT);
+        let view = &gltf["bufferViews"][accessor["bufferView"].as_u64().expect("No view") as usize];
+        let offset = view["byteOffset"].as_u64().expect("No offset") as usize;
+        let len = view["byteLength"].as_u64().expect("No length") as usize;
+        bin[offset..offset + len]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().expect("Truncated float")))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn glb_round_trips() {
+        let device = WgpuDevice::DefaultDevice;
+        let means = [Vec3::new(0.0, 1.0, 2.0), Vec3::new(-1.0, 0.5, 3.0)];
+        let rotations = [Quat::IDENTITY, Quat::from_rotation_y(0.5)];
+        let log_scales = [Vec3::new(-1.0, -2.0, -3.0), Vec3::splat(-2.0)];
+        // Degree 1, so 4 coefficients of 3 channels per splat.
+        let sh_coeffs: Vec<f32> = (0..24).map(|i| i as f32 * 0.1).collect();
+        let raw_opacities = [0.0, 2.0];
+        let splats = Splats::<Wgpu>::from_raw(
+            &means,
+            Some(&rotations),
+            Some(&log_scales),
+            Some(&sh_coeffs),
+            Some(&raw_opacities),
+            &device,
+        );
+
+        let glb = splat_to_glb(splats, None).await.expect("Failed to export");
+        let (gltf, bin) = parse_glb(&glb);
+        assert_eq!(gltf["extensionsUsed"][0], EXTENSION);
+        assert_eq!(bin.len() as u64, gltf["buffers"][0]["byteLength"]);
+
+        let primitive = &gltf["meshes"][0]["primitives"][0];
+        assert_eq!(primitive["mode"], MODE_POINTS);
+        assert!(primitive["extensions"][EXTENSION].is_object());
+        let attributes = &primitive["attributes"];
+        let attribute = |name: &str| read_accessor(&gltf, bin, &attributes[name]);
+
+        let positions = attribute("POSITION");
+        assert_eq!(positions, [0.0, 1.0, 2.0, -1.0, 0.5, 3.0]);
+
+        let scales = attribute(&format!("{EXTENSION}:SCALE"));
+        for (scale, log_scale) in scales
+            .iter()
+            .zip(log_scales.iter().flat_map(|s| s.to_array()))
+        {
+            assert!((scale - log_scale.exp()).abs() < 1e-5);
+        }
+
+        let rots = attribute(&format!("{EXTENSION}:ROTATION"));
+        for (rot, expected) in rots.chunks(4).zip(rotations) {
+            assert!(Quat::from_slice(rot).abs_diff_eq(expected, 1e-5));
+        }
+
+        let opacities = attribute(&format!("{EXTENSION}:OPACITY"));
+        assert!((opacities[0] - 0.5).abs() < 1e-5);
+        assert!((opacities[1] - 1.0 / (1.0 + (-2.0f32).exp())).abs() < 1e-5);
+
+        // Coefficient c of splat i is at sh_coeffs[(i * 4 + c) * 3..].
+        for (name, coeff) in [
+            ("SH_DEGREE_0_COEF_0", 0),
+            ("SH_DEGREE_1_COEF_0", 1),
+            ("SH_DEGREE_1_COEF_2", 3),
+        ] {
+            let data = attribute(&format!("{EXTENSION}:{name}"));
+            for i in 0..2 {
+                let start = (i * 4 + coeff) * 3;
+                for (a, b) in data[i * 3..i * 3 + 3]
+                    .iter()
+                    .zip(&sh_coeffs[start..start + 3])
+                {
+                    assert!((a - b).abs() < 1e-6, "{name}: {a} != {b}");
+                }
+            }
+        }
+        assert!(attributes
+            .get(format!("{EXTENSION}:SH_DEGREE_2_COEF_0"))
+            .is_none());
+    }
+}
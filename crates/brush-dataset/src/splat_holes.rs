@@ -0,0 +1,161 @@
+use anyhow::anyhow;
+use brush_render::{gaussian_splats::Splats, render::rgb_to_sh, Backend};
+use brush_train::scene::Scene;
+use burn::config::Config;
+use clap::Args;
+use glam::Vec3;
+use image::GenericImageView;
+use rand::{seq::SliceRandom, SeedableRng};
+use tokio_with_wasm::alias as tokio_wasm;
+
+use crate::clamp_img_to_max_size;
+
+#[derive(Config, Debug, Args)]
+pub struct HoleFillConfig {
+    /// Seed new splats in under-reconstructed regions at the end of training, and briefly
+    /// train them.
+    #[arg(long, help_heading = "Hole Filling Options", default_value = "false")]
+    #[config(default = false)]
+    pub fill_holes: bool,
+    /// Pixels where the rendered alpha is below this show the background, and are holes.
+    #[arg(long, help_heading = "Hole Filling Options", default_value = "0.5")]
+    #[config(default = 0.5)]
+    pub hole_max_alpha: f32,
+    /// Holes are searched in renders downscaled by this factor. Every pixel of the downscaled
+    /// render seeds at most one splat.
+    #[arg(long, help_heading = "Hole Filling Options", default_value = "8")]
+    #[config(default = 8)]
+    pub hole_pixel_stride: u32,
+    /// Max nr. of new splats.
+    #[arg(long, help_heading = "Hole Filling Options", default_value = "100000")]
+    #[config(default = 100000)]
+    pub hole_max_seeds: usize,
+    /// Nr. of steps to train after seeding.
+    #[arg(long, help_heading = "Hole Filling Options", default_value = "500")]
+    #[config(default = 500)]
+    pub hole_finetune_steps: u32,
+}
+
+struct Seed {
+    position: Vec3,
+    color: Vec3,
+    size: f32,
+}
+
+/// Seed new splats in regions of the scene that are under-reconstructed.
+///
+/// Every training view is rendered, and pixels where the background shows through while the
+/// training image isn't transparent are holes. Holes are filled with splats at the median depth
+/// of the rest of the view, colored like the training image. The new splats only roughly cover
+/// the holes, and need some training to fit the scene.
+///
+/// Returns the splats with the new splats added, and the nr. of new splats.
+pub async fn seed_holes<B: Backend>(
+    splats: &Splats<B>,
+    scene: &Scene,
+    config: &HoleFillConfig,
+) -> anyhow::Result<(Splats<B>, usize)> {
+    let read_err = |e| anyhow!("Failed to read data from splat {e:?}");
+    let device = splats.means.val().device();
+    let stride = config.hole_pixel_stride.max(1);
+
+    let mut seeds = vec![];
+
+    for view in scene.views.iter() {
+        tokio_wasm::task::yield_now().await;
+
+        let camera = &view.camera;
+        let img_size = glam::uvec2(
+            (view.image.width() / stride).max(1),
+            (view.image.height() / stride).max(1),
+        );
+        let (w, h) = (img_size.x as usize, img_size.y as usize);
+        let image = clamp_img_to_max_size(view.image.clone(), img_size.x.max(img_size.y));
+
+        let (color_img, aux) = splats.render_with_depth(camera, img_size);
+        let depth_img = aux.depth.ok_or_else(|| anyhow!("Depth wasn't rendered"))?;
+
+        let alpha: Vec<f32> = color_img
+            .slice([0..h, 0..w, 3..4])
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let depth: Vec<f32> = depth_img
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+
+        // Holes have no depth of their own, so place them at the typical depth of the view.
+        let mut covered: Vec<f32> = depth
+            .iter()
+            .zip(&alpha)
+            .filter(|(_, &a)| a > 0.9)
+            .map(|(&d, _)| d)
+            .collect();
+        if covered.is_empty() {
+            continue;
+        }
+        covered.sort_by(|a, b| a.total_cmp(b));
+        let hole_depth = covered[covered.len() / 2];
+        if hole_depth <= 0.0 {
+            continue;
+        }
+
+        let focal = camera.focal(img_size);
+        let center = camera.center(img_size);
+        let size = hole_depth / focal.x.max(focal.y);
+
+        for y in 0..h {
+            for x in 0..w {
+                if alpha[y * w + x] >= config.hole_max_alpha {
+                    continue;
+                }
+                let pixel = image.get_pixel(
+                    (x as u32).min(image.width() - 1),
+                    (y as u32).min(image.height() - 1),
+                );
+                // Transparent parts of the training image should show the background.
+                if pixel[3] < 128 {
+                    continue;
+                }
+                let uv = (glam::vec2(x as f32 + 0.5, y as f32 + 0.5) - center) / focal;
+                let dir = camera.model.undistort(uv).extend(1.0);
+                seeds.push(Seed {
+                    position: camera.position + camera.rotation * (dir * hole_depth),
+                    color: Vec3::new(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32) / 255.0,
+                    size,
+                });
+            }
+        }
+    }
+
+    if seeds.is_empty() {
+        return Ok((splats.clone(), 0));
+    }
+
+    if seeds.len() > config.hole_max_seeds {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        seeds.shuffle(&mut rng);
+        seeds.truncate(config.hole_max_seeds);
+    }
+
+    let means: Vec<Vec3> = seeds.iter().map(|s| s.position).collect();
+    let log_scales: Vec<Vec3> = seeds.iter().map(|s| Vec3::splat(s.size.ln())).collect();
+    let sh_coeffs: Vec<f32> = seeds
+        .iter()
+        .flat_map(|s| s.color.to_array().map(rgb_to_sh))
+        .collect();
+    let raw_opacities = vec![0.0; seeds.len()];
+    let seeded = Splats::from_raw(
+        &means,
+        None,
+        Some(&log_scales),
+        Some(&sh_coeffs),
+        Some(&raw_opacities),
+        &device,
+    );
+
+    Ok((Splats::merge(&[splats.clone(), seeded]), seeds.len()))
+}
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, io::Cursor, sync::Arc};
 
 use async_fn_stream::try_fn_stream;
 use brush_render::{render::rgb_to_sh, Backend};
@@ -6,16 +6,20 @@ use burn::tensor::{Tensor, TensorData};
 use glam::{Quat, Vec3, Vec4};
 use ply_rs::{
     parser::Parser,
-    ply::{ElementDef, Header, Property, PropertyAccess},
+    ply::{ElementDef, Encoding, Property, PropertyAccess, PropertyType, ScalarType},
 };
-use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
 use tokio_stream::Stream;
 use tokio_with_wasm::alias as tokio_wasm;
 use tracing::trace_span;
 
 use anyhow::Result;
-use brush_render::gaussian_splats::Splats;
+use brush_render::gaussian_splats::{Splats, TemporalAttributes};
 
+// Log duration of splats that are visible at all times, like `TemporalAttributes::stationary`.
+const STATIC_LOG_DURATION: f32 = 13.815_511; // ln(1e6)
+
+#[derive(Clone)]
 pub(crate) struct GaussianData {
     pub(crate) means: Vec3,
     pub(crate) log_scale: Vec3,
@@ -25,6 +29,12 @@ pub(crate) struct GaussianData {
     // NB: This is in the inria format, aka [channels, coeffs]
     // not [coeffs, channels].
     pub(crate) sh_coeffs_rest: Vec<f32>,
+    pub(crate) normal: Vec3,
+    pub(crate) label: u32,
+    // Motion of dynamic splats, see [`TemporalAttributes`].
+    pub(crate) velocity: Vec3,
+    pub(crate) time: f32,
+    pub(crate) log_duration: f32,
 }
 
 impl PropertyAccess for GaussianData {
@@ -36,12 +46,44 @@ impl PropertyAccess for GaussianData {
             rotation: Quat::IDENTITY,
             sh_dc: [0.0, 0.0, 0.0],
             sh_coeffs_rest: Vec::new(),
+            normal: Vec3::ZERO,
+            label: 0,
+            velocity: Vec3::ZERO,
+            time: 0.0,
+            log_duration: STATIC_LOG_DURATION,
         }
     }
 
     fn set_property(&mut self, key: &str, property: Property) {
         let ascii = key.as_bytes();
 
+        // Labels are plain integers, not normalized values.
+        if ascii == b"label" {
+            self.label = match property {
+                Property::UChar(v) => v as u32,
+                Property::UShort(v) => v as u32,
+                Property::UInt(v) => v,
+                Property::Char(v) => v.max(0) as u32,
+                Property::Short(v) => v.max(0) as u32,
+                Property::Int(v) => v.max(0) as u32,
+                Property::Float(v) => v.max(0.0) as u32,
+                _ => 0,
+            };
+            return;
+        }
+
+        // Two 4 bit SH coefficients, see `splat_to_compressed_ply`.
+        if let Some(idx) = key.strip_prefix("packed_f_rest_") {
+            if let (Ok(idx), Property::UChar(packed)) = (idx.parse::<usize>(), property) {
+                if idx + 1 >= self.sh_coeffs_rest.len() {
+                    self.sh_coeffs_rest.resize(idx + 2, 0.0);
+                }
+                self.sh_coeffs_rest[idx] = (packed & 0xf) as f32 / 15.0;
+                self.sh_coeffs_rest[idx + 1] = (packed >> 4) as f32 / 15.0;
+            }
+            return;
+        }
+
         let mut value = if let Property::Float(value) = property {
             value
         } else if let Property::UChar(value) = property {
@@ -75,6 +117,14 @@ impl PropertyAccess for GaussianData {
             b"red" => self.sh_dc[0] = rgb_to_sh(value),
             b"green" => self.sh_dc[1] = rgb_to_sh(value),
             b"blue" => self.sh_dc[2] = rgb_to_sh(value),
+            b"nx" => self.normal[0] = value,
+            b"ny" => self.normal[1] = value,
+            b"nz" => self.normal[2] = value,
+            b"velocity_0" => self.velocity[0] = value,
+            b"velocity_1" => self.velocity[1] = value,
+            b"velocity_2" => self.velocity[2] = value,
+            b"time" => self.time = value,
+            b"log_duration" => self.log_duration = value,
             _ if key.starts_with("f_rest_") => {
                 if let Ok(idx) = key["f_rest_".len()..].parse::<u32>() {
                     if idx >= self.sh_coeffs_rest.len() as u32 {
@@ -87,6 +137,10 @@ impl PropertyAccess for GaussianData {
         }
     }
 
+    fn get_uint(&self, key: &str) -> Option<u32> {
+        (key == "label").then_some(self.label)
+    }
+
     fn get_float(&self, key: &str) -> Option<f32> {
         let ascii = key.as_bytes();
 
@@ -105,6 +159,14 @@ impl PropertyAccess for GaussianData {
             b"f_dc_0" => Some(self.sh_dc[0]),
             b"f_dc_1" => Some(self.sh_dc[1]),
             b"f_dc_2" => Some(self.sh_dc[2]),
+            b"nx" => Some(self.normal[0]),
+            b"ny" => Some(self.normal[1]),
+            b"nz" => Some(self.normal[2]),
+            b"velocity_0" => Some(self.velocity[0]),
+            b"velocity_1" => Some(self.velocity[1]),
+            b"velocity_2" => Some(self.velocity[2]),
+            b"time" => Some(self.time),
+            b"log_duration" => Some(self.log_duration),
             _ if key.starts_with("f_rest_") => {
                 if let Ok(idx) = key["f_rest_".len()..].parse::<usize>() {
                     self.sh_coeffs_rest.get(idx).copied()
@@ -117,6 +179,35 @@ impl PropertyAccess for GaussianData {
     }
 }
 
+impl GaussianData {
+    // Map values normalized to 0-1 back to the range stored in the file.
+    fn dequantize(self, min: &Self, max: &Self) -> Self {
+        let lerp = |v: f32, min: f32, max: f32| min + v * (max - min);
+        let rotation = Vec4::from(self.rotation)
+            * (Vec4::from(max.rotation) - Vec4::from(min.rotation))
+            + Vec4::from(min.rotation);
+
+        Self {
+            means: self.means * (max.means - min.means) + min.means,
+            log_scale: self.log_scale * (max.log_scale - min.log_scale) + min.log_scale,
+            opacity: lerp(self.opacity, min.opacity, max.opacity),
+            rotation: Quat::from_vec4(rotation),
+            sh_dc: [0, 1, 2].map(|i| lerp(self.sh_dc[i], min.sh_dc[i], max.sh_dc[i])),
+            sh_coeffs_rest: self
+                .sh_coeffs_rest
+                .iter()
+                .zip(min.sh_coeffs_rest.iter().zip(&max.sh_coeffs_rest))
+                .map(|(&v, (&min, &max))| lerp(v, min, max))
+                .collect(),
+            normal: self.normal * (max.normal - min.normal) + min.normal,
+            label: self.label,
+            velocity: self.velocity * (max.velocity - min.velocity) + min.velocity,
+            time: lerp(self.time, min.time, max.time),
+            log_duration: lerp(self.log_duration, min.log_duration, max.log_duration),
+        }
+    }
+}
+
 fn interleave_coeffs(sh_dc: [f32; 3], sh_rest: &[f32]) -> Vec<f32> {
     let channels = 3;
     let coeffs_per_channel = sh_rest.len() / channels;
@@ -132,13 +223,13 @@ fn interleave_coeffs(sh_dc: [f32; 3], sh_rest: &[f32]) -> Vec<f32> {
     result
 }
 
-async fn decode_splat<T: AsyncBufRead + Unpin + 'static>(
+async fn decode_splat<T: AsyncBufRead + Unpin>(
     reader: &mut T,
     parser: &Parser<GaussianData>,
-    header: &Header,
+    encoding: Encoding,
     element: &ElementDef,
 ) -> tokio::io::Result<GaussianData> {
-    match header.encoding {
+    match encoding {
         ply_rs::ply::Encoding::Ascii => {
             let mut ascii_line = String::new();
             reader.read_line(&mut ascii_line).await?;
@@ -155,6 +246,149 @@ async fn decode_splat<T: AsyncBufRead + Unpin + 'static>(
     }
 }
 
+// Nr. of splats read at a time. Every chunk is decoded on a background task while the next chunk
+// is read.
+const CHUNK_SIZE: usize = 1 << 16;
+
+// Size in bytes of an element in a binary ply, or None if it has list properties.
+fn binary_element_size(element: &ElementDef) -> Option<usize> {
+    element
+        .properties
+        .iter()
+        .map(|p| match &p.data_type {
+            PropertyType::Scalar(ScalarType::Char | ScalarType::UChar) => Some(1),
+            PropertyType::Scalar(ScalarType::Short | ScalarType::UShort) => Some(2),
+            PropertyType::Scalar(ScalarType::Int | ScalarType::UInt | ScalarType::Float) => Some(4),
+            PropertyType::Scalar(ScalarType::Double) => Some(8),
+            PropertyType::List(..) => None,
+        })
+        .sum()
+}
+
+// Read the raw data of `count` elements.
+async fn read_chunk<T: AsyncBufRead + Unpin>(
+    reader: &mut T,
+    encoding: Encoding,
+    element_size: Option<usize>,
+    count: usize,
+) -> Result<Vec<u8>> {
+    if matches!(encoding, Encoding::Ascii) {
+        let mut lines = String::new();
+        for _ in 0..count {
+            reader.read_line(&mut lines).await?;
+        }
+        return Ok(lines.into_bytes());
+    }
+    let Some(element_size) = element_size else {
+        anyhow::bail!("Binary splat plys with list properties aren't supported");
+    };
+    let mut data = vec![0; element_size * count];
+    reader.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+// Decoded splats of a chunk of the vertex element.
+#[derive(Default)]
+struct SplatChunk {
+    means: Vec<Vec3>,
+    log_scales: Vec<Vec3>,
+    rotations: Vec<Quat>,
+    opacity: Vec<f32>,
+    sh_coeffs: Vec<f32>,
+    labels: Vec<i32>,
+    velocities: Vec<f32>,
+    times: Vec<f32>,
+    log_durations: Vec<f32>,
+}
+
+impl SplatChunk {
+    fn extend(&mut self, other: Self) {
+        self.means.extend(other.means);
+        self.log_scales.extend(other.log_scales);
+        self.rotations.extend(other.rotations);
+        self.opacity.extend(other.opacity);
+        self.sh_coeffs.extend(other.sh_coeffs);
+        self.labels.extend(other.labels);
+        self.velocities.extend(other.velocities);
+        self.times.extend(other.times);
+        self.log_durations.extend(other.log_durations);
+    }
+
+    // Only the properties in the file are used, the others are initialized like `from_raw` does.
+    fn to_splats<B: Backend>(&self, properties: &HashSet<String>, device: &B::Device) -> Splats<B> {
+        let has = |p: &str| properties.contains(p);
+        let splats = Splats::from_raw(
+            &self.means,
+            has("rot_0").then_some(self.rotations.as_slice()),
+            has("scale_0").then_some(self.log_scales.as_slice()),
+            (has("f_dc_0") || has("red")).then_some(self.sh_coeffs.as_slice()),
+            has("opacity").then_some(self.opacity.as_slice()),
+            device,
+        );
+        let n = self.labels.len();
+        let splats = if has("label") {
+            splats.with_labels(Tensor::from_data(
+                TensorData::new(self.labels.clone(), [n]),
+                device,
+            ))
+        } else {
+            splats
+        };
+        // Splats are dynamic if they have a time or a velocity. Missing attributes default to
+        // splats that don't move and are always visible.
+        if has("time") || has("velocity_0") {
+            splats.with_temporal(TemporalAttributes::new(
+                Tensor::from_data(TensorData::new(self.velocities.clone(), [n, 3]), device),
+                Tensor::from_data(TensorData::new(self.times.clone(), [n]), device),
+                Tensor::from_data(TensorData::new(self.log_durations.clone(), [n]), device),
+            ))
+        } else {
+            splats
+        }
+    }
+}
+
+// Decode the raw data of a chunk, that starts at the splat with index `start`.
+async fn decode_chunk(
+    data: Vec<u8>,
+    start: usize,
+    element: Arc<ElementDef>,
+    encoding: Encoding,
+    subsample_points: Option<u32>,
+    quant: Option<Arc<(GaussianData, GaussianData)>>,
+) -> tokio::io::Result<SplatChunk> {
+    let parser = Parser::<GaussianData>::new();
+    let count = element.count.min(start + CHUNK_SIZE) - start;
+    let mut reader = Cursor::new(data);
+    let mut chunk = SplatChunk::default();
+
+    for i in start..start + count {
+        // Doing this after first reading and parsing the points is quite wasteful, but
+        // we do need to advance the reader.
+        let mut splat = decode_splat(&mut reader, &parser, encoding, &element).await?;
+        if subsample_points.is_some_and(|subsample| i % subsample as usize != 0) {
+            continue;
+        }
+
+        if let Some((min, max)) = quant.as_deref() {
+            splat = splat.dequantize(min, max);
+        }
+
+        chunk.means.push(splat.means);
+        chunk.log_scales.push(splat.log_scale);
+        chunk.rotations.push(splat.rotation.normalize());
+        chunk.opacity.push(splat.opacity);
+        chunk.labels.push(splat.label as i32);
+        chunk.velocities.extend(splat.velocity.to_array());
+        chunk.times.push(splat.time);
+        chunk.log_durations.push(splat.log_duration);
+        chunk
+            .sh_coeffs
+            .extend(interleave_coeffs(splat.sh_dc, &splat.sh_coeffs_rest));
+    }
+    Ok(chunk)
+}
+
 pub struct SplatMetadata {
     pub up_axis: Option<Vec3>,
     pub total_splats: usize,
@@ -219,126 +453,114 @@ pub fn load_splat_from_ply<T: AsyncRead + Unpin + 'static, B: Backend>(
             rotation: Vec4::ONE,
             scale: Vec3::ONE,
         };
+        // Ranges of quantized splat properties, if the file is quantized.
+        let mut quant_min = None;
+        let mut quant_max = None;
+
         for element in &header.elements {
             let properties: HashSet<_> =
                 element.properties.iter().map(|x| x.name.clone()).collect();
 
-            let n_sh_coeffs = (3 + element
-                .properties
-                .iter()
-                .filter_map(|x| {
-                    x.name
-                        .strip_prefix("f_rest_")
-                        .and_then(|x| x.parse::<u32>().ok())
-                })
-                .max()
-                .unwrap_or(0)) as usize;
-
-            let mut means = Vec::with_capacity(element.count);
-            let mut log_scales = properties
-                .contains("scale_0")
-                .then(|| Vec::with_capacity(element.count));
-            let mut rotations = properties
-                .contains("rot_0")
-                .then(|| Vec::with_capacity(element.count));
-            let mut sh_coeffs = (properties.contains("f_dc_0") || properties.contains("red"))
-                .then(|| Vec::with_capacity(element.count * n_sh_coeffs));
-            let mut opacity = properties
-                .contains("opacity")
-                .then(|| Vec::with_capacity(element.count));
-
             if element.name == "vertex" {
                 if ["x", "y", "z"].into_iter().any(|p| !properties.contains(p)) {
                     anyhow::bail!("Invalid splat ply. Missing properties!");
                 }
 
-                let update_every = element.count.div_ceil(25);
-
-                for i in 0..element.count {
-                    // Occasionally yield.
-                    if i % 500 == 0 {
-                        tokio_wasm::task::yield_now().await;
-                    }
-
-                    // Occasionally send some updated splats.
-                    if i % update_every == update_every - 1 {
-                        let splats = Splats::from_raw(
-                            &means,
-                            rotations.as_deref(),
-                            log_scales.as_deref(),
-                            sh_coeffs.as_deref(),
-                            opacity.as_deref(),
-                            &device,
+                let element_size = binary_element_size(element);
+                let shared_element = Arc::new(element.clone());
+                let quant = quant_min
+                    .clone()
+                    .zip(quant_max.clone())
+                    .map(|(min, max)| Arc::new((min, max)));
+                let update_every = element.count.div_ceil(25).max(CHUNK_SIZE);
+
+                // Splats with scales are uploaded a chunk at a time, and only merged on the GPU.
+                // Scales of point clouds are estimated from their neighbours, which needs all
+                // points.
+                let upload_chunks = properties.contains("scale_0");
+                let mut parts: Vec<Splats<B>> = vec![];
+                let mut points = SplatChunk::default();
+                let mut since_update = 0;
+
+                let mut pending = None;
+                let mut start = 0;
+                while start < element.count || pending.is_some() {
+                    // Read the next chunk while the previous chunk is decoded.
+                    let next = if start < element.count {
+                        let count = CHUNK_SIZE.min(element.count - start);
+                        let data =
+                            read_chunk(&mut reader, header.encoding, element_size, count).await?;
+                        let decode = decode_chunk(
+                            data,
+                            start,
+                            shared_element.clone(),
+                            header.encoding,
+                            subsample_points,
+                            quant.clone(),
                         );
-
-                        emitter
-                            .emit(SplatMessage {
-                                meta: SplatMetadata {
-                                    total_splats: element.count,
-                                    up_axis,
-                                    frame_count,
-                                    current_frame: frame,
-                                },
-                                splats,
-                            })
-                            .await;
-                    }
-
-                    // Doing this after first reading and parsing the points is quite wasteful, but
-                    // we do need to advance the reader.
-                    if let Some(subsample) = subsample_points {
-                        if i % subsample as usize != 0 {
-                            continue;
+                        start += count;
+                        Some(tokio_wasm::task::spawn(decode))
+                    } else {
+                        None
+                    };
+
+                    if let Some(decoding) = pending.take() {
+                        let chunk = decoding
+                            .await
+                            .map_err(|e| anyhow::anyhow!("Failed to decode splats: {e}"))??;
+                        since_update += CHUNK_SIZE;
+                        if upload_chunks {
+                            parts.push(chunk.to_splats(&properties, &device));
+                        } else {
+                            points.extend(chunk);
                         }
-                    }
 
-                    let splat =
-                        decode_splat(&mut reader, &gaussian_parser, &header, element).await?;
-
-                    means.push(splat.means);
-                    if let Some(scales) = log_scales.as_mut() {
-                        scales.push(splat.log_scale);
-                    }
-                    if let Some(rotation) = rotations.as_mut() {
-                        rotation.push(splat.rotation.normalize());
-                    }
-                    if let Some(opacity) = opacity.as_mut() {
-                        opacity.push(splat.opacity);
-                    }
-                    if let Some(sh_coeffs) = sh_coeffs.as_mut() {
-                        let sh_coeffs_interleaved =
-                            interleave_coeffs(splat.sh_dc, &splat.sh_coeffs_rest);
-                        sh_coeffs.extend(sh_coeffs_interleaved);
+                        // Occasionally send some updated splats.
+                        let done = start >= element.count && next.is_none();
+                        if since_update >= update_every || done {
+                            since_update = 0;
+                            let splats = if upload_chunks {
+                                let merged = Splats::merge(&parts);
+                                parts = vec![merged.clone()];
+                                merged
+                            } else {
+                                points.to_splats(&properties, &device)
+                            };
+                            if done {
+                                final_splat = Some(splats.clone());
+                            }
+                            emitter
+                                .emit(SplatMessage {
+                                    meta: SplatMetadata {
+                                        total_splats: element.count,
+                                        up_axis,
+                                        frame_count,
+                                        current_frame: frame,
+                                    },
+                                    splats,
+                                })
+                                .await;
+                        }
                     }
+                    pending = next;
                 }
-
-                let splats = Splats::from_raw(
-                    &means,
-                    rotations.as_deref(),
-                    log_scales.as_deref(),
-                    sh_coeffs.as_deref(),
-                    opacity.as_deref(),
-                    &device,
+            } else if element.name == "meta_quant_min" {
+                quant_min = Some(
+                    decode_splat(&mut reader, &gaussian_parser, header.encoding, element).await?,
+                );
+            } else if element.name == "meta_quant_max" {
+                quant_max = Some(
+                    decode_splat(&mut reader, &gaussian_parser, header.encoding, element).await?,
                 );
-                final_splat = Some(splats.clone());
-                emitter
-                    .emit(SplatMessage {
-                        meta: SplatMetadata {
-                            total_splats: element.count,
-                            up_axis,
-                            frame_count,
-                            current_frame: frame,
-                        },
-                        splats,
-                    })
-                    .await;
             } else if element.name.starts_with("meta_delta_min_") {
-                let splat = decode_splat(&mut reader, &gaussian_parser, &header, element).await?;
+                let splat =
+                    decode_splat(&mut reader, &gaussian_parser, header.encoding, element).await?;
                 meta_min.mean = splat.means;
                 meta_min.rotation = splat.rotation.into();
                 meta_min.scale = splat.log_scale;
             } else if element.name.starts_with("meta_delta_max_") {
-                let splat = decode_splat(&mut reader, &gaussian_parser, &header, element).await?;
+                let splat =
+                    decode_splat(&mut reader, &gaussian_parser, header.encoding, element).await?;
                 meta_max.mean = splat.means;
                 meta_max.rotation = splat.rotation.into();
                 meta_max.scale = splat.log_scale;
@@ -347,6 +569,14 @@ pub fn load_splat_from_ply<T: AsyncRead + Unpin + 'static, B: Backend>(
                     anyhow::bail!("Need to read base splat first.");
                 };
 
+                let mut means = Vec::with_capacity(element.count);
+                let mut log_scales = properties
+                    .contains("scale_0")
+                    .then(|| Vec::with_capacity(element.count));
+                let mut rotations = properties
+                    .contains("rot_0")
+                    .then(|| Vec::with_capacity(element.count));
+
                 for i in 0..element.count {
                     // Occasionally yield.
                     if i % 500 == 0 {
@@ -355,7 +585,8 @@ pub fn load_splat_from_ply<T: AsyncRead + Unpin + 'static, B: Backend>(
                     // The splat we decode is normed to 0-1 (if quantized), so rescale to
                     // actual values afterwards.
                     let splat_enc =
-                        decode_splat(&mut reader, &gaussian_parser, &header, element).await?;
+                        decode_splat(&mut reader, &gaussian_parser, header.encoding, element)
+                            .await?;
 
                     // Let's only animate transforms for now.
                     means.push(splat_enc.means * (meta_max.mean - meta_min.mean) + meta_min.mean);
@@ -413,6 +644,8 @@ pub fn load_splat_from_ply<T: AsyncRead + Unpin + 'static, B: Backend>(
                     splats.raw_opacity.val(),
                 );
                 new_splat.norm_rotations();
+                new_splat.labels = splats.labels.clone();
+                new_splat.temporal = splats.temporal.clone();
 
                 // Emit newly animated splat.
                 emitter
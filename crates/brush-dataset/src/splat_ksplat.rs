@@ -0,0 +1,222 @@
+//! Export to the `.ksplat` format of the GaussianSplats3D web viewer.
+//!
+//! Splats are written in a single section at compression level 1. Splats are grouped in
+//! buckets of nearby splats, and positions are stored as 16 bit offsets from the center of
+//! their bucket. Scales, rotations and SH coefficients are stored as half floats, and the base
+//! color and opacity as 8 bit rgba.
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use brush_render::{gaussian_splats::Splats, render::SH_C0, Backend};
+use burn::tensor::f16;
+use glam::{IVec3, Vec3};
+
+use crate::splat_export::read_splat_data;
+
+const HEADER_SIZE: usize = 4096;
+const SECTION_HEADER_SIZE: usize = 1024;
+const COMPRESSION_LEVEL: u16 = 1;
+// Max nr. of splats per bucket.
+const BUCKET_SIZE: usize = 256;
+// Size of the cubes splats are bucketed by.
+const BUCKET_BLOCK_SIZE: f32 = 5.0;
+// Bucket centers are stored as 3 floats.
+const BUCKET_STORAGE_SIZE: usize = 12;
+// Positions are stored as offsets in `0..2 * SCALE_RANGE`.
+const SCALE_RANGE: u32 = 32767;
+// Range of the SH coefficients, only used by higher compression levels.
+const SH_RANGE: f32 = 1.5;
+
+fn put_u16(buf: &mut [u8], offset: usize, v: u16) {
+    buf[offset..offset + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, v: u32) {
+    buf[offset..offset + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_f32(buf: &mut [u8], offset: usize, v: f32) {
+    buf[offset..offset + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+fn push_half(bytes: &mut Vec<u8>, v: f32) {
+    bytes.extend(f16::from_f32(v).to_le_bytes());
+}
+
+struct Buckets {
+    // Splat indices, in file order.
+    order: Vec<usize>,
+    centers: Vec<Vec3>,
+    num_full: usize,
+    // Nr. of splats in each bucket that isn't full. These are stored after the full buckets.
+    partial_sizes: Vec<u32>,
+}
+
+// Group splats by the block they're in, and split blocks into buckets of at most BUCKET_SIZE
+// splats.
+fn bucket_splats(means: &[Vec3]) -> Buckets {
+    let mut blocks: HashMap<IVec3, Vec<usize>> = HashMap::new();
+    for (i, mean) in means.iter().enumerate() {
+        let block = (*mean / BUCKET_BLOCK_SIZE).floor().as_ivec3();
+        blocks.entry(block).or_default().push(i);
+    }
+    let mut blocks: Vec<_> = blocks.into_iter().collect();
+    blocks.sort_by_key(|(block, _)| block.to_array());
+
+    let mut full = vec![];
+    let mut partial = vec![];
+    for (block, splats) in blocks {
+        let center = (block.as_vec3() + 0.5) * BUCKET_BLOCK_SIZE;
+        for bucket in splats.chunks(BUCKET_SIZE) {
+            if bucket.len() == BUCKET_SIZE {
+                full.push((center, bucket.to_vec()));
+            } else {
+                partial.push((center, bucket.to_vec()));
+            }
+        }
+    }
+
+    let num_full = full.len();
+    let partial_sizes = partial.iter().map(|(_, b)| b.len() as u32).collect();
+    let (centers, order): (Vec<_>, Vec<_>) = full.into_iter().chain(partial).unzip();
+    Buckets {
+        order: order.concat(),
+        centers,
+        num_full,
+        partial_sizes,
+    }
+}
+
+/// Serialize splats to a ksplat file. Ksplat supports at most SH degree 2, higher order
+/// coefficients are dropped.
+pub async fn splat_to_ksplat<B: Backend>(splats: Splats<B>) -> anyhow::Result<Vec<u8>> {
+    let mut splats = splats;
+    splats.norm_rotations();
+
+    let sh_degree = splats.sh_degree().min(2);
+    let splats = splats.with_sh_degree(sh_degree);
+    let sh_rest_num = splats.sh_coeffs.dims()[1] - 1;
+
+    let data = read_splat_data(splats)
+        .await
+        .map_err(|e| anyhow!("Failed to read data from splat {e:?}"))?;
+    let n = data.len();
+
+    let means: Vec<Vec3> = data.iter().map(|s| s.means).collect();
+    let buckets = bucket_splats(&means);
+    let scene_center = means.iter().sum::<Vec3>() / n.max(1) as f32;
+
+    let bytes_per_splat = 6 + 6 + 8 + 4 + sh_rest_num * 3 * 2;
+    let buckets_size =
+        buckets.partial_sizes.len() * 4 + buckets.centers.len() * BUCKET_STORAGE_SIZE;
+    let section_size = buckets_size + n * bytes_per_splat;
+
+    let mut bytes = vec![0; HEADER_SIZE + SECTION_HEADER_SIZE];
+
+    let header = &mut bytes[..HEADER_SIZE];
+    // Version 0.1.
+    header[0] = 0;
+    header[1] = 1;
+    put_u32(header, 4, 1);
+    put_u32(header, 8, 1);
+    put_u32(header, 12, n as u32);
+    put_u32(header, 16, n as u32);
+    put_u16(header, 20, COMPRESSION_LEVEL);
+    for (i, v) in scene_center.to_array().into_iter().enumerate() {
+        put_f32(header, 24 + i * 4, v);
+    }
+    put_f32(header, 36, -SH_RANGE);
+    put_f32(header, 40, SH_RANGE);
+
+    let section = &mut bytes[HEADER_SIZE..];
+    put_u32(section, 0, n as u32);
+    put_u32(section, 4, n as u32);
+    put_u32(section, 8, BUCKET_SIZE as u32);
+    put_u32(section, 12, buckets.centers.len() as u32);
+    put_f32(section, 16, BUCKET_BLOCK_SIZE);
+    put_u16(section, 20, BUCKET_STORAGE_SIZE as u16);
+    put_u32(section, 24, SCALE_RANGE);
+    put_u32(section, 28, section_size as u32);
+    put_u32(section, 32, buckets.num_full as u32);
+    put_u32(section, 36, buckets.partial_sizes.len() as u32);
+    put_u16(section, 40, sh_degree as u16);
+
+    bytes.reserve(section_size);
+    for size in &buckets.partial_sizes {
+        bytes.extend(size.to_le_bytes());
+    }
+    for center in &buckets.centers {
+        bytes.extend(center.to_array().iter().flat_map(|v| v.to_le_bytes()));
+    }
+
+    // Bucket of every splat, in file order.
+    let splat_buckets = buckets
+        .partial_sizes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &size)| std::iter::repeat(buckets.num_full + i).take(size as usize));
+    let splat_buckets = (0..buckets.num_full)
+        .flat_map(|i| std::iter::repeat(i).take(BUCKET_SIZE))
+        .chain(splat_buckets);
+
+    let scale_factor = SCALE_RANGE as f32 / (BUCKET_BLOCK_SIZE / 2.0);
+    for (&i, bucket) in buckets.order.iter().zip(splat_buckets) {
+        let splat = &data[i];
+
+        let offset = (splat.means - buckets.centers[bucket]) * scale_factor + SCALE_RANGE as f32;
+        for v in offset.to_array() {
+            let v = v.round().clamp(0.0, 2.0 * SCALE_RANGE as f32) as u16;
+            bytes.extend(v.to_le_bytes());
+        }
+        for s in splat.log_scale.exp().to_array() {
+            push_half(&mut bytes, s);
+        }
+        let q = splat.rotation;
+        for v in [q.w, q.x, q.y, q.z] {
+            push_half(&mut bytes, v);
+        }
+
+        let opacity = 1.0 / (1.0 + (-splat.opacity).exp());
+        let rgba = [
+            splat.sh_dc[0] * SH_C0 + 0.5,
+            splat.sh_dc[1] * SH_C0 + 0.5,
+            splat.sh_dc[2] * SH_C0 + 0.5,
+            opacity,
+        ];
+        bytes.extend(rgba.map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8));
+
+        // Ply stores the coefficients per channel, ksplat per coefficient.
+        for coeff in 0..sh_rest_num {
+            for channel in 0..3 {
+                push_half(
+                    &mut bytes,
+                    splat.sh_coeffs_rest[channel * sh_rest_num + coeff],
+                );
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_buckets_come_first() {
+        // One block with a full bucket and a partial one, and one block with a partial bucket.
+        let mut means = vec![Vec3::splat(1.0); BUCKET_SIZE + 10];
+        means.extend([Vec3::splat(-1.0); 3]);
+
+        let buckets = bucket_splats(&means);
+        assert_eq!(buckets.num_full, 1);
+        assert_eq!(buckets.centers.len(), 3);
+        assert_eq!(buckets.order.len(), means.len());
+        let mut sizes = buckets.partial_sizes.clone();
+        sizes.sort();
+        assert_eq!(sizes, vec![3, 10]);
+        assert_eq!(buckets.centers[0], Vec3::splat(BUCKET_BLOCK_SIZE / 2.0));
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use brush_render::{bounding_box::BoundingBox, camera::Camera, gaussian_splats::Splats, Backend};
+use burn::{config::Config, tensor::Tensor};
+use clap::Args;
+use glam::{Mat3, Quat, UVec2, Vec3};
+use tokio_with_wasm::alias as tokio_wasm;
+
+#[derive(Config, Debug, Args)]
+pub struct MeshConfig {
+    /// Extract a mesh at the end of training, exported as an obj file next to the ply.
+    #[arg(long, help_heading = "Mesh Options", default_value = "false")]
+    #[config(default = false)]
+    pub export_mesh: bool,
+    /// Number of voxels along the longest side of the meshed volume.
+    #[arg(long, help_heading = "Mesh Options", default_value = "256")]
+    #[config(default = 256)]
+    pub mesh_resolution: u32,
+    /// Truncation distance of the signed distance field, in voxels.
+    #[arg(long, help_heading = "Mesh Options", default_value = "3.0")]
+    #[config(default = 3.0)]
+    pub mesh_truncation: f32,
+    /// Rendered pixels with a lower alpha than this don't contribute to the mesh.
+    #[arg(long, help_heading = "Mesh Options", default_value = "0.5")]
+    #[config(default = 0.5)]
+    pub mesh_min_alpha: f32,
+}
+
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub colors: Vec<Vec3>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Write the mesh as an obj file. Vertex colors are written after the vertex positions,
+    /// which most tools understand.
+    pub fn to_obj(&self) -> String {
+        let mut obj = String::from("# Exported from Brush\n");
+        for (p, c) in self.positions.iter().zip(&self.colors) {
+            obj += &format!("v {} {} {} {} {} {}\n", p.x, p.y, p.z, c.x, c.y, c.z);
+        }
+        for tri in self.indices.chunks_exact(3) {
+            obj += &format!("f {} {} {}\n", tri[0] + 1, tri[1] + 1, tri[2] + 1);
+        }
+        obj
+    }
+}
+
+/// Cameras spread evenly on a sphere around the bounds, looking at its center. Useful to mesh
+/// splats without any training views.
+pub fn sample_cameras(bounds: BoundingBox, count: usize, fov: f64) -> Vec<Camera> {
+    let radius = bounds.extent.length() * 1.5;
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+
+    (0..count)
+        .map(|i| {
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+            let r = (1.0 - y * y).sqrt();
+            let theta = golden_angle * i as f32;
+            let dir = Vec3::new(r * theta.cos(), y, r * theta.sin());
+
+            let position = bounds.center + dir * radius;
+            let forward = -dir;
+            let right = forward.cross(Vec3::Y).try_normalize().unwrap_or(Vec3::X);
+            let down = forward.cross(right);
+            let rotation = Quat::from_mat3(&Mat3::from_cols(right, down, forward));
+
+            Camera::new(position, rotation, fov, fov, glam::vec2(0.5, 0.5))
+        })
+        .collect()
+}
+
+async fn read_floats<B: Backend, const D: usize>(tensor: Tensor<B, D>) -> anyhow::Result<Vec<f32>> {
+    tensor
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(|e| anyhow!("Failed to read tensor data {e:?}"))
+}
+
+// Bounds of the bulk of the splats, ignoring outliers far away from the scene.
+fn robust_bounds(means: &[f32]) -> anyhow::Result<BoundingBox> {
+    let n = means.len() / 3;
+    anyhow::ensure!(n > 0, "Can't extract a mesh without any splats");
+    let percentile = |axis: usize, p: f32| {
+        let mut vals: Vec<f32> = (0..n).map(|i| means[i * 3 + axis]).collect();
+        vals.sort_by(f32::total_cmp);
+        vals[((n - 1) as f32 * p) as usize]
+    };
+
+    let min = Vec3::new(
+        percentile(0, 0.01),
+        percentile(1, 0.01),
+        percentile(2, 0.01),
+    );
+    let max = Vec3::new(
+        percentile(0, 0.99),
+        percentile(1, 0.99),
+        percentile(2, 0.99),
+    );
+    Ok(BoundingBox::from_min_max(min, max))
+}
+
+// Corners of a cube, and the six tetrahedra around its 0-6 diagonal for marching tetrahedra.
+const CUBE_CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [1, 1, 0],
+    [0, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [1, 1, 1],
+    [0, 1, 1],
+];
+const CUBE_TETS: [[usize; 4]; 6] = [
+    [0, 5, 1, 6],
+    [0, 1, 2, 6],
+    [0, 2, 3, 6],
+    [0, 3, 7, 6],
+    [0, 7, 4, 6],
+    [0, 4, 5, 6],
+];
+
+struct Grid {
+    dims: [usize; 3],
+    min: Vec3,
+    voxel_size: f32,
+}
+
+impl Grid {
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.dims[1] + y) * self.dims[0] + x
+    }
+
+    fn position(&self, [x, y, z]: [usize; 3]) -> Vec3 {
+        self.min + (Vec3::new(x as f32, y as f32, z as f32) + 0.5) * self.voxel_size
+    }
+}
+
+// Extract the zero level set of the sdf with marching tetrahedra. Cells with any unobserved
+// corner are skipped, so the unobserved inside of objects doesn't produce surfaces.
+fn extract_surface(grid: &Grid, sdf: &[f32], weights: &[f32], colors: &[Vec3]) -> Mesh {
+    let mut mesh = Mesh {
+        positions: vec![],
+        colors: vec![],
+        indices: vec![],
+    };
+    let mut edge_verts: HashMap<(usize, usize), u32> = HashMap::new();
+
+    let [nx, ny, nz] = grid.dims;
+
+    for z in 0..nz.saturating_sub(1) {
+        for y in 0..ny.saturating_sub(1) {
+            for x in 0..nx.saturating_sub(1) {
+                let corners = CUBE_CORNERS.map(|[dx, dy, dz]| [x + dx, y + dy, z + dz]);
+                let ids = corners.map(|c| grid.index(c));
+
+                if ids.iter().any(|&i| weights[i] <= 0.0) {
+                    continue;
+                }
+                let inside = ids.map(|i| sdf[i] < 0.0);
+                if inside.iter().all(|&i| i) || inside.iter().all(|&i| !i) {
+                    continue;
+                }
+
+                for tet in CUBE_TETS {
+                    let (ins, outs): (Vec<usize>, Vec<usize>) =
+                        tet.into_iter().partition(|&c| inside[c]);
+                    if ins.is_empty() || outs.is_empty() {
+                        continue;
+                    }
+
+                    let mut vertex = |a: usize, b: usize| {
+                        let (ia, ib) = (ids[a], ids[b]);
+                        let key = (ia.min(ib), ia.max(ib));
+                        *edge_verts.entry(key).or_insert_with(|| {
+                            let t = sdf[ia] / (sdf[ia] - sdf[ib]);
+                            let pa = grid.position(corners[a]);
+                            let pb = grid.position(corners[b]);
+                            mesh.positions.push(pa + (pb - pa) * t);
+                            mesh.colors.push(colors[ia] + (colors[ib] - colors[ia]) * t);
+                            mesh.positions.len() as u32 - 1
+                        })
+                    };
+
+                    let tris = match (ins.len(), outs.len()) {
+                        (1, 3) => {
+                            let a = ins[0];
+                            vec![[vertex(a, outs[0]), vertex(a, outs[1]), vertex(a, outs[2])]]
+                        }
+                        (3, 1) => {
+                            let a = outs[0];
+                            vec![[vertex(a, ins[0]), vertex(a, ins[1]), vertex(a, ins[2])]]
+                        }
+                        _ => {
+                            let (a, b, c, d) = (ins[0], ins[1], outs[0], outs[1]);
+                            let quad = [vertex(a, c), vertex(a, d), vertex(b, d), vertex(b, c)];
+                            vec![[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]]
+                        }
+                    };
+
+                    // Orient triangles to face from the inside towards the outside.
+                    let centroid = |cs: &[usize]| {
+                        cs.iter().map(|&c| grid.position(corners[c])).sum::<Vec3>()
+                            / cs.len() as f32
+                    };
+                    let outward = centroid(&outs) - centroid(&ins);
+
+                    for [i0, i1, i2] in tris {
+                        let [p0, p1, p2] = [i0, i1, i2].map(|i| mesh.positions[i as usize]);
+                        if (p1 - p0).cross(p2 - p0).dot(outward) < 0.0 {
+                            mesh.indices.extend([i0, i2, i1]);
+                        } else {
+                            mesh.indices.extend([i0, i1, i2]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// Extract a mesh from splats.
+///
+/// Depth & color are rendered from the given views, and fused into a truncated signed
+/// distance field. The surface is then extracted with marching tetrahedra, a variant of
+/// marching cubes without ambiguous cases. Vertex colors are taken from the renders.
+pub async fn extract_mesh<B: Backend>(
+    splats: &Splats<B>,
+    views: &[(Camera, UVec2)],
+    config: &MeshConfig,
+) -> anyhow::Result<Mesh> {
+    let device = splats.means.val().device();
+
+    let means_data = read_floats(splats.means.val()).await?;
+    let bounds = robust_bounds(&means_data)?;
+
+    let voxel_size = bounds.extent.max_element() * 2.0 / config.mesh_resolution as f32;
+    let trunc = voxel_size * config.mesh_truncation;
+    let min = bounds.min() - trunc;
+    let size = bounds.extent * 2.0 + 2.0 * trunc;
+    let dims = [size.x, size.y, size.z].map(|s| ((s / voxel_size).ceil() as usize).max(2));
+    let grid = Grid {
+        dims,
+        min,
+        voxel_size,
+    };
+    let n_voxels = dims[0] * dims[1] * dims[2];
+
+    let mut centers = Vec::with_capacity(n_voxels * 3);
+    for z in 0..dims[2] {
+        for y in 0..dims[1] {
+            for x in 0..dims[0] {
+                centers.extend(grid.position([x, y, z]).to_array());
+            }
+        }
+    }
+    let centers = Tensor::<B, 1>::from_floats(centers.as_slice(), &device).reshape([n_voxels, 3]);
+
+    let mut sdf_sum = Tensor::<B, 1>::zeros([n_voxels], &device);
+    let mut weight_sum = Tensor::<B, 1>::zeros([n_voxels], &device);
+    let mut color_sum = Tensor::<B, 2>::zeros([n_voxels, 3], &device);
+
+    for (camera, img_size) in views {
+        tokio_wasm::task::yield_now().await;
+
+        // The local space of the camera, as a matrix multiplying row vectors.
+        let rot = Mat3::from_quat(camera.rotation);
+        let to_local =
+            Tensor::<B, 1>::from_floats(rot.transpose().to_cols_array(), &device).reshape([3, 3]);
+        let cam_pos =
+            Tensor::<B, 1>::from_floats(camera.position.to_array(), &device).reshape([1, 3]);
+
+        let (w, h) = (img_size.x as usize, img_size.y as usize);
+        let (color_img, aux) = splats.render_with_depth(camera, *img_size);
+        let depth = aux
+            .depth
+            .ok_or_else(|| anyhow!("Depth wasn't rendered"))?
+            .reshape([h * w]);
+
+        let alpha = color_img.clone().slice([0..h, 0..w, 3..4]).reshape([h * w]);
+        let norm_alpha = alpha.clone().clamp_min(1e-6);
+        let color = color_img.slice([0..h, 0..w, 0..3]).reshape([h * w, 3])
+            / norm_alpha.reshape([h * w, 1]);
+
+        // Project all voxels into the view.
+        let local = (centers.clone() - cam_pos).matmul(to_local);
+        let lx = local.clone().slice([0..n_voxels, 0..1]).reshape([n_voxels]);
+        let ly = local.clone().slice([0..n_voxels, 1..2]).reshape([n_voxels]);
+        let lz = local.slice([0..n_voxels, 2..3]).reshape([n_voxels]);
+
+        let focal = camera.focal(*img_size);
+        let center = camera.center(*img_size);
+        let safe_z = lz.clone().clamp_min(1e-6);
+        let px = lx / safe_z.clone() * focal.x + center.x;
+        let py = ly / safe_z * focal.y + center.y;
+
+        let in_view = lz.clone().greater_elem(0.01).float()
+            * px.clone().greater_equal_elem(0.0).float()
+            * px.clone().lower_elem(w as f32).float()
+            * py.clone().greater_equal_elem(0.0).float()
+            * py.clone().lower_elem(h as f32).float();
+
+        let pixel = py.floor().clamp(0.0, h as f32 - 1.0) * w as f32
+            + px.floor().clamp(0.0, w as f32 - 1.0);
+        let pixel = pixel.int();
+
+        let voxel_depth = depth.select(0, pixel.clone());
+        let voxel_alpha = alpha.select(0, pixel.clone());
+        let voxel_color = color.select(0, pixel);
+
+        let sdf = voxel_depth - lz;
+        let valid = in_view
+            * voxel_alpha.greater_elem(config.mesh_min_alpha).float()
+            * sdf.clone().greater_elem(-trunc).float();
+
+        sdf_sum = sdf_sum + (sdf / trunc).clamp_max(1.0) * valid.clone();
+        color_sum = color_sum + voxel_color * valid.clone().reshape([n_voxels, 1]);
+        weight_sum = weight_sum + valid;
+    }
+
+    let weights = read_floats(weight_sum.clone()).await?;
+    let norm_weights = weight_sum.clamp_min(1e-6);
+    let sdf = read_floats(sdf_sum / norm_weights.clone()).await?;
+    let colors = read_floats(color_sum / norm_weights.reshape([n_voxels, 1])).await?;
+    let colors: Vec<_> = colors
+        .chunks_exact(3)
+        .map(|c| Vec3::new(c[0], c[1], c[2]).clamp(Vec3::ZERO, Vec3::ONE))
+        .collect();
+
+    Ok(extract_surface(&grid, &sdf, &weights, &colors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn robust_bounds_ignore_outliers() {
+        assert!(robust_bounds(&[]).is_err());
+
+        let mut means: Vec<f32> = (0..200)
+            .flat_map(|i| [i as f32 / 200.0, 0.0, 0.0])
+            .collect();
+        means.extend([1000.0, 0.0, 0.0]);
+        let bounds = robust_bounds(&means).expect("Non empty means");
+        assert!(bounds.max().x < 1.0);
+        assert!(bounds.min().x < 0.01);
+    }
+}
@@ -0,0 +1,148 @@
+use anyhow::anyhow;
+use brush_render::{camera::Camera, gaussian_splats::Splats, Backend};
+use glam::{Mat3, Quat, Vec3};
+
+// Whether a point is inside the view frustum of a camera.
+pub(crate) fn in_frustum(camera: &Camera, world_to_local: &glam::Affine3A, point: Vec3) -> bool {
+    let local = world_to_local.transform_point3(point);
+    if local.z < 0.01 {
+        return false;
+    }
+    let tan_x = (camera.fov_x * 0.5).tan() as f32;
+    let tan_y = (camera.fov_y * 0.5).tan() as f32;
+    (local.x / local.z).abs() <= tan_x && (local.y / local.z).abs() <= tan_y
+}
+
+/// Estimate a normal for every splat.
+///
+/// The normal is the shortest axis of the splat. As splats are symmetric, the sign is chosen so
+/// the normal points towards the cameras that can see the splat, weighted by how close they are.
+/// Splats that no camera sees point towards the closest camera.
+pub async fn estimate_normals<B: Backend>(
+    splats: &Splats<B>,
+    cameras: &[Camera],
+) -> anyhow::Result<Vec<Vec3>> {
+    let read_err = |e| anyhow!("Failed to read data from splat {e:?}");
+    let means: Vec<f32> = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let log_scales: Vec<f32> = splats
+        .log_scales
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let rotations: Vec<f32> = splats
+        .rotations_normed()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+
+    let world_to_local: Vec<_> = cameras.iter().map(|c| c.world_to_local()).collect();
+
+    let normals = (0..splats.num_splats())
+        .map(|i| {
+            let mean = Vec3::from_slice(&means[i * 3..i * 3 + 3]);
+            let rot = Mat3::from_quat(Quat::from_xyzw(
+                rotations[i * 4 + 1],
+                rotations[i * 4 + 2],
+                rotations[i * 4 + 3],
+                rotations[i * 4],
+            ));
+
+            let scale = &log_scales[i * 3..i * 3 + 3];
+            let shortest = (0..3)
+                .min_by(|&a, &b| scale[a].total_cmp(&scale[b]))
+                .unwrap_or(2);
+            let normal = rot.col(shortest);
+
+            let mut facing = 0.0;
+            let mut closest = (f32::MAX, 0.0);
+            for (camera, w2l) in cameras.iter().zip(&world_to_local) {
+                let to_cam = camera.position - mean;
+                let dist = to_cam.length().max(1e-6);
+                let alignment = normal.dot(to_cam) / dist;
+
+                if in_frustum(camera, w2l, mean) {
+                    facing += alignment / dist;
+                }
+                if dist < closest.0 {
+                    closest = (dist, alignment);
+                }
+            }
+
+            if facing == 0.0 {
+                facing = closest.1;
+            }
+
+            if facing < 0.0 {
+                -normal
+            } else {
+                normal
+            }
+        })
+        .collect();
+
+    Ok(normals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::backend::{wgpu::WgpuDevice, Wgpu};
+
+    #[tokio::test]
+    async fn normals_face_the_camera() {
+        let device = WgpuDevice::DefaultDevice;
+        // A splat flat in the xy plane at the origin, and one flat in the xz plane below it.
+        let means = [Vec3::ZERO, Vec3::new(0.0, -1.0, 0.0)];
+        let rotations = [
+            Quat::IDENTITY,
+            Quat::from_rotation_x(std::f32::consts::FRAC_PI_2),
+        ];
+        let log_scales = [Vec3::new(0.0, 0.0, -5.0); 2];
+        let splats = Splats::<Wgpu>::from_raw(
+            &means,
+            Some(&rotations),
+            Some(&log_scales),
+            None,
+            None,
+            &device,
+        );
+
+        // Looking along +z from in front of the splats.
+        let front = Camera::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            Quat::IDENTITY,
+            1.0,
+            1.0,
+            glam::vec2(0.5, 0.5),
+        );
+        let normals = estimate_normals(&splats, &[front])
+            .await
+            .expect("Failed to estimate normals");
+        assert!(normals[0].abs_diff_eq(Vec3::NEG_Z, 1e-5), "{}", normals[0]);
+        // The camera is above the second splat.
+        assert!(normals[1].abs_diff_eq(Vec3::Y, 1e-5), "{}", normals[1]);
+
+        // Looking along -z from behind the splats flips the first normal.
+        let back = Camera::new(
+            Vec3::new(0.0, 0.0, 3.0),
+            Quat::from_rotation_y(std::f32::consts::PI),
+            1.0,
+            1.0,
+            glam::vec2(0.5, 0.5),
+        );
+        let normals = estimate_normals(&splats, &[back])
+            .await
+            .expect("Failed to estimate normals");
+        assert!(normals[0].abs_diff_eq(Vec3::Z, 1e-5), "{}", normals[0]);
+        assert!(normals[1].abs_diff_eq(Vec3::Y, 1e-5), "{}", normals[1]);
+    }
+}
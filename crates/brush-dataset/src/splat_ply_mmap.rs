@@ -0,0 +1,349 @@
+//! Fast ply export in the layout of the original 3D Gaussian Splatting code.
+//!
+//! The file is sized up front and mapped into memory. Splats are read back from the GPU a chunk
+//! at a time, and packed straight into their place in the file by a few threads, without
+//! building a struct per splat like [`crate::splat_export::write_ply_chunked`] does.
+//!
+//! The properties are in the order of the INRIA code, so tools that read the bytes of a splat at
+//! fixed offsets load these files too: `x, y, z, nx, ny, nz, f_dc_0..2, f_rest_*, opacity,
+//! scale_0..2, rot_0..3`. Scales are stored as logs, opacities before the sigmoid, rotations as
+//! normalized `w, x, y, z` quaternions, and the `f_rest` coefficients grouped per color channel.
+//! The normals are zero. Motion of dynamic splats and labels are written after these.
+
+use std::fs::OpenOptions;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::anyhow;
+use brush_render::{gaussian_splats::Splats, Backend};
+use burn::tensor::{FloatDType, Tensor};
+use memmap2::MmapMut;
+
+/// Names of the ply properties of a splat with `sh_coeffs_num` SH coefficients per channel, in
+/// the order of the INRIA code.
+pub fn inria_property_names(sh_coeffs_num: usize) -> Vec<String> {
+    let mut names: Vec<String> = [
+        "x", "y", "z", "nx", "ny", "nz", "f_dc_0", "f_dc_1", "f_dc_2",
+    ]
+    .into_iter()
+    .map(str::to_owned)
+    .collect();
+    names.extend((0..(sh_coeffs_num - 1) * 3).map(|i| format!("f_rest_{i}")));
+    names.extend(
+        [
+            "opacity", "scale_0", "scale_1", "scale_2", "rot_0", "rot_1", "rot_2", "rot_3",
+        ]
+        .map(str::to_owned),
+    );
+    names
+}
+
+const TEMPORAL_PROPERTY_NAMES: [&str; 5] = [
+    "velocity_0",
+    "velocity_1",
+    "velocity_2",
+    "time",
+    "log_duration",
+];
+
+fn ply_header(num_splats: usize, property_names: &[String], has_labels: bool) -> String {
+    let mut header = String::from("ply\nformat binary_little_endian 1.0\n");
+    header.push_str("comment Exported from Brush\ncomment Vertical axis: y\n");
+    header.push_str(&format!("element vertex {num_splats}\n"));
+    for name in property_names {
+        header.push_str(&format!("property float {name}\n"));
+    }
+    if has_labels {
+        header.push_str("property uint label\n");
+    }
+    header.push_str("end_header\n");
+    header
+}
+
+// The parameters of a chunk of splats, as read back from the GPU.
+struct ChunkData {
+    means: Vec<f32>,
+    log_scales: Vec<f32>,
+    rotations: Vec<f32>,
+    opacities: Vec<f32>,
+    // Permuted to `[n, channel, coeffs]`.
+    sh_coeffs: Vec<f32>,
+    sh_coeffs_num: usize,
+    // Velocities, times and log durations.
+    temporal: Option<(Vec<f32>, Vec<f32>, Vec<f32>)>,
+    labels: Option<Vec<i32>>,
+}
+
+async fn read_floats<B: Backend, const D: usize>(tensor: Tensor<B, D>) -> anyhow::Result<Vec<f32>> {
+    tensor
+        .cast(FloatDType::F32)
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(|e| anyhow!("Failed to read data from splat {e:?}"))
+}
+
+async fn read_chunk<B: Backend>(
+    splats: &Splats<B>,
+    range: Range<usize>,
+) -> anyhow::Result<ChunkData> {
+    let temporal = match &splats.temporal {
+        Some(t) => Some((
+            read_floats(t.velocities.val().slice([range.clone()])).await?,
+            read_floats(t.times.val().slice([range.clone()])).await?,
+            read_floats(t.log_durations.val().slice([range.clone()])).await?,
+        )),
+        None => None,
+    };
+    let labels = match &splats.labels {
+        Some(labels) => Some(
+            labels
+                .clone()
+                .slice([range.clone()])
+                .into_data_async()
+                .await
+                .to_vec()
+                .map_err(|e| anyhow!("Failed to read labels {e:?}"))?,
+        ),
+        None => None,
+    };
+
+    Ok(ChunkData {
+        means: read_floats(splats.means.val().slice([range.clone()])).await?,
+        log_scales: read_floats(splats.log_scales.val().slice([range.clone()])).await?,
+        rotations: read_floats(splats.rotation.val().slice([range.clone()])).await?,
+        opacities: read_floats(splats.raw_opacity.val().slice([range.clone()])).await?,
+        sh_coeffs: read_floats(splats.sh_coeffs.val().slice([range]).permute([0, 2, 1])).await?,
+        sh_coeffs_num: splats.sh_coeffs.dims()[1],
+        temporal,
+        labels,
+    })
+}
+
+impl ChunkData {
+    // Write splat `i` to the bytes of its vertex.
+    fn pack(&self, i: usize, out: &mut [u8]) {
+        let mut offset = 0;
+        let mut put = |bytes: [u8; 4]| {
+            out[offset..offset + 4].copy_from_slice(&bytes);
+            offset += 4;
+        };
+
+        let n = self.sh_coeffs_num;
+        let sh = &self.sh_coeffs[i * n * 3..(i + 1) * n * 3];
+
+        self.means[i * 3..i * 3 + 3]
+            .iter()
+            .for_each(|v| put(v.to_le_bytes()));
+        // Normals.
+        (0..3).for_each(|_| put(0.0f32.to_le_bytes()));
+        (0..3).for_each(|c| put(sh[c * n].to_le_bytes()));
+        for c in 0..3 {
+            sh[c * n + 1..(c + 1) * n]
+                .iter()
+                .for_each(|v| put(v.to_le_bytes()));
+        }
+        put(self.opacities[i].to_le_bytes());
+        self.log_scales[i * 3..i * 3 + 3]
+            .iter()
+            .for_each(|v| put(v.to_le_bytes()));
+        self.rotations[i * 4..i * 4 + 4]
+            .iter()
+            .for_each(|v| put(v.to_le_bytes()));
+
+        if let Some((velocities, times, log_durations)) = &self.temporal {
+            velocities[i * 3..i * 3 + 3]
+                .iter()
+                .for_each(|v| put(v.to_le_bytes()));
+            put(times[i].to_le_bytes());
+            put(log_durations[i].to_le_bytes());
+        }
+        if let Some(labels) = &self.labels {
+            put((labels[i].max(0) as u32).to_le_bytes());
+        }
+    }
+
+    // Write all splats of the chunk, split over a few threads.
+    fn pack_all(&self, out: &mut [u8], stride: usize) {
+        let count = out.len() / stride;
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let per_thread = count.div_ceil(threads).max(1);
+
+        std::thread::scope(|scope| {
+            for (t, part) in out.chunks_mut(per_thread * stride).enumerate() {
+                scope.spawn(move || {
+                    for (j, vertex) in part.chunks_exact_mut(stride).enumerate() {
+                        self.pack(t * per_thread + j, vertex);
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// Write splats to a binary ply file at `path`, in the layout of the INRIA code. Only
+/// `chunk_size` splats are read back from the GPU at a time.
+///
+/// Splats loaded from the file with [`crate::splat_import::load_splat_from_ply`] are the same as
+/// the written splats, except for the rotations, which are normalized.
+pub async fn write_ply_mmap<B: Backend>(
+    splats: Splats<B>,
+    path: &Path,
+    chunk_size: usize,
+) -> anyhow::Result<()> {
+    let mut splats = splats;
+    splats.norm_rotations();
+
+    let n = splats.num_splats();
+    let mut property_names = inria_property_names(splats.sh_coeffs.dims()[1]);
+    if splats.temporal.is_some() {
+        property_names.extend(TEMPORAL_PROPERTY_NAMES.map(str::to_owned));
+    }
+    let has_labels = splats.labels.is_some();
+    let header = ply_header(n, &property_names, has_labels);
+    let stride = (property_names.len() + usize::from(has_labels)) * 4;
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    file.set_len((header.len() + n * stride) as u64)?;
+
+    // SAFETY: The file was just created for this export, and nothing else writes to it or
+    // truncates it while it's mapped.
+    let mut map = unsafe { MmapMut::map_mut(&file)? };
+    map[..header.len()].copy_from_slice(header.as_bytes());
+
+    let chunk_size = chunk_size.max(1);
+    for start in (0..n).step_by(chunk_size) {
+        let end = (start + chunk_size).min(n);
+        let chunk = read_chunk(&splats, start..end).await?;
+        let out = &mut map[header.len() + start * stride..header.len() + end * stride];
+        chunk.pack_all(out, stride);
+    }
+
+    map.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splat_import::load_splat_from_ply;
+    use brush_render::gaussian_splats::TemporalAttributes;
+    use burn::{
+        backend::{wgpu::WgpuDevice, Wgpu},
+        tensor::{Distribution, Int},
+    };
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn header_has_inria_property_order() {
+        let names = inria_property_names(4);
+        let header = ply_header(7, &names, false);
+        let properties: Vec<_> = header
+            .lines()
+            .filter_map(|l| l.strip_prefix("property float "))
+            .collect();
+
+        let mut expected = vec![
+            "x", "y", "z", "nx", "ny", "nz", "f_dc_0", "f_dc_1", "f_dc_2",
+        ];
+        let rest: Vec<_> = (0..9).map(|i| format!("f_rest_{i}")).collect();
+        expected.extend(rest.iter().map(String::as_str));
+        expected.extend([
+            "opacity", "scale_0", "scale_1", "scale_2", "rot_0", "rot_1", "rot_2", "rot_3",
+        ]);
+        assert_eq!(properties, expected);
+        assert!(header.contains("element vertex 7\n"));
+        assert!(header.ends_with("end_header\n"));
+    }
+
+    fn assert_close<const D: usize>(a: Tensor<Wgpu, D>, b: Tensor<Wgpu, D>) {
+        assert_eq!(a.dims(), b.dims());
+        let a: Vec<f32> = a.into_data().to_vec().expect("Wrong type");
+        let b: Vec<f32> = b.into_data().to_vec().expect("Wrong type");
+        for (a, b) in a.iter().zip(&b) {
+            assert!((a - b).abs() < 1e-6, "{a} != {b}");
+        }
+    }
+
+    async fn round_trip(splats: Splats<Wgpu>, chunk_size: usize) -> Splats<Wgpu> {
+        let path =
+            std::env::temp_dir().join(format!("brush_ply_{:016x}.ply", rand::random::<u64>()));
+        write_ply_mmap(splats, &path, chunk_size)
+            .await
+            .expect("Failed to export");
+        let file = tokio::fs::File::open(&path).await.expect("Failed to open");
+        let stream = load_splat_from_ply(file, None, WgpuDevice::DefaultDevice);
+        let mut stream = std::pin::pin!(stream);
+        let mut last = None;
+        while let Some(message) = stream.next().await {
+            last = Some(message.expect("Failed to import").splats);
+        }
+        let _ = std::fs::remove_file(&path);
+        last.expect("No splats imported")
+    }
+
+    fn random_splats(n: usize, sh_coeffs_num: usize) -> Splats<Wgpu> {
+        let device = WgpuDevice::DefaultDevice;
+        let random = Distribution::Normal(0.0, 1.0);
+        let mut splats = Splats::from_tensor_data(
+            Tensor::random([n, 3], random, &device),
+            Tensor::random([n, 4], random, &device),
+            Tensor::random([n, 3], random, &device),
+            Tensor::random([n, sh_coeffs_num, 3], random, &device),
+            Tensor::random([n], random, &device),
+        );
+        splats.norm_rotations();
+        splats
+    }
+
+    #[tokio::test]
+    async fn round_trips_through_importer() {
+        // Sizes that don't fill the last chunk.
+        for (n, sh_coeffs_num, chunk_size) in [(1000, 16, 256), (37, 4, 1000), (5, 1, 2)] {
+            let splats = random_splats(n, sh_coeffs_num);
+            let loaded = round_trip(splats.clone(), chunk_size).await;
+
+            assert_close(splats.means.val(), loaded.means.val());
+            assert_close(splats.rotation.val(), loaded.rotation.val());
+            assert_close(splats.log_scales.val(), loaded.log_scales.val());
+            assert_close(splats.raw_opacity.val(), loaded.raw_opacity.val());
+            assert_close(splats.sh_coeffs.val(), loaded.sh_coeffs.val());
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trips_labels_and_motion() {
+        let device = WgpuDevice::DefaultDevice;
+        let n = 100;
+        let random = Distribution::Normal(0.0, 1.0);
+        let labels = Tensor::<Wgpu, 1, Int>::arange(0..n as i64, &device);
+        let mut splats = random_splats(n, 4).with_labels(labels.clone());
+        splats.temporal = Some(TemporalAttributes::new(
+            Tensor::random([n, 3], random, &device),
+            Tensor::random([n], random, &device),
+            Tensor::random([n], random, &device),
+        ));
+        let loaded = round_trip(splats.clone(), 64).await;
+
+        let loaded_labels = loaded.labels.clone().expect("Labels were exported");
+        assert_eq!(
+            loaded_labels.into_data().to_vec::<i32>(),
+            labels.into_data().to_vec::<i32>()
+        );
+        let (temporal, loaded_temporal) = (
+            splats.temporal.expect("Motion was set"),
+            loaded.temporal.expect("Motion was exported"),
+        );
+        assert_close(temporal.velocities.val(), loaded_temporal.velocities.val());
+        assert_close(temporal.times.val(), loaded_temporal.times.val());
+        assert_close(
+            temporal.log_durations.val(),
+            loaded_temporal.log_durations.val(),
+        );
+    }
+}
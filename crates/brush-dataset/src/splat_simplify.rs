@@ -0,0 +1,678 @@
+use anyhow::anyhow;
+use ball_tree::BallTree;
+use brush_render::{
+    gaussian_splats::{Splats, TemporalAttributes},
+    Backend,
+};
+use burn::{
+    config::Config,
+    tensor::{Tensor, TensorData},
+};
+use clap::Args;
+use glam::{IVec3, Mat3, Quat, Vec3};
+use std::collections::HashMap;
+use tokio_with_wasm::alias as tokio_wasm;
+
+#[derive(Config, Debug, Args)]
+pub struct SimplifyConfig {
+    /// Number of splats to reduce the model to.
+    #[arg(long, help_heading = "Simplify Options", default_value = "500000")]
+    #[config(default = 500000)]
+    pub target_count: usize,
+    /// Number of nearest neighbours considered as merge candidates for each splat.
+    #[arg(long, help_heading = "Simplify Options", default_value = "8")]
+    #[config(default = 8)]
+    pub neighbours: usize,
+    /// How much a difference in color counts against merging two splats, relative to
+    /// their distance.
+    #[arg(long, help_heading = "Simplify Options", default_value = "4.0")]
+    #[config(default = 4.0)]
+    pub color_weight: f32,
+}
+
+#[derive(Clone)]
+struct CpuSplat {
+    mean: Vec3,
+    cov: Mat3,
+    opacity: f32,
+    // Interleaved [coeffs, channels] like the sh_coeffs tensor.
+    sh: Vec<f32>,
+    label: Option<i32>,
+    motion: Option<Motion>,
+}
+
+// The temporal attributes of a dynamic splat, see `TemporalAttributes`.
+#[derive(Clone, Copy, Default)]
+struct Motion {
+    velocity: Vec3,
+    time: f32,
+    log_duration: f32,
+}
+
+impl CpuSplat {
+    // Rough measure of how much this splat contributes to renders.
+    fn weight(&self) -> f32 {
+        let volume = self.cov.determinant().max(0.0).sqrt();
+        self.opacity * volume.cbrt().powi(2).max(1e-12)
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn inverse_sigmoid(x: f32) -> f32 {
+    (x / (1.0 - x)).ln()
+}
+
+// Eigen decomposition of a symmetric matrix with cyclic Jacobi rotations. Returns the
+// eigenvalues and a matrix with the eigenvectors as columns.
+fn symmetric_eigen(mat: Mat3) -> (Vec3, Mat3) {
+    let mut a = mat.to_cols_array_2d();
+    let mut v = Mat3::IDENTITY.to_cols_array_2d();
+
+    for _ in 0..32 {
+        let off_diag = a[0][1].powi(2) + a[0][2].powi(2) + a[1][2].powi(2);
+        if off_diag < 1e-24 {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-30 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            for k in 0..3 {
+                let (akp, akq) = (a[k][p], a[k][q]);
+                a[k][p] = c * akp - s * akq;
+                a[k][q] = s * akp + c * akq;
+            }
+            for k in 0..3 {
+                let (apk, aqk) = (a[p][k], a[q][k]);
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for vk in &mut v {
+                let (vkp, vkq) = (vk[p], vk[q]);
+                vk[p] = c * vkp - s * vkq;
+                vk[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+
+    // The arrays are indexed [row][col] while glam expects columns.
+    let values = Vec3::new(a[0][0], a[1][1], a[2][2]);
+    (values, Mat3::from_cols_array_2d(&v).transpose())
+}
+
+// Moment match a group of gaussians with a single gaussian.
+fn merge_group(group: &[&CpuSplat]) -> CpuSplat {
+    let weights: Vec<f32> = group.iter().map(|s| s.weight()).collect();
+    let total: f32 = weights.iter().sum();
+    let fracs: Vec<f32> = weights.iter().map(|w| w / total).collect();
+
+    let mean = group
+        .iter()
+        .zip(&fracs)
+        .map(|(s, f)| s.mean * *f)
+        .sum::<Vec3>();
+    let outer = |d: Vec3| Mat3::from_cols(d * d.x, d * d.y, d * d.z);
+    let cov = group.iter().zip(&fracs).fold(Mat3::ZERO, |acc, (s, f)| {
+        acc + (s.cov + outer(s.mean - mean)) * *f
+    });
+
+    let mut sh = vec![0.0; group[0].sh.len()];
+    for (s, f) in group.iter().zip(&fracs) {
+        for (acc, c) in sh.iter_mut().zip(&s.sh) {
+            *acc += c * f;
+        }
+    }
+
+    // The splats together occlude about as much as the union of them.
+    let transmittance: f32 = group.iter().map(|s| 1.0 - s.opacity).product();
+
+    // Keep the label that contributes the most to the group. Either all splats have a label
+    // or none do.
+    let label = group[0].label.map(|_| {
+        let mut votes: Vec<(i32, f32)> = vec![];
+        for (s, f) in group.iter().zip(&fracs) {
+            let label = s.label.unwrap_or_default();
+            match votes.iter_mut().find(|(l, _)| *l == label) {
+                Some((_, vote)) => *vote += f,
+                None => votes.push((label, *f)),
+            }
+        }
+        votes
+            .into_iter()
+            .reduce(|best, v| if v.1 > best.1 { v } else { best })
+            .expect("Groups are never empty")
+            .0
+    });
+
+    let motion = group[0].motion.map(|_| {
+        group
+            .iter()
+            .zip(&fracs)
+            .fold(Motion::default(), |acc, (s, f)| {
+                let m = s.motion.unwrap_or_default();
+                Motion {
+                    velocity: acc.velocity + m.velocity * *f,
+                    time: acc.time + m.time * f,
+                    log_duration: acc.log_duration + m.log_duration * f,
+                }
+            })
+    });
+
+    CpuSplat {
+        mean,
+        cov,
+        opacity: (1.0 - transmittance).min(0.999),
+        sh,
+        label,
+        motion,
+    }
+}
+
+fn merge_cost(a: &CpuSplat, b: &CpuSplat, color_weight: f32) -> f32 {
+    let (wa, wb) = (a.weight(), b.weight());
+    let delta = a.mean - b.mean;
+    let cov = a.cov + b.cov + Mat3::from_diagonal(Vec3::splat(1e-8));
+    let mahalanobis = delta.dot(cov.inverse() * delta);
+    let color_diff: f32 = a.sh[..3]
+        .iter()
+        .zip(&b.sh[..3])
+        .map(|(ca, cb)| (ca - cb).powi(2))
+        .sum();
+
+    // Merging two unimportant splats is cheap, even when they're further apart.
+    wa * wb / (wa + wb) * (mahalanobis + color_weight * color_diff)
+}
+
+async fn read_cpu_splats<B: Backend>(splats: &Splats<B>) -> anyhow::Result<Vec<CpuSplat>> {
+    let n_coeffs = splats.sh_coeffs.dims()[1];
+
+    let read_err = |e| anyhow!("Failed to read data from splat {e:?}");
+    let means: Vec<f32> = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let log_scales: Vec<f32> = splats
+        .log_scales
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let rotations: Vec<f32> = splats
+        .rotations_normed()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let opacities: Vec<f32> = splats
+        .raw_opacity
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let sh_coeffs: Vec<f32> = splats
+        .sh_coeffs
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let labels: Option<Vec<i32>> = match splats.labels.clone() {
+        Some(labels) => Some(labels.into_data_async().await.to_vec().map_err(read_err)?),
+        None => None,
+    };
+    let temporal: Option<[Vec<f32>; 3]> = match &splats.temporal {
+        Some(temporal) => Some([
+            temporal
+                .velocities
+                .val()
+                .into_data_async()
+                .await
+                .to_vec()
+                .map_err(read_err)?,
+            temporal
+                .times
+                .val()
+                .into_data_async()
+                .await
+                .to_vec()
+                .map_err(read_err)?,
+            temporal
+                .log_durations
+                .val()
+                .into_data_async()
+                .await
+                .to_vec()
+                .map_err(read_err)?,
+        ]),
+        None => None,
+    };
+
+    let cpu_splats = (0..splats.num_splats())
+        .map(|i| {
+            let rot = Quat::from_xyzw(
+                rotations[i * 4 + 1],
+                rotations[i * 4 + 2],
+                rotations[i * 4 + 3],
+                rotations[i * 4],
+            );
+            let rot = Mat3::from_quat(rot);
+            let scale = Vec3::new(
+                log_scales[i * 3].exp(),
+                log_scales[i * 3 + 1].exp(),
+                log_scales[i * 3 + 2].exp(),
+            );
+            let scale_sq = Mat3::from_diagonal(scale * scale);
+
+            CpuSplat {
+                mean: Vec3::from_slice(&means[i * 3..i * 3 + 3]),
+                cov: rot * scale_sq * rot.transpose(),
+                opacity: sigmoid(opacities[i]),
+                sh: sh_coeffs[i * n_coeffs * 3..(i + 1) * n_coeffs * 3].to_vec(),
+                label: labels.as_ref().map(|l| l[i]),
+                motion: temporal
+                    .as_ref()
+                    .map(|[velocities, times, log_durations]| Motion {
+                        velocity: Vec3::from_slice(&velocities[i * 3..i * 3 + 3]),
+                        time: times[i],
+                        log_duration: log_durations[i],
+                    }),
+            }
+        })
+        .collect();
+
+    Ok(cpu_splats)
+}
+
+/// Reduce the number of splats to a target count by repeatedly merging nearby splats with a
+/// similar shape and color. Merged splats are moment matched, so the overall shape and color
+/// of the scene is preserved as much as possible. Merged splats keep the label that contributes
+/// the most, and the average motion of dynamic splats.
+pub async fn simplify_splats<B: Backend>(
+    splats: Splats<B>,
+    config: &SimplifyConfig,
+) -> anyhow::Result<Splats<B>> {
+    let device = splats.means.val().device();
+    let mut cur = read_cpu_splats(&splats).await?;
+
+    while cur.len() > config.target_count {
+        let to_merge = cur.len() - config.target_count;
+
+        let tree_pos: Vec<[f64; 3]> = cur
+            .iter()
+            .map(|s| [s.mean.x as f64, s.mean.y as f64, s.mean.z as f64])
+            .collect();
+        let indices: Vec<usize> = (0..cur.len()).collect();
+        let tree = BallTree::new(tree_pos.clone(), indices);
+
+        // Find the cheapest merge partner for every splat.
+        let mut candidates = Vec::with_capacity(cur.len());
+        for (i, pos) in tree_pos.iter().enumerate() {
+            if i % 10000 == 0 {
+                tokio_wasm::task::yield_now().await;
+            }
+
+            let best = tree
+                .query()
+                .nn(pos)
+                .take(config.neighbours + 1)
+                .filter(|(_, _, j)| **j != i)
+                .map(|(_, _, &j)| (merge_cost(&cur[i], &cur[j], config.color_weight), j))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+
+            if let Some((cost, j)) = best {
+                candidates.push((cost, i, j));
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Greedily merge the cheapest pairs. Every splat is merged at most once per pass.
+        let mut merged = vec![false; cur.len()];
+        let mut next = Vec::with_capacity(cur.len());
+        for (_, i, j) in candidates {
+            if next.len() >= to_merge {
+                break;
+            }
+            if merged[i] || merged[j] {
+                continue;
+            }
+            merged[i] = true;
+            merged[j] = true;
+            next.push(merge_group(&[&cur[i], &cur[j]]));
+        }
+
+        if next.is_empty() {
+            log::warn!("Failed to simplify splats further than {}", cur.len());
+            break;
+        }
+
+        next.extend(
+            cur.into_iter()
+                .zip(merged)
+                .filter(|(_, merged)| !merged)
+                .map(|(s, _)| s),
+        );
+        cur = next;
+    }
+
+    Ok(cpu_splats_to_splats(cur, &device))
+}
+
+/// How the splats within a single voxel are reduced to one splat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VoxelDownsampleMode {
+    /// Keep the splat that contributes the most to renders.
+    Representative,
+    /// Moment match all splats in the voxel with a single splat.
+    #[default]
+    Merge,
+}
+
+/// Downsample splats on a regular grid, leaving at most one splat per occupied voxel of size
+/// `voxel_size`.
+pub async fn voxel_downsample<B: Backend>(
+    splats: Splats<B>,
+    voxel_size: f32,
+    mode: VoxelDownsampleMode,
+) -> anyhow::Result<Splats<B>> {
+    anyhow::ensure!(voxel_size > 0.0, "Voxel size must be positive");
+
+    let device = splats.means.val().device();
+    let cur = read_cpu_splats(&splats).await?;
+
+    // Group splats by voxel, keeping the order voxels are first encountered in, so the
+    // output is deterministic.
+    let mut voxels: HashMap<IVec3, usize> = HashMap::new();
+    let mut groups: Vec<Vec<&CpuSplat>> = vec![];
+    for splat in &cur {
+        let key = (splat.mean / voxel_size).floor().as_ivec3();
+        let group = *voxels.entry(key).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[group].push(splat);
+    }
+
+    let downsampled = groups
+        .into_iter()
+        .map(|group| match mode {
+            VoxelDownsampleMode::Representative => group
+                .into_iter()
+                .max_by(|a, b| a.weight().total_cmp(&b.weight()))
+                .expect("Voxel groups are never empty")
+                .clone(),
+            VoxelDownsampleMode::Merge => merge_group(&group),
+        })
+        .collect();
+
+    Ok(cpu_splats_to_splats(downsampled, &device))
+}
+
+fn cpu_splats_to_splats<B: Backend>(cur: Vec<CpuSplat>, device: &B::Device) -> Splats<B> {
+    let mut means = Vec::with_capacity(cur.len());
+    let mut rotations = Vec::with_capacity(cur.len());
+    let mut log_scales = Vec::with_capacity(cur.len());
+    let mut opacities = Vec::with_capacity(cur.len());
+    let mut sh_coeffs = Vec::with_capacity(cur.len() * cur.first().map_or(0, |s| s.sh.len()));
+    let labels: Option<Vec<i32>> = cur.iter().map(|s| s.label).collect();
+    let motions: Option<Vec<Motion>> = cur.iter().map(|s| s.motion).collect();
+
+    for splat in cur {
+        let (values, mut vecs) = symmetric_eigen(splat.cov);
+        // Make sure the eigenvectors form a proper rotation.
+        if vecs.determinant() < 0.0 {
+            vecs.z_axis = -vecs.z_axis;
+        }
+
+        means.push(splat.mean);
+        rotations.push(Quat::from_mat3(&vecs).normalize());
+        let log_scale = |v: f32| v.max(1e-16).sqrt().ln();
+        log_scales.push(Vec3::new(
+            log_scale(values.x),
+            log_scale(values.y),
+            log_scale(values.z),
+        ));
+        opacities.push(inverse_sigmoid(splat.opacity.clamp(1e-6, 0.999)));
+        sh_coeffs.extend(splat.sh);
+    }
+
+    let n = means.len();
+    let splats = Splats::from_raw(
+        &means,
+        Some(&rotations),
+        Some(&log_scales),
+        Some(&sh_coeffs),
+        Some(&opacities),
+        device,
+    );
+    let splats = match labels {
+        Some(labels) if n > 0 => {
+            splats.with_labels(Tensor::from_data(TensorData::new(labels, [n]), device))
+        }
+        _ => splats,
+    };
+    match motions {
+        Some(motions) if n > 0 => {
+            let velocities: Vec<f32> = motions.iter().flat_map(|m| m.velocity.to_array()).collect();
+            let times: Vec<f32> = motions.iter().map(|m| m.time).collect();
+            let log_durations: Vec<f32> = motions.iter().map(|m| m.log_duration).collect();
+            splats.with_temporal(TemporalAttributes::new(
+                Tensor::from_data(TensorData::new(velocities, [n, 3]), device),
+                Tensor::from_data(TensorData::new(times, [n]), device),
+                Tensor::from_data(TensorData::new(log_durations, [n]), device),
+            ))
+        }
+        _ => splats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::backend::{wgpu::WgpuDevice, Wgpu};
+    use rand::{Rng, SeedableRng};
+
+    fn splat(mean: Vec3, scale: f32, opacity: f32, color: f32) -> CpuSplat {
+        CpuSplat {
+            mean,
+            cov: Mat3::from_diagonal(Vec3::splat(scale * scale)),
+            opacity,
+            sh: vec![color; 3],
+            label: None,
+            motion: None,
+        }
+    }
+
+    #[test]
+    fn eigen_decomposition_reconstructs() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        for _ in 0..16 {
+            let rot = Mat3::from_quat(
+                Quat::from_xyzw(rng.gen(), rng.gen(), rng.gen(), rng.gen()).normalize(),
+            );
+            let scale = Vec3::new(rng.gen(), rng.gen(), rng.gen()) + 0.1;
+            let mat = rot * Mat3::from_diagonal(scale) * rot.transpose();
+
+            let (values, vecs) = symmetric_eigen(mat);
+            let rebuilt = vecs * Mat3::from_diagonal(values) * vecs.transpose();
+            assert!(rebuilt.abs_diff_eq(mat, 1e-4), "{rebuilt} != {mat}");
+            assert!((vecs.transpose() * vecs).abs_diff_eq(Mat3::IDENTITY, 1e-4));
+        }
+    }
+
+    #[test]
+    fn merge_matches_moments() {
+        let a = splat(Vec3::new(-1.0, 0.0, 0.0), 0.5, 0.5, 0.0);
+        let b = splat(Vec3::new(1.0, 0.0, 0.0), 0.5, 0.5, 1.0);
+        let merged = merge_group(&[&a, &b]);
+
+        assert!(merged.mean.abs_diff_eq(Vec3::ZERO, 1e-6));
+        // The spread of the means adds to the covariance along x only.
+        let expected = Mat3::from_diagonal(Vec3::new(1.25, 0.25, 0.25));
+        assert!(merged.cov.abs_diff_eq(expected, 1e-5), "{}", merged.cov);
+        assert!((merged.opacity - 0.75).abs() < 1e-6);
+        assert!(merged.sh.iter().all(|&c| (c - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn merge_keeps_main_label_and_average_motion() {
+        let motion = |x: f32, time: f32| Motion {
+            velocity: Vec3::new(x, 0.0, 0.0),
+            time,
+            log_duration: 0.0,
+        };
+        let mut a = splat(Vec3::ZERO, 0.5, 0.5, 0.0);
+        let mut b = splat(Vec3::ZERO, 0.5, 0.5, 0.0);
+        let mut c = splat(Vec3::ZERO, 1.0, 0.5, 0.0);
+        (a.label, a.motion) = (Some(1), Some(motion(1.0, 0.0)));
+        (b.label, b.motion) = (Some(1), Some(motion(1.0, 0.0)));
+        (c.label, c.motion) = (Some(2), Some(motion(-1.0, 1.0)));
+
+        // The bigger splat outweighs the two smaller ones.
+        let merged = merge_group(&[&a, &b, &c]);
+        assert_eq!(merged.label, Some(2));
+        let motion = merged.motion.expect("Merged splats are dynamic");
+        assert!((motion.velocity.x + 1.0 / 3.0).abs() < 1e-5);
+        assert!((motion.time - 2.0 / 3.0).abs() < 1e-5);
+
+        let merged = merge_group(&[&a, &b]);
+        assert_eq!(merged.label, Some(1));
+    }
+
+    #[test]
+    fn merge_cost_prefers_similar_splats() {
+        let a = splat(Vec3::ZERO, 0.1, 0.8, 0.0);
+        let near = splat(Vec3::new(0.1, 0.0, 0.0), 0.1, 0.8, 0.0);
+        let far = splat(Vec3::new(1.0, 0.0, 0.0), 0.1, 0.8, 0.0);
+        let other_color = splat(Vec3::new(0.1, 0.0, 0.0), 0.1, 0.8, 1.0);
+
+        assert!(merge_cost(&a, &near, 4.0) < merge_cost(&a, &far, 4.0));
+        assert!(merge_cost(&a, &near, 4.0) < merge_cost(&a, &other_color, 4.0));
+    }
+
+    #[tokio::test]
+    async fn simplifies_to_target_count() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let means: Vec<Vec3> = (0..64)
+            .map(|_| Vec3::new(rng.gen(), rng.gen(), rng.gen()))
+            .collect();
+        let rotations = vec![Quat::IDENTITY; means.len()];
+        let log_scales = vec![Vec3::splat(-3.0); means.len()];
+        let splats = Splats::<Wgpu>::from_raw(
+            &means,
+            Some(&rotations),
+            Some(&log_scales),
+            None,
+            None,
+            &device,
+        );
+
+        let config = SimplifyConfig::new().with_target_count(16);
+        let simplified = simplify_splats(splats, &config)
+            .await
+            .expect("Failed to simplify");
+        assert_eq!(simplified.num_splats(), 16);
+
+        // Merged splats stay within the bounds of the originals.
+        let means: Vec<f32> = simplified
+            .means
+            .val()
+            .into_data()
+            .to_vec()
+            .expect("Wrong type");
+        assert!(means.iter().all(|&m| (0.0..=1.0).contains(&m)));
+    }
+
+    #[tokio::test]
+    async fn voxel_downsample_keeps_one_splat_per_voxel() {
+        let device = WgpuDevice::DefaultDevice;
+        // Two splats in the voxel at the origin, two in the voxel next to it along x, and one
+        // in a voxel on its own.
+        let means = [
+            Vec3::new(0.2, 0.2, 0.2),
+            Vec3::new(0.6, 0.2, 0.2),
+            Vec3::new(1.2, 0.5, 0.5),
+            Vec3::new(1.8, 0.5, 0.5),
+            Vec3::new(-0.5, 0.5, 0.5),
+        ];
+        let rotations = [Quat::IDENTITY; 5];
+        let log_scales = [
+            Vec3::splat(-3.0),
+            Vec3::splat(-3.0),
+            Vec3::splat(-2.0),
+            Vec3::splat(-3.0),
+            Vec3::splat(-3.0),
+        ];
+        let opacities = [2.0; 5];
+        let splats = Splats::<Wgpu>::from_raw(
+            &means,
+            Some(&rotations),
+            Some(&log_scales),
+            None,
+            Some(&opacities),
+            &device,
+        )
+        .with_labels(Tensor::from_data(
+            TensorData::new(vec![1, 1, 2, 3, 4], [5]),
+            &device,
+        ));
+        let read_means = |splats: &Splats<Wgpu>| -> Vec<f32> {
+            splats.means.val().into_data().to_vec().expect("Wrong type")
+        };
+        let read_labels = |splats: &Splats<Wgpu>| -> Vec<i32> {
+            let labels = splats.labels.clone().expect("Labels are kept");
+            labels.into_data().to_vec().expect("Wrong type")
+        };
+
+        let merged = voxel_downsample(splats.clone(), 1.0, VoxelDownsampleMode::Merge)
+            .await
+            .expect("Failed to downsample");
+        assert_eq!(merged.num_splats(), 3);
+        // The bigger splat outweighs the other splat of the second voxel.
+        assert_eq!(read_labels(&merged), [1, 2, 4]);
+        let merged = read_means(&merged);
+        // The first voxel has two equal splats, merged to their center.
+        assert!(Vec3::from_slice(&merged[0..3]).abs_diff_eq(Vec3::new(0.4, 0.2, 0.2), 1e-5));
+        assert!(Vec3::from_slice(&merged[6..9]).abs_diff_eq(means[4], 1e-5));
+
+        let kept = voxel_downsample(splats, 1.0, VoxelDownsampleMode::Representative)
+            .await
+            .expect("Failed to downsample");
+        assert_eq!(kept.num_splats(), 3);
+        assert_eq!(read_labels(&kept), [1, 2, 4]);
+        let kept = read_means(&kept);
+        // The bigger splat represents the second voxel.
+        assert!(Vec3::from_slice(&kept[3..6]).abs_diff_eq(means[2], 1e-5));
+        assert!(Vec3::from_slice(&kept[6..9]).abs_diff_eq(means[4], 1e-5));
+    }
+
+    #[tokio::test]
+    async fn voxel_size_must_be_positive() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = Splats::<Wgpu>::from_raw(
+            &[Vec3::ZERO],
+            Some(&[Quat::IDENTITY]),
+            Some(&[Vec3::splat(-3.0)]),
+            None,
+            None,
+            &device,
+        );
+        assert!(voxel_downsample(splats, 0.0, VoxelDownsampleMode::Merge)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Export to the `.spz` format, a compact splat format made for the web.
+//!
+//! An spz file is a gzip compressed stream, starting with a 16 byte header. The splat attributes
+//! follow as separate arrays: 24 bit fixed point positions, then 8 bit opacities, base colors,
+//! log scales, rotations, and SH coefficients.
+
+use std::io::Write;
+
+use anyhow::anyhow;
+use brush_render::{gaussian_splats::Splats, Backend};
+use flate2::{write::GzEncoder, Compression};
+use glam::Vec3;
+
+use crate::splat_export::read_splat_data;
+
+const SPZ_MAGIC: u32 = 0x5053_474e;
+const SPZ_VERSION: u32 = 2;
+// Positions are stored with this many bits after the decimal point.
+const FRACTIONAL_BITS: u8 = 12;
+// Scale of the base color coefficients.
+const COLOR_SCALE: f32 = 0.15;
+// Bits kept of the degree 1 SH coefficients, and of the higher degrees.
+const SH1_BITS: u32 = 5;
+const SH_REST_BITS: u32 = 4;
+
+// Spz uses a right-up-back coordinate system, where brush uses right-down-forward. Converting
+// between these is a rotation around x, which flips the sign of the SH basis functions that
+// are odd in y or z.
+const SH_FLIP_YZ: [f32; 15] = [
+    -1.0, -1.0, 1.0, // Degree 1: y, z, x.
+    -1.0, 1.0, 1.0, -1.0, 1.0, // Degree 2: xy, yz, zz, xz, xx - yy.
+    -1.0, 1.0, -1.0, -1.0, 1.0, -1.0, 1.0, // Degree 3.
+];
+
+fn to_u8(x: f32) -> u8 {
+    x.round().clamp(0.0, 255.0) as u8
+}
+
+fn quantize_sh(x: f32, bits: u32) -> u8 {
+    let bucket = 1 << (8 - bits);
+    let q = (x * 128.0).round() as i32 + 128;
+    let q = (q + bucket / 2) / bucket * bucket;
+    q.clamp(0, 255) as u8
+}
+
+fn flip_yz(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, -v.y, -v.z)
+}
+
+/// Serialize splats to an spz file. Spz supports at most SH degree 3.
+pub async fn splat_to_spz<B: Backend>(splats: Splats<B>) -> anyhow::Result<Vec<u8>> {
+    let mut splats = splats;
+    splats.norm_rotations();
+
+    let sh_degree = splats.sh_degree().min(3);
+    let splats = splats.with_sh_degree(sh_degree);
+    let sh_rest_num = splats.sh_coeffs.dims()[1] - 1;
+
+    let data = read_splat_data(splats)
+        .await
+        .map_err(|e| anyhow!("Failed to read data from splat {e:?}"))?;
+    let n = data.len();
+
+    let mut bytes = Vec::with_capacity(16 + n * (9 + 1 + 3 + 3 + 3 + sh_rest_num * 3));
+    bytes.extend(SPZ_MAGIC.to_le_bytes());
+    bytes.extend(SPZ_VERSION.to_le_bytes());
+    bytes.extend((n as u32).to_le_bytes());
+    bytes.extend([sh_degree as u8, FRACTIONAL_BITS, 0, 0]);
+
+    let fixed_scale = (1 << FRACTIONAL_BITS) as f32;
+    for splat in &data {
+        for v in flip_yz(splat.means).to_array() {
+            let fixed = (v * fixed_scale).round() as i32;
+            bytes.extend(&fixed.to_le_bytes()[..3]);
+        }
+    }
+    for splat in &data {
+        let opacity = 1.0 / (1.0 + (-splat.opacity).exp());
+        bytes.push(to_u8(opacity * 255.0));
+    }
+    for splat in &data {
+        for dc in splat.sh_dc {
+            bytes.push(to_u8(dc * COLOR_SCALE * 255.0 + 0.5 * 255.0));
+        }
+    }
+    for splat in &data {
+        for s in splat.log_scale.to_array() {
+            bytes.push(to_u8((s + 10.0) * 16.0));
+        }
+    }
+    for splat in &data {
+        let q = splat.rotation;
+        // Only xyz are stored, so the real part has to be positive.
+        let sign = if q.w < 0.0 { -1.0 } else { 1.0 };
+        for v in flip_yz(q.xyz() * sign).to_array() {
+            bytes.push(to_u8(v * 127.5 + 127.5));
+        }
+    }
+    for splat in &data {
+        // Ply stores the coefficients per channel, spz per coefficient.
+        for coeff in 0..sh_rest_num {
+            for channel in 0..3 {
+                let v = splat.sh_coeffs_rest[channel * sh_rest_num + coeff] * SH_FLIP_YZ[coeff];
+                let bits = if coeff < 3 { SH1_BITS } else { SH_REST_BITS };
+                bytes.push(quantize_sh(v, bits));
+            }
+        }
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&bytes)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brush_render::sh_rotation::rotate_sh_coeffs;
+    use burn::backend::{wgpu::WgpuDevice, Wgpu};
+    use flate2::read::GzDecoder;
+    use glam::Quat;
+    use std::io::Read;
+
+    // Read back the higher degree SH coefficients as stored, `[splats, coeffs, 3]`.
+    fn read_spz_sh(spz: &[u8]) -> Vec<f32> {
+        let mut bytes = vec![];
+        GzDecoder::new(spz)
+            .read_to_end(&mut bytes)
+            .expect("Invalid gzip");
+        assert_eq!(bytes[..4], SPZ_MAGIC.to_le_bytes());
+        let n = u32::from_le_bytes(bytes[8..12].try_into().expect("Short header")) as usize;
+        // Skip the positions, opacities, colors, scales and rotations.
+        let start = 16 + n * (9 + 1 + 3 + 3 + 3);
+        bytes[start..]
+            .iter()
+            .map(|&b| (b as f32 - 128.0) / 128.0)
+            .collect()
+    }
+
+    #[test]
+    fn quantize_sh_buckets() {
+        assert_eq!(quantize_sh(0.0, 8), 128);
+        assert_eq!(quantize_sh(10.0, 8), 255);
+        assert_eq!(quantize_sh(-10.0, 8), 0);
+        // With 4 bits, values snap to multiples of 16.
+        assert_eq!(quantize_sh(0.1, SH_REST_BITS) % 16, 0);
+        assert_eq!(quantize_sh(0.1, SH_REST_BITS), 144);
+    }
+
+    #[tokio::test]
+    async fn sh_round_trips_through_spz() {
+        let device = WgpuDevice::DefaultDevice;
+        let n = 4;
+        let n_coeffs = 16;
+        // Multiples of 1/8, which survive the 4 bit quantization exactly.
+        let coeffs: Vec<f32> = (0..n * n_coeffs * 3)
+            .map(|i| ((i * 7) % 13) as f32 / 8.0 - 0.75)
+            .collect();
+        let means = vec![Vec3::ZERO; n];
+        let splats = Splats::<Wgpu>::from_raw(&means, None, None, Some(&coeffs), None, &device);
+
+        let spz = splat_to_spz(splats).await.expect("Failed to export");
+        let stored = read_spz_sh(&spz);
+        assert_eq!(stored.len(), n * (n_coeffs - 1) * 3);
+
+        // Spz stores the SH rotated by half a turn around x, see `SH_FLIP_YZ`.
+        let mut rotated = coeffs.clone();
+        rotate_sh_coeffs(&mut rotated, Quat::from_rotation_x(std::f32::consts::PI), 3);
+
+        for splat in 0..n {
+            for coeff in 1..n_coeffs {
+                for channel in 0..3 {
+                    let orig = (splat * n_coeffs + coeff) * 3 + channel;
+                    let v = stored[(splat * (n_coeffs - 1) + coeff - 1) * 3 + channel];
+                    assert!(
+                        (v - rotated[orig]).abs() < 1e-4,
+                        "Coeff {coeff} not rotated"
+                    );
+                    // Flipping back on import gives the original coefficients.
+                    assert!((v * SH_FLIP_YZ[coeff - 1] - coeffs[orig]).abs() < 1e-4);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt::Write as _;
+use std::io::{Cursor, Write as _};
+
+use anyhow::anyhow;
+use brush_render::{gaussian_splats::Splats, render::SH_C0, Backend};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::splat_mesh::Mesh;
+
+const VERTEX: &str = "interpolation = \"vertex\"";
+
+// Write an array attribute, with optional metadata like the primvar interpolation.
+fn write_array<T>(
+    usda: &mut String,
+    decl: &str,
+    values: impl Iterator<Item = T>,
+    fmt: impl Fn(&mut String, T),
+    metadata: &[&str],
+) {
+    let _ = write!(usda, "        {decl} = [");
+    for (i, v) in values.enumerate() {
+        if i > 0 {
+            usda.push_str(", ");
+        }
+        fmt(usda, v);
+    }
+    usda.push(']');
+    if !metadata.is_empty() {
+        usda.push_str(" (\n");
+        for m in metadata {
+            let _ = writeln!(usda, "            {m}");
+        }
+        usda.push_str("        )");
+    }
+    usda.push('\n');
+}
+
+fn vec3(usda: &mut String, v: &[f32]) {
+    let _ = write!(usda, "({}, {}, {})", v[0], v[1], v[2]);
+}
+
+fn scalar(usda: &mut String, v: f32) {
+    let _ = write!(usda, "{v}");
+}
+
+// A material that shows the vertex colors, as some viewers (like AR Quick Look) ignore the
+// display color otherwise.
+const DISPLAY_COLOR_MATERIAL: &str = r#"
+    def Material "DisplayColor"
+    {
+        token outputs:surface.connect = </Splats/DisplayColor/Surface.outputs:surface>
+
+        def Shader "Surface"
+        {
+            uniform token info:id = "UsdPreviewSurface"
+            color3f inputs:diffuseColor.connect = </Splats/DisplayColor/Color.outputs:result>
+            float inputs:roughness = 1
+            token outputs:surface
+        }
+
+        def Shader "Color"
+        {
+            uniform token info:id = "UsdPrimvarReader_float3"
+            string inputs:varname = "displayColor"
+            float3 outputs:result
+        }
+    }
+"#;
+
+fn points_usda(
+    means: &[f32],
+    scales: &[f32],
+    rotations: &[f32],
+    opacities: &[f32],
+    sh_coeffs: &[f32],
+    n_coeffs: usize,
+) -> String {
+    let mut usda = String::from("    def Points \"Points\"\n    {\n");
+
+    write_array(&mut usda, "point3f[] points", means.chunks(3), vec3, &[]);
+    // Show splats as points of two sigma in viewers that don't know about splats.
+    write_array(
+        &mut usda,
+        "float[] widths",
+        scales.chunks(3).map(|s| 4.0 * s[0].max(s[1]).max(s[2])),
+        scalar,
+        &[VERTEX],
+    );
+    write_array(
+        &mut usda,
+        "color3f[] primvars:displayColor",
+        sh_coeffs.chunks(n_coeffs * 3),
+        |usda, c| {
+            let rgb: Vec<f32> = c[..3]
+                .iter()
+                .map(|c| (c * SH_C0 + 0.5).clamp(0.0, 1.0))
+                .collect();
+            vec3(usda, &rgb);
+        },
+        &[VERTEX],
+    );
+    write_array(
+        &mut usda,
+        "float[] primvars:displayOpacity",
+        opacities.iter().copied(),
+        scalar,
+        &[VERTEX],
+    );
+
+    // The full splat attributes, as custom primvars.
+    write_array(
+        &mut usda,
+        "float3[] primvars:splat:scale",
+        scales.chunks(3),
+        vec3,
+        &[VERTEX],
+    );
+    // USD quaternions are written as (real, i, j, k), which matches the splat rotations.
+    write_array(
+        &mut usda,
+        "quatf[] primvars:splat:orientation",
+        rotations.chunks(4),
+        |usda, q| {
+            let _ = write!(usda, "({}, {}, {}, {})", q[0], q[1], q[2], q[3]);
+        },
+        &[VERTEX],
+    );
+    write_array(
+        &mut usda,
+        "float3[] primvars:splat:sh",
+        sh_coeffs.chunks(3),
+        vec3,
+        &[VERTEX, &format!("elementSize = {n_coeffs}")],
+    );
+    usda.push_str("        rel material:binding = </Splats/DisplayColor>\n    }\n");
+    usda
+}
+
+fn mesh_usda(mesh: &Mesh) -> String {
+    let mut usda = String::from("    def Mesh \"Mesh\"\n    {\n");
+    write_array(
+        &mut usda,
+        "point3f[] points",
+        mesh.positions.iter(),
+        |usda, p| vec3(usda, &p.to_array()),
+        &[],
+    );
+    write_array(
+        &mut usda,
+        "int[] faceVertexCounts",
+        mesh.indices.chunks(3).map(|_| 3),
+        |usda, c: u32| {
+            let _ = write!(usda, "{c}");
+        },
+        &[],
+    );
+    write_array(
+        &mut usda,
+        "int[] faceVertexIndices",
+        mesh.indices.iter(),
+        |usda, i| {
+            let _ = write!(usda, "{i}");
+        },
+        &[],
+    );
+    write_array(
+        &mut usda,
+        "color3f[] primvars:displayColor",
+        mesh.colors.iter(),
+        |usda, c| vec3(usda, &c.to_array()),
+        &[VERTEX],
+    );
+    usda.push_str("        uniform token subdivisionScheme = \"none\"\n");
+    usda.push_str("        rel material:binding = </Splats/DisplayColor>\n    }\n");
+    usda
+}
+
+fn scene_usda(prims: &[String]) -> String {
+    let mut usda = String::from(
+        "#usda 1.0\n(\n    defaultPrim = \"Splats\"\n    metersPerUnit = 1\n    upAxis = \"Y\"\n    doc = \"Exported from Brush\"\n)\n\n",
+    );
+    // Splats use a y-down convention while USDZ files are y-up, so flip the model around x.
+    usda.push_str("def Xform \"Splats\" (\n    kind = \"component\"\n)\n{\n");
+    usda.push_str("    float3 xformOp:rotateXYZ = (180, 0, 0)\n");
+    usda.push_str("    uniform token[] xformOpOrder = [\"xformOp:rotateXYZ\"]\n");
+    usda.push_str(DISPLAY_COLOR_MATERIAL);
+    for prim in prims {
+        usda.push('\n');
+        usda.push_str(prim);
+    }
+    usda.push_str("}\n");
+    usda
+}
+
+// USDZ files are zip files without compression, with all files aligned to 64 bytes.
+fn package_usdz(usda: &str) -> anyhow::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .with_alignment(64);
+    zip.start_file("scene.usda", options)?;
+    zip.write_all(usda.as_bytes())?;
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Export splats as a USDZ file.
+///
+/// The splats are stored as points, with the full splat attributes as `splat:` primvars. Most
+/// viewers don't render points, so when a mesh is given it's included as well, eg. for AR
+/// Quick Look.
+pub async fn splat_to_usdz<B: Backend>(
+    splats: Splats<B>,
+    mesh: Option<&Mesh>,
+) -> anyhow::Result<Vec<u8>> {
+    let read_err = |e| anyhow!("Failed to read data from splat {e:?}");
+    let n_coeffs = splats.sh_coeffs.dims()[1];
+
+    let means: Vec<f32> = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let scales: Vec<f32> = splats
+        .scales()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let rotations: Vec<f32> = splats
+        .rotations_normed()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let opacities: Vec<f32> = splats
+        .opacity()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let sh_coeffs: Vec<f32> = splats
+        .sh_coeffs
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+
+    let mut prims = vec![points_usda(
+        &means, &scales, &rotations, &opacities, &sh_coeffs, n_coeffs,
+    )];
+    if let Some(mesh) = mesh {
+        prims.push(mesh_usda(mesh));
+    }
+
+    package_usdz(&scene_usda(&prims))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use burn::backend::{wgpu::WgpuDevice, Wgpu};
+    use glam::{Quat, Vec3};
+    use zip::ZipArchive;
+
+    #[tokio::test]
+    async fn usdz_is_valid_archive() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = Splats::<Wgpu>::from_raw(
+            &[Vec3::new(0.0, 1.0, 2.0), Vec3::new(-1.0, 0.5, 3.0)],
+            Some(&[Quat::IDENTITY; 2]),
+            Some(&[Vec3::splat(-2.0); 2]),
+            None,
+            None,
+            &device,
+        );
+        let mesh = Mesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            colors: vec![Vec3::ONE; 3],
+            indices: vec![0, 1, 2],
+        };
+
+        let usdz = splat_to_usdz(splats, Some(&mesh))
+            .await
+            .expect("Failed to export");
+        let mut archive = ZipArchive::new(Cursor::new(usdz)).expect("Invalid zip archive");
+        let names: Vec<_> = archive.file_names().collect();
+        assert_eq!(names, ["scene.usda"]);
+
+        let mut file = archive.by_index(0).expect("Missing entry");
+        assert_eq!(file.name(), "scene.usda");
+        // USDZ requires uncompressed files, aligned to 64 bytes.
+        assert_eq!(file.compression(), CompressionMethod::Stored);
+        assert_eq!(file.data_start() % 64, 0);
+
+        let mut usda = String::new();
+        file.read_to_string(&mut usda).expect("Invalid usda");
+        assert!(usda.starts_with("#usda 1.0\n"));
+        assert!(usda.contains("defaultPrim = \"Splats\""));
+        assert!(usda.contains("point3f[] points = [(0, 1, 2), (-1, 0.5, 3)]"));
+        assert!(usda.contains("def Points \"Points\""));
+        assert!(usda.contains("def Mesh \"Mesh\""));
+        assert!(usda.contains("int[] faceVertexIndices = [0, 1, 2]"));
+    }
+}
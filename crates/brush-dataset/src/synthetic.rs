@@ -0,0 +1,319 @@
+//! Procedurally generated scenes with known cameras, for reproducible tests and tutorials that
+//! don't need to download a capture.
+//!
+//! A scene is a few textured primitives, a checkered floor, a cube and a sphere, covered in
+//! flat splats. The views orbit the scene and are rendered from these splats, so the ground
+//! truth splats are known too, and training on the views should converge to the same look.
+
+use std::sync::Arc;
+
+use brush_render::{
+    camera::Camera,
+    camera_path,
+    gaussian_splats::{inverse_sigmoid, Splats},
+    render::rgb_to_sh,
+    Backend,
+};
+use brush_train::{
+    image::tensor_into_image,
+    scene::{SceneView, ViewImageType},
+};
+use burn::config::Config;
+use glam::{Quat, Vec2, Vec3};
+use image::DynamicImage;
+use rand::{Rng, SeedableRng};
+
+use crate::Dataset;
+
+#[derive(Config, Debug)]
+pub struct SyntheticConfig {
+    /// Nr. of views orbiting the scene.
+    #[config(default = 32)]
+    pub num_views: usize,
+    /// Width and height of the views.
+    #[config(default = 256)]
+    pub resolution: u32,
+    /// Nr. of splats covering the primitives.
+    #[config(default = 50000)]
+    pub num_splats: usize,
+    /// Use every nth view for evaluation rather than training.
+    pub eval_split_every: Option<usize>,
+    #[config(default = 0)]
+    pub seed: u64,
+}
+
+/// A generated scene, with the splats its views are rendered from.
+pub struct SyntheticScene<B: Backend> {
+    pub dataset: Dataset,
+    pub gt_splats: Splats<B>,
+}
+
+/// Camera at `position` looking at `target`, with world +y pointing down in the image.
+pub fn look_at(position: Vec3, target: Vec3, fov: f64) -> Camera {
+    let rotation = camera_path::look_at(position, target, Vec3::NEG_Y);
+    Camera::new(position, rotation, fov, fov, glam::vec2(0.5, 0.5))
+}
+
+// A point on the surface of a primitive, with its normal and texture coordinate.
+struct SurfacePoint {
+    position: Vec3,
+    normal: Vec3,
+    uv: Vec2,
+}
+
+#[derive(Clone, Copy)]
+enum Primitive {
+    // Floor below the objects, as world +y is down.
+    Floor { height: f32, half_size: f32 },
+    Cube { center: Vec3, half_size: f32 },
+    Sphere { center: Vec3, radius: f32 },
+}
+
+impl Primitive {
+    fn area(&self) -> f32 {
+        match *self {
+            Self::Floor { half_size, .. } => (2.0 * half_size).powi(2),
+            Self::Cube { half_size, .. } => 6.0 * (2.0 * half_size).powi(2),
+            Self::Sphere { radius, .. } => 4.0 * std::f32::consts::PI * radius * radius,
+        }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> SurfacePoint {
+        let uv = Vec2::new(rng.gen(), rng.gen());
+        match *self {
+            Self::Floor { height, half_size } => {
+                let xz = (uv * 2.0 - 1.0) * half_size;
+                SurfacePoint {
+                    position: Vec3::new(xz.x, height, xz.y),
+                    normal: -Vec3::Y,
+                    uv: uv * 8.0,
+                }
+            }
+            Self::Cube { center, half_size } => {
+                let axis = rng.gen_range(0..3);
+                let sign = if rng.gen() { 1.0 } else { -1.0 };
+                let mut normal = Vec3::ZERO;
+                normal[axis] = sign;
+                let face = (uv * 2.0 - 1.0) * half_size;
+                let mut offset = normal * half_size;
+                offset[(axis + 1) % 3] = face.x;
+                offset[(axis + 2) % 3] = face.y;
+                SurfacePoint {
+                    position: center + offset,
+                    normal,
+                    uv: uv * 2.0,
+                }
+            }
+            Self::Sphere { center, radius } => {
+                // Uniform over the sphere, with equirectangular texture coordinates.
+                let z = 1.0 - 2.0 * uv.y;
+                let phi = uv.x * std::f32::consts::TAU;
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                let normal = Vec3::new(r * phi.cos(), z, r * phi.sin());
+                SurfacePoint {
+                    position: center + normal * radius,
+                    normal,
+                    uv: Vec2::new(uv.x, z.acos() / std::f32::consts::PI),
+                }
+            }
+        }
+    }
+}
+
+fn checker(uv: Vec2, a: Vec3, b: Vec3) -> Vec3 {
+    let cell = uv.floor();
+    if (cell.x + cell.y).rem_euclid(2.0) < 1.0 {
+        a
+    } else {
+        b
+    }
+}
+
+// The color of the texture at a uv coordinate in [0, 1], wrapping around.
+fn sample_texture(texture: &DynamicImage, uv: Vec2) -> Vec3 {
+    let (w, h) = (texture.width(), texture.height());
+    let uv = uv.rem_euclid(Vec2::ONE);
+    let x = ((uv.x * w as f32) as u32).min(w - 1);
+    let y = ((uv.y * h as f32) as u32).min(h - 1);
+    let [r, g, b, _] = image::GenericImageView::get_pixel(texture, x, y).0;
+    Vec3::new(r as f32, g as f32, b as f32) / 255.0
+}
+
+fn primitive_color(
+    primitive: Primitive,
+    point: &SurfacePoint,
+    texture: Option<&DynamicImage>,
+) -> Vec3 {
+    match primitive {
+        Primitive::Floor { .. } => checker(point.uv, Vec3::splat(0.85), Vec3::new(0.2, 0.25, 0.3)),
+        Primitive::Cube { .. } => checker(point.uv, Vec3::new(0.9, 0.5, 0.1), Vec3::splat(0.1)),
+        Primitive::Sphere { .. } => match texture {
+            Some(texture) => sample_texture(texture, point.uv),
+            // Stripes, so the sphere has detail to reconstruct.
+            None => {
+                let t = (point.uv * Vec2::new(12.0, 6.0)).fract();
+                Vec3::new(t.x, 0.3, t.y)
+            }
+        },
+    }
+}
+
+/// Flat splats covering a floor, a cube and a sphere. The sphere is covered in `texture` if
+/// given, eg. the crab test image.
+pub fn scene_splats<B: Backend>(
+    num_splats: usize,
+    texture: Option<&DynamicImage>,
+    rng: &mut impl Rng,
+    device: &B::Device,
+) -> Splats<B> {
+    let primitives = [
+        Primitive::Floor {
+            height: 1.0,
+            half_size: 2.0,
+        },
+        Primitive::Cube {
+            center: Vec3::new(-0.7, 0.5, 0.3),
+            half_size: 0.5,
+        },
+        Primitive::Sphere {
+            center: Vec3::new(0.6, 0.2, -0.2),
+            radius: 0.8,
+        },
+    ];
+    let total_area: f32 = primitives.iter().map(|p| p.area()).sum();
+
+    let mut means = vec![];
+    let mut rotations = vec![];
+    let mut log_scales = vec![];
+    let mut sh_coeffs = vec![];
+    for primitive in primitives {
+        let area = primitive.area();
+        let count = ((num_splats as f32 * area / total_area) as usize).max(1);
+        // Splats overlap a bit to cover the surface without holes.
+        let size = 0.8 * (area / count as f32).sqrt();
+        for _ in 0..count {
+            let point = primitive.sample(rng);
+            let color = primitive_color(primitive, &point, texture);
+            means.push(point.position);
+            rotations.push(Quat::from_rotation_arc(Vec3::Z, point.normal));
+            log_scales.push(Vec3::new(size.ln(), size.ln(), (size * 0.05).ln()));
+            sh_coeffs.extend(color.to_array().map(rgb_to_sh));
+        }
+    }
+    let raw_opacities = vec![inverse_sigmoid(0.98); means.len()];
+
+    Splats::from_raw(
+        &means,
+        Some(&rotations),
+        Some(&log_scales),
+        Some(&sh_coeffs),
+        Some(&raw_opacities),
+        device,
+    )
+}
+
+/// The cameras of the views, orbiting the origin at alternating heights.
+pub fn orbit_cameras(num_views: usize) -> Vec<Camera> {
+    (0..num_views)
+        .map(|i| {
+            let angle = i as f32 / num_views as f32 * std::f32::consts::TAU;
+            // Look down on the scene, as world +y is down.
+            let height = if i % 2 == 0 { -1.5 } else { -0.5 };
+            let position = Vec3::new(angle.cos() * 4.5, height, angle.sin() * 4.5);
+            look_at(position, Vec3::ZERO, 0.9)
+        })
+        .collect()
+}
+
+/// Generate a scene, and render its views.
+pub async fn generate<B: Backend>(
+    config: &SyntheticConfig,
+    texture: Option<&DynamicImage>,
+    device: &B::Device,
+) -> SyntheticScene<B> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(config.seed);
+    let gt_splats = scene_splats::<B>(config.num_splats, texture, &mut rng, device);
+
+    let img_size = glam::uvec2(config.resolution, config.resolution);
+    let (w, h) = (img_size.x as usize, img_size.y as usize);
+    let mut train_views = vec![];
+    let mut eval_views = vec![];
+    for (i, camera) in orbit_cameras(config.num_views).into_iter().enumerate() {
+        let (img, _) = gt_splats.render(&camera, img_size, false);
+        let img = img.slice([0..h, 0..w, 0..3]).into_data_async().await;
+        let view = SceneView {
+            path: format!("synthetic_{i:03}"),
+            camera,
+            image: Arc::new(tensor_into_image(img)),
+            img_type: ViewImageType::Alpha,
+            original_size: (img_size.x, img_size.y),
+            depth: None,
+        };
+        match config.eval_split_every {
+            Some(every) if i % every == 0 => eval_views.push(view),
+            _ => train_views.push(view),
+        }
+    }
+
+    SyntheticScene {
+        dataset: Dataset::from_views(train_views, eval_views),
+        gt_splats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_loader::SceneLoader;
+    use brush_render::bounding_box::BoundingBox;
+    use brush_render::gaussian_splats::RandomSplatsConfig;
+    use brush_train::train::{SplatTrainer, TrainConfig};
+    use burn::backend::{wgpu::WgpuDevice, Autodiff, Wgpu};
+
+    #[test]
+    fn cameras_look_at_the_origin() {
+        for camera in orbit_cameras(8) {
+            let forward = camera.rotation * Vec3::Z;
+            assert!(forward.abs_diff_eq(-camera.position.normalize(), 1e-5));
+            // The floor is at the bottom of the image.
+            assert!((camera.rotation * Vec3::Y).y > 0.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn trains_on_synthetic_scene() {
+        let device = WgpuDevice::DefaultDevice;
+        let config = SyntheticConfig::new()
+            .with_num_views(8)
+            .with_resolution(64)
+            .with_num_splats(5000);
+        let scene = generate::<Wgpu>(&config, None, &device).await;
+        assert_eq!(scene.dataset.train.views.len(), 8);
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let bounds = BoundingBox::from_min_max(Vec3::splat(-2.0), Vec3::splat(2.0));
+        let mut splats = Splats::<Autodiff<Wgpu>>::from_random_config(
+            &RandomSplatsConfig::new(),
+            bounds,
+            &mut rng,
+            &device,
+        );
+
+        let steps = 300;
+        let train_config = TrainConfig::new().with_total_steps(steps);
+        let mut loader = SceneLoader::new(&scene.dataset.train, 0, &device);
+        let mut trainer = SplatTrainer::new(&splats, &train_config, &device);
+
+        let mut losses = vec![];
+        for iter in 0..steps {
+            let batch = loader.next_batch().await;
+            let (new_splats, stats) = trainer.step(iter, batch, splats);
+            splats = new_splats;
+            losses.push(stats.loss.into_scalar_async().await);
+        }
+
+        let mean = |losses: &[f32]| losses.iter().sum::<f32>() / losses.len() as f32;
+        let (first, last) = (mean(&losses[..20]), mean(&losses[losses.len() - 20..]));
+        assert!(last < first * 0.7, "Loss went from {first} to {last}");
+    }
+}
@@ -2,8 +2,12 @@ use miette::IntoDiagnostic;
 
 fn main() -> miette::Result<()> {
     brush_wgsl::build_modules(
-        &["src/shaders/wg.wgsl"],
-        &[],
+        &[
+            "src/shaders/wg.wgsl",
+            "src/shaders/knn_build.wgsl",
+            "src/shaders/knn_query.wgsl",
+        ],
+        &["src/shaders/knn_helpers.wgsl"],
         "src/shaders",
         "src/shaders/mod.rs",
     )
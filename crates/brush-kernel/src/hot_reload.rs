@@ -0,0 +1,111 @@
+// Hot reloading of wgsl kernels, with the `shader-hot-reload` feature.
+//
+// Normally kernel sources are embedded in the binary with include_str!. When a watcher is started
+// kernels are instead read from disk, and any change to a .wgsl file bumps a global generation
+// counter. The generation is part of every kernel id, so burn compiles a fresh pipeline the next
+// time a kernel is dispatched. Without the feature, kernels are always the embedded sources.
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+/// Whether kernels are currently being read from disk.
+pub fn enabled() -> bool {
+    cfg!(feature = "shader-hot-reload") && ENABLED.load(Ordering::Relaxed)
+}
+
+/// The number of times shaders have been reloaded so far.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Read a shader source file. When hot reloading is disabled, or the file can't be read, this
+/// falls back to the source embedded at compile time.
+pub fn read_source(
+    manifest_dir: &str,
+    rel_path: &str,
+    embedded: &'static str,
+) -> Cow<'static, str> {
+    if enabled() {
+        let path = std::path::Path::new(manifest_dir).join(rel_path);
+        match std::fs::read_to_string(&path) {
+            Ok(source) => return Cow::Owned(source),
+            Err(e) => log::warn!("Failed to read shader {}: {e}", path.display()),
+        }
+    }
+    Cow::Borrowed(embedded)
+}
+
+#[cfg(all(feature = "shader-hot-reload", not(target_family = "wasm")))]
+mod watcher {
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::atomic::Ordering,
+        time::{Duration, SystemTime},
+    };
+
+    fn collect_shaders(dir: &Path, out: &mut HashMap<PathBuf, SystemTime>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            if path.is_dir() {
+                // Don't bother looking through build artifacts.
+                if path.file_name().is_some_and(|n| n == "target") {
+                    continue;
+                }
+                collect_shaders(&path, out);
+            } else if path.extension().is_some_and(|e| e == "wgsl") {
+                if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                    out.insert(path, modified);
+                }
+            }
+        }
+    }
+
+    /// Start watching all .wgsl files under `root`. From now on kernels are read from disk, and
+    /// kernels are recompiled whenever a file changes. `on_change` is called after every reload,
+    /// eg. to request a redraw.
+    pub fn watch_shaders(root: impl Into<PathBuf>, on_change: impl Fn() + Send + 'static) {
+        if super::ENABLED.swap(true, Ordering::Relaxed) {
+            log::warn!("Shader hot reloading was already started");
+            return;
+        }
+
+        let root = root.into();
+        log::info!("Watching shaders in {} for changes", root.display());
+
+        std::thread::spawn(move || {
+            let mut last = HashMap::new();
+            collect_shaders(&root, &mut last);
+
+            loop {
+                std::thread::sleep(Duration::from_millis(250));
+
+                let mut current = HashMap::new();
+                collect_shaders(&root, &mut current);
+
+                if current != last {
+                    let changed: Vec<_> = current
+                        .iter()
+                        .filter(|(path, time)| last.get(*path) != Some(*time))
+                        .map(|(path, _)| path.display().to_string())
+                        .collect();
+                    log::info!("Reloading shaders, changed: {}", changed.join(", "));
+
+                    super::GENERATION.fetch_add(1, Ordering::Relaxed);
+                    last = current;
+                    on_change();
+                }
+            }
+        });
+    }
+}
+
+#[cfg(all(feature = "shader-hot-reload", not(target_family = "wasm")))]
+pub use watcher::watch_shaders;
@@ -0,0 +1,95 @@
+//! Nearest neighbours of a point cloud on the GPU.
+//!
+//! The points are hashed into a grid of cells, with a linked list of the points in every bucket
+//! of the hash table. The neighbours of a point are then searched in growing rings of cells
+//! around it, until no unvisited cell can hold a nearer point.
+
+use burn::tensor::{ops::IntTensorOps, DType};
+use burn_jit::{kernel::into_contiguous, JitBackend};
+
+use crate::{
+    create_tensor, create_uniform_buffer, kernel_source_gen,
+    shaders::{knn_build, knn_helpers, knn_query},
+    CubeCount, JitRuntime, JitTensor,
+};
+
+kernel_source_gen!(KnnBuild {}, knn_build);
+kernel_source_gen!(KnnQuery {}, knn_query);
+
+// Max nr. of workgroups along one dimension of a dispatch.
+const MAX_WGS: u32 = 65535;
+
+// Points that have no neighbours this many cells away count as isolated.
+const MAX_RING: i32 = 4;
+
+fn cube_count(num_points: u32, wg_size: u32) -> CubeCount {
+    // Spread large dispatches over y, the kernels combine both ids.
+    let wgs = num_points.div_ceil(wg_size);
+    CubeCount::Static(wgs.min(MAX_WGS), wgs.div_ceil(MAX_WGS).max(1), 1)
+}
+
+/// The mean squared distance of every point of `points`, `[n, 3]`, to its 3 nearest other
+/// points, eg. to size splats to cover the gaps between them.
+///
+/// Neighbours are searched in cells of `cell_size`, which is fastest around the typical
+/// spacing of the points. Points with fewer than 3 neighbours within a few cells count the
+/// missing neighbours as just out of reach, so isolated points get a large but finite
+/// distance.
+pub fn knn_mean_sq_dist<R: JitRuntime>(points: JitTensor<R>, cell_size: f32) -> JitTensor<R> {
+    let points = into_contiguous(points);
+    let client = points.client.clone();
+    let device = points.device.clone();
+    let num_points = points.shape.dims[0];
+
+    let mean_sq_dist = create_tensor([num_points], &device, &client, DType::F32);
+    if num_points == 0 {
+        return mean_sq_dist;
+    }
+
+    // Twice as many buckets as points keeps the lists short.
+    let num_buckets = num_points * 2;
+    let uniforms = create_uniform_buffer::<R, _>(
+        knn_helpers::Uniforms {
+            num_points: num_points as u32,
+            num_buckets: num_buckets as u32,
+            cell_size,
+            max_ring: MAX_RING,
+        },
+        &device,
+        &client,
+    );
+
+    let heads = JitBackend::<R, f32, i32, u32>::int_zeros([num_buckets].into(), &device);
+    let next = create_tensor([num_points], &device, &client, DType::U32);
+
+    // SAFETY: wgsl FFI, kernel checked to have no OOB.
+    unsafe {
+        client.execute_unchecked(
+            KnnBuild::task(),
+            cube_count(num_points as u32, KnnBuild::WORKGROUP_SIZE[0]),
+            vec![
+                uniforms.clone().handle.binding(),
+                points.clone().handle.binding(),
+                heads.clone().handle.binding(),
+                next.clone().handle.binding(),
+            ],
+        );
+    }
+
+    // SAFETY: wgsl FFI, kernel checked to have no OOB.
+    unsafe {
+        client.execute_unchecked(
+            KnnQuery::task(),
+            cube_count(num_points as u32, KnnQuery::WORKGROUP_SIZE[0]),
+            vec![
+                uniforms.handle.binding(),
+                points.handle.binding(),
+                heads.handle.binding(),
+                next.handle.binding(),
+                mean_sq_dist.clone().handle.binding(),
+            ],
+        );
+    }
+
+    mean_sq_dist
+}
@@ -3,6 +3,12 @@
 // generated by the macro below.
 mod shaders;
 
+pub mod hot_reload;
+pub mod knn;
+
+// Generated shader code refers to this crate by name.
+extern crate self as brush_kernel;
+
 use burn::tensor::{DType, Shape};
 pub use burn_jit::cubecl::prelude::ExecutionMode;
 pub use burn_jit::cubecl::{
@@ -12,6 +18,7 @@ pub use burn_jit::cubecl::{
 pub use burn_jit::{cubecl::Compiler, tensor::JitTensor, JitRuntime};
 
 use bytemuck::Pod;
+pub use naga_oil::compose::ShaderDefValue;
 use wgpu::naga;
 
 pub fn calc_cube_count<const D: usize>(sizes: [u32; D], workgroup_size: [u32; 3]) -> CubeCount {
@@ -59,28 +66,68 @@ pub fn module_to_compiled<C: Compiler>(
     }
 }
 
-pub fn calc_kernel_id<T: 'static>(values: &[bool]) -> KernelId {
-    let mut kernel_id = KernelId::new::<T>();
+pub fn calc_kernel_id<T: 'static, I>(specialization: I) -> KernelId
+where
+    I: 'static + PartialEq + Eq + std::hash::Hash + std::fmt::Debug + Send + Sync,
+{
+    // Include the shader generation, so kernels get recompiled when hot reloading.
+    KernelId::new::<T>().info((hot_reload::generation(), specialization))
+}
+
+/// A value a kernel can be specialized on. Specialization values are passed to the shader
+/// as shader defs, so the shader can use `#ifdef` and `#if` to compile out unused code paths.
+pub trait ShaderDef: Copy {
+    fn shader_def(self) -> Option<ShaderDefValue>;
+}
 
-    for val in values.iter().copied() {
-        kernel_id = kernel_id.info(val);
+impl ShaderDef for bool {
+    fn shader_def(self) -> Option<ShaderDefValue> {
+        // Flags are only defined when set, so they can be checked with #ifdef.
+        self.then_some(ShaderDefValue::Bool(true))
     }
+}
 
-    kernel_id
+impl ShaderDef for u32 {
+    fn shader_def(self) -> Option<ShaderDefValue> {
+        Some(ShaderDefValue::UInt(self))
+    }
+}
+
+impl ShaderDef for i32 {
+    fn shader_def(self) -> Option<ShaderDefValue> {
+        Some(ShaderDefValue::Int(self))
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __kernel_field_ty {
+    () => {
+        bool
+    };
+    ($ty:ty) => {
+        $ty
+    };
 }
 
+/// Generate a kernel from a wgsl module.
+///
+/// Kernels can be specialized on a number of values. Fields without a type are boolean flags,
+/// eg. `Rasterize { raster_u32 }`, other fields can be u32 or i32, eg.
+/// `ProjectVisible { sh_degree: u32 }`. Each field is passed to the shader as an uppercase
+/// shader def, and each combination of values compiles to a separate pipeline.
 #[macro_export]
 macro_rules! kernel_source_gen {
-    ($struct_name:ident { $($field_name:ident),* }, $module:ident) => {
+    ($struct_name:ident { $($field_name:ident $(: $field_ty:ty)?),* }, $module:ident) => {
         #[derive(Debug, Copy, Clone)]
         pub(crate) struct $struct_name {
             $(
-                $field_name: bool,
+                $field_name: brush_kernel::__kernel_field_ty!($($field_ty)?),
             )*
         }
 
         impl $struct_name {
-            pub fn task($($field_name: bool),*) -> Box<$struct_name> {
+            pub fn task($($field_name: brush_kernel::__kernel_field_ty!($($field_ty)?)),*) -> Box<$struct_name> {
                 let kernel = Self {
                     $(
                         $field_name,
@@ -95,8 +142,8 @@ macro_rules! kernel_source_gen {
                 $(
                     let mut map = map;
 
-                    if self.$field_name {
-                        map.insert(stringify!($field_name).to_owned().to_uppercase(), naga_oil::compose::ShaderDefValue::Bool(true));
+                    if let Some(value) = brush_kernel::ShaderDef::shader_def(self.$field_name) {
+                        map.insert(stringify!($field_name).to_owned().to_uppercase(), value);
                     }
                 )*
                 map
@@ -112,12 +159,14 @@ macro_rules! kernel_source_gen {
 
         impl<C: burn_jit::cubecl::Compiler> brush_kernel::CubeTask<C> for $struct_name {
             fn id(&self) -> brush_kernel::KernelId {
-                brush_kernel::calc_kernel_id::<Self>(&[$(self.$field_name),*])
+                brush_kernel::calc_kernel_id::<Self, _>(($(self.$field_name,)*))
             }
 
             fn compile(&self,  _compilation_options: &C::CompilationOptions, _mode: brush_kernel::ExecutionMode) -> brush_kernel::CompiledKernel<C> {
                 let module = self.source();
-                brush_kernel::module_to_compiled(stringify!($struct_name), &module, Self::WORKGROUP_SIZE)
+                // Shader defs can change the workgroup size, so take it from the module.
+                let workgroup_size = module.entry_points[0].workgroup_size;
+                brush_kernel::module_to_compiled(stringify!($struct_name), &module, workgroup_size)
             }
         }
     };
@@ -177,7 +226,7 @@ pub(crate) struct CreateDispatchBuffer {}
 
 impl<C: Compiler> CubeTask<C> for CreateDispatchBuffer {
     fn id(&self) -> KernelId {
-        KernelId::new::<Self>()
+        calc_kernel_id::<Self, _>(())
     }
 
     fn compile(
@@ -193,6 +242,11 @@ impl<C: Compiler> CubeTask<C> for CreateDispatchBuffer {
     }
 }
 
+/// Create a buffer for an indirect dispatch, with enough workgroups of `wg_size` to cover the
+/// thread counts in `thread_nums`.
+///
+/// 1D dispatches with more than 65535 workgroups are spread over the y dimension, so kernels
+/// that can be dispatched with that many workgroups have to combine their x and y ids.
 pub fn create_dispatch_buffer<R: JitRuntime>(
     thread_nums: JitTensor<R>,
     wg_size: [u32; 3],
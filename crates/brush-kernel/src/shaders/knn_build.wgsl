@@ -0,0 +1,25 @@
+#import knn_helpers;
+
+@group(0) @binding(0) var<uniform> uniforms: knn_helpers::Uniforms;
+@group(0) @binding(1) var<storage, read> points: array<f32>;
+// The first point in every bucket plus one, zero for empty buckets.
+@group(0) @binding(2) var<storage, read_write> heads: array<atomic<u32>>;
+// The next point in the bucket of every point, also plus one.
+@group(0) @binding(3) var<storage, read_write> next: array<u32>;
+
+const WG_SIZE: u32 = 256u;
+
+@compute
+@workgroup_size(WG_SIZE, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3u, @builtin(num_workgroups) num_wgs: vec3u) {
+    // Large dispatches are spread over y.
+    let id = global_id.x + global_id.y * num_wgs.x * WG_SIZE;
+    if id >= uniforms.num_points {
+        return;
+    }
+
+    let pos = vec3f(points[id * 3u], points[id * 3u + 1u], points[id * 3u + 2u]);
+    let bucket = knn_helpers::bucket_of(knn_helpers::cell_of(pos, uniforms.cell_size), uniforms.num_buckets);
+    // Push the point on the front of the list of its bucket.
+    next[id] = atomicExchange(&heads[bucket], id + 1u);
+}
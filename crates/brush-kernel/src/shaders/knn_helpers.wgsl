@@ -0,0 +1,19 @@
+struct Uniforms {
+    num_points: u32,
+    // Nr. of buckets of the hash table.
+    num_buckets: u32,
+    cell_size: f32,
+    // Neighbours are searched up to this many cells away.
+    max_ring: i32,
+}
+
+fn cell_of(pos: vec3f, cell_size: f32) -> vec3i {
+    return vec3i(floor(pos / cell_size));
+}
+
+// Hash of a grid cell, from "Optimized Spatial Hashing for Collision Detection of Deformable
+// Objects" (Teschner et al. 2003).
+fn bucket_of(cell: vec3i, num_buckets: u32) -> u32 {
+    let c = bitcast<vec3u>(cell);
+    return ((c.x * 73856093u) ^ (c.y * 19349663u) ^ (c.z * 83492791u)) % num_buckets;
+}
@@ -0,0 +1,82 @@
+#import knn_helpers;
+
+@group(0) @binding(0) var<uniform> uniforms: knn_helpers::Uniforms;
+@group(0) @binding(1) var<storage, read> points: array<f32>;
+@group(0) @binding(2) var<storage, read> heads: array<u32>;
+@group(0) @binding(3) var<storage, read> next: array<u32>;
+@group(0) @binding(4) var<storage, read_write> mean_sq_dist: array<f32>;
+
+const WG_SIZE: u32 = 256u;
+
+fn load_point(id: u32) -> vec3f {
+    return vec3f(points[id * 3u], points[id * 3u + 1u], points[id * 3u + 2u]);
+}
+
+@compute
+@workgroup_size(WG_SIZE, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3u, @builtin(num_workgroups) num_wgs: vec3u) {
+    let id = global_id.x + global_id.y * num_wgs.x * WG_SIZE;
+    if id >= uniforms.num_points {
+        return;
+    }
+
+    let pos = load_point(id);
+    let cell = knn_helpers::cell_of(pos, uniforms.cell_size);
+
+    // Squared distances of the 3 nearest neighbours, sorted. Neighbours that aren't found count
+    // as just out of reach of the search.
+    let out_of_reach = f32(uniforms.max_ring + 1) * uniforms.cell_size;
+    var nearest = vec3f(out_of_reach * out_of_reach);
+
+    for (var ring = 0; ring <= uniforms.max_ring; ring++) {
+        // Visit the shell of cells `ring` cells away from the cell of the point.
+        for (var dz = -ring; dz <= ring; dz++) {
+            for (var dy = -ring; dy <= ring; dy++) {
+                for (var dx = -ring; dx <= ring; dx++) {
+                    if max(abs(dx), max(abs(dy), abs(dz))) != ring {
+                        continue;
+                    }
+
+                    let neighbour_cell = cell + vec3i(dx, dy, dz);
+                    var entry = heads[knn_helpers::bucket_of(neighbour_cell, uniforms.num_buckets)];
+
+                    while entry != 0u {
+                        let other = entry - 1u;
+                        entry = next[other];
+
+                        let other_pos = load_point(other);
+                        // Buckets also hold points of other cells, which are visited with their
+                        // own cell.
+                        if other == id || any(knn_helpers::cell_of(other_pos, uniforms.cell_size) != neighbour_cell) {
+                            continue;
+                        }
+
+                        let delta = other_pos - pos;
+                        let dist = dot(delta, delta);
+                        if dist < nearest.z {
+                            if dist < nearest.y {
+                                nearest.z = nearest.y;
+                                if dist < nearest.x {
+                                    nearest.y = nearest.x;
+                                    nearest.x = dist;
+                                } else {
+                                    nearest.y = dist;
+                                }
+                            } else {
+                                nearest.z = dist;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Points that weren't visited yet are at least this far away.
+        let reach = f32(ring) * uniforms.cell_size;
+        if nearest.z <= reach * reach {
+            break;
+        }
+    }
+
+    mean_sq_dist[id] = (nearest.x + nearest.y + nearest.z) / 3.0;
+}
@@ -8,6 +8,9 @@ struct Uniforms {
 @group(0) @binding(1) var<storage, read> thread_counts: array<i32>;
 @group(0) @binding(2) var<storage, read_write> wg_count: array<i32>;
 
+// Max nr. of workgroups along one dimension of a dispatch.
+const MAX_WGS: i32 = 65535;
+
 fn ceil_div(a: i32, b: i32) -> i32 {
     return (a + b - 1) / b;
 }
@@ -34,7 +37,18 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
         cz = thread_counts[2];
     }
 
-    wg_count[0] = ceil_div(cx, uniforms.wg_size_x);
-    wg_count[1] = ceil_div(cy, uniforms.wg_size_y);
-    wg_count[2] = ceil_div(cz, uniforms.wg_size_z);
+    var wg_x = ceil_div(cx, uniforms.wg_size_x);
+    var wg_y = ceil_div(cy, uniforms.wg_size_y);
+    let wg_z = ceil_div(cz, uniforms.wg_size_z);
+
+    // Spread 1D dispatches that need too many workgroups over y. Kernels then have to combine
+    // their x and y ids into a linear id.
+    if wg_y == 1 && wg_z == 1 && wg_x > MAX_WGS {
+        wg_y = ceil_div(wg_x, MAX_WGS);
+        wg_x = MAX_WGS;
+    }
+
+    wg_count[0] = wg_x;
+    wg_count[1] = wg_y;
+    wg_count[2] = wg_z;
 }
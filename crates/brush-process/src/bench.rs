@@ -0,0 +1,212 @@
+//! Training throughput benchmark, to catch performance regressions in the kernels or the
+//! training loop.
+//!
+//! Training runs on a synthetic scene that's generated on the fly, so results are comparable
+//! between machines and revisions without needing to download a dataset.
+
+use std::sync::Arc;
+
+use brush_dataset::{scene_loader::SceneLoader, synthetic::look_at, ModelConfig};
+use brush_render::{
+    bounding_box::BoundingBox,
+    gaussian_splats::{inverse_sigmoid, RandomSplatsConfig, Splats},
+    render::rgb_to_sh,
+};
+use brush_train::{
+    image::tensor_into_image,
+    scene::{Scene, SceneView, ViewImageType},
+    train::{SplatTrainer, TrainConfig},
+};
+use burn::{backend::Autodiff, config::Config, prelude::Backend};
+use burn_wgpu::{Wgpu, WgpuDevice, WgpuRuntime};
+use clap::Args;
+use glam::Vec3;
+use rand::{Rng, SeedableRng};
+use web_time::Instant;
+
+#[derive(Config, Debug, Args)]
+pub struct BenchConfig {
+    /// Nr. of training steps to time.
+    #[arg(long, help_heading = "Benchmark options", default_value = "1000")]
+    #[config(default = 1000)]
+    pub bench_steps: u32,
+    /// Nr. of steps to train before timing, so shader compilation doesn't count.
+    #[arg(long, help_heading = "Benchmark options", default_value = "50")]
+    #[config(default = 50)]
+    pub bench_warmup_steps: u32,
+    /// Resolution of the synthetic training views.
+    #[arg(long, help_heading = "Benchmark options", default_value = "512")]
+    #[config(default = 512)]
+    pub bench_resolution: u32,
+    /// Nr. of synthetic training views.
+    #[arg(long, help_heading = "Benchmark options", default_value = "32")]
+    #[config(default = 32)]
+    pub bench_views: usize,
+    /// Path to write the JSON report to. Printed to stdout when not set.
+    #[arg(long, help_heading = "Benchmark options")]
+    pub bench_output: Option<String>,
+}
+
+/// Mean time spent per step in each stage of training, in milliseconds.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct StageTimings {
+    /// Waiting for the next batch.
+    pub data_ms: f64,
+    /// Forward pass, backward pass and optimizer step.
+    pub train_ms: f64,
+    /// Densification and pruning.
+    pub refine_ms: f64,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct BenchReport {
+    pub steps: u32,
+    pub resolution: u32,
+    pub views: usize,
+    pub final_splats: usize,
+    pub total_secs: f64,
+    pub iters_per_sec: f64,
+    pub stages: StageTimings,
+    /// Peak GPU memory in use during the timed steps.
+    pub peak_memory_bytes: u64,
+    /// Peak GPU memory reserved by the allocator during the timed steps.
+    pub peak_memory_reserved_bytes: u64,
+}
+
+impl BenchReport {
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Generate the synthetic benchmark scene: random splats in a unit cube, rendered from views
+/// orbiting the cube.
+pub async fn synthetic_scene(config: &BenchConfig, device: &WgpuDevice) -> Scene {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+
+    let num_splats = 50000;
+    let means: Vec<Vec3> = (0..num_splats)
+        .map(|_| Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0)
+        .collect();
+    let log_scales: Vec<Vec3> = (0..num_splats)
+        .map(|_| Vec3::splat(rng.gen_range(0.005f32..0.05).ln()))
+        .collect();
+    let sh_coeffs: Vec<f32> = (0..num_splats * 3).map(|_| rgb_to_sh(rng.gen())).collect();
+    let raw_opacities: Vec<f32> = (0..num_splats)
+        .map(|_| inverse_sigmoid(rng.gen_range(0.2..0.99)))
+        .collect();
+    let gt_splats = Splats::<Wgpu>::from_raw(
+        &means,
+        None,
+        Some(&log_scales),
+        Some(&sh_coeffs),
+        Some(&raw_opacities),
+        device,
+    );
+
+    let img_size = glam::uvec2(config.bench_resolution, config.bench_resolution);
+    let (w, h) = (img_size.x as usize, img_size.y as usize);
+    let mut views = vec![];
+    for i in 0..config.bench_views {
+        let angle = i as f32 / config.bench_views as f32 * std::f32::consts::TAU;
+        let height = if i % 2 == 0 { -1.0 } else { 1.0 };
+        let position = Vec3::new(angle.cos() * 4.0, height, angle.sin() * 4.0);
+        let camera = look_at(position, Vec3::ZERO, 0.8);
+        let (img, _) = gt_splats.render(&camera, img_size, false);
+        let img = img.slice([0..h, 0..w, 0..3]).into_data_async().await;
+        views.push(SceneView {
+            path: format!("synthetic_{i:03}"),
+            camera,
+            image: Arc::new(tensor_into_image(img)),
+            img_type: ViewImageType::Alpha,
+            original_size: (img_size.x, img_size.y),
+            depth: None,
+        });
+    }
+
+    Scene::new(views)
+}
+
+/// Train on the synthetic scene, and time the training steps.
+///
+/// The device is synced after every stage of a step to time them separately, so the
+/// throughput is a bit lower than during normal training.
+pub async fn bench_train(config: &BenchConfig, device: &WgpuDevice) -> BenchReport {
+    let scene = synthetic_scene(config, device).await;
+
+    let total_steps = config.bench_warmup_steps + config.bench_steps;
+    let train_config = TrainConfig::new().with_total_steps(total_steps);
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+    let bounds = BoundingBox::from_min_max(Vec3::splat(-1.0), Vec3::splat(1.0));
+    let mut splats = Splats::<Autodiff<Wgpu>>::from_random_config(
+        &RandomSplatsConfig::new(),
+        bounds,
+        &mut rng,
+        device,
+    )
+    .with_sh_degree(ModelConfig::new().sh_degree);
+
+    let mut dataloader = SceneLoader::new(&scene, 42, device);
+    let mut trainer = SplatTrainer::new(&splats, &train_config, device);
+    let client = WgpuRuntime::client(device);
+
+    let mut stages = StageTimings {
+        data_ms: 0.0,
+        train_ms: 0.0,
+        refine_ms: 0.0,
+    };
+    let mut peak_memory_bytes = 0;
+    let mut peak_memory_reserved_bytes = 0;
+    let mut timed_start = Instant::now();
+
+    for iter in 0..total_steps {
+        if iter == config.bench_warmup_steps {
+            Wgpu::sync(device);
+            timed_start = Instant::now();
+        }
+        let timed = iter >= config.bench_warmup_steps;
+
+        let start = Instant::now();
+        let batch = dataloader.next_batch().await;
+        let extent = batch.scene_extent;
+        let data_done = Instant::now();
+
+        let (new_splats, _) = trainer.step(iter, batch, splats);
+        Wgpu::sync(device);
+        let train_done = Instant::now();
+
+        let (new_splats, _) = trainer.refine_if_needed(iter, new_splats, extent).await;
+        Wgpu::sync(device);
+        let refine_done = Instant::now();
+        splats = new_splats;
+
+        if timed {
+            stages.data_ms += (data_done - start).as_secs_f64() * 1000.0;
+            stages.train_ms += (train_done - data_done).as_secs_f64() * 1000.0;
+            stages.refine_ms += (refine_done - train_done).as_secs_f64() * 1000.0;
+
+            let memory = client.memory_usage();
+            peak_memory_bytes = peak_memory_bytes.max(memory.bytes_in_use);
+            peak_memory_reserved_bytes = peak_memory_reserved_bytes.max(memory.bytes_reserved);
+        }
+    }
+
+    let total_secs = timed_start.elapsed().as_secs_f64();
+    let steps = config.bench_steps.max(1) as f64;
+    stages.data_ms /= steps;
+    stages.train_ms /= steps;
+    stages.refine_ms /= steps;
+
+    BenchReport {
+        steps: config.bench_steps,
+        resolution: config.bench_resolution,
+        views: config.bench_views,
+        final_splats: splats.num_splats(),
+        total_secs,
+        iters_per_sec: config.bench_steps as f64 / total_secs,
+        stages,
+        peak_memory_bytes,
+        peak_memory_reserved_bytes,
+    }
+}
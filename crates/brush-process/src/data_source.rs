@@ -1,10 +1,12 @@
+use std::fmt::Debug;
 use std::io::Cursor;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::{path::Path, str::FromStr};
 
 use anyhow::anyhow;
 
-use brush_dataset::brush_vfs::{BrushVfs, PathReader};
+use brush_dataset::brush_vfs::{self, BrushVfs, PathReader};
 use brush_dataset::WasmNotSend;
 use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
 use tokio_stream::StreamExt;
@@ -16,6 +18,22 @@ pub enum DataSource {
     PickDirectory,
     Url(String),
     Path(String),
+    Files(MemoryFiles),
+}
+
+/// Files already read into memory, eg. a folder dropped on the web app. Paths are relative to
+/// where the files were imported from.
+#[derive(Clone, Default)]
+pub struct MemoryFiles {
+    pub files: Vec<(PathBuf, Arc<[u8]>)>,
+}
+
+impl Debug for MemoryFiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryFiles")
+            .field("files", &self.files.len())
+            .finish()
+    }
 }
 
 // Implement FromStr to allow Clap to parse string arguments into DataSource
@@ -26,7 +44,10 @@ impl FromStr for DataSource {
         match s.to_lowercase().as_str() {
             "pick-file" => Ok(Self::PickFile),
             "pick-directory" | "dir" => Ok(Self::PickDirectory),
-            s if s.starts_with("http://") || s.starts_with("https://") => {
+            s if s.starts_with("http://")
+                || s.starts_with("https://")
+                || crate::remote::is_remote_url(s) =>
+            {
                 Ok(Self::Url(s.to_owned()))
             }
             s if std::fs::exists(s).is_ok() => Ok(Self::Path(s.to_owned())),
@@ -40,7 +61,15 @@ async fn read_at_most<R: AsyncRead + Unpin>(
     limit: usize,
 ) -> std::io::Result<Vec<u8>> {
     let mut buffer = vec![0; limit];
-    let bytes_read = reader.read(&mut buffer).await?;
+    let mut bytes_read = 0;
+    // A single read can return less than is available, eg. for a download.
+    while bytes_read < limit {
+        let read = reader.read(&mut buffer[bytes_read..]).await?;
+        if read == 0 {
+            break;
+        }
+        bytes_read += read;
+    }
     buffer.truncate(bytes_read);
     Ok(buffer)
 }
@@ -52,17 +81,23 @@ impl DataSource {
         // Small hack to peek some bytes: Read them
         // and add them at the start again.
         let mut data = BufReader::new(reader);
-        let peek = read_at_most(&mut data, 64).await?;
+        // Tar archives are only recognized from the header of their first file.
+        let peek = read_at_most(&mut data, 512).await?;
         let reader = std::io::Cursor::new(peek.clone()).chain(data);
 
         if peek.as_slice().starts_with(b"ply") {
             let mut path_reader = PathReader::default();
             path_reader.add(Path::new("input.ply"), reader);
             Ok(BrushVfs::from_paths(path_reader))
-        } else if peek.starts_with(b"PK") {
-            BrushVfs::from_zip_reader(reader)
-                .await
-                .map_err(|e| anyhow::anyhow!(e))
+        } else if peek.get(4..8) == Some(b"ftyp".as_slice())
+            || peek.starts_with(&[0x1a, 0x45, 0xdf, 0xa3])
+        {
+            // An mp4/mov or mkv/webm video. Ffmpeg detects the actual format from the contents.
+            let mut path_reader = PathReader::default();
+            path_reader.add(Path::new("input.mp4"), reader);
+            Ok(BrushVfs::from_paths(path_reader))
+        } else if brush_vfs::is_archive(&peek) {
+            BrushVfs::from_archive_reader(reader).await
         } else if peek.starts_with(b"<!DOCTYPE html>") {
             anyhow::bail!("Failed to download data (are you trying to download from Google Drive? You might have to use the proxy.")
         } else if let Some(path_bytes) = peek.strip_prefix(b"BRUSH_PATH") {
@@ -70,7 +105,7 @@ impl DataSource {
             let path = Path::new(&string);
             BrushVfs::from_directory(path).await
         } else {
-            anyhow::bail!("only zip and ply files are supported.")
+            anyhow::bail!("only zip, tar, ply and video files are supported.")
         }
     }
 
@@ -102,6 +137,17 @@ impl DataSource {
                 Self::vfs_from_reader(reader).await
             }
             Self::Path(path) => BrushVfs::from_directory(&PathBuf::from(path)).await,
+            Self::Files(files) => match files.files.as_slice() {
+                // A single file can be an archive, so handle it like a picked file.
+                [(_, data)] => Self::vfs_from_reader(Cursor::new(data.clone())).await,
+                files => {
+                    let mut path_reader = PathReader::default();
+                    for (path, data) in files {
+                        path_reader.add(path, Cursor::new(data.clone()));
+                    }
+                    Ok(BrushVfs::from_paths(path_reader))
+                }
+            },
         }
     }
 }
@@ -1,6 +1,10 @@
 #![recursion_limit = "256"]
 
+pub mod bench;
 pub mod rerun_tools;
 
 pub mod data_source;
 pub mod process_loop;
+pub mod remote;
+#[cfg(not(target_family = "wasm"))]
+pub mod render_path;
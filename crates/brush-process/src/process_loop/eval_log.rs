@@ -0,0 +1,87 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ViewMetrics {
+    pub(crate) view: String,
+    pub(crate) psnr: f32,
+    pub(crate) ssim: f32,
+    pub(crate) lpips: f32,
+}
+
+/// The results of one evaluation, averaged over the eval views.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct EvalEntry {
+    pub(crate) iter: u32,
+    pub(crate) psnr: f32,
+    pub(crate) ssim: f32,
+    pub(crate) lpips: f32,
+    pub(crate) views: Vec<ViewMetrics>,
+}
+
+/// All evaluations of a training run, to write to a file.
+#[derive(Default)]
+pub(crate) struct EvalLog {
+    entries: Vec<EvalEntry>,
+}
+
+impl EvalLog {
+    pub(crate) fn push(&mut self, entry: EvalEntry) {
+        self.entries.push(entry);
+    }
+
+    pub(crate) fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(&self.entries)?)
+    }
+
+    /// A row per view of every evaluation.
+    pub(crate) fn to_csv(&self) -> String {
+        let mut csv = "iter,view,psnr,ssim,lpips\n".to_owned();
+        for entry in &self.entries {
+            for view in &entry.views {
+                let name = view.view.replace('"', "\"\"");
+                let _ = writeln!(
+                    csv,
+                    "{},\"{name}\",{},{},{}",
+                    entry.iter, view.psnr, view.ssim, view.lpips
+                );
+            }
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_has_row_per_view() {
+        let mut log = EvalLog::default();
+        log.push(EvalEntry {
+            iter: 100,
+            psnr: 25.0,
+            ssim: 0.75,
+            lpips: 0.25,
+            views: vec![
+                ViewMetrics {
+                    view: "images/a.png".to_owned(),
+                    psnr: 20.0,
+                    ssim: 0.5,
+                    lpips: 0.5,
+                },
+                ViewMetrics {
+                    view: "images/\"b\".png".to_owned(),
+                    psnr: 30.0,
+                    ssim: 1.0,
+                    lpips: 0.0,
+                },
+            ],
+        });
+        assert_eq!(
+            log.to_csv(),
+            "iter,view,psnr,ssim,lpips\n100,\"images/a.png\",20,0.5,0.5\n100,\"images/\"\"b\"\".png\",30,1,0\n"
+        );
+    }
+}
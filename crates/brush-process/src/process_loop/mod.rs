@@ -1,3 +1,5 @@
+#[cfg(not(target_family = "wasm"))]
+mod eval_log;
 mod process;
 mod process_args;
 
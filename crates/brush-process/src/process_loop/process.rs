@@ -4,24 +4,38 @@ use anyhow::Context;
 use burn_jit::cubecl::Runtime;
 use web_time::Instant;
 
-use crate::{data_source::DataSource, rerun_tools::VisualizeTools};
-use brush_dataset::{brush_vfs::BrushVfs, splat_import, Dataset};
+use crate::{data_source::DataSource, remote, rerun_tools::VisualizeTools};
+use brush_dataset::{
+    brush_vfs::BrushVfs,
+    splat_align,
+    splat_chunks::{ChunkIndex, CHUNK_INDEX_NAME},
+    splat_import, Dataset,
+};
 use brush_render::gaussian_splats::{RandomSplatsConfig, Splats};
-use brush_train::train::{RefineStats, TrainStepStats};
+use brush_train::metrics::{MetricsLog, StepMetrics};
+use brush_train::train::{RefineStats, TrainStepStats, TrainTweaks};
 use burn::{backend::Autodiff, module::AutodiffModule, prelude::Backend};
 use burn_wgpu::{Wgpu, WgpuDevice, WgpuRuntime};
 use glam::Vec3;
 use rand::SeedableRng;
+use tokio::io::AsyncReadExt;
 use tokio::sync::mpsc::{channel, UnboundedSender};
 use tokio::sync::mpsc::{unbounded_channel, Receiver};
 use tokio::sync::mpsc::{Sender, UnboundedReceiver};
 use tokio_stream::StreamExt;
 
-#[allow(unused)]
-use brush_dataset::splat_export;
+#[cfg(not(target_family = "wasm"))]
+use brush_render::color_lut::ColorLut;
+
+// Only used to export while training, which isn't supported on WASM.
+#[cfg(not(target_family = "wasm"))]
+use brush_dataset::{
+    splat_chunks, splat_compress, splat_export, splat_floaters, splat_gltf, splat_holes,
+    splat_ksplat, splat_mesh, splat_normals, splat_spz, splat_usdz,
+};
 
 use super::{
-    train_stream::{self, train_stream},
+    train_stream::{self, train_stream, TrainCommand},
     ProcessArgs,
 };
 
@@ -43,6 +57,13 @@ pub enum ProcessMessage {
         frame: usize,
         total_frames: usize,
     },
+    /// Opened a chunked scene, that is too big to load at once. Chunks are loaded from the vfs
+    /// as the camera moves around, with at most `budget` splats loaded at a time.
+    ViewChunks {
+        index: ChunkIndex,
+        vfs: BrushVfs,
+        budget: usize,
+    },
     /// Loaded a bunch of viewpoints to train on.
     Dataset {
         data: Dataset,
@@ -72,12 +93,21 @@ pub enum ProcessMessage {
         iter: u32,
         avg_psnr: f32,
         avg_ssim: f32,
+        avg_lpips: f32,
     },
 }
 
 #[derive(Debug, Clone)]
 pub enum ControlMessage {
     Paused(bool),
+    /// Train a single step while paused.
+    Step,
+    /// Change the learning rates and loss weights from the next step on.
+    Tweak(TrainTweaks),
+    /// Densify the splats at the next step, whether or not it's time to.
+    Refine,
+    /// Prune the transparent splats at the next step.
+    Prune,
 }
 
 async fn process_loop(
@@ -91,6 +121,9 @@ async fn process_loop(
         return;
     }
 
+    // Only renders a benchmark the first time on a GPU.
+    brush_render::tile_autotune::autotune_tile_width::<Wgpu>(&device).await;
+
     let vfs = source.into_vfs().await;
 
     let vfs = match vfs {
@@ -104,11 +137,18 @@ async fn process_loop(
     let paths: Vec<_> = vfs.file_names().collect();
     log::info!("Mounted VFS with {} files", paths.len());
 
-    let result = if paths
+    let chunk_index = paths
+        .iter()
+        .find(|p| p.file_name().is_some_and(|n| n == CHUNK_INDEX_NAME))
+        .cloned();
+
+    let result = if let Some(index_path) = chunk_index {
+        view_chunks(index_path, output.clone(), vfs, &args).await
+    } else if paths
         .iter()
         .all(|p| p.extension().is_some_and(|p| p == "ply"))
     {
-        view_process_loop(paths, output.clone(), vfs, device).await
+        view_process_loop(paths, output.clone(), vfs, device, &args).await
     } else {
         train_process_loop(output.clone(), vfs, device, control_receiver, &args).await
     };
@@ -118,13 +158,59 @@ async fn process_loop(
     }
 }
 
+async fn view_chunks(
+    index_path: std::path::PathBuf,
+    output: Sender<ProcessMessage>,
+    vfs: BrushVfs,
+    process_args: &ProcessArgs,
+) -> Result<(), anyhow::Error> {
+    let mut vfs = vfs;
+    let _ = output
+        .send(ProcessMessage::StartLoading { training: false })
+        .await;
+
+    let mut json = String::new();
+    vfs.open_path(&index_path)
+        .await?
+        .read_to_string(&mut json)
+        .await?;
+    let mut index = ChunkIndex::from_json(&json).context("Failed to parse chunk index")?;
+    log::info!(
+        "Opened chunked scene with {} chunks, {} splats",
+        index.chunks.len(),
+        index.total_splats()
+    );
+
+    // Chunk paths are relative to the index, make them relative to the vfs.
+    if let Some(parent) = index_path.parent() {
+        for chunk in &mut index.chunks {
+            chunk.path = parent.join(&chunk.path).to_string_lossy().into_owned();
+        }
+    }
+
+    let _ = output
+        .send(ProcessMessage::ViewChunks {
+            index,
+            vfs,
+            budget: process_args.process_config.chunk_budget,
+        })
+        .await;
+    let _ = output
+        .send(ProcessMessage::DoneLoading { training: false })
+        .await;
+    Ok(())
+}
+
 async fn view_process_loop(
     paths: Vec<std::path::PathBuf>,
     output: Sender<ProcessMessage>,
     vfs: BrushVfs,
     device: WgpuDevice,
+    process_args: &ProcessArgs,
 ) -> Result<(), anyhow::Error> {
     let mut vfs = vfs;
+    // Use the same alignment for all frames, so animations don't jump around.
+    let mut alignment = None;
 
     for (i, path) in paths.iter().enumerate() {
         log::info!("Loading single ply file");
@@ -157,10 +243,25 @@ async fn view_process_loop(
                 (i, paths.len())
             };
 
+            let (splats, up_axis) = if process_args.process_config.align_scene {
+                let transform = match alignment {
+                    Some(transform) => transform,
+                    None => {
+                        let up = message.meta.up_axis.unwrap_or(Vec3::NEG_Y);
+                        let transform =
+                            splat_align::estimate_splat_alignment(&message.splats, up).await?;
+                        *alignment.insert(transform)
+                    }
+                };
+                (message.splats.transformed(transform), Some(Vec3::NEG_Y))
+            } else {
+                (message.splats, message.meta.up_axis)
+            };
+
             if output
                 .send(ProcessMessage::ViewSplats {
-                    up_axis: message.meta.up_axis,
-                    splats: Box::new(message.splats),
+                    up_axis,
+                    splats: Box::new(splats),
                     frame,
                     total_frames,
                 })
@@ -178,6 +279,56 @@ async fn view_process_loop(
     Ok(())
 }
 
+/// Reload the dataset whenever the files in `dir` change, and send the new datasets.
+#[cfg(not(target_family = "wasm"))]
+fn watch_dataset(
+    dir: std::path::PathBuf,
+    load_config: brush_dataset::LoadDataseConfig,
+    transform: Option<glam::Affine3A>,
+    device: WgpuDevice,
+    sender: UnboundedSender<Dataset>,
+) {
+    log::info!("Watching {dir:?} for new images");
+
+    tokio::task::spawn(async move {
+        let changes =
+            brush_dataset::brush_vfs::watch_directory(dir, std::time::Duration::from_secs(2));
+        let mut changes = std::pin::pin!(changes);
+
+        while let Some(vfs) = changes.next().await {
+            let reloaded = async {
+                let (_, mut data_stream) =
+                    brush_dataset::load_dataset::<Wgpu>(vfs?, &load_config, &device).await?;
+                let mut dataset = None;
+                while let Some(d) = data_stream.next().await {
+                    dataset = Some(d?);
+                }
+                dataset.context("No dataset in directory")
+            };
+
+            match reloaded.await {
+                Ok(dataset) => {
+                    // Keep the scene in the same space as the splats.
+                    let dataset = match transform {
+                        Some(transform) => dataset.transformed(transform),
+                        None => dataset,
+                    };
+                    if dataset.train.views.is_empty() {
+                        continue;
+                    }
+                    // Stop watching once training is done.
+                    if sender.send(dataset).is_err() {
+                        break;
+                    }
+                }
+                // The capture might be halfway through writing some files, try again on the next
+                // change.
+                Err(e) => log::warn!("Failed to reload dataset: {e:#}"),
+            }
+        }
+    });
+}
+
 async fn train_process_loop(
     output: Sender<ProcessMessage>,
     vfs: BrushVfs,
@@ -201,9 +352,14 @@ async fn train_process_loop(
     let (mut splat_stream, mut data_stream) =
         brush_dataset::load_dataset(vfs.clone(), &process_args.load_config, &device).await?;
 
-    let visualize = VisualizeTools::new(process_args.rerun_config.rerun_enabled);
+    let visualize = VisualizeTools::new(
+        process_args.rerun_config.rerun_enabled,
+        process_args.rerun_config.rerun_save_path.as_deref(),
+    );
 
-    // Read dataset stream.
+    // Read dataset stream, or only the first views if training can start before the rest are
+    // loaded.
+    let mut loading_views = false;
     while let Some(d) = data_stream.next().await {
         dataset = d.context("Failed to parse dataset. \n")?;
 
@@ -212,10 +368,47 @@ async fn train_process_loop(
                 data: dataset.clone(),
             })
             .await;
+
+        if process_config
+            .train_after_views
+            .is_some_and(|views| dataset.train.views.len() >= views)
+        {
+            log::info!(
+                "Starting to train on {} views, loading the rest while training",
+                dataset.train.views.len()
+            );
+            loading_views = true;
+            break;
+        }
     }
 
     visualize.log_scene(&dataset.train, process_args.rerun_config.rerun_max_img_size)?;
 
+    // Keep the metrics of the last steps around, to write them to TensorBoard and rerun.
+    let mut metrics = MetricsLog::new(100);
+    #[cfg(not(target_family = "wasm"))]
+    if let Some(dir) = process_config.metrics_dir.as_ref() {
+        let dir = Path::new(process_config.export_path.as_deref().unwrap_or(".")).join(dir);
+        let writer = brush_train::tensorboard::TensorBoardWriter::create(&dir)
+            .with_context(|| format!("Failed to create metrics log in {dir:?}"))?;
+        metrics = metrics.with_sink(writer);
+    }
+    #[cfg(not(target_family = "wasm"))]
+    if let Some(sink) = visualize.metrics_sink() {
+        metrics = metrics.with_sink(sink);
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    let color_lut = match process_config.color_lut.as_ref() {
+        Some(path) => {
+            let cube = tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Failed to read color LUT {path}"))?;
+            Some(ColorLut::from_cube(&cube).with_context(|| format!("Invalid color LUT {path}"))?)
+        }
+        None => None,
+    };
+
     let estimated_up = dataset.estimate_up();
 
     // Read initial splats if any.
@@ -235,6 +428,44 @@ async fn train_process_loop(
         initial_splats = Some(message.splats);
     }
 
+    let mut scene_transform = None;
+    if process_config.align_scene {
+        // Level the ground plane using the initial splats. Without splats there are no
+        // surfaces to find the ground on, so only center the scene on the cameras.
+        let transform = if let Some(splats) = &initial_splats {
+            splat_align::estimate_splat_alignment(splats, estimated_up).await?
+        } else {
+            let positions: Vec<_> = dataset
+                .train
+                .views
+                .iter()
+                .map(|v| v.camera.position)
+                .collect();
+            splat_align::estimate_centering(&positions, estimated_up)
+        };
+
+        dataset = dataset.transformed(transform);
+        initial_splats = initial_splats.map(|s| s.transformed(transform));
+        scene_transform = Some(transform);
+
+        let _ = output
+            .send(ProcessMessage::Dataset {
+                data: dataset.clone(),
+            })
+            .await;
+        if let Some(splats) = &initial_splats {
+            let msg = ProcessMessage::ViewSplats {
+                up_axis: Some(Vec3::NEG_Y),
+                splats: Box::new(splats.valid()),
+                frame: 0,
+                total_frames: 0,
+            };
+            if output.send(msg).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+
     let _ = output
         .send(ProcessMessage::DoneLoading { training: true })
         .await;
@@ -259,30 +490,135 @@ async fn train_process_loop(
 
     let mut control_receiver = control_receiver;
 
-    let eval_scene = dataset.eval.clone();
+    let mut eval_scene = dataset.eval.clone();
+    #[cfg(not(target_family = "wasm"))]
+    let mut eval_log = super::eval_log::EvalLog::default();
+    #[cfg(not(target_family = "wasm"))]
+    let mut train_scene = dataset.train.clone();
+
+    let mut extra_devices = vec![];
+    for &gpu in &process_args.process_config.train_gpus {
+        log::info!("Also training on discrete GPU {gpu}");
+        extra_devices.push(brush_render::burn_init_discrete_gpu(gpu).await);
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    let resume = match process_config.resume.as_ref() {
+        Some(path) => Some(
+            tokio::fs::read(path)
+                .await
+                .with_context(|| format!("Failed to read checkpoint {path}"))?,
+        ),
+        None => None,
+    };
+    #[cfg(target_family = "wasm")]
+    let resume = None;
+
+    // Datasets reloaded from a watched directory, and the train scenes of those for the trainer.
+    let (dataset_sender, mut dataset_updates) = unbounded_channel::<Dataset>();
+    let (scene_sender, scene_updates) = unbounded_channel();
+    let (command_sender, train_commands) = unbounded_channel();
+    if loading_views {
+        let sender = dataset_sender.clone();
+        tokio_with_wasm::alias::task::spawn(async move {
+            while let Some(d) = data_stream.next().await {
+                match d {
+                    Ok(dataset) => {
+                        // Keep the scene in the same space as the splats.
+                        let dataset = match scene_transform {
+                            Some(transform) => dataset.transformed(transform),
+                            None => dataset,
+                        };
+                        // Stop loading once training is done.
+                        if sender.send(dataset).is_err() {
+                            break;
+                        }
+                    }
+                    // Training is already going, so carry on without this view.
+                    Err(e) => log::warn!("Failed to load view: {e:#}"),
+                }
+            }
+        });
+    }
+    #[cfg(not(target_family = "wasm"))]
+    if process_config.watch_dataset {
+        match vfs.directory() {
+            Some(dir) => watch_dataset(
+                dir.to_path_buf(),
+                process_args.load_config.clone(),
+                scene_transform,
+                device.clone(),
+                dataset_sender,
+            ),
+            None => log::warn!("Only a dataset directory can be watched for new images"),
+        }
+    }
+    #[cfg(target_family = "wasm")]
+    let _ = (dataset_sender, scene_transform);
+
     let stream = train_stream(
         dataset,
         splats,
         process_args.train_config.clone(),
         device.clone(),
+        extra_devices,
+        process_config.deterministic,
+        train_stream::CheckpointArgs {
+            every: process_config.checkpoint_every,
+            resume,
+        },
+        scene_updates,
+        train_commands,
     );
     let mut stream = std::pin::pin!(stream);
 
     let mut train_paused = false;
 
     loop {
-        let control = if train_paused {
-            control_receiver.recv().await
-        } else {
-            control_receiver.try_recv().ok()
-        };
+        // While paused, wait for controls until training resumes or a single step is asked for.
+        let mut single_step = false;
+        loop {
+            let control = if train_paused && !single_step {
+                control_receiver.recv().await
+            } else {
+                control_receiver.try_recv().ok()
+            };
+            let Some(control) = control else {
+                break;
+            };
 
-        if let Some(control) = control {
             match control {
-                ControlMessage::Paused(paused) => {
-                    train_paused = paused;
+                ControlMessage::Paused(paused) => train_paused = paused,
+                ControlMessage::Step => single_step = true,
+                ControlMessage::Tweak(tweaks) => {
+                    let _ = command_sender.send(TrainCommand::Tweak(tweaks));
                 }
+                ControlMessage::Refine => {
+                    let _ = command_sender.send(TrainCommand::Refine);
+                }
+                ControlMessage::Prune => {
+                    let _ = command_sender.send(TrainCommand::Prune);
+                }
+            }
+        }
+
+        // Every update has the whole dataset, so only the latest one matters.
+        let mut updated = None;
+        while let Ok(dataset) = dataset_updates.try_recv() {
+            updated = Some(dataset);
+        }
+        if let Some(dataset) = updated {
+            log::info!(
+                "Training on {} views of the updated dataset",
+                dataset.train.views.len()
+            );
+            eval_scene = dataset.eval.clone();
+            #[cfg(not(target_family = "wasm"))]
+            {
+                train_scene = dataset.train.clone();
             }
+            let _ = scene_sender.send(dataset.train.clone());
+            let _ = output.send(ProcessMessage::Dataset { data: dataset }).await;
         }
 
         let msg = stream.next().await;
@@ -315,7 +651,10 @@ async fn train_process_loop(
                     if let Some(eval_scene) = eval_scene.as_ref() {
                         let mut psnr = 0.0;
                         let mut ssim = 0.0;
+                        let mut lpips = 0.0;
                         let mut count = 0;
+                        #[cfg(not(target_family = "wasm"))]
+                        let mut views = vec![];
 
                         log::info!("Running evaluation for iteration {iter}");
 
@@ -327,16 +666,31 @@ async fn train_process_loop(
                             &device,
                         ) {
                             count += 1;
-                            psnr += sample.psnr.clone().into_scalar_async().await;
-                            ssim += sample.ssim.clone().into_scalar_async().await;
+                            let sample_psnr = sample.psnr.clone().into_scalar_async().await;
+                            let sample_ssim = sample.ssim.clone().into_scalar_async().await;
+                            let sample_lpips = sample.lpips.clone().into_scalar_async().await;
+                            psnr += sample_psnr;
+                            ssim += sample_ssim;
+                            lpips += sample_lpips;
+                            #[cfg(not(target_family = "wasm"))]
+                            views.push(super::eval_log::ViewMetrics {
+                                view: sample.view.path.clone(),
+                                psnr: sample_psnr,
+                                ssim: sample_ssim,
+                                lpips: sample_lpips,
+                            });
                             visualize.log_eval_sample(iter, &sample).await?;
 
                             #[cfg(not(target_family = "wasm"))]
                             if process_args.process_config.eval_save_to_disk {
                                 log::info!("Saving eval image to disk.");
 
+                                let rendered = match color_lut.as_ref() {
+                                    Some(lut) => lut.apply(sample.rendered.clone()),
+                                    None => sample.rendered.clone(),
+                                };
                                 let eval_render = brush_train::image::tensor_into_image(
-                                    sample.rendered.clone().into_data_async().await,
+                                    rendered.into_data_async().await,
                                 );
                                 let rendered: image::DynamicImage = eval_render.to_rgb8().into();
 
@@ -360,14 +714,37 @@ async fn train_process_loop(
 
                         psnr /= count as f32;
                         ssim /= count as f32;
+                        lpips /= count as f32;
 
-                        visualize.log_eval_stats(iter, psnr, ssim)?;
+                        visualize.log_eval_stats(iter, psnr, ssim, lpips)?;
+
+                        #[cfg(not(target_family = "wasm"))]
+                        if let Some(log_name) = process_config.eval_log.as_ref() {
+                            eval_log.push(super::eval_log::EvalEntry {
+                                iter,
+                                psnr,
+                                ssim,
+                                lpips,
+                                views,
+                            });
+                            let path = export_path.join(log_name);
+                            let contents = if path.extension().is_some_and(|ext| ext == "json") {
+                                eval_log.to_json()?
+                            } else {
+                                eval_log.to_csv()
+                            };
+                            if let Some(parent) = path.parent() {
+                                tokio::fs::create_dir_all(parent).await?;
+                            }
+                            tokio::fs::write(&path, contents).await?;
+                        }
 
                         if output
                             .send(ProcessMessage::EvalResult {
                                 iter,
                                 avg_psnr: psnr,
                                 avg_ssim: ssim,
+                                avg_lpips: lpips,
                             })
                             .await
                             .is_err()
@@ -387,6 +764,62 @@ async fn train_process_loop(
                     let splats = *splats.clone();
                     let output_send = output.clone();
 
+                    let hole_config = &process_args.hole_config;
+                    let splats = if is_last_step && hole_config.fill_holes {
+                        let (seeded, count) =
+                            splat_holes::seed_holes(&splats, &train_scene, hole_config).await?;
+                        if count > 0 {
+                            log::info!(
+                                "Seeded {count} splats in holes, training them for {} steps",
+                                hole_config.hole_finetune_steps
+                            );
+                            let splats = train_stream::finetune(
+                                &train_scene,
+                                seeded,
+                                &process_args.train_config,
+                                hole_config.hole_finetune_steps,
+                                &device,
+                            )
+                            .await;
+                            let _ = output
+                                .send(ProcessMessage::ViewSplats {
+                                    up_axis: None,
+                                    splats: Box::new(splats.clone()),
+                                    frame: 0,
+                                    total_frames: 1,
+                                })
+                                .await;
+                            splats
+                        } else {
+                            splats
+                        }
+                    } else {
+                        splats
+                    };
+
+                    // Intermediate exports can use a lower SH degree, as cheaper previews.
+                    let sh_degree = if is_last_step {
+                        process_config.export_sh_degree
+                    } else {
+                        process_config
+                            .export_preview_sh_degree
+                            .or(process_config.export_sh_degree)
+                    };
+                    let splats = if let Some(sh_degree) = sh_degree {
+                        splats.with_sh_degree(sh_degree)
+                    } else {
+                        splats
+                    };
+
+                    let floater_config = &process_args.floater_config;
+                    let splats = if floater_config.remove_floaters {
+                        let cameras: Vec<_> =
+                            train_scene.views.iter().map(|v| v.camera.clone()).collect();
+                        splat_floaters::remove_floaters(splats, &cameras, floater_config).await?
+                    } else {
+                        splats
+                    };
+
                     let total_steps = process_args.train_config.total_steps;
 
                     // Ad-hoc format string.
@@ -397,29 +830,143 @@ async fn train_process_loop(
 
                     tokio::fs::create_dir_all(&export_path).await?;
 
-                    // Nb: this COULD easily be done in the spawned future as well,
-                    // but for memory reasons it's not great to keep another copy of the
-                    // field.
-                    let splat_data = splat_export::splat_to_ply(splats).await?;
-
-                    tokio::task::spawn(async move {
-                        if let Err(e) = tokio::fs::write(export_path.join(&export_name), splat_data)
+                    let crop = brush_render::crop::CropVolume::new(process_config.crop.clone());
+
+                    let mut mesh = None;
+                    if is_last_step && process_args.mesh_config.export_mesh {
+                        log::info!("Extracting mesh");
+                        let views: Vec<_> = train_scene
+                            .views
+                            .iter()
+                            .map(|v| {
+                                let size = glam::uvec2(v.image.width(), v.image.height());
+                                (v.camera.clone(), size)
+                            })
+                            .collect();
+                        let mesh_splats = splats.clone().cropped(&crop).await;
+                        let extracted = splat_mesh::extract_mesh(
+                            &mesh_splats,
+                            &views,
+                            &process_args.mesh_config,
+                        )
+                        .await?;
+                        let mesh_name = Path::new(&export_name).with_extension("obj");
+                        tokio::fs::write(export_path.join(&mesh_name), extracted.to_obj())
                             .await
-                            .with_context(|| format!("Failed to export ply {export_path:?}"))
-                        {
-                            let _ = output_send.send(ProcessMessage::Error(e)).await;
-                        }
-                    });
-                }
+                            .with_context(|| format!("Failed to export mesh {mesh_name:?}"))?;
+                        mesh = Some(extracted);
+                    }
 
-                if let Some(every) = process_args.rerun_config.rerun_log_splats_every {
-                    if iter % every == 0 || is_last_step {
-                        visualize.log_splats(iter, *splats.clone()).await?;
+                    let compress_config = &process_args.compress_config;
+                    let export_file = export_path.join(&export_name);
+                    let plain_ply = !compress_config.compress
+                        && !export_name.ends_with(".glb")
+                        && !export_name.ends_with(".usdz")
+                        && !export_name.ends_with(".spz")
+                        && !export_name.ends_with(".ksplat")
+                        && !process_config.export_normals;
+
+                    if let Some(chunk_splats) =
+                        process_config.export_chunk_splats.filter(|_| plain_ply)
+                    {
+                        let splats = splats.cropped(&crop).await;
+                        let dir = export_file.with_extension("");
+                        tokio::task::spawn(async move {
+                            if let Err(e) = splat_chunks::write_chunked(&splats, &dir, chunk_splats)
+                                .await
+                                .with_context(|| format!("Failed to export chunks {dir:?}"))
+                            {
+                                let _ = output_send.send(ProcessMessage::Error(e)).await;
+                            }
+                        });
+                    } else if plain_ply {
+                        // Write plain ply files progressively in the background, so training
+                        // doesn't stall on reading back the whole model at once. This keeps a
+                        // copy of the splats around until the export is done.
+                        let splats = splats.cropped(&crop).await;
+                        tokio::task::spawn(async move {
+                            if let Err(e) = write_ply_progressive(splats, &export_file)
+                                .await
+                                .with_context(|| format!("Failed to export splats {export_file:?}"))
+                            {
+                                let _ = output_send.send(ProcessMessage::Error(e)).await;
+                            }
+                        });
+                    } else {
+                        // Nb: this COULD easily be done in the spawned future as well,
+                        // but for memory reasons it's not great to keep another copy of the
+                        // field.
+                        let splat_data = if compress_config.compress {
+                            let splats = if is_last_step && compress_config.finetune_steps > 0 {
+                                log::info!(
+                                    "Fine-tuning quantized splats for {} steps",
+                                    compress_config.finetune_steps
+                                );
+                                train_stream::finetune_quantized(
+                                    &train_scene,
+                                    splats,
+                                    &process_args.train_config,
+                                    compress_config,
+                                    &device,
+                                )
+                                .await
+                            } else {
+                                splats
+                            };
+                            // Crop after fine-tuning, as the cropped splats won't match the
+                            // training views anymore.
+                            let splats = splats.cropped(&crop).await;
+                            splat_compress::splat_to_compressed_ply(splats, compress_config).await?
+                        } else if export_name.ends_with(".glb") {
+                            splat_gltf::splat_to_glb(splats.cropped(&crop).await, mesh.as_ref())
+                                .await?
+                        } else if export_name.ends_with(".usdz") {
+                            splat_usdz::splat_to_usdz(splats.cropped(&crop).await, mesh.as_ref())
+                                .await?
+                        } else if export_name.ends_with(".spz") {
+                            splat_spz::splat_to_spz(splats.cropped(&crop).await).await?
+                        } else if export_name.ends_with(".ksplat") {
+                            splat_ksplat::splat_to_ksplat(splats.cropped(&crop).await).await?
+                        } else {
+                            let splats = splats.cropped(&crop).await;
+                            let cameras: Vec<_> =
+                                train_scene.views.iter().map(|v| v.camera.clone()).collect();
+                            let normals =
+                                splat_normals::estimate_normals(&splats, &cameras).await?;
+                            splat_export::splat_to_ply_with_normals(splats, Some(&normals)).await?
+                        };
+
+                        tokio::task::spawn(async move {
+                            if let Err(e) = tokio::fs::write(&export_file, splat_data)
+                                .await
+                                .with_context(|| format!("Failed to export splats {export_path:?}"))
+                            {
+                                let _ = output_send.send(ProcessMessage::Error(e)).await;
+                            }
+                        });
                     }
                 }
 
+                let rerun_config = &process_args.rerun_config;
+                let log_splats = if let Some(every) = rerun_config.rerun_log_splats_every {
+                    iter % every == 0 || is_last_step
+                } else {
+                    // Always keep the final splats in saved recordings.
+                    is_last_step && rerun_config.rerun_save_path.is_some()
+                };
+                if log_splats {
+                    visualize.log_splats(iter, *splats.clone()).await?;
+                }
+
                 visualize.log_splat_stats(iter, &splats)?;
 
+                if metrics.has_sinks()
+                    && (iter % process_config.metrics_every.max(1) == 0 || is_last_step)
+                {
+                    let step = StepMetrics::read_async(iter, &*stats, splats.num_splats()).await;
+                    metrics.record(step)?;
+                }
+
                 // Log out train stats.
                 if iter % process_args.rerun_config.rerun_log_train_stats_every == 0 || is_last_step
                 {
@@ -458,12 +1005,48 @@ async fn train_process_loop(
                     break;
                 }
             }
+            #[allow(unused)]
+            train_stream::TrainMessage::Checkpoint { data, iter } => {
+                #[cfg(not(target_family = "wasm"))]
+                {
+                    let export_path =
+                        Path::new(process_config.export_path.as_deref().unwrap_or("."));
+                    tokio::fs::create_dir_all(export_path).await?;
+                    let path = export_path.join(CHECKPOINT_NAME);
+                    // Write next to the checkpoint first, so a crash while writing doesn't
+                    // lose the previous checkpoint.
+                    let partial = path.with_extension("partial");
+                    tokio::fs::write(&partial, data)
+                        .await
+                        .with_context(|| format!("Failed to write checkpoint {partial:?}"))?;
+                    tokio::fs::rename(&partial, &path).await?;
+                    log::info!("Saved checkpoint for step {iter} to {path:?}");
+                }
+            }
         }
     }
 
     Ok(())
 }
 
+// File name of training checkpoints in the export path.
+#[cfg(not(target_family = "wasm"))]
+const CHECKPOINT_NAME: &str = "checkpoint.safetensors";
+
+// Nr. of splats read back and written at once for progressive exports.
+#[cfg(not(target_family = "wasm"))]
+const EXPORT_CHUNK_SIZE: usize = 1 << 18;
+
+// Write the ply next to the final file first, and only move it in place when it's complete, so
+// there's never a partially written file at the export path.
+#[cfg(not(target_family = "wasm"))]
+async fn write_ply_progressive(splats: Splats<Wgpu>, path: &Path) -> anyhow::Result<()> {
+    let partial = path.with_extension("ply.partial");
+    brush_dataset::splat_ply_mmap::write_ply_mmap(splats, &partial, EXPORT_CHUNK_SIZE).await?;
+    tokio::fs::rename(&partial, path).await?;
+    Ok(())
+}
+
 pub struct RunningProcess {
     pub start_args: ProcessArgs,
     pub messages: Receiver<ProcessMessage>,
@@ -473,6 +1056,12 @@ pub struct RunningProcess {
 pub fn start_process(source: DataSource, args: ProcessArgs, device: WgpuDevice) -> RunningProcess {
     log::info!("Starting process with source {:?}", source);
 
+    if let DataSource::Url(url) = &source {
+        if remote::is_remote_url(url) {
+            return remote::connect_remote(url, args, device);
+        }
+    }
+
     // Create a small channel. We don't want 10 updated splats to be stuck in the queue eating up memory!
     // Bigger channels could mean the train loop spends less time waiting for the UI though.
     // create a channel for the train loop.
@@ -1,4 +1,8 @@
-use brush_dataset::{LoadDataseConfig, ModelConfig};
+use brush_dataset::{
+    splat_compress::CompressConfig, splat_floaters::FloaterConfig, splat_holes::HoleFillConfig,
+    splat_mesh::MeshConfig, LoadDataseConfig, ModelConfig,
+};
+use brush_render::crop::CropLayer;
 use brush_train::train::TrainConfig;
 use burn::config::Config;
 use clap::Args;
@@ -9,6 +13,11 @@ pub struct ProcessConfig {
     #[config(default = 42)]
     #[arg(long, help_heading = "Process options", default_value = "42")]
     pub seed: u64,
+    /// Render and calculate gradients in a fixed order, so runs with the same seed give bit
+    /// identical results. This is slower, and meant for debugging.
+    #[arg(long, help_heading = "Process options", default_value = "false")]
+    #[config(default = false)]
+    pub deterministic: bool,
     /// Eval every this many steps.
     #[arg(long, help_heading = "Process options", default_value = "1000")]
     #[config(default = 1000)]
@@ -17,6 +26,26 @@ pub struct ProcessConfig {
     #[arg(long, help_heading = "Process options", default_value = "false")]
     #[config(default = false)]
     pub eval_save_to_disk: bool,
+    /// Write the eval results to this file after every eval, relative to export-path. Use a
+    /// .json extension to write JSON with the averages and the results of every view, otherwise
+    /// this writes CSV with a row per view.
+    #[arg(long, help_heading = "Process options")]
+    pub eval_log: Option<String>,
+    /// Write the training metrics (loss, PSNR, splat count, learning rates and kernel timings)
+    /// as TensorBoard event files to this folder, relative to export-path. Kernel timings are
+    /// only recorded while sync spans are enabled. With rerun enabled, the metrics are also
+    /// streamed to rerun.
+    #[arg(long, help_heading = "Process options")]
+    pub metrics_dir: Option<String>,
+    /// Record the training metrics every this many steps. Reading back the metrics waits for
+    /// the GPU, so recording every step slows down training.
+    #[arg(long, help_heading = "Process options", default_value = "10")]
+    #[config(default = 10)]
+    pub metrics_every: u32,
+    /// Color grade rendered images with a 3D LUT from a .cube file. This applies to saved eval
+    /// images, and to the viewer.
+    #[arg(long, help_heading = "Process options")]
+    pub color_lut: Option<String>,
 
     /// Export every this many steps.
     #[arg(long, help_heading = "Process options", default_value = "5000")]
@@ -29,7 +58,9 @@ pub struct ProcessConfig {
     #[arg(long, help_heading = "Process options")]
     pub export_path: Option<String>,
 
-    /// Filename of exported ply file
+    /// Filename of exported ply file. Use a .glb extension to export a glTF file, or .usdz to
+    /// export a USDZ file (both include the extracted mesh when --export-mesh is set). Use
+    /// .spz or .ksplat to export compressed files for web viewers.
     #[arg(
         long,
         help_heading = "Process options",
@@ -37,6 +68,75 @@ pub struct ProcessConfig {
     )]
     #[config(default = "String::from(\"./export_{iter}.ply\")")]
     pub export_name: String,
+
+    /// Level the ground plane of the scene and center it at the origin when loading it. The
+    /// cameras are transformed along with the splats.
+    #[arg(long, help_heading = "Process options", default_value = "false")]
+    #[config(default = false)]
+    pub align_scene: bool,
+
+    /// Keep watching a dataset directory while training, and train on images that are added to
+    /// it, eg. during a capture that's still going on.
+    #[arg(long, help_heading = "Process options", default_value = "false")]
+    #[config(default = false)]
+    pub watch_dataset: bool,
+
+    /// Start training once this many train views are loaded, rather than waiting for the whole
+    /// dataset. The rest of the views are added while training, and are picked more often for a
+    /// while after they're added.
+    #[arg(long, help_heading = "Process options")]
+    pub train_after_views: Option<usize>,
+
+    /// Estimate normals from the training views and include them in exported ply files.
+    #[arg(long, help_heading = "Process options", default_value = "false")]
+    #[config(default = false)]
+    pub export_normals: bool,
+
+    /// Convert exported splats to this SH degree, eg. to export smaller files for the web.
+    /// Higher order coefficients are dropped, or padded with zeros.
+    #[arg(long, help_heading = "Process options")]
+    pub export_sh_degree: Option<u32>,
+
+    /// Export plain ply files as a chunked scene instead, with at most this many splats per
+    /// chunk. The chunks are written to a directory named after the export, and can be viewed
+    /// even when the whole scene doesn't fit in memory.
+    #[arg(long, help_heading = "Process options")]
+    pub export_chunk_splats: Option<usize>,
+
+    /// Max nr. of splats of a chunked scene to keep loaded in the viewer.
+    #[arg(long, help_heading = "Process options", default_value = "8000000")]
+    #[config(default = 8000000)]
+    pub chunk_budget: usize,
+
+    /// Export intermediate checkpoints at this SH degree, as smaller and faster previews. The
+    /// final export still uses `export_sh_degree`.
+    #[arg(long, help_heading = "Process options")]
+    pub export_preview_sh_degree: Option<u32>,
+
+    /// Crop exported splats to a volume. Can be given multiple times to combine shapes, in
+    /// the form `[union|intersect|subtract:]shape:values`, eg. `box:0,0,0,1,1,1` or
+    /// `subtract:sphere:0,1,0,0.5`. Shapes are `box:cx,cy,cz,ex,ey,ez[,qx,qy,qz,qw]`,
+    /// `sphere:cx,cy,cz,radius` and `plane:nx,ny,nz,offset`.
+    #[arg(long, help_heading = "Process options")]
+    #[config(default = "Vec::new()")]
+    pub crop: Vec<CropLayer>,
+
+    /// Indices of more discrete GPUs to train on, eg. `--train-gpus 1,2`. Every GPU renders a
+    /// different view each step, and the gradients are averaged. Densification only uses the
+    /// views of the main GPU.
+    #[arg(long, help_heading = "Process options", value_delimiter = ',')]
+    #[config(default = "Vec::new()")]
+    pub train_gpus: Vec<usize>,
+
+    /// Save a checkpoint of the training state every this many steps, to resume training with
+    /// --resume. The checkpoint is written to the export path, and replaced every time.
+    #[arg(long, help_heading = "Process options")]
+    pub checkpoint_every: Option<u32>,
+
+    /// Resume training from a checkpoint. The dataset has to be the same as the checkpoint was
+    /// trained on.
+    #[arg(long, help_heading = "Process options")]
+    pub resume: Option<String>,
 }
 
 #[derive(Config, Args)]
@@ -56,6 +156,11 @@ pub struct RerunConfig {
     #[arg(long, help_heading = "Rerun options", default_value = "512")]
     #[config(default = 512)]
     pub rerun_max_img_size: u32,
+    /// Save the rerun recording to this .rrd file instead of streaming it to a viewer. The
+    /// recording includes the dataset cameras, eval renders, training curves and the final
+    /// splats, so it can be archived or shared.
+    #[arg(long, help_heading = "Rerun options")]
+    pub rerun_save_path: Option<String>,
 }
 
 #[derive(Config, Args)]
@@ -70,6 +175,14 @@ pub struct ProcessArgs {
     pub process_config: ProcessConfig,
     #[clap(flatten)]
     pub rerun_config: RerunConfig,
+    #[clap(flatten)]
+    pub compress_config: CompressConfig,
+    #[clap(flatten)]
+    pub mesh_config: MeshConfig,
+    #[clap(flatten)]
+    pub floater_config: FloaterConfig,
+    #[clap(flatten)]
+    pub hole_config: HoleFillConfig,
 }
 
 impl Default for ProcessArgs {
@@ -80,6 +193,10 @@ impl Default for ProcessArgs {
             load_config: LoadDataseConfig::new(),
             process_config: ProcessConfig::new(),
             rerun_config: RerunConfig::new(),
+            compress_config: CompressConfig::new(),
+            mesh_config: MeshConfig::new(),
+            floater_config: FloaterConfig::new(),
+            hole_config: HoleFillConfig::new(),
         }
     }
 }
@@ -2,13 +2,22 @@
 use async_fn_stream::try_fn_stream;
 
 use brush_dataset::{scene_loader::SceneLoader, Dataset};
-use brush_render::gaussian_splats::Splats;
-use brush_train::train::{RefineStats, SplatTrainer, TrainConfig, TrainStepStats};
+use brush_render::{gaussian_splats::Splats, RenderStats};
+use brush_train::{
+    scene::Scene,
+    train::{RefineStats, SplatTrainer, TrainConfig, TrainStepStats, TrainTweaks},
+};
 use burn::{backend::Autodiff, module::AutodiffModule};
 use burn_wgpu::{Wgpu, WgpuDevice};
+use tokio::sync::mpsc::UnboundedReceiver;
 use tokio_stream::Stream;
 use web_time::Instant;
 
+#[cfg(not(target_family = "wasm"))]
+use brush_dataset::splat_compress::{quantize_splats, CompressConfig};
+#[cfg(not(target_family = "wasm"))]
+use burn::tensor::Tensor;
+
 pub enum TrainMessage {
     TrainStep {
         splats: Box<Splats<Wgpu>>,
@@ -20,35 +29,146 @@ pub enum TrainMessage {
         stats: Box<RefineStats>,
         iter: u32,
     },
+    /// A checkpoint to resume training at `iter`.
+    Checkpoint {
+        data: Vec<u8>,
+        iter: u32,
+    },
+}
+
+/// Changes to a running training, eg. from the UI.
+pub(crate) enum TrainCommand {
+    Tweak(TrainTweaks),
+    Refine,
+    Prune,
+}
+
+pub(crate) struct CheckpointArgs {
+    /// Save a checkpoint every this many steps.
+    pub every: Option<u32>,
+    /// Checkpoint to resume from.
+    pub resume: Option<Vec<u8>>,
 }
 
 // False positive: need to pass in TrainConfig by value to keep lifetimes sane.
-#[allow(clippy::needless_pass_by_value)]
+#[allow(clippy::needless_pass_by_value, clippy::too_many_arguments)]
 pub(crate) fn train_stream(
     dataset: Dataset,
     initial_splats: Splats<Autodiff<Wgpu>>,
     config: TrainConfig,
     device: WgpuDevice,
+    extra_devices: Vec<WgpuDevice>,
+    deterministic: bool,
+    checkpoint: CheckpointArgs,
+    mut scene_updates: UnboundedReceiver<Scene>,
+    mut commands: UnboundedReceiver<TrainCommand>,
 ) -> impl Stream<Item = anyhow::Result<TrainMessage>> {
     try_fn_stream(|emitter| async move {
         let mut splats = initial_splats;
 
-        let train_scene = dataset.train.clone();
+        let mut train_scene = dataset.train.clone();
 
         let mut dataloader = SceneLoader::new(&train_scene, 42, &device);
-        let mut trainer = SplatTrainer::new(&splats, &config, &device);
+        // Every device gets its own loader, with a different seed so they see different views.
+        let mut replica_loaders: Vec<_> = extra_devices
+            .iter()
+            .enumerate()
+            .map(|(i, device)| SceneLoader::new(&train_scene, 43 + i as u64, device))
+            .collect();
+        let mut trainer = SplatTrainer::new(&splats, &config, &device)
+            .with_deterministic(deterministic)
+            .with_devices(&extra_devices)
+            .with_appearance(train_scene.views.len(), &device)
+            .with_pose_refinement(train_scene.views.len(), &device);
 
         let mut iter = 0;
 
+        if let Some(data) = checkpoint.resume {
+            let (resume_iter, resumed) = trainer.load_checkpoint(&data, &device)?;
+            log::info!("Resuming training at step {resume_iter}");
+            iter = resume_iter;
+            splats = resumed;
+            // The checkpoint might be of a run that had fewer views loaded.
+            trainer.grow_views(train_scene.views.len());
+        }
+        let mut splats = trainer.with_storage_precision(splats);
+
         #[allow(clippy::infinite_loop)]
         loop {
+            // Train on the new views of an updated scene from now on.
+            while let Ok(scene) = scene_updates.try_recv() {
+                if scene.extends(&train_scene) {
+                    // Views were only added, keep loading and favor the new views for a bit.
+                    dataloader.update_scene(&scene);
+                    for loader in &replica_loaders {
+                        loader.update_scene(&scene);
+                    }
+                    trainer.grow_views(scene.views.len());
+                } else {
+                    dataloader = SceneLoader::new(&scene, 42 + iter as u64, &device);
+                    replica_loaders = extra_devices
+                        .iter()
+                        .enumerate()
+                        .map(|(i, device)| {
+                            SceneLoader::new(&scene, 43 + i as u64 + iter as u64, device)
+                        })
+                        .collect();
+                    // The views are different, start over with new corrections.
+                    trainer = trainer
+                        .with_appearance(scene.views.len(), &device)
+                        .with_pose_refinement(scene.views.len(), &device);
+                }
+                train_scene = scene;
+            }
+
             let batch = dataloader.next_batch().await;
             let extent = batch.scene_extent;
 
-            let (new_splats, stats) = trainer.step(iter, batch, splats);
-            let (new_splats, refine) = trainer.refine_if_needed(iter, new_splats, extent).await;
+            let mut replica_batches = Vec::with_capacity(replica_loaders.len());
+            for loader in &mut replica_loaders {
+                replica_batches.push(loader.next_batch().await);
+            }
+
+            let (new_splats, stats) = trainer.step_parallel(iter, batch, replica_batches, splats);
+            let (new_splats, mut refine) = trainer.refine_if_needed(iter, new_splats, extent).await;
             splats = new_splats;
 
+            // Renders drop the intersections that don't fit in their buffers, which would
+            // silently train on a broken render. Check for that whenever the splats were refined.
+            if refine.is_some() {
+                let render_stats = RenderStats {
+                    intersection_capacity: stats.intersection_capacity,
+                    ..RenderStats::read_async(
+                        stats.num_visible.clone(),
+                        stats.num_intersections.clone(),
+                    )
+                    .await
+                };
+                if render_stats.overflowed() {
+                    anyhow::bail!(
+                        "Too many intersections, Brush currently can't handle this. {} >= {}",
+                        render_stats.num_intersections,
+                        render_stats.intersection_capacity
+                    );
+                }
+            }
+
+            while let Ok(command) = commands.try_recv() {
+                match command {
+                    TrainCommand::Tweak(tweaks) => trainer.set_tweaks(tweaks),
+                    TrainCommand::Refine => {
+                        let (new_splats, stats) = trainer.refine(iter, splats, extent).await;
+                        splats = new_splats;
+                        refine = Some(stats);
+                    }
+                    TrainCommand::Prune => {
+                        let (new_splats, stats) = trainer.prune_transparent(splats).await;
+                        splats = new_splats;
+                        refine = Some(stats);
+                    }
+                }
+            }
+
             emitter
                 .emit(TrainMessage::TrainStep {
                     splats: Box::new(splats.valid()),
@@ -67,7 +187,85 @@ pub(crate) fn train_stream(
                     .await;
             }
 
+            if checkpoint
+                .every
+                .is_some_and(|every| (iter + 1) % every == 0)
+            {
+                let data = trainer.save_checkpoint(iter + 1, &splats).await?;
+                emitter
+                    .emit(TrainMessage::Checkpoint {
+                        data,
+                        iter: iter + 1,
+                    })
+                    .await;
+            }
+
             iter += 1;
         }
     })
 }
+
+/// Briefly train splats, without refining them, eg. to fit newly added splats.
+#[cfg(not(target_family = "wasm"))]
+pub(crate) async fn finetune(
+    scene: &Scene,
+    splats: Splats<Wgpu>,
+    train_config: &TrainConfig,
+    steps: u32,
+    device: &WgpuDevice,
+) -> Splats<Wgpu> {
+    finetune_with(scene, splats, train_config, steps, device, |s| s).await
+}
+
+/// Briefly train quantized splats, snapping them back to the quantization grid after every step.
+/// This recovers some of the quality lost to quantization.
+#[cfg(not(target_family = "wasm"))]
+pub(crate) async fn finetune_quantized(
+    scene: &Scene,
+    splats: Splats<Wgpu>,
+    train_config: &TrainConfig,
+    compress_config: &CompressConfig,
+    device: &WgpuDevice,
+) -> Splats<Wgpu> {
+    finetune_with(
+        scene,
+        splats,
+        train_config,
+        compress_config.finetune_steps,
+        device,
+        |s| quantize_splats(s, compress_config),
+    )
+    .await
+}
+
+// Train splats for some steps, applying `project` to the splats before and after every step.
+#[cfg(not(target_family = "wasm"))]
+async fn finetune_with(
+    scene: &Scene,
+    splats: Splats<Wgpu>,
+    train_config: &TrainConfig,
+    steps: u32,
+    device: &WgpuDevice,
+    project: impl Fn(Splats<Autodiff<Wgpu>>) -> Splats<Autodiff<Wgpu>>,
+) -> Splats<Wgpu> {
+    let splats = Splats::<Autodiff<Wgpu>>::from_tensor_data(
+        Tensor::from_inner(splats.means.val()),
+        Tensor::from_inner(splats.rotation.val()),
+        Tensor::from_inner(splats.log_scales.val()),
+        Tensor::from_inner(splats.sh_coeffs.val()),
+        Tensor::from_inner(splats.raw_opacity.val()),
+    );
+    let mut splats = project(splats);
+
+    let config = train_config.finetune(steps);
+    let mut dataloader = SceneLoader::new(scene, 42, device);
+    let mut trainer = SplatTrainer::new(&splats, &config, device);
+
+    for iter in 0..steps {
+        let batch = dataloader.next_batch().await;
+        let (new_splats, _) = trainer.step(iter, batch, splats);
+        splats = project(new_splats);
+    }
+
+    splats.valid()
+}
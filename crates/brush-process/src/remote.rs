@@ -0,0 +1,161 @@
+//! Watch a training run in another process over WebSocket, eg. a headless training run on a
+//! server, from the viewer in a browser.
+//!
+//! The server sends snapshots of the splats as binary messages holding a ply file, and other
+//! events as JSON text messages, see [`RemoteEvent`]. The viewer renders the snapshots itself,
+//! so the camera never has to be sent to the server.
+
+use std::{io::Cursor, sync::Arc};
+
+use anyhow::anyhow;
+use brush_dataset::splat_import;
+use burn_wgpu::WgpuDevice;
+use ewebsock::{WsEvent, WsMessage};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    mpsc::{channel, unbounded_channel, Sender},
+    Notify,
+};
+use tokio_stream::StreamExt;
+
+use crate::process_loop::{ProcessArgs, ProcessMessage, RunningProcess};
+
+/// Events sent from a training server to remote viewers, as JSON text messages.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RemoteEvent {
+    Eval {
+        iter: u32,
+        avg_psnr: f32,
+        avg_ssim: f32,
+        avg_lpips: f32,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Whether a url points to a training server, rather than a file to download.
+pub fn is_remote_url(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// Connect to a training server. The process shows the splats of the server as they come in,
+/// and ends when the connection is closed.
+pub fn connect_remote(url: &str, args: ProcessArgs, device: WgpuDevice) -> RunningProcess {
+    let (sender, receiver) = channel(1);
+    // Nothing is sent back to the server, the viewer only watches.
+    let (control_sender, _) = unbounded_channel();
+
+    let url = url.to_owned();
+    tokio_with_wasm::alias::task::spawn(async move {
+        if let Err(e) = remote_loop(&url, &sender, device).await {
+            let _ = sender.send(ProcessMessage::Error(e)).await;
+        }
+    });
+
+    RunningProcess {
+        start_args: args,
+        messages: receiver,
+        control: control_sender,
+    }
+}
+
+// Sending fails when the viewer is gone, which ends the connection.
+async fn send(output: &Sender<ProcessMessage>, message: ProcessMessage) -> anyhow::Result<()> {
+    output
+        .send(message)
+        .await
+        .map_err(|_| anyhow!("Viewer was closed"))
+}
+
+async fn remote_loop(
+    url: &str,
+    output: &Sender<ProcessMessage>,
+    device: WgpuDevice,
+) -> anyhow::Result<()> {
+    send(output, ProcessMessage::NewSource).await?;
+    send(output, ProcessMessage::StartLoading { training: false }).await?;
+
+    // Wake up this task whenever the socket has a new event.
+    let notify = Arc::new(Notify::new());
+    let wake_up = notify.clone();
+    // The connection is closed when the sender is dropped, so keep it around.
+    let (_ws_sender, ws_receiver) =
+        ewebsock::connect_with_wakeup(url, ewebsock::Options::default(), move || {
+            wake_up.notify_one();
+        })
+        .map_err(|e| anyhow!("Failed to connect to {url}: {e}"))?;
+
+    let mut loaded = false;
+    loop {
+        let Some(event) = ws_receiver.try_recv() else {
+            notify.notified().await;
+            continue;
+        };
+
+        match event {
+            WsEvent::Opened => log::info!("Connected to training server {url}"),
+            WsEvent::Message(WsMessage::Binary(data)) => {
+                let stream =
+                    splat_import::load_splat_from_ply(Cursor::new(data), None, device.clone());
+                let mut stream = std::pin::pin!(stream);
+
+                // The loader sends progressively more splats, only show the full set.
+                let mut last = None;
+                while let Some(message) = stream.next().await {
+                    last = Some(message?);
+                }
+                let Some(message) = last else {
+                    continue;
+                };
+
+                send(
+                    output,
+                    ProcessMessage::ViewSplats {
+                        up_axis: message.meta.up_axis,
+                        splats: Box::new(message.splats),
+                        frame: 0,
+                        total_frames: 1,
+                    },
+                )
+                .await?;
+
+                if !loaded {
+                    loaded = true;
+                    send(output, ProcessMessage::DoneLoading { training: false }).await?;
+                }
+            }
+            WsEvent::Message(WsMessage::Text(text)) => match serde_json::from_str(&text) {
+                Ok(RemoteEvent::Eval {
+                    iter,
+                    avg_psnr,
+                    avg_ssim,
+                    avg_lpips,
+                }) => {
+                    send(
+                        output,
+                        ProcessMessage::EvalResult {
+                            iter,
+                            avg_psnr,
+                            avg_ssim,
+                            avg_lpips,
+                        },
+                    )
+                    .await?;
+                }
+                Ok(RemoteEvent::Error { message }) => {
+                    anyhow::bail!("Training server failed: {message}");
+                }
+                // A newer server can send events this viewer doesn't know about yet.
+                Err(e) => log::warn!("Skipping unknown event from {url}: {e}"),
+            },
+            WsEvent::Message(_) => {}
+            WsEvent::Error(e) => anyhow::bail!("Connection to {url} failed: {e}"),
+            WsEvent::Closed => {
+                log::info!("Training server {url} closed the connection");
+                return Ok(());
+            }
+        }
+    }
+}
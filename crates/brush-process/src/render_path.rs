@@ -0,0 +1,142 @@
+//! Render videos of splats along a camera path, eg. a turntable around a scene.
+//!
+//! Frames are written as a PNG sequence. Videos are encoded from the frames with ffmpeg, which
+//! needs to be installed.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use brush_render::{
+    camera::{focal_to_fov, fov_to_focal, Camera},
+    camera_path::CameraPath,
+    gaussian_splats::Splats,
+};
+use brush_train::image::tensor_into_image;
+use burn_wgpu::Wgpu;
+use glam::{UVec2, Vec3};
+
+/// Whether a path is a video file, rather than a directory for a PNG sequence.
+pub fn is_video_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ["mp4", "mov", "mkv", "webm"].contains(&e.to_lowercase().as_str()))
+}
+
+/// A turntable path around the splats, one full circle in `duration` seconds. Splats are
+/// assumed to be upright along `up`.
+pub async fn orbit_path(
+    splats: &Splats<Wgpu>,
+    up: Vec3,
+    duration: f32,
+) -> anyhow::Result<CameraPath> {
+    let read_err = |e| anyhow::anyhow!("Failed to read splat positions {e:?}");
+    let means = splats.means.val();
+    let center = means.clone().mean_dim(0);
+    // Use the spread of the splats, as a few far away splats would make the bounds huge.
+    let spread = (means - center.clone())
+        .powf_scalar(2.0)
+        .sum_dim(1)
+        .mean()
+        .sqrt();
+
+    let center: Vec<f32> = center.into_data_async().await.to_vec().map_err(read_err)?;
+    let spread: Vec<f32> = spread.into_data_async().await.to_vec().map_err(read_err)?;
+    let (center, spread) = (Vec3::from_slice(&center), spread[0].max(1e-3));
+
+    let up = up.normalize();
+    let side = up.any_orthonormal_vector();
+    let start = center + side * spread * 2.0 + up * spread * 0.5;
+    Ok(CameraPath::orbit(center, up, start, 0.8, duration))
+}
+
+// Fit the horizontal field of view to the image size, so pixels are square.
+fn fit_camera(mut camera: Camera, size: UVec2) -> Camera {
+    camera.fov_x = focal_to_fov(fov_to_focal(camera.fov_y, size.y), size.x);
+    camera
+}
+
+/// Render the frames of a camera path to `frame_00000.png`, `frame_00001.png`, ... in `dir`.
+/// Returns the number of frames.
+pub async fn render_frames(
+    splats: &Splats<Wgpu>,
+    path: &CameraPath,
+    size: UVec2,
+    fps: f32,
+    dir: &Path,
+) -> anyhow::Result<u32> {
+    anyhow::ensure!(!path.keyframes.is_empty(), "Camera path has no keyframes");
+    anyhow::ensure!(fps > 0.0, "Frame rate must be positive");
+    tokio::fs::create_dir_all(dir).await?;
+
+    let num_frames = (path.duration() * fps).floor() as u32 + 1;
+    for frame in 0..num_frames {
+        let camera = path.sample(frame as f32 / fps).expect("Path has keyframes");
+        let (img, _) = splats.render(&fit_camera(camera, size), size, false);
+        let img = tensor_into_image(img.into_data_async().await).to_rgb8();
+        img.save(dir.join(format!("frame_{frame:05}.png")))?;
+
+        if frame % 30 == 0 {
+            log::info!("Rendered frame {frame} / {num_frames}");
+        }
+    }
+    Ok(num_frames)
+}
+
+/// Encode the frames written by [`render_frames`] to a video with ffmpeg.
+pub async fn encode_video(frames_dir: &Path, fps: f32, output: &Path) -> anyhow::Result<()> {
+    let result = tokio::process::Command::new("ffmpeg")
+        .args([
+            "-loglevel",
+            "error",
+            "-y",
+            "-framerate",
+            &fps.to_string(),
+            "-i",
+        ])
+        .arg(frames_dir.join("frame_%05d.png"))
+        // Most encoders need an even size.
+        .args([
+            "-vf",
+            "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+            "-pix_fmt",
+            "yuv420p",
+            "-crf",
+            "18",
+        ])
+        .arg(output)
+        .output()
+        .await
+        .context("Failed to run ffmpeg. Make sure ffmpeg is installed to encode videos.")?;
+
+    anyhow::ensure!(
+        result.status.success(),
+        "ffmpeg failed to encode the video: {}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    Ok(())
+}
+
+/// Render a camera path to `output`, either a video file (see [`is_video_path`]) or a
+/// directory for a PNG sequence.
+pub async fn render_path(
+    splats: &Splats<Wgpu>,
+    path: &CameraPath,
+    size: UVec2,
+    fps: f32,
+    output: &Path,
+) -> anyhow::Result<()> {
+    if !is_video_path(output) {
+        render_frames(splats, path, size, fps, output).await?;
+        return Ok(());
+    }
+
+    let frames_dir: PathBuf =
+        std::env::temp_dir().join(format!("brush_frames_{:016x}", rand::random::<u64>()));
+    let result = async {
+        render_frames(splats, path, size, fps, &frames_dir).await?;
+        encode_video(&frames_dir, fps, output).await
+    }
+    .await;
+    let _ = tokio::fs::remove_dir_all(&frames_dir).await;
+    result
+}
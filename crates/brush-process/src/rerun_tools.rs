@@ -3,8 +3,9 @@
 use std::sync::Arc;
 
 use brush_dataset::clamp_img_to_max_size;
-use brush_render::{gaussian_splats::Splats, AutodiffBackend, Backend};
+use brush_render::{gaussian_splats::Splats, AutodiffBackend, Backend, RenderStats};
 use brush_train::eval::EvalSample;
+use brush_train::metrics::{MetricsSink, StepMetrics};
 use brush_train::{image::tensor_into_image, scene::Scene, train::RefineStats};
 use brush_train::{ssim::Ssim, train::TrainStepStats};
 use burn::tensor::{activation::sigmoid, ElementConversion};
@@ -15,17 +16,43 @@ use anyhow::Result;
 use brush_rerun::BurnToRerun;
 use burn_jit::cubecl::MemoryUsage;
 
+/// Streams the training metrics to rerun, next to the other training stats.
+#[cfg(not(target_family = "wasm"))]
+pub struct RerunMetrics {
+    rec: rerun::RecordingStream,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl MetricsSink for RerunMetrics {
+    fn write(&mut self, metrics: &StepMetrics) -> Result<()> {
+        self.rec.set_time_sequence("iterations", metrics.iter);
+        for (name, value) in metrics.scalars() {
+            self.rec
+                .log(format!("metrics/{name}"), &rerun::Scalar::new(value))?;
+        }
+        Ok(())
+    }
+}
+
 pub struct VisualizeTools {
     #[cfg(not(target_family = "wasm"))]
     rec: Option<rerun::RecordingStream>,
 }
 
 impl VisualizeTools {
+    /// Create the tools to log to rerun. When `save_path` is set the recording is written to
+    /// that file, otherwise it's streamed to a running viewer if `enabled` is set.
     #[allow(unused_variables)]
-    pub fn new(enabled: bool) -> Self {
+    pub fn new(enabled: bool, save_path: Option<&str>) -> Self {
         // Spawn rerun - creating this is already explicitly done by a user.
         #[cfg(not(target_family = "wasm"))]
-        if enabled {
+        if let Some(path) = save_path {
+            let rec = rerun::RecordingStreamBuilder::new("Brush").save(path);
+            if let Err(e) = &rec {
+                log::error!("Failed to create rerun recording at {path}: {e}");
+            }
+            Self { rec: rec.ok() }
+        } else if enabled {
             Self {
                 rec: rerun::RecordingStreamBuilder::new("Brush")
                     .connect_tcp()
@@ -39,6 +66,13 @@ impl VisualizeTools {
         Self {}
     }
 
+    /// A sink to stream the training metrics to rerun, if logging to rerun is enabled.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn metrics_sink(&self) -> Option<RerunMetrics> {
+        let rec = self.rec.clone().filter(|rec| rec.is_enabled())?;
+        Some(RerunMetrics { rec })
+    }
+
     #[allow(unused_variables)]
     pub async fn log_splats<B: Backend>(&self, iter: u32, splats: Splats<B>) -> Result<()> {
         #[cfg(not(target_family = "wasm"))]
@@ -149,13 +183,20 @@ impl VisualizeTools {
     }
 
     #[allow(unused_variables)]
-    pub fn log_eval_stats(&self, iter: u32, avg_psnr: f32, avg_ssim: f32) -> Result<()> {
+    pub fn log_eval_stats(
+        &self,
+        iter: u32,
+        avg_psnr: f32,
+        avg_ssim: f32,
+        avg_lpips: f32,
+    ) -> Result<()> {
         #[cfg(not(target_family = "wasm"))]
         if let Some(rec) = self.rec.as_ref() {
             if rec.is_enabled() {
                 rec.set_time_sequence("iterations", iter);
                 rec.log("psnr/eval", &rerun::Scalar::new(avg_psnr as f64))?;
                 rec.log("ssim/eval", &rerun::Scalar::new(avg_ssim as f64))?;
+                rec.log("lpips/eval", &rerun::Scalar::new(avg_lpips as f64))?;
             }
         }
         Ok(())
@@ -243,19 +284,17 @@ impl VisualizeTools {
                 rec.log("lr/coeffs", &rerun::Scalar::new(stats.lr_coeffs))?;
                 rec.log("lr/opac", &rerun::Scalar::new(stats.lr_opac))?;
 
+                let render_stats = RenderStats {
+                    intersection_capacity: stats.intersection_capacity,
+                    ..RenderStats::read_async(stats.num_visible, stats.num_intersections).await
+                };
                 rec.log(
                     "splats/num_intersects",
-                    &rerun::Scalar::new(
-                        stats
-                            .num_intersections
-                            .into_scalar_async()
-                            .await
-                            .elem::<f64>(),
-                    ),
+                    &rerun::Scalar::new(render_stats.num_intersections as f64),
                 )?;
                 rec.log(
                     "splats/splats_visible",
-                    &rerun::Scalar::new(stats.num_visible.into_scalar_async().await.elem::<f64>()),
+                    &rerun::Scalar::new(render_stats.num_visible as f64),
                 )?;
 
                 let [img_h, img_w, _] = stats.pred_image.dims();
@@ -309,6 +348,10 @@ impl VisualizeTools {
                     "refine/num_scale_pruned",
                     &rerun::Scalar::new(refine.num_scale_pruned as f64),
                 );
+                let _ = rec.log(
+                    "refine/num_budget_pruned",
+                    &rerun::Scalar::new(refine.num_budget_pruned as f64),
+                );
             }
         }
 
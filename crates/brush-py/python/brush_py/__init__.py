@@ -0,0 +1,164 @@
+"""Brush's wgpu gaussian splat renderer for PyTorch.
+
+`rasterization` follows the API of gsplat's `rasterization`, so brush can be swapped in for
+gsplat on machines without CUDA. Tensors are handed to brush through dlpack. Brush renders with
+wgpu, which can't share memory with torch, so data is copied through the CPU.
+"""
+
+import numpy as np
+import torch
+
+from . import _brush
+
+__all__ = ["render_gaussians", "rasterization"]
+
+SH_C0 = 0.28209479177387814
+
+
+def _to_numpy(tensor):
+    return np.from_dlpack(tensor.detach().to("cpu", torch.float32).contiguous())
+
+
+def _to_torch(array, device):
+    return torch.from_dlpack(np.ascontiguousarray(array)).to(device)
+
+
+class _RenderGaussians(torch.autograd.Function):
+    @staticmethod
+    def forward(
+        ctx,
+        aux_out,
+        means,
+        quats,
+        log_scales,
+        sh_coeffs,
+        raw_opacities,
+        viewmat,
+        K,
+        width,
+        height,
+        antialiased,
+        render_depth,
+    ):
+        image, aux, state = _brush.render_gaussians(
+            _to_numpy(means),
+            _to_numpy(quats),
+            _to_numpy(log_scales),
+            _to_numpy(sh_coeffs),
+            _to_numpy(raw_opacities),
+            _to_numpy(viewmat),
+            _to_numpy(K),
+            width,
+            height,
+            antialiased=antialiased,
+            render_depth=render_depth,
+        )
+        ctx.state = state
+        ctx.device = means.device
+        aux_out.update(
+            {
+                key: _to_torch(value, means.device) if isinstance(value, np.ndarray) else value
+                for key, value in aux.items()
+            }
+        )
+        return _to_torch(image, means.device)
+
+    @staticmethod
+    def backward(ctx, v_image):
+        grads = ctx.state.backward(_to_numpy(v_image))
+        grads = [_to_torch(grad, ctx.device) for grad in grads]
+        return (None, *grads, None, None, None, None, None, None)
+
+
+def render_gaussians(
+    means,
+    quats,
+    log_scales,
+    sh_coeffs,
+    raw_opacities,
+    viewmat,
+    K,
+    width,
+    height,
+    antialiased=False,
+    render_depth=False,
+):
+    """Render gaussians with one camera, differentiable with respect to the gaussians.
+
+    Quaternions are wxyz, scales are in log space and opacities are before the sigmoid.
+    `sh_coeffs` are `[N, (degree + 1)^2, 3]`. `viewmat` is the 4x4 world to camera matrix and
+    `K` the 3x3 intrinsics, with camera axes +x right, +y down and +z forward.
+
+    Returns the `[height, width, 4]` RGBA image, and a dict of aux buffers: `num_visible`,
+    `visible_ids`, `radii`, and `depth` and `normals` when rendering depth.
+    """
+    aux = {}
+    image = _RenderGaussians.apply(
+        aux,
+        means,
+        quats,
+        log_scales,
+        sh_coeffs,
+        raw_opacities,
+        viewmat,
+        K,
+        width,
+        height,
+        antialiased,
+        render_depth,
+    )
+    return image, aux
+
+
+def rasterization(
+    means,
+    quats,
+    scales,
+    opacities,
+    colors,
+    viewmats,
+    Ks,
+    width,
+    height,
+    sh_degree=None,
+    rasterize_mode="classic",
+    **kwargs,
+):
+    """Like `gsplat.rasterization`, for `[C, ...]` batches of cameras.
+
+    Colors are SH coefficients `[N, K, 3]` when `sh_degree` is set, otherwise RGB `[N, 3]`.
+    Other gsplat options aren't supported and are ignored.
+
+    Returns the `[C, height, width, 3]` colors, `[C, height, width, 1]` alphas, and a dict with
+    the aux buffers of every camera.
+    """
+    if sh_degree is None:
+        sh_coeffs = ((colors - 0.5) / SH_C0).unsqueeze(1)
+    else:
+        sh_coeffs = colors[:, : (sh_degree + 1) ** 2]
+
+    log_scales = torch.log(scales)
+    raw_opacities = torch.logit(opacities, eps=1e-6)
+    antialiased = rasterize_mode == "antialiased"
+
+    renders = []
+    auxs = []
+    for viewmat, K in zip(viewmats, Ks):
+        image, aux = render_gaussians(
+            means,
+            quats,
+            log_scales,
+            sh_coeffs,
+            raw_opacities,
+            viewmat,
+            K,
+            width,
+            height,
+            antialiased=antialiased,
+        )
+        renders.append(image)
+        auxs.append(aux)
+
+    renders = torch.stack(renders)
+    meta = {key: [aux[key] for aux in auxs] for key in auxs[0]} if auxs else {}
+    return renders[..., :3], renders[..., 3:], meta
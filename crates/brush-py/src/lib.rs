@@ -0,0 +1,239 @@
+//! Python bindings for the brush renderer, see the README of this crate.
+//!
+//! Arrays are passed as numpy arrays. The python package wraps this in a torch autograd
+//! function, and hands torch tensors over through dlpack.
+
+use std::sync::OnceLock;
+
+use brush_render::{
+    camera::{focal_to_fov, Camera},
+    gaussian_splats::Splats,
+    RenderAux,
+};
+use burn::{
+    backend::{wgpu::WgpuDevice, Autodiff, Wgpu},
+    module::Param,
+    prelude::*,
+    tensor::TensorData,
+};
+use numpy::{PyArray1, PyArrayDyn, PyArrayMethods, PyReadonlyArrayDyn, PyUntypedArrayMethods};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+
+type B = Autodiff<Wgpu>;
+
+fn device() -> &'static WgpuDevice {
+    static DEVICE: OnceLock<WgpuDevice> = OnceLock::new();
+    DEVICE.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("Failed to create runtime")
+            .block_on(brush_render::burn_init_setup())
+    })
+}
+
+fn to_tensor<const D: usize>(
+    name: &str,
+    array: &PyReadonlyArrayDyn<'_, f32>,
+) -> PyResult<Tensor<B, D>> {
+    let shape = array.shape().to_vec();
+    if shape.len() != D {
+        return Err(PyValueError::new_err(format!(
+            "{name} must have {D} dimensions, got shape {shape:?}"
+        )));
+    }
+    let data = array
+        .as_slice()
+        .map_err(|_| PyValueError::new_err(format!("{name} must be contiguous")))?;
+    Ok(Tensor::from_data(
+        TensorData::new(data.to_vec(), shape),
+        device(),
+    ))
+}
+
+fn to_numpy<'py, BT: Backend, const D: usize>(
+    py: Python<'py>,
+    tensor: Tensor<BT, D>,
+) -> PyResult<Bound<'py, PyArrayDyn<f32>>> {
+    let shape = tensor.dims().to_vec();
+    let data = tensor
+        .into_data()
+        .convert::<f32>()
+        .to_vec::<f32>()
+        .map_err(|e| PyValueError::new_err(format!("Failed to read tensor: {e:?}")))?;
+    Ok(PyArray1::from_vec(py, data).reshape(shape)?)
+}
+
+// Convert a gsplat style world to camera matrix and intrinsics to a camera. Both use camera
+// axes with +x right, +y down and +z forward, like brush.
+fn camera_from_matrices(
+    viewmat: &PyReadonlyArrayDyn<'_, f32>,
+    intrinsics: &PyReadonlyArrayDyn<'_, f32>,
+    width: u32,
+    height: u32,
+) -> PyResult<Camera> {
+    if viewmat.shape() != [4, 4] || intrinsics.shape() != [3, 3] {
+        return Err(PyValueError::new_err(
+            "viewmat must be a 4x4 matrix, and K a 3x3 matrix",
+        ));
+    }
+    let viewmat = viewmat
+        .as_slice()
+        .map_err(|_| PyValueError::new_err("viewmat must be contiguous"))?;
+    let k = intrinsics
+        .as_slice()
+        .map_err(|_| PyValueError::new_err("K must be contiguous"))?;
+
+    // Numpy arrays are row major, glam matrices column major.
+    let world_to_cam = glam::Mat4::from_cols_slice(viewmat).transpose();
+    let (_, rotation, position) = world_to_cam.inverse().to_scale_rotation_translation();
+    let (fx, fy, cx, cy) = (k[0], k[4], k[2], k[5]);
+
+    Ok(Camera::new(
+        position,
+        rotation,
+        focal_to_fov(fx as f64, width),
+        focal_to_fov(fy as f64, height),
+        glam::vec2(cx / width as f32, cy / height as f32),
+    ))
+}
+
+fn aux_to_dict<'py>(py: Python<'py>, aux: RenderAux<B>) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    let num_visible = aux.num_visible.into_data().convert::<i32>().to_vec::<i32>();
+    let num_visible = num_visible
+        .ok()
+        .and_then(|n| n.first().copied())
+        .unwrap_or(0);
+    dict.set_item("num_visible", num_visible)?;
+    let visible_ids = if num_visible > 0 {
+        aux.global_from_compact_gid
+            .slice([0..num_visible as usize])
+            .into_data()
+            .convert::<i64>()
+            .to_vec::<i64>()
+            .map_err(|e| PyValueError::new_err(format!("Failed to read tensor: {e:?}")))?
+    } else {
+        vec![]
+    };
+    dict.set_item("visible_ids", PyArray1::from_vec(py, visible_ids))?;
+    dict.set_item("radii", to_numpy(py, aux.radii)?)?;
+    if let Some(depth) = aux.depth {
+        dict.set_item("depth", to_numpy(py, depth)?)?;
+    }
+    if let Some(normals) = aux.normals {
+        dict.set_item("normals", to_numpy(py, normals)?)?;
+    }
+    Ok(dict)
+}
+
+/// The state of a render, to calculate the gradients of the splats.
+#[pyclass(unsendable)]
+struct RenderState {
+    splats: Splats<B>,
+    image: Tensor<B, 3>,
+}
+
+fn grad_or_zeros<const D: usize>(
+    param: &Param<Tensor<B, D>>,
+    grads: &<B as burn::tensor::backend::AutodiffBackend>::Gradients,
+) -> Tensor<Wgpu, D> {
+    let val = param.val();
+    val.grad(grads)
+        .unwrap_or_else(|| Tensor::zeros(val.dims(), &val.device()))
+}
+
+#[pymethods]
+impl RenderState {
+    /// Backpropagate the gradient of the rendered image. Returns the gradients of the means,
+    /// quats, log scales, SH coefficients and raw opacities.
+    #[allow(clippy::type_complexity)]
+    fn backward<'py>(
+        &self,
+        py: Python<'py>,
+        v_image: PyReadonlyArrayDyn<'py, f32>,
+    ) -> PyResult<(
+        Bound<'py, PyArrayDyn<f32>>,
+        Bound<'py, PyArrayDyn<f32>>,
+        Bound<'py, PyArrayDyn<f32>>,
+        Bound<'py, PyArrayDyn<f32>>,
+        Bound<'py, PyArrayDyn<f32>>,
+    )> {
+        let v_image = to_tensor::<3>("v_image", &v_image)?;
+        if v_image.dims() != self.image.dims() {
+            return Err(PyValueError::new_err(
+                "Gradient must have the shape of the image",
+            ));
+        }
+        let grads = (self.image.clone() * v_image).sum().backward();
+        let splats = &self.splats;
+        Ok((
+            to_numpy(py, grad_or_zeros(&splats.means, &grads))?,
+            to_numpy(py, grad_or_zeros(&splats.rotation, &grads))?,
+            to_numpy(py, grad_or_zeros(&splats.log_scales, &grads))?,
+            to_numpy(py, grad_or_zeros(&splats.sh_coeffs, &grads))?,
+            to_numpy(py, grad_or_zeros(&splats.raw_opacity, &grads))?,
+        ))
+    }
+}
+
+/// Render gaussians with a camera. Returns the `[height, width, 4]` RGBA image, a dict with
+/// aux buffers, and the state to calculate gradients with.
+///
+/// Quaternions are in wxyz order, scales in log space, and opacities before the sigmoid. The
+/// SH coefficients are `[N, (degree + 1)^2, 3]`.
+#[pyfunction]
+#[pyo3(signature = (means, quats, log_scales, sh_coeffs, raw_opacities, viewmat, intrinsics, width, height, antialiased = false, render_depth = false))]
+#[allow(clippy::too_many_arguments)]
+fn render_gaussians<'py>(
+    py: Python<'py>,
+    means: PyReadonlyArrayDyn<'py, f32>,
+    quats: PyReadonlyArrayDyn<'py, f32>,
+    log_scales: PyReadonlyArrayDyn<'py, f32>,
+    sh_coeffs: PyReadonlyArrayDyn<'py, f32>,
+    raw_opacities: PyReadonlyArrayDyn<'py, f32>,
+    viewmat: PyReadonlyArrayDyn<'py, f32>,
+    intrinsics: PyReadonlyArrayDyn<'py, f32>,
+    width: u32,
+    height: u32,
+    antialiased: bool,
+    render_depth: bool,
+) -> PyResult<(Bound<'py, PyArrayDyn<f32>>, Bound<'py, PyDict>, RenderState)> {
+    if antialiased && render_depth {
+        return Err(PyValueError::new_err(
+            "Depth can't be rendered with antialiasing",
+        ));
+    }
+    if width == 0 || height == 0 {
+        return Err(PyValueError::new_err("Can't render 0 sized images"));
+    }
+
+    let camera = camera_from_matrices(&viewmat, &intrinsics, width, height)?;
+    let splats = Splats::from_tensor_data(
+        to_tensor("means", &means)?,
+        to_tensor("quats", &quats)?,
+        to_tensor("log_scales", &log_scales)?,
+        to_tensor("sh_coeffs", &sh_coeffs)?,
+        to_tensor("raw_opacities", &raw_opacities)?,
+    );
+
+    let img_size = glam::uvec2(width, height);
+    let (image, aux) = if antialiased {
+        splats.render_antialiased(&camera, img_size, false)
+    } else if render_depth {
+        splats.render_with_depth(&camera, img_size)
+    } else {
+        splats.render(&camera, img_size, false)
+    };
+
+    let image_np = to_numpy(py, image.clone().inner())?;
+    let aux = aux_to_dict(py, aux)?;
+
+    Ok((image_np, aux, RenderState { splats, image }))
+}
+
+#[pymodule]
+fn _brush(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(render_gaussians, m)?)?;
+    m.add_class::<RenderState>()?;
+    Ok(())
+}
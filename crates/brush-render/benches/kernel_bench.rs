@@ -0,0 +1,330 @@
+//! Device side timings of the sort, prefix sum and render kernels, written as JSON, to catch
+//! performance regressions.
+//!
+//! Every case is timed with timestamp queries written right before and after its kernels, so
+//! the timings don't include any CPU overhead. Renders are also timed per stage with the sync
+//! spans, see [`sync_span::take_timings`]. Those are wall clock timings of the stages, which
+//! include some overhead, but show where the time goes, eg. how long `ProjectSplats` takes.
+//!
+//! Run with `cargo bench -p brush-render --bench kernel_bench -- [--output report.json]
+//! [--baseline old_report.json] [--threshold 0.1]`. With a baseline, the bench fails when a
+//! case got slower by more than the threshold.
+
+#![allow(clippy::single_range_in_vec_init)]
+#![recursion_limit = "256"]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use brush_prefix_sum::prefix_sum;
+use brush_render::{render, tile_autotune::bench_scene};
+use brush_sort::radix_argsort;
+use burn::prelude::Backend;
+use burn::tensor::{Int, Tensor};
+use burn_jit::cubecl::Runtime;
+use burn_wgpu::{JitBackend, RuntimeOptions, WgpuDevice, WgpuRuntime};
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::layer::SubscriberExt;
+
+// Without fusion, so the kernels run in the order they're launched.
+type Inner = JitBackend<WgpuRuntime, f32, i32, u32>;
+
+const SIZES: [usize; 3] = [1 << 16, 1 << 18, 1 << 20];
+const RESOLUTIONS: [glam::UVec2; 2] = [glam::uvec2(512, 512), glam::uvec2(1920, 1080)];
+
+const WARMUP_SAMPLES: u32 = 3;
+const SAMPLES: u32 = 20;
+
+#[derive(Serialize, Deserialize)]
+struct CaseResult {
+    name: String,
+    /// Median device time, in milliseconds.
+    median_ms: f64,
+    min_ms: f64,
+    max_ms: f64,
+    /// Mean wall clock time of every stage of a render, in milliseconds.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    stages_ms: BTreeMap<String, f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Report {
+    adapter: String,
+    cases: Vec<CaseResult>,
+}
+
+// Writes timestamps before and after a case on the device burn runs on.
+struct GpuTimer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+}
+
+impl GpuTimer {
+    fn new(device: wgpu::Device, queue: wgpu::Queue) -> Self {
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Bench timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let buffer = |usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Bench timestamps"),
+                size: 16,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let resolve = buffer(wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC);
+        let readback = buffer(wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST);
+        Self {
+            device,
+            queue,
+            query_set,
+            resolve,
+            readback,
+        }
+    }
+
+    fn write_timestamp(&self, index: u32) {
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Bench timestamp"),
+            timestamp_writes: Some(wgpu::ComputePassTimestampWrites {
+                query_set: &self.query_set,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: None,
+            }),
+        });
+        self.queue.submit([encoder.finish()]);
+    }
+
+    // Time the kernels launched by `work` on the device.
+    //
+    // The kernels are only submitted when the client is flushed, as the runtime is set up to
+    // never flush by itself. That way the first timestamp is submitted right before the
+    // kernels, and there's no gap between them while the CPU is still launching kernels.
+    fn time(&self, device: &WgpuDevice, work: impl FnOnce()) -> Duration {
+        let client = WgpuRuntime::client(device);
+        Inner::sync(device);
+
+        work();
+        self.write_timestamp(0);
+        client.flush();
+        self.write_timestamp(1);
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, 16);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = self.readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        self.device.poll(wgpu::Maintain::Wait);
+        let [start, end]: [u64; 2] = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        self.readback.unmap();
+
+        let nanos = end.saturating_sub(start) as f64 * self.queue.get_timestamp_period() as f64;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    fn bench(&self, name: String, device: &WgpuDevice, mut work: impl FnMut()) -> CaseResult {
+        for _ in 0..WARMUP_SAMPLES {
+            self.time(device, &mut work);
+        }
+        let mut times: Vec<f64> = (0..SAMPLES)
+            .map(|_| self.time(device, &mut work).as_secs_f64() * 1000.0)
+            .collect();
+        times.sort_by(f64::total_cmp);
+
+        let result = CaseResult {
+            name,
+            median_ms: times[times.len() / 2],
+            min_ms: times[0],
+            max_ms: times[times.len() - 1],
+            stages_ms: BTreeMap::new(),
+        };
+        eprintln!("{}: {:.3} ms", result.name, result.median_ms);
+        result
+    }
+}
+
+async fn init_device() -> (WgpuDevice, GpuTimer, String) {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        })
+        .await
+        .expect("No GPU found");
+    assert!(
+        adapter.features().contains(wgpu::Features::TIMESTAMP_QUERY),
+        "The GPU doesn't support timestamp queries"
+    );
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("Kernel bench"),
+                required_features: adapter
+                    .features()
+                    .difference(wgpu::Features::MAPPABLE_PRIMARY_BUFFERS),
+                required_limits: adapter.limits(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        )
+        .await
+        .expect("Failed to create device");
+
+    let info = adapter.get_info();
+    render::set_hard_floats_available(
+        adapter
+            .features()
+            .contains(wgpu::Features::SHADER_FLOAT32_ATOMIC),
+    );
+
+    let timer = GpuTimer::new(device.clone(), queue.clone());
+    let setup = burn_wgpu::WgpuSetup {
+        instance,
+        adapter,
+        device,
+        queue,
+    };
+    let options = RuntimeOptions {
+        tasks_max: 1 << 20,
+        memory_config: burn_wgpu::MemoryConfiguration::ExclusivePages,
+    };
+    let device = burn_wgpu::init_device(setup, options);
+    (device, timer, format!("{} ({:?})", info.name, info.backend))
+}
+
+fn bench_sort(timer: &GpuTimer, device: &WgpuDevice, num: usize) -> CaseResult {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let keys: Vec<i32> = (0..num).map(|_| rng.gen_range(0..i32::MAX)).collect();
+    let values: Vec<i32> = (0..num as i32).collect();
+    let keys = Tensor::<Inner, 1, Int>::from_ints(keys.as_slice(), device).into_primitive();
+    let values = Tensor::<Inner, 1, Int>::from_ints(values.as_slice(), device).into_primitive();
+    let n_sort = Tensor::<Inner, 1, Int>::from_ints([num as i32], device).into_primitive();
+
+    timer.bench(format!("radix_argsort/{num}"), device, || {
+        let _ = radix_argsort(keys.clone(), values.clone(), &n_sort, 32);
+    })
+}
+
+fn bench_prefix_sum(timer: &GpuTimer, device: &WgpuDevice, num: usize) -> CaseResult {
+    let data: Vec<i32> = (0..num as i32).map(|x| x % 64).collect();
+    let input = Tensor::<Inner, 1, Int>::from_ints(data.as_slice(), device).into_primitive();
+
+    timer.bench(format!("prefix_sum/{num}"), device, || {
+        let _ = prefix_sum(input.clone());
+    })
+}
+
+fn bench_render(
+    timer: &GpuTimer,
+    device: &WgpuDevice,
+    num: usize,
+    size: glam::UVec2,
+) -> CaseResult {
+    let (splats, camera) = bench_scene::<Inner>(num, device);
+    let name = format!("render/{num}/{}x{}", size.x, size.y);
+    let mut result = timer.bench(name, device, || {
+        let _ = splats.render(&camera, size, true);
+    });
+
+    // Time the stages separately, as syncing between them changes the total timing.
+    sync_span::take_timings();
+    sync_span::set_enabled(true);
+    for _ in 0..SAMPLES {
+        let _ = splats.render(&camera, size, true);
+        Inner::sync(device);
+    }
+    sync_span::set_enabled(false);
+    result.stages_ms = sync_span::take_timings()
+        .into_iter()
+        .map(|(stage, time)| {
+            let ms = time.as_secs_f64() * 1000.0 / SAMPLES as f64;
+            (stage.to_owned(), ms)
+        })
+        .collect();
+    result
+}
+
+// Cases of the report that are slower than in the baseline by more than the threshold.
+fn regressions(report: &Report, baseline: &Report, threshold: f64) -> Vec<String> {
+    report
+        .cases
+        .iter()
+        .filter_map(|case| {
+            let old = baseline.cases.iter().find(|c| c.name == case.name)?;
+            (case.median_ms > old.median_ms * (1.0 + threshold)).then(|| {
+                format!(
+                    "{}: {:.3} ms -> {:.3} ms",
+                    case.name, old.median_ms, case.median_ms
+                )
+            })
+        })
+        .collect()
+}
+
+fn main() -> anyhow::Result<()> {
+    // Cargo passes extra arguments like --bench, which are ignored.
+    let mut output = None;
+    let mut baseline = None;
+    let mut threshold = 0.1;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = args.next(),
+            "--baseline" => baseline = args.next(),
+            "--threshold" => {
+                threshold = args
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .ok_or_else(|| anyhow::anyhow!("--threshold needs a number"))?;
+            }
+            _ => {}
+        }
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    let (device, timer, adapter) = runtime.block_on(init_device());
+    tracing::subscriber::set_global_default(
+        tracing_subscriber::registry().with(sync_span::SyncLayer::<Inner>::new(device.clone())),
+    )?;
+
+    let mut cases = vec![];
+    for num in SIZES {
+        cases.push(bench_sort(&timer, &device, num));
+        cases.push(bench_prefix_sum(&timer, &device, num));
+    }
+    for num in SIZES {
+        for size in RESOLUTIONS {
+            cases.push(bench_render(&timer, &device, num, size));
+        }
+    }
+    let report = Report { adapter, cases };
+
+    let json = serde_json::to_string_pretty(&report)?;
+    match output {
+        Some(path) => std::fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+
+    if let Some(path) = baseline {
+        let baseline: Report = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let slower = regressions(&report, &baseline, threshold);
+        if !slower.is_empty() {
+            anyhow::bail!(
+                "Kernels got more than {:.0}% slower:\n{}",
+                threshold * 100.0,
+                slower.join("\n")
+            );
+        }
+    }
+    Ok(())
+}
@@ -6,10 +6,14 @@ fn main() -> miette::Result<()> {
             "src/shaders/project_forward.wgsl",
             "src/shaders/project_visible.wgsl",
             "src/shaders/map_gaussian_to_intersects.wgsl",
+            "src/shaders/cull_tiles.wgsl",
             "src/shaders/rasterize.wgsl",
             "src/shaders/rasterize_backwards.wgsl",
+            "src/shaders/rasterize_features.wgsl",
+            "src/shaders/rasterize_features_backwards.wgsl",
             "src/shaders/gather_grads.wgsl",
             "src/shaders/project_backwards.wgsl",
+            "src/shaders/sum_isect_grads.wgsl",
         ],
         &["src/shaders/helpers.wgsl"],
         "src/shaders",
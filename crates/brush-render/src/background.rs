@@ -0,0 +1,48 @@
+use glam::Vec3;
+
+/// What the splats are composited over, see [`crate::Backend::render_splats`].
+///
+/// Rendered colors are premultiplied by their alpha, so a black background is the same as no
+/// background. The alpha channel of a render stays the coverage of the splats, and gradients
+/// only flow to the splats, never to the background.
+#[derive(Debug, Clone)]
+pub enum Background<T> {
+    Color(Vec3),
+    /// An image stretched over the whole view, as a `[height, width, 3]` tensor.
+    Image(T),
+    /// An equirectangular environment map as a `[height, width, 3]` tensor, with +y pointing
+    /// down. Every pixel samples the map in the direction it looks, ignoring lens distortion.
+    Environment(T),
+}
+
+impl<T> Default for Background<T> {
+    fn default() -> Self {
+        Self::Color(Vec3::ZERO)
+    }
+}
+
+impl<T> Background<T> {
+    /// The constant color of the background, black for textured backgrounds.
+    pub fn color(&self) -> Vec3 {
+        match self {
+            Self::Color(color) => *color,
+            Self::Image(_) | Self::Environment(_) => Vec3::ZERO,
+        }
+    }
+
+    pub fn texture(&self) -> Option<&T> {
+        match self {
+            Self::Color(_) => None,
+            Self::Image(texture) | Self::Environment(texture) => Some(texture),
+        }
+    }
+
+    /// Convert the texture of the background, eg. to a different backend.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Background<U> {
+        match self {
+            Self::Color(color) => Background::Color(color),
+            Self::Image(texture) => Background::Image(f(texture)),
+            Self::Environment(texture) => Background::Environment(f(texture)),
+        }
+    }
+}
@@ -10,7 +10,7 @@ use burn::{
     tensor::{
         backend::AutodiffBackend,
         ops::FloatTensor,
-        repr::{CustomOpDescription, HandleContainer, OperationDescription},
+        repr::{CustomOpDescription, HandleContainer, OperationDescription, TensorDescription},
         DType, Tensor, TensorPrimitive,
     },
 };
@@ -21,12 +21,21 @@ use burn_wgpu::WgpuRuntime;
 use crate::{
     camera::Camera,
     render::{
-        calc_tile_bounds, max_intersections, render_backward, render_forward, sh_coeffs_for_degree,
-        sh_degree_from_coeffs,
+        calc_tile_bounds, max_intersections, render_backward, render_features_backward,
+        render_features_forward, render_forward, sh_coeffs_for_degree, sh_degree_from_coeffs,
+        RenderSettings, TemporalRender,
     },
-    shaders, BBase, Backend, GaussianBackwardState, RenderAuxPrimitive, SplatGrads,
+    shaders, BBase, Backend, FeatureRenderState, GaussianBackwardState, RenderAuxPrimitive,
+    SplatGrads,
 };
 
+// Split up the descriptions of a custom op, for ops with a varying nr. of inputs or outputs.
+fn descriptions<const I: usize, const O: usize>(
+    (inputs, outputs): ([TensorDescription; I], [TensorDescription; O]),
+) -> (Vec<TensorDescription>, Vec<TensorDescription>) {
+    (inputs.into(), outputs.into())
+}
+
 // Implement forward functions for the inner wgpu backend.
 impl Backend for BBase {
     fn render_splats(
@@ -39,6 +48,7 @@ impl Backend for BBase {
         sh_coeffs: FloatTensor<Self>,
         raw_opacity: FloatTensor<Self>,
         render_u32_buffer: bool,
+        settings: RenderSettings<FloatTensor<Self>>,
     ) -> (FloatTensor<Self>, RenderAuxPrimitive<Self>) {
         render_forward(
             camera,
@@ -49,6 +59,7 @@ impl Backend for BBase {
             sh_coeffs,
             raw_opacity,
             render_u32_buffer,
+            settings,
         )
     }
 
@@ -62,6 +73,7 @@ impl Backend for BBase {
             state.quats,
             state.log_scales,
             state.raw_opac,
+            state.temporal,
             state.out_img,
             state.projected_splats,
             state.uniforms_buffer,
@@ -70,18 +82,123 @@ impl Backend for BBase {
             state.tile_offsets,
             state.final_index,
             state.sh_degree,
+            state.camera_model,
+            state.custom_projection,
+            state.antialias,
+            state.options,
+            state.background,
         )
     }
+
+    fn render_features(
+        state: FeatureRenderState<Self>,
+        features: FloatTensor<Self>,
+    ) -> FloatTensor<Self> {
+        render_features_forward(features, state)
+    }
+
+    fn render_features_bwd(
+        state: FeatureRenderState<Self>,
+        v_output: FloatTensor<Self>,
+    ) -> FloatTensor<Self> {
+        render_features_backward(v_output, state)
+    }
+
+    fn knn_mean_sq_dist(points: FloatTensor<Self>, cell_size: f32) -> FloatTensor<Self> {
+        brush_kernel::knn::knn_mean_sq_dist(points, cell_size)
+    }
 }
 
 #[derive(Debug)]
 struct RenderBackwards;
 
-const NUM_ARGS: usize = 6;
+const NUM_ARGS: usize = 7;
+
+// The inputs of a render, to render again in the backward pass, see
+// [`RenderOptions::recompute_backward`].
+#[derive(Debug, Clone)]
+struct RenderInputs<B: Backend> {
+    camera: Camera,
+    img_size: glam::UVec2,
+    means: FloatTensor<B>,
+    log_scales: FloatTensor<B>,
+    quats: FloatTensor<B>,
+    sh_coeffs: FloatTensor<B>,
+    raw_opacity: FloatTensor<B>,
+    render_u32_buffer: bool,
+    settings: RenderSettings<FloatTensor<B>>,
+}
+
+impl<B: Backend> RenderInputs<B> {
+    fn render(self) -> GaussianBackwardState<B> {
+        let means = Tensor::<B, 2>::from_primitive(TensorPrimitive::Float(self.means));
+        let xy_dummy = Tensor::<B, 2>::zeros([means.dims()[0], 2], &means.device());
+        let means = means.into_primitive().tensor();
+
+        let (out_img, aux) = B::render_splats(
+            &self.camera,
+            self.img_size,
+            means.clone(),
+            xy_dummy.into_primitive().tensor(),
+            self.log_scales.clone(),
+            self.quats.clone(),
+            self.sh_coeffs.clone(),
+            self.raw_opacity.clone(),
+            self.render_u32_buffer,
+            self.settings.clone(),
+        );
+        backward_state(
+            &self.camera,
+            self.settings,
+            [means, self.log_scales, self.quats, self.raw_opacity],
+            self.sh_coeffs,
+            out_img,
+            aux,
+        )
+    }
+}
+
+// What a render keeps for the backward pass.
+#[derive(Debug, Clone)]
+enum RenderState<B: Backend> {
+    Stored(GaussianBackwardState<B>),
+    Recompute(RenderInputs<B>),
+}
+
+fn backward_state<B: Backend>(
+    camera: &Camera,
+    settings: RenderSettings<FloatTensor<B>>,
+    [means, log_scales, quats, raw_opac]: [FloatTensor<B>; 4],
+    sh_coeffs: FloatTensor<B>,
+    out_img: FloatTensor<B>,
+    aux: RenderAuxPrimitive<B>,
+) -> GaussianBackwardState<B> {
+    let num_coeffs = Tensor::<B, 3>::from_primitive(TensorPrimitive::Float(sh_coeffs)).dims()[1];
+    GaussianBackwardState {
+        means,
+        log_scales,
+        quats,
+        raw_opac,
+        temporal: settings.temporal,
+        sh_degree: sh_degree_from_coeffs(num_coeffs as u32),
+        out_img,
+        projected_splats: aux.projected_splats,
+        uniforms_buffer: aux.uniforms_buffer,
+        final_index: aux.final_index,
+        tile_offsets: aux.tile_offsets,
+        compact_gid_from_isect: aux.compact_gid_from_isect,
+        global_from_compact_gid: aux.global_from_compact_gid,
+        camera_model: camera.model,
+        custom_projection: camera.projection_matrix().is_some(),
+        antialias: settings.antialias,
+        options: settings.options,
+        background: settings.background,
+    }
+}
 
 // Implement gradient registration when rendering backwards.
 impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
-    type State = GaussianBackwardState<B>;
+    type State = RenderState<B>;
 
     fn backward(
         self,
@@ -91,13 +208,19 @@ impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
     ) {
         let _span = tracing::trace_span!("render_gaussians backwards").entered();
 
-        let state = ops.state;
+        let state = match ops.state {
+            RenderState::Stored(state) => state,
+            RenderState::Recompute(inputs) => {
+                let _span = tracing::trace_span!("Recompute render").entered();
+                inputs.render()
+            }
+        };
 
         let v_output = grads.consume::<B>(&ops.node);
 
         // Register gradients for parent nodes (This code is already skipped entirely
         // if no parent nodes require gradients).
-        let [mean_parent, xys_parent, log_scales_parent, quats_parent, coeffs_parent, raw_opacity_parent] =
+        let [mean_parent, xys_parent, log_scales_parent, quats_parent, coeffs_parent, raw_opacity_parent, temporal_parent] =
             ops.parents;
 
         let v_tens = B::render_splats_bwd(state, v_output);
@@ -126,6 +249,33 @@ impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
         if let Some(node) = raw_opacity_parent {
             grads.register::<B>(node.id, v_tens.v_raw_opac);
         }
+
+        if let (Some(node), Some(v_temporal)) = (temporal_parent, v_tens.v_temporal) {
+            grads.register::<B>(node.id, v_temporal);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RenderFeaturesBackwards;
+
+impl<B: Backend> Backward<B, 1> for RenderFeaturesBackwards {
+    type State = FeatureRenderState<B>;
+
+    fn backward(
+        self,
+        ops: Ops<Self::State, 1>,
+        grads: &mut Gradients,
+        _checkpointer: &mut Checkpointer,
+    ) {
+        let _span = tracing::trace_span!("render_features backwards").entered();
+
+        let v_output = grads.consume::<B>(&ops.node);
+        let [features_parent] = ops.parents;
+
+        if let Some(node) = features_parent {
+            grads.register::<B>(node.id, B::render_features_bwd(ops.state, v_output));
+        }
     }
 }
 
@@ -141,10 +291,18 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
         sh_coeffs: FloatTensor<Self>,
         raw_opacity: FloatTensor<Self>,
         render_u32_buffer: bool,
+        settings: RenderSettings<FloatTensor<Self>>,
     ) -> (FloatTensor<Self>, RenderAuxPrimitive<Self>) {
         // Get backend tensors & dequantize if needed. Could try and support quantized inputs
         // in the future.
 
+        // Static splats have no temporal attributes. The means stand in for them, and don't
+        // get any extra gradients from it.
+        let temporal_node = settings
+            .temporal
+            .as_ref()
+            .map_or_else(|| means.node.clone(), |t| t.attributes.node.clone());
+
         // Prepare backward pass, and check if we even need to do it. Store nodes that need gradients.
         let prep_nodes = RenderBackwards
             .prepare::<C>([
@@ -154,6 +312,7 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
                 quats.node.clone(),
                 sh_coeffs.node.clone(),
                 raw_opacity.node.clone(),
+                temporal_node,
             ])
             .compute_bound()
             .stateful();
@@ -169,11 +328,12 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
             sh_coeffs.clone().into_primitive(),
             raw_opacity.clone().into_primitive(),
             render_u32_buffer,
+            settings.clone().map(|t| t.into_primitive()),
         );
 
         let wrapped_aux = RenderAuxPrimitive::<Self> {
             projected_splats: <Self as AutodiffBackend>::from_inner(aux.projected_splats.clone()),
-            radii: <Self as AutodiffBackend>::from_inner(aux.radii),
+            radii: <Self as AutodiffBackend>::from_inner(aux.radii.clone()),
             num_intersections: aux.num_intersections.clone(),
             num_visible: aux.num_visible.clone(),
             final_index: aux.final_index.clone(),
@@ -181,27 +341,55 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
             compact_gid_from_isect: aux.compact_gid_from_isect.clone(),
             global_from_compact_gid: aux.global_from_compact_gid.clone(),
             uniforms_buffer: aux.uniforms_buffer.clone(),
+            culled_intersections: aux.culled_intersections.clone(),
+            // Depth and normals aren't differentiable.
+            depth: aux.depth.clone().map(<Self as AutodiffBackend>::from_inner),
+            normals: aux
+                .normals
+                .clone()
+                .map(<Self as AutodiffBackend>::from_inner),
+            tile_width: aux.tile_width,
         };
 
         match prep_nodes {
             OpsKind::Tracked(prep) => {
-                // Save state needed for backward pass.
-                let state = GaussianBackwardState {
-                    means: means.into_primitive(),
-                    log_scales: log_scales.into_primitive(),
-                    quats: quats.into_primitive(),
-                    raw_opac: raw_opacity.into_primitive(),
-                    sh_degree: sh_degree_from_coeffs(
-                        Tensor::<Self, 3>::from_primitive(TensorPrimitive::Float(sh_coeffs)).dims()
-                            [1] as u32,
-                    ),
-                    out_img: out_img.clone(),
-                    projected_splats: aux.projected_splats,
-                    uniforms_buffer: aux.uniforms_buffer,
-                    final_index: aux.final_index,
-                    tile_offsets: aux.tile_offsets,
-                    compact_gid_from_isect: aux.compact_gid_from_isect,
-                    global_from_compact_gid: aux.global_from_compact_gid,
+                assert!(
+                    settings.solid.is_none(),
+                    "Solid renders aren't differentiable"
+                );
+
+                // Save state needed for backward pass. Depth isn't needed for the gradients, so
+                // isn't rendered again.
+                let settings = RenderSettings {
+                    render_depth: false,
+                    ..settings.map(|t| t.into_primitive())
+                };
+                let state = if settings.options.recompute_backward {
+                    RenderState::Recompute(RenderInputs {
+                        camera: camera.clone(),
+                        img_size,
+                        means: means.into_primitive(),
+                        log_scales: log_scales.into_primitive(),
+                        quats: quats.into_primitive(),
+                        sh_coeffs: sh_coeffs.into_primitive(),
+                        raw_opacity: raw_opacity.into_primitive(),
+                        render_u32_buffer,
+                        settings,
+                    })
+                } else {
+                    RenderState::Stored(backward_state(
+                        camera,
+                        settings,
+                        [
+                            means.into_primitive(),
+                            log_scales.into_primitive(),
+                            quats.into_primitive(),
+                            raw_opacity.into_primitive(),
+                        ],
+                        sh_coeffs.into_primitive(),
+                        out_img.clone(),
+                        aux,
+                    ))
                 };
 
                 let finish = prep.finish(state, out_img);
@@ -215,6 +403,56 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
             }
         }
     }
+
+    fn render_features(
+        state: FeatureRenderState<Self>,
+        features: FloatTensor<Self>,
+    ) -> FloatTensor<Self> {
+        // The projected splats aren't differentiated through, only the features are.
+        let state = inner_feature_state::<B, C>(state);
+
+        let prep_nodes = RenderFeaturesBackwards
+            .prepare::<C>([features.node.clone()])
+            .compute_bound()
+            .stateful();
+
+        let out = B::render_features(state.clone(), features.into_primitive());
+
+        match prep_nodes {
+            OpsKind::Tracked(prep) => prep.finish(state, out),
+            OpsKind::UnTracked(prep) => prep.finish(out),
+        }
+    }
+
+    fn render_features_bwd(
+        state: FeatureRenderState<Self>,
+        v_output: FloatTensor<Self>,
+    ) -> FloatTensor<Self> {
+        let v_features = B::render_features_bwd(
+            inner_feature_state::<B, C>(state),
+            <Self as AutodiffBackend>::inner(v_output),
+        );
+        <Self as AutodiffBackend>::from_inner(v_features)
+    }
+
+    fn knn_mean_sq_dist(points: FloatTensor<Self>, cell_size: f32) -> FloatTensor<Self> {
+        let dist = B::knn_mean_sq_dist(<Self as AutodiffBackend>::inner(points), cell_size);
+        <Self as AutodiffBackend>::from_inner(dist)
+    }
+}
+
+fn inner_feature_state<B: Backend, C: CheckpointStrategy>(
+    state: FeatureRenderState<Autodiff<B, C>>,
+) -> FeatureRenderState<B> {
+    FeatureRenderState {
+        projected_splats: <Autodiff<B, C> as AutodiffBackend>::inner(state.projected_splats),
+        uniforms_buffer: state.uniforms_buffer,
+        compact_gid_from_isect: state.compact_gid_from_isect,
+        global_from_compact_gid: state.global_from_compact_gid,
+        tile_offsets: state.tile_offsets,
+        final_index: state.final_index,
+        tile_width: state.tile_width,
+    }
 }
 
 impl Backend for Fusion<BBase> {
@@ -228,31 +466,57 @@ impl Backend for Fusion<BBase> {
         sh_coeffs: FloatTensor<Self>,
         raw_opacity: FloatTensor<Self>,
         render_u32_buffer: bool,
+        mut settings: RenderSettings<FloatTensor<Self>>,
     ) -> (FloatTensor<Self>, RenderAuxPrimitive<Self>) {
         struct CustomOp {
             cam: Camera,
             img_size: glam::UVec2,
+            time: Option<f32>,
             render_u32_buffer: bool,
+            // The temporal attributes are taken out, and passed as an input of the op instead.
+            settings: RenderSettings<FloatTensor<Fusion<BBase>>>,
             desc: CustomOpDescription,
         }
 
         impl Operation<FusionJitRuntime<WgpuRuntime, u32>> for CustomOp {
             fn execute(self: Box<Self>, h: &mut HandleContainer<JitFusionHandle<WgpuRuntime>>) {
-                let (
-                    [means, xy_dummy, log_scales, quats, sh_coeffs, raw_opacity],
-                    [projected_splats, uniforms_buffer, num_intersections, num_visible, final_index, tile_offsets, compact_gid_from_isect, global_from_compact_gid, radii, out_img],
-                ) = self.desc.consume();
+                // The temporal attributes are only an input when the splats are rendered at a
+                // point in time, and the depth and normals are only outputs when they're rendered.
+                let (inputs, outputs) = match (self.time.is_some(), self.settings.render_depth) {
+                    (false, false) => descriptions(self.desc.consume::<6, 11>()),
+                    (false, true) => descriptions(self.desc.consume::<6, 13>()),
+                    (true, false) => descriptions(self.desc.consume::<7, 11>()),
+                    (true, true) => descriptions(self.desc.consume::<7, 13>()),
+                };
+                let [means, xy_dummy, log_scales, quats, sh_coeffs, raw_opacity] =
+                    std::array::from_fn(|i| h.get_float_tensor::<BBase>(&inputs[i]));
+                let temporal = self.time.map(|time| TemporalRender {
+                    attributes: h.get_float_tensor::<BBase>(&inputs[6]),
+                    time,
+                });
+                let [projected_splats, uniforms_buffer, num_intersections, num_visible, final_index, tile_offsets, compact_gid_from_isect, global_from_compact_gid, radii, culled_intersections, out_img] =
+                    std::array::from_fn(|i| outputs[i].clone());
+                let depth_outputs = self
+                    .settings
+                    .render_depth
+                    .then(|| (outputs[11].clone(), outputs[12].clone()));
 
                 let (img, aux) = BBase::render_splats(
                     &self.cam,
                     self.img_size,
-                    h.get_float_tensor::<BBase>(&means),
-                    h.get_float_tensor::<BBase>(&xy_dummy),
-                    h.get_float_tensor::<BBase>(&log_scales),
-                    h.get_float_tensor::<BBase>(&quats),
-                    h.get_float_tensor::<BBase>(&sh_coeffs),
-                    h.get_float_tensor::<BBase>(&raw_opacity),
+                    means,
+                    xy_dummy,
+                    log_scales,
+                    quats,
+                    sh_coeffs,
+                    raw_opacity,
                     self.render_u32_buffer,
+                    RenderSettings {
+                        temporal,
+                        ..self
+                            .settings
+                            .map(|t| h.get_float_tensor::<BBase>(&t.into_description()))
+                    },
                 );
 
                 // Register output.
@@ -272,6 +536,14 @@ impl Backend for Fusion<BBase> {
                     aux.global_from_compact_gid,
                 );
                 h.register_float_tensor::<BBase>(&radii.id, aux.radii);
+                h.register_int_tensor::<BBase>(&culled_intersections.id, aux.culled_intersections);
+
+                if let (Some((depth, normals)), Some(depth_img), Some(normals_img)) =
+                    (depth_outputs, aux.depth, aux.normals)
+                {
+                    h.register_float_tensor::<BBase>(&depth.id, depth_img);
+                    h.register_float_tensor::<BBase>(&normals.id, normals_img);
+                }
             }
         }
 
@@ -282,8 +554,9 @@ impl Backend for Fusion<BBase> {
 
         let proj_size = size_of::<shaders::helpers::ProjectedSplat>() / 4;
         let uniforms_size = size_of::<shaders::helpers::RenderUniforms>() / 4;
-        let tile_bounds = calc_tile_bounds(img_size);
-        let max_intersects = max_intersections(img_size, num_points as u32);
+        let options = settings.options;
+        let tile_bounds = calc_tile_bounds(img_size, options.tile_width);
+        let max_intersects = max_intersections(img_size, num_points as u32, &options);
 
         // If render_u32_buffer is true, we render a packed buffer of u32 values, otherwise
         // render RGBA f32 values.
@@ -309,36 +582,61 @@ impl Backend for Fusion<BBase> {
                 .tensor_uninitialized(vec![max_intersects as usize], DType::I32),
             global_from_compact_gid: client.tensor_uninitialized(vec![num_points], DType::I32),
             radii: client.tensor_uninitialized(vec![num_points], DType::F32),
+            culled_intersections: client.tensor_uninitialized(vec![1], DType::I32),
+            depth: settings.render_depth.then(|| {
+                client.tensor_uninitialized(
+                    vec![img_size.y as usize, img_size.x as usize],
+                    DType::F32,
+                )
+            }),
+            normals: settings.render_depth.then(|| {
+                client.tensor_uninitialized(
+                    vec![img_size.y as usize, img_size.x as usize, 3],
+                    DType::F32,
+                )
+            }),
+            tile_width: options.tile_width,
         };
 
-        let desc = CustomOpDescription::new(
-            "render_splats",
-            &[
-                means.into_description(),
-                xy_grad_dummy.into_description(),
-                log_scales.into_description(),
-                quats.into_description(),
-                sh_coeffs.into_description(),
-                raw_opacity.into_description(),
-            ],
-            &[
-                aux.projected_splats.to_description_out(),
-                aux.uniforms_buffer.to_description_out(),
-                aux.num_intersections.to_description_out(),
-                aux.num_visible.to_description_out(),
-                aux.final_index.to_description_out(),
-                aux.tile_offsets.to_description_out(),
-                aux.compact_gid_from_isect.to_description_out(),
-                aux.global_from_compact_gid.to_description_out(),
-                aux.radii.to_description_out(),
-                out_img.to_description_out(),
-            ],
-        );
+        let mut outputs = vec![
+            aux.projected_splats.to_description_out(),
+            aux.uniforms_buffer.to_description_out(),
+            aux.num_intersections.to_description_out(),
+            aux.num_visible.to_description_out(),
+            aux.final_index.to_description_out(),
+            aux.tile_offsets.to_description_out(),
+            aux.compact_gid_from_isect.to_description_out(),
+            aux.global_from_compact_gid.to_description_out(),
+            aux.radii.to_description_out(),
+            aux.culled_intersections.to_description_out(),
+            out_img.to_description_out(),
+        ];
+        if let (Some(depth), Some(normals)) = (&aux.depth, &aux.normals) {
+            outputs.push(depth.to_description_out());
+            outputs.push(normals.to_description_out());
+        }
+
+        let time = settings.temporal.as_ref().map(|t| t.time);
+        let mut inputs = vec![
+            means.into_description(),
+            xy_grad_dummy.into_description(),
+            log_scales.into_description(),
+            quats.into_description(),
+            sh_coeffs.into_description(),
+            raw_opacity.into_description(),
+        ];
+        if let Some(temporal) = settings.temporal.take() {
+            inputs.push(temporal.attributes.into_description());
+        }
+
+        let desc = CustomOpDescription::new("render_splats", &inputs, &outputs);
 
         let op = CustomOp {
             cam: cam.clone(),
             img_size,
+            time,
             render_u32_buffer,
+            settings,
             desc: desc.clone(),
         };
 
@@ -358,8 +656,15 @@ impl Backend for Fusion<BBase> {
 
         impl Operation<FusionJitRuntime<WgpuRuntime, u32>> for CustomOp {
             fn execute(self: Box<Self>, h: &mut HandleContainer<JitFusionHandle<WgpuRuntime>>) {
-                let ([v_output], [v_means, v_quats, v_scales, v_coeffs, v_raw_opac, v_xy]) =
-                    self.desc.consume();
+                // The temporal gradients are only an output when rendered at a point in time.
+                let (inputs, outputs) = if self.state.temporal.is_some() {
+                    descriptions(self.desc.consume::<1, 7>())
+                } else {
+                    descriptions(self.desc.consume::<1, 6>())
+                };
+                let v_output = &inputs[0];
+                let [v_means, v_quats, v_scales, v_coeffs, v_raw_opac, v_xy] =
+                    std::array::from_fn(|i| outputs[i].clone());
 
                 let state = self.state;
 
@@ -368,6 +673,9 @@ impl Backend for Fusion<BBase> {
                     log_scales: h.get_float_tensor::<BBase>(&state.log_scales.into_description()),
                     quats: h.get_float_tensor::<BBase>(&state.quats.into_description()),
                     raw_opac: h.get_float_tensor::<BBase>(&state.raw_opac.into_description()),
+                    temporal: state
+                        .temporal
+                        .map(|t| t.map(|a| h.get_float_tensor::<BBase>(&a.into_description()))),
                     out_img: h.get_float_tensor::<BBase>(&state.out_img.into_description()),
                     projected_splats: h
                         .get_float_tensor::<BBase>(&state.projected_splats.into_description()),
@@ -380,10 +688,17 @@ impl Backend for Fusion<BBase> {
                     global_from_compact_gid: h
                         .get_int_tensor::<BBase>(&state.global_from_compact_gid.into_description()),
                     sh_degree: state.sh_degree,
+                    camera_model: state.camera_model,
+                    custom_projection: state.custom_projection,
+                    antialias: state.antialias,
+                    options: state.options,
+                    background: state
+                        .background
+                        .map(|t| h.get_float_tensor::<BBase>(&t.into_description())),
                 };
 
                 let grads =
-                    BBase::render_splats_bwd(inner_state, h.get_float_tensor::<BBase>(&v_output));
+                    BBase::render_splats_bwd(inner_state, h.get_float_tensor::<BBase>(v_output));
 
                 // // Register output.
                 h.register_float_tensor::<BBase>(&v_means.id, grads.v_means);
@@ -392,6 +707,10 @@ impl Backend for Fusion<BBase> {
                 h.register_float_tensor::<BBase>(&v_coeffs.id, grads.v_coeffs);
                 h.register_float_tensor::<BBase>(&v_raw_opac.id, grads.v_raw_opac);
                 h.register_float_tensor::<BBase>(&v_xy.id, grads.v_xy);
+                if let (Some(v_temporal), Some(v_temporal_out)) = (grads.v_temporal, outputs.get(6))
+                {
+                    h.register_float_tensor::<BBase>(&v_temporal_out.id, v_temporal);
+                }
             }
         }
 
@@ -408,29 +727,185 @@ impl Backend for Fusion<BBase> {
             v_scales: client.tensor_uninitialized(vec![num_points, 3], DType::F32),
             v_coeffs: client.tensor_uninitialized(vec![num_points, coeffs, 3], DType::F32),
             v_raw_opac: client.tensor_uninitialized(vec![num_points], DType::F32),
+            v_temporal: state
+                .temporal
+                .as_ref()
+                .map(|_| client.tensor_uninitialized(vec![num_points, 5], DType::F32)),
             v_xy: client.tensor_uninitialized(vec![num_points, 2], DType::F32),
         };
 
+        let mut outputs = vec![
+            grads.v_means.to_description_out(),
+            grads.v_quats.to_description_out(),
+            grads.v_scales.to_description_out(),
+            grads.v_coeffs.to_description_out(),
+            grads.v_raw_opac.to_description_out(),
+            grads.v_xy.to_description_out(),
+        ];
+        if let Some(v_temporal) = &grads.v_temporal {
+            outputs.push(v_temporal.to_description_out());
+        }
+
+        let desc =
+            CustomOpDescription::new("render_splat_bwd", &[v_output.into_description()], &outputs);
+
+        let op = CustomOp {
+            state,
+            desc: desc.clone(),
+        };
+
+        client.register(vec![stream], OperationDescription::Custom(desc), op);
+        grads
+    }
+
+    fn render_features(
+        state: FeatureRenderState<Self>,
+        features: FloatTensor<Self>,
+    ) -> FloatTensor<Self> {
+        struct CustomOp {
+            desc: CustomOpDescription,
+            tile_width: u32,
+        }
+
+        impl Operation<FusionJitRuntime<WgpuRuntime, u32>> for CustomOp {
+            fn execute(self: Box<Self>, h: &mut HandleContainer<JitFusionHandle<WgpuRuntime>>) {
+                let (inputs, [out_features]) = self.desc.consume::<7, 1>();
+                let [features, projected_splats, uniforms_buffer, compact_gid_from_isect, global_from_compact_gid, tile_offsets, final_index] =
+                    inputs;
+
+                let state = FeatureRenderState {
+                    projected_splats: h.get_float_tensor::<BBase>(&projected_splats),
+                    uniforms_buffer: h.get_int_tensor::<BBase>(&uniforms_buffer),
+                    compact_gid_from_isect: h.get_int_tensor::<BBase>(&compact_gid_from_isect),
+                    global_from_compact_gid: h.get_int_tensor::<BBase>(&global_from_compact_gid),
+                    tile_offsets: h.get_int_tensor::<BBase>(&tile_offsets),
+                    final_index: h.get_int_tensor::<BBase>(&final_index),
+                    tile_width: self.tile_width,
+                };
+                let out = BBase::render_features(state, h.get_float_tensor::<BBase>(&features));
+                h.register_float_tensor::<BBase>(&out_features.id, out);
+            }
+        }
+
+        let tile_width = state.tile_width;
+        let stream = features.stream;
+        let client = features.client.clone();
+        let channels = features.shape[1];
+        let [h, w] = [state.final_index.shape[0], state.final_index.shape[1]];
+        let out_features = client.tensor_uninitialized(vec![h, w, channels], DType::F32);
+
         let desc = CustomOpDescription::new(
-            "render_splat_bwd",
-            &[v_output.into_description()],
+            "render_features",
             &[
-                grads.v_means.to_description_out(),
-                grads.v_quats.to_description_out(),
-                grads.v_scales.to_description_out(),
-                grads.v_coeffs.to_description_out(),
-                grads.v_raw_opac.to_description_out(),
-                grads.v_xy.to_description_out(),
+                features.into_description(),
+                state.projected_splats.into_description(),
+                state.uniforms_buffer.into_description(),
+                state.compact_gid_from_isect.into_description(),
+                state.global_from_compact_gid.into_description(),
+                state.tile_offsets.into_description(),
+                state.final_index.into_description(),
             ],
+            &[out_features.to_description_out()],
+        );
+
+        let op = CustomOp {
+            desc: desc.clone(),
+            tile_width,
+        };
+        client.register(vec![stream], OperationDescription::Custom(desc), op);
+        out_features
+    }
+
+    fn render_features_bwd(
+        state: FeatureRenderState<Self>,
+        v_output: FloatTensor<Self>,
+    ) -> FloatTensor<Self> {
+        struct CustomOp {
+            desc: CustomOpDescription,
+            tile_width: u32,
+        }
+
+        impl Operation<FusionJitRuntime<WgpuRuntime, u32>> for CustomOp {
+            fn execute(self: Box<Self>, h: &mut HandleContainer<JitFusionHandle<WgpuRuntime>>) {
+                let (inputs, [v_features]) = self.desc.consume::<7, 1>();
+                let [v_output, projected_splats, uniforms_buffer, compact_gid_from_isect, global_from_compact_gid, tile_offsets, final_index] =
+                    inputs;
+
+                let state = FeatureRenderState {
+                    projected_splats: h.get_float_tensor::<BBase>(&projected_splats),
+                    uniforms_buffer: h.get_int_tensor::<BBase>(&uniforms_buffer),
+                    compact_gid_from_isect: h.get_int_tensor::<BBase>(&compact_gid_from_isect),
+                    global_from_compact_gid: h.get_int_tensor::<BBase>(&global_from_compact_gid),
+                    tile_offsets: h.get_int_tensor::<BBase>(&tile_offsets),
+                    final_index: h.get_int_tensor::<BBase>(&final_index),
+                    tile_width: self.tile_width,
+                };
+                let grads =
+                    BBase::render_features_bwd(state, h.get_float_tensor::<BBase>(&v_output));
+                h.register_float_tensor::<BBase>(&v_features.id, grads);
+            }
+        }
+
+        let tile_width = state.tile_width;
+        let stream = v_output.stream;
+        let client = v_output.client.clone();
+        let num_points = state.projected_splats.shape[0];
+        let channels = v_output.shape[2];
+        let v_features = client.tensor_uninitialized(vec![num_points, channels], DType::F32);
+
+        let desc = CustomOpDescription::new(
+            "render_features_bwd",
+            &[
+                v_output.into_description(),
+                state.projected_splats.into_description(),
+                state.uniforms_buffer.into_description(),
+                state.compact_gid_from_isect.into_description(),
+                state.global_from_compact_gid.into_description(),
+                state.tile_offsets.into_description(),
+                state.final_index.into_description(),
+            ],
+            &[v_features.to_description_out()],
         );
 
         let op = CustomOp {
-            state,
             desc: desc.clone(),
+            tile_width,
         };
+        client.register(vec![stream], OperationDescription::Custom(desc), op);
+        v_features
+    }
+
+    fn knn_mean_sq_dist(points: FloatTensor<Self>, cell_size: f32) -> FloatTensor<Self> {
+        struct CustomOp {
+            desc: CustomOpDescription,
+            cell_size: f32,
+        }
+
+        impl Operation<FusionJitRuntime<WgpuRuntime, u32>> for CustomOp {
+            fn execute(self: Box<Self>, h: &mut HandleContainer<JitFusionHandle<WgpuRuntime>>) {
+                let ([points], [mean_sq_dist]) = self.desc.consume::<1, 1>();
+                let out =
+                    BBase::knn_mean_sq_dist(h.get_float_tensor::<BBase>(&points), self.cell_size);
+                h.register_float_tensor::<BBase>(&mean_sq_dist.id, out);
+            }
+        }
+
+        let stream = points.stream;
+        let client = points.client.clone();
+        let mean_sq_dist = client.tensor_uninitialized(vec![points.shape[0]], DType::F32);
+
+        let desc = CustomOpDescription::new(
+            "knn_mean_sq_dist",
+            &[points.into_description()],
+            &[mean_sq_dist.to_description_out()],
+        );
 
+        let op = CustomOp {
+            desc: desc.clone(),
+            cell_size,
+        };
         client.register(vec![stream], OperationDescription::Custom(desc), op);
-        grads
+        mean_sq_dist
     }
 }
 
@@ -1,12 +1,94 @@
-use glam::Affine3A;
+use glam::{Affine3A, Mat4, Vec2};
 
-#[derive(Debug, Default, Clone)]
+use crate::raycast::Ray;
+
+/// Lens distortion of a camera. Like in OpenCV and COLMAP, the distortion is applied to
+/// normalized image coordinates, that is the camera space position divided by its depth.
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum CameraModel {
+    #[default]
+    Pinhole,
+    /// Radial-tangential distortion, with coefficients `[k1, k2, p1, p2]`.
+    OpenCv([f32; 4]),
+    /// Equidistant fisheye distortion, with coefficients `[k1, k2, k3, k4]`.
+    Fisheye([f32; 4]),
+}
+
+impl CameraModel {
+    /// The distortion coefficients, zero for a pinhole camera.
+    pub fn params(&self) -> [f32; 4] {
+        match self {
+            Self::Pinhole => [0.0; 4],
+            Self::OpenCv(params) | Self::Fisheye(params) => *params,
+        }
+    }
+
+    /// Distort normalized image coordinates.
+    pub fn distort(&self, uv: Vec2) -> Vec2 {
+        match *self {
+            Self::Pinhole => uv,
+            Self::OpenCv([k1, k2, p1, p2]) => {
+                let r2 = uv.length_squared();
+                let radial = 1.0 + r2 * (k1 + r2 * k2);
+                let xy = uv.x * uv.y;
+                uv * radial
+                    + glam::vec2(
+                        2.0 * p1 * xy + p2 * (r2 + 2.0 * uv.x * uv.x),
+                        p1 * (r2 + 2.0 * uv.y * uv.y) + 2.0 * p2 * xy,
+                    )
+            }
+            Self::Fisheye([k1, k2, k3, k4]) => {
+                let r = uv.length();
+                if r < 1e-8 {
+                    return uv;
+                }
+                let theta = r.atan();
+                let t2 = theta * theta;
+                let theta_d = theta * (1.0 + t2 * (k1 + t2 * (k2 + t2 * (k3 + t2 * k4))));
+                uv * (theta_d / r)
+            }
+        }
+    }
+
+    /// Undo [`Self::distort`]. There is no closed form for this, so this iterates towards
+    /// the undistorted coordinates.
+    pub fn undistort(&self, distorted: Vec2) -> Vec2 {
+        if *self == Self::Pinhole {
+            return distorted;
+        }
+        let mut uv = distorted;
+        for _ in 0..20 {
+            uv += distorted - self.distort(uv);
+        }
+        uv
+    }
+}
+
+/// How a camera projects camera space to the image.
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Projection {
+    /// Perspective projection with the field of view of the camera.
+    #[default]
+    Perspective,
+    /// Parallel projection, where the image covers `size` world units (width, height).
+    Orthographic { size: Vec2 },
+    /// Any projection from camera space to clip space. After the divide by w, x and y in
+    /// [-1, 1] cover the image from left to right and top to bottom, offset by the camera
+    /// center. Lens distortion isn't applied with a custom projection.
+    Matrix(Mat4),
+}
+
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Camera {
     pub fov_x: f64,
     pub fov_y: f64,
     pub center_uv: glam::Vec2,
     pub position: glam::Vec3,
     pub rotation: glam::Quat,
+    #[serde(default)]
+    pub model: CameraModel,
+    #[serde(default)]
+    pub projection: Projection,
 }
 
 impl Camera {
@@ -23,6 +105,32 @@ impl Camera {
             center_uv,
             position,
             rotation,
+            model: CameraModel::Pinhole,
+            projection: Projection::Perspective,
+        }
+    }
+
+    pub fn with_model(mut self, model: CameraModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    /// The matrix from camera space to clip space, or None for a perspective camera, which
+    /// is projected with its focal and lens distortion instead.
+    pub fn projection_matrix(&self) -> Option<Mat4> {
+        match self.projection {
+            Projection::Perspective => None,
+            Projection::Orthographic { size } => Some(Mat4::from_scale(glam::vec3(
+                2.0 / size.x,
+                2.0 / size.y,
+                1.0,
+            ))),
+            Projection::Matrix(matrix) => Some(matrix),
         }
     }
 
@@ -47,6 +155,47 @@ impl Camera {
     pub fn world_to_local(&self) -> Affine3A {
         self.local_to_world().inverse()
     }
+
+    /// The ray from the camera through a pixel.
+    pub fn pixel_ray(&self, img_size: glam::UVec2, pixel: glam::Vec2) -> Ray {
+        if let Some(projection) = self.projection_matrix() {
+            // Unproject two points on the ray at different clip space depths.
+            let ndc = (pixel - self.center(img_size)) / (0.5 * img_size.as_vec2());
+            let inv = projection.inverse();
+            let near = inv.project_point3(ndc.extend(0.0));
+            let far = inv.project_point3(ndc.extend(1.0));
+            return Ray {
+                origin: self.local_to_world().transform_point3(near),
+                dir: self.rotation * (far - near).normalize(),
+            };
+        }
+
+        let local = self
+            .model
+            .undistort((pixel - self.center(img_size)) / self.focal(img_size));
+        let dir = self.rotation * glam::vec3(local.x, local.y, 1.0).normalize();
+        Ray {
+            origin: self.position,
+            dir,
+        }
+    }
+
+    /// Project a world space point to pixel coordinates, the inverse of [`Self::pixel_ray`].
+    /// Returns None for points behind the camera.
+    pub fn world_to_pixel(&self, img_size: glam::UVec2, point: glam::Vec3) -> Option<Vec2> {
+        let local = self.world_to_local().transform_point3(point);
+
+        if let Some(projection) = self.projection_matrix() {
+            let ndc = projection.project_point3(local).truncate();
+            return Some(self.center(img_size) + ndc * 0.5 * img_size.as_vec2());
+        }
+
+        if local.z <= 0.0 {
+            return None;
+        }
+        let uv = self.model.distort(local.truncate() / local.z);
+        Some(self.center(img_size) + uv * self.focal(img_size))
+    }
 }
 // Converts field of view to focal length
 pub fn fov_to_focal(fov_rad: f64, pixels: u32) -> f64 {
@@ -57,3 +206,82 @@ pub fn fov_to_focal(fov_rad: f64, pixels: u32) -> f64 {
 pub fn focal_to_fov(focal: f64, pixels: u32) -> f64 {
     2.0 * f64::atan((pixels as f64) / (2.0 * focal))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undistort_inverts_distort() {
+        let models = [
+            CameraModel::Pinhole,
+            CameraModel::OpenCv([-0.1, 0.02, 0.001, -0.002]),
+            CameraModel::Fisheye([0.05, -0.01, 0.002, 0.0]),
+        ];
+        for model in models {
+            for uv in [Vec2::ZERO, glam::vec2(0.3, -0.2), glam::vec2(-0.5, 0.4)] {
+                let roundtrip = model.undistort(model.distort(uv));
+                assert!(
+                    roundtrip.abs_diff_eq(uv, 1e-4),
+                    "{model:?} {uv} {roundtrip}"
+                );
+            }
+        }
+        // A fisheye bends straight lines, even without extra distortion.
+        let fisheye = CameraModel::Fisheye([0.0; 4]);
+        assert!(fisheye.distort(glam::vec2(1.0, 0.0)).x < 1.0);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        )
+        .with_projection(Projection::Orthographic {
+            size: glam::vec2(4.0, 2.0),
+        });
+        let img_size = glam::uvec2(200, 100);
+
+        let center = camera.pixel_ray(img_size, glam::vec2(100.0, 50.0));
+        let corner = camera.pixel_ray(img_size, glam::vec2(200.0, 100.0));
+        assert!(center.dir.abs_diff_eq(glam::Vec3::Z, 1e-5));
+        assert!(corner.dir.abs_diff_eq(glam::Vec3::Z, 1e-5));
+        assert!(corner
+            .origin
+            .truncate()
+            .abs_diff_eq(glam::vec2(2.0, 1.0), 1e-5));
+    }
+
+    #[test]
+    fn world_to_pixel_inverts_pixel_ray() {
+        let camera = Camera::new(
+            glam::vec3(1.0, 2.0, 3.0),
+            glam::Quat::from_rotation_y(0.3),
+            0.8,
+            0.6,
+            glam::vec2(0.4, 0.5),
+        );
+        let img_size = glam::uvec2(200, 100);
+        let pixel = glam::vec2(150.0, 20.0);
+        assert!(camera
+            .world_to_pixel(img_size, camera.position - camera.rotation * glam::Vec3::Z)
+            .is_none());
+
+        for camera in [
+            camera.clone(),
+            camera.with_projection(Projection::Orthographic {
+                size: glam::vec2(4.0, 2.0),
+            }),
+        ] {
+            let ray = camera.pixel_ray(img_size, pixel);
+            let projected = camera
+                .world_to_pixel(img_size, ray.origin + ray.dir * 5.0)
+                .expect("Point is in front of the camera");
+            assert!(projected.abs_diff_eq(pixel, 1e-3));
+        }
+    }
+}
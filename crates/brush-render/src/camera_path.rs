@@ -0,0 +1,289 @@
+//! A camera path, used to render and preview fly-throughs of a scene.
+//!
+//! Camera paths are stored as JSON:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "interpolation": "smooth",
+//!   "keyframes": [
+//!     {
+//!       "time": 0.0,
+//!       "camera": {
+//!         "fov_x": 1.0,
+//!         "fov_y": 0.75,
+//!         "center_uv": [0.5, 0.5],
+//!         "position": [0.0, 0.0, -5.0],
+//!         "rotation": [0.0, 0.0, 0.0, 1.0]
+//!       }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! - `time` is in seconds. Keyframes don't need to be sorted.
+//! - `fov_x` and `fov_y` are in radians, `center_uv` is the principal point as a fraction of
+//!   the image size.
+//! - `position` is the camera position in world space, and `rotation` the camera to world
+//!   rotation as an `[x, y, z, w]` quaternion. Cameras look along +z, with +y pointing down.
+//! - `interpolation` is one of `step`, `linear` or `smooth`, and defaults to `smooth`.
+
+use glam::{Mat3, Quat, Vec2, Vec3};
+
+use crate::camera::Camera;
+
+pub const CAMERA_PATH_VERSION: u32 = 1;
+
+/// How cameras are interpolated between keyframes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Hold each keyframe until the next one.
+    Step,
+    /// Move at a constant speed between keyframes.
+    Linear,
+    /// Move along a Catmull-Rom spline through the keyframes.
+    #[default]
+    Smooth,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct CameraKeyframe {
+    /// Time of the keyframe in seconds.
+    pub time: f32,
+    pub camera: Camera,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct CameraPath {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+fn default_version() -> u32 {
+    CAMERA_PATH_VERSION
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * ((2.0 * p1)
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// The rotation of a camera at `position` looking at `target`, with `up` pointing up in the image.
+pub fn look_at(position: Vec3, target: Vec3, up: Vec3) -> Quat {
+    // Cameras look along +z, with +y pointing down.
+    let forward = (target - position).normalize();
+    let right = (-up).cross(forward).normalize();
+    let down = forward.cross(right);
+    Quat::from_mat3(&Mat3::from_cols(right, down, forward))
+}
+
+impl CameraPath {
+    pub fn new(interpolation: Interpolation, mut keyframes: Vec<CameraKeyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self {
+            version: CAMERA_PATH_VERSION,
+            interpolation,
+            keyframes,
+        }
+    }
+
+    /// A turntable path, circling once around `center` in `duration` seconds, starting at
+    /// `start` and always looking at the center. The camera circles around the `up` axis, and
+    /// stays at the same height along it.
+    pub fn orbit(center: Vec3, up: Vec3, start: Vec3, fov_y: f64, duration: f32) -> Self {
+        // Enough keyframes that the spline is very close to a circle.
+        const KEYFRAMES: usize = 64;
+
+        let up = up.normalize();
+        let offset = start - center;
+        let keyframes = (0..=KEYFRAMES)
+            .map(|i| {
+                let t = i as f32 / KEYFRAMES as f32;
+                let rotation = Quat::from_axis_angle(up, t * std::f32::consts::TAU);
+                let position = center + rotation * offset;
+                CameraKeyframe {
+                    time: t * duration,
+                    camera: Camera::new(
+                        position,
+                        look_at(position, center, up),
+                        fov_y,
+                        fov_y,
+                        Vec2::splat(0.5),
+                    ),
+                }
+            })
+            .collect();
+        Self::new(Interpolation::Smooth, keyframes)
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let path: Self = serde_json::from_str(json)?;
+        anyhow::ensure!(
+            path.version <= CAMERA_PATH_VERSION,
+            "Unsupported camera path version {}",
+            path.version
+        );
+        Ok(Self::new(path.interpolation, path.keyframes))
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    /// The camera at a time in seconds, clamped to the start and end of the path.
+    pub fn sample(&self, time: f32) -> Option<Camera> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+
+        if time <= first.time {
+            return Some(first.camera.clone());
+        }
+        if time >= last.time {
+            return Some(last.camera.clone());
+        }
+
+        // Index of the keyframe ending the current segment.
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        let (k1, k2) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 {
+            (time - k1.time) / span
+        } else {
+            1.0
+        };
+
+        let (c1, c2) = (&k1.camera, &k2.camera);
+        let position = match self.interpolation {
+            Interpolation::Step => return Some(c1.clone()),
+            Interpolation::Linear => c1.position.lerp(c2.position, t),
+            Interpolation::Smooth => {
+                let p0 = self.keyframes[next.saturating_sub(2)].camera.position;
+                let p3 = self.keyframes[(next + 1).min(self.keyframes.len() - 1)]
+                    .camera
+                    .position;
+                catmull_rom(p0, c1.position, c2.position, p3, t)
+            }
+        };
+        let rotation: Quat = c1.rotation.slerp(c2.rotation, t);
+        let lerp_f64 = |a: f64, b: f64| a + (b - a) * t as f64;
+        let center_uv: Vec2 = c1.center_uv.lerp(c2.center_uv, t);
+
+        // The lens model and projection can't be blended, keep those of the last keyframe.
+        Some(Camera {
+            fov_x: lerp_f64(c1.fov_x, c2.fov_x),
+            fov_y: lerp_f64(c1.fov_y, c2.fov_y),
+            center_uv,
+            position,
+            rotation,
+            ..c1.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{CameraModel, Projection};
+
+    fn keyframe(time: f32, x: f32) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            camera: Camera::new(
+                Vec3::new(x, 0.0, 0.0),
+                Quat::IDENTITY,
+                1.0,
+                1.0,
+                Vec2::splat(0.5),
+            ),
+        }
+    }
+
+    #[test]
+    fn sample_camera_path() {
+        let path = CameraPath::new(
+            Interpolation::Linear,
+            vec![keyframe(2.0, 4.0), keyframe(0.0, 0.0), keyframe(1.0, 2.0)],
+        );
+        assert_eq!(path.duration(), 2.0);
+
+        let sample = |t| path.sample(t).expect("Non empty path").position.x;
+        assert_eq!(sample(-1.0), 0.0);
+        assert_eq!(sample(0.5), 1.0);
+        assert_eq!(sample(1.5), 3.0);
+        assert_eq!(sample(3.0), 4.0);
+
+        // Points on a line stay on the line with the spline.
+        let mut keyframes = path.keyframes.clone();
+        keyframes.push(keyframe(3.0, 6.0));
+        let smooth = CameraPath::new(Interpolation::Smooth, keyframes);
+        let x = smooth.sample(1.5).expect("Non empty path").position.x;
+        assert!((x - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sample_keeps_lens_and_projection() {
+        let projection = Projection::Orthographic {
+            size: Vec2::new(4.0, 3.0),
+        };
+        let model = CameraModel::OpenCv([0.1, -0.05, 0.0, 0.0]);
+        let keyframes = [0.0, 1.0].map(|time| {
+            let mut key = keyframe(time, time);
+            key.camera = key.camera.with_model(model).with_projection(projection);
+            key
+        });
+        let path = CameraPath::new(Interpolation::Smooth, keyframes.to_vec());
+
+        let camera = path.sample(0.5).expect("Non empty path");
+        assert_eq!(camera.model, model);
+        assert_eq!(camera.projection, projection);
+        assert!((camera.position.x - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn orbit_looks_at_center() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let start = center + Vec3::new(4.0, -1.0, 0.0);
+        let path = CameraPath::orbit(center, Vec3::NEG_Y, start, 0.8, 10.0);
+        assert_eq!(path.duration(), 10.0);
+
+        for t in [0.0, 2.5, 3.3, 10.0] {
+            let camera = path.sample(t).expect("Non empty path");
+            let forward = camera.rotation * Vec3::Z;
+            let to_center = (center - camera.position).normalize();
+            assert!(
+                forward.dot(to_center) > 0.999,
+                "Not looking at the center at {t}"
+            );
+            // Stays at the same height.
+            assert!((camera.position.y - start.y).abs() < 1e-4);
+        }
+        let end = path.sample(10.0).expect("Non empty path").position;
+        assert!(end.distance(start) < 1e-4);
+    }
+
+    #[test]
+    fn camera_path_json() {
+        let path = CameraPath::new(Interpolation::Step, vec![keyframe(0.0, 1.0)]);
+        let json = path.to_json().expect("Serializable path");
+        let parsed = CameraPath::from_json(&json).expect("Valid path");
+        assert_eq!(parsed.interpolation, Interpolation::Step);
+        assert_eq!(parsed.keyframes[0].camera.position, Vec3::X);
+
+        let minimal = r#"{ "keyframes": [] }"#;
+        let parsed = CameraPath::from_json(minimal).expect("Valid path");
+        assert_eq!(parsed.interpolation, Interpolation::Smooth);
+    }
+}
@@ -0,0 +1,220 @@
+//! Match the colors of splat models, eg. captures made under different lighting that are
+//! merged in a [`crate::splat_scene::SplatScene`].
+
+use anyhow::anyhow;
+use ball_tree::BallTree;
+use burn::tensor::{Tensor, TensorData};
+use glam::Vec3;
+
+use crate::{gaussian_splats::Splats, render::SH_C0, Backend};
+
+// Nr. of overlapping splats needed to estimate a transform.
+const MIN_MATCHES: usize = 64;
+// Keep transforms from blowing up on scenes that barely overlap.
+const GAIN_RANGE: (f32, f32) = (0.25, 4.0);
+
+/// A per channel color transform, `color * gain + offset`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorTransform {
+    pub gain: Vec3,
+    pub offset: Vec3,
+}
+
+impl Default for ColorTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl ColorTransform {
+    pub const IDENTITY: Self = Self {
+        gain: Vec3::ONE,
+        offset: Vec3::ZERO,
+    };
+
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        color * self.gain + self.offset
+    }
+
+    /// Apply the transform to the colors of splats. The gain scales all SH coefficients, so
+    /// view dependent effects are scaled along, while the offset only shifts the base color.
+    pub fn apply_to_splats<B: Backend>(&self, mut splats: Splats<B>) -> Splats<B> {
+        if *self == Self::IDENTITY {
+            return splats;
+        }
+
+        let n_coeffs = splats.sh_coeffs.dims()[1];
+        // The base color is `dc * SH_C0 + 0.5`, solve for the new dc coefficient.
+        let dc_bias = (0.5 * (self.gain - 1.0) + self.offset) / SH_C0;
+        let mut bias = vec![0.0; n_coeffs * 3];
+        bias[..3].copy_from_slice(&dc_bias.to_array());
+
+        Splats::map_param(&mut splats.sh_coeffs, |coeffs| {
+            let device = coeffs.device();
+            let gain = Tensor::<B, 1>::from_floats(self.gain.to_array(), &device);
+            let bias = Tensor::from_data(TensorData::new(bias, [1, n_coeffs, 3]), &device);
+            coeffs * gain.reshape([1, 1, 3]) + bias
+        });
+        splats
+    }
+}
+
+// Least squares fit of `y = x * gain + offset`, or `y = x * gain` without an offset.
+fn fit_channel(pairs: &[(f32, f32)], with_offset: bool) -> (f32, f32) {
+    let n = pairs.len() as f32;
+    let (gain, offset) = if with_offset {
+        let mean_x = pairs.iter().map(|p| p.0).sum::<f32>() / n;
+        let mean_y = pairs.iter().map(|p| p.1).sum::<f32>() / n;
+        let cov: f32 = pairs.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let var: f32 = pairs.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        if var > 1e-6 {
+            let gain = (cov / var).clamp(GAIN_RANGE.0, GAIN_RANGE.1);
+            (gain, mean_y - gain * mean_x)
+        } else {
+            (1.0, mean_y - mean_x)
+        }
+    } else {
+        let xy: f32 = pairs.iter().map(|(x, y)| x * y).sum();
+        let xx: f32 = pairs.iter().map(|(x, _)| x * x).sum();
+        let gain = if xx > 1e-6 { xy / xx } else { 1.0 };
+        (gain.clamp(GAIN_RANGE.0, GAIN_RANGE.1), 0.0)
+    };
+    (gain, offset)
+}
+
+struct ColorSamples {
+    positions: Vec<[f64; 3]>,
+    colors: Vec<Vec3>,
+    radii: Vec<f32>,
+}
+
+// Read the positions, base colors and sizes of the opaque splats. Transparent splats are left
+// out, as their colors barely contribute to the image.
+async fn read_samples<B: Backend>(splats: &Splats<B>) -> anyhow::Result<ColorSamples> {
+    let read_err = |e| anyhow!("Failed to read data from splat {e:?}");
+    let n_coeffs = splats.sh_coeffs.dims()[1];
+
+    let means: Vec<f32> = splats
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let scales: Vec<f32> = splats
+        .scales()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let opacities: Vec<f32> = splats
+        .opacity()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+    let sh_coeffs: Vec<f32> = splats
+        .sh_coeffs
+        .val()
+        .into_data_async()
+        .await
+        .to_vec()
+        .map_err(read_err)?;
+
+    let mut samples = ColorSamples {
+        positions: vec![],
+        colors: vec![],
+        radii: vec![],
+    };
+    for (i, &opacity) in opacities.iter().enumerate() {
+        if opacity < 0.5 {
+            continue;
+        }
+        let m = &means[i * 3..i * 3 + 3];
+        let s = &scales[i * 3..i * 3 + 3];
+        let dc = Vec3::from_slice(&sh_coeffs[i * n_coeffs * 3..i * n_coeffs * 3 + 3]);
+        samples
+            .positions
+            .push([m[0] as f64, m[1] as f64, m[2] as f64]);
+        samples.colors.push(dc * SH_C0 + 0.5);
+        samples.radii.push(s[0].max(s[1]).max(s[2]));
+    }
+    Ok(samples)
+}
+
+/// Estimate the color transform that matches `splats` to `reference`, where both overlap.
+/// Both are expected to be placed in the same space.
+///
+/// Splats are matched to the nearest reference splat within twice their size. Without
+/// `ambient`, only a gain per channel is estimated, which keeps black black. With `ambient`,
+/// an offset is estimated as well, to account for a difference in ambient light.
+///
+/// Returns the identity transform when the models barely overlap.
+pub async fn estimate_color_transform<B: Backend>(
+    reference: &Splats<B>,
+    splats: &Splats<B>,
+    ambient: bool,
+) -> anyhow::Result<ColorTransform> {
+    let reference = read_samples(reference).await?;
+    let samples = read_samples(splats).await?;
+
+    if reference.positions.is_empty() {
+        return Ok(ColorTransform::IDENTITY);
+    }
+
+    let tree = BallTree::new(reference.positions.clone(), reference.colors.clone());
+    let pairs: Vec<(Vec3, Vec3)> = samples
+        .positions
+        .iter()
+        .zip(samples.colors.iter().zip(&samples.radii))
+        .filter_map(|(pos, (&color, &radius))| {
+            let (_, dist, &ref_color) = tree.query().nn(pos).next()?;
+            (dist < 2.0 * radius as f64).then_some((color, ref_color))
+        })
+        .collect();
+
+    if pairs.len() < MIN_MATCHES {
+        log::warn!(
+            "Only {} overlapping splats, not harmonizing colors",
+            pairs.len()
+        );
+        return Ok(ColorTransform::IDENTITY);
+    }
+
+    let mut gain = Vec3::ONE;
+    let mut offset = Vec3::ZERO;
+    for c in 0..3 {
+        let channel: Vec<_> = pairs.iter().map(|(x, y)| (x[c], y[c])).collect();
+        (gain[c], offset[c]) = fit_channel(&channel, ambient);
+    }
+    Ok(ColorTransform { gain, offset })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_color_transform() {
+        let pairs: Vec<_> = (0..10)
+            .map(|i| {
+                let x = i as f32 / 10.0;
+                (x, x * 0.8 + 0.1)
+            })
+            .collect();
+
+        let (gain, offset) = fit_channel(&pairs, true);
+        assert!((gain - 0.8).abs() < 1e-4);
+        assert!((offset - 0.1).abs() < 1e-4);
+
+        let (gain, offset) = fit_channel(&pairs, false);
+        assert!(gain > 0.8);
+        assert_eq!(offset, 0.0);
+
+        let transform = ColorTransform {
+            gain: Vec3::splat(gain),
+            offset: Vec3::ZERO,
+        };
+        assert_eq!(transform.apply(Vec3::ZERO), Vec3::ZERO);
+    }
+}
@@ -0,0 +1,207 @@
+//! 3D color lookup tables, as used for color grading.
+//!
+//! LUTs are loaded from `.cube` files. These list `LUT_3D_SIZE^3` output colors, with the red
+//! input changing fastest, then green, then blue. Input colors are mapped from
+//! `DOMAIN_MIN..DOMAIN_MAX` (defaulting to 0..1) onto the table, and interpolated trilinearly.
+
+use anyhow::Context;
+use burn::{
+    prelude::Backend,
+    tensor::{Int, Tensor, TensorData},
+};
+use glam::Vec3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorLut {
+    pub title: Option<String>,
+    /// Number of entries along each axis of the table.
+    pub size: usize,
+    pub domain_min: Vec3,
+    pub domain_max: Vec3,
+    /// Output colors, `size^3` rgb triplets.
+    pub table: Vec<f32>,
+}
+
+fn parse_vec3(values: &[&str]) -> anyhow::Result<Vec3> {
+    anyhow::ensure!(values.len() == 3, "Expected 3 values, got {}", values.len());
+    let mut v = [0.0; 3];
+    for (v, s) in v.iter_mut().zip(values) {
+        *v = s.parse().with_context(|| format!("Invalid number {s}"))?;
+    }
+    Ok(Vec3::from_array(v))
+}
+
+impl ColorLut {
+    /// Parse a `.cube` file. Only 3D LUTs are supported.
+    pub fn from_cube(cube: &str) -> anyhow::Result<Self> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = Vec3::ZERO;
+        let mut domain_max = Vec3::ONE;
+        let mut table = vec![];
+
+        for (i, line) in cube.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let err = || format!("Invalid LUT on line {}", i + 1);
+
+            match parts[0] {
+                "TITLE" => {
+                    title = Some(line["TITLE".len()..].trim().trim_matches('"').to_owned());
+                }
+                "LUT_3D_SIZE" => {
+                    let n: usize = parts
+                        .get(1)
+                        .and_then(|n| n.parse().ok())
+                        .with_context(err)?;
+                    anyhow::ensure!(n >= 2, "LUT size must be at least 2");
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => anyhow::bail!("1D LUTs aren't supported"),
+                "DOMAIN_MIN" => domain_min = parse_vec3(&parts[1..]).with_context(err)?,
+                "DOMAIN_MAX" => domain_max = parse_vec3(&parts[1..]).with_context(err)?,
+                // Same as DOMAIN_MIN and DOMAIN_MAX, for all channels at once.
+                "LUT_3D_INPUT_RANGE" => {
+                    let range: Vec<f32> =
+                        parts[1..].iter().filter_map(|v| v.parse().ok()).collect();
+                    let [min, max] = range[..] else {
+                        anyhow::bail!(err());
+                    };
+                    domain_min = Vec3::splat(min);
+                    domain_max = Vec3::splat(max);
+                }
+                _ => {
+                    let color = parse_vec3(&parts).with_context(err)?;
+                    table.extend(color.to_array());
+                }
+            }
+        }
+
+        let size = size.context("LUT is missing LUT_3D_SIZE")?;
+        anyhow::ensure!(
+            table.len() == size.pow(3) * 3,
+            "LUT has {} entries, expected {}",
+            table.len() / 3,
+            size.pow(3)
+        );
+        anyhow::ensure!(domain_max.cmpgt(domain_min).all(), "LUT domain is empty");
+
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> Vec3 {
+        let i = (r + g * self.size + b * self.size * self.size) * 3;
+        Vec3::from_slice(&self.table[i..i + 3])
+    }
+
+    /// Look up a single color.
+    pub fn sample(&self, color: Vec3) -> Vec3 {
+        let max = (self.size - 1) as f32;
+        let pos = ((color - self.domain_min) / (self.domain_max - self.domain_min))
+            .clamp(Vec3::ZERO, Vec3::ONE)
+            * max;
+        let lo = pos.floor().min(Vec3::splat(max - 1.0));
+        let t = pos - lo;
+        let [r, g, b] = lo.to_array().map(|x| x as usize);
+
+        let lerp = |a: Vec3, b: Vec3, t: f32| a.lerp(b, t);
+        let c00 = lerp(self.entry(r, g, b), self.entry(r + 1, g, b), t.x);
+        let c10 = lerp(self.entry(r, g + 1, b), self.entry(r + 1, g + 1, b), t.x);
+        let c01 = lerp(self.entry(r, g, b + 1), self.entry(r + 1, g, b + 1), t.x);
+        let c11 = lerp(
+            self.entry(r, g + 1, b + 1),
+            self.entry(r + 1, g + 1, b + 1),
+            t.x,
+        );
+        lerp(lerp(c00, c10, t.y), lerp(c01, c11, t.y), t.z)
+    }
+
+    /// Apply the LUT to an image of shape `[h, w, c]`, with rgb in the first 3 channels. Any
+    /// other channels, like alpha, are kept as is.
+    pub fn apply<B: Backend>(&self, img: Tensor<B, 3>) -> Tensor<B, 3> {
+        let device = img.device();
+        let [h, w, c] = img.dims();
+        let n = h * w;
+        let size = self.size;
+        let max = (size - 1) as f32;
+
+        let table: Tensor<B, 2> = Tensor::from_data(
+            TensorData::new(self.table.clone(), [size.pow(3), 3]),
+            &device,
+        );
+        let domain_min: Tensor<B, 2> = Tensor::from_floats([self.domain_min.to_array()], &device);
+        let domain_range: Tensor<B, 2> =
+            Tensor::from_floats([(self.domain_max - self.domain_min).to_array()], &device);
+
+        let rgb = img.clone().slice([0..h, 0..w, 0..3]).reshape([n, 3]);
+        let pos = ((rgb - domain_min) / domain_range)
+            .clamp(0.0, 1.0)
+            .mul_scalar(max);
+        let lo = pos.clone().floor().clamp_max(max - 1.0);
+        let t = pos - lo.clone();
+        let lo = lo.int();
+
+        let channel = |x: &Tensor<B, 2>, i: usize| x.clone().slice([0..n, i..i + 1]);
+        let channel_int = |x: &Tensor<B, 2, Int>, i: usize| x.clone().slice([0..n, i..i + 1]);
+
+        let mut out = Tensor::<B, 2>::zeros([n, 3], &device);
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let mut index = Tensor::<B, 2, Int>::zeros([n, 1], &device);
+            let mut weight = Tensor::<B, 2>::ones([n, 1], &device);
+            for (i, &o) in offset.iter().enumerate() {
+                let stride = size.pow(i as u32) as i32;
+                index = index + channel_int(&lo, i).add_scalar(o as i32).mul_scalar(stride);
+                let t = channel(&t, i);
+                weight = weight * if o == 1 { t } else { t.neg().add_scalar(1.0) };
+            }
+            out = out + table.clone().select(0, index.reshape([n])) * weight;
+        }
+
+        let out = out.reshape([h, w, 3]);
+        if c > 3 {
+            Tensor::cat(vec![out, img.slice([0..h, 0..w, 3..c])], 2)
+        } else {
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_sample_cube() {
+        // Identity LUT, with the red and blue outputs swapped.
+        let mut cube = String::from("# Swap\nTITLE \"Swap red and blue\"\nLUT_3D_SIZE 2\n\n");
+        for b in 0..2 {
+            for g in 0..2 {
+                for r in 0..2 {
+                    cube.push_str(&format!("{b} {g} {r}\n"));
+                }
+            }
+        }
+
+        let lut = ColorLut::from_cube(&cube).expect("Valid LUT");
+        assert_eq!(lut.title.as_deref(), Some("Swap red and blue"));
+        assert_eq!(lut.size, 2);
+
+        let color = lut.sample(Vec3::new(0.25, 0.5, 1.0));
+        assert!(color.abs_diff_eq(Vec3::new(1.0, 0.5, 0.25), 1e-6));
+        // Colors outside of the domain are clamped.
+        assert!(lut.sample(Vec3::splat(2.0)).abs_diff_eq(Vec3::ONE, 1e-6));
+
+        assert!(ColorLut::from_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(ColorLut::from_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+    }
+}
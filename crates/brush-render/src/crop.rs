@@ -0,0 +1,280 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+use burn::tensor::{Bool, Tensor};
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+use crate::Backend;
+
+/// A simple shape that can be used to crop splats.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum CropShape {
+    /// An oriented box, with the extent measured from the center.
+    Box {
+        center: Vec3,
+        extent: Vec3,
+        rotation: Quat,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    /// The half space on the side the normal points to, ie. where `dot(normal, p) >= offset`.
+    Plane {
+        normal: Vec3,
+        offset: f32,
+    },
+}
+
+/// How a shape is combined with the shapes before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum CropOp {
+    Union,
+    Intersection,
+    Subtraction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CropLayer {
+    pub op: CropOp,
+    pub shape: CropShape,
+}
+
+/// A volume built up from simple shapes, applied in order.
+///
+/// When the first layer is a union the volume starts out empty, otherwise it starts out as
+/// the whole scene, so eg. a single subtraction cuts a hole in the scene. An empty volume
+/// contains everything.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CropVolume {
+    pub layers: Vec<CropLayer>,
+}
+
+/// An oriented box that splats are cropped to while rendering, see
+/// [`crate::gaussian_splats::Splats::with_crop_box`].
+///
+/// Unlike a [`CropVolume`] this is applied when projecting the splats, so it can be moved
+/// around every frame for free.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CropBox {
+    pub center: Vec3,
+    /// Half the size of the box along each of its axes.
+    pub extent: Vec3,
+    pub rotation: Quat,
+}
+
+impl CropBox {
+    pub fn new(center: Vec3, extent: Vec3) -> Self {
+        Self {
+            center,
+            extent,
+            rotation: Quat::IDENTITY,
+        }
+    }
+
+    /// The transform from world space to the box, where the box spans -1 to 1 on every axis.
+    pub fn world_to_unit(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.extent, self.rotation, self.center).inverse()
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        let local = self.rotation.inverse() * (point - self.center);
+        local.abs().cmple(self.extent).all()
+    }
+
+    /// The world space axes of the box, scaled by the extent.
+    pub fn axes(&self) -> [Vec3; 3] {
+        [Vec3::X, Vec3::Y, Vec3::Z].map(|axis| self.rotation * (axis * self.extent))
+    }
+
+    /// The 8 corners of the box, where bit 0, 1 and 2 of the index are the side along the x, y
+    /// and z axis.
+    pub fn corners(&self) -> [Vec3; 8] {
+        let [x, y, z] = self.axes();
+        std::array::from_fn(|i| {
+            let side = |bit: usize| if i & (1 << bit) == 0 { -1.0 } else { 1.0 };
+            self.center + x * side(0) + y * side(1) + z * side(2)
+        })
+    }
+
+    /// The layer of a [`CropVolume`] that crops to this box.
+    pub fn as_layer(&self) -> CropLayer {
+        CropLayer {
+            op: CropOp::Intersection,
+            shape: CropShape::Box {
+                center: self.center,
+                extent: self.extent,
+                rotation: self.rotation,
+            },
+        }
+    }
+}
+
+impl CropShape {
+    // 1.0 for every point inside of the shape, 0.0 otherwise.
+    fn inside<B: Backend>(&self, points: Tensor<B, 2>) -> Tensor<B, 1> {
+        let device = points.device();
+        let [n, _] = points.dims();
+        let vec = |v: Vec3| Tensor::<B, 1>::from_floats(v.to_array(), &device);
+
+        let inside = match *self {
+            Self::Box {
+                center,
+                extent,
+                rotation,
+            } => {
+                // Points are row vectors, so to multiply by the inverse rotation, multiply by
+                // the rotation itself.
+                let rot = Tensor::<B, 1>::from_floats(
+                    Mat3::from_quat(rotation).transpose().to_cols_array(),
+                    &device,
+                )
+                .reshape([3, 3]);
+                let local = (points - vec(center).reshape([1, 3])).matmul(rot);
+                (local.abs() - vec(extent).reshape([1, 3]))
+                    .max_dim(1)
+                    .lower_equal_elem(0.0)
+            }
+            Self::Sphere { center, radius } => (points - vec(center).reshape([1, 3]))
+                .powf_scalar(2.0)
+                .sum_dim(1)
+                .lower_equal_elem(radius * radius),
+            Self::Plane { normal, offset } => points
+                .matmul(vec(normal.normalize()).reshape([3, 1]))
+                .greater_equal_elem(offset),
+        };
+
+        inside.float().reshape([n])
+    }
+}
+
+impl CropVolume {
+    pub fn new(layers: Vec<CropLayer>) -> Self {
+        Self { layers }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Calculate which of the points `[n, 3]` lie inside of this volume.
+    pub fn contains<B: Backend>(&self, points: Tensor<B, 2>) -> Tensor<B, 1, Bool> {
+        let [n, _] = points.dims();
+        let device = points.device();
+
+        let start = match self.layers.first() {
+            Some(layer) if layer.op == CropOp::Union => Tensor::zeros([n], &device),
+            _ => Tensor::ones([n], &device),
+        };
+
+        self.layers
+            .iter()
+            .fold(start, |acc, layer| {
+                let inside = layer.shape.inside(points.clone());
+                match layer.op {
+                    CropOp::Union => (acc + inside).clamp_max(1.0),
+                    CropOp::Intersection => acc * inside,
+                    CropOp::Subtraction => acc * (inside.neg() + 1.0),
+                }
+            })
+            .greater_elem(0.5)
+    }
+}
+
+/// Parse a crop layer from a string like `[op:]shape:numbers`, eg. `box:0,0,0,1,1,1` or
+/// `subtract:sphere:0,1,0,0.5`.
+///
+/// The op is one of `union` (the default), `intersect` or `subtract`. Shapes are
+/// - `box:cx,cy,cz,ex,ey,ez` with an optional rotation quaternion `,qx,qy,qz,qw`
+/// - `sphere:cx,cy,cz,radius`
+/// - `plane:nx,ny,nz,offset`
+impl FromStr for CropLayer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split(':').collect();
+        let (op, shape, values) = match parts.as_slice() {
+            [shape, values] => ("union", *shape, *values),
+            [op, shape, values] => (*op, *shape, *values),
+            _ => anyhow::bail!("Invalid crop layer '{s}', expected [op:]shape:values"),
+        };
+
+        let op = match op {
+            "union" => CropOp::Union,
+            "intersect" => CropOp::Intersection,
+            "subtract" => CropOp::Subtraction,
+            _ => anyhow::bail!("Unknown crop operation '{op}'"),
+        };
+
+        let values = values
+            .split(',')
+            .map(|v| v.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid numbers in crop layer '{s}'"))?;
+
+        let shape = match (shape, values.as_slice()) {
+            ("box", [cx, cy, cz, ex, ey, ez]) => CropShape::Box {
+                center: Vec3::new(*cx, *cy, *cz),
+                extent: Vec3::new(*ex, *ey, *ez),
+                rotation: Quat::IDENTITY,
+            },
+            ("box", [cx, cy, cz, ex, ey, ez, qx, qy, qz, qw]) => CropShape::Box {
+                center: Vec3::new(*cx, *cy, *cz),
+                extent: Vec3::new(*ex, *ey, *ez),
+                rotation: Quat::from_xyzw(*qx, *qy, *qz, *qw).normalize(),
+            },
+            ("sphere", [cx, cy, cz, radius]) => CropShape::Sphere {
+                center: Vec3::new(*cx, *cy, *cz),
+                radius: *radius,
+            },
+            ("plane", [nx, ny, nz, offset]) => CropShape::Plane {
+                normal: Vec3::new(*nx, *ny, *nz),
+                offset: *offset,
+            },
+            _ => anyhow::bail!("Invalid crop shape '{shape}' with {} values", values.len()),
+        };
+
+        Ok(Self { op, shape })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_crop_layers() {
+        let layer: CropLayer = "box:0,1,2,1,1,1".parse().expect("Valid box");
+        assert_eq!(layer.op, CropOp::Union);
+        assert_eq!(
+            layer.shape,
+            CropShape::Box {
+                center: Vec3::new(0.0, 1.0, 2.0),
+                extent: Vec3::ONE,
+                rotation: Quat::IDENTITY,
+            }
+        );
+
+        let layer: CropLayer = "subtract:sphere:0,0,0,0.5".parse().expect("Valid sphere");
+        assert_eq!(layer.op, CropOp::Subtraction);
+
+        assert!("sphere:0,0,0".parse::<CropLayer>().is_err());
+        assert!("xor:plane:0,1,0,0".parse::<CropLayer>().is_err());
+    }
+
+    #[test]
+    fn crop_box_unit_transform() {
+        let crop_box = CropBox {
+            center: Vec3::new(1.0, 2.0, 3.0),
+            extent: Vec3::new(0.5, 1.0, 2.0),
+            rotation: Quat::from_rotation_y(0.5),
+        };
+        let to_unit = crop_box.world_to_unit();
+        for corner in crop_box.corners() {
+            let local = to_unit.transform_point3(corner);
+            assert!((local.abs() - Vec3::ONE).abs().max_element() < 1e-5);
+            assert!(crop_box.contains(crop_box.center.lerp(corner, 0.99)));
+            assert!(!crop_box.contains(crop_box.center.lerp(corner, 1.01)));
+        }
+    }
+}
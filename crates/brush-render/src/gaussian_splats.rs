@@ -1,17 +1,25 @@
 use crate::{
+    background::Background,
     bounding_box::BoundingBox,
     camera::Camera,
-    render::{sh_coeffs_for_degree, sh_degree_from_coeffs},
+    crop::{CropBox, CropVolume},
+    render::{
+        sh_coeffs_for_degree, sh_degree_from_coeffs, RenderOptions, RenderSettings, SolidRender,
+        TemporalRender, SH_C0,
+    },
     safetensor_utils::safetensor_to_burn,
-    Backend, RenderAux,
+    sh_rotation::sh_rotation_matrix,
+    Backend, RenderAux, RenderAuxPrimitive,
 };
-use ball_tree::BallTree;
 use burn::{
     config::Config,
-    module::{Module, Param, ParamId},
-    tensor::{activation::sigmoid, Tensor, TensorData, TensorPrimitive},
+    module::{Ignored, Module, Param, ParamId},
+    tensor::{
+        activation::sigmoid, ops::FloatTensor, Bool, DType, FloatDType, Int, Tensor, TensorData,
+        TensorPrimitive,
+    },
 };
-use glam::{Quat, Vec3};
+use glam::{Affine3A, Quat, Vec3};
 use rand::Rng;
 use safetensors::SafeTensors;
 
@@ -29,14 +37,131 @@ pub struct Splats<B: Backend> {
     pub raw_opacity: Param<Tensor<B, 1>>,
     pub log_scales: Param<Tensor<B, 2>>,
 
+    /// Optional integer label per splat, eg. to segment a scene into separate objects.
+    pub labels: Option<Tensor<B, 1, Int>>,
+
+    /// Optional attributes to animate splats over time, for dynamic scenes.
+    pub temporal: Option<TemporalAttributes<B>>,
+
+    /// Only splats inside of this box are rendered, see [`Self::with_crop_box`].
+    pub crop_box: Ignored<Option<CropBox>>,
+
+    /// Render the splats as solid surfaces, see [`Self::with_solid`].
+    pub solid: Ignored<Option<SolidRender>>,
+
+    /// How the splats are rasterized, see [`Self::with_render_options`].
+    pub render_options: Ignored<RenderOptions>,
+
+    /// Time in seconds to render dynamic splats at, see [`Self::with_time`].
+    pub time: Ignored<Option<f32>>,
+
     // Dummy input to track screenspace gradient.
     pub xys_dummy: Tensor<B, 2>,
 }
 
+/// Per splat attributes for dynamic scenes.
+///
+/// Splats move linearly with their velocity, and fade in and out around their timestamp.
+#[derive(Module, Debug)]
+pub struct TemporalAttributes<B: Backend> {
+    /// Velocity of every splat, in units per second.
+    pub velocities: Param<Tensor<B, 2>>,
+    /// Time in seconds where a splat is at its mean, and at its most opaque.
+    pub times: Param<Tensor<B, 1>>,
+    /// Log of the standard deviation in seconds of how quickly a splat fades around its time.
+    pub log_durations: Param<Tensor<B, 1>>,
+}
+
+impl<B: Backend> TemporalAttributes<B> {
+    pub fn new(velocities: Tensor<B, 2>, times: Tensor<B, 1>, log_durations: Tensor<B, 1>) -> Self {
+        assert_eq!(velocities.dims()[1], 3, "Velocities must be 3D");
+        assert_eq!(
+            times.dims()[0],
+            velocities.dims()[0],
+            "Need exactly one time per splat"
+        );
+        assert_eq!(
+            log_durations.dims()[0],
+            velocities.dims()[0],
+            "Need exactly one duration per splat"
+        );
+
+        Self {
+            velocities: Param::initialized(ParamId::new(), velocities.detach().require_grad()),
+            times: Param::initialized(ParamId::new(), times.detach().require_grad()),
+            log_durations: Param::initialized(
+                ParamId::new(),
+                log_durations.detach().require_grad(),
+            ),
+        }
+    }
+
+    /// Attributes for splats that don't move and are visible at any time.
+    pub fn stationary(num_splats: usize, device: &B::Device) -> Self {
+        Self::new(
+            Tensor::zeros([num_splats, 3], device),
+            Tensor::zeros([num_splats], device),
+            Tensor::full([num_splats], 1e6f32.ln(), device),
+        )
+    }
+
+    /// The earliest and latest time of the splats, in seconds.
+    pub async fn time_range(&self) -> (f32, f32) {
+        let times = self.times.val();
+        if times.dims()[0] == 0 {
+            return (0.0, 0.0);
+        }
+        let data = Tensor::cat(vec![times.clone().min(), times.max()], 0)
+            .into_data_async()
+            .await;
+        let range: Vec<f32> = data.iter::<f32>().collect();
+        (range[0], range[1])
+    }
+
+    fn select(&self, inds: Tensor<B, 1, Int>) -> Self {
+        Self::new(
+            self.velocities.val().select(0, inds.clone()),
+            self.times.val().select(0, inds.clone()),
+            self.log_durations.val().select(0, inds),
+        )
+    }
+}
+
+fn lerp<B: Backend, const D: usize>(a: Tensor<B, D>, b: Tensor<B, D>, t: f32) -> Tensor<B, D> {
+    a.clone() + (b - a).mul_scalar(t)
+}
+
 fn norm_vec<B: Backend>(vec: Tensor<B, 2>) -> Tensor<B, 2> {
     vec.clone() / Tensor::clamp_min(Tensor::sum_dim(vec.powf_scalar(2.0), 1).sqrt(), 1e-12)
 }
 
+// A grid cell size around the typical spacing of points, for the nearest neighbour search. Only
+// the central 90% of the points on every axis count, so outliers don't blow up the cells.
+fn neighbour_cell_size(points: &[Vec3]) -> f32 {
+    let n = points.len();
+    if n == 0 {
+        return 1.0;
+    }
+
+    let extent = Vec3::from_array([0, 1, 2].map(|axis| {
+        let mut coords: Vec<f32> = points.iter().map(|p| p[axis]).collect();
+        let low = *coords.select_nth_unstable_by(n / 20, f32::total_cmp).1;
+        let high = *coords
+            .select_nth_unstable_by(n - 1 - n / 20, f32::total_cmp)
+            .1;
+        high - low
+    }));
+    // Flat point clouds still need cells with some depth.
+    let extent = extent.max(Vec3::splat(extent.max_element() * 0.01));
+
+    let cell_size = (extent.x * extent.y * extent.z / n as f32).cbrt();
+    if cell_size.is_finite() && cell_size > 0.0 {
+        cell_size
+    } else {
+        1.0
+    }
+}
+
 pub fn inverse_sigmoid(x: f32) -> f32 {
     (x / (1.0 - x)).ln()
 }
@@ -85,7 +210,8 @@ impl<B: Backend> Splats<B> {
         let n_splats = means.len();
 
         let means_tensor: Vec<f32> = means.iter().flat_map(|v| [v.x, v.y, v.z]).collect();
-        let means_tensor = Tensor::from_data(TensorData::new(means_tensor, [n_splats, 3]), device);
+        let means_tensor: Tensor<B, 2> =
+            Tensor::from_data(TensorData::new(means_tensor, [n_splats, 3]), device);
 
         let rotations = if let Some(rotations) = rotations {
             let rotations: Vec<f32> = rotations
@@ -105,25 +231,14 @@ impl<B: Backend> Splats<B> {
             let log_scales: Vec<f32> = log_scales.iter().flat_map(|v| [v.x, v.y, v.z]).collect();
             Tensor::from_data(TensorData::new(log_scales, [n_splats, 3]), device)
         } else {
-            let tree_pos: Vec<[f64; 3]> = means
-                .iter()
-                .map(|v| [v.x as f64, v.y as f64, v.z as f64])
-                .collect();
-
-            let empty = vec![(); tree_pos.len()];
-            let tree = BallTree::new(tree_pos.clone(), empty);
-
-            let extents: Vec<_> = tree_pos
-                .iter()
-                .map(|p| {
-                    // Get average of 5 nearest distances.
-                    (tree.query().nn(p).skip(1).take(5).map(|x| x.1).sum::<f64>() / 5.0)
-                        .max(1e-12)
-                        .ln() as f32
-                })
-                .collect();
-
-            Tensor::<B, 1>::from_floats(extents.as_slice(), device)
+            // As in the reference implementation, start out as spheres with the RMS distance to
+            // the 3 nearest splats.
+            let mean_sq_dist: Tensor<B, 1> =
+                Tensor::from_primitive(TensorPrimitive::Float(B::knn_mean_sq_dist(
+                    means_tensor.clone().into_primitive().tensor(),
+                    neighbour_cell_size(means),
+                )));
+            (mean_sq_dist.clamp_min(1e-7).log() * 0.5)
                 .reshape([n_splats, 1])
                 .repeat_dim(1, 3)
         };
@@ -163,7 +278,9 @@ impl<B: Backend> Splats<B> {
         )
     }
 
-    /// Set the SH degree of this splat to be equal to `sh_degree`
+    /// Set the SH degree of this splat to be equal to `sh_degree`. Lowering the degree drops the
+    /// higher order coefficients, raising it pads them with zeros, so the base colors are
+    /// unchanged either way.
     pub fn with_sh_degree(mut self, sh_degree: u32) -> Self {
         let n_coeffs = sh_coeffs_for_degree(sh_degree) as usize;
 
@@ -207,6 +324,12 @@ impl<B: Backend> Splats<B> {
             rotation: Param::initialized(ParamId::new(), rotation.detach().require_grad()),
             raw_opacity: Param::initialized(ParamId::new(), raw_opacity.detach().require_grad()),
             log_scales: Param::initialized(ParamId::new(), log_scales.detach().require_grad()),
+            labels: None,
+            temporal: None,
+            crop_box: Ignored(None),
+            solid: Ignored(None),
+            render_options: Ignored(RenderOptions::default()),
+            time: Ignored(None),
             xys_dummy: Tensor::zeros([num_points, 2], &device).require_grad(),
         }
     }
@@ -220,26 +343,367 @@ impl<B: Backend> Splats<B> {
         *param = Param::initialized(id, f(tensor).detach().require_grad());
     }
 
+    /// A copy of the splats on `device`, with all their attributes. The parameters of the copy
+    /// are new, so renders of the copy have their own gradients.
+    pub fn to_device(&self, device: &B::Device) -> Self {
+        fn moved<B: Backend, const D: usize>(
+            param: &Param<Tensor<B, D>>,
+            device: &B::Device,
+        ) -> Param<Tensor<B, D>> {
+            Param::initialized(
+                ParamId::new(),
+                param.val().detach().to_device(device).require_grad(),
+            )
+        }
+
+        let mut copy = self.clone();
+        copy.means = moved(&self.means, device);
+        copy.rotation = moved(&self.rotation, device);
+        copy.log_scales = moved(&self.log_scales, device);
+        copy.sh_coeffs = moved(&self.sh_coeffs, device);
+        copy.raw_opacity = moved(&self.raw_opacity, device);
+        copy.labels = self.labels.clone().map(|l| l.to_device(device));
+        copy.temporal = self.temporal.as_ref().map(|t| TemporalAttributes {
+            velocities: moved(&t.velocities, device),
+            times: moved(&t.times, device),
+            log_durations: moved(&t.log_durations, device),
+        });
+        copy.xys_dummy = Tensor::zeros([self.num_splats(), 2], device).require_grad();
+        copy
+    }
+
+    /// Store the scales and SH coefficients at half precision, which roughly halves the memory
+    /// of the splats. The means lose a lot of detail far away from the origin, so they're only
+    /// stored at half precision if `means` is set.
+    ///
+    /// Rendering still computes everything at full precision, and gradients flow back to the
+    /// half precision parameters.
+    pub fn with_half_precision(mut self, means: bool) -> Self {
+        if means {
+            Self::map_param(&mut self.means, |t| t.cast(FloatDType::F16));
+        }
+        Self::map_param(&mut self.log_scales, |t| t.cast(FloatDType::F16));
+        Self::map_param(&mut self.sh_coeffs, |t| t.cast(FloatDType::F16));
+        self
+    }
+
+    /// Store all parameters at full precision again, see [`Self::with_half_precision`].
+    pub fn with_full_precision(mut self) -> Self {
+        if !self.is_half_precision() {
+            return self;
+        }
+        Self::map_param(&mut self.means, |t| t.cast(FloatDType::F32));
+        Self::map_param(&mut self.log_scales, |t| t.cast(FloatDType::F32));
+        Self::map_param(&mut self.sh_coeffs, |t| t.cast(FloatDType::F32));
+        self
+    }
+
+    /// Whether some parameters are stored at half precision, see [`Self::with_half_precision`].
+    pub fn is_half_precision(&self) -> bool {
+        self.sh_coeffs.val().dtype() == DType::F16
+    }
+
+    /// Render the splats. Splats with temporal attributes are rendered at the time set with
+    /// [`Self::with_time`], or as stored without a time.
     pub fn render(
         &self,
         camera: &Camera,
         img_size: glam::UVec2,
         render_u32_buffer: bool,
     ) -> (Tensor<B, 3>, RenderAux<B>) {
-        let (img, aux) = B::render_splats(
+        self.render_with(
+            camera,
+            img_size,
+            render_u32_buffer,
+            false,
+            false,
+            Background::default(),
+        )
+    }
+
+    /// Render the splats over a background, see [`Background`].
+    pub fn render_with_background(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        render_u32_buffer: bool,
+        background: Background<Tensor<B, 3>>,
+    ) -> (Tensor<B, 3>, RenderAux<B>) {
+        self.render_with(
+            camera,
+            img_size,
+            render_u32_buffer,
+            false,
+            false,
+            background,
+        )
+    }
+
+    /// Render the splats from several cameras, into an `[N, H, W, C]` image. Returns the aux of
+    /// every view.
+    ///
+    /// This is the same as calling [`Self::render_with_background`] for every camera and
+    /// stacking the images. Nothing is shared between the views, each is projected, binned and
+    /// rasterized on its own, so a batch costs as much as rendering the views one by one.
+    pub fn render_batch(
+        &self,
+        cameras: &[Camera],
+        img_size: glam::UVec2,
+        render_u32_buffer: bool,
+        background: Background<Tensor<B, 3>>,
+    ) -> (Tensor<B, 4>, Vec<RenderAux<B>>) {
+        assert!(!cameras.is_empty(), "Need at least one camera to render");
+
+        let (imgs, auxes) = cameras
+            .iter()
+            .map(|camera| {
+                self.render_with(
+                    camera,
+                    img_size,
+                    render_u32_buffer,
+                    false,
+                    false,
+                    background.clone(),
+                )
+            })
+            .unzip();
+        (Tensor::stack(imgs, 0), auxes)
+    }
+
+    /// Render the splats with the opacity compensated for the screenspace blur, as in
+    /// Mip-Splatting. See [`Backend::render_splats`].
+    pub fn render_antialiased(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        render_u32_buffer: bool,
+    ) -> (Tensor<B, 3>, RenderAux<B>) {
+        self.render_with(
             camera,
             img_size,
-            self.means.val().into_primitive().tensor(),
+            render_u32_buffer,
+            false,
+            true,
+            Background::default(),
+        )
+    }
+
+    /// Render the splats, along with a depth and normal map in [`RenderAux::depth`] and
+    /// [`RenderAux::normals`].
+    pub fn render_with_depth(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+    ) -> (Tensor<B, 3>, RenderAux<B>) {
+        self.render_with(camera, img_size, false, true, false, Background::default())
+    }
+
+    /// Render the camera space depth of every pixel, `[h, w]`, as the mean depth of the splats
+    /// covering the pixel weighted by their contribution, or 0 where nothing is rendered.
+    ///
+    /// Unlike [`RenderAux::depth`] this is differentiable, as the depth of every splat is
+    /// rendered as its color, so it can be used in a training loss.
+    pub fn render_depth_map(&self, camera: &Camera, img_size: glam::UVec2) -> Tensor<B, 2> {
+        let n = self.num_splats();
+        let device = self.means.device();
+        let means = self.means.val().cast(FloatDType::F32);
+
+        // Only the depth of the means is needed, the z row of the world to camera transform.
+        let world_to_local = camera.world_to_local();
+        let m = world_to_local.matrix3;
+        let z_row = Tensor::<B, 1>::from_floats([m.x_axis.z, m.y_axis.z, m.z_axis.z], &device)
+            .reshape([3, 1]);
+        // Dynamic splats are moved in the kernels, move their depth along with them.
+        let moved = match (&self.temporal, *self.time) {
+            (Some(temporal), Some(time)) => {
+                let dt = temporal.times.val().neg().add_scalar(time).unsqueeze_dim(1);
+                means.clone() + (temporal.velocities.val() * dt).cast(FloatDType::F32)
+            }
+            _ => means.clone(),
+        };
+        let depth = moved.matmul(z_row) + world_to_local.translation.z;
+        let depth_coeffs = ((depth - 0.5) / SH_C0).reshape([n, 1, 1]).repeat_dim(2, 3);
+
+        let (img, _) = B::render_splats(
+            camera,
+            img_size,
+            means.into_primitive().tensor(),
             self.xys_dummy.clone().into_primitive().tensor(),
-            self.log_scales.val().into_primitive().tensor(),
+            self.log_scales
+                .val()
+                .cast(FloatDType::F32)
+                .into_primitive()
+                .tensor(),
             self.rotation.val().into_primitive().tensor(),
-            self.sh_coeffs.val().into_primitive().tensor(),
+            depth_coeffs.into_primitive().tensor(),
             self.raw_opacity.val().into_primitive().tensor(),
-            render_u32_buffer,
+            false,
+            RenderSettings {
+                temporal: self.temporal_render(),
+                crop_box: *self.crop_box,
+                options: *self.render_options,
+                ..Default::default()
+            },
         );
+        let img: Tensor<B, 3> = Tensor::from_primitive(TensorPrimitive::Float(img));
+        let [h, w, _] = img.dims();
+        let depth = img.clone().slice([0..h, 0..w, 0..1]);
+        let alpha = img.slice([0..h, 0..w, 3..4]);
+        (depth / alpha.clamp_min(1e-6)).reshape([h, w])
+    }
+
+    /// Render the camera space normal of every pixel, `[h, w, 3]`, as the mean normal of the
+    /// splats covering the pixel, or 0 where nothing is rendered. The normal of a splat is its
+    /// shortest axis, facing the camera, which is the surface normal of a flattened splat.
+    ///
+    /// Like [`Self::render_depth_map`] this is differentiable, with gradients flowing to the
+    /// rotations of the splats.
+    pub fn render_normal_map(&self, camera: &Camera, img_size: glam::UVec2) -> Tensor<B, 3> {
+        let n = self.num_splats();
+        let device = self.means.device();
+        let means = self.means.val().cast(FloatDType::F32);
+
+        // The columns of the rotation matrices, see `quat_to_mat` in helpers.wgsl.
+        let quats = self.rotations_normed();
+        let q = |i: usize| quats.clone().slice([0..n, i..i + 1]);
+        let (w, x, y, z) = (q(0), q(1), q(2), q(3));
+        let axes = [
+            [
+                (y.clone() * y.clone() + z.clone() * z.clone()) * -2.0 + 1.0,
+                (x.clone() * y.clone() + w.clone() * z.clone()) * 2.0,
+                (x.clone() * z.clone() - w.clone() * y.clone()) * 2.0,
+            ],
+            [
+                (x.clone() * y.clone() - w.clone() * z.clone()) * 2.0,
+                (x.clone() * x.clone() + z.clone() * z.clone()) * -2.0 + 1.0,
+                (y.clone() * z.clone() + w.clone() * x.clone()) * 2.0,
+            ],
+            [
+                (x.clone() * z.clone() + w.clone() * y.clone()) * 2.0,
+                (y.clone() * z.clone() - w.clone() * x.clone()) * 2.0,
+                (x.clone() * x.clone() + y * y) * -2.0 + 1.0,
+            ],
+        ];
+
+        // Pick the axis of the smallest scale.
+        let log_scales = self.log_scales.val().cast(FloatDType::F32);
+        let shortest = log_scales
+            .clone()
+            .equal(log_scales.clone().min_dim(1).repeat_dim(1, 3))
+            .float();
+        let normals = axes
+            .into_iter()
+            .enumerate()
+            .map(|(i, axis)| {
+                Tensor::cat(axis.to_vec(), 1) * shortest.clone().slice([0..n, i..i + 1])
+            })
+            .reduce(|a, b| a + b)
+            .expect("Three axes");
+
+        // Rotate to camera space, and flip the normals facing away from the camera.
+        let world_to_local = camera.world_to_local();
+        let rotation =
+            Tensor::<B, 2>::from_floats(world_to_local.matrix3.to_cols_array_2d(), &device);
+        let normals = normals.matmul(rotation.clone());
+        let means_c = means.clone().matmul(rotation)
+            + Tensor::<B, 1>::from_floats(world_to_local.translation.to_array(), &device)
+                .unsqueeze_dim(0);
+        let facing = (normals.clone() * means_c)
+            .sum_dim(1)
+            .lower_elem(0.0)
+            .float()
+            * 2.0
+            - 1.0;
+        let normals = normals * facing.detach();
+
+        // Encode the normals as colors in [0, 1], as the rasterizer clamps negative colors.
+        let normal_coeffs = (normals * 0.5 / SH_C0).reshape([n, 1, 3]);
+
+        let (img, _) = B::render_splats(
+            camera,
+            img_size,
+            means.into_primitive().tensor(),
+            self.xys_dummy.clone().into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            self.rotation.val().into_primitive().tensor(),
+            normal_coeffs.into_primitive().tensor(),
+            self.raw_opacity.val().into_primitive().tensor(),
+            false,
+            RenderSettings {
+                temporal: self.temporal_render(),
+                crop_box: *self.crop_box,
+                options: *self.render_options,
+                ..Default::default()
+            },
+        );
+        let img: Tensor<B, 3> = Tensor::from_primitive(TensorPrimitive::Float(img));
+        let [h, w, _] = img.dims();
+        let color = img.clone().slice([0..h, 0..w, 0..3]);
+        let alpha = img.slice([0..h, 0..w, 3..4]);
+        // Colors are blended over a black background, so the colors are scaled by the alpha.
+        (color * 2.0 - alpha.clone()) / alpha.clamp_min(1e-6)
+    }
+
+    /// Render the splats, and blend a number of extra features per splat, `[num_splats,
+    /// channels]`, into an `[h, w, channels]` image, eg. to distill semantic features into the
+    /// splats. See [`Backend::render_features`].
+    pub fn render_features(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        features: Tensor<B, 2>,
+    ) -> (Tensor<B, 3>, Tensor<B, 3>, RenderAux<B>) {
+        assert_eq!(
+            features.dims()[0],
+            self.num_splats(),
+            "Need features for every splat"
+        );
+        let (img, aux) =
+            self.render_primitive(camera, img_size, false, false, false, Background::default());
+        let features = B::render_features(
+            aux.feature_state(),
+            features.cast(FloatDType::F32).into_primitive().tensor(),
+        );
+        (
+            img,
+            Tensor::from_primitive(TensorPrimitive::Float(features)),
+            aux.into_wrapped(),
+        )
+    }
 
-        let img = Tensor::from_primitive(TensorPrimitive::Float(img));
+    /// How much every splat contributes to a render, `[num_splats]`. This is the alpha of the
+    /// splat times the transmittance in front of it, summed over all pixels, so splats that are
+    /// occluded, culled or outside of the view contribute nothing. Not differentiable.
+    pub fn contributions(&self, camera: &Camera, img_size: glam::UVec2) -> Tensor<B, 1> {
+        let (_, aux) =
+            self.render_primitive(camera, img_size, false, false, false, Background::default());
+        let ones = Tensor::<B, 3>::ones(
+            [img_size.y as usize, img_size.x as usize, 1],
+            &self.means.device(),
+        );
+        let contributions =
+            B::render_features_bwd(aux.feature_state(), ones.into_primitive().tensor());
+        Tensor::<B, 2>::from_primitive(TensorPrimitive::Float(contributions))
+            .reshape([self.num_splats()])
+    }
 
+    fn render_with(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        render_u32_buffer: bool,
+        render_depth: bool,
+        antialias: bool,
+        background: Background<Tensor<B, 3>>,
+    ) -> (Tensor<B, 3>, RenderAux<B>) {
+        let (img, aux) = self.render_primitive(
+            camera,
+            img_size,
+            render_u32_buffer,
+            render_depth,
+            antialias,
+            background,
+        );
         let wrapped_aux = aux.into_wrapped();
         if cfg!(feature = "debug_validation") {
             wrapped_aux.clone().debug_assert_valid();
@@ -247,6 +711,70 @@ impl<B: Backend> Splats<B> {
         (img, wrapped_aux)
     }
 
+    fn render_primitive(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        render_u32_buffer: bool,
+        render_depth: bool,
+        antialias: bool,
+        background: Background<Tensor<B, 3>>,
+    ) -> (Tensor<B, 3>, RenderAuxPrimitive<B>) {
+        // The kernels only read f32, so half precision parameters are cast first. Casting is a
+        // no-op for parameters that are already f32.
+        let (img, aux) = B::render_splats(
+            camera,
+            img_size,
+            self.means
+                .val()
+                .cast(FloatDType::F32)
+                .into_primitive()
+                .tensor(),
+            self.xys_dummy.clone().into_primitive().tensor(),
+            self.log_scales
+                .val()
+                .cast(FloatDType::F32)
+                .into_primitive()
+                .tensor(),
+            self.rotation.val().into_primitive().tensor(),
+            self.sh_coeffs
+                .val()
+                .cast(FloatDType::F32)
+                .into_primitive()
+                .tensor(),
+            self.raw_opacity.val().into_primitive().tensor(),
+            render_u32_buffer,
+            RenderSettings {
+                temporal: self.temporal_render(),
+                render_depth,
+                antialias,
+                crop_box: *self.crop_box,
+                solid: *self.solid,
+                options: *self.render_options,
+                background: background.map(|t| t.into_primitive().tensor()),
+            },
+        );
+
+        (Tensor::from_primitive(TensorPrimitive::Float(img)), aux)
+    }
+
+    /// The temporal attributes packed for the render kernels, when rendering at a point in time.
+    fn temporal_render(&self) -> Option<TemporalRender<FloatTensor<B>>> {
+        let (temporal, time) = (self.temporal.as_ref()?, (*self.time)?);
+        let attributes = Tensor::cat(
+            vec![
+                temporal.velocities.val(),
+                temporal.times.val().unsqueeze_dim(1),
+                temporal.log_durations.val().unsqueeze_dim(1),
+            ],
+            1,
+        );
+        Some(TemporalRender {
+            attributes: attributes.cast(FloatDType::F32).into_primitive().tensor(),
+            time,
+        })
+    }
+
     pub fn opacity(&self) -> Tensor<B, 1> {
         sigmoid(self.raw_opacity.val())
     }
@@ -277,6 +805,364 @@ impl<B: Backend> Splats<B> {
         ))
     }
 
+    /// Apply a transform to these splats, returning the transformed splats.
+    ///
+    /// Splats can only represent similarity transforms. For transforms with a non-uniform scale
+    /// the average scale is used. View dependent colors are rotated along with the splats.
+    pub fn transformed(&self, transform: Affine3A) -> Self {
+        let device = self.means.val().device();
+        let (scale, rotation, _) = transform.to_scale_rotation_translation();
+        let uniform_scale = (scale.x * scale.y * scale.z).abs().cbrt();
+
+        // Means are row vectors, so multiply by the transposed matrix. The column major
+        // matrix is exactly the row major transposed matrix.
+        let mat = Tensor::<B, 1>::from_floats(
+            glam::Mat3::from(transform.matrix3).to_cols_array(),
+            &device,
+        )
+        .reshape([3, 3]);
+        let translation =
+            Tensor::<B, 1>::from_floats(Vec3::from(transform.translation).to_array(), &device)
+                .reshape([1, 3]);
+        let means = self.means.val().matmul(mat.clone()) + translation;
+
+        // Left multiply every quaternion (w, x, y, z) by the rotation of the transform.
+        let [x, y, z, w] = rotation.to_array();
+        #[rustfmt::skip]
+        let quat_mat = Tensor::<B, 1>::from_floats(
+            [
+                w, x, y, z,
+                -x, w, z, -y,
+                -y, -z, w, x,
+                -z, y, -x, w,
+            ],
+            &device,
+        )
+        .reshape([4, 4]);
+        let rotations = self.rotation.val().matmul(quat_mat);
+
+        let log_scales = self.log_scales.val() + uniform_scale.ln();
+
+        let mut transformed = Self::from_tensor_data(
+            means,
+            rotations,
+            log_scales,
+            self.rotated_sh_coeffs(rotation),
+            self.raw_opacity.val(),
+        );
+        transformed.labels = self.labels.clone();
+        transformed.temporal = self.temporal.as_ref().map(|t| {
+            TemporalAttributes::new(
+                t.velocities.val().matmul(mat),
+                t.times.val(),
+                t.log_durations.val(),
+            )
+        });
+        transformed
+    }
+
+    /// Rotate the view dependent colors of the splats, without moving the splats. Colors seen
+    /// from a direction `d` afterwards are the colors seen from `rotation^-1 * d` before.
+    pub fn with_rotated_sh(mut self, rotation: Quat) -> Self {
+        let sh_coeffs = self.rotated_sh_coeffs(rotation);
+        Self::map_param(&mut self.sh_coeffs, |_| sh_coeffs);
+        self
+    }
+
+    // Rotate the SH coefficients with a matmul on the device, see [`sh_rotation_matrix`].
+    fn rotated_sh_coeffs(&self, rotation: Quat) -> Tensor<B, 3> {
+        let sh_coeffs = self.sh_coeffs.val();
+        let sh_degree = self.sh_degree();
+        if sh_degree == 0 || rotation == Quat::IDENTITY {
+            return sh_coeffs;
+        }
+
+        let [n, n_coeffs, _] = sh_coeffs.dims();
+        let sh_mat = Tensor::<B, 1>::from_floats(
+            sh_rotation_matrix(rotation, sh_degree).as_slice(),
+            &sh_coeffs.device(),
+        )
+        .reshape([n_coeffs, n_coeffs]);
+
+        // Rotate coefficients per color channel, as row vectors.
+        let half_precision = sh_coeffs.dtype() == DType::F16;
+        let rotated = sh_coeffs
+            .cast(FloatDType::F32)
+            .swap_dims(1, 2)
+            .reshape([n * 3, n_coeffs])
+            .matmul(sh_mat)
+            .reshape([n, 3, n_coeffs])
+            .swap_dims(1, 2);
+        if half_precision {
+            rotated.cast(FloatDType::F16)
+        } else {
+            rotated
+        }
+    }
+
+    /// Concatenate multiple splat models into one. Models are padded or truncated
+    /// to the highest SH degree among them.
+    pub fn merge(splats: &[Self]) -> Self {
+        Self::merge_transformed(splats.iter().map(|s| (s, Affine3A::IDENTITY)))
+    }
+
+    /// Like [`Splats::merge`], but first places every model in the merged space with a transform.
+    ///
+    /// Panics if no models are given.
+    pub fn merge_transformed<'a>(models: impl IntoIterator<Item = (&'a Self, Affine3A)>) -> Self {
+        let models: Vec<_> = models.into_iter().collect();
+        assert!(!models.is_empty(), "Need at least one model to merge");
+
+        let sh_degree = models.iter().map(|(s, _)| s.sh_degree()).max().unwrap_or(0);
+
+        let parts: Vec<_> = models
+            .into_iter()
+            .map(|(s, transform)| {
+                let s = if transform == Affine3A::IDENTITY {
+                    s.clone()
+                } else {
+                    s.transformed(transform)
+                };
+                s.with_sh_degree(sh_degree)
+            })
+            .collect();
+
+        let mut merged = Self::from_tensor_data(
+            Tensor::cat(parts.iter().map(|s| s.means.val()).collect(), 0),
+            Tensor::cat(parts.iter().map(|s| s.rotation.val()).collect(), 0),
+            Tensor::cat(parts.iter().map(|s| s.log_scales.val()).collect(), 0),
+            Tensor::cat(parts.iter().map(|s| s.sh_coeffs.val()).collect(), 0),
+            Tensor::cat(parts.iter().map(|s| s.raw_opacity.val()).collect(), 0),
+        );
+
+        // Keep labels if any model has them, unlabeled models get label 0.
+        if parts.iter().any(|s| s.labels.is_some()) {
+            let labels = parts
+                .iter()
+                .map(|s| {
+                    s.labels
+                        .clone()
+                        .unwrap_or_else(|| Tensor::zeros([s.num_splats()], &s.means.val().device()))
+                })
+                .collect();
+            merged.labels = Some(Tensor::cat(labels, 0));
+        }
+
+        // Likewise keep temporal attributes, where the other models are stationary.
+        if parts.iter().any(|s| s.temporal.is_some()) {
+            let temporal: Vec<_> = parts
+                .iter()
+                .map(|s| {
+                    s.temporal.clone().unwrap_or_else(|| {
+                        TemporalAttributes::stationary(s.num_splats(), &s.means.val().device())
+                    })
+                })
+                .collect();
+            merged.temporal = Some(TemporalAttributes::new(
+                Tensor::cat(temporal.iter().map(|t| t.velocities.val()).collect(), 0),
+                Tensor::cat(temporal.iter().map(|t| t.times.val()).collect(), 0),
+                Tensor::cat(temporal.iter().map(|t| t.log_durations.val()).collect(), 0),
+            ));
+        }
+
+        merged
+    }
+
+    /// Blend between two sets of splats at `t` in 0..1, eg. to play back a sequence of frames
+    /// at a higher rate than it was captured at.
+    ///
+    /// When both have the same nr. of splats, the splats are assumed to match by index, and
+    /// every attribute is interpolated. Otherwise, the two are cross-faded. Temporal attributes
+    /// are interpolated when both splats have them, and kept when only one does.
+    pub fn interpolate(a: &Self, b: &Self, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let sh_degree = a.sh_degree().max(b.sh_degree());
+        let (a, b) = (
+            a.clone().with_sh_degree(sh_degree),
+            b.clone().with_sh_degree(sh_degree),
+        );
+
+        if a.num_splats() != b.num_splats() {
+            let fade = |splats: Self, weight: f32| {
+                let mut splats = splats;
+                Self::map_param(&mut splats.raw_opacity, |raw| {
+                    let opac = sigmoid(raw).mul_scalar(weight).clamp(1e-6, 1.0 - 1e-6);
+                    (opac.clone() / opac.neg().add_scalar(1.0)).log()
+                });
+                splats
+            };
+            return Self::merge(&[fade(a, 1.0 - t), fade(b, t)]);
+        }
+
+        // Take the shortest path between rotations, q and -q are the same rotation.
+        let (rot_a, rot_b) = (a.rotations_normed(), b.rotations_normed());
+        let dot = (rot_a.clone() * rot_b.clone()).sum_dim(1);
+        let flip = dot.lower_elem(0.0).float().mul_scalar(-2.0).add_scalar(1.0);
+        let rotation = norm_vec(lerp(rot_a, rot_b * flip, t));
+
+        let mut interpolated = Self::from_tensor_data(
+            lerp(a.means.val(), b.means.val(), t),
+            rotation,
+            lerp(a.log_scales.val(), b.log_scales.val(), t),
+            lerp(a.sh_coeffs.val(), b.sh_coeffs.val(), t),
+            lerp(a.raw_opacity.val(), b.raw_opacity.val(), t),
+        );
+        interpolated.labels = a.labels.clone();
+        interpolated.temporal = match (&a.temporal, &b.temporal) {
+            (Some(ta), Some(tb)) => Some(TemporalAttributes::new(
+                lerp(ta.velocities.val(), tb.velocities.val(), t),
+                lerp(ta.times.val(), tb.times.val(), t),
+                lerp(ta.log_durations.val(), tb.log_durations.val(), t),
+            )),
+            (Some(temporal), None) | (None, Some(temporal)) => Some(temporal.clone()),
+            (None, None) => None,
+        };
+        interpolated
+    }
+
+    /// Set temporal attributes for every splat, see [`TemporalAttributes`].
+    pub fn with_temporal(mut self, temporal: TemporalAttributes<B>) -> Self {
+        assert_eq!(
+            temporal.times.dims()[0],
+            self.num_splats(),
+            "Need exactly one time per splat"
+        );
+        self.temporal = Some(temporal);
+        self
+    }
+
+    /// Set a label for every splat.
+    pub fn with_labels(mut self, labels: Tensor<B, 1, Int>) -> Self {
+        assert_eq!(
+            labels.dims()[0],
+            self.num_splats(),
+            "Need exactly one label per splat"
+        );
+        self.labels = Some(labels);
+        self
+    }
+
+    /// Replace the colors of the splats with a distinct color per label, to visualize
+    /// the labels. Unlabeled splats are left as is.
+    pub fn with_label_colors(&self) -> Self {
+        let Some(labels) = self.labels.clone() else {
+            return self.clone();
+        };
+        let n = self.num_splats();
+        let device = self.means.val().device();
+
+        // Spread the labels over the color space by multiplying with irrational numbers.
+        let hues = Tensor::<B, 1>::from_floats([0.618_034, 0.414_213_57, 0.732_050_8], &device)
+            .reshape([1, 3]);
+        let scaled = labels.float().reshape([n, 1]) * hues;
+        let rgb = (scaled.clone() - scaled.floor()) * 0.8 + 0.1;
+        let coeffs = ((rgb - 0.5) / SH_C0).reshape([n, 1, 3]);
+
+        let mut colored = self.clone();
+        Self::map_param(&mut colored.sh_coeffs, |_| coeffs);
+        colored
+    }
+
+    /// Hide all splats whose label is not one of `visible_labels`. Hidden splats are made fully
+    /// transparent, so they're culled before rasterization.
+    pub fn with_visible_labels(&self, visible_labels: &[u32]) -> Self {
+        let Some(labels) = self.labels.clone() else {
+            return self.clone();
+        };
+        let n = self.num_splats();
+        let device = self.means.val().device();
+
+        let visible = visible_labels
+            .iter()
+            .fold(Tensor::<B, 1>::zeros([n], &device), |acc, &label| {
+                acc + labels.clone().equal_elem(label as i32).float()
+            })
+            .greater_elem(0.0);
+
+        self.with_hidden(visible.bool_not())
+    }
+
+    /// Hide the splats where `hidden` is true, by making them fully transparent.
+    pub fn with_hidden(&self, hidden: Tensor<B, 1, Bool>) -> Self {
+        let mut filtered = self.clone();
+        Self::map_param(&mut filtered.raw_opacity, |opac| {
+            // Below the opacity where splats are culled.
+            opac.mask_fill(hidden, -1e4)
+        });
+        filtered
+    }
+
+    /// Hide all splats outside of the crop volume, like [`Self::with_visible_labels`]. The
+    /// splats are kept, so this is cheap to change every frame.
+    pub fn with_crop(&self, crop: &CropVolume) -> Self {
+        if crop.is_empty() {
+            return self.clone();
+        }
+        self.with_hidden(crop.contains(self.means.val()).bool_not())
+    }
+
+    /// Only render the splats with their mean inside of `crop_box`. Like [`Self::with_crop`]
+    /// this keeps all splats, but the splats outside are skipped while rendering, and removed
+    /// by [`Self::cropped`].
+    pub fn with_crop_box(mut self, crop_box: Option<CropBox>) -> Self {
+        self.crop_box = Ignored(crop_box);
+        self
+    }
+
+    /// Render the splats as solid surfaces, see [`SolidRender`]. Renders with this can't be
+    /// differentiated.
+    pub fn with_solid(mut self, solid: Option<SolidRender>) -> Self {
+        self.solid = Ignored(solid);
+        self
+    }
+
+    /// Rasterize the splats with these options, see [`RenderOptions`]. Like the crop box, the
+    /// options are reset when new splats are created from these, eg. by [`Self::retained`].
+    pub fn with_render_options(mut self, options: RenderOptions) -> Self {
+        self.render_options = Ignored(options);
+        self
+    }
+
+    /// Render splats with temporal attributes at a point in time in seconds, see
+    /// [`TemporalAttributes`]. The splats are moved and faded while they're projected, so
+    /// gradients flow back to the temporal attributes. Splats without temporal attributes look
+    /// the same at any time.
+    pub fn with_time(mut self, time: Option<f32>) -> Self {
+        self.time = Ignored(time);
+        self
+    }
+
+    /// Remove all splats where `keep` is false.
+    pub async fn retained(self, keep: Tensor<B, 1, Bool>) -> Self {
+        let inds = keep.argwhere_async().await.squeeze(1);
+        self.selected(inds)
+    }
+
+    /// The splats at `inds`, in that order.
+    pub fn selected(&self, inds: Tensor<B, 1, Int>) -> Self {
+        let mut selected = Self::from_tensor_data(
+            self.means.val().select(0, inds.clone()),
+            self.rotation.val().select(0, inds.clone()),
+            self.log_scales.val().select(0, inds.clone()),
+            self.sh_coeffs.val().select(0, inds.clone()),
+            self.raw_opacity.val().select(0, inds.clone()),
+        );
+        selected.temporal = self.temporal.as_ref().map(|t| t.select(inds.clone()));
+        selected.labels = self.labels.as_ref().map(|l| l.clone().select(0, inds));
+        selected
+    }
+
+    /// Remove all splats outside of the crop volume, and outside of the crop box if set.
+    pub async fn cropped(self, crop: &CropVolume) -> Self {
+        let mut crop = crop.clone();
+        crop.layers.extend(self.crop_box.map(|b| b.as_layer()));
+        if crop.is_empty() {
+            return self;
+        }
+        let inside = crop.contains(self.means.val());
+        self.retained(inside).await
+    }
+
     pub fn sh_degree(&self) -> u32 {
         let [_, coeffs, _] = self.sh_coeffs.dims();
         sh_degree_from_coeffs(coeffs as u32)
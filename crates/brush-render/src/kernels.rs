@@ -1,14 +1,84 @@
 use super::shaders::{
-    map_gaussian_to_intersects, project_backwards, project_forward, project_visible, rasterize,
-    rasterize_backwards,
+    cull_tiles, map_gaussian_to_intersects, project_backwards, project_forward, project_visible,
+    rasterize, rasterize_backwards, rasterize_features, rasterize_features_backwards,
+    sum_isect_grads,
 };
 use crate::shaders::gather_grads;
 use brush_kernel::kernel_source_gen;
 
-kernel_source_gen!(ProjectSplats {}, project_forward);
-kernel_source_gen!(ProjectVisible {}, project_visible);
+kernel_source_gen!(
+    ProjectSplats {
+        distort_opencv,
+        distort_fisheye,
+        custom_projection,
+        crop_box,
+        antialias,
+        solid,
+        temporal
+    },
+    project_forward
+);
+kernel_source_gen!(
+    ProjectVisible {
+        sh_degree: u32,
+        tile_width: u32,
+        distort_opencv,
+        distort_fisheye,
+        custom_projection,
+        antialias,
+        solid
+    },
+    project_visible
+);
 kernel_source_gen!(MapGaussiansToIntersect {}, map_gaussian_to_intersects);
-kernel_source_gen!(Rasterize { raster_u32 }, rasterize);
-kernel_source_gen!(RasterizeBackwards { hard_float }, rasterize_backwards);
-kernel_source_gen!(GatherGrads {}, gather_grads);
-kernel_source_gen!(ProjectBackwards {}, project_backwards);
+kernel_source_gen!(CullTiles { tile_width: u32 }, cull_tiles);
+kernel_source_gen!(
+    Rasterize {
+        tile_width: u32,
+        raster_u32,
+        render_depth,
+        background_texture,
+        background_environment,
+        occlusion_cull,
+        solid
+    },
+    rasterize
+);
+kernel_source_gen!(
+    RasterizeBackwards {
+        tile_width: u32,
+        hard_float,
+        deterministic,
+        background_texture,
+        background_environment,
+        absgrad
+    },
+    rasterize_backwards
+);
+kernel_source_gen!(
+    RasterizeFeatures {
+        tile_width: u32,
+        feature_channels: u32
+    },
+    rasterize_features
+);
+kernel_source_gen!(
+    RasterizeFeaturesBackwards {
+        tile_width: u32,
+        feature_channels: u32,
+        hard_float
+    },
+    rasterize_features_backwards
+);
+kernel_source_gen!(GatherGrads { temporal }, gather_grads);
+kernel_source_gen!(SumIsectGrads { absgrad }, sum_isect_grads);
+kernel_source_gen!(
+    ProjectBackwards {
+        distort_opencv,
+        distort_fisheye,
+        custom_projection,
+        antialias,
+        temporal
+    },
+    project_backwards
+);
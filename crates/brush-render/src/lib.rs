@@ -2,14 +2,16 @@
 #![allow(clippy::too_many_arguments)]
 #![allow(clippy::single_range_in_vec_init)]
 
+use background::Background;
 use burn::prelude::Tensor;
 use burn::tensor::ops::{FloatTensor, IntTensor};
 use burn::tensor::{ElementConversion, Int, TensorPrimitive};
 use burn_jit::JitBackend;
 use burn_wgpu::graphics::AutoGraphicsApi;
 use burn_wgpu::{RuntimeOptions, WgpuDevice, WgpuRuntime};
-use camera::Camera;
-use shaders::helpers::TILE_WIDTH;
+use camera::{Camera, CameraModel};
+use render::{RenderOptions, RenderSettings, TemporalRender};
+use std::time::Duration;
 use wgpu::{Adapter, Device, Queue};
 
 mod burn_glue;
@@ -21,10 +23,25 @@ mod shaders;
 #[cfg(all(test, not(target_family = "wasm")))]
 mod tests;
 
+pub mod background;
 pub mod bounding_box;
 pub mod camera;
+pub mod camera_path;
+pub mod color_harmonize;
+pub mod color_lut;
+pub mod crop;
 pub mod gaussian_splats;
+pub mod lod;
+pub mod raycast;
 pub mod render;
+pub mod render_cache;
+pub mod selection;
+pub mod sh_rotation;
+pub mod splat_scene;
+pub mod splat_stats;
+pub mod stereo;
+pub mod tile_autotune;
+pub mod tone_map;
 
 #[derive(Debug, Clone)]
 pub struct RenderAuxPrimitive<B: Backend> {
@@ -38,9 +55,26 @@ pub struct RenderAuxPrimitive<B: Backend> {
     pub compact_gid_from_isect: IntTensor<B>,
     pub global_from_compact_gid: IntTensor<B>,
     pub radii: FloatTensor<B>,
+    pub culled_intersections: IntTensor<B>,
+    pub depth: Option<FloatTensor<B>>,
+    pub normals: Option<FloatTensor<B>>,
+    /// The tile width the splats were rasterized with, see [`RenderOptions::tile_width`].
+    pub tile_width: u32,
 }
 
 impl<B: Backend> RenderAuxPrimitive<B> {
+    fn feature_state(&self) -> FeatureRenderState<B> {
+        FeatureRenderState {
+            projected_splats: self.projected_splats.clone(),
+            uniforms_buffer: self.uniforms_buffer.clone(),
+            compact_gid_from_isect: self.compact_gid_from_isect.clone(),
+            global_from_compact_gid: self.global_from_compact_gid.clone(),
+            tile_offsets: self.tile_offsets.clone(),
+            final_index: self.final_index.clone(),
+            tile_width: self.tile_width,
+        }
+    }
+
     fn into_wrapped(self) -> RenderAux<B> {
         RenderAux {
             num_intersections: Tensor::from_primitive(self.num_intersections),
@@ -50,6 +84,14 @@ impl<B: Backend> RenderAuxPrimitive<B> {
             compact_gid_from_isect: Tensor::from_primitive(self.compact_gid_from_isect),
             global_from_compact_gid: Tensor::from_primitive(self.global_from_compact_gid),
             radii: Tensor::from_primitive(TensorPrimitive::Float(self.radii)),
+            culled_intersections: Tensor::from_primitive(self.culled_intersections),
+            depth: self
+                .depth
+                .map(|d| Tensor::from_primitive(TensorPrimitive::Float(d))),
+            normals: self
+                .normals
+                .map(|n| Tensor::from_primitive(TensorPrimitive::Float(n))),
+            tile_width: self.tile_width,
         }
     }
 }
@@ -64,29 +106,156 @@ pub struct RenderAux<B: Backend> {
     pub compact_gid_from_isect: Tensor<B, 1, Int>,
     pub global_from_compact_gid: Tensor<B, 1, Int>,
     pub radii: Tensor<B, 1>,
+    /// Nr. of intersections that weren't rasterized, because the tile was already opaque in
+    /// front of them. Always 0 without [`RenderOptions::occlusion_culling`].
+    pub culled_intersections: Tensor<B, 1, Int>,
+    /// Camera space depth of every pixel, `[h, w]`. This is the mean depth of the splats
+    /// covering the pixel weighted by their contribution, or 0 where nothing is rendered.
+    ///
+    /// Only rendered when asked for, see [`Backend::render_splats`]. Not differentiable.
+    pub depth: Option<Tensor<B, 2>>,
+    /// Camera space normal of every pixel, `[h, w, 3]`. The normal of a splat is its shortest
+    /// axis facing the camera, these are blended like the depth. Zero where nothing is rendered.
+    ///
+    /// Only rendered when asked for, see [`Backend::render_splats`]. Not differentiable.
+    pub normals: Option<Tensor<B, 3>>,
+    /// The tile width the splats were rasterized with, see [`RenderOptions::tile_width`].
+    pub tile_width: u32,
+}
+
+/// Min, mean and max of a value over the tiles of a render.
+#[derive(Debug, Clone, Copy)]
+pub struct TileStats {
+    pub min: u32,
+    pub mean: f32,
+    pub max: u32,
 }
 
 #[derive(Debug, Clone)]
 pub struct RenderStats {
     pub num_visible: u32,
     pub num_intersections: u32,
+    /// Nr. of intersections the buffers of the render had room for, or 0 if unknown.
+    pub intersection_capacity: u32,
+    /// Nr. of intersections per tile. Only read back by [`RenderAux::read_stats_async`].
+    pub tile_intersections: Option<TileStats>,
+    /// Nr. of intersections skipped by occlusion culling, see [`RenderAux::culled_intersections`].
+    /// Only read back by [`RenderAux::read_stats_async`].
+    pub culled_intersections: u32,
+    /// Time spent in the render kernels since the stats were last read, per kernel. These are
+    /// only recorded while sync spans are enabled, see [`sync_span::set_enabled`], and include
+    /// all kernels that ran in between, eg. of training.
+    pub kernel_timings: Vec<(&'static str, Duration)>,
+}
+
+impl RenderStats {
+    /// Whether the render had more intersections than fit in its buffers, so some were dropped.
+    pub fn overflowed(&self) -> bool {
+        self.intersection_capacity > 0 && self.num_intersections >= self.intersection_capacity
+    }
+
+    /// Read back the render statistics from their GPU tensors.
+    ///
+    /// This is a single small async readback, so it doesn't stall the GPU queue and also works on wasm,
+    /// where blocking readbacks are impossible.
+    pub async fn read_async<B: burn::tensor::backend::Backend>(
+        num_visible: Tensor<B, 1, Int>,
+        num_intersections: Tensor<B, 1, Int>,
+    ) -> Self {
+        // Concatenate the values so everything is read back in one go.
+        let data = Tensor::cat(vec![num_visible, num_intersections], 0)
+            .into_data_async()
+            .await;
+        let values: Vec<i32> = data.iter::<i32>().collect();
+
+        Self {
+            num_visible: values[0].max(0) as u32,
+            num_intersections: values[1].max(0) as u32,
+            intersection_capacity: 0,
+            tile_intersections: None,
+            culled_intersections: 0,
+            kernel_timings: vec![],
+        }
+    }
 }
 
+// Max nr. of intersections to allocate buffers for. Renders with more intersections drop the
+// extra ones, see [`RenderStats::overflowed`]. Training fails when its renders hit this.
 const INTERSECTS_UPPER_BOUND: u32 = shaders::map_gaussian_to_intersects::WORKGROUP_SIZE[0] * 65535;
 const GAUSSIANS_UPPER_BOUND: u32 = 256 * 65535;
 
 impl<B: Backend> RenderAux<B> {
+    /// Release the intersection buffers, which are only needed to debug a render. With
+    /// [`RenderOptions::recompute_backward`], they then don't stay in memory until the backward
+    /// pass. [`Self::read_stats_async`] and [`Self::debug_assert_valid`] can't be used after.
+    pub fn without_intersections(mut self) -> Self {
+        let device = self.num_visible.device();
+        self.compact_gid_from_isect = Tensor::zeros([0], &device);
+        self.tile_offsets = Tensor::zeros([0], &device);
+        self
+    }
+
+    /// Nr. of intersections the buffers of this render have room for.
+    pub fn intersection_capacity(&self) -> u32 {
+        self.compact_gid_from_isect.dims()[0] as u32
+    }
+
+    /// Read back the nr. of visible splats and intersections of this render, without blocking.
+    /// This is cheaper than [`Self::read_stats_async`], eg. to read back the counts of every
+    /// frame for [`render::IntersectionBudget`].
+    pub async fn read_counts_async(&self) -> RenderStats {
+        let stats =
+            RenderStats::read_async(self.num_visible.clone(), self.num_intersections.clone()).await;
+        RenderStats {
+            intersection_capacity: self.intersection_capacity(),
+            ..stats
+        }
+    }
+
+    /// Read back the statistics of this render, without blocking.
+    pub async fn read_stats_async(&self) -> RenderStats {
+        let tiles = self.calc_tile_depth();
+        let num_tiles = tiles.shape().num_elements();
+
+        // Concatenate the values so everything is read back in one go.
+        let data = Tensor::cat(
+            vec![
+                self.num_visible.clone(),
+                self.num_intersections.clone(),
+                tiles.clone().min(),
+                tiles.clone().max(),
+                tiles.sum(),
+                self.culled_intersections.clone(),
+            ],
+            0,
+        )
+        .into_data_async()
+        .await;
+        let values: Vec<i32> = data.iter::<i32>().collect();
+
+        RenderStats {
+            num_visible: values[0].max(0) as u32,
+            num_intersections: values[1].max(0) as u32,
+            intersection_capacity: self.intersection_capacity(),
+            tile_intersections: Some(TileStats {
+                min: values[2].max(0) as u32,
+                mean: values[4].max(0) as f32 / num_tiles.max(1) as f32,
+                max: values[3].max(0) as u32,
+            }),
+            culled_intersections: values[5].max(0) as u32,
+            kernel_timings: sync_span::take_timings(),
+        }
+    }
+
     pub fn calc_tile_depth(&self) -> Tensor<B, 2, Int> {
         let bins = self.tile_offsets.clone();
         let n_bins = bins.dims()[0];
         let max = bins.clone().slice([1..n_bins]);
         let min = bins.slice([0..n_bins - 1]);
         let [h, w] = self.final_index.shape().dims();
-        let [ty, tx] = [
-            h.div_ceil(TILE_WIDTH as usize),
-            w.div_ceil(TILE_WIDTH as usize),
-        ];
-        (max - min).reshape([ty, tx])
+        let tile_bounds =
+            render::calc_tile_bounds(glam::uvec2(w as u32, h as u32), self.tile_width);
+        (max - min).reshape([tile_bounds.y as usize, tile_bounds.x as usize])
     }
 
     pub fn debug_assert_valid(self) {
@@ -106,9 +275,10 @@ impl<B: Backend> RenderAux<B> {
         //     "somehow there are more gaussian visible than intersections."
         // );
 
+        let max_intersections = self.compact_gid_from_isect.dims()[0] as i32;
         assert!(
-            num_intersections >= 0 && num_intersections < INTERSECTS_UPPER_BOUND as i32,
-            "Too many intersections, Brush currently can't handle this. {num_intersections} > {INTERSECTS_UPPER_BOUND}"
+            num_intersections >= 0 && num_intersections < max_intersections,
+            "Intersection buffer is full, some splats weren't rendered. {num_intersections} >= {max_intersections}"
         );
 
         assert!(
@@ -197,6 +367,8 @@ pub struct SplatGrads<B: Backend> {
     v_scales: FloatTensor<B>,
     v_coeffs: FloatTensor<B>,
     v_raw_opac: FloatTensor<B>,
+    // Gradients of the temporal attributes, when rendered at a point in time.
+    v_temporal: Option<FloatTensor<B>>,
     v_xy: FloatTensor<B>,
 }
 
@@ -206,6 +378,7 @@ pub struct GaussianBackwardState<B: Backend> {
     quats: FloatTensor<B>,
     log_scales: FloatTensor<B>,
     raw_opac: FloatTensor<B>,
+    temporal: Option<TemporalRender<FloatTensor<B>>>,
 
     out_img: FloatTensor<B>,
 
@@ -217,6 +390,24 @@ pub struct GaussianBackwardState<B: Backend> {
     final_index: IntTensor<B>,
 
     sh_degree: u32,
+    camera_model: CameraModel,
+    custom_projection: bool,
+    antialias: bool,
+    options: RenderOptions,
+    background: Background<FloatTensor<B>>,
+}
+
+/// The buffers of a render that are needed to blend extra features, see
+/// [`Backend::render_features`].
+#[derive(Debug, Clone)]
+pub struct FeatureRenderState<B: Backend> {
+    projected_splats: FloatTensor<B>,
+    uniforms_buffer: IntTensor<B>,
+    compact_gid_from_isect: IntTensor<B>,
+    global_from_compact_gid: IntTensor<B>,
+    tile_offsets: IntTensor<B>,
+    final_index: IntTensor<B>,
+    tile_width: u32,
 }
 
 // Custom operations in Burn work by extending the backend with an extra func.
@@ -229,6 +420,8 @@ pub trait Backend: burn::tensor::backend::Backend {
     /// The [`xy_grad_dummy`] variable is only used to carry screenspace xy gradients.
     /// This function can optionally render a "u32" buffer, which is a packed RGBA (8 bits per channel)
     /// buffer. This is useful when the results need to be displayed immediately.
+    /// The `settings` pick what else is rendered and how, eg. a depth map, a background or a
+    /// point in time, see [`RenderSettings`].
     fn render_splats(
         camera: &Camera,
         img_size: glam::UVec2,
@@ -239,6 +432,7 @@ pub trait Backend: burn::tensor::backend::Backend {
         sh_coeffs: FloatTensor<Self>,
         raw_opacity: FloatTensor<Self>,
         render_u32_buffer: bool,
+        settings: RenderSettings<FloatTensor<Self>>,
     ) -> (FloatTensor<Self>, RenderAuxPrimitive<Self>);
 
     /// Backward pass for `render_splats`.
@@ -251,6 +445,33 @@ pub trait Backend: burn::tensor::backend::Backend {
     ) -> SplatGrads<Self> {
         panic!("Do not call this manually.");
     }
+
+    /// Blend a number of features per splat, `[num_splats, channels]`, into an
+    /// `[h, w, channels]` image, eg. semantic features to distill into the splats.
+    ///
+    /// The features are blended exactly like the colors of the render the `state` is from, but
+    /// without a background. Gradients only flow to the features, not to the geometry, which is
+    /// left to the color render. Each channel count compiles to its own kernels.
+    fn render_features(
+        state: FeatureRenderState<Self>,
+        features: FloatTensor<Self>,
+    ) -> FloatTensor<Self>;
+
+    /// Backward pass for `render_features`.
+    ///
+    /// `render_features` uses this to calculate gradients. With a `v_output` of ones, this is
+    /// how much every splat contributes to the render, see [`Splats::contributions`].
+    #[allow(unused_variables)]
+    fn render_features_bwd(
+        state: FeatureRenderState<Self>,
+        v_output: FloatTensor<Self>,
+    ) -> FloatTensor<Self> {
+        panic!("Do not call this manually.");
+    }
+
+    /// The mean squared distance of every point of `points`, `[n, 3]`, to its 3 nearest other
+    /// points, see [`brush_kernel::knn::knn_mean_sq_dist`]. This isn't differentiable.
+    fn knn_mean_sq_dist(points: FloatTensor<Self>, cell_size: f32) -> FloatTensor<Self>;
 }
 
 pub trait AutodiffBackend:
@@ -267,17 +488,27 @@ fn burn_options() -> RuntimeOptions {
     }
 }
 
-fn set_hard_floats(adapter: &Adapter) {
+fn setup_adapter(adapter: &Adapter) {
     let hard_floats = adapter
         .features()
         .contains(wgpu::Features::SHADER_FLOAT32_ATOMIC);
 
     render::set_hard_floats_available(hard_floats);
     log::info!("Running with native atomic floats: {hard_floats}");
+
+    let half_floats = adapter.features().contains(wgpu::Features::SHADER_F16);
+    render::set_half_floats_available(half_floats);
+    log::info!("Running with f16 support: {half_floats}");
+
+    let onesweep = brush_sort::onesweep_supported(&adapter.get_info());
+    brush_sort::set_onesweep_available(onesweep);
+    log::info!("Sorting with onesweep: {onesweep}");
+
+    tile_autotune::init_adapter(adapter);
 }
 
 pub fn burn_init_device(adapter: Adapter, device: Device, queue: Queue) -> WgpuDevice {
-    set_hard_floats(&adapter);
+    setup_adapter(&adapter);
 
     let setup = burn_wgpu::WgpuSetup {
         instance: wgpu::Instance::new(&wgpu::InstanceDescriptor::default()), // unused... need to fix this in Burn.
@@ -288,10 +519,18 @@ pub fn burn_init_device(adapter: Adapter, device: Device, queue: Queue) -> WgpuD
     burn_wgpu::init_device(setup, burn_options())
 }
 
+/// Set up the discrete GPU with this index, eg. to train on multiple GPUs.
+pub async fn burn_init_discrete_gpu(index: usize) -> WgpuDevice {
+    let device = WgpuDevice::DiscreteGpu(index);
+    let setup = burn_wgpu::init_setup_async::<AutoGraphicsApi>(&device, burn_options()).await;
+    setup_adapter(&setup.adapter);
+    device
+}
+
 pub async fn burn_init_setup() -> WgpuDevice {
     let setup =
         burn_wgpu::init_setup_async::<AutoGraphicsApi>(&WgpuDevice::DefaultDevice, burn_options())
             .await;
-    set_hard_floats(&setup.adapter);
+    setup_adapter(&setup.adapter);
     WgpuDevice::DefaultDevice
 }
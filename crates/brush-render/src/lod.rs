@@ -0,0 +1,476 @@
+//! Level of detail, to render scenes with more splats than can be drawn every frame.
+//!
+//! The splats are clustered in an octree, and every node of the tree gets a proxy splat that
+//! approximates all splats in the node. When rendering, nodes that are small enough on screen
+//! are drawn as their proxy, instead of descending into their children.
+
+use std::ops::Range;
+
+use anyhow::anyhow;
+use burn::{
+    config::Config,
+    tensor::{Int, Tensor},
+};
+use glam::{Mat3, Quat, UVec2, Vec3};
+
+use crate::{
+    camera::Camera,
+    gaussian_splats::{inverse_sigmoid, Splats},
+    Backend,
+};
+
+// Stops splitting nodes when many splats are at the same position.
+const MAX_DEPTH: u32 = 20;
+
+#[derive(Config, Debug)]
+pub struct LodConfig {
+    /// Max nr. of splats in a leaf of the tree.
+    #[config(default = 64)]
+    pub leaf_size: usize,
+}
+
+#[derive(Debug, Clone)]
+struct LodNode {
+    center: Vec3,
+    /// Radius around the center that contains all splats of this node, up to 3 sigma.
+    radius: f32,
+    /// The splats in this node, as a range of the reordered splats.
+    splats: Range<u32>,
+    children: Vec<u32>,
+}
+
+/// Splats with a level of detail tree, see the [module docs](self).
+///
+/// Labels and temporal attributes aren't kept.
+#[derive(Debug, Clone)]
+pub struct SplatLod<B: Backend> {
+    /// The original splats, reordered so the splats of every node are a contiguous range,
+    /// followed by the proxy splat of every node.
+    splats: Splats<B>,
+    nodes: Vec<LodNode>,
+}
+
+// Weighted sums to merge splats, by matching the mean and covariance of their gaussians.
+// Splats are weighted by opacity * area, which is also used to give the proxy the same
+// coverage.
+#[derive(Clone)]
+struct Moments {
+    weight: f32,
+    mean: Vec3,
+    second: Mat3,
+    coeffs: Vec<f32>,
+}
+
+impl Moments {
+    fn new(n_coeffs: usize) -> Self {
+        Self {
+            weight: 0.0,
+            mean: Vec3::ZERO,
+            second: Mat3::ZERO,
+            coeffs: vec![0.0; n_coeffs],
+        }
+    }
+
+    fn add_splat(&mut self, mean: Vec3, scale: Vec3, rotation: Quat, opacity: f32, coeffs: &[f32]) {
+        let area = (scale.x * scale.y * scale.z).powf(2.0 / 3.0);
+        let weight = opacity * area;
+        let rot = Mat3::from_quat(rotation);
+        let cov = rot * Mat3::from_diagonal(scale * scale) * rot.transpose();
+
+        self.weight += weight;
+        self.mean += mean * weight;
+        self.second += (cov + outer(mean)) * weight;
+        for (sum, c) in self.coeffs.iter_mut().zip(coeffs) {
+            *sum += c * weight;
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.weight += other.weight;
+        self.mean += other.mean;
+        self.second += other.second;
+        for (sum, c) in self.coeffs.iter_mut().zip(&other.coeffs) {
+            *sum += c;
+        }
+    }
+
+    fn center(&self) -> Vec3 {
+        self.mean / self.weight.max(1e-12)
+    }
+
+    // The splat with the same moments, as (mean, rotation, log scale, raw opacity).
+    fn proxy(&self) -> (Vec3, Quat, Vec3, f32) {
+        let weight = self.weight.max(1e-12);
+        let mean = self.center();
+        let cov = self.second * (1.0 / weight) - outer(mean);
+
+        let (mut axes, variance) = symmetric_eigen(cov);
+        if axes.determinant() < 0.0 {
+            axes.z_axis = -axes.z_axis;
+        }
+        let scale = variance.max(Vec3::splat(1e-12)).powf(0.5);
+
+        let area = (scale.x * scale.y * scale.z).powf(2.0 / 3.0);
+        let opacity = (self.weight / area.max(1e-12)).clamp(1e-4, 0.99);
+        (
+            mean,
+            Quat::from_mat3(&axes).normalize(),
+            Vec3::from_array(scale.to_array().map(f32::ln)),
+            inverse_sigmoid(opacity),
+        )
+    }
+}
+
+fn outer(v: Vec3) -> Mat3 {
+    Mat3::from_cols(v * v.x, v * v.y, v * v.z)
+}
+
+// Eigen decomposition of a symmetric matrix with Jacobi rotations. Returns the eigenvectors
+// as columns, and the eigenvalues.
+fn symmetric_eigen(mut a: Mat3) -> (Mat3, Vec3) {
+    let mut vectors = Mat3::IDENTITY;
+    for _ in 0..16 {
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            let apq = a.col(q)[p];
+            if apq.abs() < 1e-20 {
+                continue;
+            }
+            let theta = (a.col(q)[q] - a.col(p)[p]) / (2.0 * apq);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            let mut rot = Mat3::IDENTITY;
+            rot.col_mut(p)[p] = c;
+            rot.col_mut(q)[q] = c;
+            rot.col_mut(q)[p] = s;
+            rot.col_mut(p)[q] = -s;
+            a = rot.transpose() * a * rot;
+            vectors *= rot;
+        }
+    }
+    (vectors, Vec3::new(a.x_axis.x, a.y_axis.y, a.z_axis.z))
+}
+
+struct TreeBuilder<'a> {
+    means: &'a [Vec3],
+    scales: &'a [f32],
+    rotations: &'a [f32],
+    opacity: &'a [f32],
+    coeffs: &'a [f32],
+    leaf_size: usize,
+
+    order: Vec<u32>,
+    nodes: Vec<LodNode>,
+    moments: Vec<Moments>,
+}
+
+impl TreeBuilder<'_> {
+    fn scale(&self, i: usize) -> Vec3 {
+        Vec3::from_slice(&self.scales[i * 3..])
+    }
+
+    fn rotation(&self, i: usize) -> Quat {
+        // Rotations are stored as wxyz.
+        let r = &self.rotations[i * 4..];
+        Quat::from_xyzw(r[1], r[2], r[3], r[0])
+    }
+
+    fn build(&mut self, range: Range<usize>, depth: u32) -> u32 {
+        let node_id = self.nodes.len();
+        let n_coeffs = self.coeffs.len() / self.means.len();
+        self.nodes.push(LodNode {
+            center: Vec3::ZERO,
+            radius: 0.0,
+            splats: range.start as u32..range.end as u32,
+            children: vec![],
+        });
+        self.moments.push(Moments::new(n_coeffs));
+
+        let (min, max) =
+            self.order[range.clone()]
+                .iter()
+                .fold((Vec3::MAX, Vec3::MIN), |(min, max), &i| {
+                    let mean = self.means[i as usize];
+                    (min.min(mean), max.max(mean))
+                });
+        let split = (min + max) / 2.0;
+        let octant = |mean: Vec3| {
+            (mean.x > split.x) as u8
+                | ((mean.y > split.y) as u8) << 1
+                | ((mean.z > split.z) as u8) << 2
+        };
+
+        let means = self.means;
+        self.order[range.clone()].sort_unstable_by_key(|&i| octant(means[i as usize]));
+        let is_leaf = range.len() <= self.leaf_size
+            || depth >= MAX_DEPTH
+            || octant(means[self.order[range.start] as usize])
+                == octant(means[self.order[range.end - 1] as usize]);
+
+        if is_leaf {
+            let mut moments = Moments::new(n_coeffs);
+            for &i in &self.order[range.clone()] {
+                let i = i as usize;
+                moments.add_splat(
+                    means[i],
+                    self.scale(i),
+                    self.rotation(i),
+                    self.opacity[i],
+                    &self.coeffs[i * n_coeffs..(i + 1) * n_coeffs],
+                );
+            }
+            let center = moments.center();
+            let radius = self.order[range]
+                .iter()
+                .map(|&i| {
+                    let i = i as usize;
+                    means[i].distance(center) + 3.0 * self.scale(i).max_element()
+                })
+                .fold(0.0, f32::max);
+            self.nodes[node_id].center = center;
+            self.nodes[node_id].radius = radius;
+            self.moments[node_id] = moments;
+            return node_id as u32;
+        }
+
+        // Split into the runs of splats in the same octant.
+        let mut children = vec![];
+        let mut start = range.start;
+        while start < range.end {
+            let cur = octant(means[self.order[start] as usize]);
+            let end = (start..range.end)
+                .find(|&i| octant(means[self.order[i] as usize]) != cur)
+                .unwrap_or(range.end);
+            children.push(self.build(start..end, depth + 1));
+            start = end;
+        }
+
+        let mut moments = Moments::new(n_coeffs);
+        for &child in &children {
+            moments.add(&self.moments[child as usize]);
+        }
+        let center = moments.center();
+        let radius = children
+            .iter()
+            .map(|&c| {
+                let child = &self.nodes[c as usize];
+                child.center.distance(center) + child.radius
+            })
+            .fold(0.0, f32::max);
+
+        let node = &mut self.nodes[node_id];
+        node.center = center;
+        node.radius = radius;
+        node.children = children;
+        self.moments[node_id] = moments;
+        node_id as u32
+    }
+}
+
+// Diameter in pixels of a sphere, or None when it's outside of the view.
+fn screen_size(camera: &Camera, img_size: UVec2, center: Vec3, radius: f32) -> Option<f32> {
+    let local = camera.world_to_local().transform_point3(center);
+
+    if let Some(projection) = camera.projection_matrix() {
+        let a = projection.project_point3(local);
+        let b = projection.project_point3(local + Vec3::new(radius, 0.0, 0.0));
+        return Some((b - a).truncate().length() * img_size.x as f32);
+    }
+
+    // Test against the planes through the edges of the image. Lens distortion is ignored.
+    let focal = camera.focal(img_size);
+    let min = -camera.center(img_size) / focal;
+    let max = (img_size.as_vec2() - camera.center(img_size)) / focal;
+    for (coord, min, max) in [(local.x, min.x, max.x), (local.y, min.y, max.y)] {
+        if coord - max * local.z > radius * (1.0 + max * max).sqrt()
+            || min * local.z - coord > radius * (1.0 + min * min).sqrt()
+        {
+            return None;
+        }
+    }
+    if local.z < -radius {
+        return None;
+    }
+
+    let dist = local.length();
+    if dist <= radius {
+        return Some(f32::INFINITY);
+    }
+    Some(2.0 * radius * focal.max_element() / (dist - radius))
+}
+
+impl<B: Backend> SplatLod<B> {
+    /// Build the tree. This reads back the splats, and runs on the CPU.
+    pub async fn new(splats: &Splats<B>, config: &LodConfig) -> anyhow::Result<Self> {
+        let read_err = |e| anyhow!("Failed to read splats {e:?}");
+        let num_splats = splats.num_splats();
+        if num_splats == 0 {
+            anyhow::bail!("Can't build a level of detail tree without any splats");
+        }
+
+        let means: Vec<f32> = splats
+            .means
+            .val()
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let scales: Vec<f32> = splats
+            .scales()
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let rotations: Vec<f32> = splats
+            .rotations_normed()
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let opacity: Vec<f32> = splats
+            .opacity()
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let coeffs: Vec<f32> = splats
+            .sh_coeffs
+            .val()
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let means: Vec<Vec3> = means.chunks_exact(3).map(Vec3::from_slice).collect();
+
+        let mut builder = TreeBuilder {
+            means: &means,
+            scales: &scales,
+            rotations: &rotations,
+            opacity: &opacity,
+            coeffs: &coeffs,
+            leaf_size: config.leaf_size.max(1),
+            order: (0..num_splats as u32).collect(),
+            nodes: vec![],
+            moments: vec![],
+        };
+        builder.build(0..num_splats, 0);
+
+        let device = splats.means.val().device();
+        let order: Vec<i32> = builder.order.iter().map(|&i| i as i32).collect();
+        let order = Tensor::<B, 1, Int>::from_ints(order.as_slice(), &device);
+        let reordered = Splats::from_tensor_data(
+            splats.means.val().select(0, order.clone()),
+            splats.rotation.val().select(0, order.clone()),
+            splats.log_scales.val().select(0, order.clone()),
+            splats.sh_coeffs.val().select(0, order.clone()),
+            splats.raw_opacity.val().select(0, order),
+        );
+
+        let proxies: Vec<_> = builder.moments.iter().map(Moments::proxy).collect();
+        let weights = builder.moments.iter().map(|m| 1.0 / m.weight.max(1e-12));
+        let proxy_coeffs: Vec<f32> = builder
+            .moments
+            .iter()
+            .zip(weights)
+            .flat_map(|(m, w)| m.coeffs.iter().map(move |c| c * w))
+            .collect();
+        let proxies = Splats::from_raw(
+            &proxies.iter().map(|p| p.0).collect::<Vec<_>>(),
+            Some(&proxies.iter().map(|p| p.1).collect::<Vec<_>>()),
+            Some(&proxies.iter().map(|p| p.2).collect::<Vec<_>>()),
+            Some(&proxy_coeffs),
+            Some(&proxies.iter().map(|p| p.3).collect::<Vec<_>>()),
+            &device,
+        );
+
+        Ok(Self {
+            splats: Splats::merge(&[reordered, proxies]),
+            nodes: builder.nodes,
+        })
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Select which splats to render for a view, as indices in [`Self::splats`]. Nodes are
+    /// drawn as their proxy once they're smaller than `pixel_error` pixels on screen.
+    pub fn select(&self, camera: &Camera, img_size: UVec2, pixel_error: f32) -> Vec<u32> {
+        let proxy_offset = self.splats.num_splats() - self.nodes.len();
+
+        let mut selected = vec![];
+        let mut stack = vec![0];
+        while let Some(node_id) = stack.pop() {
+            let node = &self.nodes[node_id as usize];
+            let Some(size) = screen_size(camera, img_size, node.center, node.radius) else {
+                continue;
+            };
+
+            if size <= pixel_error {
+                selected.push(proxy_offset as u32 + node_id);
+            } else if node.children.is_empty() {
+                selected.extend(node.splats.clone());
+            } else {
+                stack.extend(&node.children);
+            }
+        }
+
+        // Always render something, the renderer culls the root if it's out of view.
+        if selected.is_empty() {
+            selected.push(proxy_offset as u32);
+        }
+        selected
+    }
+
+    /// All splats of the tree, followed by the proxy splat of every node.
+    pub fn splats(&self) -> &Splats<B> {
+        &self.splats
+    }
+
+    /// The splats to render for a view, see [`Self::select`].
+    pub fn splats_for_view(&self, camera: &Camera, img_size: UVec2, pixel_error: f32) -> Splats<B> {
+        let device = self.splats.means.val().device();
+        let inds: Vec<i32> = self
+            .select(camera, img_size, pixel_error)
+            .into_iter()
+            .map(|i| i as i32)
+            .collect();
+        let inds = Tensor::<B, 1, Int>::from_ints(inds.as_slice(), &device);
+        let splats = &self.splats;
+        Splats::from_tensor_data(
+            splats.means.val().select(0, inds.clone()),
+            splats.rotation.val().select(0, inds.clone()),
+            splats.log_scales.val().select(0, inds.clone()),
+            splats.sh_coeffs.val().select(0, inds.clone()),
+            splats.raw_opacity.val().select(0, inds),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_splats_match_moments() {
+        let mut moments = Moments::new(3);
+        for x in [-1.0, 1.0] {
+            moments.add_splat(
+                Vec3::new(x, 0.0, 0.0),
+                Vec3::splat(0.5),
+                Quat::IDENTITY,
+                0.5,
+                &[1.0, 2.0, 3.0],
+            );
+        }
+        let (mean, rotation, log_scale, _) = moments.proxy();
+        assert!(mean.abs_diff_eq(Vec3::ZERO, 1e-5));
+
+        // The variance along x is the variance of the splats plus the spread of the means.
+        let scale = (rotation * log_scale.exp()).abs();
+        assert!((scale.x - (1.0f32 + 0.25).sqrt()).abs() < 1e-4);
+        assert!((scale.y - 0.5).abs() < 1e-4);
+        assert!((scale.z - 0.5).abs() < 1e-4);
+    }
+}
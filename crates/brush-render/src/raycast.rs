@@ -0,0 +1,162 @@
+use anyhow::anyhow;
+use burn::tensor::{backend::Backend, Tensor};
+use glam::{UVec2, Vec2, Vec3};
+
+use crate::{camera::Camera, crop::CropVolume, gaussian_splats::Splats};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Normalized direction of the ray.
+    pub dir: Vec3,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    pub position: Vec3,
+    /// Distance along the ray to the hit.
+    pub distance: f32,
+    /// Index of the splat that was hit.
+    pub splat_id: u32,
+    /// Alpha accumulated along the ray, up to and including the hit splat.
+    pub alpha: f32,
+}
+
+/// What's under a pixel of a view, see [`pick`].
+#[derive(Debug, Clone, Copy)]
+pub struct PickResult {
+    /// Index of the splat under the pixel.
+    pub splat_id: u32,
+    /// Where the pixel's ray hits the splats.
+    pub world_pos: Vec3,
+    /// Camera space depth of the hit.
+    pub depth: f32,
+}
+
+type Vec3T<B> = [Tensor<B, 1>; 3];
+
+fn dot<B: Backend>(a: &Vec3T<B>, b: &Vec3T<B>) -> Tensor<B, 1> {
+    a[0].clone() * b[0].clone() + a[1].clone() * b[1].clone() + a[2].clone() * b[2].clone()
+}
+
+fn cross<B: Backend>(a: &Vec3T<B>, b: &Vec3T<B>) -> Vec3T<B> {
+    [
+        a[1].clone() * b[2].clone() - a[2].clone() * b[1].clone(),
+        a[2].clone() * b[0].clone() - a[0].clone() * b[2].clone(),
+        a[0].clone() * b[1].clone() - a[1].clone() * b[0].clone(),
+    ]
+}
+
+fn columns<B: Backend>(tensor: Tensor<B, 2>) -> Vec3T<B> {
+    let n = tensor.dims()[0];
+    [0, 1, 2].map(|c| tensor.clone().slice([0..n, c..c + 1]).reshape([n]))
+}
+
+// Rotate vectors by the inverse of unit quaternions (w, x, y, z).
+fn rotate_inv<B: Backend>(w: &Tensor<B, 1>, xyz: &Vec3T<B>, v: &Vec3T<B>) -> Vec3T<B> {
+    // The inverse of a unit quaternion is its conjugate.
+    let u = xyz.clone().map(|c| -c);
+    let uv = cross(&u, v);
+    let uuv = cross(&u, &uv);
+    [0, 1, 2].map(|i| v[i].clone() + (uv[i].clone() * w.clone() + uuv[i].clone()) * 2.0)
+}
+
+/// Cast a ray against the splats, and return where the ray has accumulated `hit_alpha` opacity.
+///
+/// Each splat is evaluated at its point of maximum density along the ray. The responses are
+/// composited front to back like the rasterizer does, and the splat where the accumulated alpha
+/// crosses `hit_alpha` is the hit. Splats are evaluated on the device of the splats, only
+/// the per splat depth & alpha are read back.
+pub async fn raycast<B: crate::Backend>(
+    splats: &Splats<B>,
+    ray: Ray,
+    hit_alpha: f32,
+) -> anyhow::Result<Option<RaycastHit>> {
+    let n = splats.num_splats();
+    if n == 0 {
+        return Ok(None);
+    }
+    let device = splats.means.val().device();
+    let dir = ray.dir.normalize();
+
+    let origin = Tensor::<B, 1>::from_floats(ray.origin.to_array(), &device).reshape([1, 3]);
+    let rel_origin = columns(origin - splats.means.val());
+    let dirs = columns(
+        Tensor::<B, 1>::from_floats(dir.to_array(), &device)
+            .reshape([1, 3])
+            .repeat_dim(0, n),
+    );
+
+    let quats = splats.rotations_normed();
+    let w = quats.clone().slice([0..n, 0..1]).reshape([n]);
+    let xyz = columns(quats.slice([0..n, 1..4]));
+    let scales = columns(splats.scales());
+
+    // Transform the ray to the space where the splat is a unit gaussian.
+    let o_local = rotate_inv(&w, &xyz, &rel_origin);
+    let d_local = rotate_inv(&w, &xyz, &dirs);
+    let o_local = [0, 1, 2].map(|i| o_local[i].clone() / scales[i].clone());
+    let d_local = [0, 1, 2].map(|i| d_local[i].clone() / scales[i].clone());
+
+    let dd = dot(&d_local, &d_local).clamp_min(1e-12);
+    let od = dot(&o_local, &d_local);
+    let oo = dot(&o_local, &o_local);
+
+    let t = -od.clone() / dd.clone();
+    let dist_sq = (oo - od.powf_scalar(2.0) / dd).clamp_min(0.0);
+    let alpha = (splats.opacity() * (dist_sq * -0.5).exp()).clamp_max(0.999);
+
+    // Ignore splats behind the ray, and splats too faint to matter, like the rasterizer.
+    let valid =
+        t.clone().greater_elem(0.0).float() * alpha.clone().greater_elem(1.0 / 255.0).float();
+    // Splats outside of the crop box aren't rendered, so can't be hit either.
+    let valid = match *splats.crop_box {
+        Some(crop_box) => {
+            let inside = CropVolume::new(vec![crop_box.as_layer()]).contains(splats.means.val());
+            valid * inside.float()
+        }
+        None => valid,
+    };
+    let alpha = alpha * valid;
+
+    let read_err = |e| anyhow!("Failed to read raycast data {e:?}");
+    let t: Vec<f32> = t.into_data_async().await.to_vec().map_err(read_err)?;
+    let alpha: Vec<f32> = alpha.into_data_async().await.to_vec().map_err(read_err)?;
+
+    let mut candidates: Vec<usize> = (0..n).filter(|&i| alpha[i] > 0.0).collect();
+    candidates.sort_by(|&a, &b| t[a].total_cmp(&t[b]));
+
+    let mut transmittance = 1.0;
+    for i in candidates {
+        transmittance *= 1.0 - alpha[i];
+        let accumulated = 1.0 - transmittance;
+
+        if accumulated >= hit_alpha {
+            return Ok(Some(RaycastHit {
+                position: ray.origin + dir * t[i],
+                distance: t[i],
+                splat_id: i as u32,
+                alpha: accumulated,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find the splat and position under a pixel of a view of `img_size` pixels. This is where the
+/// pixel's ray is half opaque, so faint splats in front of a surface are skipped.
+pub async fn pick<B: crate::Backend>(
+    splats: &Splats<B>,
+    camera: &Camera,
+    img_size: UVec2,
+    pixel: Vec2,
+) -> anyhow::Result<Option<PickResult>> {
+    let ray = camera.pixel_ray(img_size, pixel);
+    let hit = raycast(splats, ray, 0.5).await?;
+    Ok(hit.map(|hit| PickResult {
+        splat_id: hit.splat_id,
+        world_pos: hit.position,
+        depth: camera.world_to_local().transform_point3(hit.position).z,
+    }))
+}
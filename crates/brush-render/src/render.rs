@@ -3,13 +3,16 @@ use super::shaders;
 use std::mem::{offset_of, size_of};
 
 use crate::{
-    camera::Camera,
+    background::Background,
+    camera::{Camera, CameraModel},
+    crop::CropBox,
     dim_check::DimCheck,
     kernels::{
-        GatherGrads, MapGaussiansToIntersect, ProjectBackwards, ProjectSplats, ProjectVisible,
-        Rasterize, RasterizeBackwards,
+        CullTiles, GatherGrads, MapGaussiansToIntersect, ProjectBackwards, ProjectSplats,
+        ProjectVisible, Rasterize, RasterizeBackwards, RasterizeFeatures,
+        RasterizeFeaturesBackwards, SumIsectGrads,
     },
-    RenderAuxPrimitive, SplatGrads, INTERSECTS_UPPER_BOUND,
+    FeatureRenderState, RenderAuxPrimitive, RenderStats, SplatGrads, INTERSECTS_UPPER_BOUND,
 };
 
 use brush_kernel::create_dispatch_buffer;
@@ -20,6 +23,7 @@ use brush_prefix_sum::prefix_sum;
 use brush_sort::radix_argsort;
 use burn::tensor::ops::IntTensorOps;
 use burn::tensor::{ops::IntTensor, DType};
+use burn_jit::kernel::into_contiguous;
 use burn_jit::JitBackend;
 use burn_wgpu::JitTensor;
 use burn_wgpu::WgpuRuntime;
@@ -29,6 +33,34 @@ use glam::{ivec2, uvec2};
 
 type InnerWgpu = JitBackend<WgpuRuntime, f32, i32, u32>;
 
+// Whether to compile the projection kernels with OpenCV or fisheye distortion.
+fn distortion_defines(model: CameraModel) -> (bool, bool) {
+    (
+        matches!(model, CameraModel::OpenCv(_)),
+        matches!(model, CameraModel::Fisheye(_)),
+    )
+}
+
+// Whether to compile the rasterize kernels with a background texture, and whether it's an
+// environment map.
+fn background_defines<T>(background: &Background<T>) -> (bool, bool) {
+    (
+        background.texture().is_some(),
+        matches!(background, Background::Environment(_)),
+    )
+}
+
+fn background_size(background: &Background<JitTensor<WgpuRuntime>>) -> glam::IVec2 {
+    background.texture().map_or(glam::IVec2::ZERO, |texture| {
+        let dims = &texture.shape.dims;
+        assert!(
+            dims[0] > 0 && dims[1] > 0,
+            "Background texture can't be empty"
+        );
+        ivec2(dims[1] as i32, dims[0] as i32)
+    })
+}
+
 pub const SH_C0: f32 = shaders::gather_grads::SH_C0;
 
 pub const fn sh_coeffs_for_degree(degree: u32) -> u32 {
@@ -50,30 +82,113 @@ pub fn rgb_to_sh(rgb: f32) -> f32 {
     (rgb - 0.5) / shaders::gather_grads::SH_C0
 }
 
-pub(crate) fn calc_tile_bounds(img_size: glam::UVec2) -> glam::UVec2 {
+pub(crate) fn calc_tile_bounds(img_size: glam::UVec2, tile_width: u32) -> glam::UVec2 {
     uvec2(
-        img_size.x.div_ceil(shaders::helpers::TILE_WIDTH),
-        img_size.y.div_ceil(shaders::helpers::TILE_WIDTH),
+        img_size.x.div_ceil(tile_width),
+        img_size.y.div_ceil(tile_width),
     )
 }
 
-pub(crate) fn max_intersections(img_size: glam::UVec2, num_splats: u32) -> u32 {
+pub(crate) fn max_intersections(
+    img_size: glam::UVec2,
+    num_splats: u32,
+    options: &RenderOptions,
+) -> u32 {
     // Divide screen into tiles.
-    let tile_bounds = calc_tile_bounds(img_size);
+    let tile_bounds = calc_tile_bounds(img_size, options.tile_width);
     let num_tiles = tile_bounds[0] * tile_bounds[1];
 
-    // On wasm, we cannot do a sync readback at all.
-    // Instead, can just estimate a max number of intersects. All the kernels only handle the actual
-    // number of intersects, and spin up empty threads for the rest atm. In the future, could use indirect
-    // dispatch to avoid this.
-    // Estimating the max number of intersects can be a bad hack though... The worst case sceneario is so massive
-    // that it's easy to run out of memory... How do we actually properly deal with this :/
+    // Buffers have to be allocated before the number of intersections is known, and reading it
+    // back would stall (and is impossible on wasm). Instead, allocate for the worst case, up to
+    // a memory budget. The kernels are dispatched indirectly, so they only run for the actual
+    // intersections. When there are more intersections than fit, the extra ones are dropped.
     let max = num_splats.saturating_mul(num_tiles);
-
-    // clamp to max nr. of dispatches.
+    let max = match options.intersection_density {
+        Some(density) => {
+            let expected = (density as f64 * num_splats as f64 * num_tiles as f64).ceil();
+            max.min((expected as u32).max(MIN_INTERSECTIONS))
+        }
+        None => max,
+    };
     max.min(INTERSECTS_UPPER_BOUND)
 }
 
+const MIN_INTERSECTIONS: u32 = 64 * 1024;
+// Only keep the budgets of the last few resolutions, eg. while resizing the viewer.
+const MAX_BUDGETS: usize = 8;
+
+fn next_intersection_density(current: f32, measured: f32, overflowed: bool) -> f32 {
+    if overflowed {
+        // The measured count is capped by the buffer size, so grow quickly.
+        return current.max(measured) * 2.0;
+    }
+    // Leave room for views that have more intersections, and only shrink slowly, so a few
+    // views with few intersections don't make the next views overflow.
+    let target = measured * 2.0;
+    if target > current {
+        target
+    } else {
+        (current * 0.95).max(target)
+    }
+}
+
+/// Sizes the intersection buffers of renders from the counts of finished renders (see
+/// [`crate::RenderAux::read_counts_async`]) instead of for the worst case, which saves a lot of
+/// memory. This matters most on wasm, where memory is tight. The budget is kept per resolution,
+/// and scales with the nr. of splats. Renders without a budget, eg. for training, still
+/// allocate for the worst case.
+///
+/// When a render has more intersections than estimated, the extra intersections are dropped
+/// for that render, and the budget grows.
+#[derive(Debug, Clone, Default)]
+pub struct IntersectionBudget {
+    // Expected nr. of intersections per splat and tile, for each resolution that has a budget.
+    densities: Vec<(glam::UVec2, f32)>,
+}
+
+impl IntersectionBudget {
+    /// Update the budget of this resolution from the counts of a render with `options`.
+    pub fn update(
+        &mut self,
+        stats: &RenderStats,
+        num_splats: usize,
+        img_size: glam::UVec2,
+        options: &RenderOptions,
+    ) {
+        let tile_bounds = calc_tile_bounds(img_size, options.tile_width);
+        let slots = num_splats as f64 * (tile_bounds.x * tile_bounds.y) as f64;
+        if stats.intersection_capacity == 0 || slots == 0.0 {
+            return;
+        }
+        let measured = (stats.num_intersections as f64 / slots) as f32;
+
+        let index = self
+            .densities
+            .iter()
+            .position(|(size, _)| *size == img_size);
+        let current = index.map(|i| self.densities.remove(i).1).unwrap_or(0.0);
+        let next = next_intersection_density(current, measured, stats.overflowed());
+        self.densities.push((img_size, next));
+        if self.densities.len() > MAX_BUDGETS {
+            self.densities.remove(0);
+        }
+    }
+
+    /// `options` with the budget of this resolution, if there is one, see
+    /// [`RenderOptions::intersection_density`].
+    pub fn apply(&self, options: RenderOptions, img_size: glam::UVec2) -> RenderOptions {
+        let density = self
+            .densities
+            .iter()
+            .find(|(size, _)| *size == img_size)
+            .map(|(_, density)| *density);
+        RenderOptions {
+            intersection_density: density,
+            ..options
+        }
+    }
+}
+
 fn copy_tensor(tensor: IntTensor<InnerWgpu>) -> IntTensor<InnerWgpu> {
     // Just an operation to force a new output.
     InnerWgpu::int_add_scalar(tensor, 0)
@@ -88,7 +203,17 @@ pub(crate) fn render_forward(
     sh_coeffs: JitTensor<WgpuRuntime>,
     raw_opacities: JitTensor<WgpuRuntime>,
     raster_u32: bool,
+    settings: RenderSettings<JitTensor<WgpuRuntime>>,
 ) -> (JitTensor<WgpuRuntime>, RenderAuxPrimitive<InnerWgpu>) {
+    let RenderSettings {
+        temporal,
+        render_depth,
+        antialias,
+        crop_box,
+        solid,
+        options,
+        background,
+    } = settings;
     assert!(
         img_size[0] > 0 && img_size[1] > 0,
         "Can't render 0 sized images"
@@ -110,10 +235,25 @@ pub(crate) fn render_forward(
         .check_dims(&sh_coeffs, &["D".into(), "C".into(), 3.into()])
         .check_dims(&raw_opacities, &["D".into()]);
 
+    // The kernels index the temporal attributes directly.
+    let temporal = temporal.map(|t| t.map(into_contiguous));
+    if let Some(temporal) = &temporal {
+        DimCheck::new()
+            .check_dims(&means, &["D".into(), 3.into()])
+            .check_dims(&temporal.attributes, &["D".into(), 5.into()]);
+    }
+
+    // The kernels index the background texture directly.
+    let background = background.map(into_contiguous);
+    if let Some(texture) = background.texture() {
+        DimCheck::new().check_dims(texture, &["H".into(), "W".into(), 3.into()]);
+    }
+
     // Divide screen into tiles.
+    let tile_width = options.tile_width;
     let tile_bounds = ivec2(
-        img_size.x.div_ceil(shaders::helpers::TILE_WIDTH) as i32,
-        img_size.y.div_ceil(shaders::helpers::TILE_WIDTH) as i32,
+        img_size.x.div_ceil(tile_width) as i32,
+        img_size.y.div_ceil(tile_width) as i32,
     );
 
     // A note on some confusing naming that'll be used throughout this function:
@@ -135,47 +275,87 @@ pub(crate) fn render_forward(
     let uniforms_buffer = create_uniform_buffer(
         shaders::helpers::RenderUniforms {
             viewmat: glam::Mat4::from(camera.world_to_local()).to_cols_array_2d(),
+            projection: camera
+                .projection_matrix()
+                .unwrap_or(glam::Mat4::IDENTITY)
+                .to_cols_array_2d(),
+            crop_box: crop_box
+                .map_or(glam::Mat4::IDENTITY, |b| b.world_to_unit())
+                .to_cols_array_2d(),
             camera_position: [camera.position.x, camera.position.y, camera.position.z, 0.0],
+            distortion: camera.model.params(),
             focal: camera.focal(img_size).into(),
             pixel_center: camera.center(img_size).into(),
+            background: background.color().extend(0.0).into(),
+            background_size: background_size(&background).into(),
             img_size: ivec2(img_size.x as i32, img_size.y as i32).into(),
             tile_bounds: tile_bounds.into(),
             num_visible: 0,
             num_intersections: 0,
             sh_degree,
             total_splats,
+            solid_threshold: solid.map_or(0.0, |s| s.opacity_threshold),
+            solid_cutoff: solid.map_or(0.0, |s| s.cutoff()),
+            time: temporal.as_ref().map_or(0.0, |t| t.time),
         },
         device,
         &client,
     );
 
     let device = &means.device.clone();
+    let (distort_opencv, distort_fisheye) = distortion_defines(camera.model);
+    let custom_projection = camera.projection_matrix().is_some();
 
     let num_points = means.shape.dims[0];
     let client = &means.client.clone();
 
     let radii = InnerWgpu::float_zeros([num_points].into(), device);
 
+    // The means and raw opacities at the render time, written while projecting the splats.
+    let temporal_out = temporal.as_ref().map(|_| {
+        (
+            create_tensor::<2, _>([num_points, 3], device, client, DType::F32),
+            create_tensor::<1, _>([num_points], device, client, DType::F32),
+        )
+    });
+
     let (global_from_compact_gid, num_visible) = {
         let global_from_presort_gid = InnerWgpu::int_zeros([num_points].into(), device);
         let depths = create_tensor([num_points], device, client, DType::F32);
 
+        let mut bindings = vec![
+            uniforms_buffer.clone().handle.binding(),
+            means.clone().handle.binding(),
+            quats.clone().handle.binding(),
+            log_scales.clone().handle.binding(),
+            raw_opacities.clone().handle.binding(),
+            global_from_presort_gid.clone().handle.binding(),
+            depths.clone().handle.binding(),
+            radii.clone().handle.binding(),
+        ];
+        if let (Some(temporal), Some((means_t, raw_opacities_t))) = (&temporal, &temporal_out) {
+            bindings.extend([
+                temporal.attributes.handle.clone().binding(),
+                means_t.handle.clone().binding(),
+                raw_opacities_t.handle.clone().binding(),
+            ]);
+        }
+
         tracing::trace_span!("ProjectSplats", sync_burn = true).in_scope(||
             // SAFETY: wgsl FFI, kernel checked to have no OOB.
             unsafe {
             client.execute_unchecked(
-                ProjectSplats::task(),
+                ProjectSplats::task(
+                    distort_opencv,
+                    distort_fisheye,
+                    custom_projection,
+                    crop_box.is_some(),
+                    antialias,
+                    solid.is_some(),
+                    temporal.is_some(),
+                ),
                 calc_cube_count([num_points as u32], ProjectSplats::WORKGROUP_SIZE),
-                vec![
-                    uniforms_buffer.clone().handle.binding(),
-                    means.clone().handle.binding(),
-                    quats.clone().handle.binding(),
-                    log_scales.clone().handle.binding(),
-                    raw_opacities.clone().handle.binding(),
-                    global_from_presort_gid.clone().handle.binding(),
-                    depths.clone().handle.binding(),
-                    radii.clone().handle.binding(),
-                ],
+                bindings,
             );
         });
 
@@ -186,6 +366,17 @@ pub(crate) fn render_forward(
             &[num_vis_field_offset..num_vis_field_offset + 1],
         ));
 
+        // Splats are compacted in whatever order the threads run. Put them back in their
+        // original order, so splats at the same depth are sorted the same way every time.
+        // Otherwise splats with equal depths can swap places between frames and flicker. The
+        // radix sorts are stable, so this order is kept by the depth sort, and the tile sort
+        // below keeps the depth order within every tile.
+        let (global_from_presort_gid, depths) =
+            tracing::trace_span!("PresortOrder", sync_burn = true).in_scope(|| {
+                let bits = u32::BITS - (num_points as u32).leading_zeros();
+                radix_argsort(global_from_presort_gid, depths, &num_visible, bits)
+            });
+
         let (_, global_from_compact_gid) = tracing::trace_span!("DepthSort", sync_burn = true)
             .in_scope(|| {
                 // Interpret the depth as a u32. This is fine for a radix sort, as long as the depth > 0.0,
@@ -196,13 +387,16 @@ pub(crate) fn render_forward(
         (global_from_compact_gid, num_visible)
     };
 
+    // The other kernels only see the splats as they are at the render time.
+    let (means, raw_opacities) = temporal_out.unwrap_or((means, raw_opacities));
+
     let projected_size = size_of::<shaders::helpers::ProjectedSplat>() / size_of::<f32>();
     let projected_splats =
         create_tensor::<2, _>([num_points, projected_size], device, client, DType::F32);
 
     let num_vis_wg = create_dispatch_buffer(num_visible.clone(), [shaders::helpers::MAIN_WG, 1, 1]);
 
-    let max_intersects = max_intersections(img_size, num_points as u32);
+    let max_intersects = max_intersections(img_size, num_points as u32, &options);
     // 1 extra length to make this an exclusive sum.
     let tiles_hit_per_splat = InnerWgpu::int_zeros([num_points + 1].into(), device);
     let isect_info =
@@ -212,13 +406,21 @@ pub(crate) fn render_forward(
         // SAFETY: Kernel has to contain no OOB indexing.
         unsafe {
         client.execute_unchecked(
-            ProjectVisible::task(),
+            ProjectVisible::task(
+                sh_degree,
+                tile_width,
+                distort_opencv,
+                distort_fisheye,
+                custom_projection,
+                antialias,
+                solid.is_some(),
+            ),
             CubeCount::Dynamic(num_vis_wg.clone().handle.binding()),
             vec![
                 uniforms_buffer.clone().handle.binding(),
-                means.handle.binding(),
-                log_scales.handle.binding(),
-                quats.handle.binding(),
+                means.handle.clone().binding(),
+                log_scales.handle.clone().binding(),
+                quats.handle.clone().binding(),
                 sh_coeffs.handle.binding(),
                 raw_opacities.handle.binding(),
                 global_from_compact_gid.handle.clone().binding(),
@@ -231,10 +433,15 @@ pub(crate) fn render_forward(
 
     let num_intersections_offset =
         offset_of!(shaders::helpers::RenderUniforms, num_intersections) / 4;
-    let num_intersections = copy_tensor(InnerWgpu::int_slice(
-        uniforms_buffer.clone(),
-        &[num_intersections_offset..num_intersections_offset + 1],
-    ));
+    // The counter includes intersections that didn't fit in the buffers, only count the ones
+    // that were written.
+    let num_intersections = InnerWgpu::int_clamp_max(
+        InnerWgpu::int_slice(
+            uniforms_buffer.clone(),
+            &[num_intersections_offset..num_intersections_offset + 1],
+        ),
+        max_intersects as i32,
+    );
 
     let intersect_wg_buf = create_dispatch_buffer(
         num_intersections.clone(),
@@ -297,6 +504,39 @@ pub(crate) fn render_forward(
         (tile_offsets, compact_gid_from_isect)
     };
 
+    // Find where the tiles become opaque, so the rasterizer can skip the splats behind that.
+    // The bound assumes gaussian falloff, so solid renders aren't culled.
+    let tile_ends = (options.occlusion_culling && solid.is_none()).then(|| {
+        let _span = tracing::trace_span!("CullTiles", sync_burn = true).entered();
+        let num_tiles = (tile_bounds.x * tile_bounds.y) as usize;
+        let tile_ends = create_tensor::<1, _>([num_tiles], device, client, DType::I32);
+
+        // SAFETY: Kernel has to contain no OOB indexing.
+        unsafe {
+            client.execute_unchecked(
+                CullTiles::task(tile_width),
+                CubeCount::Static(tile_bounds.x as u32, tile_bounds.y as u32, 1),
+                vec![
+                    uniforms_buffer.clone().handle.binding(),
+                    compact_gid_from_isect.handle.clone().binding(),
+                    tile_offsets.handle.clone().binding(),
+                    projected_splats.handle.clone().binding(),
+                    tile_ends.handle.clone().binding(),
+                ],
+            );
+        }
+        tile_ends
+    });
+
+    let culled_intersections = match &tile_ends {
+        Some(tile_ends) => {
+            let num_offsets = tile_offsets.shape.dims[0];
+            let full_ends = InnerWgpu::int_slice(tile_offsets.clone(), &[1..num_offsets]);
+            InnerWgpu::int_sum(InnerWgpu::int_sub(full_ends, tile_ends.clone()))
+        }
+        None => InnerWgpu::int_zeros([1].into(), device),
+    };
+
     let _span = tracing::trace_span!("Rasterize", sync_burn = true).entered();
 
     let out_dim = if raster_u32 {
@@ -327,19 +567,54 @@ pub(crate) fn render_forward(
         DType::I32,
     );
 
+    let mut bindings = vec![
+        uniforms_buffer.clone().handle.binding(),
+        compact_gid_from_isect.handle.clone().binding(),
+        tile_offsets.handle.clone().binding(),
+        projected_splats.handle.clone().binding(),
+        out_img.handle.clone().binding(),
+        final_index.handle.clone().binding(),
+    ];
+
+    let (depth, normals) = if render_depth {
+        let [h, w] = [img_size.y as usize, img_size.x as usize];
+        let depth = create_tensor::<2, _>([h, w], device, client, DType::F32);
+        let normals = create_tensor::<3, _>([h, w, 3], device, client, DType::F32);
+        bindings.extend([
+            global_from_compact_gid.handle.clone().binding(),
+            means.handle.binding(),
+            log_scales.handle.binding(),
+            quats.handle.binding(),
+            depth.handle.clone().binding(),
+            normals.handle.clone().binding(),
+        ]);
+        (Some(depth), Some(normals))
+    } else {
+        (None, None)
+    };
+
+    let (background_texture, background_environment) = background_defines(&background);
+    if let Some(texture) = background.texture() {
+        bindings.push(texture.handle.clone().binding());
+    }
+    if let Some(tile_ends) = &tile_ends {
+        bindings.push(tile_ends.handle.clone().binding());
+    }
+
     // SAFETY: Kernel has to contain no OOB indexing.
     unsafe {
         client.execute_unchecked(
-            Rasterize::task(raster_u32),
-            calc_cube_count([img_size.x, img_size.y], Rasterize::WORKGROUP_SIZE),
-            vec![
-                uniforms_buffer.clone().handle.binding(),
-                compact_gid_from_isect.handle.clone().binding(),
-                tile_offsets.handle.clone().binding(),
-                projected_splats.handle.clone().binding(),
-                out_img.handle.clone().binding(),
-                final_index.handle.clone().binding(),
-            ],
+            Rasterize::task(
+                tile_width,
+                raster_u32,
+                render_depth,
+                background_texture,
+                background_environment,
+                tile_ends.is_some(),
+                solid.is_some(),
+            ),
+            CubeCount::Static(tile_bounds.x as u32, tile_bounds.y as u32, 1),
+            bindings,
         );
     }
 
@@ -355,10 +630,118 @@ pub(crate) fn render_forward(
             compact_gid_from_isect,
             global_from_compact_gid,
             radii,
+            culled_intersections,
+            depth,
+            normals,
+            tile_width,
         },
     )
 }
 
+/// Render splats as solid surfaces rather than fuzzy blobs, eg. for relighting or to extract a
+/// mesh. Splats below the opacity threshold are skipped, and the others are fully opaque out to
+/// `cutoff_sigma` standard deviations, with a hard edge.
+///
+/// Solid renders aren't differentiable. Splats look best as solid surfaces when they're
+/// trained to be flat, with their normals lined up with the depth, see
+/// [`crate::gaussian_splats::Splats::render_normal_map`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolidRender {
+    pub opacity_threshold: f32,
+    /// At most 3, the extent of the splats in the tile binning.
+    pub cutoff_sigma: f32,
+}
+
+impl Default for SolidRender {
+    fn default() -> Self {
+        Self {
+            opacity_threshold: 0.5,
+            cutoff_sigma: 2.0,
+        }
+    }
+}
+
+impl SolidRender {
+    // The cutoff as the exponent of the gaussian, 0.5 * sigma^2.
+    fn cutoff(&self) -> f32 {
+        let sigma = self.cutoff_sigma.clamp(0.0, 3.0);
+        0.5 * sigma * sigma
+    }
+}
+
+/// Render dynamic splats at a point in time, see
+/// [`crate::gaussian_splats::TemporalAttributes`]. The splats are moved and faded while they're
+/// projected, so gradients flow to their motion.
+#[derive(Debug, Clone)]
+pub struct TemporalRender<T> {
+    /// The velocity, time and log duration of every splat, `[num_splats, 5]`.
+    pub attributes: T,
+    /// Time in seconds to render the splats at.
+    pub time: f32,
+}
+
+impl<T> TemporalRender<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> TemporalRender<U> {
+        TemporalRender {
+            attributes: f(self.attributes),
+            time: self.time,
+        }
+    }
+}
+
+/// What to render besides the splats themselves, see [`crate::Backend::render_splats`].
+#[derive(Debug, Clone)]
+pub struct RenderSettings<T> {
+    /// Move and fade dynamic splats to a point in time while they're projected, see
+    /// [`TemporalRender`].
+    pub temporal: Option<TemporalRender<T>>,
+    /// Render a depth and normal map as well, see [`crate::RenderAux::depth`] and
+    /// [`crate::RenderAux::normals`].
+    pub render_depth: bool,
+    /// Scale the opacity of splats down to compensate for the screenspace blur, as in
+    /// Mip-Splatting. This reduces aliasing when rendering at other resolutions than the splats
+    /// were trained at, but splats have to be trained with it to look right.
+    pub antialias: bool,
+    /// Only render splats with their mean inside of the box.
+    pub crop_box: Option<CropBox>,
+    /// Render splats as hard edged opaque surfaces, see [`SolidRender`]. Solid renders can't
+    /// be differentiated.
+    pub solid: Option<SolidRender>,
+    /// How the splats are rasterized, see [`RenderOptions`].
+    pub options: RenderOptions,
+    /// What the splats are composited over, see [`Background`].
+    pub background: Background<T>,
+}
+
+impl<T> Default for RenderSettings<T> {
+    fn default() -> Self {
+        Self {
+            temporal: None,
+            render_depth: false,
+            antialias: false,
+            crop_box: None,
+            solid: None,
+            options: RenderOptions::default(),
+            background: Background::default(),
+        }
+    }
+}
+
+impl<T> RenderSettings<T> {
+    /// Convert the tensors of the settings, eg. to a different backend.
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> RenderSettings<U> {
+        RenderSettings {
+            temporal: self.temporal.map(|t| t.map(&mut f)),
+            render_depth: self.render_depth,
+            antialias: self.antialias,
+            crop_box: self.crop_box,
+            solid: self.solid,
+            options: self.options,
+            background: self.background.map(f),
+        }
+    }
+}
+
 use std::sync::atomic::{AtomicBool, Ordering};
 
 // TODO: Properly register hardware atomic floats as a cube feature when
@@ -374,6 +757,80 @@ pub fn has_hard_floats() -> bool {
     HARD_FLOATS_AVAILABLE.load(Ordering::SeqCst)
 }
 
+static HALF_FLOATS_AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Set whether the GPU supports f16 in shaders, which storing splats at half precision needs,
+/// see [`crate::gaussian_splats::Splats::with_half_precision`].
+pub fn set_half_floats_available(available: bool) {
+    HALF_FLOATS_AVAILABLE.store(available, Ordering::SeqCst);
+}
+
+pub fn has_half_floats() -> bool {
+    HALF_FLOATS_AVAILABLE.load(Ordering::SeqCst)
+}
+
+/// The tile widths the kernels can be compiled with.
+pub const TILE_WIDTHS: [u32; 3] = [8, 16, 32];
+
+/// How splats are rasterized. These don't change what is rendered, but trade off speed and
+/// memory. The options are kept with a render, so its backward pass always matches the forward
+/// pass, even if other renders with other options run in between.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderOptions {
+    /// Rasterize in tiles of `tile_width` x `tile_width` pixels, one of [`TILE_WIDTHS`].
+    /// Smaller tiles waste less work on splats that only cover part of a tile, bigger tiles
+    /// share more work between pixels, and which is faster depends on the GPU.
+    pub tile_width: u32,
+    /// Render and calculate gradients in a fixed order, so the same inputs always give bit
+    /// identical results. Gradients are normally summed with atomics, in whatever order the GPU
+    /// runs, which makes training runs differ slightly. This is slower, and meant for debugging
+    /// and exact tests. The backward pass also needs 36 bytes for every intersection the
+    /// buffers have room for.
+    pub deterministic: bool,
+    /// Sum the absolute screenspace gradients of every pixel, rather than the signed gradients,
+    /// for the xy gradients used to densify, as in AbsGS. The signed gradients of the pixels
+    /// around a big splat cancel out, so it's never split, even if it's blurry. The gradients
+    /// of the means aren't affected.
+    pub absgrad: bool,
+    /// Skip the splats of a tile that are behind splats which make the whole tile opaque. A
+    /// coarse prepass bounds the opacity of every tile from the splats covering it, which saves
+    /// a lot of rasterizing in scenes with heavy occlusion, eg. indoor scans. The culling is
+    /// conservative, so renders are the same. See [`crate::RenderAux::culled_intersections`].
+    pub occlusion_culling: bool,
+    /// Don't keep the projected splats and intersection buffers of a differentiable render
+    /// until the backward pass, but render again from the inputs to calculate the gradients.
+    /// This saves a lot of memory at high resolutions, and makes training steps ~20% slower.
+    pub recompute_backward: bool,
+    /// Expected nr. of intersections per splat and tile, to size the intersection buffers for
+    /// instead of the worst case, see [`IntersectionBudget`].
+    pub intersection_density: Option<f32>,
+}
+
+impl Default for RenderOptions {
+    /// The tile width tuned for the current adapter, see [`crate::tile_autotune`].
+    fn default() -> Self {
+        Self {
+            tile_width: crate::tile_autotune::default_tile_width(),
+            deterministic: false,
+            absgrad: false,
+            occlusion_culling: false,
+            recompute_backward: false,
+            intersection_density: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn with_tile_width(mut self, tile_width: u32) -> Self {
+        assert!(
+            TILE_WIDTHS.contains(&tile_width),
+            "Unsupported tile width {tile_width}"
+        );
+        self.tile_width = tile_width;
+        self
+    }
+}
+
 pub(crate) fn render_backward(
     v_output: JitTensor<WgpuRuntime>,
 
@@ -381,6 +838,7 @@ pub(crate) fn render_backward(
     quats: JitTensor<WgpuRuntime>,
     log_scales: JitTensor<WgpuRuntime>,
     raw_opac: JitTensor<WgpuRuntime>,
+    temporal: Option<TemporalRender<JitTensor<WgpuRuntime>>>,
     out_img: JitTensor<WgpuRuntime>,
 
     projected_splats: JitTensor<WgpuRuntime>,
@@ -390,10 +848,16 @@ pub(crate) fn render_backward(
     tile_offsets: JitTensor<WgpuRuntime>,
     final_index: JitTensor<WgpuRuntime>,
     sh_degree: u32,
+    camera_model: CameraModel,
+    custom_projection: bool,
+    antialias: bool,
+    options: RenderOptions,
+    background: Background<JitTensor<WgpuRuntime>>,
 ) -> SplatGrads<InnerWgpu> {
     let device = &out_img.device;
     let img_dimgs = out_img.shape.dims;
     let img_size = glam::uvec2(img_dimgs[1] as u32, img_dimgs[0] as u32);
+    let (distort_opencv, distort_fisheye) = distortion_defines(camera_model);
 
     let num_points = means.shape.dims[0];
 
@@ -415,10 +879,7 @@ pub(crate) fn render_backward(
     );
     let v_raw_opac = InnerWgpu::float_zeros([num_points].into(), device);
 
-    let tile_bounds = uvec2(
-        img_size.x.div_ceil(shaders::helpers::TILE_WIDTH),
-        img_size.y.div_ceil(shaders::helpers::TILE_WIDTH),
-    );
+    let tile_bounds = calc_tile_bounds(img_size, options.tile_width);
     let invocations = tile_bounds.x * tile_bounds.y;
 
     // These gradients are atomically added to so important to zero them.
@@ -426,65 +887,179 @@ pub(crate) fn render_backward(
     let v_colors = InnerWgpu::float_zeros([num_points, 4].into(), device);
 
     let hard_floats = has_hard_floats();
+    let deterministic = options.deterministic;
+
+    // The summed absolute xy gradients, reported instead of the signed ones.
+    let absgrad = options.absgrad;
+    let v_xys_abs = absgrad.then(|| InnerWgpu::float_zeros([num_points, 2].into(), device));
+
+    let background = background.map(into_contiguous);
+    let (background_texture, background_environment) = background_defines(&background);
+
+    // When deterministic, the gradients of every intersection are written out first, and then
+    // summed per splat.
+    let max_intersects = compact_gid_from_isect.shape.dims[0];
+    let num_intersections_offset =
+        offset_of!(shaders::helpers::RenderUniforms, num_intersections) / 4;
+    let num_intersections = deterministic.then(|| {
+        InnerWgpu::int_clamp_max(
+            InnerWgpu::int_slice(
+                uniforms_buffer.clone(),
+                &[num_intersections_offset..num_intersections_offset + 1],
+            ),
+            max_intersects as i32,
+        )
+    });
+    let v_isect = deterministic.then(|| {
+        let projected_size = size_of::<shaders::helpers::ProjectedSplat>() / size_of::<f32>();
+        InnerWgpu::float_zeros([max_intersects, projected_size].into(), device)
+    });
+    let v_isect_abs = (deterministic && absgrad)
+        .then(|| InnerWgpu::float_zeros([max_intersects, 2].into(), device));
 
     tracing::trace_span!("RasterizeBackwards", sync_burn = true).in_scope(||
             // SAFETY: Kernel has to contain no OOB indexing.
             unsafe {
-                client.execute_unchecked(
-                    RasterizeBackwards::task(hard_floats),
-                    CubeCount::Static(invocations, 1, 1),
-                    vec![
+                let mut bindings = vec![
                         uniforms_buffer.clone().handle.binding(),
-                        compact_gid_from_isect.handle.binding(),
+                        compact_gid_from_isect.clone().handle.binding(),
                         tile_offsets.handle.binding(),
                         projected_splats.handle.binding(),
                         final_index.handle.binding(),
                         out_img.handle.binding(),
                         v_output.handle.binding(),
+                    ];
+                if let Some(v_isect) = &v_isect {
+                    bindings.push(v_isect.handle.clone().binding());
+                    if let Some(v_isect_abs) = &v_isect_abs {
+                        bindings.push(v_isect_abs.handle.clone().binding());
+                    }
+                } else {
+                    bindings.extend([
                         v_xys_local.clone().handle.binding(),
                         v_conics.clone().handle.binding(),
                         v_colors.clone().handle.binding(),
-                    ],
+                    ]);
+                    if let Some(v_xys_abs) = &v_xys_abs {
+                        bindings.push(v_xys_abs.handle.clone().binding());
+                    }
+                }
+                if let Some(texture) = background.texture() {
+                    bindings.push(texture.handle.clone().binding());
+                }
+                client.execute_unchecked(
+                    RasterizeBackwards::task(
+                        options.tile_width,
+                        hard_floats,
+                        deterministic,
+                        background_texture,
+                        background_environment,
+                        absgrad,
+                    ),
+                    CubeCount::Static(invocations, 1, 1),
+                    bindings,
                 );
             });
 
+    if let (Some(v_isect), Some(num_intersections)) = (v_isect, num_intersections) {
+        let _span = tracing::trace_span!("SumIsectGrads", sync_burn = true).entered();
+
+        // Radix sort is stable, so the intersections of a splat stay sorted by tile.
+        let isect_ids = InnerWgpu::int_arange(0..max_intersects as i64, device);
+        let bits = u32::BITS - (num_points as u32).leading_zeros();
+        let (compact_gid_from_sorted, isect_from_sorted) =
+            radix_argsort(compact_gid_from_isect, isect_ids, &num_intersections, bits);
+
+        let sum_wg_buf =
+            create_dispatch_buffer(num_intersections.clone(), SumIsectGrads::WORKGROUP_SIZE);
+
+        let mut bindings = vec![
+            num_intersections.handle.binding(),
+            compact_gid_from_sorted.handle.binding(),
+            isect_from_sorted.handle.binding(),
+            v_isect.handle.binding(),
+            v_xys_local.clone().handle.binding(),
+            v_conics.clone().handle.binding(),
+            v_colors.clone().handle.binding(),
+        ];
+        if let (Some(v_isect_abs), Some(v_xys_abs)) = (v_isect_abs, &v_xys_abs) {
+            bindings.extend([
+                v_isect_abs.handle.binding(),
+                v_xys_abs.handle.clone().binding(),
+            ]);
+        }
+
+        // SAFETY: Kernel has to contain no OOB indexing.
+        unsafe {
+            client.execute_unchecked(
+                SumIsectGrads::task(absgrad),
+                CubeCount::Dynamic(sum_wg_buf.handle.binding()),
+                bindings,
+            );
+        }
+    }
+
     let _span = tracing::trace_span!("GatherGrads", sync_burn = true).entered();
 
+    let mut bindings = vec![
+        uniforms_buffer.clone().handle.binding(),
+        global_from_compact_gid.clone().handle.binding(),
+        means.clone().handle.binding(),
+        v_colors.clone().handle.binding(),
+        v_coeffs.handle.clone().binding(),
+    ];
+    if let Some(temporal) = &temporal {
+        bindings.push(temporal.attributes.handle.clone().binding());
+    }
+
     // SAFETY: Kernel has to contain no OOB indexing.
     unsafe {
         client.execute_unchecked(
-            GatherGrads::task(),
+            GatherGrads::task(temporal.is_some()),
             calc_cube_count([num_points as u32], GatherGrads::WORKGROUP_SIZE),
-            vec![
-                uniforms_buffer.clone().handle.binding(),
-                global_from_compact_gid.clone().handle.binding(),
-                raw_opac.handle.binding(),
-                means.clone().handle.binding(),
-                v_colors.handle.binding(),
-                v_coeffs.handle.clone().binding(),
-                v_raw_opac.handle.clone().binding(),
-            ],
+            bindings,
         );
     }
 
+    let v_temporal = temporal
+        .as_ref()
+        .map(|_| InnerWgpu::float_zeros([num_points, 5].into(), device));
+
+    let mut bindings = vec![
+        uniforms_buffer.handle.binding(),
+        means.handle.binding(),
+        log_scales.handle.binding(),
+        quats.handle.binding(),
+        raw_opac.handle.binding(),
+        global_from_compact_gid.handle.binding(),
+        v_xys_local.handle.clone().binding(),
+        v_conics.handle.binding(),
+        v_colors.handle.binding(),
+        v_means.handle.clone().binding(),
+        v_scales.handle.clone().binding(),
+        v_quats.handle.clone().binding(),
+        v_raw_opac.handle.clone().binding(),
+    ];
+    if let (Some(temporal), Some(v_temporal)) = (&temporal, &v_temporal) {
+        bindings.extend([
+            temporal.attributes.handle.clone().binding(),
+            v_temporal.handle.clone().binding(),
+        ]);
+    }
+
     tracing::trace_span!("ProjectBackwards", sync_burn = true).in_scope(||
         // SAFETY: Kernel has to contain no OOB indexing.
         unsafe {
         client.execute_unchecked(
-            ProjectBackwards::task(),
+            ProjectBackwards::task(
+                distort_opencv,
+                distort_fisheye,
+                custom_projection,
+                antialias,
+                temporal.is_some(),
+            ),
             calc_cube_count([num_points as u32], ProjectBackwards::WORKGROUP_SIZE),
-            vec![
-                uniforms_buffer.handle.binding(),
-                means.handle.binding(),
-                log_scales.handle.binding(),
-                quats.handle.binding(),
-                global_from_compact_gid.handle.binding(),
-                v_xys_local.handle.clone().binding(),
-                v_conics.handle.binding(),
-                v_means.handle.clone().binding(),
-                v_scales.handle.clone().binding(),
-                v_quats.handle.clone().binding(),
-            ],
+            bindings,
         );
     });
 
@@ -494,6 +1069,93 @@ pub(crate) fn render_backward(
         v_scales,
         v_coeffs,
         v_raw_opac,
-        v_xy: v_xys_local,
+        v_temporal,
+        // The means already have their gradients from the signed xy gradients, the xy
+        // gradients themselves are only used to densify.
+        v_xy: v_xys_abs.unwrap_or(v_xys_local),
+    }
+}
+
+/// Blend a number of features per splat, `[num_splats, channels]`, into an `[h, w, channels]`
+/// image, using the projected splats and intersections of a render.
+pub(crate) fn render_features_forward(
+    features: JitTensor<WgpuRuntime>,
+    state: FeatureRenderState<InnerWgpu>,
+) -> JitTensor<WgpuRuntime> {
+    let _span = tracing::trace_span!("RasterizeFeatures", sync_burn = true).entered();
+
+    let features = into_contiguous(features);
+    let channels = features.shape.dims[1];
+    let [h, w] = [
+        state.final_index.shape.dims[0],
+        state.final_index.shape.dims[1],
+    ];
+    let device = &features.device;
+    let client = &features.client;
+
+    let out_features = create_tensor::<3, _>([h, w, channels], device, client, DType::F32);
+
+    let tile_bounds = calc_tile_bounds(uvec2(w as u32, h as u32), state.tile_width);
+
+    // SAFETY: Kernel has to contain no OOB indexing.
+    unsafe {
+        client.execute_unchecked(
+            RasterizeFeatures::task(state.tile_width, channels as u32),
+            CubeCount::Static(tile_bounds.x, tile_bounds.y, 1),
+            vec![
+                state.uniforms_buffer.handle.binding(),
+                state.compact_gid_from_isect.handle.binding(),
+                state.tile_offsets.handle.binding(),
+                state.projected_splats.handle.binding(),
+                state.global_from_compact_gid.handle.binding(),
+                features.handle.binding(),
+                out_features.handle.clone().binding(),
+            ],
+        );
     }
+
+    out_features
+}
+
+/// Gradients of the features of [`render_features_forward`]. Only the features get gradients,
+/// the blending weights are taken as given.
+pub(crate) fn render_features_backward(
+    v_output: JitTensor<WgpuRuntime>,
+    state: FeatureRenderState<InnerWgpu>,
+) -> JitTensor<WgpuRuntime> {
+    let _span = tracing::trace_span!("RasterizeFeaturesBackwards", sync_burn = true).entered();
+
+    let v_output = into_contiguous(v_output);
+    let channels = v_output.shape.dims[2];
+    let num_points = state.projected_splats.shape.dims[0];
+    let device = &v_output.device;
+    let client = &v_output.client;
+
+    // Atomically added to, so has to be zeroed.
+    let v_features = InnerWgpu::float_zeros([num_points, channels].into(), device);
+
+    let img_size = uvec2(
+        state.final_index.shape.dims[1] as u32,
+        state.final_index.shape.dims[0] as u32,
+    );
+    let tile_bounds = calc_tile_bounds(img_size, state.tile_width);
+
+    // SAFETY: Kernel has to contain no OOB indexing.
+    unsafe {
+        client.execute_unchecked(
+            RasterizeFeaturesBackwards::task(state.tile_width, channels as u32, has_hard_floats()),
+            CubeCount::Static(tile_bounds.x * tile_bounds.y, 1, 1),
+            vec![
+                state.uniforms_buffer.handle.binding(),
+                state.compact_gid_from_isect.handle.binding(),
+                state.tile_offsets.handle.binding(),
+                state.projected_splats.handle.binding(),
+                state.global_from_compact_gid.handle.binding(),
+                v_output.handle.binding(),
+                v_features.handle.clone().binding(),
+            ],
+        );
+    }
+
+    v_features
 }
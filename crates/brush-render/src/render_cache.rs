@@ -0,0 +1,129 @@
+//! Reuse the last render while the camera and splats don't change, eg. in a viewer where the
+//! camera is standing still.
+//!
+//! Most of the cost of a render is projecting, depth sorting and binning the splats into tiles,
+//! which only depends on the camera and the splats. A cached render keeps those results in its
+//! [`RenderAux`], so nothing has to run on the GPU until the camera moves or the splats change,
+//! eg. after a training step.
+
+use burn::prelude::Tensor;
+
+use crate::{camera::Camera, Backend, RenderAux};
+
+/// What a render depends on. Renders with a matching key give the same image.
+#[derive(Debug, Clone)]
+pub struct RenderKey {
+    pub camera: Camera,
+    pub img_size: glam::UVec2,
+    /// Changes whenever the splats or how they're rendered change, eg. on every training step.
+    pub generation: u64,
+    pub render_u32_buffer: bool,
+}
+
+// Camera motion below this is float noise, eg. from damping the velocity of the camera
+// controls, rather than the camera moving. Relative to the distance from the origin for
+// positions.
+const MOVE_TOLERANCE: f32 = 1e-6;
+
+impl RenderKey {
+    /// Whether a render with this key looks the same as a render with `other`.
+    pub fn matches(&self, other: &Self) -> bool {
+        let (a, b) = (&self.camera, &other.camera);
+        let max_move = MOVE_TOLERANCE * a.position.length().max(1.0);
+        let same_camera = a.fov_x == b.fov_x
+            && a.fov_y == b.fov_y
+            && a.center_uv == b.center_uv
+            && a.model == b.model
+            && a.projection == b.projection
+            && a.position.distance(b.position) <= max_move
+            // q and -q are the same rotation.
+            && (a.rotation.abs_diff_eq(b.rotation, MOVE_TOLERANCE)
+                || a.rotation.abs_diff_eq(-b.rotation, MOVE_TOLERANCE));
+        same_camera
+            && self.img_size == other.img_size
+            && self.generation == other.generation
+            && self.render_u32_buffer == other.render_u32_buffer
+    }
+}
+
+/// The last render, with the sort and tile binning results in its aux.
+#[derive(Default)]
+pub struct RenderCache<B: Backend> {
+    last: Option<(RenderKey, Tensor<B, 3>, RenderAux<B>)>,
+    hits: u64,
+}
+
+impl<B: Backend> RenderCache<B> {
+    /// The cached render, if it has a matching key.
+    pub fn get(&self, key: &RenderKey) -> Option<(Tensor<B, 3>, RenderAux<B>)> {
+        self.last
+            .as_ref()
+            .filter(|(last_key, _, _)| last_key.matches(key))
+            .map(|(_, img, aux)| (img.clone(), aux.clone()))
+    }
+
+    pub fn insert(&mut self, key: RenderKey, img: Tensor<B, 3>, aux: RenderAux<B>) {
+        self.last = Some((key, img, aux));
+    }
+
+    /// The cached render if it matches `key`, or else a new render, which is cached.
+    pub fn render(
+        &mut self,
+        key: RenderKey,
+        render: impl FnOnce() -> (Tensor<B, 3>, RenderAux<B>),
+    ) -> (Tensor<B, 3>, RenderAux<B>) {
+        if let Some(cached) = self.get(&key) {
+            self.hits += 1;
+            return cached;
+        }
+        let (img, aux) = render();
+        self.insert(key, img.clone(), aux.clone());
+        (img, aux)
+    }
+
+    /// Render again next time, eg. when settings that aren't in the key change.
+    pub fn invalidate(&mut self) {
+        self.last = None;
+    }
+
+    /// Nr. of renders that were reused.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_still_camera() {
+        let camera = Camera::new(
+            glam::vec3(1.0, 2.0, 3.0),
+            glam::Quat::from_rotation_y(0.5),
+            0.8,
+            0.6,
+            glam::vec2(0.5, 0.5),
+        );
+        let key = RenderKey {
+            camera: camera.clone(),
+            img_size: glam::uvec2(64, 48),
+            generation: 0,
+            render_u32_buffer: true,
+        };
+
+        // Float noise in the camera, or the same rotation as -q, still match.
+        let mut noisy = key.clone();
+        noisy.camera.position.x += 1e-7;
+        noisy.camera.rotation = -camera.rotation;
+        assert!(key.matches(&noisy));
+
+        let mut moved = key.clone();
+        moved.camera.position.x += 1e-3;
+        assert!(!key.matches(&moved));
+
+        let mut trained = key.clone();
+        trained.generation += 1;
+        assert!(!key.matches(&trained));
+    }
+}
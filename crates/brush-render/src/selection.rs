@@ -0,0 +1,191 @@
+//! Select splats, and edit the selected splats.
+//!
+//! A selection is a mask with one entry per splat, like the masks of [`Splats::with_hidden`] and
+//! [`Splats::retained`], so selections can be combined with the usual tensor ops.
+
+use burn::tensor::{Bool, ElementConversion, Tensor};
+use glam::{Affine3A, Mat3, Quat, UVec2, Vec2, Vec3};
+
+use crate::{
+    camera::Camera,
+    crop::{CropBox, CropVolume},
+    gaussian_splats::Splats,
+    render::SH_C0,
+    Backend,
+};
+
+// Color of selected splats in the viewer.
+const HIGHLIGHT: Vec3 = Vec3::new(1.0, 0.55, 0.0);
+
+// Like `mask_where`, but the mask has one entry per splat, and is broadcast over the other
+// dimensions.
+fn select_rows<B: Backend, const D: usize>(
+    tensor: Tensor<B, D>,
+    selection: &Tensor<B, 1, Bool>,
+    value: Tensor<B, D>,
+) -> Tensor<B, D> {
+    let dims = tensor.dims();
+    let mut mask_dims = [1; D];
+    mask_dims[0] = dims[0];
+    let mask = selection.clone().reshape(mask_dims).expand(dims);
+    tensor.mask_where(mask, value)
+}
+
+impl<B: Backend> Splats<B> {
+    /// Select the splats with their mean inside of a box.
+    pub fn select_in_box(&self, crop_box: &CropBox) -> Tensor<B, 1, Bool> {
+        CropVolume::new(vec![crop_box.as_layer()]).contains(self.means.val())
+    }
+
+    /// Select the splats with their mean inside of a lasso, a polygon in pixel coordinates
+    /// of an image of `img_size` seen from `camera`. Lens distortion is ignored.
+    pub fn select_in_lasso(
+        &self,
+        camera: &Camera,
+        img_size: UVec2,
+        lasso: &[Vec2],
+    ) -> Tensor<B, 1, Bool> {
+        let n = self.num_splats();
+        let device = self.means.val().device();
+
+        // Means are row vectors, so multiply by the transposed matrix, see `transformed`.
+        let world_to_local = camera.world_to_local();
+        let mat = Tensor::<B, 1>::from_floats(
+            Mat3::from(world_to_local.matrix3).to_cols_array(),
+            &device,
+        )
+        .reshape([3, 3]);
+        let translation =
+            Tensor::<B, 1>::from_floats(Vec3::from(world_to_local.translation).to_array(), &device)
+                .reshape([1, 3]);
+        let local = self.means.val().matmul(mat) + translation;
+
+        let column = |t: Tensor<B, 2>, c: usize| t.slice([0..n, c..c + 1]).reshape([n]);
+        let center = camera.center(img_size);
+        let (x, y, in_front) = if let Some(projection) = camera.projection_matrix() {
+            let local = Tensor::cat(vec![local, Tensor::ones([n, 1], &device)], 1);
+            let proj =
+                Tensor::<B, 1>::from_floats(projection.to_cols_array(), &device).reshape([4, 4]);
+            let clip = local.matmul(proj);
+            let w = column(clip.clone(), 3);
+            let half_size = img_size.as_vec2() * 0.5;
+            (
+                (column(clip.clone(), 0) / w.clone()) * half_size.x + center.x,
+                (column(clip, 1) / w.clone()) * half_size.y + center.y,
+                w.greater_elem(0.0),
+            )
+        } else {
+            let focal = camera.focal(img_size);
+            let z = column(local.clone(), 2);
+            let safe_z = z.clone().clamp_min(1e-6);
+            (
+                column(local.clone(), 0) / safe_z.clone() * focal.x + center.x,
+                column(local, 1) / safe_z * focal.y + center.y,
+                z.greater_elem(0.0),
+            )
+        };
+
+        // Even-odd rule: flip whether a point is inside for every edge to its right.
+        let mut inside = Tensor::<B, 1>::zeros([n], &device);
+        for (i, &a) in lasso.iter().enumerate() {
+            let b = lasso[(i + 1) % lasso.len()];
+            if a.y == b.y {
+                continue;
+            }
+            let crosses =
+                (y.clone().lower_elem(a.y).float() - y.clone().lower_elem(b.y).float()).abs();
+            let edge_x = (y.clone() - a.y) * ((b.x - a.x) / (b.y - a.y)) + a.x;
+            let hit = crosses * x.clone().lower(edge_x).float();
+            inside = (inside - hit).abs();
+        }
+
+        inside.greater_elem(0.5).bool_and(in_front)
+    }
+
+    /// The average position of the selected splats, or None if nothing is selected.
+    pub async fn selection_center(&self, selection: Tensor<B, 1, Bool>) -> Option<Vec3> {
+        let n = self.num_splats();
+        let weights = selection.float().reshape([n, 1]);
+        let count = weights
+            .clone()
+            .sum()
+            .into_scalar_async()
+            .await
+            .elem::<f32>();
+        if count < 0.5 {
+            return None;
+        }
+        let sum = (self.means.val() * weights)
+            .sum_dim(0)
+            .into_data_async()
+            .await
+            .to_vec::<f32>()
+            .ok()?;
+        Some(Vec3::from_slice(&sum) / count)
+    }
+
+    /// Remove the selected splats.
+    pub async fn deleted(self, selection: Tensor<B, 1, Bool>) -> Self {
+        self.retained(selection.bool_not()).await
+    }
+
+    /// Add a copy of the selected splats, after all other splats.
+    pub async fn duplicated(self, selection: Tensor<B, 1, Bool>) -> Self {
+        let copies = self.clone().retained(selection).await;
+        Self::merge(&[self, copies])
+    }
+
+    /// Apply a transform to the selected splats, see [`Self::transformed`].
+    pub fn transformed_selection(
+        &self,
+        selection: Tensor<B, 1, Bool>,
+        transform: Affine3A,
+    ) -> Self {
+        let moved = self.transformed(transform);
+        let mut edited = Self::from_tensor_data(
+            select_rows(self.means.val(), &selection, moved.means.val()),
+            select_rows(self.rotation.val(), &selection, moved.rotation.val()),
+            select_rows(self.log_scales.val(), &selection, moved.log_scales.val()),
+            select_rows(self.sh_coeffs.val(), &selection, moved.sh_coeffs.val()),
+            self.raw_opacity.val(),
+        );
+        edited.labels = self.labels.clone();
+        edited.temporal = moved.temporal;
+        edited.crop_box = self.crop_box;
+        edited.solid = self.solid;
+        edited.render_options = self.render_options;
+        edited.time = self.time;
+        edited
+    }
+
+    /// Tint the selected splats, to show the selection in the viewer.
+    pub fn with_highlighted(&self, selection: Tensor<B, 1, Bool>) -> Self {
+        let [n, n_coeffs, _] = self.sh_coeffs.dims();
+        let device = self.means.val().device();
+
+        let coeffs = self.sh_coeffs.val();
+        let base = coeffs.clone().slice([0..n, 0..1]);
+        let highlight =
+            Tensor::<B, 1>::from_floats(((HIGHLIGHT - 0.5) / SH_C0).to_array(), &device)
+                .reshape([1, 1, 3]);
+        let tinted = base.clone() * 0.4 + highlight * 0.6;
+        let base = select_rows(base, &selection, tinted);
+
+        let mut highlighted = self.clone();
+        Self::map_param(&mut highlighted.sh_coeffs, |_| {
+            if n_coeffs > 1 {
+                Tensor::cat(vec![base, coeffs.slice([0..n, 1..n_coeffs])], 1)
+            } else {
+                base
+            }
+        });
+        highlighted
+    }
+}
+
+/// The transform that moves, rotates and scales around `pivot`.
+pub fn transform_around(pivot: Vec3, translation: Vec3, rotation: Quat, scale: f32) -> Affine3A {
+    Affine3A::from_translation(pivot + translation)
+        * Affine3A::from_scale_rotation_translation(Vec3::splat(scale), rotation, Vec3::ZERO)
+        * Affine3A::from_translation(-pivot)
+}
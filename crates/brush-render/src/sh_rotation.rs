@@ -0,0 +1,288 @@
+// Rotation of spherical harmonics coefficients.
+//
+// Bands are rotated with the recursion of Ivanic & Ruedenberg, "Rotation Matrices for Real
+// Spherical Harmonics. Direct Determination by Recursion" (1996), with the corrections of their
+// 1998 erratum. The rotation of band 1 follows directly from the rotation matrix, and every
+// higher band is built from band 1 and the band below it.
+use glam::{DMat3, Quat};
+
+use crate::render::sh_coeffs_for_degree;
+
+const MAX_DEGREE: u32 = 4;
+
+// A rotation matrix of a single band `l`, indexed by `m, n` in `-l..=l`.
+#[derive(Clone)]
+struct Band {
+    l: i32,
+    values: Vec<f64>,
+}
+
+impl Band {
+    fn get(&self, m: i32, n: i32) -> f64 {
+        self.values[((m + self.l) * (2 * self.l + 1) + n + self.l) as usize]
+    }
+}
+
+// Band 1 is the rotation matrix itself, as the basis functions are (-y, z, -x) up to a constant.
+fn band_1(rotation: Quat) -> Band {
+    let m = DMat3::from_quat(rotation.as_dquat());
+    let r = |i: usize, j: usize| m.col(j)[i];
+    #[rustfmt::skip]
+    let values = vec![
+        r(1, 1), -r(1, 2), r(1, 0),
+        -r(2, 1), r(2, 2), -r(2, 0),
+        r(0, 1), -r(0, 2), r(0, 0),
+    ];
+    Band { l: 1, values }
+}
+
+// Band `l` from band 1 and band `l - 1`, see table 2 of the paper.
+fn next_band(r1: &Band, prev: &Band) -> Band {
+    let l = prev.l + 1;
+
+    let p = |i: i32, a: i32, b: i32| {
+        if b == l {
+            r1.get(i, 1) * prev.get(a, l - 1) - r1.get(i, -1) * prev.get(a, -l + 1)
+        } else if b == -l {
+            r1.get(i, 1) * prev.get(a, -l + 1) + r1.get(i, -1) * prev.get(a, l - 1)
+        } else {
+            r1.get(i, 0) * prev.get(a, b)
+        }
+    };
+
+    let mut values = Vec::with_capacity(((2 * l + 1) * (2 * l + 1)) as usize);
+    for m in -l..=l {
+        for n in -l..=l {
+            let delta = if m == 0 { 1.0 } else { 0.0 };
+            let denom = if n.abs() == l {
+                (2 * l * (2 * l - 1)) as f64
+            } else {
+                ((l + n) * (l - n)) as f64
+            };
+            let (lf, am) = (l as f64, m.abs() as f64);
+
+            let u = (((l + m) * (l - m)) as f64 / denom).sqrt();
+            let v = 0.5
+                * ((1.0 + delta) * (lf + am - 1.0) * (lf + am) / denom).sqrt()
+                * (1.0 - 2.0 * delta);
+            let w = -0.5 * ((lf - am - 1.0) * (lf - am) / denom).max(0.0).sqrt() * (1.0 - delta);
+
+            let mut value = 0.0;
+            if u != 0.0 {
+                value += u * p(0, m, n);
+            }
+            if v != 0.0 {
+                value += v * match m {
+                    0 => p(1, 1, n) + p(-1, -1, n),
+                    1 => p(1, 0, n) * 2.0f64.sqrt(),
+                    -1 => p(-1, 0, n) * 2.0f64.sqrt(),
+                    m if m > 0 => p(1, m - 1, n) - p(-1, -m + 1, n),
+                    m => p(1, m + 1, n) + p(-1, -m - 1, n),
+                };
+            }
+            if w != 0.0 {
+                value += w * if m > 0 {
+                    p(1, m + 1, n) + p(-1, -m - 1, n)
+                } else {
+                    p(1, m - 1, n) - p(-1, -m + 1, n)
+                };
+            }
+            values.push(value);
+        }
+    }
+    Band { l, values }
+}
+
+/// Calculate the matrix that rotates SH coefficients of the given degree. The result is a
+/// row major `[n, n]` matrix with `n = (degree + 1)^2`, meant to multiply coefficients as row
+/// vectors, ie. `rotated = coeffs * mat`.
+///
+/// A view dependent color evaluated along a direction `d` after rotating matches the original
+/// color evaluated along `rotation^-1 * d`.
+pub fn sh_rotation_matrix(rotation: Quat, degree: u32) -> Vec<f32> {
+    assert!(degree <= MAX_DEGREE, "SH degree {degree} is not supported");
+
+    let n_coeffs = sh_coeffs_for_degree(degree) as usize;
+    let mut mat = vec![0.0; n_coeffs * n_coeffs];
+    mat[0] = 1.0;
+    if degree == 0 {
+        return mat;
+    }
+
+    let r1 = band_1(rotation);
+    let mut band = r1.clone();
+    for l in 1..=degree as i32 {
+        if l > 1 {
+            band = next_band(&r1, &band);
+        }
+
+        // The band rotates coefficients as column vectors, so the block is its transpose.
+        let offset = (l * l) as usize;
+        for m in -l..=l {
+            for n in -l..=l {
+                let row = offset + (n + l) as usize;
+                let col = offset + (m + l) as usize;
+                mat[row * n_coeffs + col] = band.get(m, n) as f32;
+            }
+        }
+    }
+    mat
+}
+
+/// Rotate SH coefficients on the CPU, laid out as `[splats, coeffs, 3]` like
+/// [`crate::gaussian_splats::Splats::sh_coeffs`]. See [`sh_rotation_matrix`].
+pub fn rotate_sh_coeffs(coeffs: &mut [f32], rotation: Quat, degree: u32) {
+    let n_coeffs = sh_coeffs_for_degree(degree) as usize;
+    assert_eq!(
+        coeffs.len() % (n_coeffs * 3),
+        0,
+        "Coefficients don't match SH degree {degree}"
+    );
+    if degree == 0 {
+        return;
+    }
+
+    let mat = sh_rotation_matrix(rotation, degree);
+    let mut rotated = vec![0.0; n_coeffs * 3];
+    for splat in coeffs.chunks_exact_mut(n_coeffs * 3) {
+        rotated.fill(0.0);
+        for k in 0..n_coeffs {
+            for m in 0..n_coeffs {
+                let weight = mat[m * n_coeffs + k];
+                if weight == 0.0 {
+                    continue;
+                }
+                for c in 0..3 {
+                    rotated[k * 3 + c] += splat[m * 3 + c] * weight;
+                }
+            }
+        }
+        splat.copy_from_slice(&rotated);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{DVec3, Vec3};
+
+    /// Evaluate the SH basis functions up to degree 4 at a unit direction. This mirrors
+    /// `sh_coeffs_to_color` in project_visible.wgsl.
+    fn sh_basis(dir: DVec3) -> [f64; 25] {
+        let (x, y, z) = (dir.x, dir.y, dir.z);
+        let mut sh = [0.0; 25];
+
+        sh[0] = 0.2820947917738781;
+
+        let f_tmp0a = 0.48860251190292;
+        sh[1] = -f_tmp0a * y;
+        sh[2] = f_tmp0a * z;
+        sh[3] = -f_tmp0a * x;
+
+        let z2 = z * z;
+        let f_tmp0b = -1.092548430592079 * z;
+        let f_tmp1a = 0.5462742152960395;
+        let f_c1 = x * x - y * y;
+        let f_s1 = 2.0 * x * y;
+        sh[6] = 0.9461746957575601 * z2 - 0.3153915652525201;
+        sh[7] = f_tmp0b * x;
+        sh[5] = f_tmp0b * y;
+        sh[8] = f_tmp1a * f_c1;
+        sh[4] = f_tmp1a * f_s1;
+
+        let f_tmp0c = -2.285228997322329 * z2 + 0.4570457994644658;
+        let f_tmp1b = 1.445305721320277 * z;
+        let f_tmp2a = -0.5900435899266435;
+        let f_c2 = x * f_c1 - y * f_s1;
+        let f_s2 = x * f_s1 + y * f_c1;
+        sh[12] = z * (1.865881662950577 * z2 - 1.119528997770346);
+        sh[13] = f_tmp0c * x;
+        sh[11] = f_tmp0c * y;
+        sh[14] = f_tmp1b * f_c1;
+        sh[10] = f_tmp1b * f_s1;
+        sh[15] = f_tmp2a * f_c2;
+        sh[9] = f_tmp2a * f_s2;
+
+        let f_tmp0d = z * (-4.683325804901025 * z2 + 2.007139630671868);
+        let f_tmp1c = 3.31161143515146 * z2 - 0.47308734787878;
+        let f_tmp2b = -1.770130769779931 * z;
+        let f_tmp3a = 0.6258357354491763;
+        let f_c3 = x * f_c2 - y * f_s2;
+        let f_s3 = x * f_s2 + y * f_c2;
+        sh[20] = 1.984313483298443 * z * sh[12] - 1.006230589874905 * sh[6];
+        sh[21] = f_tmp0d * x;
+        sh[19] = f_tmp0d * y;
+        sh[22] = f_tmp1c * f_c1;
+        sh[18] = f_tmp1c * f_s1;
+        sh[23] = f_tmp2b * f_c2;
+        sh[17] = f_tmp2b * f_s2;
+        sh[24] = f_tmp3a * f_c3;
+        sh[16] = f_tmp3a * f_s3;
+
+        sh
+    }
+
+    // Roughly uniform directions on the sphere.
+    fn fibonacci_sphere(count: usize) -> Vec<Vec3> {
+        let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+        (0..count)
+            .map(|i| {
+                let z = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+                let r = (1.0 - z * z).sqrt();
+                let theta = golden_angle * i as f32;
+                Vec3::new(r * theta.cos(), r * theta.sin(), z)
+            })
+            .collect()
+    }
+
+    fn eval(c: &[f32], dir: Vec3) -> f32 {
+        let basis = sh_basis(dir.as_dvec3());
+        c.iter().zip(basis).map(|(c, b)| c * b as f32).sum()
+    }
+
+    #[test]
+    fn rotated_coeffs_match_rotated_directions() {
+        let rotation = Quat::from_euler(glam::EulerRot::XYZ, 0.3, -1.2, 2.1);
+        let degree = 4;
+        let n = sh_coeffs_for_degree(degree) as usize;
+        let mat = sh_rotation_matrix(rotation, degree);
+
+        let coeffs: Vec<f32> = (0..n).map(|i| ((i * 7 % 11) as f32 - 5.0) / 5.0).collect();
+        let rotated: Vec<f32> = (0..n)
+            .map(|k| (0..n).map(|m| coeffs[m] * mat[m * n + k]).sum())
+            .collect();
+
+        for dir in fibonacci_sphere(17) {
+            let expected = eval(&coeffs, rotation.inverse() * dir);
+            let actual = eval(&rotated, dir);
+            assert!(
+                (expected - actual).abs() < 1e-4,
+                "Mismatch at {dir}: {expected} vs {actual}"
+            );
+        }
+    }
+
+    #[test]
+    fn cpu_rotation_matches_matrix() {
+        let rotation = Quat::from_rotation_y(0.7);
+        let degree = 3;
+        let n = sh_coeffs_for_degree(degree) as usize;
+        let mat = sh_rotation_matrix(rotation, degree);
+
+        // Two splats, with different coefficients per channel.
+        let mut coeffs: Vec<f32> = (0..n * 6).map(|i| (i as f32 * 0.37).sin()).collect();
+        let original = coeffs.clone();
+        rotate_sh_coeffs(&mut coeffs, rotation, degree);
+
+        for splat in 0..2 {
+            for c in 0..3 {
+                let channel: Vec<f32> = (0..n).map(|m| original[(splat * n + m) * 3 + c]).collect();
+                for k in 0..n {
+                    let expected: f32 = (0..n).map(|m| channel[m] * mat[m * n + k]).sum();
+                    let actual = coeffs[(splat * n + k) * 3 + c];
+                    assert!((expected - actual).abs() < 1e-5);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+#import helpers
+
+@group(0) @binding(0) var<uniform> uniforms: helpers::RenderUniforms;
+@group(0) @binding(1) var<storage, read> compact_gid_from_isect: array<i32>;
+@group(0) @binding(2) var<storage, read> tile_offsets: array<i32>;
+@group(0) @binding(3) var<storage, read> projected_splats: array<helpers::ProjectedSplat>;
+@group(0) @binding(4) var<storage, read_write> tile_ends: array<i32>;
+
+// Pixels stop blending below a transmittance of 1e-4, leave some margin for rounding.
+const LOG_MIN_TRANSMITTANCE: f32 = -10.0;
+
+var<workgroup> local_log_t: array<f32, helpers::TILE_SIZE>;
+var<workgroup> batch_log_t: f32;
+var<workgroup> tile_end: atomic<i32>;
+
+// The smallest alpha of a splat over all pixels of a tile, or 0 if some pixels skip the splat.
+// The falloff of a gaussian is convex, so it's furthest along in one of the corners.
+fn min_alpha(projected: helpers::ProjectedSplat, tile_min: vec2f, tile_max: vec2f) -> f32 {
+    let xy = vec2f(projected.xy_x, projected.xy_y);
+    let conic = vec3f(projected.conic_x, projected.conic_y, projected.conic_z);
+
+    var max_sigma = 0.0;
+    for (var c = 0u; c < 4u; c++) {
+        let corner = select(tile_min, tile_max, vec2(c % 2u == 1u, c / 2u == 1u));
+        let delta = xy - corner;
+        let sigma = 0.5f * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y;
+        max_sigma = max(max_sigma, sigma);
+    }
+
+    let alpha = min(0.999f, projected.color_a * exp(-max_sigma));
+    if alpha < 1.0f / 255.0f {
+        return 0.0;
+    }
+    return alpha;
+}
+
+// Find the intersection after which all pixels of a tile are opaque, so the splats behind it
+// don't need to be rasterized. This is conservative: the transmittance of every pixel is at
+// most the product of (1 - min alpha) of the splats in front of it.
+@compute
+@workgroup_size(helpers::TILE_SIZE, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(local_invocation_index) local_idx: u32,
+) {
+    let tile_id = i32(workgroup_id.x) + i32(workgroup_id.y) * uniforms.tile_bounds.x;
+    let range = vec2i(tile_offsets[tile_id], tile_offsets[tile_id + 1]);
+
+    // Centers of the outermost pixels of the tile.
+    let tile_min = vec2f(workgroup_id.xy * helpers::TILE_WIDTH) + 0.5;
+    let tile_max = tile_min + f32(helpers::TILE_WIDTH - 1u);
+
+    if local_idx == 0u {
+        atomicStore(&tile_end, range.y);
+    }
+
+    // Log of the transmittance bound in front of the current batch.
+    var log_t = 0.0;
+    let num_batches = helpers::ceil_div(range.y - range.x, i32(helpers::TILE_SIZE));
+
+    for (var b = 0; b < num_batches; b++) {
+        let batch_start = range.x + b * i32(helpers::TILE_SIZE);
+        let remaining = min(i32(helpers::TILE_SIZE), range.y - batch_start);
+
+        var log_opacity = 0.0;
+        if i32(local_idx) < remaining {
+            let compact_gid = compact_gid_from_isect[batch_start + i32(local_idx)];
+            log_opacity = log(1.0 - min_alpha(projected_splats[compact_gid], tile_min, tile_max));
+        }
+        local_log_t[local_idx] = log_opacity;
+        workgroupBarrier();
+
+        // Inclusive prefix sum over the batch.
+        for (var offset = 1u; offset < helpers::TILE_SIZE; offset *= 2u) {
+            var add = 0.0;
+            if local_idx >= offset {
+                add = local_log_t[local_idx - offset];
+            }
+            workgroupBarrier();
+            local_log_t[local_idx] += add;
+            workgroupBarrier();
+        }
+
+        // Pixels stop at the first splat that makes them opaque, without blending it, so the
+        // tile can end there.
+        if i32(local_idx) < remaining && log_t + local_log_t[local_idx] <= LOG_MIN_TRANSMITTANCE {
+            atomicMin(&tile_end, batch_start + i32(local_idx));
+        }
+
+        if local_idx == helpers::TILE_SIZE - 1u {
+            batch_log_t = local_log_t[local_idx];
+        }
+        log_t += workgroupUniformLoad(&batch_log_t);
+
+        if log_t <= LOG_MIN_TRANSMITTANCE {
+            break;
+        }
+    }
+
+    workgroupBarrier();
+    if local_idx == 0u {
+        tile_ends[tile_id] = atomicLoad(&tile_end);
+    }
+}
@@ -1,14 +1,33 @@
+// The tile width is picked at runtime, see `render::set_tile_width`.
+#ifdef TILE_WIDTH
+const TILE_WIDTH: u32 = u32(#{TILE_WIDTH});
+#else
 const TILE_WIDTH: u32 = 16u;
-// Nb: TILE_SIZE should be <= 512 for max compatibility.
+#endif
+// Nb: TILE_SIZE should be <= 256 for max compatibility.
 const TILE_SIZE: u32 = TILE_WIDTH * TILE_WIDTH;
 
 const MAIN_WG: u32 = 256u;
 
+// Linear id of a thread in a 1D indirect dispatch. Big dispatches are spread over y, see
+// `create_dispatch_buffer`.
+fn dispatch_id(global_id: vec3u, num_wgs: vec3u, wg_size: u32) -> u32 {
+    return global_id.x + global_id.y * num_wgs.x * wg_size;
+}
+
 struct RenderUniforms {
     // View matrix transform world to view position.
     viewmat: mat4x4f,
+    // Camera space to clip space, only used with CUSTOM_PROJECTION.
+    projection: mat4x4f,
+    // World space to the [-1, 1] cube of the crop box, only used with CROP_BOX.
+    crop_box: mat4x4f,
     // Position of camera (xyz + pad)
     camera_position: vec4f,
+    // Lens distortion coefficients, see `CameraModel`.
+    distortion: vec4f,
+    // Constant background color (rgb + pad), composited behind the splats.
+    background: vec4f,
     // Focal of camera (fx, fy)
     focal: vec2f,
     // Img resolution (w, h)
@@ -16,6 +35,8 @@ struct RenderUniforms {
     tile_bounds: vec2i,
     // Camera center (cx, cy).
     pixel_center: vec2f,
+    // Size of the background texture (w, h), only used with BACKGROUND_TEXTURE.
+    background_size: vec2i,
     // Degree of sh coeffecients used.
     sh_degree: u32,
 #ifdef UNIFORM_WRITE
@@ -30,6 +51,12 @@ struct RenderUniforms {
     num_intersections: i32,
 #endif
     total_splats: u32,
+    // Splats with a lower opacity are skipped, only used with SOLID.
+    solid_threshold: f32,
+    // Splats are opaque up to this distance, as 0.5 * sigma^2, only used with SOLID.
+    solid_cutoff: f32,
+    // Time in seconds to move and fade the splats to, only used with TEMPORAL.
+    time: f32,
 }
 
 // nb: this struct has a bunch of padding but that's probably fine.
@@ -55,6 +82,25 @@ struct PackedVec3 {
     z: f32,
 }
 
+// The motion of a splat, see `TemporalAttributes`.
+struct TemporalSplat {
+    velocity_x: f32,
+    velocity_y: f32,
+    velocity_z: f32,
+    time: f32,
+    log_duration: f32,
+}
+
+fn temporal_velocity(temporal: TemporalSplat) -> vec3f {
+    return vec3f(temporal.velocity_x, temporal.velocity_y, temporal.velocity_z);
+}
+
+// How much a splat is faded out at `dt` seconds from its time.
+fn temporal_falloff(temporal: TemporalSplat, dt: f32) -> f32 {
+    let x = dt * exp(-temporal.log_duration);
+    return exp(-0.5 * x * x);
+}
+
 fn get_bbox(center: vec2f, dims: vec2f, bounds: vec2i) -> vec4i {
     // get bounding box with center and dims, within bounds
     // bounding box coords returned in tile coords, inclusive min, exclusive max
@@ -144,11 +190,90 @@ fn calc_cam_J(mean_c: vec3f, focal: vec2f, img_size: vec2i, pixel_center: vec2f)
     return J;
 }
 
-fn calc_cov2d(cov3d: mat3x3f, mean_c: vec3f, focal: vec2f, img_size: vec2i, pixel_center: vec2f, viewmat: mat4x4f) -> mat2x2f {
+// Distort normalized image coordinates. The camera model is chosen with the DISTORT_OPENCV and
+// DISTORT_FISHEYE defines, without either this is a pinhole camera.
+fn distort(uv: vec2f, params: vec4f) -> vec2f {
+    var out = uv;
+#ifdef DISTORT_OPENCV
+    // Radial-tangential distortion, params are (k1, k2, p1, p2).
+    let r2 = dot(uv, uv);
+    let radial = 1.0 + r2 * (params.x + r2 * params.y);
+    let xy = uv.x * uv.y;
+    out = uv * radial + vec2f(
+        2.0 * params.z * xy + params.w * (r2 + 2.0 * uv.x * uv.x),
+        params.z * (r2 + 2.0 * uv.y * uv.y) + 2.0 * params.w * xy,
+    );
+#endif
+#ifdef DISTORT_FISHEYE
+    // Equidistant fisheye, params are (k1, k2, k3, k4).
+    let r = length(uv);
+    if r > 1e-8 {
+        let theta = atan(r);
+        let t2 = theta * theta;
+        let theta_d = theta * (1.0 + t2 * (params.x + t2 * (params.y + t2 * (params.z + t2 * params.w))));
+        out = uv * (theta_d / r);
+    }
+#endif
+    return out;
+}
+
+// Project a camera space position to pixel coordinates.
+fn project_mean(mean_c: vec3f, focal: vec2f, img_size: vec2i, pixel_center: vec2f, distortion: vec4f, projection: mat4x4f) -> vec2f {
+#ifdef CUSTOM_PROJECTION
+    let clip = projection * vec4f(mean_c, 1.0);
+    return clip.xy / clip.w * 0.5 * vec2f(img_size) + pixel_center;
+#else
+    return focal * distort(mean_c.xy / mean_c.z, distortion) + pixel_center;
+#endif
+}
+
+// Jacobian of a custom projection matrix, from camera space to pixels.
+fn calc_projection_J(mean_c: vec3f, projection: mat4x4f, img_size: vec2i) -> mat3x2f {
+    let clip = projection * vec4f(mean_c, 1.0);
+    let ndc = clip.xy / clip.w;
+    let scale = 0.5 * vec2f(img_size) / clip.w;
+    return mat3x2f(
+        scale * (projection[0].xy - ndc * projection[0].w),
+        scale * (projection[1].xy - ndc * projection[1].w),
+        scale * (projection[2].xy - ndc * projection[2].w),
+    );
+}
+
+// Jacobian of the distortion in pixel space, at the projection of a camera space position.
+//
+// Splats are only gaussians after projection if the distortion is affine, so the distortion
+// is linearized around the splat center. The derivative is taken with central differences.
+fn distortion_jacobian(mean_c: vec3f, focal: vec2f, distortion: vec4f) -> mat2x2f {
+    var J = mat2x2f(vec2f(1.0, 0.0), vec2f(0.0, 1.0));
+    var distorted = false;
+#ifdef DISTORT_OPENCV
+    distorted = true;
+#endif
+#ifdef DISTORT_FISHEYE
+    distorted = true;
+#endif
+    if distorted {
+        let uv = mean_c.xy / mean_c.z;
+        let eps = 1e-3;
+        let dx = vec2f(eps, 0.0);
+        let dy = vec2f(0.0, eps);
+        let d_du = (distort(uv + dx, distortion) - distort(uv - dx, distortion)) / (2.0 * eps);
+        let d_dv = (distort(uv + dy, distortion) - distort(uv - dy, distortion)) / (2.0 * eps);
+        // Scale from normalized coordinates to pixels.
+        J = mat2x2f(d_du * focal / focal.x, d_dv * focal / focal.y);
+    }
+    return J;
+}
+
+fn calc_cov2d(cov3d: mat3x3f, mean_c: vec3f, focal: vec2f, img_size: vec2i, pixel_center: vec2f, viewmat: mat4x4f, distortion: vec4f, projection: mat4x4f) -> mat2x2f {
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let covar_cam = R * cov3d * transpose(R);
 
-    let J = calc_cam_J(mean_c, focal, img_size, pixel_center);
+#ifdef CUSTOM_PROJECTION
+    let J = calc_projection_J(mean_c, projection, img_size);
+#else
+    let J = distortion_jacobian(mean_c, focal, distortion) * calc_cam_J(mean_c, focal, img_size, pixel_center);
+#endif
 
     var cov2d = J * covar_cam * transpose(J);
 
@@ -283,6 +408,31 @@ fn as_packed(vec: vec3f) -> PackedVec3 {
     return PackedVec3(vec.x, vec.y, vec.z);
 }
 
+// The opacity of a splat faded by its temporal falloff, clamped so it stays a valid raw opacity.
+fn temporal_opacity(raw_opac: f32, falloff: f32) -> f32 {
+    return clamp(sigmoid(raw_opac) * falloff, 1e-6, 1.0 - 1e-6);
+}
+
+fn inverse_sigmoid(x: f32) -> f32 {
+    return log(x / (1.0 - x));
+}
+
 fn sigmoid(x: f32) -> f32 {
     return 1.0 / (1.0 + exp(-x));
 }
+
+const PI: f32 = 3.14159265358979;
+
+// The world space direction of the ray through a pixel, ignoring lens distortion.
+fn pixel_dir(pixel_coord: vec2f, focal: vec2f, pixel_center: vec2f, viewmat: mat4x4f) -> vec3f {
+    let dir_c = vec3f((pixel_coord - pixel_center) / focal, 1.0);
+    let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
+    return normalize(transpose(R) * dir_c);
+}
+
+// Where a direction lands on an equirectangular map, with +y pointing down like the camera.
+fn equirect_uv(dir: vec3f) -> vec2f {
+    let u = 0.5 + atan2(dir.x, dir.z) / (2.0 * PI);
+    let v = 0.5 + asin(clamp(dir.y, -1.0, 1.0)) / PI;
+    return vec2f(u, v);
+}
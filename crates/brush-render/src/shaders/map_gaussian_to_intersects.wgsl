@@ -14,10 +14,15 @@ struct IsectInfo {
 @group(0) @binding(4) var<storage, read_write> tile_id_from_isect: array<i32>;
 @group(0) @binding(5) var<storage, read_write> compact_gid_from_isect: array<i32>;
 
+const WG_SIZE: u32 = 512u;
+
 @compute
-@workgroup_size(512, 1, 1)
-fn main(@builtin(global_invocation_id) gid: vec3u) {
-    let total_id = i32(gid.x);
+@workgroup_size(WG_SIZE, 1, 1)
+fn main(
+    @builtin(global_invocation_id) gid: vec3u,
+    @builtin(num_workgroups) num_wgs: vec3u,
+) {
+    let total_id = i32(helpers::dispatch_id(gid, num_wgs, WG_SIZE));
 
     if total_id >= num_intersections {
         return;
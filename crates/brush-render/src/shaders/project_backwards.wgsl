@@ -6,15 +6,23 @@
 @group(0) @binding(1) var<storage, read> means: array<helpers::PackedVec3>;
 @group(0) @binding(2) var<storage, read> log_scales: array<helpers::PackedVec3>;
 @group(0) @binding(3) var<storage, read> quats: array<vec4f>;
+@group(0) @binding(4) var<storage, read> raw_opacities: array<f32>;
 
-@group(0) @binding(4) var<storage, read> global_from_compact_gid: array<i32>;
+@group(0) @binding(5) var<storage, read> global_from_compact_gid: array<i32>;
 
-@group(0) @binding(5) var<storage, read> v_xys: array<vec2f>;
-@group(0) @binding(6) var<storage, read> v_conics: array<helpers::PackedVec3>;
+@group(0) @binding(6) var<storage, read> v_xys: array<vec2f>;
+@group(0) @binding(7) var<storage, read> v_conics: array<helpers::PackedVec3>;
+@group(0) @binding(8) var<storage, read> v_colors: array<vec4f>;
 
-@group(0) @binding(7) var<storage, read_write> v_means: array<helpers::PackedVec3>;
-@group(0) @binding(8) var<storage, read_write> v_scales: array<helpers::PackedVec3>;
-@group(0) @binding(9) var<storage, read_write> v_quats: array<vec4f>;
+@group(0) @binding(9) var<storage, read_write> v_means: array<helpers::PackedVec3>;
+@group(0) @binding(10) var<storage, read_write> v_scales: array<helpers::PackedVec3>;
+@group(0) @binding(11) var<storage, read_write> v_quats: array<vec4f>;
+@group(0) @binding(12) var<storage, read_write> v_opacs: array<f32>;
+
+#ifdef TEMPORAL
+@group(0) @binding(13) var<storage, read> temporal: array<helpers::TemporalSplat>;
+@group(0) @binding(14) var<storage, read_write> v_temporal: array<helpers::TemporalSplat>;
+#endif
 
 
 fn normalize_vjp(quat: vec4f) -> mat4x4f {
@@ -69,6 +77,24 @@ fn inverse_vjp(Minv: mat2x2f, v_Minv: mat2x2f) -> mat2x2f {
     return mat2x2f(-Minv[0], -Minv[1]) * v_Minv * Minv;
 }
 
+// Gradient of the opacity compensation with respect to the (blurred) 2D covariance.
+fn cov_compensation_vjp(cov2d: mat2x2f, compensation: f32, v_compensation: f32) -> mat2x2f {
+    let conic = helpers::inverse(cov2d);
+    let det_conic = determinant(conic);
+    let v_sqr_comp = v_compensation * 0.5 / (compensation + 1e-6f);
+    let one_minus_sqr_comp = 1.0 - compensation * compensation;
+    return mat2x2f(
+        vec2f(
+            v_sqr_comp * (one_minus_sqr_comp * conic[0][0] - helpers::COV_BLUR * det_conic),
+            v_sqr_comp * one_minus_sqr_comp * conic[0][1],
+        ),
+        vec2f(
+            v_sqr_comp * one_minus_sqr_comp * conic[1][0],
+            v_sqr_comp * (one_minus_sqr_comp * conic[1][1] - helpers::COV_BLUR * det_conic),
+        ),
+    );
+}
+
 fn outer_product(a: vec3<f32>, b: vec3<f32>) -> mat3x3<f32> {
     return mat3x3f(
         a.x * b.x, a.x * b.y, a.x * b.z,
@@ -148,6 +174,37 @@ fn persp_proj_vjp(
     return v_mean3d;
 }
 
+// Like persp_proj_vjp, for a custom projection matrix.
+fn custom_proj_vjp(
+    J: mat3x2f,
+    // fwd inputs
+    mean3d: vec3f,
+    cov3d: mat3x3f,
+    projection: mat4x4f,
+    // grad outputs
+    v_cov2d: mat2x2f,
+    v_mean2d: vec2f,
+) -> vec3f {
+    var v_mean3d = transpose(J) * v_mean2d;
+
+    // The jacobian only changes with the position through the divide by w:
+    // dJ_ik/dx_j = -(J_ij * w_k + J_ik * w_j) / w, with w_k the w coefficients of the matrix.
+    var J_cols = J;
+    var v_J = v_cov2d * J * transpose(cov3d) + transpose(v_cov2d) * J * cov3d;
+    let w = (projection * vec4f(mean3d, 1.0)).w;
+    let w_coeffs = vec3f(projection[0].w, projection[1].w, projection[2].w);
+
+    for (var j = 0u; j < 3u; j++) {
+        var v = 0.0;
+        for (var k = 0u; k < 3u; k++) {
+            v += dot(v_J[k], J_cols[j] * w_coeffs[k] + J_cols[k] * w_coeffs[j]);
+        }
+        v_mean3d[j] -= v / w;
+    }
+
+    return v_mean3d;
+}
+
 @compute
 @workgroup_size(256, 1, 1)
 fn main(@builtin(global_invocation_id) gid: vec3u) {
@@ -162,7 +219,14 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let pixel_center = uniforms.pixel_center;
 
     let global_gid = global_from_compact_gid[compact_gid];
-    let mean = helpers::as_vec(means[global_gid]);
+    var mean = helpers::as_vec(means[global_gid]);
+#ifdef TEMPORAL
+    // The splat was projected where it is at the render time, see project_forward.
+    let motion = temporal[global_gid];
+    let dt = uniforms.time - motion.time;
+    let velocity = helpers::temporal_velocity(motion);
+    mean += velocity * dt;
+#endif
     let scale = exp(helpers::as_vec(log_scales[global_gid]));
     let quat_unorm = quats[global_gid];
     let quat = normalize(quat_unorm);
@@ -180,23 +244,57 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let M = rotmat * S;
 
     let covar = M * transpose(M);
-    let cov2d = helpers::calc_cov2d(covar, mean_c, focal, img_size, pixel_center, viewmat);
+    let cov2d = helpers::calc_cov2d(covar, mean_c, focal, img_size, pixel_center, viewmat, uniforms.distortion, uniforms.projection);
     let covar2d_inv = helpers::inverse(cov2d);
 
     let v_covar2d_inv = mat2x2f(vec2f(v_conics.x, v_conics.y * 0.5f), vec2f(v_conics.y * 0.5f, v_conics.z));
 
-    let v_covar2d = inverse_vjp(covar2d_inv, v_covar2d_inv);
+    var v_covar2d = inverse_vjp(covar2d_inv, v_covar2d_inv);
+
+    // Transform alpha gradient to opacity gradient.
+    let raw_opac = raw_opacities[global_gid];
+    let v_alpha = v_colors[compact_gid].w;
+#ifdef TEMPORAL
+    // The splat is faded by its falloff, the raw opacity gets its gradient below.
+    let falloff = helpers::temporal_falloff(motion, dt);
+    let opac = helpers::temporal_opacity(raw_opac, falloff);
+    var v_opac = v_alpha;
+#else
+    let opac = helpers::sigmoid(raw_opac);
+    var v_opac = v_alpha * opac * (1.0 - opac);
+#endif
+
+#ifdef ANTIALIAS
+    // The rendered alpha is the opacity scaled by the compensation, which depends on the
+    // 2D covariance.
+    let compensation = helpers::cov_compensation(vec3f(cov2d[0][0], cov2d[0][1], cov2d[1][1]));
+    v_opac *= compensation;
+    v_covar2d += cov_compensation_vjp(cov2d, compensation, v_alpha * opac);
+#endif
 
     // covar_world_to_cam
     let covar_c = R * covar * transpose(R);
 
+#ifdef CUSTOM_PROJECTION
+    let J = helpers::calc_projection_J(mean_c, uniforms.projection, img_size);
+    let v_mean_c = custom_proj_vjp(J, mean_c, covar_c, uniforms.projection, v_covar2d, v_mean2d);
+    let v_covar_c = transpose(J) * v_covar2d * J;
+#else
+    // Take the gradients back through the lens distortion, to the pinhole projection. The
+    // distortion is linearized around the splat center, so how its jacobian changes with the
+    // splat position is ignored.
+    let J_dist = helpers::distortion_jacobian(mean_c, focal, uniforms.distortion);
+    let v_covar2d_pinhole = transpose(J_dist) * v_covar2d * J_dist;
+    let v_mean2d_pinhole = transpose(J_dist) * v_mean2d;
+
     // persp_proj_vjp
     let J = helpers::calc_cam_J(mean_c, focal, img_size, pixel_center);
-    let v_mean_c = persp_proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d, v_mean2d);
+    let v_mean_c = persp_proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d_pinhole, v_mean2d_pinhole);
     // cov = J * V * Jt; G = df/dcov = v_cov
     // -> df/dV = Jt * G * J
     // -> df/dJ = G * J * Vt + Gt * J * V
-    let v_covar_c = transpose(J) * v_covar2d * J;
+    let v_covar_c = transpose(J) * v_covar2d_pinhole * J;
+#endif
 
     // df/dx = -fx * rz2 * df/dJ_02
     // df/dy = -fy * rz2 * df/dJ_12
@@ -237,7 +335,34 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     // grad for (quat, scale) from covar
     let v_quat = normalize_vjp(quat_unorm) * quat_to_mat_vjp(quat, v_M * S);
 
+#ifdef TEMPORAL
+    // The faded opacity is clamped, which has no gradient where the clamp is hit.
+    let sig = helpers::sigmoid(raw_opac);
+    let faded = sig * falloff;
+    if faded <= 1e-6 || faded >= 1.0 - 1e-6 {
+        v_opac = 0.0;
+    }
+    let v_falloff = v_opac * sig;
+    v_opac *= falloff * sig * (1.0 - sig);
+
+    // falloff = exp(-0.5 * x^2), with x = dt / duration.
+    let inv_duration = exp(-motion.log_duration);
+    let x = dt * inv_duration;
+    let v_x = -v_falloff * falloff * x;
+    // mean_t = mean + velocity * dt, with dt = time - splat time.
+    let v_dt = v_x * inv_duration + dot(v_mean, velocity);
+    let v_velocity = v_mean * dt;
+    v_temporal[global_gid] = helpers::TemporalSplat(
+        v_velocity.x,
+        v_velocity.y,
+        v_velocity.z,
+        -v_dt,
+        -v_x * x,
+    );
+#endif
+
     v_means[global_gid] = helpers::as_packed(v_mean);
     v_scales[global_gid] = helpers::as_packed(v_scale_exp);
     v_quats[global_gid] = v_quat;
+    v_opacs[global_gid] = v_opac;
 }
@@ -15,6 +15,13 @@
 
 @group(0) @binding(7) var<storage, read_write> radii: array<f32>;
 
+#ifdef TEMPORAL
+@group(0) @binding(8) var<storage, read> temporal: array<helpers::TemporalSplat>;
+// The means and raw opacities at the render time, which the later kernels read instead.
+@group(0) @binding(9) var<storage, read_write> means_t: array<helpers::PackedVec3>;
+@group(0) @binding(10) var<storage, read_write> raw_opacities_t: array<f32>;
+#endif
+
 @compute
 @workgroup_size(helpers::MAIN_WG, 1, 1)
 fn main(@builtin(global_invocation_id) global_id: vec3u) {
@@ -24,9 +31,29 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
         return;
     }
 
-    // Project world space to camera space.
-    let mean = helpers::as_vec(means[global_gid]);
+    var mean = helpers::as_vec(means[global_gid]);
+    var raw_opac = raw_opacities[global_gid];
+
+#ifdef TEMPORAL
+    // Move and fade the splat to where it is at the render time.
+    let motion = temporal[global_gid];
+    let dt = uniforms.time - motion.time;
+    mean += helpers::temporal_velocity(motion) * dt;
+    raw_opac = helpers::inverse_sigmoid(
+        helpers::temporal_opacity(raw_opac, helpers::temporal_falloff(motion, dt))
+    );
+    means_t[global_gid] = helpers::as_packed(mean);
+    raw_opacities_t[global_gid] = raw_opac;
+#endif
+
+#ifdef CROP_BOX
+    let mean_box = (uniforms.crop_box * vec4f(mean, 1.0)).xyz;
+    if any(abs(mean_box) > vec3f(1.0)) {
+        return;
+    }
+#endif
 
+    // Project world space to camera space.
     let img_size = uniforms.img_size;
     let viewmat = uniforms.viewmat;
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
@@ -38,15 +65,20 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
 
     let scale = exp(helpers::as_vec(log_scales[global_gid]));
     let quat = normalize(quats[global_gid]);
-    let raw_opac = raw_opacities[global_gid];
 
     // inv_sigmoid(1.0 / 255.0);
     if raw_opac <= -5.537 {
         return;
     }
 
+#ifdef SOLID
+    if helpers::sigmoid(raw_opac) < uniforms.solid_threshold {
+        return;
+    }
+#endif
+
     let cov3d = helpers::calc_cov3d(scale, quat);
-    let cov2d = helpers::calc_cov2d(cov3d, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat);
+    let cov2d = helpers::calc_cov2d(cov3d, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat, uniforms.distortion, uniforms.projection);
     let det = determinant(cov2d);
 
     if det <= 0.0 {
@@ -57,9 +89,14 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     let conic = helpers::inverse(cov2d);
 
     // compute the projected mean
-    let mean2d = uniforms.focal * mean_c.xy * (1.0 / mean_c.z) + uniforms.pixel_center;
-
-    let opac = helpers::sigmoid(raw_opac);
+    let mean2d = helpers::project_mean(mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, uniforms.distortion, uniforms.projection);
+
+    var opac = helpers::sigmoid(raw_opac);
+#ifdef ANTIALIAS
+    // Scale the opacity down by how much the blur grew the splat, so the blur doesn't make
+    // splats that are small on screen look bigger.
+    opac *= helpers::cov_compensation(vec3f(cov2d[0][0], cov2d[0][1], cov2d[1][1]));
+#endif
     let radius = helpers::radius_from_cov(cov2d, opac);
 
     if radius <= 0 {
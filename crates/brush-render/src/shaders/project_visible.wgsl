@@ -56,17 +56,15 @@ struct ShCoeffs {
 // Evaluate spherical harmonics bases at unit direction for high orders using approach described by
 // Efficient Spherical Harmonic Evaluation, Peter-Pike Sloan, JCGT 2013
 // See https://jcgt.org/published/0002/02/06/ for reference implementation
+//
+// The SH degree is a kernel specialization constant, so unused bands are compiled out.
 fn sh_coeffs_to_color(
-    degree: u32,
     viewdir: vec3f,
     sh: ShCoeffs,
 ) -> vec3f {
     var colors = 0.2820947917738781f * sh.b0_c0;
 
-    if (degree == 0) {
-        return colors;
-    }
-
+#if SH_DEGREE >= 1
     let x = viewdir.x;
     let y = viewdir.y;
     let z = viewdir.z;
@@ -77,9 +75,7 @@ fn sh_coeffs_to_color(
                     z * sh.b1_c1 -
                     x * sh.b1_c2);
 
-    if (degree == 1) {
-        return colors;
-    }
+#if SH_DEGREE >= 2
     let z2 = z * z;
 
     let fTmp0B = -1.092548430592079 * z;
@@ -99,9 +95,7 @@ fn sh_coeffs_to_color(
         pSH7 * sh.b2_c3 +
         pSH8 * sh.b2_c4;
 
-    if (degree == 2) {
-        return colors;
-    }
+#if SH_DEGREE >= 3
 
     let fTmp0C = -2.285228997322329f * z2 + 0.4570457994644658f;
     let fTmp1B = 1.445305721320277f * z;
@@ -123,9 +117,7 @@ fn sh_coeffs_to_color(
                 pSH14 * sh.b3_c5 +
                 pSH15 * sh.b3_c6;
 
-    if (degree == 3) {
-        return colors;
-    }
+#if SH_DEGREE >= 4
 
     let fTmp0D = z * (-4.683325804901025f * z2 + 2.007139630671868f);
     let fTmp1C = 3.31161143515146f * z2 - 0.47308734787878f;
@@ -151,6 +143,10 @@ fn sh_coeffs_to_color(
                 pSH22 * sh.b4_c6 +
                 pSH23 * sh.b4_c7 +
                 pSH24 * sh.b4_c8;
+#endif
+#endif
+#endif
+#endif
     return colors;
 }
 
@@ -166,8 +162,11 @@ fn read_coeffs(base_id: ptr<function, u32>) -> vec3f {
 
 @compute
 @workgroup_size(helpers::MAIN_WG, 1, 1)
-fn main(@builtin(global_invocation_id) gid: vec3u) {
-    let compact_gid = i32(gid.x);
+fn main(
+    @builtin(global_invocation_id) gid: vec3u,
+    @builtin(num_workgroups) num_wgs: vec3u,
+) {
+    let compact_gid = i32(helpers::dispatch_id(gid, num_wgs, helpers::MAIN_WG));
 
     if compact_gid >= uniforms.num_visible {
         return;
@@ -179,19 +178,29 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let mean = helpers::as_vec(means[global_gid]);
     let scale = exp(helpers::as_vec(log_scales[global_gid]));
     let quat = normalize(quats[global_gid]);
-    let opac = helpers::sigmoid(raw_opacities[global_gid]);
+    var opac = helpers::sigmoid(raw_opacities[global_gid]);
 
     let viewmat = uniforms.viewmat;
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let mean_c = R * mean + viewmat[3].xyz;
 
     let covar = helpers::calc_cov3d(scale, quat);
-    let cov2d = helpers::calc_cov2d(covar, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat);
+    let cov2d = helpers::calc_cov2d(covar, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat, uniforms.distortion, uniforms.projection);
     let conic = helpers::inverse(cov2d);
 
+#ifdef ANTIALIAS
+    // Scale the opacity down by how much the blur grew the splat, so the blur doesn't make
+    // splats that are small on screen look bigger.
+    opac *= helpers::cov_compensation(vec3f(cov2d[0][0], cov2d[0][1], cov2d[1][1]));
+#endif
+
+#ifdef SOLID
+    // Solid splats are opaque, up to the cutoff that the rasterizer hardens their edges at.
+    opac = 1.0;
+#endif
+
     // compute the projected mean
-    let rz = 1.0 / mean_c.z;
-    let mean2d = uniforms.focal * mean_c.xy * rz + uniforms.pixel_center;
+    let mean2d = helpers::project_mean(mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, uniforms.distortion, uniforms.projection);
 
     let sh_degree = uniforms.sh_degree;
     let num_coeffs = num_sh_coeffs(sh_degree);
@@ -200,45 +209,45 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     var sh = ShCoeffs();
     sh.b0_c0 = read_coeffs(&base_id);
 
-    if sh_degree >= 1 {
-        sh.b1_c0 = read_coeffs(&base_id);
-        sh.b1_c1 = read_coeffs(&base_id);
-        sh.b1_c2 = read_coeffs(&base_id);
-
-        if sh_degree >= 2 {
-            sh.b2_c0 = read_coeffs(&base_id);
-            sh.b2_c1 = read_coeffs(&base_id);
-            sh.b2_c2 = read_coeffs(&base_id);
-            sh.b2_c3 = read_coeffs(&base_id);
-            sh.b2_c4 = read_coeffs(&base_id);
-
-            if sh_degree >= 3 {
-                sh.b3_c0 = read_coeffs(&base_id);
-                sh.b3_c1 = read_coeffs(&base_id);
-                sh.b3_c2 = read_coeffs(&base_id);
-                sh.b3_c3 = read_coeffs(&base_id);
-                sh.b3_c4 = read_coeffs(&base_id);
-                sh.b3_c5 = read_coeffs(&base_id);
-                sh.b3_c6 = read_coeffs(&base_id);
-
-                if sh_degree >= 4 {
-                    sh.b4_c0 = read_coeffs(&base_id);
-                    sh.b4_c1 = read_coeffs(&base_id);
-                    sh.b4_c2 = read_coeffs(&base_id);
-                    sh.b4_c3 = read_coeffs(&base_id);
-                    sh.b4_c4 = read_coeffs(&base_id);
-                    sh.b4_c5 = read_coeffs(&base_id);
-                    sh.b4_c6 = read_coeffs(&base_id);
-                    sh.b4_c7 = read_coeffs(&base_id);
-                    sh.b4_c8 = read_coeffs(&base_id);
-                }
-            }
-        }
-    }
+#if SH_DEGREE >= 1
+    sh.b1_c0 = read_coeffs(&base_id);
+    sh.b1_c1 = read_coeffs(&base_id);
+    sh.b1_c2 = read_coeffs(&base_id);
+#endif
+
+#if SH_DEGREE >= 2
+    sh.b2_c0 = read_coeffs(&base_id);
+    sh.b2_c1 = read_coeffs(&base_id);
+    sh.b2_c2 = read_coeffs(&base_id);
+    sh.b2_c3 = read_coeffs(&base_id);
+    sh.b2_c4 = read_coeffs(&base_id);
+#endif
+
+#if SH_DEGREE >= 3
+    sh.b3_c0 = read_coeffs(&base_id);
+    sh.b3_c1 = read_coeffs(&base_id);
+    sh.b3_c2 = read_coeffs(&base_id);
+    sh.b3_c3 = read_coeffs(&base_id);
+    sh.b3_c4 = read_coeffs(&base_id);
+    sh.b3_c5 = read_coeffs(&base_id);
+    sh.b3_c6 = read_coeffs(&base_id);
+#endif
+
+#if SH_DEGREE >= 4
+    sh.b4_c0 = read_coeffs(&base_id);
+    sh.b4_c1 = read_coeffs(&base_id);
+    sh.b4_c2 = read_coeffs(&base_id);
+    sh.b4_c3 = read_coeffs(&base_id);
+    sh.b4_c4 = read_coeffs(&base_id);
+    sh.b4_c5 = read_coeffs(&base_id);
+    sh.b4_c6 = read_coeffs(&base_id);
+    sh.b4_c7 = read_coeffs(&base_id);
+    sh.b4_c8 = read_coeffs(&base_id);
+#endif
 
     let viewdir = normalize(mean - uniforms.camera_position.xyz);
 
-    var color = sh_coeffs_to_color(sh_degree, viewdir, sh) + vec3f(0.5);
+    var color = sh_coeffs_to_color(viewdir, sh) + vec3f(0.5);
 
     projected[compact_gid] = helpers::create_projected_splat(
         mean2d,
@@ -256,12 +265,16 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     for (var ty = tile_min.y; ty < tile_max.y; ty++) {
         for (var tx = tile_min.x; tx < tile_max.x; tx++) {
             if helpers::can_be_visible(vec2i(tx, ty), mean2d, conic, opac) {
-                // Add to the tile hit count.
-                num_tiles_hit += 1;
+                // The counter keeps counting when the intersection buffer is full, so it's
+                // clear how many intersections there would have been.
                 let isect_id = atomicAdd(&uniforms.num_intersections, 1);
 
-                let tile_id = tx + ty * uniforms.tile_bounds.x; // tile within image
-                isect_info[isect_id] = IsectInfo(compact_gid, tile_id);
+                if u32(isect_id) < arrayLength(&isect_info) {
+                    // Add to the tile hit count.
+                    num_tiles_hit += 1;
+                    let tile_id = tx + ty * uniforms.tile_bounds.x; // tile within image
+                    isect_info[isect_id] = IsectInfo(compact_gid, tile_id);
+                }
             }
         }
     }
@@ -13,6 +13,99 @@
 
 @group(0) @binding(5) var<storage, read_write> final_index : array<i32>;
 
+#ifdef RENDER_DEPTH
+    @group(0) @binding(6) var<storage, read> global_from_compact_gid: array<i32>;
+    @group(0) @binding(7) var<storage, read> means: array<helpers::PackedVec3>;
+    @group(0) @binding(8) var<storage, read> log_scales: array<helpers::PackedVec3>;
+    @group(0) @binding(9) var<storage, read> quats: array<vec4f>;
+    @group(0) @binding(10) var<storage, read_write> out_depth: array<f32>;
+    @group(0) @binding(11) var<storage, read_write> out_normals: array<helpers::PackedVec3>;
+
+    // Camera space depth (x) and normal (yzw) of the splats in the batch.
+    var<workgroup> local_depth_normal: array<vec4f, helpers::TILE_SIZE>;
+
+    // The camera space depth and normal of a splat. The normal is the shortest axis of the splat,
+    // facing the camera.
+    fn splat_depth_normal(compact_gid: i32) -> vec4f {
+        let global_gid = global_from_compact_gid[compact_gid];
+        let viewmat = uniforms.viewmat;
+        let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
+        let mean_c = R * helpers::as_vec(means[global_gid]) + viewmat[3].xyz;
+
+        let scale = helpers::as_vec(log_scales[global_gid]);
+        let axes = R * helpers::quat_to_mat(normalize(quats[global_gid]));
+        var normal = axes[0];
+        if scale.y < scale.x && scale.y <= scale.z {
+            normal = axes[1];
+        } else if scale.z < scale.x && scale.z < scale.y {
+            normal = axes[2];
+        }
+        if dot(normal, mean_c) > 0.0 {
+            normal = -normal;
+        }
+        return vec4f(mean_c.z, normal);
+    }
+#endif
+
+#ifdef BACKGROUND_TEXTURE
+    #ifdef RENDER_DEPTH
+        @group(0) @binding(12) var<storage, read> background: array<helpers::PackedVec3>;
+    #else
+        @group(0) @binding(6) var<storage, read> background: array<helpers::PackedVec3>;
+    #endif
+#endif
+
+// Where the splats of every tile stop contributing, see cull_tiles.wgsl.
+#ifdef OCCLUSION_CULL
+    #ifdef RENDER_DEPTH
+        #ifdef BACKGROUND_TEXTURE
+            @group(0) @binding(13) var<storage, read> tile_ends: array<i32>;
+        #else
+            @group(0) @binding(12) var<storage, read> tile_ends: array<i32>;
+        #endif
+    #else
+        #ifdef BACKGROUND_TEXTURE
+            @group(0) @binding(7) var<storage, read> tile_ends: array<i32>;
+        #else
+            @group(0) @binding(6) var<storage, read> tile_ends: array<i32>;
+        #endif
+    #endif
+#endif
+
+#ifdef BACKGROUND_TEXTURE
+    fn background_texel(texel: vec2i) -> vec3f {
+        let size = uniforms.background_size;
+    #ifdef BACKGROUND_ENVIRONMENT
+        // Environment maps wrap around horizontally.
+        let x = ((texel.x % size.x) + size.x) % size.x;
+    #else
+        let x = clamp(texel.x, 0, size.x - 1);
+    #endif
+        let y = clamp(texel.y, 0, size.y - 1);
+        return helpers::as_vec(background[x + y * size.x]);
+    }
+#endif
+
+// The background behind a pixel, bilinearly sampled from the background texture if any.
+fn background_color(pixel_coord: vec2f) -> vec3f {
+#ifdef BACKGROUND_TEXTURE
+    #ifdef BACKGROUND_ENVIRONMENT
+        let dir = helpers::pixel_dir(pixel_coord, uniforms.focal, uniforms.pixel_center, uniforms.viewmat);
+        let uv = helpers::equirect_uv(dir);
+    #else
+        let uv = pixel_coord / vec2f(uniforms.img_size);
+    #endif
+    let pos = uv * vec2f(uniforms.background_size) - 0.5;
+    let texel = vec2i(floor(pos));
+    let f = pos - floor(pos);
+    let top = mix(background_texel(texel), background_texel(texel + vec2i(1, 0)), f.x);
+    let bottom = mix(background_texel(texel + vec2i(0, 1)), background_texel(texel + vec2i(1, 1)), f.x);
+    return mix(top, bottom, f.y);
+#else
+    return uniforms.background.rgb;
+#endif
+}
+
 var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
 
 // kernel function for rasterizing each tile
@@ -39,12 +132,19 @@ fn main(
 
     // have all threads in tile process the same gaussians in batches
     // first collect gaussians between the bin counts.
-    let range = vec2i(tile_offsets[tile_id], tile_offsets[tile_id + 1]);
+    var range = vec2i(tile_offsets[tile_id], tile_offsets[tile_id + 1]);
+#ifdef OCCLUSION_CULL
+    range.y = min(range.y, tile_ends[tile_id]);
+#endif
 
     let num_batches = helpers::ceil_div(range.y - range.x, i32(helpers::TILE_SIZE));
     // current visibility left to render
     var T = 1.0;
     var pix_out = vec3f(0.0);
+#ifdef RENDER_DEPTH
+    var depth_out = 0.0;
+    var normal_out = vec3f(0.0);
+#endif
 
     // collect and process batches of gaussians
     // each thread loads one gaussian at a time before rasterizing its
@@ -65,7 +165,11 @@ fn main(
 
         if i32(local_idx) < remaining {
             let load_isect_id = batch_start + i32(local_idx);
-            local_batch[local_idx] = projected_splats[compact_gid_from_isect[load_isect_id]];
+            let compact_gid = compact_gid_from_isect[load_isect_id];
+            local_batch[local_idx] = projected_splats[compact_gid];
+#ifdef RENDER_DEPTH
+            local_depth_normal[local_idx] = splat_depth_normal(compact_gid);
+#endif
         }
         // Wait for all writes to complete.
         workgroupBarrier();
@@ -79,7 +183,12 @@ fn main(
 
             let delta = xy - pixel_coord;
             let sigma = 0.5f * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y;
+#ifdef SOLID
+            // Hard edged splats, fully opaque up to the cutoff.
+            let alpha = select(0.0f, 0.999f, sigma <= uniforms.solid_cutoff);
+#else
             let alpha = min(0.999f, color.a * exp(-sigma));
+#endif
 
             if (sigma < 0.0f || alpha < 1.0f / 255.0f) {
                 continue;
@@ -95,6 +204,11 @@ fn main(
             let vis = alpha * T;
             let clamped_rgb = max(color.rgb, vec3f(0.0));
             pix_out += clamped_rgb * vis;
+#ifdef RENDER_DEPTH
+            let depth_normal = local_depth_normal[t];
+            depth_out += depth_normal.x * vis;
+            normal_out += depth_normal.yzw * vis;
+#endif
             T = next_T;
 
             let isect_id = batch_start + t;
@@ -104,7 +218,8 @@ fn main(
 
     if inside {
         let img_alpha = (1.0 - T);
-        let final_color = vec4f(pix_out, img_alpha);
+        // The alpha is kept as the coverage of the splats, which the backward pass relies on.
+        let final_color = vec4f(pix_out + T * background_color(pixel_coord), img_alpha);
         #ifdef RASTER_U32
             let colors_u = vec4u(clamp(final_color * 255.0, vec4f(0.0), vec4f(255.0)));
             let packed: u32 = colors_u.x | (colors_u.y << 8u) | (colors_u.z << 16u) | (colors_u.w << 24u);
@@ -114,5 +229,20 @@ fn main(
             out_img[pix_id] = final_color;
             final_index[pix_id] = final_idx;
         #endif
+
+        #ifdef RENDER_DEPTH
+            // Normalize by the alpha, so the depth is the weighted mean depth of the splats.
+            var depth = 0.0;
+            if img_alpha > 1e-6 {
+                depth = depth_out / img_alpha;
+            }
+            out_depth[pix_id] = depth;
+
+            var normal = vec3f(0.0);
+            if length(normal_out) > 1e-6 {
+                normal = normalize(normal_out);
+            }
+            out_normals[pix_id] = helpers::PackedVec3(normal.x, normal.y, normal.z);
+        #endif
     }
 }
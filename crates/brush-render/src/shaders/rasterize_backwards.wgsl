@@ -11,16 +11,80 @@
 @group(0) @binding(5) var<storage, read> output: array<vec4f>;
 @group(0) @binding(6) var<storage, read> v_output: array<vec4f>;
 
+#ifdef DETERMINISTIC
+    // The gradient of every intersection, summed per splat by sum_isect_grads.
+    @group(0) @binding(7) var<storage, read_write> v_isect: array<helpers::ProjectedSplat>;
+#ifdef ABSGRAD
+    @group(0) @binding(8) var<storage, read_write> v_isect_abs: array<vec2f>;
+#endif
+#else
 #ifdef HARD_FLOAT
     @group(0) @binding(7) var<storage, read_write> v_xy: array<atomic<f32>>;
     @group(0) @binding(8) var<storage, read_write> v_conics: array<atomic<f32>>;
     @group(0) @binding(9) var<storage, read_write> v_colors: array<atomic<f32>>;
+#ifdef ABSGRAD
+    @group(0) @binding(10) var<storage, read_write> v_xy_abs: array<atomic<f32>>;
+#endif
 #else
     @group(0) @binding(7) var<storage, read_write> v_xy: array<atomic<u32>>;
     @group(0) @binding(8) var<storage, read_write> v_conics: array<atomic<u32>>;
     @group(0) @binding(9) var<storage, read_write> v_colors: array<atomic<u32>>;
+#ifdef ABSGRAD
+    @group(0) @binding(10) var<storage, read_write> v_xy_abs: array<atomic<u32>>;
+#endif
+#endif
+#endif
+
+// The background texture comes after the gradient buffers.
+#ifdef BACKGROUND_TEXTURE
+#ifdef DETERMINISTIC
+#ifdef ABSGRAD
+    @group(0) @binding(9) var<storage, read> background: array<helpers::PackedVec3>;
+#else
+    @group(0) @binding(8) var<storage, read> background: array<helpers::PackedVec3>;
+#endif
+#else
+#ifdef ABSGRAD
+    @group(0) @binding(11) var<storage, read> background: array<helpers::PackedVec3>;
+#else
+    @group(0) @binding(10) var<storage, read> background: array<helpers::PackedVec3>;
+#endif
+#endif
 #endif
 
+#ifdef BACKGROUND_TEXTURE
+    fn background_texel(texel: vec2i) -> vec3f {
+        let size = uniforms.background_size;
+    #ifdef BACKGROUND_ENVIRONMENT
+        // Environment maps wrap around horizontally.
+        let x = ((texel.x % size.x) + size.x) % size.x;
+    #else
+        let x = clamp(texel.x, 0, size.x - 1);
+    #endif
+        let y = clamp(texel.y, 0, size.y - 1);
+        return helpers::as_vec(background[x + y * size.x]);
+    }
+#endif
+
+// The background behind a pixel, bilinearly sampled from the background texture if any.
+fn background_color(pixel_coord: vec2f) -> vec3f {
+#ifdef BACKGROUND_TEXTURE
+    #ifdef BACKGROUND_ENVIRONMENT
+        let dir = helpers::pixel_dir(pixel_coord, uniforms.focal, uniforms.pixel_center, uniforms.viewmat);
+        let uv = helpers::equirect_uv(dir);
+    #else
+        let uv = pixel_coord / vec2f(uniforms.img_size);
+    #endif
+    let pos = uv * vec2f(uniforms.background_size) - 0.5;
+    let texel = vec2i(floor(pos));
+    let f = pos - floor(pos);
+    let top = mix(background_texel(texel), background_texel(texel + vec2i(1, 0)), f.x);
+    let bottom = mix(background_texel(texel + vec2i(0, 1)), background_texel(texel + vec2i(1, 1)), f.x);
+    return mix(top, bottom, f.y);
+#else
+    return uniforms.background.rgb;
+#endif
+}
 
 const BATCH_SIZE = helpers::TILE_SIZE;
 
@@ -38,7 +102,19 @@ var<workgroup> local_id: array<i32, BATCH_SIZE>;
 var<workgroup> grad_count: atomic<i32>;
 var<workgroup> gather_grads: array<helpers::ProjectedSplat, BATCH_SIZE>;
 var<workgroup> gather_grad_id: array<i32, BATCH_SIZE>;
+#ifdef ABSGRAD
+var<workgroup> gather_abs_grads: array<vec2f, BATCH_SIZE>;
+#endif
 
+#ifdef DETERMINISTIC
+fn add_grads(a: helpers::ProjectedSplat, b: helpers::ProjectedSplat) -> helpers::ProjectedSplat {
+    return helpers::create_projected_splat(
+        vec2f(a.xy_x, a.xy_y) + vec2f(b.xy_x, b.xy_y),
+        vec3f(a.conic_x, a.conic_y, a.conic_z) + vec3f(b.conic_x, b.conic_y, b.conic_z),
+        vec4f(a.color_r, a.color_g, a.color_b, a.color_a) + vec4f(b.color_r, b.color_g, b.color_b, b.color_a),
+    );
+}
+#else
 fn add_bitcast(cur: u32, add: f32) -> u32 {
     return bitcast<u32>(bitcast<f32>(cur) + add);
 }
@@ -133,6 +209,27 @@ fn write_grads_atomic(grads: helpers::ProjectedSplat, id: i32) {
 #endif
 }
 
+#ifdef ABSGRAD
+fn write_abs_grads_atomic(xy_abs: vec2f, id: i32) {
+#ifdef HARD_FLOAT
+    atomicAdd(&v_xy_abs[id * 2 + 0], xy_abs.x);
+    atomicAdd(&v_xy_abs[id * 2 + 1], xy_abs.y);
+#else
+    var old_value = atomicLoad(&v_xy_abs[id * 2 + 0]);
+    loop {
+        let cas = atomicCompareExchangeWeak(&v_xy_abs[id * 2 + 0], old_value, add_bitcast(old_value, xy_abs.x));
+        if cas.exchanged { break; } else { old_value = cas.old_value; }
+    }
+    old_value = atomicLoad(&v_xy_abs[id * 2 + 1]);
+    loop {
+        let cas = atomicCompareExchangeWeak(&v_xy_abs[id * 2 + 1], old_value, add_bitcast(old_value, xy_abs.y));
+        if cas.exchanged { break; } else { old_value = cas.old_value; }
+    }
+#endif
+}
+#endif
+#endif
+
 // kernel function for rasterizing each tile
 // each thread treats a single pixel
 // each thread group uses the same gaussian data in a tile
@@ -173,7 +270,12 @@ fn main(
     var T = T_final;
 
     var final_isect = 0;
+    // The color behind the current splat. The background acts like a final opaque splat, no
+    // gradients flow to it.
     var buffer = vec3f(0.0);
+    if inside {
+        buffer = T_final * background_color(pixel_coord);
+    }
 
     if inside {
         final_isect = final_index[pix_id];
@@ -275,12 +377,36 @@ fn main(
                     }
                 }
 
+#ifdef DETERMINISTIC
+                // Every subgroup writes its sum to a fixed slot, to be summed in a fixed order.
+                let v_xy_sum = subgroupAdd(v_xy);
+                let v_conic_sum = subgroupAdd(v_conic);
+                let v_colors_sum = subgroupAdd(v_colors);
+#ifdef ABSGRAD
+                // The absolute gradient of every pixel, so they don't cancel out.
+                let v_xy_abs_sum = subgroupAdd(abs(v_xy));
+#endif
+                if subgroup_invocation_id == 0 {
+                    let slot = tt * sg_per_tile + i32(local_idx / subgroup_size);
+                    gather_grads[slot] = helpers::create_projected_splat(
+                        v_xy_sum,
+                        v_conic_sum,
+                        v_colors_sum
+                    );
+#ifdef ABSGRAD
+                    gather_abs_grads[slot] = v_xy_abs_sum;
+#endif
+                }
+#else
                 // Queue a new gradient if this subgroup has any.
                 // The gradient is sum of all gradients in the subgroup.
                 if subgroupAny(splat_active) {
                     var v_xy_sum = subgroupAdd(v_xy);
                     var v_conic_sum = subgroupAdd(v_conic);
                     var v_colors_sum = subgroupAdd(v_colors);
+#ifdef ABSGRAD
+                    let v_xy_abs_sum = subgroupAdd(abs(v_xy));
+#endif
 
                     // First thread of subgroup writes the gradient. This should be a
                     // subgroupBallot() when it's supported.
@@ -292,15 +418,42 @@ fn main(
                             v_colors_sum
                         );
                         gather_grad_id[grad_idx] = local_id[t];
+#ifdef ABSGRAD
+                        gather_abs_grads[grad_idx] = v_xy_abs_sum;
+#endif
                     }
                 }
+#endif
             }
 
             // Make sure all threads are done, and flush a batch of gradients.
             workgroupBarrier();
+#ifdef DETERMINISTIC
+            // One thread per splat sums the subgroups. Every intersection belongs to exactly
+            // one tile, so this doesn't need atomics.
+            let tt = i32(local_idx);
+            if tt < microbatch_size && tb + tt < remaining {
+                var grads = gather_grads[tt * sg_per_tile];
+                for (var sg = 1; sg < sg_per_tile; sg++) {
+                    grads = add_grads(grads, gather_grads[tt * sg_per_tile + sg]);
+                }
+                v_isect[batch_end - 1 - (tb + tt)] = grads;
+#ifdef ABSGRAD
+                var abs_grads = gather_abs_grads[tt * sg_per_tile];
+                for (var sg = 1; sg < sg_per_tile; sg++) {
+                    abs_grads += gather_abs_grads[tt * sg_per_tile + sg];
+                }
+                v_isect_abs[batch_end - 1 - (tb + tt)] = abs_grads;
+#endif
+            }
+#else
             if local_idx < u32(grad_count) {
                 write_grads_atomic(gather_grads[local_idx], gather_grad_id[local_idx]);
+#ifdef ABSGRAD
+                write_abs_grads_atomic(gather_abs_grads[local_idx], gather_grad_id[local_idx]);
+#endif
             }
+#endif
             workgroupBarrier();
         }
     }
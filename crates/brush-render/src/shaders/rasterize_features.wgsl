@@ -0,0 +1,96 @@
+#import helpers
+
+@group(0) @binding(0) var<uniform> uniforms: helpers::RenderUniforms;
+@group(0) @binding(1) var<storage, read> compact_gid_from_isect: array<i32>;
+@group(0) @binding(2) var<storage, read> tile_offsets: array<i32>;
+@group(0) @binding(3) var<storage, read> projected_splats: array<helpers::ProjectedSplat>;
+@group(0) @binding(4) var<storage, read> global_from_compact_gid: array<i32>;
+// Features of every splat, [num_splats, CHANNELS].
+@group(0) @binding(5) var<storage, read> features: array<f32>;
+// Blended features of every pixel, [h, w, CHANNELS].
+@group(0) @binding(6) var<storage, read_write> out_features: array<f32>;
+
+#ifdef FEATURE_CHANNELS
+    const CHANNELS: u32 = u32(#{FEATURE_CHANNELS});
+#else
+    const CHANNELS: u32 = 1u;
+#endif
+
+var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
+var<workgroup> local_gid: array<i32, helpers::TILE_SIZE>;
+
+// Blends the features of the splats exactly like rasterize blends the colors, without a
+// background.
+@compute
+@workgroup_size(helpers::TILE_WIDTH, helpers::TILE_WIDTH, 1)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3u,
+    @builtin(local_invocation_index) local_idx: u32,
+    @builtin(workgroup_id) workgroup_id: vec3u,
+) {
+    let img_size = uniforms.img_size;
+
+    let pix_id = i32(global_id.x) + i32(global_id.y) * img_size.x;
+    let tile_id = i32(workgroup_id.x) + i32(workgroup_id.y) * uniforms.tile_bounds.x;
+    let pixel_coord = vec2f(global_id.xy) + 0.5;
+
+    let inside = i32(global_id.x) < img_size.x && i32(global_id.y) < img_size.y;
+    var done = !inside;
+
+    let range = vec2i(tile_offsets[tile_id], tile_offsets[tile_id + 1]);
+    let num_batches = helpers::ceil_div(range.y - range.x, i32(helpers::TILE_SIZE));
+
+    var T = 1.0;
+    var pix_out: array<f32, CHANNELS>;
+
+    for (var b = 0; b < num_batches; b++) {
+        let batch_start = range.x + b * i32(helpers::TILE_SIZE);
+
+        workgroupBarrier();
+
+        let remaining = min(i32(helpers::TILE_SIZE), range.y - batch_start);
+
+        if i32(local_idx) < remaining {
+            let compact_gid = compact_gid_from_isect[batch_start + i32(local_idx)];
+            local_batch[local_idx] = projected_splats[compact_gid];
+            local_gid[local_idx] = global_from_compact_gid[compact_gid];
+        }
+        workgroupBarrier();
+
+        for (var t = 0; t < remaining && !done; t++) {
+            let projected = local_batch[t];
+
+            let xy = vec2f(projected.xy_x, projected.xy_y);
+            let conic = vec3f(projected.conic_x, projected.conic_y, projected.conic_z);
+
+            let delta = xy - pixel_coord;
+            let sigma = 0.5f * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y;
+            let alpha = min(0.999f, projected.color_a * exp(-sigma));
+
+            if (sigma < 0.0f || alpha < 1.0f / 255.0f) {
+                continue;
+            }
+
+            let next_T = T * (1.0 - alpha);
+
+            if next_T <= 1e-4f {
+                done = true;
+                break;
+            }
+
+            let vis = alpha * T;
+            let base = u32(local_gid[t]) * CHANNELS;
+            for (var c = 0u; c < CHANNELS; c++) {
+                pix_out[c] += features[base + c] * vis;
+            }
+            T = next_T;
+        }
+    }
+
+    if inside {
+        let base = u32(pix_id) * CHANNELS;
+        for (var c = 0u; c < CHANNELS; c++) {
+            out_features[base + c] = pix_out[c];
+        }
+    }
+}
@@ -0,0 +1,126 @@
+#import helpers;
+
+@group(0) @binding(0) var<uniform> uniforms: helpers::RenderUniforms;
+@group(0) @binding(1) var<storage, read> compact_gid_from_isect: array<i32>;
+@group(0) @binding(2) var<storage, read> tile_offsets: array<i32>;
+@group(0) @binding(3) var<storage, read> projected_splats: array<helpers::ProjectedSplat>;
+@group(0) @binding(4) var<storage, read> global_from_compact_gid: array<i32>;
+@group(0) @binding(5) var<storage, read> v_output: array<f32>;
+
+#ifdef HARD_FLOAT
+    @group(0) @binding(6) var<storage, read_write> v_features: array<atomic<f32>>;
+#else
+    @group(0) @binding(6) var<storage, read_write> v_features: array<atomic<u32>>;
+#endif
+
+#ifdef FEATURE_CHANNELS
+    const CHANNELS: u32 = u32(#{FEATURE_CHANNELS});
+#else
+    const CHANNELS: u32 = 1u;
+#endif
+
+var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
+var<workgroup> local_gid: array<i32, helpers::TILE_SIZE>;
+
+fn add_bitcast(cur: u32, add: f32) -> u32 {
+    return bitcast<u32>(bitcast<f32>(cur) + add);
+}
+
+// The blended features are linear in the features of the splats, so the gradient of a splat
+// feature is the sum of the pixel gradients weighted by how visible the splat is in those pixels.
+// The weights are recomputed front to back, exactly like rasterize_features computes them.
+@compute
+@workgroup_size(helpers::TILE_SIZE, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(local_invocation_index) local_idx: u32,
+    @builtin(subgroup_invocation_id) subgroup_invocation_id: u32
+) {
+    let img_size = uniforms.img_size;
+    let tile_bounds = uniforms.tile_bounds;
+
+    let tile_id = i32(workgroup_id.x);
+    let tile_loc = vec2i(tile_id % tile_bounds.x, tile_id / tile_bounds.x);
+    let pixel_coordi = tile_loc * i32(helpers::TILE_WIDTH) + vec2i(i32(local_idx % helpers::TILE_WIDTH), i32(local_idx / helpers::TILE_WIDTH));
+    let pix_id = pixel_coordi.x + pixel_coordi.y * img_size.x;
+    let pixel_coord = vec2f(pixel_coordi) + 0.5;
+
+    let inside = pixel_coordi.x < img_size.x && pixel_coordi.y < img_size.y;
+    var done = !inside;
+
+    var v_pix: array<f32, CHANNELS>;
+    if inside {
+        let base = u32(pix_id) * CHANNELS;
+        for (var c = 0u; c < CHANNELS; c++) {
+            v_pix[c] = v_output[base + c];
+        }
+    }
+
+    let range = vec2i(tile_offsets[tile_id], tile_offsets[tile_id + 1]);
+    let num_batches = helpers::ceil_div(range.y - range.x, i32(helpers::TILE_SIZE));
+
+    var T = 1.0;
+
+    for (var b = 0; b < num_batches; b++) {
+        let batch_start = range.x + b * i32(helpers::TILE_SIZE);
+
+        workgroupBarrier();
+
+        let remaining = min(i32(helpers::TILE_SIZE), range.y - batch_start);
+
+        if i32(local_idx) < remaining {
+            let compact_gid = compact_gid_from_isect[batch_start + i32(local_idx)];
+            local_batch[local_idx] = projected_splats[compact_gid];
+            local_gid[local_idx] = global_from_compact_gid[compact_gid];
+        }
+        workgroupBarrier();
+
+        // All threads go through all splats, so the subgroup can sum the gradients.
+        for (var t = 0; t < remaining; t++) {
+            var vis = 0.0;
+
+            if !done {
+                let projected = local_batch[t];
+
+                let xy = vec2f(projected.xy_x, projected.xy_y);
+                let conic = vec3f(projected.conic_x, projected.conic_y, projected.conic_z);
+
+                let delta = xy - pixel_coord;
+                let sigma = 0.5f * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y;
+                let alpha = min(0.999f, projected.color_a * exp(-sigma));
+
+                if sigma >= 0.0f && alpha >= 1.0f / 255.0f {
+                    let next_T = T * (1.0 - alpha);
+
+                    if next_T <= 1e-4f {
+                        done = true;
+                    } else {
+                        vis = alpha * T;
+                        T = next_T;
+                    }
+                }
+            }
+
+            if subgroupAny(vis > 0.0) {
+                let base = u32(local_gid[t]) * CHANNELS;
+
+                for (var c = 0u; c < CHANNELS; c++) {
+                    // One thread per subgroup writes the summed gradient.
+                    let v_feature = subgroupAdd(vis * v_pix[c]);
+
+                    if subgroup_invocation_id == 0u {
+#ifdef HARD_FLOAT
+                        atomicAdd(&v_features[base + c], v_feature);
+#else
+                        var old_value = atomicLoad(&v_features[base + c]);
+                        loop {
+                            let cas = atomicCompareExchangeWeak(&v_features[base + c], old_value, add_bitcast(old_value, v_feature));
+                            if cas.exchanged { break; } else { old_value = cas.old_value; }
+                        }
+#endif
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+#import helpers;
+
+// Sums the gradients of all intersections of a splat, in a fixed order. Used instead of atomics
+// when rendering deterministically.
+
+@group(0) @binding(0) var<storage, read> num_intersections: i32;
+// Intersections sorted by splat, and within a splat by tile.
+@group(0) @binding(1) var<storage, read> compact_gid_from_sorted: array<i32>;
+@group(0) @binding(2) var<storage, read> isect_from_sorted: array<i32>;
+@group(0) @binding(3) var<storage, read> v_isect: array<helpers::ProjectedSplat>;
+
+@group(0) @binding(4) var<storage, read_write> v_xy: array<vec2f>;
+@group(0) @binding(5) var<storage, read_write> v_conics: array<f32>;
+@group(0) @binding(6) var<storage, read_write> v_colors: array<vec4f>;
+
+#ifdef ABSGRAD
+@group(0) @binding(7) var<storage, read> v_isect_abs: array<vec2f>;
+@group(0) @binding(8) var<storage, read_write> v_xy_abs: array<vec2f>;
+#endif
+
+const WG_SIZE: u32 = 256u;
+
+@compute
+@workgroup_size(WG_SIZE, 1, 1)
+fn main(
+    @builtin(global_invocation_id) gid: vec3u,
+    @builtin(num_workgroups) num_wgs: vec3u,
+) {
+    let sorted_id = i32(helpers::dispatch_id(gid, num_wgs, WG_SIZE));
+
+    if sorted_id >= num_intersections {
+        return;
+    }
+
+    // Only the first intersection of every splat sums its gradients.
+    let compact_gid = compact_gid_from_sorted[sorted_id];
+    if sorted_id > 0 && compact_gid_from_sorted[sorted_id - 1] == compact_gid {
+        return;
+    }
+
+    var xy = vec2f(0.0);
+    var conic = vec3f(0.0);
+    var color = vec4f(0.0);
+    var xy_abs = vec2f(0.0);
+
+    for (var i = sorted_id; i < num_intersections && compact_gid_from_sorted[i] == compact_gid; i++) {
+        let grads = v_isect[isect_from_sorted[i]];
+        xy += vec2f(grads.xy_x, grads.xy_y);
+        conic += vec3f(grads.conic_x, grads.conic_y, grads.conic_z);
+        color += vec4f(grads.color_r, grads.color_g, grads.color_b, grads.color_a);
+#ifdef ABSGRAD
+        xy_abs += v_isect_abs[isect_from_sorted[i]];
+#endif
+    }
+
+    v_xy[compact_gid] = xy;
+    v_conics[compact_gid * 3 + 0] = conic.x;
+    v_conics[compact_gid * 3 + 1] = conic.y;
+    v_conics[compact_gid * 3 + 2] = conic.z;
+    v_colors[compact_gid] = color;
+#ifdef ABSGRAD
+    v_xy_abs[compact_gid] = xy_abs;
+#endif
+}
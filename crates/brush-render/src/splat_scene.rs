@@ -0,0 +1,137 @@
+use crate::{
+    camera::Camera,
+    color_harmonize::{estimate_color_transform, ColorTransform},
+    gaussian_splats::Splats,
+    Backend, RenderAux,
+};
+use burn::tensor::Tensor;
+use glam::Affine3A;
+
+/// A single model in a [`SplatScene`].
+#[derive(Debug, Clone)]
+pub struct SceneModel<B: Backend> {
+    pub name: String,
+    pub splats: Splats<B>,
+    /// Transform from the model's local space to world space.
+    pub transform: Affine3A,
+    pub visible: bool,
+}
+
+/// A collection of splat models, each with their own transform and visibility.
+///
+/// All visible models are combined into one set of splats and rendered in a single pass, so
+/// splats of different models are sorted & blended correctly against each other.
+#[derive(Debug, Clone)]
+pub struct SplatScene<B: Backend> {
+    models: Vec<SceneModel<B>>,
+}
+
+impl<B: Backend> Default for SplatScene<B> {
+    fn default() -> Self {
+        Self { models: vec![] }
+    }
+}
+
+impl<B: Backend> SplatScene<B> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a model to the scene, returns the index of the model.
+    pub fn add_model(&mut self, name: impl Into<String>, splats: Splats<B>) -> usize {
+        self.models.push(SceneModel {
+            name: name.into(),
+            splats,
+            transform: Affine3A::IDENTITY,
+            visible: true,
+        });
+        self.models.len() - 1
+    }
+
+    pub fn remove_model(&mut self, index: usize) -> SceneModel<B> {
+        self.models.remove(index)
+    }
+
+    pub fn models(&self) -> &[SceneModel<B>] {
+        &self.models
+    }
+
+    pub fn model_mut(&mut self, index: usize) -> Option<&mut SceneModel<B>> {
+        self.models.get_mut(index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    /// Total number of splats of all visible models.
+    pub fn num_visible_splats(&self) -> usize {
+        self.models
+            .iter()
+            .filter(|m| m.visible)
+            .map(|m| m.splats.num_splats())
+            .sum()
+    }
+
+    /// Combine all visible models into a single set of splats in world space.
+    ///
+    /// Models with a lower SH degree are padded to the highest SH degree in the scene.
+    /// Returns None if there are no visible models.
+    pub fn combined(&self) -> Option<Splats<B>> {
+        let visible: Vec<_> = self.models.iter().filter(|m| m.visible).collect();
+
+        if visible.len() == 1 && visible[0].transform == Affine3A::IDENTITY {
+            return Some(visible[0].splats.clone());
+        }
+
+        if visible.is_empty() {
+            return None;
+        }
+
+        Some(Splats::merge_transformed(
+            visible.iter().map(|m| (&m.splats, m.transform)),
+        ))
+    }
+
+    /// Match the colors of the visible models, so there are no seams where captures made under
+    /// different lighting overlap.
+    ///
+    /// The first visible model is kept as is, every next model is matched to the models before
+    /// it, see [`estimate_color_transform`]. Returns the transform applied to each model.
+    pub async fn harmonize_colors(&mut self, ambient: bool) -> anyhow::Result<Vec<ColorTransform>> {
+        let mut transforms = vec![ColorTransform::IDENTITY; self.models.len()];
+        let mut reference: Option<Splats<B>> = None;
+
+        for (model, applied) in self.models.iter_mut().zip(&mut transforms) {
+            if !model.visible {
+                continue;
+            }
+
+            let world = model.splats.transformed(model.transform);
+            let world = match reference.take() {
+                Some(reference) => {
+                    let transform = estimate_color_transform(&reference, &world, ambient).await?;
+                    model.splats = transform.apply_to_splats(model.splats.clone());
+                    *applied = transform;
+                    let world = transform.apply_to_splats(world);
+                    Splats::merge(&[reference, world])
+                }
+                None => world,
+            };
+            reference = Some(world);
+        }
+
+        Ok(transforms)
+    }
+
+    /// Render all visible models in one pass. Returns None if there are no visible models.
+    pub fn render(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        render_u32_buffer: bool,
+    ) -> Option<(Tensor<B, 3>, RenderAux<B>)> {
+        let splats = self.combined()?;
+        Some(splats.render(camera, img_size, render_u32_buffer))
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::anyhow;
+use burn::tensor::Tensor;
+use glam::Vec3;
+
+use crate::{
+    bounding_box::BoundingBox, camera::Camera, gaussian_splats::Splats,
+    render::sh_degree_from_coeffs, Backend,
+};
+
+const NUM_BINS: usize = 32;
+
+/// A histogram with evenly sized bins between `min` and `max`. When `log_scale` is set, the
+/// bins are evenly sized in log space instead.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub log_scale: bool,
+    pub counts: Vec<u32>,
+    /// The value of every splat, NaN for splats that aren't counted.
+    pub values: Vec<f32>,
+}
+
+impl Histogram {
+    fn map(&self, v: f32) -> f32 {
+        if self.log_scale {
+            v.max(1e-12).ln()
+        } else {
+            v
+        }
+    }
+
+    fn from_values(values: Vec<f32>, log_scale: bool) -> Self {
+        let mut hist = Self {
+            min: 0.0,
+            max: 1.0,
+            log_scale,
+            counts: vec![0; NUM_BINS],
+            values: vec![],
+        };
+
+        let (min, max) = values
+            .iter()
+            .filter(|v| v.is_finite())
+            .map(|&v| hist.map(v))
+            .fold((f32::MAX, f32::MIN), |(min, max), v| {
+                (min.min(v), max.max(v))
+            });
+        if min <= max {
+            let unmap = |v: f32| if log_scale { v.exp() } else { v };
+            (hist.min, hist.max) = (unmap(min), unmap(max));
+        }
+
+        for &v in &values {
+            if let Some(bin) = hist.bin_of(v) {
+                hist.counts[bin] += 1;
+            }
+        }
+        hist.values = values;
+        hist
+    }
+
+    /// The bin a value falls in, or None if it isn't counted.
+    pub fn bin_of(&self, v: f32) -> Option<usize> {
+        if !v.is_finite() {
+            return None;
+        }
+        let (v, min, max) = (self.map(v), self.map(self.min), self.map(self.max));
+        let range = (max - min).max(1e-12);
+        // Float to int casts saturate, so values just below the min end up in the first bin.
+        let bin = ((v - min) / range * self.counts.len() as f32) as usize;
+        Some(bin.min(self.counts.len() - 1))
+    }
+
+    /// Whether every splat falls in bin `i`.
+    pub fn splats_in_bin(&self, i: usize) -> Vec<bool> {
+        self.values
+            .iter()
+            .map(|&v| self.bin_of(v) == Some(i))
+            .collect()
+    }
+
+    /// The range of values that falls in bin `i`.
+    pub fn bin_range(&self, i: usize) -> (f32, f32) {
+        let n = self.counts.len() as f32;
+        let lerp = |t: f32| {
+            if self.log_scale {
+                (self.min.ln() + (self.max.ln() - self.min.ln()) * t).exp()
+            } else {
+                self.min + (self.max - self.min) * t
+            }
+        };
+        (lerp(i as f32 / n), lerp((i + 1) as f32 / n))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SplatStats {
+    pub num_splats: usize,
+    pub bounds: BoundingBox,
+    pub opacity: Histogram,
+    /// Histogram of the largest scale of each splat.
+    pub scale: Histogram,
+    /// Histogram of the projected diameter of the splats in front of the camera, as a fraction
+    /// of the image height.
+    pub screen_size: Option<Histogram>,
+    /// Mean squared SH coefficient of every band, averaged over the color channels.
+    pub sh_band_energy: Vec<f32>,
+    /// Histogram of the mean squared SH coefficient of the bands above the base color of each
+    /// splat, if there are any.
+    pub sh_energy: Option<Histogram>,
+}
+
+impl<B: Backend> Splats<B> {
+    /// Calculate statistics about these splats. When a camera is given, this includes how
+    /// large the splats appear from that camera.
+    pub async fn stats(&self, camera: Option<&Camera>) -> anyhow::Result<SplatStats> {
+        let read_err = |e| anyhow!("Failed to read splat stats {e:?}");
+        let num_splats = self.num_splats();
+        let splats = self.clone().with_full_precision();
+
+        let means = splats.means.val();
+        let min: Vec<f32> = means
+            .clone()
+            .min_dim(0)
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let max: Vec<f32> = means
+            .clone()
+            .max_dim(0)
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let bounds = BoundingBox::from_min_max(Vec3::from_slice(&min), Vec3::from_slice(&max));
+
+        let opacity: Vec<f32> = splats
+            .opacity()
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let max_scale = splats.scales().max_dim(1);
+        let scale: Vec<f32> = max_scale
+            .clone()
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+
+        let screen_size = if let Some(camera) = camera {
+            let device = means.device();
+            let world_to_local = camera.world_to_local();
+            let forward = world_to_local.matrix3.row(2);
+            let forward = Tensor::<B, 1>::from_floats(forward.to_array(), &device).reshape([3, 1]);
+            let depth = means.matmul(forward).reshape([num_splats]) + world_to_local.translation.z;
+
+            // The projected diameter is 2 * scale * focal / depth, with focal in pixels being
+            // height / (2 * tan(fov_y / 2)).
+            let tan_half_fov = (camera.fov_y / 2.0).tan() as f32;
+            let size =
+                max_scale.reshape([num_splats]) / depth.clone().clamp_min(1e-6) / tan_half_fov;
+
+            let depth: Vec<f32> = depth.into_data_async().await.to_vec().map_err(read_err)?;
+            let size: Vec<f32> = size.into_data_async().await.to_vec().map_err(read_err)?;
+            let size = depth
+                .iter()
+                .zip(size)
+                .map(|(&d, s)| if d > 0.0 { s } else { f32::NAN })
+                .collect();
+            Some(Histogram::from_values(size, true))
+        } else {
+            None
+        };
+
+        let [_, n_coeffs, _] = splats.sh_coeffs.dims();
+        let sq_coeffs = splats.sh_coeffs.val().powf_scalar(2.0).mean_dim(2);
+        let coeff_energy: Vec<f32> = sq_coeffs
+            .clone()
+            .mean_dim(0)
+            .into_data_async()
+            .await
+            .to_vec()
+            .map_err(read_err)?;
+        let sh_band_energy = (0..=sh_degree_from_coeffs(n_coeffs as u32) as usize)
+            .map(|l| {
+                let band = &coeff_energy[l * l..(l + 1) * (l + 1)];
+                band.iter().sum::<f32>() / band.len() as f32
+            })
+            .collect();
+
+        let sh_energy = if n_coeffs > 1 {
+            let energy: Vec<f32> = sq_coeffs
+                .slice([0..num_splats, 1..n_coeffs])
+                .mean_dim(1)
+                .into_data_async()
+                .await
+                .to_vec()
+                .map_err(read_err)?;
+            Some(Histogram::from_values(energy, true))
+        } else {
+            None
+        };
+
+        Ok(SplatStats {
+            num_splats,
+            bounds,
+            opacity: Histogram::from_values(opacity, false),
+            scale: Histogram::from_values(scale, true),
+            screen_size,
+            sh_band_energy,
+            sh_energy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Histogram;
+
+    #[test]
+    fn histogram_bins_select_splats() {
+        let hist = Histogram::from_values(vec![0.001, 0.01, 0.1, f32::NAN, 10.0], true);
+        // The NaN isn't counted.
+        assert_eq!(hist.counts.iter().sum::<u32>(), 4);
+        assert_eq!(
+            hist.splats_in_bin(0),
+            vec![true, false, false, false, false]
+        );
+        assert_eq!(
+            hist.splats_in_bin(hist.counts.len() - 1),
+            vec![false, false, false, false, true]
+        );
+    }
+}
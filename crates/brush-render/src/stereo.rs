@@ -0,0 +1,142 @@
+//! Stereo rendering, eg. for side-by-side 3D displays or VR headsets.
+//!
+//! A headset frontend (eg. with OpenXR) locates the eye views every frame, converts them with
+//! [`xr_eye_camera`], renders both eyes with [`Splats::render_stereo`], and submits the images
+//! to its swapchains.
+
+use glam::{Quat, Vec3};
+
+use crate::{background::Background, camera::Camera, gaussian_splats::Splats, Backend, RenderAux};
+use burn::tensor::Tensor;
+
+/// The cameras of the left and right eye.
+#[derive(Debug, Clone)]
+pub struct StereoCameras {
+    pub left: Camera,
+    pub right: Camera,
+}
+
+impl StereoCameras {
+    /// Eyes at either side of `camera`, `ipd` apart along its horizontal axis, looking the same
+    /// way as the camera. The IPD is in scene units.
+    pub fn from_center(camera: &Camera, ipd: f32) -> Self {
+        let offset = camera.rotation * Vec3::X * (ipd * 0.5);
+        let mut left = camera.clone();
+        left.position -= offset;
+        let mut right = camera.clone();
+        right.position += offset;
+        Self { left, right }
+    }
+}
+
+/// The field of view of an eye, as the angles of the edges of the view. Left and down are
+/// negative, like `XrFovf` in OpenXR.
+#[derive(Debug, Clone, Copy)]
+pub struct EyeFov {
+    pub angle_left: f32,
+    pub angle_right: f32,
+    pub angle_up: f32,
+    pub angle_down: f32,
+}
+
+/// The camera of an eye view located by a headset. OpenXR views look down -z with y up, these
+/// are turned to look down +z with y down, like all cameras. The fov of headsets is usually
+/// asymmetric, which moves the center of the camera.
+pub fn xr_eye_camera(position: Vec3, orientation: Quat, fov: EyeFov) -> Camera {
+    let [left, right, up, down] = [
+        fov.angle_left,
+        fov.angle_right,
+        fov.angle_up,
+        fov.angle_down,
+    ]
+    .map(|a| a.tan() as f64);
+
+    // The focal length is the same as for a symmetric fov with the same total extent.
+    let fov_x = 2.0 * ((right - left) / 2.0).atan();
+    let fov_y = 2.0 * ((up - down) / 2.0).atan();
+    let center_uv = glam::vec2((-left / (right - left)) as f32, (up / (up - down)) as f32);
+
+    Camera::new(
+        position,
+        orientation * Quat::from_rotation_x(std::f32::consts::PI),
+        fov_x,
+        fov_y,
+        center_uv,
+    )
+}
+
+impl<B: Backend> Splats<B> {
+    /// Render the left and right eye, each at `eye_size`, see [`Self::render_batch`].
+    pub fn render_stereo(
+        &self,
+        eyes: &StereoCameras,
+        eye_size: glam::UVec2,
+        render_u32_buffer: bool,
+        background: Background<Tensor<B, 3>>,
+    ) -> ([Tensor<B, 3>; 2], [RenderAux<B>; 2]) {
+        let cameras = [eyes.left.clone(), eyes.right.clone()];
+        let (imgs, auxes) = self.render_batch(&cameras, eye_size, render_u32_buffer, background);
+        let [left, right] = [0, 1].map(|i| imgs.clone().slice([i..i + 1]).squeeze::<3>(0));
+        let [left_aux, right_aux]: [RenderAux<B>; 2] =
+            auxes.try_into().expect("Rendered two views");
+        ([left, right], [left_aux, right_aux])
+    }
+
+    /// Render both eyes next to each other, into an `[h, 2 * w, c]` image with the left eye on
+    /// the left, eg. for side-by-side 3D displays.
+    pub fn render_side_by_side(
+        &self,
+        eyes: &StereoCameras,
+        eye_size: glam::UVec2,
+        render_u32_buffer: bool,
+        background: Background<Tensor<B, 3>>,
+    ) -> (Tensor<B, 3>, [RenderAux<B>; 2]) {
+        let ([left, right], auxes) =
+            self.render_stereo(eyes, eye_size, render_u32_buffer, background);
+        (Tensor::cat(vec![left, right], 1), auxes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eyes_are_ipd_apart() {
+        let camera = Camera::new(
+            glam::vec3(1.0, 2.0, 3.0),
+            Quat::from_rotation_y(0.5),
+            0.8,
+            0.6,
+            glam::vec2(0.5, 0.5),
+        );
+        let eyes = StereoCameras::from_center(&camera, 0.064);
+        assert!((eyes.left.position.distance(eyes.right.position) - 0.064).abs() < 1e-6);
+        let right_dir = camera.rotation * Vec3::X;
+        assert!((eyes.right.position - eyes.left.position).dot(right_dir) > 0.0);
+    }
+
+    #[test]
+    fn xr_eye_projects_fov_edges() {
+        let fov = EyeFov {
+            angle_left: -0.9,
+            angle_right: 0.7,
+            angle_up: 0.8,
+            angle_down: -0.85,
+        };
+        let camera = xr_eye_camera(Vec3::ZERO, Quat::IDENTITY, fov);
+        let img_size = glam::uvec2(200, 100);
+
+        // OpenXR looks down -z with y up.
+        let top_left = glam::vec3(fov.angle_left.tan(), fov.angle_up.tan(), -1.0);
+        let bottom_right = glam::vec3(fov.angle_right.tan(), fov.angle_down.tan(), -1.0);
+        let top_left = camera
+            .world_to_pixel(img_size, top_left)
+            .expect("In front of the eye");
+        let bottom_right = camera
+            .world_to_pixel(img_size, bottom_right)
+            .expect("In front of the eye");
+        assert!(top_left.abs_diff_eq(glam::Vec2::ZERO, 1e-3));
+        assert!(bottom_right.abs_diff_eq(img_size.as_vec2(), 1e-3));
+    }
+}
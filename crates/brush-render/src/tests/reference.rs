@@ -1,6 +1,7 @@
 use crate::{
     camera::{focal_to_fov, fov_to_focal, Camera},
     gaussian_splats::Splats,
+    render::RenderSettings,
     safetensor_utils::safetensor_to_burn,
     Backend,
 };
@@ -130,6 +131,7 @@ async fn test_reference() -> Result<()> {
             splats.sh_coeffs.val().into_primitive().tensor(),
             splats.raw_opacity.val().into_primitive().tensor(),
             false,
+            RenderSettings::default(),
         );
 
         let (out, aux) = (Tensor::from_primitive(TensorPrimitive::Float(img)), aux);
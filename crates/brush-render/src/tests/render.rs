@@ -1,8 +1,17 @@
-use crate::{camera::Camera, Backend};
+use crate::{
+    background::Background,
+    camera::Camera,
+    crop::CropBox,
+    gaussian_splats::{Splats, TemporalAttributes},
+    lod::{LodConfig, SplatLod},
+    raycast::{pick, raycast, Ray},
+    render::{self, RenderOptions, RenderSettings, SolidRender},
+    Backend,
+};
 use assert_approx_eq::assert_approx_eq;
 use burn::{
     backend::Autodiff,
-    tensor::{Tensor, TensorPrimitive},
+    tensor::{Bool, Tensor, TensorPrimitive},
 };
 use burn_wgpu::{Wgpu, WgpuDevice};
 
@@ -42,6 +51,7 @@ async fn renders_at_all() {
         sh_coeffs.into_primitive().tensor(),
         raw_opacity.into_primitive().tensor(),
         false,
+        RenderSettings::default(),
     );
     aux.into_wrapped().debug_assert_valid();
 
@@ -57,3 +67,984 @@ async fn renders_at_all() {
     assert_approx_eq!(rgb_mean, 0.0, 1e-5);
     assert_approx_eq!(alpha_mean, 0.0);
 }
+
+#[tokio::test]
+async fn renders_depth_and_normals() {
+    // A single flat splat in front of the camera, facing it.
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(32, 32);
+    let device = WgpuDevice::DefaultDevice;
+    let splats = Splats::<Wgpu>::from_raw(
+        &[glam::vec3(0.0, 0.0, 5.0)],
+        None,
+        Some(&[glam::vec3(1.0, 1.0, 0.01).ln()]),
+        Some(&[1.0, 1.0, 1.0]),
+        Some(&[5.0]),
+        &device,
+    );
+    let (_, aux) = splats.render_with_depth(&cam, img_size);
+
+    let depth = aux.depth.expect("Depth should be rendered");
+    let normals = aux.normals.expect("Normals should be rendered");
+    assert_eq!(depth.dims(), [32, 32]);
+    assert_eq!(normals.dims(), [32, 32, 3]);
+
+    let depth = depth
+        .into_data_async()
+        .await
+        .to_vec::<f32>()
+        .expect("Wrong type");
+    let normals = normals
+        .into_data_async()
+        .await
+        .to_vec::<f32>()
+        .expect("Wrong type");
+    let center = 16 * 32 + 16;
+    assert_approx_eq!(depth[center], 5.0, 1e-3);
+    assert_approx_eq!(normals[center * 3 + 2], -1.0, 1e-3);
+
+    // Normal renders don't render depth.
+    let (_, aux) = splats.render(&cam, img_size, false);
+    assert!(aux.depth.is_none() && aux.normals.is_none());
+
+    // The differentiable depth matches.
+    let depth_map = splats
+        .render_depth_map(&cam, img_size)
+        .into_data_async()
+        .await
+        .to_vec::<f32>()
+        .expect("Wrong type");
+    assert_approx_eq!(depth_map[center], 5.0, 1e-3);
+
+    // And so do the differentiable normals, from the shortest axis of the splat.
+    let normal_map = splats
+        .render_normal_map(&cam, img_size)
+        .into_data_async()
+        .await
+        .to_vec::<f32>()
+        .expect("Wrong type");
+    assert_approx_eq!(normal_map[center * 3 + 2], -1.0, 1e-3);
+}
+
+#[tokio::test]
+async fn solid_render_hardens_splats() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(32, 32);
+    let device = WgpuDevice::DefaultDevice;
+    // An opaque and a transparent splat, side by side.
+    let splats = Splats::<Wgpu>::from_raw(
+        &[glam::vec3(-0.5, 0.0, 5.0), glam::vec3(0.5, 0.0, 5.0)],
+        None,
+        Some(&[glam::Vec3::splat(0.2f32.ln()); 2]),
+        None,
+        Some(&[2.0, -1.0]),
+        &device,
+    )
+    .with_solid(Some(SolidRender::default()));
+
+    let (img, aux) = splats.render(&cam, img_size, false);
+    assert_eq!(aux.num_visible.into_scalar_async().await, 1);
+
+    let img = img
+        .into_data_async()
+        .await
+        .to_vec::<f32>()
+        .expect("Wrong type");
+    let alpha = |x: usize, y: usize| img[(y * 32 + x) * 4 + 3];
+    // The opaque splat is fully opaque at its center, rather than at its opacity.
+    assert!(alpha(9, 16) > 0.99);
+    assert_eq!(alpha(22, 16), 0.0);
+}
+
+#[tokio::test]
+async fn picks_front_splat() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(32, 32);
+    let device = WgpuDevice::DefaultDevice;
+    // Two flat splats facing the camera, the second one in front.
+    let splats = Splats::<Wgpu>::from_raw(
+        &[glam::vec3(0.0, 0.0, 5.0), glam::vec3(0.0, 0.0, 3.0)],
+        None,
+        Some(&[glam::vec3(0.1, 0.1, 0.01).ln(); 2]),
+        None,
+        Some(&[5.0, 5.0]),
+        &device,
+    );
+
+    let hit = pick(&splats, &cam, img_size, glam::vec2(16.0, 16.0))
+        .await
+        .expect("Failed to pick")
+        .expect("Nothing under the center pixel");
+    assert_eq!(hit.splat_id, 1);
+    assert_approx_eq!(hit.depth, 3.0, 1e-2);
+
+    // Nothing covers the corner.
+    let miss = pick(&splats, &cam, img_size, glam::vec2(0.0, 0.0))
+        .await
+        .expect("Failed to pick");
+    assert!(miss.is_none());
+}
+
+#[tokio::test]
+async fn raycast_composites_single_splat() {
+    let device = WgpuDevice::DefaultDevice;
+    // A round splat with a standard deviation of 0.5 and an opacity of 0.5.
+    let splats = Splats::<Wgpu>::from_raw(
+        &[glam::vec3(0.0, 0.0, 5.0)],
+        None,
+        Some(&[glam::Vec3::splat(0.5f32.ln())]),
+        None,
+        Some(&[0.0]),
+        &device,
+    );
+
+    // Through the center, the ray accumulates the full opacity at the mean.
+    let center = Ray {
+        origin: glam::Vec3::ZERO,
+        dir: glam::Vec3::Z,
+    };
+    let hit = raycast(&splats, center, 0.4)
+        .await
+        .expect("Failed to raycast")
+        .expect("Missed the splat");
+    assert_eq!(hit.splat_id, 0);
+    assert_approx_eq!(hit.distance, 5.0, 1e-4);
+    assert_approx_eq!(hit.alpha, 0.5, 1e-4);
+    assert!(hit.position.abs_diff_eq(glam::vec3(0.0, 0.0, 5.0), 1e-4));
+
+    // One standard deviation off center, the opacity falls off like the gaussian.
+    let offset = Ray {
+        origin: glam::vec3(0.5, 0.0, 0.0),
+        dir: glam::Vec3::Z,
+    };
+    let hit = raycast(&splats, offset, 0.3)
+        .await
+        .expect("Failed to raycast")
+        .expect("Missed the splat");
+    assert_approx_eq!(hit.alpha, 0.5 * (-0.5f32).exp(), 1e-4);
+    assert_approx_eq!(hit.distance, 5.0, 1e-4);
+
+    // The ray never gets opaque enough for a higher threshold.
+    let miss = raycast(&splats, offset, 0.4)
+        .await
+        .expect("Failed to raycast");
+    assert!(miss.is_none());
+}
+
+#[tokio::test]
+async fn crop_box_skips_splats() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(32, 32);
+    let device = WgpuDevice::DefaultDevice;
+    let splats = Splats::<Wgpu>::from_raw(
+        &[glam::vec3(0.0, 0.0, 5.0), glam::vec3(0.5, 0.0, 5.0)],
+        None,
+        None,
+        None,
+        None,
+        &device,
+    );
+
+    let crop_box = CropBox::new(glam::vec3(0.0, 0.0, 5.0), glam::Vec3::splat(0.25));
+    for (crop_box, expected) in [(None, 2), (Some(crop_box), 1)] {
+        let splats = splats.clone().with_crop_box(crop_box);
+        let (_, aux) = splats.render(&cam, img_size, false);
+        assert_eq!(aux.num_visible.into_scalar_async().await, expected);
+    }
+}
+
+#[tokio::test]
+async fn renders_over_background() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(32, 32);
+    let device = WgpuDevice::DefaultDevice;
+    // A small opaque white splat in the center.
+    let splats = Splats::<Wgpu>::from_raw(
+        &[glam::vec3(0.0, 0.0, 5.0)],
+        None,
+        Some(&[glam::Vec3::splat(0.05).ln()]),
+        Some(&[10.0, 10.0, 10.0]),
+        Some(&[10.0]),
+        &device,
+    );
+
+    let color = glam::vec3(0.25, 0.5, 0.75);
+    let texture = Tensor::<Wgpu, 1>::from_floats(color.to_array(), &device).reshape([1, 1, 3]);
+    for background in [
+        Background::Color(color),
+        Background::Image(texture.clone()),
+        Background::Environment(texture),
+    ] {
+        let (img, _) = splats.render_with_background(&cam, img_size, false, background);
+        let img = img
+            .into_data_async()
+            .await
+            .to_vec::<f32>()
+            .expect("Wrong type");
+
+        // The corner only shows the background, and is still transparent.
+        assert_approx_eq!(img[0], color.x, 1e-5);
+        assert_approx_eq!(img[1], color.y, 1e-5);
+        assert_approx_eq!(img[2], color.z, 1e-5);
+        assert_approx_eq!(img[3], 0.0, 1e-5);
+
+        // The center is covered by the splat.
+        let center = (16 * 32 + 16) * 4;
+        assert!(img[center + 3] > 0.9);
+        assert!(img[center] > color.x);
+    }
+}
+
+#[tokio::test]
+async fn deterministic_gradients() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(64, 64);
+    let device = WgpuDevice::DefaultDevice;
+    // Lots of overlapping splats, some at the same depth.
+    let means: Vec<_> = (0..256)
+        .map(|i| {
+            glam::vec3(
+                (i % 16) as f32 * 0.05 - 0.4,
+                (i / 16) as f32 * 0.05 - 0.4,
+                5.0,
+            )
+        })
+        .collect();
+    let options = RenderOptions {
+        deterministic: true,
+        ..Default::default()
+    };
+    let splats = Splats::<DiffBack>::from_raw(&means, None, None, None, None, &device)
+        .with_render_options(options);
+
+    let mut grads = vec![];
+    for _ in 0..2 {
+        let (img, _) = splats.render(&cam, img_size, false);
+        let backward = img.powi_scalar(2.0).mean().backward();
+        let v_means = splats.means.grad(&backward).expect("No means gradient");
+        grads.push(v_means.into_data_async().await);
+    }
+
+    assert_eq!(grads[0], grads[1]);
+}
+
+#[tokio::test]
+async fn recomputed_backward_matches() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(64, 64);
+    let device = WgpuDevice::DefaultDevice;
+    let means: Vec<_> = (0..64)
+        .map(|i| glam::vec3((i % 8) as f32 * 0.1 - 0.4, (i / 8) as f32 * 0.1 - 0.4, 5.0))
+        .collect();
+    let splats = Splats::<DiffBack>::from_raw(&means, None, None, None, None, &device);
+
+    let mut grads = vec![];
+    for recompute_backward in [false, true] {
+        let options = RenderOptions {
+            recompute_backward,
+            ..Default::default()
+        };
+        let (img, _) = splats
+            .clone()
+            .with_render_options(options)
+            .render(&cam, img_size, false);
+        let backward = img.powi_scalar(2.0).mean().backward();
+        let v_opac = splats
+            .raw_opacity
+            .grad(&backward)
+            .expect("No opacity gradient");
+        grads.push(
+            v_opac
+                .into_data_async()
+                .await
+                .to_vec::<f32>()
+                .expect("Wrong type"),
+        );
+    }
+
+    for (a, b) in grads[0].iter().zip(&grads[1]) {
+        assert_approx_eq!(a, b, 1e-5);
+    }
+}
+
+#[tokio::test]
+async fn temporal_render_matches_moved_splats() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(64, 64);
+    let device = WgpuDevice::DefaultDevice;
+    let time = 0.5;
+    let means: Vec<_> = (0..64)
+        .map(|i| glam::vec3((i % 8) as f32 * 0.1 - 0.4, (i / 8) as f32 * 0.1 - 0.4, 5.0))
+        .collect();
+    let velocities: Vec<_> = (0..64)
+        .map(|i| glam::vec3((i % 3) as f32 * 0.1 - 0.1, (i % 5) as f32 * 0.05 - 0.1, 0.2))
+        .collect();
+    let times: Vec<_> = (0..64).map(|i| (i % 4) as f32 * 0.25).collect();
+    let log_durations: Vec<_> = (0..64).map(|i| ((i % 3) as f32 * 0.2 + 0.1).ln()).collect();
+
+    // Move and fade the splats on the CPU, as the kernels should.
+    let moved: Vec<_> = (0..64)
+        .map(|i| means[i] + velocities[i] * (time - times[i]))
+        .collect();
+    let raw_opacities: Vec<_> = (0..64)
+        .map(|i| {
+            let x = (time - times[i]) / log_durations[i].exp();
+            let opacity = (0.5 * (-0.5 * x * x).exp()).clamp(1e-6, 1.0 - 1e-6);
+            (opacity / (1.0 - opacity)).ln()
+        })
+        .collect();
+    let reference =
+        Splats::<DiffBack>::from_raw(&moved, None, None, None, Some(&raw_opacities), &device);
+
+    let velocities_flat: Vec<f32> = velocities.iter().flat_map(|v| v.to_array()).collect();
+    let splats = Splats::<DiffBack>::from_raw(&means, None, None, None, None, &device)
+        .with_temporal(TemporalAttributes::new(
+            Tensor::<DiffBack, 1>::from_floats(velocities_flat.as_slice(), &device)
+                .reshape([64, 3]),
+            Tensor::from_floats(times.as_slice(), &device),
+            Tensor::from_floats(log_durations.as_slice(), &device),
+        ))
+        .with_time(Some(time));
+
+    let (img, _) = splats.render(&cam, img_size, false);
+    let (ref_img, _) = reference.render(&cam, img_size, false);
+    let diff = (img.clone() - ref_img.clone())
+        .abs()
+        .max()
+        .into_scalar_async()
+        .await;
+    assert!(diff < 1e-4, "Temporal render differs by {diff}");
+
+    // The gradient of a moved mean flows to the mean as is, and to the velocity scaled by the
+    // time since the splat's time.
+    let backward = img.powi_scalar(2.0).mean().backward();
+    let ref_backward = ref_img.powi_scalar(2.0).mean().backward();
+    let temporal = splats.temporal.as_ref().expect("Temporal was set");
+    let mut grads = vec![];
+    for grad in [
+        splats.means.grad(&backward),
+        reference.means.grad(&ref_backward),
+        temporal.velocities.grad(&backward),
+    ] {
+        let grad = grad.expect("No gradient");
+        grads.push(
+            grad.into_data_async()
+                .await
+                .to_vec::<f32>()
+                .expect("Wrong type"),
+        );
+    }
+    let (v_means, v_ref, v_velocities) = (&grads[0], &grads[1], &grads[2]);
+    for i in 0..64 * 3 {
+        assert_approx_eq!(v_means[i], v_ref[i], 1e-5);
+        assert_approx_eq!(v_velocities[i], v_ref[i] * (time - times[i / 3]), 1e-5);
+    }
+    assert!(v_velocities.iter().any(|v| *v != 0.0));
+    assert!(temporal.times.grad(&backward).is_some());
+    assert!(temporal.log_durations.grad(&backward).is_some());
+}
+
+#[tokio::test]
+async fn backward_keeps_tile_width() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(64, 64);
+    let device = WgpuDevice::DefaultDevice;
+    let means: Vec<_> = (0..64)
+        .map(|i| glam::vec3((i % 8) as f32 * 0.1 - 0.4, (i / 8) as f32 * 0.1 - 0.4, 5.0))
+        .collect();
+    let splats = Splats::<DiffBack>::from_raw(&means, None, None, None, None, &device);
+
+    let mut grads = vec![];
+    for width in [8, 16] {
+        let options = RenderOptions::default().with_tile_width(width);
+        let splats = splats.clone().with_render_options(options);
+        let (img, _) = splats.render(&cam, img_size, false);
+        // A render with another tile width in between mustn't change the backward pass.
+        let other = RenderOptions::default().with_tile_width(24 - width);
+        let _ = splats
+            .clone()
+            .with_render_options(other)
+            .render(&cam, img_size, false);
+        let backward = img.powi_scalar(2.0).mean().backward();
+        let v_opac = splats
+            .raw_opacity
+            .grad(&backward)
+            .expect("No opacity gradient");
+        grads.push(
+            v_opac
+                .into_data_async()
+                .await
+                .to_vec::<f32>()
+                .expect("Wrong type"),
+        );
+    }
+
+    for (a, b) in grads[0].iter().zip(&grads[1]) {
+        assert_approx_eq!(a, b, 1e-5);
+    }
+}
+
+#[tokio::test]
+async fn lod_merges_far_splats() {
+    let device = WgpuDevice::DefaultDevice;
+    let means: Vec<_> = (0..512)
+        .map(|i| glam::vec3((i % 8) as f32, ((i / 8) % 8) as f32, (i / 64) as f32) * 0.1)
+        .collect();
+    let splats = Splats::<Wgpu>::from_raw(&means, None, None, None, None, &device);
+    let lod = SplatLod::new(&splats, &LodConfig::new().with_leaf_size(8))
+        .await
+        .expect("Failed to build tree");
+    assert_eq!(lod.splats().num_splats(), 512 + lod.num_nodes());
+
+    let img_size = glam::uvec2(64, 64);
+    let cam = |distance: f32| {
+        Camera::new(
+            glam::vec3(0.35, 0.35, -distance),
+            glam::Quat::IDENTITY,
+            1.0,
+            1.0,
+            glam::vec2(0.5, 0.5),
+        )
+    };
+
+    // Up close without any error, all splats are drawn.
+    assert_eq!(lod.select(&cam(5.0), img_size, 0.0).len(), 512);
+    // Far away everything is merged into the root.
+    assert_eq!(lod.select(&cam(1e4), img_size, 2.0).len(), 1);
+}
+
+#[tokio::test]
+async fn selection_edits() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(32, 32);
+    let device = WgpuDevice::DefaultDevice;
+    let splats = Splats::<Wgpu>::from_raw(
+        &[glam::vec3(0.0, 0.0, 5.0), glam::vec3(0.5, 0.0, 5.0)],
+        None,
+        None,
+        None,
+        None,
+        &device,
+    );
+    let count = |selection: Tensor<Wgpu, 1, Bool>| async move {
+        selection.int().sum().into_scalar_async().await
+    };
+
+    // The first splat is in the middle of the image, the second further right.
+    let lasso = [
+        glam::vec2(12.0, 12.0),
+        glam::vec2(20.0, 12.0),
+        glam::vec2(20.0, 20.0),
+        glam::vec2(12.0, 20.0),
+    ];
+    let selection = splats.select_in_lasso(&cam, img_size, &lasso);
+    assert_eq!(count(selection.clone()).await, 1);
+    let crop_box = CropBox::new(glam::vec3(0.0, 0.0, 5.0), glam::Vec3::splat(0.25));
+    assert_eq!(count(splats.select_in_box(&crop_box)).await, 1);
+
+    let center = splats.selection_center(selection.clone()).await;
+    assert_eq!(center, Some(glam::vec3(0.0, 0.0, 5.0)));
+
+    let transform = crate::selection::transform_around(
+        glam::vec3(0.0, 0.0, 5.0),
+        glam::vec3(0.0, 1.0, 0.0),
+        glam::Quat::IDENTITY,
+        1.0,
+    );
+    let moved = splats.transformed_selection(selection.clone(), transform);
+    let means = moved
+        .means
+        .val()
+        .into_data_async()
+        .await
+        .to_vec::<f32>()
+        .expect("Wrong type");
+    assert_eq!(means, vec![0.0, 1.0, 5.0, 0.5, 0.0, 5.0]);
+
+    let deleted = splats.clone().deleted(selection.clone()).await;
+    assert_eq!(deleted.num_splats(), 1);
+    let duplicated = splats.duplicated(selection).await;
+    assert_eq!(duplicated.num_splats(), 3);
+}
+
+#[tokio::test]
+async fn interpolation_keeps_temporal() {
+    let device = WgpuDevice::DefaultDevice;
+    let means = [glam::vec3(0.0, 0.0, 5.0), glam::vec3(0.5, 0.0, 5.0)];
+    let splats = Splats::<Wgpu>::from_raw(&means, None, None, None, None, &device);
+    let temporal = |velocity: f32, time: f32| {
+        TemporalAttributes::new(
+            Tensor::full([2, 3], velocity, &device),
+            Tensor::full([2], time, &device),
+            Tensor::full([2], 0.0, &device),
+        )
+    };
+    let read = |t: Tensor<Wgpu, 1>| t.into_data().to_vec::<f32>().expect("Wrong type");
+
+    let a = splats.clone().with_temporal(temporal(0.0, 0.0));
+    let b = splats.clone().with_temporal(temporal(2.0, 1.0));
+    let blended = Splats::interpolate(&a, &b, 0.25);
+    let blended = blended.temporal.expect("Temporal attributes were dropped");
+    for v in read(blended.velocities.val().flatten(0, 1)) {
+        assert_approx_eq!(v, 0.5, 1e-5);
+    }
+    for time in read(blended.times.val()) {
+        assert_approx_eq!(time, 0.25, 1e-5);
+    }
+
+    // Only one side moving keeps its motion.
+    let blended = Splats::interpolate(&splats, &b, 0.25);
+    let blended = blended.temporal.expect("Temporal attributes were dropped");
+    assert_eq!(read(blended.times.val()), vec![1.0, 1.0]);
+
+    // Cross-fading keeps the motion of both.
+    let single = Splats::<Wgpu>::from_raw(&means[..1], None, None, None, None, &device);
+    let blended = Splats::interpolate(&single, &b, 0.5);
+    let blended = blended.temporal.expect("Temporal attributes were dropped");
+    assert_eq!(read(blended.times.val()), vec![0.0, 1.0, 1.0]);
+}
+
+#[tokio::test]
+async fn batch_matches_single_renders() {
+    let device = WgpuDevice::DefaultDevice;
+    let splats = Splats::<Wgpu>::from_raw(
+        &[glam::vec3(0.0, 0.0, 5.0), glam::vec3(0.5, 0.2, 4.0)],
+        None,
+        None,
+        None,
+        None,
+        &device,
+    );
+    let img_size = glam::uvec2(32, 32);
+    let cameras: Vec<_> = [-0.2, 0.0, 0.3]
+        .into_iter()
+        .map(|x| {
+            Camera::new(
+                glam::vec3(x, 0.0, 0.0),
+                glam::Quat::IDENTITY,
+                0.5,
+                0.5,
+                glam::vec2(0.5, 0.5),
+            )
+        })
+        .collect();
+
+    let (batch, auxes) = splats.render_batch(&cameras, img_size, false, Background::default());
+    assert_eq!(batch.dims(), [3, 32, 32, 4]);
+    assert_eq!(auxes.len(), 3);
+
+    for (i, camera) in cameras.iter().enumerate() {
+        let (img, _) = splats.render(camera, img_size, false);
+        let view = batch.clone().slice([i..i + 1]).squeeze::<3>(0);
+        let diff = (view - img).abs().max().into_scalar_async().await;
+        assert!(diff < 1e-6, "View {i} differs by {diff}");
+    }
+}
+
+#[tokio::test]
+async fn renders_feature_channels() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(48, 32);
+    let device = WgpuDevice::DefaultDevice;
+    let means: Vec<_> = (0..16)
+        .map(|i| glam::vec3((i % 4) as f32 * 0.2 - 0.3, (i / 4) as f32 * 0.2 - 0.3, 5.0))
+        .collect();
+    let splats = Splats::<DiffBack>::from_raw(&means, None, None, None, None, &device);
+
+    let channels = 12;
+    let features = Tensor::<DiffBack, 2>::ones([16, channels], &device).require_grad();
+    let (img, rendered, _) = splats.render_features(&cam, img_size, features.clone());
+    assert_eq!(rendered.dims(), [32, 48, channels]);
+
+    // Features of one blend to the alpha of the render in every channel.
+    let alpha = img.slice([0..32, 0..48, 3..4]);
+    let diff = (rendered.clone() - alpha.clone())
+        .abs()
+        .max()
+        .into_scalar_async()
+        .await;
+    assert!(diff < 1e-5, "Features differ from alpha by {diff}");
+
+    // The blend is linear in the features, so every channel gets the same gradient, which sums
+    // to the alpha of the render.
+    let backward = rendered.sum().backward();
+    let v_features = features.grad(&backward).expect("No features gradient");
+    let spread = (v_features.clone() - v_features.clone().slice([0..16, 0..1]))
+        .abs()
+        .max()
+        .into_scalar_async()
+        .await;
+    assert!(spread < 1e-4, "Channel gradients differ by {spread}");
+    let total = v_features.sum().into_scalar_async().await;
+    let expected = alpha.sum().into_scalar_async().await * channels as f32;
+    assert_approx_eq!(total, expected, expected * 1e-4);
+}
+
+#[tokio::test]
+async fn intersection_budget_shrinks_buffers() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(520, 264);
+    let device = WgpuDevice::DefaultDevice;
+    let means: Vec<_> = (0..2000)
+        .map(|i| {
+            glam::vec3(
+                (i % 40) as f32 * 0.05 - 1.0,
+                (i / 40) as f32 * 0.04 - 1.0,
+                5.0,
+            )
+        })
+        .collect();
+    let splats = Splats::<Wgpu>::from_raw(&means, None, None, None, None, &device);
+
+    let (_, aux) = splats.render(&cam, img_size, false);
+    let worst_case = aux.read_counts_async().await;
+    assert!(!worst_case.overflowed());
+
+    let mut budget = render::IntersectionBudget::default();
+    budget.update(
+        &worst_case,
+        splats.num_splats(),
+        img_size,
+        &splats.render_options,
+    );
+    let options = budget.apply(*splats.render_options, img_size);
+    let (_, aux) = splats
+        .with_render_options(options)
+        .render(&cam, img_size, false);
+    let budgeted = aux.read_counts_async().await;
+
+    assert!(budgeted.intersection_capacity < worst_case.intersection_capacity);
+    assert!(!budgeted.overflowed());
+    assert_eq!(budgeted.num_intersections, worst_case.num_intersections);
+}
+
+#[tokio::test]
+async fn intersection_overflow_is_reported() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(520, 264);
+    let device = WgpuDevice::DefaultDevice;
+    // Big splats that all cover the whole image, so there are many more intersections than
+    // the smallest buffers fit.
+    let means: Vec<_> = (0..1000)
+        .map(|i| glam::vec3((i % 10) as f32 * 0.01, (i / 10) as f32 * 0.001, 5.0))
+        .collect();
+    let log_scales = vec![glam::Vec3::splat(1.0); means.len()];
+    let options = RenderOptions {
+        intersection_density: Some(0.0),
+        deterministic: true,
+        ..Default::default()
+    };
+    let splats = Splats::<DiffBack>::from_raw(&means, None, Some(&log_scales), None, None, &device)
+        .with_render_options(options);
+
+    let (img, aux) = splats.render(&cam, img_size, false);
+    let stats = aux.read_counts_async().await;
+    assert!(stats.overflowed());
+    assert_eq!(stats.num_intersections, stats.intersection_capacity);
+
+    // The extra intersections are dropped, the backward pass only sees the ones that fit.
+    let backward = img.mean().backward();
+    let v_means = splats.means.grad(&backward).expect("No means gradient");
+    let max_grad = v_means.abs().max().into_scalar_async().await;
+    assert!(max_grad.is_finite());
+}
+
+#[tokio::test]
+async fn occlusion_culling_keeps_render() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(64, 64);
+    let device = WgpuDevice::DefaultDevice;
+    // Layers of large opaque splats, most of which are hidden behind the first few.
+    let means: Vec<_> = (0..32)
+        .map(|i| glam::vec3(0.0, 0.0, 2.0 + i as f32 * 0.5))
+        .collect();
+    let rotations = vec![glam::Quat::IDENTITY; means.len()];
+    let log_scales = vec![glam::Vec3::splat(3.0); means.len()];
+    let raw_opacities = vec![8.0; means.len()];
+    let splats = Splats::<Wgpu>::from_raw(
+        &means,
+        Some(&rotations),
+        Some(&log_scales),
+        None,
+        Some(&raw_opacities),
+        &device,
+    );
+
+    let (img, aux) = splats.render(&cam, img_size, false);
+    let options = RenderOptions {
+        occlusion_culling: true,
+        ..Default::default()
+    };
+    let (culled_img, culled_aux) = splats
+        .with_render_options(options)
+        .render(&cam, img_size, false);
+
+    assert_eq!(
+        img.into_data_async().await,
+        culled_img.into_data_async().await
+    );
+    assert_eq!(
+        aux.final_index.into_data_async().await,
+        culled_aux.final_index.into_data_async().await
+    );
+    let stats = culled_aux.read_stats_async().await;
+    assert!(stats.culled_intersections > 0);
+    assert!(stats.culled_intersections < stats.num_intersections);
+}
+
+#[tokio::test]
+async fn absgrad_doesnt_cancel() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(64, 64);
+    let device = WgpuDevice::DefaultDevice;
+    // A splat in the center, so the gradients of the pixels around it cancel out.
+    let splats = Splats::<DiffBack>::from_raw(
+        &[glam::vec3(0.0, 0.0, 5.0)],
+        None,
+        Some(&[glam::Vec3::splat(0.2f32.ln())]),
+        None,
+        None,
+        &device,
+    );
+
+    let mut xy_grads = vec![];
+    let mut mean_grads = vec![];
+    for absgrad in [false, true] {
+        let options = RenderOptions {
+            absgrad,
+            ..Default::default()
+        };
+        let (img, _) = splats
+            .clone()
+            .with_render_options(options)
+            .render(&cam, img_size, false);
+        let backward = img.powi_scalar(2.0).mean().backward();
+        let v_xy = splats.xys_dummy.grad(&backward).expect("No xy gradient");
+        let v_means = splats.means.grad(&backward).expect("No means gradient");
+        xy_grads.push(
+            v_xy.into_data_async()
+                .await
+                .to_vec::<f32>()
+                .expect("Wrong type"),
+        );
+        mean_grads.push(
+            v_means
+                .into_data_async()
+                .await
+                .to_vec::<f32>()
+                .expect("Wrong type"),
+        );
+    }
+
+    let norm = |v: &[f32]| (v[0] * v[0] + v[1] * v[1]).sqrt();
+    assert!(norm(&xy_grads[1]) > 100.0 * norm(&xy_grads[0]));
+    // The gradients of the means still come from the signed gradients.
+    for (a, b) in mean_grads[0].iter().zip(&mean_grads[1]) {
+        assert_approx_eq!(a, b, 1e-6);
+    }
+}
+
+#[tokio::test]
+async fn knn_matches_brute_force() {
+    use rand::{Rng, SeedableRng};
+
+    let device = WgpuDevice::DefaultDevice;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let mut points: Vec<glam::Vec3> = (0..500)
+        .map(|_| glam::vec3(rng.gen(), rng.gen(), rng.gen()))
+        .collect();
+    // An isolated point, with no neighbours within the search.
+    points.push(glam::Vec3::splat(10.0));
+
+    let flat: Vec<f32> = points.iter().flat_map(|p| p.to_array()).collect();
+    let tensor =
+        Tensor::<Wgpu, 1>::from_floats(flat.as_slice(), &device).reshape([points.len(), 3]);
+    let cell_size = 0.1;
+    let mean_sq_dist: Tensor<Wgpu, 1> = Tensor::from_primitive(TensorPrimitive::Float(
+        Wgpu::knn_mean_sq_dist(tensor.into_primitive().tensor(), cell_size),
+    ));
+    let mean_sq_dist = mean_sq_dist
+        .into_data_async()
+        .await
+        .to_vec::<f32>()
+        .expect("f32 data");
+
+    for (i, p) in points[..500].iter().enumerate() {
+        let mut dists: Vec<f32> = points
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, q)| p.distance_squared(*q))
+            .collect();
+        dists.sort_by(f32::total_cmp);
+        let expected = dists[..3].iter().sum::<f32>() / 3.0;
+        assert_approx_eq!(mean_sq_dist[i], expected, 1e-6);
+    }
+
+    // Neighbours out of reach of the 4 rings of cells count as 5 cells away.
+    assert_approx_eq!(mean_sq_dist[500], (5.0 * cell_size).powi(2), 1e-6);
+}
+
+#[tokio::test]
+async fn antialiased_gradients_match_finite_differences() {
+    let cam = Camera::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::IDENTITY,
+        0.5,
+        0.5,
+        glam::vec2(0.5, 0.5),
+    );
+    let img_size = glam::uvec2(32, 32);
+    let device = WgpuDevice::DefaultDevice;
+    // A rotated splat only a few pixels wide, where the opacity compensation matters most.
+    let splats = Splats::<DiffBack>::from_raw(
+        &[glam::vec3(0.02, -0.01, 5.0)],
+        Some(&[glam::Quat::from_euler(glam::EulerRot::XYZ, 0.3, 0.5, 0.1)]),
+        Some(&[glam::vec3(0.03f32.ln(), 0.06f32.ln(), 0.04f32.ln())]),
+        None,
+        Some(&[0.5]),
+        &device,
+    );
+    // Weigh the pixels unevenly, so the gradients of the pixels don't cancel out.
+    let weights: Vec<f32> = (0..32 * 32 * 3)
+        .map(|i| ((i * 7919) % 13) as f32 / 13.0)
+        .collect();
+    let weights =
+        Tensor::<DiffBack, 1>::from_floats(weights.as_slice(), &device).reshape([32, 32, 3]);
+    let loss = |splats: &Splats<DiffBack>| {
+        let (img, _) = splats.render_antialiased(&cam, img_size, false);
+        (img.slice([0..32, 0..32, 0..3]) * weights.clone()).sum()
+    };
+
+    let backward = loss(&splats).backward();
+    let v_opac = splats
+        .raw_opacity
+        .grad(&backward)
+        .expect("No opacity gradient")
+        .into_data_async()
+        .await
+        .to_vec::<f32>()
+        .expect("Wrong type");
+    let v_scales = splats
+        .log_scales
+        .grad(&backward)
+        .expect("No scale gradient")
+        .into_data_async()
+        .await
+        .to_vec::<f32>()
+        .expect("Wrong type");
+
+    // Central differences of the loss.
+    let h = 1e-2;
+    let central = |perturb: &dyn Fn(&mut Splats<DiffBack>, f32)| {
+        let mut plus = splats.clone();
+        perturb(&mut plus, h);
+        let mut minus = splats.clone();
+        perturb(&mut minus, -h);
+        (loss(&plus).into_scalar() - loss(&minus).into_scalar()) / (2.0 * h)
+    };
+
+    let fd_opac = central(&|s, d| Splats::map_param(&mut s.raw_opacity, |o| o + d));
+    assert_approx_eq!(v_opac[0], fd_opac, 0.05 * fd_opac.abs().max(1e-2));
+
+    // The scales change the covariance, and through the compensation the opacity too.
+    for axis in 0..3 {
+        let fd_scale = central(&|s, d| {
+            let mut offset = [0.0; 3];
+            offset[axis] = d;
+            let offset = Tensor::<DiffBack, 1>::from_floats(offset, &device).reshape([1, 3]);
+            Splats::map_param(&mut s.log_scales, |l| l + offset);
+        });
+        assert_approx_eq!(v_scales[axis], fd_scale, 0.05 * fd_scale.abs().max(1e-2));
+    }
+}
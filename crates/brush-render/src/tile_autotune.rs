@@ -0,0 +1,239 @@
+//! Pick the fastest tile width for a GPU, see [`crate::render::RenderOptions::tile_width`].
+//!
+//! A benchmark frame is rendered with every tile width the GPU supports, and the fastest width
+//! is kept. The result is saved per adapter in a config file, so this only runs once per GPU.
+//! The tuned width is the default of new [`RenderOptions`], renders that already have their
+//! options keep their width.
+
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use glam::Vec3;
+use rand::{Rng, SeedableRng};
+
+use crate::{
+    camera::Camera,
+    gaussian_splats::Splats,
+    render::{RenderOptions, TILE_WIDTHS},
+    shaders, Backend,
+};
+
+const BENCH_SPLATS: usize = 100_000;
+const BENCH_SIZE: glam::UVec2 = glam::uvec2(1280, 720);
+const BENCH_FRAMES: u32 = 10;
+
+struct AdapterInfo {
+    key: String,
+    limits: wgpu::Limits,
+    tuned: bool,
+    tile_width: u32,
+}
+
+static ADAPTER: Mutex<Option<AdapterInfo>> = Mutex::new(None);
+
+/// The tile widths whose kernels fit in the workgroup limits of a device. The backward pass
+/// uses the most workgroup memory, with two splats and ids per pixel of a tile.
+pub fn supported_tile_widths(limits: &wgpu::Limits) -> Vec<u32> {
+    let bytes_per_pixel = 2 * (size_of::<shaders::helpers::ProjectedSplat>() + size_of::<i32>());
+    TILE_WIDTHS
+        .into_iter()
+        .filter(|&width| {
+            let pixels = width * width;
+            pixels <= limits.max_compute_invocations_per_workgroup
+                && width <= limits.max_compute_workgroup_size_x
+                && width <= limits.max_compute_workgroup_size_y
+                && pixels as usize * bytes_per_pixel
+                    <= limits.max_compute_workgroup_storage_size as usize
+        })
+        .collect()
+}
+
+// The width to use before tuning. The compiled in default if the device supports it, or else
+// the widest width it supports.
+fn untuned_tile_width(limits: &wgpu::Limits) -> u32 {
+    let supported = supported_tile_widths(limits);
+    if supported.contains(&shaders::helpers::TILE_WIDTH) {
+        shaders::helpers::TILE_WIDTH
+    } else {
+        supported.last().copied().unwrap_or(TILE_WIDTHS[0])
+    }
+}
+
+/// The tile width of new [`RenderOptions`]. This is the tuned width of the adapter if it was
+/// tuned, see [`autotune_tile_width`], or else a width the adapter supports.
+pub fn default_tile_width() -> u32 {
+    ADAPTER
+        .lock()
+        .expect("Adapter lock poisoned")
+        .as_ref()
+        .map_or(shaders::helpers::TILE_WIDTH, |adapter| adapter.tile_width)
+}
+
+// Where the tuned tile widths are saved, as a json map from adapter to width.
+fn config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("brush").join("tile_widths.json"))
+}
+
+fn read_config() -> HashMap<String, u32> {
+    config_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn write_config(key: &str, width: u32) -> anyhow::Result<()> {
+    let path = config_path().ok_or_else(|| anyhow::anyhow!("No config directory"))?;
+    let mut config = read_config();
+    config.insert(key.to_owned(), width);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&config)?)?;
+    Ok(())
+}
+
+/// Remember the adapter that's rendering, and use its saved tile width if it was tuned before.
+pub(crate) fn init_adapter(adapter: &wgpu::Adapter) {
+    let info = adapter.get_info();
+    // The fastest width can change with the driver, so tune again after driver updates.
+    let key = format!("{} ({:?}, {})", info.name, info.backend, info.driver_info);
+    let limits = adapter.limits();
+
+    let saved = read_config()
+        .get(&key)
+        .copied()
+        .filter(|width| supported_tile_widths(&limits).contains(width));
+    if let Some(width) = saved {
+        log::info!("Using tuned tile width {width}");
+    }
+
+    *ADAPTER.lock().expect("Adapter lock poisoned") = Some(AdapterInfo {
+        key,
+        tile_width: saved.unwrap_or_else(|| untuned_tile_width(&limits)),
+        limits,
+        tuned: saved.is_some(),
+    });
+}
+
+/// A seeded scene of `num` random splats in front of a camera, with a mix of sizes. Used to
+/// tune the tile width, and by the kernel benchmarks.
+pub fn bench_scene<B: Backend>(num: usize, device: &B::Device) -> (Splats<B>, Camera) {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let means: Vec<_> = (0..num)
+        .map(|_| {
+            let z = rng.gen_range(2.0..10.0);
+            let xy = glam::vec2(rng.gen_range(-0.8..0.8), rng.gen_range(-0.5..0.5)) * z;
+            xy.extend(z)
+        })
+        .collect();
+    let log_scales: Vec<_> = (0..num)
+        .map(|_| Vec3::splat(rng.gen_range(-5.0..-1.5)))
+        .collect();
+    let splats = Splats::from_raw(&means, None, Some(&log_scales), None, None, device);
+    let camera = Camera::new(
+        Vec3::ZERO,
+        glam::Quat::IDENTITY,
+        1.2,
+        0.75,
+        glam::vec2(0.5, 0.5),
+    );
+    (splats, camera)
+}
+
+async fn time_renders<B: Backend>(splats: &Splats<B>, camera: &Camera) -> Duration {
+    // Warm up, which also compiles the kernels.
+    let (img, _) = splats.render(camera, BENCH_SIZE, true);
+    let _ = img.into_data_async().await;
+
+    let start = Instant::now();
+    let mut last = None;
+    for _ in 0..BENCH_FRAMES {
+        last = Some(splats.render(camera, BENCH_SIZE, true).0);
+    }
+    // Reading back the last frame waits for all of them.
+    if let Some(img) = last {
+        let _ = img.into_data_async().await;
+    }
+    start.elapsed()
+}
+
+/// Render a benchmark frame with every supported tile width, and keep the fastest one. This
+/// only runs once per adapter, later calls return the tuned width right away.
+///
+/// Does nothing on the web, which has no timers or config files, or when no adapter was set up
+/// with one of the `burn_init` functions.
+pub async fn autotune_tile_width<B: Backend>(device: &B::Device) -> u32 {
+    let (key, widths) = {
+        let adapter = ADAPTER.lock().expect("Adapter lock poisoned");
+        match adapter.as_ref() {
+            Some(adapter) if !adapter.tuned && !cfg!(target_family = "wasm") => {
+                (adapter.key.clone(), supported_tile_widths(&adapter.limits))
+            }
+            _ => return default_tile_width(),
+        }
+    };
+
+    let (splats, camera) = bench_scene::<B>(BENCH_SPLATS, device);
+
+    let mut best = (default_tile_width(), Duration::MAX);
+    for width in widths {
+        let options = RenderOptions::default().with_tile_width(width);
+        let time = time_renders(&splats.clone().with_render_options(options), &camera).await;
+        log::info!("Tile width {width}: {time:?} for {BENCH_FRAMES} frames");
+        if time < best.1 {
+            best = (width, time);
+        }
+    }
+
+    let width = best.0;
+    log::info!("Tuned tile width to {width}");
+
+    if let Err(e) = write_config(&key, width) {
+        log::warn!("Failed to save tuned tile width: {e}");
+    }
+    if let Some(adapter) = ADAPTER.lock().expect("Adapter lock poisoned").as_mut() {
+        adapter.tuned = true;
+        adapter.tile_width = width;
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{supported_tile_widths, untuned_tile_width};
+
+    #[test]
+    fn limits_filter_tile_widths() {
+        let desktop = wgpu::Limits {
+            max_compute_invocations_per_workgroup: 1024,
+            max_compute_workgroup_size_x: 1024,
+            max_compute_workgroup_size_y: 1024,
+            max_compute_workgroup_storage_size: 32768,
+            ..Default::default()
+        };
+        assert_eq!(supported_tile_widths(&desktop), vec![8, 16]);
+
+        // The default WebGPU limits don't have room for the backward pass of 16x16 tiles.
+        assert_eq!(supported_tile_widths(&wgpu::Limits::default()), vec![8]);
+    }
+
+    #[test]
+    fn untuned_width_is_supported() {
+        let desktop = wgpu::Limits {
+            max_compute_invocations_per_workgroup: 1024,
+            max_compute_workgroup_size_x: 1024,
+            max_compute_workgroup_size_y: 1024,
+            max_compute_workgroup_storage_size: 32768,
+            ..Default::default()
+        };
+        assert_eq!(untuned_tile_width(&desktop), 16);
+        assert_eq!(untuned_tile_width(&wgpu::Limits::default()), 8);
+    }
+}
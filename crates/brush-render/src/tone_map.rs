@@ -0,0 +1,132 @@
+//! Tone mapping of linear HDR colors for display.
+//!
+//! Splats trained on HDR images, eg. EXR files, have linear colors that go well above 1. These
+//! are only mapped to displayable sRGB colors when shown, so the splats themselves keep the
+//! full range of the capture.
+
+use burn::{prelude::Backend, tensor::Tensor};
+use glam::Vec3;
+
+/// The curve that compresses linear colors into the displayable range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// The filmic ACES curve, as fitted by Krzysztof Narkowicz. Bright highlights roll off
+    /// smoothly, with a bit more contrast in the midtones.
+    #[default]
+    Aces,
+    /// `x / (1 + x)`, which never clips but looks flat.
+    Reinhard,
+    /// No curve, everything above 1 clips.
+    Clamp,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [Self; 3] = [Self::Aces, Self::Reinhard, Self::Clamp];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Aces => "ACES",
+            Self::Reinhard => "Reinhard",
+            Self::Clamp => "Clamp",
+        }
+    }
+
+    fn map(&self, x: f32) -> f32 {
+        match self {
+            Self::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+            Self::Reinhard => x / (1.0 + x),
+            Self::Clamp => x,
+        }
+    }
+}
+
+/// Maps linear HDR colors to sRGB colors in 0..1.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    /// Exposure adjustment in stops, colors are scaled by `2^exposure` before the curve.
+    pub exposure: f32,
+}
+
+fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl ToneMapping {
+    /// Map a single color.
+    pub fn map(&self, color: Vec3) -> Vec3 {
+        let scale = self.exposure.exp2();
+        Vec3::from_array(
+            (color.max(Vec3::ZERO) * scale)
+                .to_array()
+                .map(|x| linear_to_srgb(self.operator.map(x).clamp(0.0, 1.0))),
+        )
+    }
+
+    /// Map an image of shape `[h, w, c]`, with rgb in the first 3 channels. Any other channels,
+    /// like alpha, are kept as is.
+    pub fn apply<B: Backend>(&self, img: Tensor<B, 3>) -> Tensor<B, 3> {
+        let [h, w, c] = img.dims();
+        let x = img
+            .clone()
+            .slice([0..h, 0..w, 0..3])
+            .clamp_min(0.0)
+            .mul_scalar(self.exposure.exp2());
+
+        let x = match self.operator {
+            ToneMapOperator::Aces => {
+                let num = x.clone() * (x.clone() * 2.51 + 0.03);
+                let den = x.clone() * (x * 2.43 + 0.59) + 0.14;
+                num / den
+            }
+            ToneMapOperator::Reinhard => x.clone() / (x + 1.0),
+            ToneMapOperator::Clamp => x,
+        }
+        .clamp(0.0, 1.0);
+
+        let linear = x.clone() * 12.92;
+        let srgb = x.clone().powf_scalar(1.0 / 2.4) * 1.055 - 0.055;
+        let out = srgb.mask_where(x.lower_equal_elem(0.003_130_8), linear);
+
+        if c > 3 {
+            Tensor::cat(vec![out, img.slice([0..h, 0..w, 3..c])], 2)
+        } else {
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_hdr_to_display_range() {
+        for operator in ToneMapOperator::ALL {
+            let tone_mapping = ToneMapping {
+                operator,
+                exposure: 0.0,
+            };
+            assert_eq!(tone_mapping.map(Vec3::ZERO), Vec3::ZERO);
+            let bright = tone_mapping.map(Vec3::splat(100.0));
+            assert!(bright.cmple(Vec3::ONE).all());
+            assert!(bright.x > 0.9);
+        }
+
+        // Without a curve, linear 0.5 is sRGB 0.735, one stop up clips.
+        let clamp = ToneMapping {
+            operator: ToneMapOperator::Clamp,
+            exposure: 0.0,
+        };
+        assert!((clamp.map(Vec3::splat(0.5)).x - 0.735).abs() < 1e-3);
+        let brighter = ToneMapping {
+            exposure: 1.0,
+            ..clamp
+        };
+        assert!(brighter.map(Vec3::splat(0.5)).abs_diff_eq(Vec3::ONE, 1e-6));
+    }
+}
@@ -8,6 +8,8 @@ fn main() -> miette::Result<()> {
             "src/shaders/sort_scan_add.wgsl",
             "src/shaders/sort_scan.wgsl",
             "src/shaders/sort_scatter.wgsl",
+            "src/shaders/sort_global_hist.wgsl",
+            "src/shaders/sort_onesweep.wgsl",
         ],
         &["src/shaders/sorting.wgsl"],
         "src/shaders",
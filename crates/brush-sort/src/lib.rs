@@ -10,10 +10,13 @@ use burn_jit::JitBackend;
 use burn_wgpu::JitTensor;
 use burn_wgpu::WgpuRuntime;
 use shaders::sort_count;
+use shaders::sort_global_hist;
+use shaders::sort_onesweep;
 use shaders::sort_reduce;
 use shaders::sort_scan;
 use shaders::sort_scan_add;
 use shaders::sort_scatter;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use brush_kernel::kernel_source_gen;
 
@@ -22,19 +25,88 @@ mod shaders;
 const WG: u32 = shaders::sorting::WG;
 const ELEMENTS_PER_THREAD: u32 = shaders::sorting::ELEMENTS_PER_THREAD;
 const BLOCK_SIZE: u32 = WG * ELEMENTS_PER_THREAD;
+const BITS_PER_PASS: u32 = shaders::sorting::BITS_PER_PASS;
 const BIN_COUNT: u32 = shaders::sorting::BIN_COUNT;
+const MAX_PASSES: u32 = shaders::sorting::MAX_PASSES;
 
 kernel_source_gen!(SortCount {}, sort_count);
 kernel_source_gen!(SortReduce {}, sort_reduce);
 kernel_source_gen!(SortScanAdd {}, sort_scan_add);
 kernel_source_gen!(SortScan {}, sort_scan);
 kernel_source_gen!(SortScatter {}, sort_scatter);
+kernel_source_gen!(SortGlobalHist {}, sort_global_hist);
+kernel_source_gen!(SortOnesweep {}, sort_onesweep);
+
+type InnerBackend = JitBackend<WgpuRuntime, f32, i32, u32>;
+
+/// How keys are sorted, see [`radix_argsort_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortMethod {
+    /// Count, reduce, scan and scatter the keys in separate dispatches for every pass. This works
+    /// on any WebGPU device.
+    MultiPass,
+    /// Sort every pass in a single dispatch, where workgroups wait on the workgroups before them.
+    /// This is faster, but needs the GPU to keep running the workgroups that are waited on, which
+    /// WebGPU doesn't guarantee, see [`onesweep_supported`].
+    Onesweep,
+}
+
+static ONESWEEP_AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Allow [`radix_argsort`] to use the onesweep sort. This is off by default, as the sort hangs on
+/// devices that don't guarantee forward progress.
+pub fn set_onesweep_available(available: bool) {
+    ONESWEEP_AVAILABLE.store(available, Ordering::SeqCst);
+}
 
+pub fn has_onesweep() -> bool {
+    ONESWEEP_AVAILABLE.load(Ordering::SeqCst)
+}
+
+/// Whether an adapter is known to keep making progress on workgroups that other workgroups wait
+/// on, which the onesweep sort needs. WebGPU makes no such promise, but native drivers for real
+/// GPUs keep running workgroups that have started. Browsers, software rasterizers and the GL
+/// backend fall back to the multi-pass sort.
+pub fn onesweep_supported(info: &wgpu::AdapterInfo) -> bool {
+    !cfg!(target_family = "wasm")
+        && matches!(
+            info.device_type,
+            wgpu::DeviceType::DiscreteGpu | wgpu::DeviceType::IntegratedGpu
+        )
+        && matches!(
+            info.backend,
+            wgpu::Backend::Vulkan | wgpu::Backend::Metal | wgpu::Backend::Dx12
+        )
+}
+
+fn zeros(size: u32, device: &burn_wgpu::WgpuDevice) -> JitTensor<WgpuRuntime> {
+    Tensor::<InnerBackend, 1, Int>::zeros([size as usize], device).into_primitive()
+}
+
+/// Sort `input_values` by the lowest `sorting_bits` of `input_keys`. The sort is stable, and
+/// only the first `n_sort` elements are sorted. This uses the onesweep sort where it's
+/// available, see [`set_onesweep_available`].
 pub fn radix_argsort(
     input_keys: JitTensor<WgpuRuntime>,
     input_values: JitTensor<WgpuRuntime>,
     n_sort: &JitTensor<WgpuRuntime>,
     sorting_bits: u32,
+) -> (JitTensor<WgpuRuntime>, JitTensor<WgpuRuntime>) {
+    let method = if has_onesweep() {
+        SortMethod::Onesweep
+    } else {
+        SortMethod::MultiPass
+    };
+    radix_argsort_with(method, input_keys, input_values, n_sort, sorting_bits)
+}
+
+/// Like [`radix_argsort`], with the sort picked by the caller.
+pub fn radix_argsort_with(
+    method: SortMethod,
+    input_keys: JitTensor<WgpuRuntime>,
+    input_values: JitTensor<WgpuRuntime>,
+    n_sort: &JitTensor<WgpuRuntime>,
+    sorting_bits: u32,
 ) -> (JitTensor<WgpuRuntime>, JitTensor<WgpuRuntime>) {
     assert_eq!(
         input_keys.shape.dims[0], input_values.shape.dims[0],
@@ -43,8 +115,20 @@ pub fn radix_argsort(
     assert_eq!(n_sort.shape.dims[0], 1, "Sort count must have one element");
     assert!(sorting_bits <= 32, "Can only sort up to 32 bits");
 
-    let _span = tracing::trace_span!("Radix sort").entered();
+    let _span = tracing::trace_span!("Radix sort", ?method).entered();
+
+    match method {
+        SortMethod::MultiPass => argsort_multi_pass(input_keys, input_values, n_sort, sorting_bits),
+        SortMethod::Onesweep => argsort_onesweep(input_keys, input_values, n_sort, sorting_bits),
+    }
+}
 
+fn argsort_multi_pass(
+    input_keys: JitTensor<WgpuRuntime>,
+    input_values: JitTensor<WgpuRuntime>,
+    n_sort: &JitTensor<WgpuRuntime>,
+    sorting_bits: u32,
+) -> (JitTensor<WgpuRuntime>, JitTensor<WgpuRuntime>) {
     let client = &input_keys.client.clone();
     let max_n = input_keys.shape.dims[0] as u32;
 
@@ -54,7 +138,7 @@ pub fn radix_argsort(
     let max_needed_wgs = max_n.div_ceil(BLOCK_SIZE);
 
     let num_wgs = create_dispatch_buffer(n_sort.clone(), [BLOCK_SIZE, 1, 1]);
-    let num_reduce_wgs: Tensor<JitBackend<WgpuRuntime, f32, i32, u32>, 1, Int> =
+    let num_reduce_wgs: Tensor<InnerBackend, 1, Int> =
         Tensor::from_primitive(create_dispatch_buffer(num_wgs.clone(), [BLOCK_SIZE, 1, 1]))
             * Tensor::from_ints([BIN_COUNT, 1, 1], device);
     let num_reduce_wgs: JitTensor<WgpuRuntime> = num_reduce_wgs.into_primitive();
@@ -62,9 +146,11 @@ pub fn radix_argsort(
     let mut cur_keys = input_keys;
     let mut cur_vals = input_values;
 
-    for pass in 0..sorting_bits.div_ceil(4) {
+    for pass in 0..sorting_bits.div_ceil(BITS_PER_PASS) {
         let uniforms_buffer: JitTensor<WgpuRuntime> = create_uniform_buffer(
-            shaders::sort_count::Uniforms { shift: pass * 4 },
+            sort_count::Uniforms {
+                shift: pass * BITS_PER_PASS,
+            },
             device,
             client,
         );
@@ -160,76 +246,173 @@ pub fn radix_argsort(
     (cur_keys, cur_vals)
 }
 
+fn argsort_onesweep(
+    input_keys: JitTensor<WgpuRuntime>,
+    input_values: JitTensor<WgpuRuntime>,
+    n_sort: &JitTensor<WgpuRuntime>,
+    sorting_bits: u32,
+) -> (JitTensor<WgpuRuntime>, JitTensor<WgpuRuntime>) {
+    let client = &input_keys.client.clone();
+    let max_n = input_keys.shape.dims[0] as u32;
+
+    // compute buffer and dispatch sizes
+    let device = &input_keys.device.clone();
+
+    let max_needed_wgs = max_n.div_ceil(BLOCK_SIZE);
+    let num_passes = sorting_bits.div_ceil(BITS_PER_PASS);
+
+    let num_wgs = create_dispatch_buffer(n_sort.clone(), [BLOCK_SIZE, 1, 1]);
+
+    // Count the digits for all passes upfront.
+    let global_hist = zeros(MAX_PASSES * BIN_COUNT, device);
+
+    // SAFETY: wgsl FFI, kernel checked to have no OOB.
+    unsafe {
+        client.execute_unchecked(
+            SortGlobalHist::task(),
+            CubeCount::Dynamic(num_wgs.clone().handle.binding()),
+            vec![
+                create_uniform_buffer(sort_global_hist::Uniforms { num_passes }, device, client)
+                    .handle
+                    .binding(),
+                n_sort.clone().handle.binding(),
+                input_keys.handle.clone().binding(),
+                global_hist.clone().handle.binding(),
+            ],
+        );
+    }
+
+    // Each pass needs a partition counter, and the status of each bin for each tile.
+    let status_stride = 1 + max_needed_wgs * BIN_COUNT;
+    let tile_status = zeros((num_passes * status_stride).max(1), device);
+
+    let mut cur_keys = input_keys;
+    let mut cur_vals = input_values;
+
+    for pass in 0..num_passes {
+        let uniforms_buffer: JitTensor<WgpuRuntime> = create_uniform_buffer(
+            sort_onesweep::Uniforms {
+                shift: pass * BITS_PER_PASS,
+                sort_pass: pass,
+                status_offset: pass * status_stride,
+            },
+            device,
+            client,
+        );
+
+        let output_keys = create_tensor::<1, _>([max_n as usize], device, client, cur_keys.dtype());
+        let output_values =
+            create_tensor::<1, _>([max_n as usize], device, client, cur_vals.dtype());
+
+        // SAFETY: Kernel has to contain no OOB indexing.
+        unsafe {
+            client.execute_unchecked(
+                SortOnesweep::task(),
+                CubeCount::Dynamic(num_wgs.clone().handle.binding()),
+                vec![
+                    uniforms_buffer.handle.clone().binding(),
+                    n_sort.clone().handle.binding(),
+                    cur_keys.handle.clone().binding(),
+                    cur_vals.handle.clone().binding(),
+                    global_hist.handle.clone().binding(),
+                    tile_status.handle.clone().binding(),
+                    output_keys.handle.clone().binding(),
+                    output_values.handle.clone().binding(),
+                ],
+            );
+        }
+
+        cur_keys = output_keys;
+        cur_vals = output_values;
+    }
+    (cur_keys, cur_vals)
+}
+
 #[cfg(all(test, not(target_family = "wasm")))]
 mod tests {
-    use crate::radix_argsort;
+    use crate::{radix_argsort_with, SortMethod};
     use burn::tensor::{Int, Tensor};
     use burn_wgpu::{JitBackend, WgpuRuntime};
     use rand::Rng;
 
     type Backend = JitBackend<WgpuRuntime, f32, i32, u32>;
 
+    const METHODS: [SortMethod; 2] = [SortMethod::MultiPass, SortMethod::Onesweep];
+
     pub fn argsort<T: Ord>(data: &[T]) -> Vec<usize> {
         let mut indices = (0..data.len()).collect::<Vec<_>>();
         indices.sort_by_key(|&i| &data[i]);
         indices
     }
 
+    fn sort(method: SortMethod, keys: &[i32], values: &[i32], bits: u32) -> (Vec<i32>, Vec<i32>) {
+        let device = Default::default();
+        let keys_t = Tensor::<Backend, 1, Int>::from_ints(keys, &device).into_primitive();
+        let values_t = Tensor::<Backend, 1, Int>::from_ints(values, &device).into_primitive();
+        let num_points =
+            Tensor::<Backend, 1, Int>::from_ints([keys.len() as i32], &device).into_primitive();
+        let (ret_keys, ret_values) =
+            radix_argsort_with(method, keys_t, values_t, &num_points, bits);
+
+        let ret_keys = Tensor::<Backend, 1, Int>::from_primitive(ret_keys).into_data();
+        let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values).into_data();
+        (
+            ret_keys.to_vec().expect("Wrong type"),
+            ret_values.to_vec().expect("Wrong type"),
+        )
+    }
+
+    fn assert_sorted(method: SortMethod, keys_inp: &[i32], values_inp: &[i32], bits: u32) {
+        let (ret_keys, ret_values) = sort(method, keys_inp, values_inp, bits);
+
+        let inds = argsort(keys_inp);
+        let ref_keys: Vec<i32> = inds.iter().map(|&i| keys_inp[i]).collect();
+        let ref_values: Vec<i32> = inds.iter().map(|&i| values_inp[i]).collect();
+        assert_eq!(ret_keys, ref_keys, "{method:?} keys aren't sorted");
+        assert_eq!(ret_values, ref_values, "{method:?} values aren't sorted");
+    }
+
     #[test]
     fn test_sorting() {
-        for i in 0..128 {
-            let keys_inp = [
-                5 + i * 4,
-                i,
-                6,
-                123,
-                74657,
-                123,
-                999,
-                2i32.pow(24) + 123,
-                6,
-                7,
-                8,
-                0,
-                i * 2,
-                16 + i,
-                128 * i,
-            ];
-
-            let values_inp: Vec<_> = keys_inp.iter().copied().map(|x| x * 2 + 5).collect();
-
-            let device = Default::default();
-            let keys = Tensor::<Backend, 1, Int>::from_ints(keys_inp, &device).into_primitive();
-
-            let values = Tensor::<Backend, 1, Int>::from_ints(values_inp.as_slice(), &device)
-                .into_primitive();
-            let num_points = Tensor::<Backend, 1, Int>::from_ints([keys_inp.len() as i32], &device)
-                .into_primitive();
-            let (ret_keys, ret_values) = radix_argsort(keys, values, &num_points, 32);
-
-            let ret_keys = Tensor::<Backend, 1, Int>::from_primitive(ret_keys).into_data();
-
-            let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values).into_data();
-
-            let inds = argsort(&keys_inp);
-
-            let ref_keys: Vec<u32> = inds.iter().map(|&i| keys_inp[i] as u32).collect();
-            let ref_values: Vec<u32> = inds.iter().map(|&i| values_inp[i] as u32).collect();
-
-            for (((key, val), ref_key), ref_val) in ret_keys
-                .as_slice::<i32>()
-                .expect("Wrong type")
-                .iter()
-                .zip(ret_values.as_slice::<i32>().expect("Wrong type"))
-                .zip(ref_keys)
-                .zip(ref_values)
-            {
-                assert_eq!(*key, ref_key as i32);
-                assert_eq!(*val, ref_val as i32);
+        for method in METHODS {
+            for i in 0..128 {
+                let keys_inp = [
+                    5 + i * 4,
+                    i,
+                    6,
+                    123,
+                    74657,
+                    123,
+                    999,
+                    2i32.pow(24) + 123,
+                    6,
+                    7,
+                    8,
+                    0,
+                    i * 2,
+                    16 + i,
+                    128 * i,
+                ];
+                let values_inp: Vec<_> = keys_inp.iter().copied().map(|x| x * 2 + 5).collect();
+                assert_sorted(method, &keys_inp, &values_inp, 32);
             }
         }
     }
 
+    #[test]
+    fn test_sorting_is_stable() {
+        // Lots of equal keys, which have to keep their input order.
+        let keys_inp: Vec<i32> = (0..50000).map(|i| (i * 7919) % 13).collect();
+        let values_inp: Vec<i32> = (0..keys_inp.len() as i32).collect();
+
+        for method in METHODS {
+            let (_, ret_values) = sort(method, &keys_inp, &values_inp, 4);
+            // The std sort is stable as well.
+            let ref_values: Vec<i32> = argsort(&keys_inp).into_iter().map(|i| i as i32).collect();
+            assert_eq!(ret_values, ref_values, "{method:?} sort isn't stable");
+        }
+    }
+
     #[test]
     fn test_sorting_big() {
         // Simulate some data as one might find for a bunch of gaussians.
@@ -247,34 +430,27 @@ mod tests {
         }
 
         let values_inp: Vec<_> = keys_inp.iter().map(|&x| x * 2 + 5).collect();
+        for method in METHODS {
+            assert_sorted(method, &keys_inp, &values_inp, 32);
+        }
+    }
 
-        let device = Default::default();
-        let keys =
-            Tensor::<Backend, 1, Int>::from_ints(keys_inp.as_slice(), &device).into_primitive();
-        let values =
-            Tensor::<Backend, 1, Int>::from_ints(values_inp.as_slice(), &device).into_primitive();
-        let num_points =
-            Tensor::<Backend, 1, Int>::from_ints([keys_inp.len() as i32], &device).into_primitive();
-
-        let (ret_keys, ret_values) = radix_argsort(keys, values, &num_points, 32);
-
-        let ret_keys = Tensor::<Backend, 1, Int>::from_primitive(ret_keys).to_data();
-        let ret_values = Tensor::<Backend, 1, Int>::from_primitive(ret_values).to_data();
-
-        let inds = argsort(&keys_inp);
-        let ref_keys: Vec<u32> = inds.iter().map(|&i| keys_inp[i]).collect();
-        let ref_values: Vec<u32> = inds.iter().map(|&i| values_inp[i]).collect();
-
-        for (((key, val), ref_key), ref_val) in ret_keys
-            .as_slice::<i32>()
-            .expect("Wrong type")
-            .iter()
-            .zip(ret_values.as_slice::<i32>().expect("Wrong type"))
-            .zip(ref_keys)
-            .zip(ref_values)
-        {
-            assert_eq!(*key, ref_key as i32);
-            assert_eq!(*val, ref_val as i32);
+    #[test]
+    fn onesweep_matches_multi_pass() {
+        // Sizes around the tile size, and with many tiles that have to look back over each other.
+        let mut rng = rand::thread_rng();
+        let block = crate::BLOCK_SIZE as usize;
+        for n in [1, block - 1, block, block + 1, 1000 * block + 17] {
+            let keys_inp: Vec<i32> = (0..n).map(|_| rng.gen_range(0..i32::MAX)).collect();
+            let values_inp: Vec<i32> = (0..n as i32).collect();
+            for bits in [4, 17, 32] {
+                let onesweep = sort(SortMethod::Onesweep, &keys_inp, &values_inp, bits);
+                let multi_pass = sort(SortMethod::MultiPass, &keys_inp, &values_inp, bits);
+                assert_eq!(
+                    onesweep, multi_pass,
+                    "Sorts differ for {n} keys, {bits} bits"
+                );
+            }
         }
     }
 }
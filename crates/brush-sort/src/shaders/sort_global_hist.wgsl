@@ -0,0 +1,59 @@
+#import sorting
+
+struct Uniforms {
+    num_passes: u32,
+}
+
+@group(0) @binding(0) var<storage, read> config: Uniforms;
+@group(0) @binding(1) var<storage, read> num_keys_arr: array<u32>;
+@group(0) @binding(2) var<storage, read> src: array<u32>;
+@group(0) @binding(3) var<storage, read_write> global_hist: array<atomic<u32>>;
+
+var<workgroup> histogram: array<atomic<u32>, sorting::MAX_PASSES * sorting::BIN_COUNT>;
+
+// Count the digits of all radix passes at once. The digit counts of a pass don't
+// depend on the order of the keys, so this only has to read the keys once.
+@compute
+@workgroup_size(sorting::WG, 1, 1)
+fn main(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) gid: vec3<u32>,
+) {
+    let num_keys = num_keys_arr[0];
+    let num_wgs = sorting::div_ceil(num_keys, sorting::BLOCK_SIZE);
+    let group_id = gid.x;
+
+    if group_id >= num_wgs {
+        return;
+    }
+
+    let hist_size = config.num_passes * sorting::BIN_COUNT;
+
+    if local_id.x < hist_size {
+        atomicStore(&histogram[local_id.x], 0u);
+    }
+    workgroupBarrier();
+
+    var data_index = sorting::BLOCK_SIZE * group_id + local_id.x;
+
+    for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
+        if data_index < num_keys {
+            let key = src[data_index];
+
+            for (var sort_pass = 0u; sort_pass < config.num_passes; sort_pass++) {
+                let digit = (key >> (sort_pass * sorting::BITS_PER_PASS)) & (sorting::BIN_COUNT - 1u);
+                atomicAdd(&histogram[sort_pass * sorting::BIN_COUNT + digit], 1u);
+            }
+        }
+        data_index += sorting::WG;
+    }
+    workgroupBarrier();
+
+    if local_id.x < hist_size {
+        let count = atomicLoad(&histogram[local_id.x]);
+
+        if count > 0u {
+            atomicAdd(&global_hist[local_id.x], count);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+#import sorting
+
+struct Uniforms {
+    shift: u32,
+    sort_pass: u32,
+    // Where the state of this pass starts in the tile status buffer.
+    status_offset: u32,
+}
+
+// The status of a tile is packed as 2 flag bits and a 30 bit count.
+const FLAG_NOT_READY: u32 = 0u;
+const FLAG_AGGREGATE: u32 = 1u << 30u;
+const FLAG_INCLUSIVE: u32 = 2u << 30u;
+const FLAG_MASK: u32 = 3u << 30u;
+const VALUE_MASK: u32 = (1u << 30u) - 1u;
+
+@group(0) @binding(0) var<storage, read> config: Uniforms;
+@group(0) @binding(1) var<storage, read> num_keys_arr: array<u32>;
+@group(0) @binding(2) var<storage, read> src: array<u32>;
+@group(0) @binding(3) var<storage, read> values: array<u32>;
+@group(0) @binding(4) var<storage, read> global_hist: array<u32>;
+// First element is the partition counter, then BIN_COUNT statuses per tile.
+@group(0) @binding(5) var<storage, read_write> tile_status: array<atomic<u32>>;
+@group(0) @binding(6) var<storage, read_write> out: array<u32>;
+@group(0) @binding(7) var<storage, read_write> out_values: array<u32>;
+
+var<workgroup> partition_id: u32;
+var<workgroup> tile_histogram: array<atomic<u32>, sorting::BIN_COUNT>;
+
+var<workgroup> lds_sums: array<u32, sorting::WG>;
+var<workgroup> lds_scratch: array<u32, sorting::WG>;
+var<workgroup> bin_offset_cache: array<u32, sorting::WG>;
+var<workgroup> local_histogram: array<atomic<u32>, sorting::BIN_COUNT>;
+
+// Single pass radix sort pass, following "Onesweep: A Faster Least Significant Digit Radix Sort for GPUs".
+//
+// Each workgroup counts the digits in its tile, publishes them, and finds the offsets of all
+// preceding tiles with a decoupled lookback. This means a pass is a single dispatch instead of a
+// count, reduce, scan and scatter dispatch, and keys are only read twice per pass.
+@compute
+@workgroup_size(sorting::WG, 1, 1)
+fn main(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) gid: vec3<u32>,
+) {
+    let num_keys = num_keys_arr[0];
+    let num_wgs = sorting::div_ceil(num_keys, sorting::BLOCK_SIZE);
+
+    if gid.x >= num_wgs {
+        return;
+    }
+
+    let status_base = config.status_offset;
+
+    // Tiles are assigned in the order workgroups start, rather than by workgroup id. That way
+    // a tile can only wait on tiles that are guaranteed to have started already.
+    if local_id.x == 0u {
+        partition_id = atomicAdd(&tile_status[status_base], 1u);
+    }
+    if local_id.x < sorting::BIN_COUNT {
+        atomicStore(&tile_histogram[local_id.x], 0u);
+    }
+    let part = workgroupUniformLoad(&partition_id);
+
+    let wg_block_start = sorting::BLOCK_SIZE * part;
+
+    // Count digits in this tile.
+    var data_index = wg_block_start + local_id.x;
+    for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
+        if data_index < num_keys {
+            let key_index = (src[data_index] >> config.shift) & 0xfu;
+            atomicAdd(&tile_histogram[key_index], 1u);
+        }
+        data_index += sorting::WG;
+    }
+    workgroupBarrier();
+
+    if local_id.x < sorting::BIN_COUNT {
+        let bin = local_id.x;
+        let count = atomicLoad(&tile_histogram[bin]);
+        let status_index = status_base + 1u + part * sorting::BIN_COUNT + bin;
+        var exclusive = 0u;
+
+        if part == 0u {
+            atomicStore(&tile_status[status_index], FLAG_INCLUSIVE | count);
+        } else {
+            // Publish the tile count, so later tiles don't have to wait for the lookback.
+            atomicStore(&tile_status[status_index], FLAG_AGGREGATE | count);
+
+            // Decoupled lookback: accumulate preceding tiles until a tile with
+            // an inclusive prefix is found. The first tile is always inclusive.
+            var lookback = part - 1u;
+            loop {
+                let status = atomicLoad(&tile_status[status_base + 1u + lookback * sorting::BIN_COUNT + bin]);
+                let flag = status & FLAG_MASK;
+
+                if flag == FLAG_NOT_READY {
+                    continue;
+                }
+
+                exclusive += status & VALUE_MASK;
+
+                if flag == FLAG_INCLUSIVE {
+                    break;
+                }
+                lookback -= 1u;
+            }
+
+            atomicStore(&tile_status[status_index], FLAG_INCLUSIVE | (exclusive + count));
+        }
+
+        // Offset of this digit across all keys.
+        var global_offset = 0u;
+        for (var b = 0u; b < bin; b++) {
+            global_offset += global_hist[config.sort_pass * sorting::BIN_COUNT + b];
+        }
+        bin_offset_cache[bin] = global_offset + exclusive;
+    }
+    workgroupBarrier();
+
+    // Now scatter the keys of the tile, using a stable local sort to get the rank in each bin.
+    data_index = wg_block_start + local_id.x;
+    for (var i = 0u; i < sorting::ELEMENTS_PER_THREAD; i++) {
+        if local_id.x < sorting::BIN_COUNT {
+            atomicStore(&local_histogram[local_id.x], 0u);
+        }
+        var local_key = ~0u;
+        var local_value = 0u;
+
+        if data_index < num_keys {
+            local_key = src[data_index];
+            local_value = values[data_index];
+        }
+
+        for (var bit_shift = 0u; bit_shift < sorting::BITS_PER_PASS; bit_shift += 2u) {
+            let key_index = (local_key >> config.shift) & 0xfu;
+            let bit_key = (key_index >> bit_shift) & 3u;
+            var packed_histogram = 1u << (bit_key * 8u);
+            // workgroup prefix sum
+            var sum = packed_histogram;
+            lds_scratch[local_id.x] = sum;
+            for (var i = 0u; i < 8u; i++) {
+                workgroupBarrier();
+                if local_id.x >= (1u << i) {
+                    sum += lds_scratch[local_id.x - (1u << i)];
+                }
+                workgroupBarrier();
+                lds_scratch[local_id.x] = sum;
+            }
+            workgroupBarrier();
+            packed_histogram = lds_scratch[sorting::WG - 1u];
+            packed_histogram = (packed_histogram << 8u) + (packed_histogram << 16u) + (packed_histogram << 24u);
+            var local_sum = packed_histogram;
+            if local_id.x > 0u {
+                local_sum += lds_scratch[local_id.x - 1u];
+            }
+            let key_offset = (local_sum >> (bit_key * 8u)) & 0xffu;
+
+            lds_sums[key_offset] = local_key;
+            workgroupBarrier();
+            local_key = lds_sums[local_id.x];
+            workgroupBarrier();
+
+            lds_sums[key_offset] = local_value;
+            workgroupBarrier();
+            local_value = lds_sums[local_id.x];
+            workgroupBarrier();
+        }
+        let key_index = (local_key >> config.shift) & 0xfu;
+        atomicAdd(&local_histogram[key_index], 1u);
+        workgroupBarrier();
+        var histogram_local_sum = 0u;
+        if local_id.x < sorting::BIN_COUNT {
+            histogram_local_sum = atomicLoad(&local_histogram[local_id.x]);
+        }
+        // workgroup prefix sum of histogram
+        var histogram_prefix_sum = histogram_local_sum;
+        if local_id.x < sorting::BIN_COUNT {
+            lds_scratch[local_id.x] = histogram_prefix_sum;
+        }
+        for (var i = 0u; i < 4u; i++) {
+            workgroupBarrier();
+            if local_id.x >= (1u << i) && local_id.x < sorting::BIN_COUNT {
+                histogram_prefix_sum += lds_scratch[local_id.x - (1u << i)];
+            }
+            workgroupBarrier();
+            if local_id.x < sorting::BIN_COUNT {
+                lds_scratch[local_id.x] = histogram_prefix_sum;
+            }
+        }
+        let global_offset = bin_offset_cache[key_index];
+        workgroupBarrier();
+        var local_offset = local_id.x;
+        if key_index > 0u {
+            local_offset -= lds_scratch[key_index - 1u];
+        }
+        let total_offset = global_offset + local_offset;
+        if total_offset < num_keys {
+            out[total_offset] = local_key;
+            out_values[total_offset] = local_value;
+        }
+        if local_id.x < sorting::BIN_COUNT {
+            bin_offset_cache[local_id.x] += atomicLoad(&local_histogram[local_id.x]);
+        }
+        workgroupBarrier();
+        data_index += sorting::WG;
+    }
+}
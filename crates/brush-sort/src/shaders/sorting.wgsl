@@ -23,6 +23,9 @@ const ELEMENTS_PER_THREAD: u32 = 4;
 
 const BLOCK_SIZE = WG * ELEMENTS_PER_THREAD;
 
+// Max nr. of passes needed to sort 32 bit keys.
+const MAX_PASSES: u32 = 8;
+
 fn div_ceil(a: u32, b: u32) -> u32 {
     return (a + b - 1u) / b;
 }
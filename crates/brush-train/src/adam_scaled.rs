@@ -9,10 +9,12 @@ use burn::{
     },
     prelude::Backend,
     record::Record,
-    tensor::{backend::AutodiffBackend, Device, ElementConversion, Tensor},
+    tensor::{backend::AutodiffBackend, DType, Device, ElementConversion, FloatDType, Tensor},
     LearningRate,
 };
 
+use crate::sparse_adam::SparseAdam;
+
 /// Adam optimizer as described in the paper [Adam: A Method for Stochastic Optimization](https://arxiv.org/pdf/1412.6980.pdf).
 #[derive(Clone)]
 pub struct AdamScaled {
@@ -51,9 +53,22 @@ pub struct AdamState<B: Backend, const D: usize> {
     /// The current adaptive momentum.
     pub momentum: AdaptiveMomentumState<B, D>,
     pub scaling: Option<Tensor<B, D>>,
+    /// An f32 copy of a parameter stored at half precision. The steps update this copy, and
+    /// the parameter is cast from it, so updates smaller than the f16 precision aren't lost.
+    pub master: Option<Tensor<B, D>>,
 }
 
 impl AdamScaledConfig {
+    /// Initialize an optimizer with the same settings that only steps visible splats, see
+    /// [`SparseAdam`].
+    pub(crate) fn init_sparse(&self) -> SparseAdam {
+        SparseAdam {
+            beta_1: self.beta_1,
+            beta_2: self.beta_2,
+            epsilon: self.epsilon,
+        }
+    }
+
     /// Initialize Adam optimizer.
     ///
     /// # Returns
@@ -86,39 +101,57 @@ impl<B: Backend> SimpleOptimizer<B> for AdamScaled {
         &self,
         lr: LearningRate,
         tensor: Tensor<B, D>,
-        mut grad: Tensor<B, D>,
+        grad: Tensor<B, D>,
         state: Option<Self::State<D>>,
     ) -> (Tensor<B, D>, Option<Self::State<D>>) {
         let mut state_momentum = None;
         let mut scaling = None;
+        let mut master = None;
 
         if let Some(state) = state {
             state_momentum = Some(state.momentum);
             scaling = state.scaling;
+            master = state.master;
         }
 
+        // Parameters stored at half precision are stepped on their f32 copy, and the moments are
+        // always kept in f32, as their small values would underflow at half precision.
+        let half_precision = tensor.dtype() == DType::F16;
+        let tensor = match master {
+            Some(master) if half_precision && master.dims() == tensor.dims() => master,
+            _ => tensor.cast(FloatDType::F32),
+        };
+        let mut grad = grad.cast(FloatDType::F32);
+
         if let Some(weight_decay) = &self.weight_decay {
             grad = weight_decay.transform(grad, tensor.clone());
         }
 
         let (grad, state_momentum) = self.momentum.transform(grad, state_momentum);
 
-        let state = AdamState {
-            momentum: state_momentum,
-            scaling: scaling.clone(),
-        };
-
-        let delta = if let Some(scale) = scaling {
+        let delta = if let Some(scale) = scaling.clone() {
             grad * (scale * lr).unsqueeze()
         } else {
             grad * lr
         };
 
-        (tensor - delta, Some(state))
+        let tensor = tensor - delta;
+        let state = AdamState {
+            momentum: state_momentum,
+            scaling,
+            master: half_precision.then(|| tensor.clone()),
+        };
+        let tensor = if half_precision {
+            tensor.cast(FloatDType::F16)
+        } else {
+            tensor
+        };
+        (tensor, Some(state))
     }
 
     fn to_device<const D: usize>(mut state: Self::State<D>, device: &Device<B>) -> Self::State<D> {
         state.momentum = state.momentum.to_device(device);
+        state.master = state.master.map(|master| master.to_device(device));
         state
     }
 }
@@ -168,3 +201,46 @@ impl AdaptiveMomentum {
         (grad, state)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use burn::{
+        backend::{wgpu::WgpuDevice, Wgpu},
+        optim::SimpleOptimizer,
+        tensor::{DType, FloatDType, Tensor},
+    };
+
+    use super::{AdamScaled, AdaptiveMomentum};
+
+    #[test]
+    fn half_precision_steps_change_parameters() {
+        let device = WgpuDevice::DefaultDevice;
+        let optim = AdamScaled {
+            momentum: AdaptiveMomentum {
+                beta_1: 0.9,
+                beta_2: 0.999,
+                epsilon: 1e-15,
+            },
+            weight_decay: None,
+        };
+
+        let mut tensor =
+            Tensor::<Wgpu, 1>::from_floats([1000.0, -1000.0], &device).cast(FloatDType::F16);
+        let grad = Tensor::<Wgpu, 1>::from_floats([1.0, -1.0], &device).cast(FloatDType::F16);
+        let mut state = None;
+        for _ in 0..100 {
+            (tensor, state) = optim.step(0.01, tensor, grad.clone(), state);
+        }
+        assert_eq!(tensor.dtype(), DType::F16);
+
+        // Every step is much smaller than the f16 precision around 1000, which is 0.5, but the
+        // steps add up on the f32 copy.
+        let values: Vec<f32> = tensor
+            .cast(FloatDType::F32)
+            .into_data()
+            .to_vec()
+            .expect("Wrong type");
+        assert!((values[0] - 999.0).abs() <= 0.5, "{values:?}");
+        assert!((values[1] + 999.0).abs() <= 0.5, "{values:?}");
+    }
+}
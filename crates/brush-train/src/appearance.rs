@@ -0,0 +1,64 @@
+//! Per view appearance correction, for the exposure and white balance differences between the
+//! photos of a capture.
+//!
+//! The correction is applied to the rendered image in the loss, so the splats don't have to
+//! bake in the differences between views. It's only used while training, the splats are rendered
+//! as is afterwards.
+
+use brush_render::Backend;
+use burn::{
+    module::{Module, Param, ParamId},
+    tensor::Tensor,
+};
+
+#[derive(Module, Debug)]
+pub struct Appearance<B: Backend> {
+    /// Log of the exposure of every view, `[views]`.
+    pub log_exposure: Param<Tensor<B, 1>>,
+    /// Color transform of every view, `[views, 3, 3]`, multiplying the colors as row vectors.
+    pub color: Param<Tensor<B, 3>>,
+}
+
+impl<B: Backend> Appearance<B> {
+    /// No correction for any of the `num_views` views.
+    pub fn new(num_views: usize, device: &B::Device) -> Self {
+        let identity = Tensor::<B, 2>::eye(3, device)
+            .reshape([1, 3, 3])
+            .repeat_dim(0, num_views);
+        Self {
+            log_exposure: Param::initialized(
+                ParamId::new(),
+                Tensor::zeros([num_views], device).require_grad(),
+            ),
+            color: Param::initialized(ParamId::new(), identity.require_grad()),
+        }
+    }
+
+    pub fn num_views(&self) -> usize {
+        self.log_exposure.dims()[0]
+    }
+
+    /// Correct a `[h, w, 3]` image as seen in a view.
+    pub fn apply(&self, rgb: Tensor<B, 3>, view: usize) -> Tensor<B, 3> {
+        let [h, w, _] = rgb.dims();
+        let exposure = self
+            .log_exposure
+            .val()
+            .slice([view..view + 1])
+            .exp()
+            .reshape([1, 1]);
+        let color = self.color.val().slice([view..view + 1]).reshape([3, 3]);
+        (rgb.reshape([h * w, 3]).matmul(color) * exposure).reshape([h, w, 3])
+    }
+
+    /// A copy on another device, eg. to correct the views of other GPUs. The copy isn't trained.
+    pub fn to_device(&self, device: &B::Device) -> Self {
+        Self {
+            log_exposure: Param::initialized(
+                ParamId::new(),
+                self.log_exposure.val().detach().to_device(device),
+            ),
+            color: Param::initialized(ParamId::new(), self.color.val().detach().to_device(device)),
+        }
+    }
+}
@@ -0,0 +1,443 @@
+//! Checkpoints of a training run, to resume training after it stopped.
+//!
+//! A checkpoint is a safetensors file with the splats, the Adam moments of every parameter, and
+//! the step to resume at. The splats are stored under the same names as
+//! [`Splats::from_safetensors`] reads, along with their labels and temporal attributes if they
+//! have them. The appearance and pose corrections of the views are stored too, see
+//! [`load_pose_refinement`]. Densification statistics aren't stored, these are gathered again
+//! after resuming.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context};
+use brush_render::gaussian_splats::{Splats, TemporalAttributes};
+use burn::{
+    backend::{wgpu::WgpuDevice, Autodiff, Wgpu},
+    lr_scheduler::LrScheduler,
+    module::{Param, ParamId},
+    optim::{record::AdaptorRecord, AdaptiveMomentumState, Optimizer},
+    tensor::{Int, Tensor, TensorData},
+};
+use safetensors::{tensor::TensorView, Dtype, SafeTensors};
+
+use crate::{
+    adam_scaled::{AdamScaled, AdamState},
+    appearance::Appearance,
+    pose_refine::PoseRefinement,
+    stats::RefineRecord,
+    train::SplatTrainer,
+};
+
+type B = Autodiff<Wgpu>;
+type Record = hashbrown::HashMap<ParamId, AdaptorRecord<AdamScaled, B>>;
+
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+struct Entry {
+    name: String,
+    dtype: Dtype,
+    shape: Vec<usize>,
+    bytes: Vec<u8>,
+}
+
+async fn read_entry<const D: usize>(
+    name: String,
+    tensor: Tensor<Wgpu, D>,
+) -> anyhow::Result<Entry> {
+    let shape = tensor.dims().to_vec();
+    // Half precision parameters are saved at full precision.
+    let data: Vec<f32> = tensor
+        .into_data_async()
+        .await
+        .convert::<f32>()
+        .to_vec()
+        .map_err(|e| anyhow!("Failed to read tensor {name}: {e:?}"))?;
+    Ok(Entry {
+        name,
+        dtype: Dtype::F32,
+        shape,
+        bytes: data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+    })
+}
+
+async fn read_labels(labels: Tensor<B, 1, Int>) -> anyhow::Result<Entry> {
+    let shape = labels.dims().to_vec();
+    let data: Vec<i32> = labels
+        .into_data_async()
+        .await
+        .convert::<i32>()
+        .to_vec()
+        .map_err(|e| anyhow!("Failed to read labels: {e:?}"))?;
+    Ok(Entry {
+        name: "labels".to_owned(),
+        dtype: Dtype::I32,
+        shape,
+        bytes: data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+    })
+}
+
+fn load_tensor<const D: usize>(
+    tensors: &SafeTensors,
+    name: &str,
+    device: &WgpuDevice,
+) -> anyhow::Result<Tensor<Wgpu, D>> {
+    let view = tensors.tensor(name)?;
+    anyhow::ensure!(view.dtype() == Dtype::F32, "Expected f32 data for {name}");
+    let data: Vec<f32> = view
+        .data()
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok(Tensor::from_data(
+        TensorData::new(data, view.shape()),
+        device,
+    ))
+}
+
+fn load_labels(tensors: &SafeTensors, device: &WgpuDevice) -> anyhow::Result<Tensor<B, 1, Int>> {
+    let view = tensors.tensor("labels")?;
+    anyhow::ensure!(view.dtype() == Dtype::I32, "Expected i32 data for labels");
+    let data: Vec<i32> = view
+        .data()
+        .chunks_exact(4)
+        .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok(Tensor::from_data(
+        TensorData::new(data, view.shape()),
+        device,
+    ))
+}
+
+// A parameter to continue training from a loaded tensor.
+fn trainable<const D: usize>(tensor: Tensor<Wgpu, D>) -> Param<Tensor<B, D>> {
+    Param::initialized(ParamId::new(), Tensor::from_inner(tensor).require_grad())
+}
+
+// Whether the checkpoint has a tensor called `name`.
+fn has_tensor(tensors: &SafeTensors, name: &str) -> bool {
+    tensors.tensor(name).is_ok()
+}
+
+// The pose corrections of the views, if the checkpoint has them.
+fn load_pose_tensors(
+    tensors: &SafeTensors,
+    device: &WgpuDevice,
+) -> anyhow::Result<Option<[Tensor<Wgpu, 2>; 2]>> {
+    if !has_tensor(tensors, "pose.rotations") {
+        return Ok(None);
+    }
+    Ok(Some([
+        load_tensor(tensors, "pose.rotations", device)?,
+        load_tensor(tensors, "pose.translations", device)?,
+    ]))
+}
+
+/// The pose corrections stored in a checkpoint, eg. to export the corrected cameras of the train
+/// views. `None` if the run didn't refine poses.
+pub fn load_pose_refinement(
+    data: &[u8],
+    device: &WgpuDevice,
+) -> anyhow::Result<Option<PoseRefinement<Wgpu>>> {
+    let tensors = SafeTensors::deserialize(data)?;
+    Ok(
+        load_pose_tensors(&tensors, device)?.map(|[rotations, translations]| PoseRefinement {
+            rotations: Param::initialized(ParamId::new(), rotations),
+            translations: Param::initialized(ParamId::new(), translations),
+        }),
+    )
+}
+
+struct CheckpointWriter {
+    record: Record,
+    entries: Vec<Entry>,
+    metadata: HashMap<String, String>,
+}
+
+impl CheckpointWriter {
+    // Add the param and its optimizer state to the checkpoint.
+    async fn add_param<const D: usize>(
+        &mut self,
+        name: &str,
+        param: &Param<Tensor<B, D>>,
+    ) -> anyhow::Result<()> {
+        let entry = read_entry(name.to_owned(), param.val().inner()).await?;
+        self.entries.push(entry);
+
+        if let Some(state) = self.record.get(&param.id) {
+            let state: AdamState<Wgpu, D> = state.clone().into_state();
+            let momentum = state.momentum;
+            let moment_1 = read_entry(format!("{name}.moment_1"), momentum.moment_1).await?;
+            let moment_2 = read_entry(format!("{name}.moment_2"), momentum.moment_2).await?;
+            self.entries.extend([moment_1, moment_2]);
+            self.metadata
+                .insert(format!("{name}.time"), momentum.time.to_string());
+        }
+        Ok(())
+    }
+
+    fn serialize(self) -> anyhow::Result<Vec<u8>> {
+        let views = self
+            .entries
+            .iter()
+            .map(|e| {
+                let view = TensorView::new(e.dtype, e.shape.clone(), &e.bytes)?;
+                Ok((e.name.clone(), view))
+            })
+            .collect::<Result<Vec<_>, safetensors::SafeTensorError>>()?;
+        Ok(safetensors::serialize(views, &Some(self.metadata))?)
+    }
+}
+
+struct CheckpointReader<'a> {
+    tensors: SafeTensors<'a>,
+    metadata: HashMap<String, String>,
+    record: Record,
+    device: WgpuDevice,
+}
+
+impl CheckpointReader<'_> {
+    // Restore the optimizer state of a param, if the checkpoint has it.
+    fn load_state<const D: usize>(
+        &mut self,
+        name: &str,
+        param: &Param<Tensor<B, D>>,
+    ) -> anyhow::Result<()> {
+        let Some(time) = self.metadata.get(&format!("{name}.time")) else {
+            return Ok(());
+        };
+        let momentum = AdaptiveMomentumState::new(
+            time.parse()?,
+            load_tensor(&self.tensors, &format!("{name}.moment_1"), &self.device)?,
+            load_tensor(&self.tensors, &format!("{name}.moment_2"), &self.device)?,
+        );
+        let state = AdamState::<Wgpu, D> {
+            momentum,
+            // The learning rate scaling is set up again on the next step.
+            scaling: None,
+            // Half precision parameters are stepped from their stored values again.
+            master: None,
+        };
+        self.record
+            .insert(param.id, AdaptorRecord::from_state(state));
+        Ok(())
+    }
+}
+
+impl SplatTrainer {
+    /// Serialize the splats and the training state to a checkpoint. `iter` is the step to
+    /// resume at.
+    pub async fn save_checkpoint(&self, iter: u32, splats: &Splats<B>) -> anyhow::Result<Vec<u8>> {
+        let mut writer = CheckpointWriter {
+            record: self.optim.to_record(),
+            entries: vec![],
+            metadata: HashMap::new(),
+        };
+        writer
+            .metadata
+            .insert("version".to_owned(), CHECKPOINT_VERSION.to_string());
+        writer.metadata.insert("iter".to_owned(), iter.to_string());
+
+        writer.add_param("means", &splats.means).await?;
+        writer.add_param("quats", &splats.rotation).await?;
+        writer.add_param("scales", &splats.log_scales).await?;
+        writer.add_param("coeffs", &splats.sh_coeffs).await?;
+        writer.add_param("opacities", &splats.raw_opacity).await?;
+        if let Some(labels) = &splats.labels {
+            writer.entries.push(read_labels(labels.clone()).await?);
+        }
+        if let Some(temporal) = &splats.temporal {
+            writer.add_param("velocities", &temporal.velocities).await?;
+            writer.add_param("times", &temporal.times).await?;
+            writer
+                .add_param("log_durations", &temporal.log_durations)
+                .await?;
+        }
+
+        // The corrections of the views have their own optimizers.
+        if let Some(appearance) = &self.appearance {
+            writer.record = self.appearance_optim.to_record();
+            writer
+                .add_param("appearance.log_exposure", &appearance.log_exposure)
+                .await?;
+            writer
+                .add_param("appearance.color", &appearance.color)
+                .await?;
+        }
+        if let Some(pose) = &self.pose {
+            writer.record = self.pose_optim.to_record();
+            writer.add_param("pose.rotations", &pose.rotations).await?;
+            writer
+                .add_param("pose.translations", &pose.translations)
+                .await?;
+        }
+        writer.serialize()
+    }
+
+    /// Restore the training state from a checkpoint. Returns the step to resume at, and the
+    /// splats to continue training.
+    pub fn load_checkpoint(
+        &mut self,
+        data: &[u8],
+        device: &WgpuDevice,
+    ) -> anyhow::Result<(u32, Splats<B>)> {
+        let (_, header) = SafeTensors::read_metadata(data)?;
+        let mut reader = CheckpointReader {
+            tensors: SafeTensors::deserialize(data)?,
+            metadata: header.metadata().clone().unwrap_or_default(),
+            record: Record::new(),
+            device: device.clone(),
+        };
+
+        let version: u32 = reader
+            .metadata
+            .get("version")
+            .context("Checkpoint has no version")?
+            .parse()?;
+        anyhow::ensure!(
+            version <= CHECKPOINT_VERSION,
+            "Unsupported checkpoint version {version}"
+        );
+        let iter: u32 = reader
+            .metadata
+            .get("iter")
+            .context("Checkpoint has no step")?
+            .parse()?;
+
+        let mut splats = Splats::from_safetensors(&reader.tensors, device)?;
+        reader.load_state("means", &splats.means)?;
+        reader.load_state("quats", &splats.rotation)?;
+        reader.load_state("scales", &splats.log_scales)?;
+        reader.load_state("coeffs", &splats.sh_coeffs)?;
+        reader.load_state("opacities", &splats.raw_opacity)?;
+        if has_tensor(&reader.tensors, "labels") {
+            splats = splats.with_labels(load_labels(&reader.tensors, device)?);
+        }
+        if has_tensor(&reader.tensors, "velocities") {
+            let temporal = TemporalAttributes::new(
+                Tensor::from_inner(load_tensor(&reader.tensors, "velocities", device)?),
+                Tensor::from_inner(load_tensor(&reader.tensors, "times", device)?),
+                Tensor::from_inner(load_tensor(&reader.tensors, "log_durations", device)?),
+            );
+            reader.load_state("velocities", &temporal.velocities)?;
+            reader.load_state("times", &temporal.times)?;
+            reader.load_state("log_durations", &temporal.log_durations)?;
+            splats = splats.with_temporal(temporal);
+        }
+
+        self.optim = self
+            .optim
+            .clone()
+            .load_record(std::mem::take(&mut reader.record));
+
+        // Only resume the view corrections that are still enabled.
+        if self.appearance.is_some() && has_tensor(&reader.tensors, "appearance.log_exposure") {
+            let appearance = Appearance {
+                log_exposure: trainable(load_tensor(
+                    &reader.tensors,
+                    "appearance.log_exposure",
+                    device,
+                )?),
+                color: trainable(load_tensor(&reader.tensors, "appearance.color", device)?),
+            };
+            reader.load_state("appearance.log_exposure", &appearance.log_exposure)?;
+            reader.load_state("appearance.color", &appearance.color)?;
+            self.appearance_optim = self
+                .appearance_optim
+                .clone()
+                .load_record(std::mem::take(&mut reader.record));
+            self.appearance = Some(appearance);
+        }
+
+        if self.pose.is_some() {
+            if let Some([rotations, translations]) = load_pose_tensors(&reader.tensors, device)? {
+                let pose = PoseRefinement {
+                    rotations: trainable(rotations),
+                    translations: trainable(translations),
+                };
+                reader.load_state("pose.rotations", &pose.rotations)?;
+                reader.load_state("pose.translations", &pose.translations)?;
+                self.pose_optim = self.pose_optim.clone().load_record(reader.record);
+                self.pose = Some(pose);
+            }
+        }
+        self.refine_record = RefineRecord::new(splats.num_splats(), device);
+
+        Ok((iter, splats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brush_render::gaussian_splats::{Splats, TemporalAttributes};
+    use burn::{
+        backend::wgpu::WgpuDevice,
+        tensor::{Int, Tensor, TensorData},
+    };
+    use glam::Vec3;
+
+    use super::B;
+    use crate::train::{SplatTrainer, TrainConfig};
+
+    fn read<const D: usize>(tensor: Tensor<B, D>) -> Vec<f32> {
+        tensor.into_data().to_vec().expect("Wrong type")
+    }
+
+    #[tokio::test]
+    async fn checkpoint_round_trips() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = Splats::<B>::from_raw(
+            &[Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0)],
+            None,
+            None,
+            None,
+            None,
+            &device,
+        )
+        .with_labels(Tensor::from_data(TensorData::new(vec![3, 7], [2]), &device))
+        .with_temporal(TemporalAttributes::stationary(2, &device));
+
+        let config = TrainConfig::new()
+            .with_appearance_model(true)
+            .with_pose_refinement(true);
+        let new_trainer = || {
+            SplatTrainer::new(&splats, &config, &device)
+                .with_appearance(2, &device)
+                .with_pose_refinement(2, &device)
+        };
+
+        // Move the corrections away from their defaults.
+        let mut trainer = new_trainer();
+        let appearance = trainer.appearance.as_mut().expect("Appearance is enabled");
+        Splats::map_param(&mut appearance.log_exposure, |t| t + 0.25);
+        let pose = trainer.pose.as_mut().expect("Pose refinement is enabled");
+        Splats::map_param(&mut pose.translations, |t| t + 0.5);
+
+        let data = trainer
+            .save_checkpoint(12, &splats)
+            .await
+            .expect("Failed to save checkpoint");
+        let mut resumed = new_trainer();
+        let (iter, loaded) = resumed
+            .load_checkpoint(&data, &device)
+            .expect("Failed to load checkpoint");
+
+        assert_eq!(iter, 12);
+        assert_eq!(read(loaded.means.val()), read(splats.means.val()));
+        let labels: Tensor<B, 1, Int> = loaded.labels.expect("Labels are saved");
+        assert_eq!(
+            labels.into_data().to_vec::<i32>().expect("Wrong type"),
+            [3, 7]
+        );
+        let temporal = loaded.temporal.expect("Temporal attributes are saved");
+        let expected = splats.temporal.as_ref().expect("Splats are dynamic");
+        assert_eq!(
+            read(temporal.log_durations.val()),
+            read(expected.log_durations.val())
+        );
+
+        let appearance = resumed.appearance.expect("Appearance is enabled");
+        assert_eq!(read(appearance.log_exposure.val()), [0.25, 0.25]);
+        let pose = resumed.pose.expect("Pose refinement is enabled");
+        assert_eq!(read(pose.translations.val()), [0.5; 6]);
+    }
+}
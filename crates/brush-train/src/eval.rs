@@ -4,6 +4,7 @@ use burn::tensor::Tensor;
 use rand::seq::IteratorRandom;
 
 use crate::image::view_to_sample;
+use crate::lpips_lite::LpipsLite;
 use crate::scene::{Scene, SceneView};
 use crate::ssim::Ssim;
 
@@ -16,6 +17,8 @@ pub struct EvalSample<B: Backend> {
     // but would complicate displaying things in the stats panel a bit.
     pub psnr: Tensor<B, 1>,
     pub ssim: Tensor<B, 1>,
+    /// Perceptual distance, see [`LpipsLite`].
+    pub lpips: Tensor<B, 1>,
     pub aux: RenderAux<B>,
 }
 
@@ -58,14 +61,17 @@ pub fn eval_stats<B: Backend>(
 
         let ssim_measure = Ssim::new(11, 3, &device);
         let ssim = ssim_measure
-            .ssim(render_rgb.clone().unsqueeze(), gt_rgb.unsqueeze())
+            .ssim(render_rgb.clone().unsqueeze(), gt_rgb.clone().unsqueeze())
             .mean();
 
+        let lpips = LpipsLite::new(3, 3, &device).distance(render_rgb.clone(), gt_rgb);
+
         EvalSample {
             index,
             view,
             psnr,
             ssim,
+            lpips,
             rendered: render_rgb,
             aux,
         }
@@ -0,0 +1,151 @@
+//! Hooks to extend training from other crates, with extra loss terms, see [`RegularizerTerm`],
+//! and callbacks that observe every step, see [`TrainCallback`].
+//!
+//! Both are added to a trainer with [`SplatTrainer::with_regularizer`] and
+//! [`SplatTrainer::with_callback`].
+//!
+//! [`SplatTrainer::with_regularizer`]: crate::train::SplatTrainer::with_regularizer
+//! [`SplatTrainer::with_callback`]: crate::train::SplatTrainer::with_callback
+
+use brush_render::{gaussian_splats::Splats, RenderAux};
+use burn::{
+    backend::{Autodiff, Wgpu},
+    tensor::Tensor,
+};
+
+use crate::train::SceneBatch;
+
+type B = Autodiff<Wgpu>;
+
+/// A training step, as seen by hooks.
+pub struct StepContext<'a> {
+    pub iter: u32,
+    pub batch: &'a SceneBatch<B>,
+    /// The rendered image, `[h, w, 4]`, before the appearance correction of the view.
+    pub pred_image: &'a Tensor<B, 3>,
+    pub aux: &'a RenderAux<B>,
+    /// The splats of the step. With a pose correction these are moved to the corrected camera.
+    pub splats: &'a Splats<B>,
+}
+
+/// An extra term of the training loss, eg. a penalty on the shape of the splats.
+///
+/// With multiple GPUs, terms are calculated for the batch of every device, like the rest of the
+/// loss, so the step can be on any of the devices.
+pub trait RegularizerTerm: Send {
+    /// Name of the term, for traces.
+    fn name(&self) -> &str;
+
+    /// The weighted loss to add for a step, a differentiable scalar tensor, or `None` to skip
+    /// the term for this step.
+    fn loss(&self, step: &StepContext<'_>) -> Option<Tensor<B, 1>>;
+}
+
+/// Observes the training steps of the main device, eg. to log extra statistics.
+pub trait TrainCallback: Send {
+    /// Called with the total loss of a step, before the backward pass.
+    fn on_step(&mut self, step: &StepContext<'_>, loss: &Tensor<B, 1>);
+}
+
+/// Penalizes splats that are more than `max_ratio` times longer than they are wide, as in
+/// PhysGaussian. Needle-like splats look fine in the train views, but show up as spikes from
+/// other angles.
+pub struct ScaleAnisotropy {
+    pub weight: f32,
+    pub max_ratio: f32,
+}
+
+impl RegularizerTerm for ScaleAnisotropy {
+    fn name(&self) -> &str {
+        "Scale anisotropy"
+    }
+
+    fn loss(&self, step: &StepContext<'_>) -> Option<Tensor<B, 1>> {
+        let log_scales = step.splats.log_scales.val();
+        // The log of the ratio of the longest to the shortest axis.
+        let log_ratio = log_scales.clone().max_dim(1) - log_scales.min_dim(1);
+        let excess = (log_ratio.exp() - self.max_ratio).clamp_min(0.0);
+        Some(excess.mean() * self.weight)
+    }
+}
+
+/// Pushes opacities towards fully opaque or fully transparent, by penalizing their binary
+/// entropy. Transparent splats can then be pruned, and the rest make up crisp surfaces.
+pub struct OpacityEntropy {
+    pub weight: f32,
+}
+
+impl RegularizerTerm for OpacityEntropy {
+    fn name(&self) -> &str {
+        "Opacity entropy"
+    }
+
+    fn loss(&self, step: &StepContext<'_>) -> Option<Tensor<B, 1>> {
+        let opacity = step.splats.opacity().clamp(1e-6, 1.0 - 1e-6);
+        let entropy = (opacity.clone() * opacity.clone().log()
+            + (opacity.clone().neg() + 1.0) * (opacity.neg() + 1.0).log())
+        .neg();
+        Some(entropy.mean() * self.weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use brush_render::{bounding_box::BoundingBox, gaussian_splats::RandomSplatsConfig};
+    use burn::backend::wgpu::WgpuDevice;
+    use glam::Vec3;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::train::{test_batch, SplatTrainer, TrainConfig};
+
+    struct Constant;
+
+    impl RegularizerTerm for Constant {
+        fn name(&self) -> &str {
+            "Constant"
+        }
+
+        fn loss(&self, step: &StepContext<'_>) -> Option<Tensor<B, 1>> {
+            Some(Tensor::from_floats([1.0], &step.splats.means.device()))
+        }
+    }
+
+    struct CountSteps(Arc<AtomicU32>);
+
+    impl TrainCallback for CountSteps {
+        fn on_step(&mut self, _step: &StepContext<'_>, _loss: &Tensor<B, 1>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn regularizers_add_to_loss() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let bounds = BoundingBox::from_min_max(Vec3::splat(-1.0), Vec3::splat(1.0));
+        let splats =
+            Splats::<B>::from_random_config(&RandomSplatsConfig::new(), bounds, &mut rng, &device);
+
+        let batch = test_batch(&device);
+
+        let config = TrainConfig::new();
+        let (_, plain) =
+            SplatTrainer::new(&splats, &config, &device).step(0, batch.clone(), splats.clone());
+
+        let steps = Arc::new(AtomicU32::new(0));
+        let (_, extra) = SplatTrainer::new(&splats, &config, &device)
+            .with_regularizer(Constant)
+            .with_callback(CountSteps(steps.clone()))
+            .step(0, batch, splats);
+
+        let diff = extra.loss.into_scalar() - plain.loss.into_scalar();
+        assert!((diff - 1.0).abs() < 1e-4, "Loss changed by {diff}");
+        assert_eq!(steps.load(Ordering::Relaxed), 1);
+    }
+}
@@ -1,12 +1,16 @@
 use burn::{
     prelude::Backend,
-    tensor::{DType, Tensor, TensorData},
+    tensor::{DType, Int, Tensor, TensorData},
 };
 use image::{DynamicImage, Rgb32FImage, Rgba32FImage};
 
-use crate::scene::{SceneView, ViewImageType};
+use crate::{
+    scene::{SceneView, ViewDepth, ViewImageType},
+    train::DepthTarget,
+};
 
-// Converts an image to a train sample. The tensor will be a floating point image with a [0, 1] image.
+// Converts an image to a train sample. The tensor will be a floating point image with a [0, 1] image,
+// or linear colors without an upper bound for HDR images.
 //
 // This assume the input image has un-premultiplied alpha, whereas the output has pre-multiplied alpha.
 pub fn view_to_sample<B: Backend>(view: &SceneView, device: &B::Device) -> Tensor<B, 3> {
@@ -32,6 +36,55 @@ pub fn view_to_sample<B: Backend>(view: &SceneView, device: &B::Device) -> Tenso
     Tensor::from_data(tensor_data, device)
 }
 
+/// Whether an image has linear float colors, eg. from an EXR or Radiance HDR file, rather than
+/// 8 or 16 bit sRGB colors. Splats trained on these have linear colors that can go above 1, and
+/// need tone mapping to display, see [`brush_render::tone_map`].
+pub fn is_hdr(image: &DynamicImage) -> bool {
+    matches!(
+        image.color(),
+        image::ColorType::Rgb32F | image::ColorType::Rgba32F
+    )
+}
+
+/// Converts the depth of a view to a train sample, at the size of the image of the view.
+pub fn view_to_depth<B: Backend>(view: &SceneView, device: &B::Device) -> Option<DepthTarget<B>> {
+    let (w, h) = (view.image.width(), view.image.height());
+
+    match view.depth.as_ref()? {
+        ViewDepth::Map(depth) => {
+            // Resize without filtering, so unknown depths don't blend into known depths.
+            let depth = if depth.width() != w || depth.height() != h {
+                depth.resize_exact(w, h, image::imageops::FilterType::Nearest)
+            } else {
+                depth.as_ref().clone()
+            };
+            let data = TensorData::new(depth.to_luma32f().into_vec(), [h as usize, w as usize]);
+            Some(DepthTarget::Map(Tensor::from_data(data, device)))
+        }
+        ViewDepth::Points(points) => {
+            let (pixels, depth): (Vec<i32>, Vec<f32>) = points
+                .iter()
+                .filter(|(uv, _)| {
+                    uv.cmpge(glam::Vec2::ZERO).all() && uv.cmplt(glam::Vec2::ONE).all()
+                })
+                .map(|(uv, depth)| {
+                    let x = (uv.x * w as f32) as i32;
+                    let y = (uv.y * h as f32) as i32;
+                    (y * w as i32 + x, *depth)
+                })
+                .unzip();
+            if pixels.is_empty() {
+                return None;
+            }
+            let n = pixels.len();
+            Some(DepthTarget::Points {
+                pixels: Tensor::<B, 1, Int>::from_data(TensorData::new(pixels, [n]), device),
+                depth: Tensor::from_data(TensorData::new(depth, [n]), device),
+            })
+        }
+    }
+}
+
 pub trait TensorDataToImage {
     fn into_image(self) -> DynamicImage;
 }
@@ -59,3 +112,15 @@ pub fn tensor_into_image(data: TensorData) -> DynamicImage {
 
     img
 }
+
+/// Decode an image file to a `[height, width, 3]` tensor, eg. to render splats over an image
+/// background.
+pub fn load_rgb_tensor<B: Backend>(
+    data: &[u8],
+    device: &B::Device,
+) -> anyhow::Result<Tensor<B, 3>> {
+    let image = image::load_from_memory(data)?;
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let data = TensorData::new(image.to_rgb32f().into_vec(), [h, w, 3]);
+    Ok(Tensor::from_data(data, device))
+}
@@ -1,12 +1,25 @@
 #![recursion_limit = "256"]
 
+pub mod appearance;
+pub mod checkpoint;
 pub mod eval;
+pub mod hooks;
+pub mod lpips_lite;
+pub mod lr_schedule;
+pub mod metrics;
+pub mod parallel;
+pub mod pose_refine;
 pub mod ssim;
+#[cfg(not(target_family = "wasm"))]
+pub mod tensorboard;
 pub mod train;
 
 pub mod image;
 pub mod scene;
 
 mod adam_scaled;
+mod mcmc;
+mod sparse_adam;
+mod sparse_adam_kernel;
 mod stats;
 mod stats_kernel;
@@ -0,0 +1,75 @@
+//! A cheap perceptual distance in the spirit of LPIPS, without a learned network.
+//!
+//! LPIPS compares deep features of two images, normalized per pixel over the channels. Here the
+//! features are the responses of fixed edge filters at a few scales of an image pyramid, which
+//! capture the structure LPIPS is most sensitive to. Lower is more similar. The values aren't
+//! comparable to LPIPS with VGG or AlexNet features, only between runs of brush.
+
+use burn::tensor::{
+    backend::Backend,
+    module::{avg_pool2d, conv2d},
+    ops::ConvOptions,
+    Tensor,
+};
+
+// Sobel filters for horizontal and vertical edges, and their diagonal versions.
+const EDGE_FILTERS: [[f32; 9]; 4] = [
+    [-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0],
+    [-1.0, -2.0, -1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 1.0],
+    [-2.0, -1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 1.0, 2.0],
+    [0.0, -1.0, -2.0, 1.0, 0.0, -1.0, 2.0, 1.0, 0.0],
+];
+
+// Keeps flat areas, where there are no edges to normalize, from amplifying noise.
+const NORM_EPS: f32 = 1e-2;
+
+pub struct LpipsLite<B: Backend> {
+    weights: Tensor<B, 4>,
+    channels: usize,
+    num_scales: usize,
+}
+
+impl<B: Backend> LpipsLite<B> {
+    pub fn new(channels: usize, num_scales: usize, device: &B::Device) -> Self {
+        let filters: Vec<f32> = (0..channels)
+            .flat_map(|_| EDGE_FILTERS.iter().flatten().map(|w| w / 4.0))
+            .collect();
+        // Channels out, in (per group), h, w.
+        let weights = Tensor::<B, 1>::from_floats(filters.as_slice(), device).reshape([
+            channels * EDGE_FILTERS.len(),
+            1,
+            3,
+            3,
+        ]);
+        Self {
+            weights,
+            channels,
+            num_scales,
+        }
+    }
+
+    fn features(&self, img: Tensor<B, 4>) -> Tensor<B, 4> {
+        let conv_options = ConvOptions::new([1, 1], [1, 1], [1, 1], self.channels);
+        let features = conv2d(img, self.weights.clone(), None, conv_options);
+        let norm = (features.clone().powf_scalar(2.0).sum_dim(1) + NORM_EPS * NORM_EPS).sqrt();
+        features / norm
+    }
+
+    /// The distance between two [H, W, C] images.
+    pub fn distance(&self, img1: Tensor<B, 3>, img2: Tensor<B, 3>) -> Tensor<B, 1> {
+        // Images are [H, W, C], need them as [N, C, H, W].
+        let mut img1: Tensor<B, 4> = img1.permute([2, 0, 1]).unsqueeze();
+        let mut img2: Tensor<B, 4> = img2.permute([2, 0, 1]).unsqueeze();
+
+        let mut total = Tensor::zeros([1], &img1.device());
+        for scale in 0..self.num_scales {
+            if scale > 0 {
+                img1 = avg_pool2d(img1, [2, 2], [2, 2], [0, 0], false);
+                img2 = avg_pool2d(img2, [2, 2], [2, 2], [0, 0], false);
+            }
+            let diff = self.features(img1.clone()) - self.features(img2.clone());
+            total = total + diff.powf_scalar(2.0).sum_dim(1).mean();
+        }
+        total / self.num_scales as f32
+    }
+}
@@ -0,0 +1,84 @@
+//! Learning rate schedules of the splat parameters, like the reference 3DGS implementation.
+//!
+//! Every parameter group can decay exponentially from a start to an end rate, and ramps up over
+//! some warmup steps first. The means decay, the other parameters keep a constant rate.
+
+use std::f64::consts::FRAC_PI_2;
+
+/// The learning rate of a parameter group at every step.
+#[derive(Clone, Debug)]
+pub struct LrSchedule {
+    start: f64,
+    end: f64,
+    total_steps: u32,
+    warmup_steps: u32,
+    warmup_mult: f64,
+}
+
+impl LrSchedule {
+    /// A schedule that decays exponentially from `start` at step 0 to `end` at `total_steps`.
+    pub fn exponential(start: f64, end: f64, total_steps: u32) -> Self {
+        Self {
+            start,
+            end,
+            total_steps,
+            warmup_steps: 0,
+            warmup_mult: 1.0,
+        }
+    }
+
+    /// A schedule with the same rate at every step.
+    pub fn constant(lr: f64) -> Self {
+        Self::exponential(lr, lr, 1)
+    }
+
+    /// Ramp up from `mult` times the rate to the full rate over the first `steps` steps.
+    pub fn with_warmup(mut self, steps: u32, mult: f64) -> Self {
+        self.warmup_steps = steps;
+        self.warmup_mult = mult;
+        self
+    }
+
+    /// The learning rate at step `iter`.
+    pub fn lr_at(&self, iter: u32) -> f64 {
+        // Smoothly ramp up, like the "delay" of the reference implementation.
+        let warmup = if iter < self.warmup_steps {
+            let t = iter as f64 / self.warmup_steps as f64;
+            self.warmup_mult + (1.0 - self.warmup_mult) * (FRAC_PI_2 * t).sin()
+        } else {
+            1.0
+        };
+
+        // Interpolate in log space, which is an exponential decay.
+        let t = (iter as f64 / self.total_steps.max(1) as f64).clamp(0.0, 1.0);
+        let lr = if self.start == self.end {
+            self.start
+        } else {
+            (self.start.ln() * (1.0 - t) + self.end.ln() * t).exp()
+        };
+        warmup * lr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LrSchedule;
+
+    #[test]
+    fn decays_exponentially() {
+        let schedule = LrSchedule::exponential(1e-4, 1e-6, 100);
+        assert!((schedule.lr_at(0) - 1e-4).abs() < 1e-12);
+        assert!((schedule.lr_at(50) - 1e-5).abs() < 1e-12);
+        assert!((schedule.lr_at(100) - 1e-6).abs() < 1e-12);
+        // Stays at the end rate after the last step.
+        assert!((schedule.lr_at(200) - 1e-6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn warms_up() {
+        let schedule = LrSchedule::constant(1e-2).with_warmup(10, 0.01);
+        assert!((schedule.lr_at(0) - 1e-4).abs() < 1e-12);
+        assert!(schedule.lr_at(5) < 1e-2);
+        assert!((schedule.lr_at(10) - 1e-2).abs() < 1e-12);
+    }
+}
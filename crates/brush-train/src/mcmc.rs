@@ -0,0 +1,63 @@
+//! Helpers for the densification of 3DGS-MCMC (Kheradmand et al. 2024, "3D Gaussian Splatting
+//! as Markov Chain Monte Carlo").
+//!
+//! Rather than cloning and splitting splats with high gradients, low opacity splats are moved to
+//! where other splats are, and new splats are added up to a budget. Both are sampled in
+//! proportion to the opacity of the splats. A splat that gets copies shares its opacity and
+//! scale with them, such that together they render roughly like the original splat.
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+
+/// Sample `count` splats in proportion to their opacity. Returns how often every splat was
+/// sampled, or None if no splat has any opacity.
+pub(crate) fn sample_by_opacity(
+    opacity: &[f32],
+    count: usize,
+    rng: &mut impl Rng,
+) -> Option<Vec<u32>> {
+    let weights = WeightedIndex::new(opacity.iter().map(|o| o.max(0.0))).ok()?;
+    let mut samples = vec![0; opacity.len()];
+    for _ in 0..count {
+        samples[weights.sample(rng)] += 1;
+    }
+    Some(samples)
+}
+
+/// The opacity, and the factor to multiply the scale with, of a splat with `opacity` that is
+/// split into `copies` splats at the same position. See eq. 9 of the paper.
+pub(crate) fn relocated(opacity: f32, copies: u32) -> (f32, f32) {
+    let n = copies.max(1) as i32;
+    let new_opacity = 1.0 - (1.0 - opacity).powf(1.0 / n as f32);
+
+    let mut denom = 0.0;
+    for i in 1..=n {
+        let mut binomial = 1.0;
+        for k in 0..i {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            denom += binomial * sign / ((k + 1) as f32).sqrt() * new_opacity.powi(k + 1);
+            // C(i - 1, k + 1) from C(i - 1, k).
+            binomial *= (i - 1 - k) as f32 / (k + 1) as f32;
+        }
+    }
+    (new_opacity, opacity / denom.max(1e-12))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::relocated;
+
+    #[test]
+    fn single_copy_is_unchanged() {
+        let (opacity, scale) = relocated(0.6, 1);
+        assert!((opacity - 0.6).abs() < 1e-6);
+        assert!((scale - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn copies_share_opacity() {
+        let (opacity, scale) = relocated(0.9, 2);
+        // Two splats of the new opacity together are as opaque as the original.
+        assert!((1.0 - (1.0 - opacity).powi(2) - 0.9).abs() < 1e-5);
+        assert!(opacity < 0.9 && scale > 0.0);
+    }
+}
@@ -0,0 +1,165 @@
+//! Metrics of the training steps, to compare runs across config changes.
+//!
+//! [`StepMetrics`] are read back from the stats of a step, and recorded in a [`MetricsLog`],
+//! which keeps the recent steps and writes every step to its sinks, eg. TensorBoard event
+//! files with [`crate::tensorboard::TensorBoardWriter`].
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::Result;
+use brush_render::AutodiffBackend;
+use burn::tensor::ElementConversion;
+use burn::tensor::Tensor;
+
+use crate::train::TrainStepStats;
+
+/// The metrics of a training step.
+#[derive(Debug, Clone, Default)]
+pub struct StepMetrics {
+    pub iter: u32,
+    pub loss: f32,
+    /// PSNR of the rendered training view.
+    pub psnr: f32,
+    pub num_splats: u32,
+    /// The learning rates of the splat parameters, by name.
+    pub learning_rates: Vec<(&'static str, f64)>,
+    /// Time spent in the kernels since the last step, see [`sync_span::take_timings`]. These
+    /// are only recorded while sync spans are enabled.
+    pub kernel_timings: Vec<(&'static str, Duration)>,
+}
+
+impl StepMetrics {
+    /// Read back the metrics of a step. This waits for the step to finish on the GPU.
+    pub async fn read_async<B: AutodiffBackend>(
+        iter: u32,
+        stats: &TrainStepStats<B>,
+        num_splats: u32,
+    ) -> Self {
+        let [img_h, img_w, _] = stats.pred_image.dims();
+        let pred_rgb = stats.pred_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+        let gt_rgb = stats.gt_images.clone().slice([0..img_h, 0..img_w, 0..3]);
+        let mse = (pred_rgb - gt_rgb).powf_scalar(2.0).mean();
+        let psnr = mse.recip().log() * 10.0 / std::f32::consts::LN_10;
+
+        // Read back the loss and PSNR together.
+        let values = Tensor::cat(vec![stats.loss.clone(), psnr], 0)
+            .into_data_async()
+            .await;
+        let values: Vec<f32> = values.iter::<B::FloatElem>().map(|v| v.elem()).collect();
+
+        Self {
+            iter,
+            loss: values[0],
+            psnr: values[1],
+            num_splats,
+            learning_rates: vec![
+                ("mean", stats.lr_mean),
+                ("rotation", stats.lr_rotation),
+                ("scale", stats.lr_scale),
+                ("coeffs", stats.lr_coeffs),
+                ("opac", stats.lr_opac),
+            ],
+            kernel_timings: sync_span::take_timings(),
+        }
+    }
+
+    /// The metrics as named scalars, with the same names for every sink. Kernel timings are
+    /// in milliseconds.
+    pub fn scalars(&self) -> Vec<(String, f64)> {
+        let mut scalars = vec![
+            ("loss".to_owned(), self.loss as f64),
+            ("psnr".to_owned(), self.psnr as f64),
+            ("splats/count".to_owned(), self.num_splats as f64),
+        ];
+        scalars.extend(
+            self.learning_rates
+                .iter()
+                .map(|(name, lr)| (format!("lr/{name}"), *lr)),
+        );
+        scalars.extend(
+            self.kernel_timings
+                .iter()
+                .map(|(name, time)| (format!("kernels/{name}"), time.as_secs_f64() * 1000.0)),
+        );
+        scalars
+    }
+}
+
+/// Somewhere to write the metrics of every step to.
+pub trait MetricsSink: Send {
+    fn write(&mut self, metrics: &StepMetrics) -> Result<()>;
+}
+
+/// Keeps the metrics of the last steps, and writes every step to its sinks.
+pub struct MetricsLog {
+    recent: VecDeque<StepMetrics>,
+    window: usize,
+    sinks: Vec<Box<dyn MetricsSink>>,
+}
+
+impl MetricsLog {
+    /// A log that keeps the metrics of the last `window` steps.
+    pub fn new(window: usize) -> Self {
+        Self {
+            recent: VecDeque::with_capacity(window),
+            window,
+            sinks: vec![],
+        }
+    }
+
+    pub fn with_sink(mut self, sink: impl MetricsSink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    pub fn has_sinks(&self) -> bool {
+        !self.sinks.is_empty()
+    }
+
+    /// Record the metrics of a step, and write them to every sink.
+    pub fn record(&mut self, metrics: StepMetrics) -> Result<()> {
+        for sink in &mut self.sinks {
+            sink.write(&metrics)?;
+        }
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        if self.window > 0 {
+            self.recent.push_back(metrics);
+        }
+        Ok(())
+    }
+
+    /// The metrics of the last steps, oldest first.
+    pub fn recent(&self) -> impl Iterator<Item = &StepMetrics> {
+        self.recent.iter()
+    }
+
+    /// The mean of a metric over the last steps, or None before the first step.
+    pub fn rolling_mean(&self, metric: impl Fn(&StepMetrics) -> f64) -> Option<f64> {
+        (!self.recent.is_empty())
+            .then(|| self.recent.iter().map(metric).sum::<f64>() / self.recent.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_window() {
+        let mut log = MetricsLog::new(2);
+        assert_eq!(log.rolling_mean(|m| m.loss as f64), None);
+        for (iter, loss) in [1.0, 2.0, 4.0].into_iter().enumerate() {
+            log.record(StepMetrics {
+                iter: iter as u32,
+                loss,
+                ..Default::default()
+            })
+            .expect("No sinks to fail");
+        }
+        assert_eq!(log.recent().map(|m| m.iter).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(log.rolling_mean(|m| m.loss as f64), Some(3.0));
+    }
+}
@@ -0,0 +1,234 @@
+//! Data parallel training on multiple devices.
+//!
+//! Every extra device renders a different view each step. The splats are copied to the extra
+//! devices, and their gradients are gathered on the main device and averaged with the gradients
+//! of the main device. The optimizer state and densification statistics only live on the main
+//! device, so densification only sees the views of the main device.
+
+use brush_render::{gaussian_splats::Splats, render::RenderOptions, AutodiffBackend};
+use burn::{
+    backend::{wgpu::WgpuDevice, Autodiff, Wgpu},
+    module::Param,
+    tensor::Tensor,
+};
+
+use crate::{
+    appearance::Appearance,
+    hooks::RegularizerTerm,
+    pose_refine::PoseRefinement,
+    ssim::Ssim,
+    train::{render_loss, SceneBatch, TrainConfig},
+};
+
+type B = Autodiff<Wgpu>;
+type Gradients = <B as AutodiffBackend>::Gradients;
+
+struct Replica {
+    device: WgpuDevice,
+    ssim: Ssim<B>,
+}
+
+pub struct DataParallel {
+    replicas: Vec<Replica>,
+}
+
+// Average the gradient of a parameter over the main device and the replicas.
+fn average_grad<const D: usize>(
+    param: &Param<Tensor<B, D>>,
+    grads: &mut Gradients,
+    replicas: &[(&Param<Tensor<B, D>>, &Gradients)],
+) {
+    let param = param.val();
+    let device = param.device();
+
+    let mut total = param.grad(grads);
+    for (replica, replica_grads) in replicas {
+        let Some(grad) = replica.val().grad(replica_grads) else {
+            continue;
+        };
+        let grad = grad.to_device(&device);
+        total = Some(match total {
+            Some(total) => total + grad,
+            None => grad,
+        });
+    }
+
+    if let Some(total) = total {
+        param.grad_replace(grads, total / (replicas.len() + 1) as f32);
+    }
+}
+
+impl DataParallel {
+    /// Train on these devices besides the main device.
+    pub fn new(devices: &[WgpuDevice], config: &TrainConfig) -> Self {
+        let replicas = devices
+            .iter()
+            .map(|device| Replica {
+                device: device.clone(),
+                ssim: Ssim::new(config.ssim_window_size, 3, device),
+            })
+            .collect();
+        Self { replicas }
+    }
+
+    pub fn num_replicas(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// Calculate the gradients of every replica for its batch, and average them into the
+    /// gradients of the main device.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn average_grads(
+        &self,
+        config: &TrainConfig,
+        iter: u32,
+        regularizers: &[Box<dyn RegularizerTerm>],
+        splats: &Splats<B>,
+        render_options: RenderOptions,
+        appearance: Option<&Appearance<B>>,
+        pose: Option<&PoseRefinement<B>>,
+        batches: Vec<SceneBatch<B>>,
+        grads: &mut Gradients,
+    ) {
+        assert_eq!(
+            batches.len(),
+            self.replicas.len(),
+            "Need a batch for every replica device"
+        );
+
+        // Work is submitted to every device before any gradients are copied back, so the
+        // replicas run at the same time.
+        let replicas: Vec<_> = self
+            .replicas
+            .iter()
+            .zip(batches)
+            .map(|(replica, batch)| {
+                let device = &replica.device;
+                let splats = splats.to_device(device);
+                // Replicas use the appearance of the views, but only the main device learns it.
+                let appearance = appearance.map(|a| a.to_device(device));
+                let pose = pose.map(|p| p.to_device(device));
+                // Only the steps of the main device are passed to callbacks.
+                let (_, _, loss) = render_loss(
+                    config,
+                    &replica.ssim,
+                    iter,
+                    &batch,
+                    &splats,
+                    render_options,
+                    appearance.as_ref(),
+                    pose.as_ref(),
+                    regularizers,
+                    &mut [],
+                );
+                let grads = loss.backward();
+                (splats, grads)
+            })
+            .collect();
+
+        let means: Vec<_> = replicas.iter().map(|(s, g)| (&s.means, g)).collect();
+        average_grad(&splats.means, grads, &means);
+        let rotation: Vec<_> = replicas.iter().map(|(s, g)| (&s.rotation, g)).collect();
+        average_grad(&splats.rotation, grads, &rotation);
+        let scales: Vec<_> = replicas.iter().map(|(s, g)| (&s.log_scales, g)).collect();
+        average_grad(&splats.log_scales, grads, &scales);
+        let coeffs: Vec<_> = replicas.iter().map(|(s, g)| (&s.sh_coeffs, g)).collect();
+        average_grad(&splats.sh_coeffs, grads, &coeffs);
+        let opacity: Vec<_> = replicas.iter().map(|(s, g)| (&s.raw_opacity, g)).collect();
+        average_grad(&splats.raw_opacity, grads, &opacity);
+
+        if let Some(temporal) = &splats.temporal {
+            let replica_temporal: Vec<_> = replicas
+                .iter()
+                .filter_map(|(s, g)| s.temporal.as_ref().map(|t| (t, g)))
+                .collect();
+            let velocities: Vec<_> = replica_temporal
+                .iter()
+                .map(|(t, g)| (&t.velocities, *g))
+                .collect();
+            average_grad(&temporal.velocities, grads, &velocities);
+            let times: Vec<_> = replica_temporal
+                .iter()
+                .map(|(t, g)| (&t.times, *g))
+                .collect();
+            average_grad(&temporal.times, grads, &times);
+            let durations: Vec<_> = replica_temporal
+                .iter()
+                .map(|(t, g)| (&t.log_durations, *g))
+                .collect();
+            average_grad(&temporal.log_durations, grads, &durations);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brush_render::{
+        bounding_box::BoundingBox,
+        gaussian_splats::{RandomSplatsConfig, TemporalAttributes},
+    };
+    use glam::Vec3;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::train::test_batch;
+
+    #[test]
+    fn averaged_grads_match_single_device() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let bounds = BoundingBox::from_min_max(Vec3::splat(-1.0), Vec3::splat(1.0));
+        let splats =
+            Splats::<B>::from_random_config(&RandomSplatsConfig::new(), bounds, &mut rng, &device);
+        let temporal = TemporalAttributes::stationary(splats.num_splats(), &device);
+        let splats = splats.with_temporal(temporal);
+
+        let batch = test_batch(&device);
+
+        let config = TrainConfig::new();
+        let ssim = Ssim::new(config.ssim_window_size, 3, &device);
+        let options = RenderOptions::default();
+        let (_, _, loss) = render_loss(
+            &config,
+            &ssim,
+            0,
+            &batch,
+            &splats,
+            options,
+            None,
+            None,
+            &[],
+            &mut [],
+        );
+        let mut grads = loss.backward();
+        let read_grad = |grads: &Gradients| -> Vec<f32> {
+            let grad = splats
+                .means
+                .val()
+                .grad(grads)
+                .expect("Means have a gradient");
+            grad.into_data().to_vec().expect("Wrong type")
+        };
+        let single = read_grad(&grads);
+
+        // The replica runs on the same GPU and renders the same view, so the average is the
+        // gradient of a single device.
+        DataParallel::new(&[device.clone()], &config).average_grads(
+            &config,
+            0,
+            &[],
+            &splats,
+            options,
+            None,
+            None,
+            vec![batch],
+            &mut grads,
+        );
+        let averaged = read_grad(&grads);
+
+        assert!(single.iter().any(|&g| g != 0.0));
+        for (a, b) in single.iter().zip(&averaged) {
+            assert!((a - b).abs() <= 1e-5 * a.abs().max(1.0), "{a} != {b}");
+        }
+    }
+}
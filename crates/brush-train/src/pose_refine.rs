@@ -0,0 +1,242 @@
+//! Per view camera pose refinement, to correct slightly wrong camera poses of a dataset.
+//!
+//! Every view learns a small rotation and translation of its camera. Rather than moving the
+//! camera, the splats are moved the opposite way before rendering with the original camera,
+//! which renders the same image. The image loss then flows back through the projection into
+//! the splat positions and rotations, and from there into the pose corrections.
+//!
+//! Moving the splats costs a copy of the means and rotations every step. View dependent colors
+//! are evaluated with the view directions of the moved splats, which are rotated by the
+//! correction, this is negligible for small corrections.
+//!
+//! The corrections are saved in checkpoints, see [`crate::checkpoint`], and the corrected
+//! cameras can be exported from there.
+
+use brush_render::{camera::Camera, gaussian_splats::Splats, Backend};
+use burn::{
+    module::{Module, Param, ParamId},
+    tensor::{FloatDType, Tensor},
+};
+
+#[derive(Module, Debug)]
+pub struct PoseRefinement<B: Backend> {
+    /// Rotation of the camera of every view as an axis angle, in the camera frame, `[views, 3]`.
+    pub rotations: Param<Tensor<B, 2>>,
+    /// Translation of the camera of every view, in the camera frame, `[views, 3]`.
+    pub translations: Param<Tensor<B, 2>>,
+}
+
+// Hamilton product of `[n, 4]` quaternions, stored as w, x, y, z. Either side can be a single
+// quaternion.
+fn quat_mul<B: Backend>(a: Tensor<B, 2>, b: Tensor<B, 2>) -> Tensor<B, 2> {
+    let part = |q: &Tensor<B, 2>, i: usize| q.clone().slice([0..q.dims()[0], i..i + 1]);
+    let [aw, ax, ay, az] = [0, 1, 2, 3].map(|i| part(&a, i));
+    let [bw, bx, by, bz] = [0, 1, 2, 3].map(|i| part(&b, i));
+
+    Tensor::cat(
+        vec![
+            aw.clone() * bw.clone()
+                - ax.clone() * bx.clone()
+                - ay.clone() * by.clone()
+                - az.clone() * bz.clone(),
+            aw.clone() * bx.clone() + ax.clone() * bw.clone() + ay.clone() * bz.clone()
+                - az.clone() * by.clone(),
+            aw.clone() * by.clone() - ax.clone() * bz.clone()
+                + ay.clone() * bw.clone()
+                + az.clone() * bx.clone(),
+            aw * bz + ax * by - ay * bx + az * bw,
+        ],
+        1,
+    )
+}
+
+// Rotation matrix of a `[1, 4]` unit quaternion.
+fn quat_to_mat<B: Backend>(q: Tensor<B, 2>) -> Tensor<B, 2> {
+    let [w, x, y, z] = [0, 1, 2, 3].map(|i| q.clone().slice([0..1, i..i + 1]));
+    let one = w.ones_like();
+    let two = |a: &Tensor<B, 2>, b: &Tensor<B, 2>| (a.clone() * b.clone()) * 2.0;
+
+    Tensor::cat(
+        vec![
+            one.clone() - two(&y, &y) - two(&z, &z),
+            two(&x, &y) - two(&w, &z),
+            two(&x, &z) + two(&w, &y),
+            two(&x, &y) + two(&w, &z),
+            one.clone() - two(&x, &x) - two(&z, &z),
+            two(&y, &z) - two(&w, &x),
+            two(&x, &z) - two(&w, &y),
+            two(&y, &z) + two(&w, &x),
+            one - two(&x, &x) - two(&y, &y),
+        ],
+        1,
+    )
+    .reshape([3, 3])
+}
+
+// Quaternion of a `[1, 3]` axis angle rotation.
+fn axis_angle_to_quat<B: Backend>(axis_angle: Tensor<B, 2>) -> Tensor<B, 2> {
+    // Offset the angle slightly, so the gradient is defined at zero rotation.
+    let angle = (axis_angle.clone().powf_scalar(2.0).sum_dim(1) + 1e-12).sqrt();
+    let half = angle.clone() * 0.5;
+    Tensor::cat(
+        vec![half.clone().cos(), axis_angle * (half.sin() / angle)],
+        1,
+    )
+}
+
+fn quat_tensor<B: Backend>(q: glam::Quat, device: &B::Device) -> Tensor<B, 2> {
+    Tensor::<B, 1>::from_floats([q.w, q.x, q.y, q.z], device).reshape([1, 4])
+}
+
+impl<B: Backend> PoseRefinement<B> {
+    /// No correction for any of the `num_views` views.
+    pub fn new(num_views: usize, device: &B::Device) -> Self {
+        let zeros = || {
+            Param::initialized(
+                ParamId::new(),
+                Tensor::zeros([num_views, 3], device).require_grad(),
+            )
+        };
+        Self {
+            rotations: zeros(),
+            translations: zeros(),
+        }
+    }
+
+    pub fn num_views(&self) -> usize {
+        self.rotations.dims()[0]
+    }
+
+    /// Move the splats such that rendering them with the original camera of `view` renders them
+    /// as seen by the corrected camera.
+    pub fn apply(&self, splats: &Splats<B>, camera: &Camera, view: usize) -> Splats<B> {
+        let device = splats.means.device();
+        let rot_delta = axis_angle_to_quat(self.rotations.val().slice([view..view + 1]));
+        let trans_delta = self.translations.val().slice([view..view + 1]);
+
+        // The corrected camera to world transform is C * D, with C the original camera and
+        // D the correction. The splats are moved by C * D^-1 * C^-1 instead.
+        let cam_rot = quat_tensor::<B>(camera.rotation, &device);
+        let cam_rot_inv = quat_tensor::<B>(camera.rotation.inverse(), &device);
+        let delta_inv = rot_delta
+            * Tensor::<B, 1>::from_floats([1.0, -1.0, -1.0, -1.0], &device).reshape([1, 4]);
+        let quat = quat_mul(quat_mul(cam_rot, delta_inv), cam_rot_inv);
+        let rot_mat = quat_to_mat(quat.clone());
+
+        // Read as rows, the columns of the rotation are its transpose.
+        let cam_mat = glam::Mat3::from_quat(camera.rotation).to_cols_array();
+        let cam_mat = Tensor::<B, 1>::from_floats(cam_mat, &device).reshape([3, 3]);
+        let pos = Tensor::<B, 1>::from_floats(camera.position.to_array(), &device).reshape([1, 3]);
+
+        // Row vectors, so points are multiplied by the transposed matrices.
+        let new_pos = pos.clone() + trans_delta.matmul(cam_mat);
+        let offset = pos - new_pos.matmul(rot_mat.clone().transpose());
+
+        let means = splats.means.val().cast(FloatDType::F32);
+        let means = means.matmul(rot_mat.transpose()) + offset;
+        let rotations = quat_mul(quat, splats.rotation.val().cast(FloatDType::F32));
+
+        let mut moved = splats.clone();
+        moved.means = Param::initialized(ParamId::new(), means);
+        moved.rotation = Param::initialized(ParamId::new(), rotations);
+        moved
+    }
+
+    /// The corrected cameras of the views, given the original `cameras` of the views in order.
+    /// Views without a correction keep their camera.
+    pub async fn corrected_cameras(&self, cameras: &[Camera]) -> Vec<Camera> {
+        let read = |t: Tensor<B, 2>| async move {
+            let v: Vec<f32> = t.into_data_async().await.iter::<f32>().collect();
+            v.chunks_exact(3)
+                .map(|c| glam::vec3(c[0], c[1], c[2]))
+                .collect::<Vec<_>>()
+        };
+        let rotations = read(self.rotations.val()).await;
+        let translations = read(self.translations.val()).await;
+
+        cameras
+            .iter()
+            .enumerate()
+            .map(|(view, camera)| {
+                let mut corrected = camera.clone();
+                if let (Some(axis_angle), Some(translation)) =
+                    (rotations.get(view), translations.get(view))
+                {
+                    corrected.position = camera.position + camera.rotation * *translation;
+                    corrected.rotation =
+                        camera.rotation * glam::Quat::from_scaled_axis(*axis_angle);
+                }
+                corrected
+            })
+            .collect()
+    }
+
+    /// A copy on another device, eg. to correct the views of other GPUs. The copy isn't trained.
+    pub fn to_device(&self, device: &B::Device) -> Self {
+        Self {
+            rotations: Param::initialized(
+                ParamId::new(),
+                self.rotations.val().detach().to_device(device),
+            ),
+            translations: Param::initialized(
+                ParamId::new(),
+                self.translations.val().detach().to_device(device),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brush_render::{camera::Camera, gaussian_splats::Splats};
+    use burn::{
+        backend::{wgpu::WgpuDevice, Wgpu},
+        module::{Param, ParamId},
+        tensor::Tensor,
+    };
+    use glam::{vec3, Quat};
+
+    use super::PoseRefinement;
+
+    #[tokio::test]
+    async fn moved_splats_match_corrected_camera() {
+        let device = WgpuDevice::DefaultDevice;
+        let camera = Camera::new(
+            vec3(0.5, -1.0, 2.0),
+            Quat::from_euler(glam::EulerRot::XYZ, 0.3, -0.4, 0.2),
+            0.8,
+            0.8,
+            glam::vec2(0.5, 0.5),
+        );
+        let (axis_angle, translation) = (vec3(0.1, -0.2, 0.05), vec3(0.3, 0.1, -0.2));
+        let param = |v: glam::Vec3| {
+            let t = Tensor::<Wgpu, 1>::from_floats(v.to_array(), &device).reshape([1, 3]);
+            Param::initialized(ParamId::new(), t)
+        };
+        let pose = PoseRefinement {
+            rotations: param(axis_angle),
+            translations: param(translation),
+        };
+
+        let means = [vec3(0.1, 0.2, 0.3), vec3(-1.0, 0.5, 4.0)];
+        let splats = Splats::<Wgpu>::from_raw(&means, None, None, None, None, &device);
+        let moved = pose.apply(&splats, &camera, 0);
+        let moved: Vec<f32> = moved.means.val().into_data().to_vec().expect("Wrong type");
+
+        let corrected = pose
+            .corrected_cameras(&[camera.clone(), camera.clone()])
+            .await;
+        // The second view has no correction.
+        assert_eq!(corrected[1].position, camera.position);
+        let corrected = &corrected[0];
+        assert!((corrected.rotation * Quat::from_scaled_axis(-axis_angle))
+            .abs_diff_eq(camera.rotation, 1e-5));
+
+        for (i, mean) in means.iter().enumerate() {
+            let moved = vec3(moved[i * 3], moved[i * 3 + 1], moved[i * 3 + 2]);
+            let seen = camera.world_to_local().transform_point3(moved);
+            let expected = corrected.world_to_local().transform_point3(*mean);
+            assert!((seen - expected).length() < 1e-5);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use brush_render::{bounding_box::BoundingBox, camera::Camera};
-use glam::{vec3, Affine3A, Vec3};
+use glam::{vec3, Affine3A, Vec2, Vec3};
 use std::sync::Arc;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -15,12 +15,26 @@ pub enum ViewImageType {
     Masked,
 }
 
+/// Known depth of a view, to supervise the depth of the splats while training.
+#[derive(Debug, Clone)]
+pub enum ViewDepth {
+    /// A depth map, eg. from a monocular depth estimator, where brighter is further away.
+    /// Only known up to a scale and offset. Zero where the depth is unknown.
+    Map(Arc<image::DynamicImage>),
+    /// The camera space depth of sparse points, eg. the COLMAP points seen by a view. Points
+    /// are in uv coordinates of the image.
+    Points(Arc<Vec<(Vec2, f32)>>),
+}
+
 #[derive(Debug, Clone)]
 pub struct SceneView {
     pub path: String,
     pub camera: Camera,
     pub image: Arc<image::DynamicImage>,
     pub img_type: ViewImageType,
+    /// Size of the image on disk, before it was clamped to the max resolution.
+    pub original_size: (u32, u32),
+    pub depth: Option<ViewDepth>,
 }
 
 // Encapsulates a multi-view scene including cameras and the splats.
@@ -56,6 +70,17 @@ impl Scene {
         }
     }
 
+    /// Whether this scene starts with the views of `other`, in the same order, eg. when views
+    /// were added to `other` while loading.
+    pub fn extends(&self, other: &Self) -> bool {
+        self.views.len() >= other.views.len()
+            && self
+                .views
+                .iter()
+                .zip(other.views.iter())
+                .all(|(a, b)| a.path == b.path)
+    }
+
     // Returns the extent of the cameras in the scene.
     pub fn bounds(&self) -> BoundingBox {
         self.adjusted_bounds(0.0, 0.0)
@@ -0,0 +1,241 @@
+//! Adam step that only updates the splats visible in the rendered view.
+//!
+//! The backward pass only writes gradients for visible splats, so stepping the other splats
+//! would only decay their moments. Skipping them makes steps much cheaper for big scenes, where
+//! only a small part of the splats is visible in any view. Moments are bias corrected with the
+//! total nr. of steps like a dense step, but the moments of splats that aren't visible don't
+//! decay.
+//!
+//! Gradients of splats that aren't visible are ignored, so terms that add gradients to every
+//! splat, like regularizers, need a dense step.
+
+use brush_kernel::create_dispatch_buffer;
+use brush_render::RenderAux;
+use burn::backend::wgpu::JitBackend;
+use burn::backend::{Autodiff, Wgpu};
+use burn::module::{Param, ParamId};
+use burn::optim::record::AdaptorRecord;
+use burn::optim::AdaptiveMomentumState;
+use burn::prelude::*;
+use burn::tensor::backend::AutodiffBackend;
+use burn_fusion::client::FusionClient;
+use burn_jit::cubecl;
+use burn_jit::cubecl::prelude::ScalarArg;
+use burn_jit::cubecl::wgpu::WgpuRuntime;
+use burn_jit::cubecl::CubeDim;
+
+use crate::adam_scaled::{AdamScaled, AdamState};
+use crate::sparse_adam_kernel::sparse_adam_kernel;
+
+type B = Autodiff<Wgpu>;
+type BInner = Wgpu;
+type InnerWgpu = JitBackend<WgpuRuntime, f32, i32, u32>;
+type Gradients = <B as AutodiffBackend>::Gradients;
+type Record = hashbrown::HashMap<ParamId, AdaptorRecord<AdamScaled, B>>;
+
+#[derive(Clone)]
+pub(crate) struct SparseAdam {
+    pub(crate) beta_1: f32,
+    pub(crate) beta_2: f32,
+    pub(crate) epsilon: f32,
+}
+
+impl SparseAdam {
+    /// Step the splats of `param` that are visible in the render of `aux`. The optimizer state
+    /// is read from and written back to `record`. `lr_scales` scales the learning rate of every
+    /// value in a row of the parameter.
+    pub(crate) fn step<const D: usize>(
+        &self,
+        lr: f64,
+        param: &mut Param<Tensor<B, D>>,
+        lr_scales: &[f32],
+        grads: &mut Gradients,
+        aux: &RenderAux<B>,
+        record: &mut Record,
+    ) {
+        let Some(grad) = param.grad_remove(grads) else {
+            return;
+        };
+
+        let value = param.val().inner();
+        let device = value.device();
+        let dims = value.dims();
+        let row_len = dims[1..].iter().product::<usize>();
+        assert_eq!(
+            lr_scales.len(),
+            row_len,
+            "Need a learning rate scale for every value in a row"
+        );
+
+        let state: Option<AdamState<BInner, D>> =
+            record.get(&param.id).map(|r| r.clone().into_state());
+        // Keep the learning rate scaling of dense steps.
+        let scaling = state.as_ref().and_then(|s| s.scaling.clone());
+        let mut momentum = state.map_or_else(
+            || {
+                AdaptiveMomentumState::new(
+                    0,
+                    Tensor::zeros(dims, &device),
+                    Tensor::zeros(dims, &device),
+                )
+            },
+            |s| s.momentum,
+        );
+        momentum.time += 1;
+        let time = momentum.time as i32;
+
+        // The kernel updates in place, so update copies. The current splats might still be in
+        // use elsewhere, eg. to display them, and the moments are shared with earlier copies of
+        // the optimizer record.
+        let updated = value.add_scalar(0.0);
+        momentum.moment_1 = momentum.moment_1.add_scalar(0.0);
+        momentum.moment_2 = momentum.moment_2.add_scalar(0.0);
+
+        let lr_scales = Tensor::<BInner, 1>::from_floats(lr_scales, &device);
+
+        let client = &updated.clone().into_primitive().tensor().client;
+        let compact_gid = client
+            .resolve_tensor_int::<InnerWgpu>(aux.global_from_compact_gid.clone().into_primitive());
+        let num_visible =
+            client.resolve_tensor_int::<InnerWgpu>(aux.num_visible.clone().into_primitive());
+        let grad = client.resolve_tensor_float::<InnerWgpu>(grad.into_primitive().tensor());
+        let lr_scales =
+            client.resolve_tensor_float::<InnerWgpu>(lr_scales.into_primitive().tensor());
+        let params =
+            client.resolve_tensor_float::<InnerWgpu>(updated.clone().into_primitive().tensor());
+        let moment_1 = client
+            .resolve_tensor_float::<InnerWgpu>(momentum.moment_1.clone().into_primitive().tensor());
+        let moment_2 = client
+            .resolve_tensor_float::<InnerWgpu>(momentum.moment_2.clone().into_primitive().tensor());
+
+        let inner_client = &compact_gid.client;
+
+        const WG_SIZE: u32 = 256;
+        sparse_adam_kernel::launch::<WgpuRuntime>(
+            inner_client,
+            cubecl::CubeCount::Dynamic(
+                create_dispatch_buffer(num_visible.clone(), [WG_SIZE, 1, 1])
+                    .handle
+                    .binding(),
+            ),
+            CubeDim::new(WG_SIZE, 1, 1),
+            compact_gid.as_tensor_arg::<u32>(1),
+            num_visible.as_tensor_arg::<u32>(1),
+            grad.as_tensor_arg::<f32>(1),
+            lr_scales.as_tensor_arg::<f32>(1),
+            params.as_tensor_arg::<f32>(1),
+            moment_1.as_tensor_arg::<f32>(1),
+            moment_2.as_tensor_arg::<f32>(1),
+            ScalarArg::new(lr as f32),
+            ScalarArg::new(self.beta_1),
+            ScalarArg::new(self.beta_2),
+            ScalarArg::new(self.epsilon),
+            ScalarArg::new(1.0 - self.beta_1.powi(time)),
+            ScalarArg::new(1.0 - self.beta_2.powi(time)),
+            row_len as u32,
+        );
+
+        *param = Param::initialized(param.id, Tensor::from_inner(updated).require_grad());
+
+        let state = AdamState::<BInner, D> {
+            momentum,
+            scaling,
+            master: None,
+        };
+        record.insert(param.id, AdaptorRecord::from_state(state));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brush_render::{
+        bounding_box::BoundingBox,
+        gaussian_splats::{RandomSplatsConfig, Splats},
+        render::RenderOptions,
+    };
+    use burn::{
+        backend::wgpu::WgpuDevice,
+        optim::{adaptor::OptimizerAdaptor, GradientsParams, Optimizer},
+    };
+    use glam::Vec3;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        adam_scaled::AdamScaledConfig,
+        ssim::Ssim,
+        train::{render_loss, test_batch, TrainConfig},
+    };
+
+    #[test]
+    fn sparse_step_matches_dense_step_when_all_visible() {
+        let device = WgpuDevice::DefaultDevice;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        // Small enough to be fully in view of the test camera.
+        let bounds = BoundingBox::from_min_max(Vec3::splat(-0.5), Vec3::splat(0.5));
+        let config = RandomSplatsConfig::new().with_init_count(64);
+        let splats = Splats::<B>::from_random_config(&config, bounds, &mut rng, &device);
+
+        let config = TrainConfig::new();
+        let ssim = Ssim::new(config.ssim_window_size, 3, &device);
+        let batch = test_batch(&device);
+        let backward = |splats: &Splats<B>| {
+            let (_, aux, loss) = render_loss(
+                &config,
+                &ssim,
+                0,
+                &batch,
+                splats,
+                RenderOptions::default(),
+                None,
+                None,
+                &[],
+                &mut [],
+            );
+            (aux, loss.backward())
+        };
+        let read =
+            |t: Tensor<BInner, 2>| -> Vec<f32> { t.into_data().to_vec().expect("Wrong type") };
+
+        let optim_config = AdamScaledConfig::new();
+        let mut dense_optim: OptimizerAdaptor<AdamScaled, Splats<B>, B> = optim_config.init();
+        let sparse_optim = optim_config.init_sparse();
+        let mut dense = splats.clone();
+        let mut sparse = splats;
+        let mut record = Record::new();
+        let mut earlier = None;
+
+        for _ in 0..2 {
+            let (_, mut grads) = backward(&dense);
+            let grads = GradientsParams::from_params(&mut grads, &dense, &[dense.means.id]);
+            dense = dense_optim.step(1e-2, dense, grads);
+
+            let (aux, mut grads) = backward(&sparse);
+            let num_visible = aux.num_visible.clone().into_scalar() as usize;
+            assert_eq!(num_visible, sparse.num_splats());
+            // Keep a copy of the optimizer state, which the next step shouldn't change.
+            if let Some(state) = record.get(&sparse.means.id) {
+                let state: AdamState<BInner, 2> = state.clone().into_state();
+                earlier = Some((record.clone(), read(state.momentum.moment_1)));
+            }
+            sparse_optim.step(
+                1e-2,
+                &mut sparse.means,
+                &[1.0; 3],
+                &mut grads,
+                &aux,
+                &mut record,
+            );
+        }
+
+        let dense = read(dense.means.val().inner());
+        let sparse_means = read(sparse.means.val().inner());
+        for (d, s) in dense.iter().zip(&sparse_means) {
+            assert!((d - s).abs() < 1e-5, "{d} != {s}");
+        }
+
+        let (earlier, moment_1) = earlier.expect("The second step has earlier state");
+        let state: AdamState<BInner, 2> = earlier[&sparse.means.id].clone().into_state();
+        assert_eq!(read(state.momentum.moment_1), moment_1);
+    }
+}
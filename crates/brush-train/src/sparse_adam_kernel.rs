@@ -0,0 +1,44 @@
+use burn_jit::cubecl;
+use burn_jit::cubecl::{cube, prelude::*};
+
+#[cube(launch)]
+pub fn sparse_adam_kernel(
+    gs_ids: &Tensor<u32>,
+    num_visible: &Tensor<u32>,
+    grads: &Tensor<f32>,
+    lr_scales: &Tensor<f32>,
+    params: &mut Tensor<f32>,
+    moment_1: &mut Tensor<f32>,
+    moment_2: &mut Tensor<f32>,
+    lr: f32,
+    beta_1: f32,
+    beta_2: f32,
+    epsilon: f32,
+    bias_correction_1: f32,
+    bias_correction_2: f32,
+    #[comptime] row_len: u32,
+) {
+    let compact_gid = ABSOLUTE_POS_X;
+    let num_vis = num_visible[0];
+
+    if compact_gid >= num_vis {
+        terminate!();
+    }
+
+    let global_gid = gs_ids[compact_gid];
+    let base = global_gid * row_len;
+
+    for i in 0..row_len {
+        let id = base + i;
+        let grad = grads[id];
+
+        // Same as m * beta + grad * (1 - beta).
+        let m1 = beta_1 * (moment_1[id] - grad) + grad;
+        let m2 = beta_2 * (moment_2[id] - grad * grad) + grad * grad;
+        moment_1[id] = m1;
+        moment_2[id] = m2;
+
+        let step = (m1 / bias_correction_1) / (f32::sqrt(m2 / bias_correction_2) + epsilon);
+        params[id] -= lr * lr_scales[i] * step;
+    }
+}
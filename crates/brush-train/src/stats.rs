@@ -21,6 +21,12 @@ pub(crate) struct RefineRecord {
     grad_2d_accum: Tensor<B, 1>,
     xy_grad_counts: Tensor<B, 1, Int>,
     max_radii: Tensor<B, 1>,
+    // Opacity times the normalized radius of every gaussian, summed over the renders it's
+    // visible in. Used to prune the least important gaussians when over the splat budget.
+    importance: Tensor<B, 1>,
+    // The longest side of the gathered renders, summed, to scale the absgrad threshold.
+    resolution_sum: f32,
+    num_renders: u32,
 }
 
 impl RefineRecord {
@@ -29,13 +35,23 @@ impl RefineRecord {
             grad_2d_accum: Tensor::zeros([num_points], device),
             xy_grad_counts: Tensor::zeros([num_points], device),
             max_radii: Tensor::zeros([num_points], device),
+            importance: Tensor::zeros([num_points], device),
+            resolution_sum: 0.0,
+            num_renders: 0,
         }
     }
 
-    pub(crate) fn gather_stats(&self, xys_grad: Tensor<BInner, 2>, aux: RenderAux<B>) {
+    pub(crate) fn gather_stats(
+        &mut self,
+        xys_grad: Tensor<BInner, 2>,
+        opacities: Tensor<BInner, 1>,
+        aux: RenderAux<B>,
+    ) {
         let _span = trace_span!("Gather stats", sync_burn = true);
 
         let [h, w] = aux.final_index.shape().dims();
+        self.resolution_sum += w.max(h) as f32;
+        self.num_renders += 1;
         let client = &self.xy_grad_counts.clone().into_primitive().client;
 
         let compact_gid =
@@ -44,6 +60,8 @@ impl RefineRecord {
         let radii =
             client.resolve_tensor_float::<InnerWgpu>(aux.radii.inner().into_primitive().tensor());
         let xys_grad = client.resolve_tensor_float::<InnerWgpu>(xys_grad.into_primitive().tensor());
+        let opacities =
+            client.resolve_tensor_float::<InnerWgpu>(opacities.into_primitive().tensor());
 
         let inner_client = &compact_gid.client;
 
@@ -55,6 +73,9 @@ impl RefineRecord {
         let max_radii = client.resolve_tensor_float::<InnerWgpu>(
             self.max_radii.clone().inner().into_primitive().tensor(),
         );
+        let importance = client.resolve_tensor_float::<InnerWgpu>(
+            self.importance.clone().inner().into_primitive().tensor(),
+        );
 
         const WG_SIZE: u32 = 256;
         // Execute lazily the kernel with the launch information and the given buffers. For
@@ -70,10 +91,12 @@ impl RefineRecord {
             compact_gid.as_tensor_arg::<u32>(1),
             num_visible.as_tensor_arg::<u32>(1),
             radii.as_tensor_arg::<f32>(1),
+            opacities.as_tensor_arg::<f32>(1),
             xys_grad.as_tensor_arg::<f32>(2),
             grad_2d_accum.as_tensor_arg::<f32>(1),
             grad_counts.as_tensor_arg::<u32>(1),
             max_radii.as_tensor_arg::<f32>(1),
+            importance.as_tensor_arg::<f32>(1),
             w as u32,
             h as u32,
         );
@@ -83,7 +106,16 @@ impl RefineRecord {
         self.grad_2d_accum.clone() / self.xy_grad_counts.clone().clamp_min(1).float()
     }
 
+    /// The mean longest side of the gathered renders, if any.
+    pub(crate) fn mean_resolution(&self) -> Option<f32> {
+        (self.num_renders > 0).then(|| self.resolution_sum / self.num_renders as f32)
+    }
+
     pub(crate) fn max_radii(&self) -> Tensor<B, 1> {
         self.max_radii.clone()
     }
+
+    pub(crate) fn importance(&self) -> Tensor<B, 1> {
+        self.importance.clone()
+    }
 }
@@ -7,10 +7,12 @@ pub fn stats_gather_kernel(
     gs_ids: &Tensor<u32>,
     num_visible: &Tensor<u32>,
     radii: &Tensor<f32>,
+    opacities: &Tensor<f32>,
     xy_grads: &Tensor<Line<f32>>,
     norm_sum: &mut Tensor<f32>,
     counts: &mut Tensor<u32>,
     max_radii: &mut Tensor<f32>,
+    importance: &mut Tensor<f32>,
     #[comptime] w: u32,
     #[comptime] h: u32,
 ) {
@@ -38,4 +40,5 @@ pub fn stats_gather_kernel(
 
     let radii_norm = radius / comptime!(if w > h { w as f32 } else { h as f32 });
     max_radii[global_gid] = f32::max(radii_norm, max_radii[global_gid]);
+    importance[global_gid] += opacities[global_gid] * radii_norm;
 }
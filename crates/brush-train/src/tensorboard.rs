@@ -0,0 +1,170 @@
+//! Write training metrics as TensorBoard event files.
+//!
+//! An event file is a sequence of records, each holding a protobuf encoded `Event` message.
+//! The few messages needed for scalars are encoded by hand.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+use crate::metrics::{MetricsSink, StepMetrics};
+
+// CRC-32C (Castagnoli), which TensorBoard uses to check records.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn masked_crc32c(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    ((crc >> 15) | (crc << 17)).wrapping_add(0xa282_ead8)
+}
+
+// Minimal protobuf encoding of the `Event` and `Summary` messages of TensorBoard.
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    put_varint(buf, ((field << 3) | 2) as u64);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn encode_event(
+    wall_time: f64,
+    step: u64,
+    file_version: Option<&str>,
+    scalars: &[(String, f64)],
+) -> Vec<u8> {
+    let mut event = vec![];
+    // wall_time = 1 (double), step = 2 (int64).
+    put_varint(&mut event, (1 << 3) | 1);
+    event.extend_from_slice(&wall_time.to_le_bytes());
+    put_varint(&mut event, 2 << 3);
+    put_varint(&mut event, step);
+
+    if let Some(version) = file_version {
+        put_bytes(&mut event, 3, version.as_bytes());
+    }
+
+    if !scalars.is_empty() {
+        let mut summary = vec![];
+        for (tag, value) in scalars {
+            // Summary.Value with tag = 1 and simple_value = 2 (float).
+            let mut summary_value = vec![];
+            put_bytes(&mut summary_value, 1, tag.as_bytes());
+            put_varint(&mut summary_value, (2 << 3) | 5);
+            summary_value.extend_from_slice(&(*value as f32).to_le_bytes());
+            put_bytes(&mut summary, 1, &summary_value);
+        }
+        put_bytes(&mut event, 5, &summary);
+    }
+    event
+}
+
+// A record of a TensorBoard event file: the length, a checksum of the length, the data, and a
+// checksum of the data.
+fn encode_record(data: &[u8]) -> Vec<u8> {
+    let len = (data.len() as u64).to_le_bytes();
+    let mut record = Vec::with_capacity(data.len() + 16);
+    record.extend_from_slice(&len);
+    record.extend_from_slice(&masked_crc32c(&len).to_le_bytes());
+    record.extend_from_slice(data);
+    record.extend_from_slice(&masked_crc32c(data).to_le_bytes());
+    record
+}
+
+fn wall_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |t| t.as_secs_f64())
+}
+
+/// Writes the metrics as a TensorBoard event file, which can be viewed with
+/// `tensorboard --logdir <dir>`. Every run writes a new file. Records are written right away,
+/// so TensorBoard can follow a run while it's training.
+pub struct TensorBoardWriter {
+    file: File,
+}
+
+impl TensorBoardWriter {
+    pub fn create(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let time = wall_time();
+        // Runs started in the same second, eg. several processes logging to one dir, still get
+        // their own file.
+        let path = dir.join(format!(
+            "events.out.tfevents.{}.brush.{}.{:08x}",
+            time as u64,
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        let mut file = File::create_new(path)?;
+        file.write_all(&encode_record(&encode_event(
+            time,
+            0,
+            Some("brain.Event:2"),
+            &[],
+        )))?;
+        Ok(Self { file })
+    }
+}
+
+impl MetricsSink for TensorBoardWriter {
+    fn write(&mut self, metrics: &StepMetrics) -> Result<()> {
+        let event = encode_event(wall_time(), metrics.iter as u64, None, &metrics.scalars());
+        self.file.write_all(&encode_record(&event))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+    }
+
+    #[test]
+    fn encodes_event_records() {
+        let event = encode_event(1.0, 300, None, &[("loss".to_owned(), 0.5)]);
+        let record = encode_record(&event);
+        assert_eq!(record.len(), event.len() + 16);
+        assert_eq!(record[..8], (event.len() as u64).to_le_bytes());
+        assert_eq!(&record[12..12 + event.len()], event.as_slice());
+
+        // Step 300 is a two byte varint.
+        assert_eq!(event[9..12], [0x10, 0xAC, 0x02]);
+    }
+
+    #[test]
+    fn runs_write_separate_files() {
+        let dir =
+            std::env::temp_dir().join(format!("brush_tensorboard_{:016x}", rand::random::<u64>()));
+        let _first = TensorBoardWriter::create(&dir).expect("Failed to create writer");
+        let _second = TensorBoardWriter::create(&dir).expect("Failed to create writer");
+        let files = std::fs::read_dir(&dir).expect("Failed to read dir").count();
+        std::fs::remove_dir_all(&dir).expect("Failed to clean up");
+        assert_eq!(files, 2);
+    }
+}
@@ -1,26 +1,83 @@
 use anyhow::Result;
-use brush_render::gaussian_splats::{inverse_sigmoid, Splats};
-use brush_render::render::sh_coeffs_for_degree;
-use brush_render::{AutodiffBackend, Backend};
+use brush_render::camera::Camera;
+use brush_render::gaussian_splats::{inverse_sigmoid, Splats, TemporalAttributes};
+use brush_render::render::{self, sh_coeffs_for_degree, RenderOptions};
+use brush_render::{AutodiffBackend, Backend, RenderAux};
 use burn::backend::wgpu::WgpuDevice;
 use burn::backend::{Autodiff, Wgpu};
-use burn::lr_scheduler::exponential::{ExponentialLrScheduler, ExponentialLrSchedulerConfig};
-use burn::lr_scheduler::LrScheduler;
 use burn::module::{Param, ParamId};
 use burn::optim::adaptor::OptimizerAdaptor;
 use burn::optim::record::AdaptorRecord;
 use burn::optim::Optimizer;
 use burn::tensor::activation::sigmoid;
-use burn::tensor::{Bool, Distribution, Int};
+use burn::tensor::{Bool, DType, Distribution, FloatDType, Int};
 use burn::{config::Config, optim::GradientsParams, tensor::Tensor};
 use hashbrown::HashMap;
+use rand::{rngs::StdRng, SeedableRng};
 use tracing::trace_span;
 
 use crate::adam_scaled::{AdamScaled, AdamScaledConfig, AdamState};
+use crate::appearance::Appearance;
+use crate::hooks::{RegularizerTerm, StepContext, TrainCallback};
+use crate::lr_schedule::LrSchedule;
+use crate::mcmc::{relocated, sample_by_opacity};
+use crate::parallel::DataParallel;
+use crate::pose_refine::PoseRefinement;
 use crate::scene::{SceneView, ViewImageType};
+use crate::sparse_adam::SparseAdam;
 use crate::ssim::Ssim;
 use crate::stats::RefineRecord;
-use clap::Args;
+use clap::{Args, ValueEnum};
+
+/// How splats are added and removed while training.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, ValueEnum,
+)]
+pub enum DensificationStrategy {
+    /// Clone and split splats with high gradients, and prune transparent and huge splats.
+    #[default]
+    Classic,
+    /// Move transparent splats to where other splats are, and add splats up to a budget, as in
+    /// 3DGS-MCMC. Noise is added to the positions of transparent splats every step.
+    Mcmc,
+}
+
+/// Which screenspace gradients of the splats decide what to clone and split, with
+/// [`DensificationStrategy::Classic`].
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, ValueEnum,
+)]
+pub enum DensifySignal {
+    /// The norm of the summed xy gradients of every pixel, as in 3DGS.
+    #[default]
+    Grad,
+    /// The norm of the summed absolute xy gradients of every pixel, as in AbsGS. These don't
+    /// cancel out over the pixels of a big blurry splat, so it's split as well. See
+    /// [`RenderOptions::absgrad`].
+    AbsGrad,
+}
+
+// The resolution the absgrad threshold is given for.
+const ABSGRAD_REFERENCE_RESOLUTION: f32 = 1000.0;
+
+/// How the errors of the rendered colors are mixed into the image loss. The default is the
+/// 0.8 * L1 + 0.2 * D-SSIM of the reference 3DGS implementation.
+#[derive(Config, Args)]
+pub struct LossConfig {
+    /// Weight of the L1 loss.
+    #[config(default = 0.8)]
+    #[arg(long, help_heading = "Loss options", default_value = "0.8")]
+    pub l1_weight: f32,
+    /// Weight of the L2 loss.
+    #[config(default = 0.0)]
+    #[arg(long, help_heading = "Loss options", default_value = "0.0")]
+    pub l2_weight: f32,
+    /// Weight of the D-SSIM loss, 1 - SSIM. This used to be `--ssim-weight`, which also set the
+    /// L1 weight to 1 - the weight, so it was renamed to not silently change old runs.
+    #[config(default = 0.2)]
+    #[arg(long, help_heading = "Loss options", default_value = "0.2")]
+    pub dssim_weight: f32,
+}
 
 #[derive(Config, Args)]
 pub struct TrainConfig {
@@ -29,10 +86,9 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Training options", default_value = "30000")]
     pub total_steps: u32,
 
-    /// Weight of SSIM loss (compared to l1 loss)
-    #[config(default = 0.2)]
-    #[clap(long, help_heading = "Training options", default_value = "0.2")]
-    ssim_weight: f32,
+    #[config(default = "LossConfig::new()")]
+    #[clap(flatten)]
+    pub loss: LossConfig,
 
     /// SSIM window size
     #[config(default = 11)]
@@ -44,7 +100,8 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Training options", default_value = "1e-4")]
     lr_mean: f64,
 
-    /// Start learning rate for the mean.
+    /// End learning rate for the mean. The rate decays exponentially to it over all steps.
+    /// Both rates are relative to the extent of the scene.
     #[config(default = 1e-6)]
     #[arg(long, help_heading = "Training options", default_value = "1e-6")]
     lr_mean_end: f64,
@@ -57,6 +114,12 @@ pub struct TrainConfig {
     #[config(default = 25.0)]
     #[arg(long, help_heading = "Training options", default_value = "20.0")]
     lr_coeffs_sh_scale: f32,
+    /// Train only the base colors at first, and unlock one more SH band every this many steps.
+    /// 0 trains all bands from the start. Initialized splats start with zero higher bands, the
+    /// higher bands of loaded splats are kept as is until they're unlocked.
+    #[config(default = 1000)]
+    #[arg(long, help_heading = "Training options", default_value = "1000")]
+    sh_degree_interval: u32,
     /// Learning rate for the opacity.
     #[config(default = 3e-2)]
     #[arg(long, help_heading = "Training options", default_value = "3e-2")]
@@ -69,17 +132,145 @@ pub struct TrainConfig {
     #[config(default = 1e-3)]
     #[arg(long, help_heading = "Training options", default_value = "1e-3")]
     lr_rotation: f64,
+    /// Nr. of steps to ramp up all learning rates over at the start of training.
+    #[config(default = 0)]
+    #[arg(long, help_heading = "Training options", default_value = "0")]
+    lr_warmup_steps: u32,
+    /// Fraction of the learning rates to start the warmup at.
+    #[config(default = 0.01)]
+    #[arg(long, help_heading = "Training options", default_value = "0.01")]
+    lr_warmup_mult: f64,
 
     /// Weight of mean-opacity loss.
     #[config(default = 0.0)]
     #[arg(long, help_heading = "Training options", default_value = "0.0")]
     opac_loss_weight: f32,
 
+    /// Weight of the depth loss, for views with a known depth. See `ViewDepth`. The depth
+    /// helps against floaters in regions with little texture.
+    #[config(default = 0.0)]
+    #[arg(long, help_heading = "Training options", default_value = "0.0")]
+    pub depth_loss_weight: f32,
+
+    /// Weight of the loss on the smallest scale of every splat, which flattens the splats into
+    /// surfels as in 2DGS. Flat splats give cleaner surfaces for solid renders and meshing.
+    #[config(default = 0.0)]
+    #[arg(long, help_heading = "Training options", default_value = "0.0")]
+    pub flatten_loss_weight: f32,
+
+    /// Weight of the loss between the rendered normals and the normals of the rendered depth,
+    /// which lines up flattened splats with the surface they're on.
+    #[config(default = 0.0)]
+    #[arg(long, help_heading = "Training options", default_value = "0.0")]
+    pub normal_consistency_weight: f32,
+
+    /// Learn an exposure and color correction for every view, so exposure and white balance
+    /// differences between photos aren't baked into the splats. Only used while training.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub appearance_model: bool,
+
+    /// Learning rate of the per view exposure and color correction.
+    #[config(default = 1e-3)]
+    #[arg(long, help_heading = "Training options", default_value = "1e-3")]
+    lr_appearance: f64,
+
+    /// Refine the camera pose of every view while training, to correct slightly wrong poses.
+    /// Only used while training.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub pose_refinement: bool,
+
+    /// Learning rate of the camera pose corrections.
+    #[config(default = 1e-5)]
+    #[arg(long, help_heading = "Training options", default_value = "1e-5")]
+    lr_pose: f64,
+
     /// How much opacity to subtrat every refine step.
     #[config(default = 0.004)]
     #[arg(long, help_heading = "Training options", default_value = "0.004")]
     opac_refine_subtract: f32,
 
+    /// Render with the opacity of splats compensated for the screenspace blur (Mip-Splatting
+    /// style), so the splats alias less when rendered at other resolutions.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub antialias: bool,
+
+    /// Only update the splats visible in the training view every step. This speeds up
+    /// training big scenes, where only a small part is visible in any view. Not used when
+    /// training on multiple GPUs, as each GPU sees different splats, with regularizers, which
+    /// affect splats that aren't visible, or with `half_precision`.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    sparse_adam: bool,
+
+    /// Store the scales and SH coefficients at half precision while training, which halves the
+    /// memory the renders read. The optimizer steps an f32 copy of these, so small updates
+    /// aren't lost. Ignored on GPUs without f16 support.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    half_precision: bool,
+
+    /// With half precision, also store the means at half precision. Renders read less memory,
+    /// but lose detail in scenes far away from the origin.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    half_precision_means: bool,
+
+    /// Render again in the backward pass, rather than keeping the buffers of the forward pass in
+    /// memory until then. Saves a lot of memory at high resolutions, but steps take ~20% longer.
+    #[config(default = false)]
+    #[arg(long, help_heading = "Training options", default_value = "false")]
+    pub recompute_backward: bool,
+
+    /// Epsilon of the Adam optimizer, added to the moments for numerical stability.
+    #[config(default = 1e-15)]
+    #[arg(long, help_heading = "Training options", default_value = "1e-15")]
+    adam_epsilon: f32,
+
+    /// How splats are added and removed.
+    #[config(default = "DensificationStrategy::Classic")]
+    #[arg(
+        long,
+        help_heading = "Refine options",
+        value_enum,
+        default_value = "classic"
+    )]
+    pub densification: DensificationStrategy,
+
+    /// With MCMC densification, the max nr. of splats.
+    #[config(default = 1000000)]
+    #[arg(long, help_heading = "Refine options", default_value = "1000000")]
+    pub max_splats: u32,
+
+    /// With clone and split densification, prune the least important splats to stay under this
+    /// many splats. Unlimited by default.
+    #[arg(long, help_heading = "Refine options")]
+    pub splat_budget: Option<u32>,
+
+    /// With MCMC densification, the nr. of splats grows by this fraction every refine step,
+    /// until reaching `max_splats`.
+    #[config(default = 0.05)]
+    #[arg(long, help_heading = "Refine options", default_value = "0.05")]
+    mcmc_growth: f32,
+
+    /// With MCMC densification, how much noise to add to the positions of transparent splats,
+    /// relative to the learning rate of the means.
+    #[config(default = 5e5)]
+    #[arg(long, help_heading = "Refine options", default_value = "5e5")]
+    mcmc_noise_lr: f32,
+
+    /// With MCMC densification, weight of the loss on the mean opacity.
+    #[config(default = 0.01)]
+    #[arg(long, help_heading = "Refine options", default_value = "0.01")]
+    mcmc_opacity_reg: f32,
+
+    /// With MCMC densification, weight of the loss on the mean scale.
+    #[config(default = 0.01)]
+    #[arg(long, help_heading = "Refine options", default_value = "0.01")]
+    mcmc_scale_reg: f32,
+
     /// GSs with opacity below this value will be pruned
     #[config(default = 0.002)]
     #[arg(long, help_heading = "Refine options", default_value = "0.002")]
@@ -90,6 +281,23 @@ pub struct TrainConfig {
     #[arg(long, help_heading = "Refine options", default_value = "0.0002")]
     densify_grad_thresh: f32,
 
+    /// Which gradients to compare to the densify threshold.
+    #[config(default = "DensifySignal::Grad")]
+    #[arg(
+        long,
+        help_heading = "Refine options",
+        value_enum,
+        default_value = "grad"
+    )]
+    pub densify_signal: DensifySignal,
+
+    /// Threshold for the absolute positional gradient norm, for views with 1000 pixels on
+    /// their longest side. Small splats get smaller gradients at higher resolutions, so this
+    /// is scaled by the resolution of the training views.
+    #[config(default = 0.0008)]
+    #[arg(long, help_heading = "Refine options", default_value = "0.0008")]
+    densify_absgrad_thresh: f32,
+
     /// Gaussians bigger than this size in screenspace radius are split
     #[config(default = 0.1)]
     #[arg(long, help_heading = "Refine options", default_value = "0.1")]
@@ -131,21 +339,128 @@ pub struct TrainConfig {
     alpha_loss_weight: f32,
 }
 
+impl TrainConfig {
+    /// Config to briefly fine-tune an already trained model for `steps` steps. The learning
+    /// rate of the means starts where a full training run ends, and all SH bands are trained.
+    pub fn finetune(&self, steps: u32) -> Self {
+        self.clone()
+            .with_total_steps(steps)
+            .with_lr_mean(self.lr_mean_end)
+            .with_sh_degree_interval(0)
+    }
+
+    /// The learning rates and loss weights that can be changed while training.
+    pub fn tweaks(&self) -> TrainTweaks {
+        TrainTweaks {
+            lr_mean: self.lr_mean,
+            lr_coeffs_dc: self.lr_coeffs_dc,
+            lr_opac: self.lr_opac,
+            lr_scale: self.lr_scale,
+            lr_rotation: self.lr_rotation,
+            l1_weight: self.loss.l1_weight,
+            l2_weight: self.loss.l2_weight,
+            dssim_weight: self.loss.dssim_weight,
+            opac_loss_weight: self.opac_loss_weight,
+            depth_loss_weight: self.depth_loss_weight,
+        }
+    }
+
+    /// Config with the learning rates and loss weights of `tweaks`. The end learning rate of
+    /// the means moves along with its start rate.
+    pub fn with_tweaks(&self, tweaks: TrainTweaks) -> Self {
+        let mut config = self.clone();
+        config.lr_mean_end = if self.lr_mean > 0.0 {
+            self.lr_mean_end * tweaks.lr_mean / self.lr_mean
+        } else {
+            tweaks.lr_mean
+        };
+        config.lr_mean = tweaks.lr_mean;
+        config.lr_coeffs_dc = tweaks.lr_coeffs_dc;
+        config.lr_opac = tweaks.lr_opac;
+        config.lr_scale = tweaks.lr_scale;
+        config.lr_rotation = tweaks.lr_rotation;
+        config.loss.l1_weight = tweaks.l1_weight;
+        config.loss.l2_weight = tweaks.l2_weight;
+        config.loss.dssim_weight = tweaks.dssim_weight;
+        config.opac_loss_weight = tweaks.opac_loss_weight;
+        config.depth_loss_weight = tweaks.depth_loss_weight;
+        config
+    }
+}
+
+/// The learning rates and loss weights of a [`TrainConfig`], which can be changed while
+/// training with [`SplatTrainer::set_tweaks`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrainTweaks {
+    /// Start learning rate of the means.
+    pub lr_mean: f64,
+    pub lr_coeffs_dc: f64,
+    pub lr_opac: f64,
+    pub lr_scale: f64,
+    pub lr_rotation: f64,
+    pub l1_weight: f32,
+    pub l2_weight: f32,
+    pub dssim_weight: f32,
+    pub opac_loss_weight: f32,
+    pub depth_loss_weight: f32,
+}
+
 type B = Autodiff<Wgpu>;
 
+/// Known depth of a train view, see [`crate::scene::ViewDepth`].
+#[derive(Clone, Debug)]
+pub enum DepthTarget<B: Backend> {
+    /// A `[h, w]` depth map, only known up to a scale and offset. Zero where unknown.
+    Map(Tensor<B, 2>),
+    /// The depth of sparse points, at flat pixel indices into the image.
+    Points {
+        pixels: Tensor<B, 1, Int>,
+        depth: Tensor<B, 1>,
+    },
+}
+
 #[derive(Clone, Debug)]
 pub struct SceneBatch<B: Backend> {
     pub gt_image: Tensor<B, 3>,
+    pub gt_depth: Option<DepthTarget<B>>,
     pub gt_view: SceneView,
+    /// Index of the view in the scene.
+    pub view_index: usize,
     pub scene_extent: f32,
 }
 
-#[derive(Clone)]
+/// A batch with a black 16x16 image, seen from a camera looking at the origin from -z.
+#[cfg(test)]
+pub(crate) fn test_batch(device: &WgpuDevice) -> SceneBatch<B> {
+    SceneBatch {
+        gt_image: Tensor::zeros([16, 16, 3], device),
+        gt_depth: None,
+        gt_view: SceneView {
+            path: "test".to_owned(),
+            camera: Camera::new(
+                glam::vec3(0.0, 0.0, -3.0),
+                glam::Quat::IDENTITY,
+                0.8,
+                0.8,
+                glam::vec2(0.5, 0.5),
+            ),
+            image: std::sync::Arc::new(image::DynamicImage::new_rgb8(16, 16)),
+            img_type: ViewImageType::Alpha,
+            original_size: (16, 16),
+            depth: None,
+        },
+        view_index: 0,
+        scene_extent: 1.0,
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct RefineStats {
     pub num_split: usize,
     pub num_cloned: usize,
     pub num_transparent_pruned: usize,
     pub num_scale_pruned: usize,
+    pub num_budget_pruned: usize,
 }
 
 #[derive(Clone)]
@@ -156,6 +471,8 @@ pub struct TrainStepStats<B: AutodiffBackend> {
 
     pub num_intersections: Tensor<B, 1, Int>,
     pub num_visible: Tensor<B, 1, Int>,
+    /// See [`RenderAux::intersection_capacity`].
+    pub intersection_capacity: u32,
 
     pub loss: Tensor<B, 1>,
 
@@ -167,13 +484,25 @@ pub struct TrainStepStats<B: AutodiffBackend> {
 }
 
 type OptimizerType = OptimizerAdaptor<AdamScaled, Splats<B>, B>;
+type AppearanceOptimizer = OptimizerAdaptor<AdamScaled, Appearance<B>, B>;
+type PoseOptimizer = OptimizerAdaptor<AdamScaled, PoseRefinement<B>, B>;
 
 pub struct SplatTrainer {
     config: TrainConfig,
-    sched_mean: ExponentialLrScheduler,
-    optim: OptimizerType,
+    // Schedules of the means, rotations, scales, coefficients and opacities.
+    lr_schedules: [LrSchedule; 5],
+    pub(crate) optim: OptimizerType,
+    sparse_optim: SparseAdam,
     ssim: Ssim<B>,
-    refine_record: RefineRecord,
+    pub(crate) refine_record: RefineRecord,
+    parallel: Option<DataParallel>,
+    pub(crate) appearance: Option<Appearance<B>>,
+    pub(crate) appearance_optim: AppearanceOptimizer,
+    pub(crate) pose: Option<PoseRefinement<B>>,
+    pub(crate) pose_optim: PoseOptimizer,
+    regularizers: Vec<Box<dyn RegularizerTerm>>,
+    callbacks: Vec<Box<dyn TrainCallback>>,
+    render_options: RenderOptions,
 }
 
 fn quaternion_vec_multiply<B: Backend>(
@@ -226,22 +555,389 @@ pub fn inv_sigmoid<B: Backend>(x: Tensor<B, 1>) -> Tensor<B, 1> {
     (x.clone() / (-x + 1.0)).log()
 }
 
+// Error of the rendered depth compared to the known depth, relative to the depth, so the loss
+// doesn't depend on the scale of the scene.
+fn depth_loss<B: Backend>(pred: Tensor<B, 2>, target: &DepthTarget<B>) -> Tensor<B, 1> {
+    match target {
+        DepthTarget::Points { pixels, depth } => {
+            let [h, w] = pred.dims();
+            let pred = pred.reshape([h * w]).select(0, pixels.clone());
+            ((pred - depth.clone()).abs() / depth.clone().clamp_min(1e-6)).mean()
+        }
+        DepthTarget::Map(depth) => {
+            // Fit the scale and offset that best map the depth map to the rendered depth, over
+            // the pixels where both are known, and compare to the mapped depth.
+            let known =
+                depth.clone().greater_elem(0.0).float() * pred.clone().greater_elem(0.0).float();
+            let count = known.clone().sum().clamp_min(1.0);
+            let mean =
+                |t: Tensor<B, 2>| ((t * known.clone()).sum() / count.clone()).reshape([1, 1]);
+
+            let pred_mean = mean(pred.clone().detach());
+            let depth_mean = mean(depth.clone());
+            let d_pred = (pred.clone().detach() - pred_mean.clone()) * known.clone();
+            let d_depth = (depth.clone() - depth_mean.clone()) * known.clone();
+            let scale = ((d_depth.clone() * d_pred).sum()
+                / d_depth.powf_scalar(2.0).sum().clamp_min(1e-12))
+            .reshape([1, 1]);
+            let aligned = (depth.clone() - depth_mean) * scale + pred_mean.clone();
+
+            ((pred - aligned).abs() * known).sum()
+                / (count * pred_mean.reshape([1]).clamp_min(1e-6))
+        }
+    }
+}
+
+// The camera space normals of a depth map, from the cross product of the differences to the
+// neighbouring pixels, `[h - 1, w - 1, 3]`. The normals face the camera.
+fn depth_normals<B: Backend>(depth: Tensor<B, 2>, camera: &Camera) -> Tensor<B, 3> {
+    let [h, w] = depth.dims();
+    let device = depth.device();
+    let img_size = glam::uvec2(w as u32, h as u32);
+    let (focal, center) = (camera.focal(img_size), camera.center(img_size));
+
+    // Unproject the pixel centers to camera space.
+    let u = Tensor::<B, 1, Int>::arange(0..w as i64, &device)
+        .float()
+        .add_scalar(0.5 - center.x)
+        .div_scalar(focal.x)
+        .reshape([1, w]);
+    let v = Tensor::<B, 1, Int>::arange(0..h as i64, &device)
+        .float()
+        .add_scalar(0.5 - center.y)
+        .div_scalar(focal.y)
+        .reshape([h, 1]);
+    let points = Tensor::stack::<3>(vec![u * depth.clone(), v * depth.clone(), depth], 2);
+
+    let corner = points.clone().slice([0..h - 1, 0..w - 1]);
+    let dx = points.clone().slice([0..h - 1, 1..w]) - corner.clone();
+    let dy = points.slice([1..h, 0..w - 1]) - corner;
+    let c = |t: &Tensor<B, 3>, i: usize| t.clone().slice([0..h - 1, 0..w - 1, i..i + 1]);
+
+    // With y down, dy x dx faces the camera.
+    let normals = Tensor::cat(
+        vec![
+            c(&dy, 1) * c(&dx, 2) - c(&dy, 2) * c(&dx, 1),
+            c(&dy, 2) * c(&dx, 0) - c(&dy, 0) * c(&dx, 2),
+            c(&dy, 0) * c(&dx, 1) - c(&dy, 1) * c(&dx, 0),
+        ],
+        2,
+    );
+    let len = normals.clone().powf_scalar(2.0).sum_dim(2).sqrt();
+    normals / len.clamp_min(1e-12)
+}
+
+// How far the rendered normals are from the normals of the rendered depth, where both are known.
+fn normal_consistency_loss<B: Backend>(
+    normals: Tensor<B, 3>,
+    depth: Tensor<B, 2>,
+    camera: &Camera,
+) -> Tensor<B, 1> {
+    let [h, w] = depth.dims();
+    let known = depth
+        .clone()
+        .greater_elem(0.0)
+        .float()
+        .slice([0..h - 1, 0..w - 1])
+        .unsqueeze_dim(2);
+    let normals = normals.slice([0..h - 1, 0..w - 1]);
+    let cos = (normals * depth_normals(depth, camera)).sum_dim(2);
+    ((cos.neg() + 1.0) * known.clone()).sum() / known.sum().clamp_min(1.0)
+}
+
+/// Render the view of a batch, and calculate the training loss. The rendered colors are
+/// corrected by the appearance of the view, and the camera by the pose correction of the view,
+/// if any. The loss includes the extra `regularizers`, and the `callbacks` observe the step.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn render_loss(
+    config: &TrainConfig,
+    ssim: &Ssim<B>,
+    iter: u32,
+    batch: &SceneBatch<B>,
+    splats: &Splats<B>,
+    render_options: RenderOptions,
+    appearance: Option<&Appearance<B>>,
+    pose: Option<&PoseRefinement<B>>,
+    regularizers: &[Box<dyn RegularizerTerm>],
+    callbacks: &mut [Box<dyn TrainCallback>],
+) -> (Tensor<B, 3>, RenderAux<B>, Tensor<B, 1>) {
+    let [img_h, img_w, _] = batch.gt_image.dims();
+
+    let camera = &batch.gt_view.camera;
+
+    let splats = match pose.filter(|p| batch.view_index < p.num_views()) {
+        Some(pose) => pose.apply(splats, camera, batch.view_index),
+        None => splats.clone(),
+    }
+    .with_render_options(render_options);
+    let splats = &splats;
+
+    let img_size = glam::uvec2(img_w as u32, img_h as u32);
+    let (pred_image, aux) = if config.antialias {
+        splats.render_antialiased(camera, img_size, false)
+    } else {
+        splats.render(camera, img_size, false)
+    };
+
+    let _span = trace_span!("Calculate losses", sync_burn = true).entered();
+
+    let mut pred_rgb = pred_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+    if let Some(appearance) = appearance.filter(|a| batch.view_index < a.num_views()) {
+        pred_rgb = appearance.apply(pred_rgb, batch.view_index);
+    }
+    let gt_rgb = batch.gt_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+
+    let mask = (batch.gt_view.img_type == ViewImageType::Masked
+        && batch.gt_view.image.color().has_alpha())
+    .then(|| batch.gt_image.clone().slice([0..img_h, 0..img_w, 3..4]));
+
+    // The SSIM of a pixel depends on its neighbours. Fill in the masked out pixels from the
+    // ground truth so no gradient flows to them through the SSIM of nearby pixels.
+    if let Some(mask) = &mask {
+        pred_rgb = pred_rgb * mask.clone() + gt_rgb.clone() * (mask.clone().neg() + 1.0);
+    }
+
+    let loss_config = &config.loss;
+    let diff = pred_rgb.clone() - gt_rgb.clone();
+    let mut total_err = diff.clone().abs() * loss_config.l1_weight;
+    if loss_config.l2_weight > 0.0 {
+        total_err = total_err + diff.powi_scalar(2) * loss_config.l2_weight;
+    }
+    if loss_config.dssim_weight > 0.0 {
+        let ssim_err = ssim.ssim(pred_rgb, gt_rgb).neg().add_scalar(1.0);
+        total_err = total_err + ssim_err * loss_config.dssim_weight;
+    }
+
+    let mut loss = if let Some(mask) = mask {
+        // In masked mode, weigh the errors by the mask.
+        (total_err * mask).mean()
+    } else if batch.gt_view.image.color().has_alpha() {
+        // In alpha mode, add the l1 error of the alpha channel to the total error.
+        let alpha_input = batch.gt_image.clone().slice([0..img_h, 0..img_w, 3..4]);
+        let pred_alpha = pred_image.clone().slice([0..img_h, 0..img_w, 3..4]);
+        total_err.mean() + (alpha_input - pred_alpha).abs().mean() * config.alpha_loss_weight
+    } else {
+        total_err.mean()
+    };
+
+    // Add in opacity loss if enabled.
+    if config.opac_loss_weight > 0.0 {
+        let opac_loss = splats.opacity().mean();
+        loss = loss + opac_loss * config.opac_loss_weight;
+    }
+
+    // MCMC keeps splats small and transparent unless needed, so the spare ones are relocated.
+    if config.densification == DensificationStrategy::Mcmc {
+        loss = loss
+            + splats.opacity().mean() * config.mcmc_opacity_reg
+            + splats.scales().mean() * config.mcmc_scale_reg;
+    }
+
+    if config.flatten_loss_weight > 0.0 {
+        let min_scales = splats.scales().min_dim(1);
+        loss = loss + min_scales.mean() * config.flatten_loss_weight;
+    }
+
+    let gt_depth = batch
+        .gt_depth
+        .as_ref()
+        .filter(|_| config.depth_loss_weight > 0.0);
+    if gt_depth.is_some() || config.normal_consistency_weight > 0.0 {
+        let pred_depth = splats.render_depth_map(camera, img_size);
+        if let Some(gt_depth) = gt_depth {
+            loss = loss + depth_loss(pred_depth.clone(), gt_depth) * config.depth_loss_weight;
+        }
+        if config.normal_consistency_weight > 0.0 {
+            let normals = splats.render_normal_map(camera, img_size);
+            loss = loss
+                + normal_consistency_loss(normals, pred_depth, camera)
+                    * config.normal_consistency_weight;
+        }
+    }
+
+    let step = StepContext {
+        iter,
+        batch,
+        pred_image: &pred_image,
+        aux: &aux,
+        splats,
+    };
+    for term in regularizers {
+        let _span = trace_span!("Regularizer", term = term.name()).entered();
+        if let Some(term_loss) = term.loss(&step) {
+            loss = loss + term_loss;
+        }
+    }
+    for callback in callbacks {
+        callback.on_step(&step, &loss);
+    }
+
+    (pred_image, aux, loss)
+}
+
+fn lr_schedules(config: &TrainConfig) -> [LrSchedule; 5] {
+    [
+        LrSchedule::exponential(config.lr_mean, config.lr_mean_end, config.total_steps),
+        LrSchedule::constant(config.lr_rotation),
+        // Scale is relative to the scene scale, but the exp() activation function
+        // means "offsetting" all values also solves the learning rate scaling.
+        LrSchedule::constant(config.lr_scale),
+        LrSchedule::constant(config.lr_coeffs_dc),
+        LrSchedule::constant(config.lr_opac),
+    ]
+    .map(|s| s.with_warmup(config.lr_warmup_steps, config.lr_warmup_mult))
+}
+
 impl SplatTrainer {
     pub fn new(splats: &Splats<B>, config: &TrainConfig, device: &WgpuDevice) -> Self {
-        let optim = AdamScaledConfig::new().with_epsilon(1e-15).init();
+        let optim_config = AdamScaledConfig::new().with_epsilon(config.adam_epsilon);
+        let optim = optim_config.init();
+        let sparse_optim = optim_config.init_sparse();
 
         let ssim = Ssim::new(config.ssim_window_size, 3, device);
 
-        let decay = (config.lr_mean_end / config.lr_mean).powf(1.0 / config.total_steps as f64);
-        let lr_mean = ExponentialLrSchedulerConfig::new(config.lr_mean, decay);
+        if config.half_precision && !render::has_half_floats() {
+            log::warn!("The GPU doesn't support f16, training at full precision instead");
+        }
 
         Self {
             config: config.clone(),
-            sched_mean: lr_mean.init().expect("Lr schedule must be valid."),
+            lr_schedules: lr_schedules(config),
             optim,
+            sparse_optim,
             refine_record: RefineRecord::new(splats.num_splats(), device),
             ssim,
+            parallel: None,
+            appearance: None,
+            appearance_optim: optim_config.init(),
+            pose: None,
+            pose_optim: optim_config.init(),
+            regularizers: vec![],
+            callbacks: vec![],
+            render_options: RenderOptions {
+                absgrad: config.densify_signal == DensifySignal::AbsGrad,
+                recompute_backward: config.recompute_backward,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Render and calculate gradients in a fixed order, so runs with the same seed give bit
+    /// identical results, see [`RenderOptions::deterministic`].
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.render_options.deterministic = deterministic;
+        self
+    }
+
+    /// Add an extra term to the training loss, see [`RegularizerTerm`].
+    pub fn with_regularizer(mut self, term: impl RegularizerTerm + 'static) -> Self {
+        self.regularizers.push(Box::new(term));
+        self
+    }
+
+    /// Observe every training step, see [`TrainCallback`].
+    pub fn with_callback(mut self, callback: impl TrainCallback + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Learn the appearance of the `num_views` views of the train scene, if enabled in the
+    /// config. See [`Appearance`].
+    pub fn with_appearance(mut self, num_views: usize, device: &WgpuDevice) -> Self {
+        self.appearance = self
+            .config
+            .appearance_model
+            .then(|| Appearance::new(num_views, device));
+        self
+    }
+
+    /// Refine the camera poses of the `num_views` views of the train scene, if enabled in the
+    /// config. See [`PoseRefinement`].
+    pub fn with_pose_refinement(mut self, num_views: usize, device: &WgpuDevice) -> Self {
+        self.pose = self
+            .config
+            .pose_refinement
+            .then(|| PoseRefinement::new(num_views, device));
+        self
+    }
+
+    /// Grow the appearance and pose corrections to the `num_views` views of a train scene that
+    /// had views added. The added views start without a correction, the corrections of the
+    /// existing views and their optimizer state are kept.
+    pub fn grow_views(&mut self, num_views: usize) {
+        if let Some(appearance) = &mut self.appearance {
+            let added = num_views.saturating_sub(appearance.num_views());
+            if added > 0 {
+                let device = appearance.log_exposure.device();
+                let new = Appearance::<B>::new(added, &device);
+                let mut record = self.appearance_optim.to_record();
+                map_param(
+                    &mut appearance.log_exposure,
+                    &mut record,
+                    |t| Tensor::cat(vec![t, new.log_exposure.val()], 0),
+                    |t| pad_views(t, added),
+                );
+                map_param(
+                    &mut appearance.color,
+                    &mut record,
+                    |t| Tensor::cat(vec![t, new.color.val()], 0),
+                    |t| pad_views(t, added),
+                );
+                self.appearance_optim = self.appearance_optim.clone().load_record(record);
+            }
         }
+
+        if let Some(pose) = &mut self.pose {
+            let added = num_views.saturating_sub(pose.num_views());
+            if added > 0 {
+                let mut record = self.pose_optim.to_record();
+                map_param(
+                    &mut pose.rotations,
+                    &mut record,
+                    |t| pad_views(t, added),
+                    |t| pad_views(t, added),
+                );
+                map_param(
+                    &mut pose.translations,
+                    &mut record,
+                    |t| pad_views(t, added),
+                    |t| pad_views(t, added),
+                );
+                self.pose_optim = self.pose_optim.clone().load_record(record);
+            }
+        }
+    }
+
+    /// Change the learning rates and loss weights from the next step on.
+    pub fn set_tweaks(&mut self, tweaks: TrainTweaks) {
+        self.config = self.config.with_tweaks(tweaks);
+        self.lr_schedules = lr_schedules(&self.config);
+    }
+
+    /// The pose corrections of the train views, if refined.
+    pub fn pose_refinement(&self) -> Option<&PoseRefinement<B>> {
+        self.pose.as_ref()
+    }
+
+    /// Store the splats at the precision set in the config, see [`Splats::with_half_precision`].
+    pub fn with_storage_precision(&self, splats: Splats<B>) -> Splats<B> {
+        if self.half_precision() {
+            splats.with_half_precision(self.config.half_precision_means)
+        } else {
+            splats
+        }
+    }
+
+    // Half precision is only used when the GPU supports it.
+    fn half_precision(&self) -> bool {
+        self.config.half_precision && render::has_half_floats()
+    }
+
+    /// Also train on these devices, see [`DataParallel`]. Steps then need a batch for every
+    /// extra device, see [`Self::step_parallel`].
+    pub fn with_devices(mut self, devices: &[WgpuDevice]) -> Self {
+        self.parallel = (!devices.is_empty()).then(|| DataParallel::new(devices, &self.config));
+        self
     }
 
     pub fn step(
@@ -249,73 +945,99 @@ impl SplatTrainer {
         iter: u32,
         batch: SceneBatch<B>,
         splats: Splats<B>,
+    ) -> (Splats<B>, TrainStepStats<B>) {
+        self.step_parallel(iter, batch, vec![], splats)
+    }
+
+    /// Take a step on the main device's batch, and a batch for every extra device. The stats
+    /// are of the main device's batch.
+    pub fn step_parallel(
+        &mut self,
+        iter: u32,
+        batch: SceneBatch<B>,
+        replica_batches: Vec<SceneBatch<B>>,
+        splats: Splats<B>,
     ) -> (Splats<B>, TrainStepStats<B>) {
         let mut splats = splats;
 
-        let [img_h, img_w, _] = batch.gt_image.dims();
+        let appearance = self.appearance.as_ref();
+        let pose = self.pose.as_ref();
+        let (pred_image, aux, loss) = render_loss(
+            &self.config,
+            &self.ssim,
+            iter,
+            &batch,
+            &splats,
+            self.render_options,
+            appearance,
+            pose,
+            &self.regularizers,
+            &mut self.callbacks,
+        );
+        // The backward pass renders again, so don't keep the intersections around until then.
+        let aux = if self.config.recompute_backward {
+            aux.without_intersections()
+        } else {
+            aux
+        };
 
-        let (pred_image, aux, loss) = {
-            let camera = &batch.gt_view.camera;
+        let mut grads = trace_span!("Backward pass", sync_burn = true).in_scope(|| loss.backward());
 
-            let (pred_image, aux) =
-                splats.render(camera, glam::uvec2(img_w as u32, img_h as u32), false);
+        if let Some(parallel) = &self.parallel {
+            trace_span!("Replica steps", sync_burn = true).in_scope(|| {
+                parallel.average_grads(
+                    &self.config,
+                    iter,
+                    &self.regularizers,
+                    &splats,
+                    self.render_options,
+                    appearance,
+                    pose,
+                    replica_batches,
+                    &mut grads,
+                );
+            });
+        }
 
-            let _span = trace_span!("Calculate losses", sync_burn = true).entered();
+        if let Some(appearance) = self.appearance.take() {
+            let grads = GradientsParams::from_module(&mut grads, &appearance);
+            let appearance =
+                self.appearance_optim
+                    .step(self.config.lr_appearance, appearance, grads);
+            self.appearance = Some(appearance);
+        }
 
-            let pred_rgb = pred_image.clone().slice([0..img_h, 0..img_w, 0..3]);
-            let gt_rgb = batch.gt_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+        if let Some(pose) = self.pose.take() {
+            let grads = GradientsParams::from_module(&mut grads, &pose);
+            self.pose = Some(self.pose_optim.step(self.config.lr_pose, pose, grads));
+        }
 
-            let l1_rgb = (pred_rgb.clone() - gt_rgb).abs();
+        let sh_degree = self.active_sh_degree(iter, &splats);
+        if sh_degree < splats.sh_degree() {
+            lock_sh_bands(&splats, &mut grads, sh_degree);
+        }
 
-            let total_err = if self.config.ssim_weight > 0.0 {
-                let gt_rgb = batch.gt_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+        let [lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac] =
+            self.lr_schedules.each_ref().map(|s| s.lr_at(iter));
+        let lr_mean = lr_mean * batch.scene_extent as f64;
 
-                let ssim_err = -self.ssim.ssim(pred_rgb, gt_rgb);
-                l1_rgb * (1.0 - self.config.ssim_weight) + ssim_err * self.config.ssim_weight
-            } else {
-                l1_rgb
-            };
-
-            let mut loss = if batch.gt_view.image.color().has_alpha() {
-                let alpha_input = batch.gt_image.clone().slice([0..img_h, 0..img_w, 3..4]);
-
-                match batch.gt_view.img_type {
-                    // In masked mode, weigh the errors by the alpha channel.
-                    ViewImageType::Masked => (total_err * alpha_input).mean(),
-                    // In alpha mode, add the l1 error of the alpha channel to the total error.
-                    ViewImageType::Alpha => {
-                        let pred_alpha = pred_image.clone().slice([0..img_h, 0..img_w, 3..4]);
-                        total_err.mean()
-                            + (alpha_input - pred_alpha).abs().mean()
-                                * self.config.alpha_loss_weight
-                    }
-                }
-            } else {
-                total_err.mean()
-            };
+        // With multiple GPUs, splats visible on the other GPUs have gradients too, as do all
+        // splats with regularizers. The sparse kernel only steps f32 parameters.
+        let sparse = self.config.sparse_adam
+            && self.parallel.is_none()
+            && self.regularizers.is_empty()
+            && !self.half_precision();
 
-            // Add in opacity loss if enabled.
-            if self.config.opac_loss_weight > 0.0 {
-                let opac_loss = splats.opacity().mean();
-                loss = loss + opac_loss * self.config.opac_loss_weight;
+        splats = trace_span!("Optimizer step", sync_burn = true).in_scope(|| {
+            if sparse {
+                return self.sparse_step(
+                    splats,
+                    &mut grads,
+                    &aux,
+                    [lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac],
+                );
             }
 
-            (pred_image, aux, loss)
-        };
-
-        let mut grads = trace_span!("Backward pass", sync_burn = true).in_scope(|| loss.backward());
-
-        let (lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac) = (
-            self.sched_mean.step() * batch.scene_extent as f64,
-            self.config.lr_rotation,
-            // Scale is relative to the scene scale, but the exp() activation function
-            // means "offsetting" all values also solves the learning rate scaling.
-            self.config.lr_scale,
-            self.config.lr_coeffs_dc,
-            self.config.lr_opac,
-        );
-
-        splats = trace_span!("Optimizer step", sync_burn = true).in_scope(|| {
             splats = trace_span!("SH Coeffs step", sync_burn = true).in_scope(|| {
                 let grad_coeff =
                     GradientsParams::from_params(&mut grads, &splats, &[splats.sh_coeffs.id]);
@@ -376,8 +1098,14 @@ impl SplatTrainer {
             splats
         });
 
+        if self.config.densification == DensificationStrategy::Mcmc {
+            splats = trace_span!("MCMC noise", sync_burn = true)
+                .in_scope(|| self.mcmc_noise(splats, lr_mean));
+        }
+
         let num_visible = aux.num_visible.clone();
         let num_intersections = aux.num_intersections.clone();
+        let intersection_capacity = aux.intersection_capacity();
 
         trace_span!("Housekeeping", sync_burn = true).in_scope(|| {
             // TODO: Burn really should implement +=
@@ -389,7 +1117,8 @@ impl SplatTrainer {
                     .expect("XY gradients need to be calculated.");
 
                 let aux = aux.clone();
-                self.refine_record.gather_stats(xys_grad, aux);
+                let opacities = splats.opacity().inner();
+                self.refine_record.gather_stats(xys_grad, opacities, aux);
             }
         });
 
@@ -399,6 +1128,7 @@ impl SplatTrainer {
             gt_views: batch.gt_view,
             num_visible,
             num_intersections,
+            intersection_capacity,
             loss,
             lr_mean,
             lr_rotation,
@@ -410,6 +1140,85 @@ impl SplatTrainer {
         (splats, stats)
     }
 
+    /// The highest SH band trained at step `iter`, see `sh_degree_interval` in the config.
+    pub fn active_sh_degree(&self, iter: u32, splats: &Splats<B>) -> u32 {
+        let interval = self.config.sh_degree_interval;
+        if interval == 0 {
+            splats.sh_degree()
+        } else {
+            (iter / interval).min(splats.sh_degree())
+        }
+    }
+
+    // Step only the splats visible in the view of `aux`, see [`SparseAdam`].
+    fn sparse_step(
+        &mut self,
+        splats: Splats<B>,
+        grads: &mut <B as AutodiffBackend>::Gradients,
+        aux: &RenderAux<B>,
+        [lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac]: [f64; 5],
+    ) -> Splats<B> {
+        let mut splats = splats;
+        let mut record = self.optim.to_record();
+        let optim = &self.sparse_optim;
+
+        let coeff_count = sh_coeffs_for_degree(splats.sh_degree()) as usize;
+        let coeff_scales: Vec<f32> = (0..coeff_count)
+            .flat_map(|i| {
+                let scale = if i == 0 {
+                    1.0
+                } else {
+                    1.0 / self.config.lr_coeffs_sh_scale
+                };
+                [scale; 3]
+            })
+            .collect();
+
+        optim.step(
+            lr_coeffs,
+            &mut splats.sh_coeffs,
+            &coeff_scales,
+            grads,
+            aux,
+            &mut record,
+        );
+        optim.step(
+            lr_rotation,
+            &mut splats.rotation,
+            &[1.0; 4],
+            grads,
+            aux,
+            &mut record,
+        );
+        optim.step(
+            lr_scale,
+            &mut splats.log_scales,
+            &[1.0; 3],
+            grads,
+            aux,
+            &mut record,
+        );
+        optim.step(
+            lr_mean,
+            &mut splats.means,
+            &[1.0; 3],
+            grads,
+            aux,
+            &mut record,
+        );
+        optim.step(
+            lr_opac,
+            &mut splats.raw_opacity,
+            &[1.0],
+            grads,
+            aux,
+            &mut record,
+        );
+
+        self.optim = self.optim.clone().load_record(record);
+        splats
+    }
+
     pub async fn refine_if_needed(
         &mut self,
         iter: u32,
@@ -420,31 +1229,262 @@ impl SplatTrainer {
             && iter >= self.config.refine_start_iter
             && iter % self.config.refine_every == 0;
 
-        if do_refine {
-            // If not refining, update splat to step with gradients applied.
-            let (refined_splats, refine) = self.refine_splats(iter, splats, scene_extent).await;
-            (refined_splats, Some(refine))
-        } else {
-            (splats, None)
+        if !do_refine {
+            return (splats, None);
         }
+        let (refined_splats, refine) = self.refine(iter, splats, scene_extent).await;
+        (refined_splats, Some(refine))
     }
 
-    async fn refine_splats(
+    /// Refine the splats right away, whether or not it's time to, eg. when asked to from the UI.
+    pub async fn refine(
         &mut self,
         iter: u32,
         splats: Splats<B>,
         scene_extent: f32,
     ) -> (Splats<B>, RefineStats) {
+        match self.config.densification {
+            DensificationStrategy::Classic => self.refine_splats(iter, splats, scene_extent).await,
+            DensificationStrategy::Mcmc => self.refine_splats_mcmc(iter, splats).await,
+        }
+    }
+
+    /// Prune the splats that are more transparent than the cull opacity, without densifying.
+    pub async fn prune_transparent(&mut self, splats: Splats<B>) -> (Splats<B>, RefineStats) {
         let mut record = self.optim.to_record();
+        let mut splats = with_master_params(splats.with_full_precision(), &record);
+        let device = splats.means.device();
 
+        let start_count = splats.num_splats();
+        let alpha_mask = splats.opacity().lower_elem(self.config.cull_opacity);
+        prune_points(&mut splats, &mut record, alpha_mask).await;
+
+        self.refine_record = RefineRecord::new(splats.num_splats(), &device);
+        self.optim = self.optim.clone().load_record(record);
+
+        let stats = RefineStats {
+            num_transparent_pruned: start_count - splats.num_splats(),
+            ..Default::default()
+        };
+        (self.with_storage_precision(splats), stats)
+    }
+
+    // Move the positions of transparent splats around a bit, along their shape, so they explore
+    // the scene. Opaque splats barely move.
+    fn mcmc_noise(&mut self, splats: Splats<B>, lr_mean: f64) -> Splats<B> {
         let mut splats = splats;
+        let n = splats.num_splats();
+        let device = splats.means.device();
+
+        let opacity = splats.opacity().inner();
+        let gate = sigmoid((opacity.neg() + 1.0 - 0.995) * 100.0).reshape([n, 1]);
+        let scales = splats.scales().inner().cast(FloatDType::F32);
+        let noise = quaternion_vec_multiply(
+            splats.rotations_normed().inner(),
+            Tensor::random([n, 3], Distribution::Normal(0.0, 1.0), &device)
+                * scales.powf_scalar(2.0),
+        ) * gate
+            * (self.config.mcmc_noise_lr as f64 * lr_mean) as f32;
+
+        // Half precision means are moved on their f32 copy, see [`AdamState::master`].
+        let mut record = self.optim.to_record();
+        let mut state: Option<AdamState<_, 2>> = record
+            .get(&splats.means.id)
+            .map(|entry| entry.clone().into_state());
+        let master = state.as_mut().and_then(|state| state.master.take());
+
+        Splats::map_param(&mut splats.means, |means| {
+            let half_precision = means.dtype() == DType::F16;
+            let means = master.map_or_else(|| means.cast(FloatDType::F32), Tensor::from_inner);
+            let moved = means + Tensor::from_inner(noise);
+            if half_precision {
+                if let Some(state) = state.as_mut() {
+                    state.master = Some(moved.clone().inner());
+                }
+                moved.cast(FloatDType::F16)
+            } else {
+                moved
+            }
+        });
+        if let Some(state) = state {
+            record.insert(splats.means.id, AdaptorRecord::from_state(state));
+            self.optim = self.optim.clone().load_record(record);
+        }
+        splats
+    }
+
+    // Relocate transparent splats and add new splats, see [`crate::mcmc`].
+    async fn refine_splats_mcmc(
+        &mut self,
+        iter: u32,
+        splats: Splats<B>,
+    ) -> (Splats<B>, RefineStats) {
+        let mut record = self.optim.to_record();
+        let mut splats = with_master_params(splats.with_full_precision(), &record);
+        let device = splats.means.device();
+        let n = splats.num_splats();
+
+        let opacity: Vec<f32> = splats
+            .opacity()
+            .into_data_async()
+            .await
+            .to_vec()
+            .expect("Wrong type");
+        let dead: Vec<bool> = opacity
+            .iter()
+            .map(|&o| o <= self.config.cull_opacity)
+            .collect();
+        let num_dead = dead.iter().filter(|&&d| d).count();
+
+        let target = ((n as f32 * (1.0 + self.config.mcmc_growth)) as usize)
+            .min(self.config.max_splats as usize)
+            .max(n);
+        let num_added = target - n;
+
+        // Only sample splats that stay.
+        let alive: Vec<f32> = opacity
+            .iter()
+            .zip(&dead)
+            .map(|(&o, &d)| if d { 0.0 } else { o })
+            .collect();
+        let mut rng = StdRng::seed_from_u64(iter as u64);
+        let Some(samples) = sample_by_opacity(&alive, num_dead + num_added, &mut rng) else {
+            return (self.with_storage_precision(splats), RefineStats::default());
+        };
+
+        // Sampled splats share their opacity and scale with their copies.
+        let mut new_opacity = opacity.clone();
+        let mut log_scale_offset = vec![0.0; n];
+        let mut keep_state = vec![1.0; n];
+        let mut copy_inds = vec![];
+        for (i, &count) in samples.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let (op, scale) = relocated(opacity[i], count + 1);
+            new_opacity[i] = op;
+            log_scale_offset[i] = scale.ln();
+            keep_state[i] = 0.0;
+            copy_inds.extend(std::iter::repeat_n(i as i32, count as usize));
+        }
+
+        let new_raw_opacity: Vec<f32> = new_opacity
+            .iter()
+            .map(|&o| inverse_sigmoid(o.clamp(1e-6, 1.0 - 1e-6)))
+            .collect();
+        let new_raw_opacity = Tensor::<B, 1>::from_floats(new_raw_opacity.as_slice(), &device);
+        let log_scale_offset =
+            Tensor::<B, 1>::from_floats(log_scale_offset.as_slice(), &device).reshape([n, 1]);
+        let keep_state = Tensor::<B, 1>::from_floats(keep_state.as_slice(), &device).inner();
+
+        // Restart the optimizer for the sampled splats.
+        let keep_1 = keep_state.clone();
+        map_param(
+            &mut splats.raw_opacity,
+            &mut record,
+            |_| new_raw_opacity,
+            |s| s * keep_1.clone(),
+        );
+        let keep_2 = keep_state.reshape([n, 1]);
+        map_param(
+            &mut splats.log_scales,
+            &mut record,
+            |s| s + log_scale_offset,
+            |s| s * keep_2.clone(),
+        );
+        map_param(
+            &mut splats.means,
+            &mut record,
+            |m| m,
+            |s| s * keep_2.clone(),
+        );
+        map_param(
+            &mut splats.rotation,
+            &mut record,
+            |r| r,
+            |s| s * keep_2.clone(),
+        );
+        let keep_3 = keep_2.clone().unsqueeze_dim(2);
+        map_param(
+            &mut splats.sh_coeffs,
+            &mut record,
+            |c| c,
+            |s| s * keep_3.clone(),
+        );
+
+        // Copies start at the same place as the splats they're copied from.
+        let copy_count = copy_inds.len();
+        let copy_inds = Tensor::<B, 1, Int>::from_ints(copy_inds.as_slice(), &device);
+        let append_means = splats.means.val().select(0, copy_inds.clone());
+        let append_rots = splats.rotation.val().select(0, copy_inds.clone());
+        let append_coeffs = splats.sh_coeffs.val().select(0, copy_inds.clone());
+        let append_opac = splats.raw_opacity.val().select(0, copy_inds.clone());
+        let append_scales = splats.log_scales.val().select(0, copy_inds.clone());
+        let inherited = Inherited::gather(&splats, copy_inds);
+
+        let dead = Tensor::<B, 1>::from_floats(
+            dead.iter()
+                .map(|&d| if d { 1.0 } else { 0.0 })
+                .collect::<Vec<f32>>()
+                .as_slice(),
+            &device,
+        )
+        .greater_elem(0.5);
+        prune_points(&mut splats, &mut record, dead).await;
+
+        if copy_count > 0 {
+            concat_splats(
+                &mut splats,
+                &mut record,
+                append_means,
+                append_rots,
+                append_coeffs,
+                append_opac,
+                append_scales,
+                inherited,
+            );
+        }
+
+        self.refine_record = RefineRecord::new(splats.num_splats(), &device);
+        self.optim = self.optim.clone().load_record(record);
+
+        let stats = RefineStats {
+            num_split: 0,
+            num_cloned: num_added,
+            num_transparent_pruned: num_dead,
+            num_scale_pruned: 0,
+            num_budget_pruned: 0,
+        };
+        (self.with_storage_precision(splats), stats)
+    }
+
+    async fn refine_splats(
+        &mut self,
+        iter: u32,
+        splats: Splats<B>,
+        scene_extent: f32,
+    ) -> (Splats<B>, RefineStats) {
+        let mut record = self.optim.to_record();
+
+        // Splitting and cloning is done at full precision, the moments are f32 already.
+        let mut splats = with_master_params(splats.with_full_precision(), &record);
 
         let device = splats.means.device();
 
         // Otherwise, do refinement, but do the split/clone on gaussians with no grads applied.
         let avg_grad = self.refine_record.average_grad_2d();
 
-        let is_grad_high = avg_grad.greater_equal_elem(self.config.densify_grad_thresh);
+        let grad_thresh = match self.config.densify_signal {
+            DensifySignal::Grad => self.config.densify_grad_thresh,
+            DensifySignal::AbsGrad => {
+                let resolution = self
+                    .refine_record
+                    .mean_resolution()
+                    .unwrap_or(ABSGRAD_REFERENCE_RESOLUTION);
+                self.config.densify_absgrad_thresh * ABSGRAD_REFERENCE_RESOLUTION / resolution
+            }
+        };
+        let is_grad_high = avg_grad.greater_equal_elem(grad_thresh);
         let split_clone_size_mask = splats
             .scales()
             .max_dim(1)
@@ -456,6 +1496,8 @@ impl SplatTrainer {
         let mut append_coeffs = vec![];
         let mut append_opac = vec![];
         let mut append_scales = vec![];
+        // The splats each appended splat is densified from.
+        let mut parents = vec![];
 
         let clone_mask =
             Tensor::stack::<2>(vec![is_grad_high.clone(), split_clone_size_mask.clone()], 1)
@@ -468,6 +1510,7 @@ impl SplatTrainer {
         let clone_count = clone_inds.dims()[0];
         if clone_count > 0 {
             let clone_inds = clone_inds.squeeze(1);
+            parents.push(clone_inds.clone());
             let cur_means = splats.means.val().select(0, clone_inds.clone());
             let cur_rots = splats.rotations_normed().select(0, clone_inds.clone());
             let cur_scale = splats.log_scales.val().select(0, clone_inds.clone());
@@ -509,6 +1552,9 @@ impl SplatTrainer {
         let split_count = split_inds.dims()[0];
         if split_count > 0 {
             let split_inds = split_inds.squeeze(1);
+            // Both halves of a split come from the same splat.
+            parents.push(split_inds.clone());
+            parents.push(split_inds.clone());
 
             // Some parts can be straightforwardly copied to the new splats.
             let cur_means = splats.means.val().select(0, split_inds.clone());
@@ -538,7 +1584,27 @@ impl SplatTrainer {
             append_opac.push(cur_raw_opac);
         }
 
-        prune_points(&mut splats, &mut record, split_mask.clone()).await;
+        // Stay under the splat budget, by pruning the least important splats that aren't
+        // densified. Split splats are replaced, so only count the extra one.
+        let num_over = self.config.splat_budget.map_or(0, |budget| {
+            (splats.num_splats() + clone_count + split_count).saturating_sub(budget as usize)
+        });
+        let mut budget_pruned = 0;
+        let mut prune_mask = split_mask.clone();
+        if num_over > 0 {
+            let densified = Tensor::stack::<2>(vec![clone_mask, split_mask], 1)
+                .any_dim(1)
+                .squeeze::<1>(1);
+            let (budget_mask, count) =
+                least_important(self.refine_record.importance(), densified, num_over).await;
+            prune_mask = Tensor::stack::<2>(vec![prune_mask, budget_mask], 1)
+                .any_dim(1)
+                .squeeze::<1>(1);
+            budget_pruned = count;
+        }
+        let inherited =
+            (!parents.is_empty()).then(|| Inherited::gather(&splats, Tensor::cat(parents, 0)));
+        prune_points(&mut splats, &mut record, prune_mask).await;
 
         // Do some more processing. Important to do this last as otherwise you might mess up the correspondence
         // of gradient <-> splat.
@@ -573,7 +1639,7 @@ impl SplatTrainer {
         prune_points(&mut splats, &mut record, scale_mask).await;
         let scale_pruned = start_count - splats.num_splats();
 
-        if !append_means.is_empty() {
+        if let Some(inherited) = inherited {
             let append_means = Tensor::cat(append_means, 0);
             let append_rots = Tensor::cat(append_rots, 0);
             let append_coeffs = Tensor::cat(append_coeffs, 0);
@@ -588,6 +1654,7 @@ impl SplatTrainer {
                 append_coeffs,
                 append_opac,
                 append_scales,
+                inherited,
             );
         }
 
@@ -610,12 +1677,62 @@ impl SplatTrainer {
             num_cloned: clone_count,
             num_transparent_pruned: alpha_pruned,
             num_scale_pruned: scale_pruned,
+            num_budget_pruned: budget_pruned,
         };
 
-        (splats, stats)
+        (self.with_storage_precision(splats), stats)
     }
 }
 
+// Zero the gradients of the SH bands above `sh_degree`, so those bands stay at zero until
+// they're unlocked.
+fn lock_sh_bands(
+    splats: &Splats<B>,
+    grads: &mut <B as AutodiffBackend>::Gradients,
+    sh_degree: u32,
+) {
+    let coeffs = splats.sh_coeffs.val();
+    let Some(grad) = coeffs.grad_remove(grads) else {
+        return;
+    };
+    let [n, n_coeffs, _] = grad.dims();
+    let active = sh_coeffs_for_degree(sh_degree) as usize;
+    let locked = grad.clone().slice([0..n, active..n_coeffs]).zeros_like();
+    coeffs.grad_replace(grads, grad.slice_assign([0..n, active..n_coeffs], locked));
+}
+
+// Set the parameters stored at half precision to their f32 copy in the optimizer, see
+// [`AdamState::master`]. The splats need to be at full precision already.
+fn with_master_params<B: AutodiffBackend>(
+    mut splats: Splats<B>,
+    record: &HashMap<ParamId, AdaptorRecord<AdamScaled, B>>,
+) -> Splats<B> {
+    fn restore<B: AutodiffBackend, const D: usize>(
+        param: &mut Param<Tensor<B, D>>,
+        record: &HashMap<ParamId, AdaptorRecord<AdamScaled, B>>,
+    ) {
+        let Some(entry) = record.get(&param.id) else {
+            return;
+        };
+        let state: AdamState<_, D> = entry.clone().into_state();
+        if let Some(master) = state.master {
+            Splats::map_param(param, |_| Tensor::from_inner(master));
+        }
+    }
+    restore(&mut splats.means, record);
+    restore(&mut splats.log_scales, record);
+    restore(&mut splats.sh_coeffs, record);
+    splats
+}
+
+// Append `added` rows of zeros to a per view tensor.
+fn pad_views<B: Backend, const D: usize>(tensor: Tensor<B, D>, added: usize) -> Tensor<B, D> {
+    let mut dims = tensor.dims();
+    dims[0] = added;
+    let zeros = Tensor::zeros(dims, &tensor.device());
+    Tensor::cat(vec![tensor, zeros], 0)
+}
+
 fn map_param<B: AutodiffBackend, const D: usize>(
     param: &mut Param<Tensor<B, D>>,
     record: &mut HashMap<ParamId, AdaptorRecord<AdamScaled, B>>,
@@ -623,10 +1740,75 @@ fn map_param<B: AutodiffBackend, const D: usize>(
     map_opt: impl Fn(Tensor<B::InnerBackend, D>) -> Tensor<B::InnerBackend, D>,
 ) {
     Splats::map_param(param, map_param);
-    let mut state: AdamState<_, D> = record[&param.id].clone().into_state();
-    state.momentum.moment_1 = map_opt(state.momentum.moment_1);
-    state.momentum.moment_2 = map_opt(state.momentum.moment_2);
-    record.insert(param.id, AdaptorRecord::from_state(state));
+    // Parameters that haven't been stepped yet have no optimizer state to map.
+    if let Some(record_entry) = record.get(&param.id) {
+        let mut state: AdamState<_, D> = record_entry.clone().into_state();
+        state.momentum.moment_1 = map_opt(state.momentum.moment_1);
+        state.momentum.moment_2 = map_opt(state.momentum.moment_2);
+        // Refining is done at full precision, so the mapped parameter is the new f32 copy.
+        if state.master.is_some() {
+            state.master = Some(param.val().inner().cast(FloatDType::F32));
+        }
+        record.insert(param.id, AdaptorRecord::from_state(state));
+    }
+}
+
+/// Attributes that densified splats copy from the splats they're densified from, rather than
+/// being computed by the refine step.
+pub struct Inherited<B: Backend> {
+    pub labels: Option<Tensor<B, 1, Int>>,
+    pub temporal: Option<TemporalAttributes<B>>,
+}
+
+impl<B: Backend> Inherited<B> {
+    /// Gather the attributes of the splats at `parents`. Must be called before any pruning, as
+    /// pruning changes the splat indices.
+    pub fn gather(splats: &Splats<B>, parents: Tensor<B, 1, Int>) -> Self {
+        Self {
+            labels: splats.labels.clone().map(|l| l.select(0, parents.clone())),
+            temporal: splats.temporal.as_ref().map(|t| {
+                TemporalAttributes::new(
+                    t.velocities.val().select(0, parents.clone()),
+                    t.times.val().select(0, parents.clone()),
+                    t.log_durations.val().select(0, parents),
+                )
+            }),
+        }
+    }
+}
+
+// Mask of the `count` splats with the lowest importance, skipping the splats in `keep`. Returns
+// the mask and how many splats are in it, which is less than `count` if there aren't enough.
+async fn least_important<B: AutodiffBackend>(
+    importance: Tensor<B, 1>,
+    keep: Tensor<B, 1, Bool>,
+    count: usize,
+) -> (Tensor<B, 1, Bool>, usize) {
+    let device = importance.device();
+    let importance: Vec<f32> = importance
+        .into_data_async()
+        .await
+        .to_vec()
+        .expect("Wrong type");
+    let keep: Vec<i32> = keep
+        .int()
+        .into_data_async()
+        .await
+        .to_vec()
+        .expect("Wrong type");
+
+    let mut candidates: Vec<usize> = (0..importance.len()).filter(|&i| keep[i] == 0).collect();
+    let count = count.min(candidates.len());
+    let mut prune = vec![0.0; importance.len()];
+    if count > 0 {
+        candidates
+            .select_nth_unstable_by(count - 1, |&a, &b| importance[a].total_cmp(&importance[b]));
+        for &i in &candidates[..count] {
+            prune[i] = 1.0;
+        }
+    }
+    let prune = Tensor::<B, 1>::from_floats(prune.as_slice(), &device).greater_elem(0.5);
+    (prune, count)
 }
 
 // Prunes points based on the given mask.
@@ -693,6 +1875,30 @@ pub async fn prune_points<B: AutodiffBackend>(
             |x| x.select(0, valid_inds.clone()),
             |x| x.select(0, valid_inds.clone().inner()),
         );
+        splats.labels = splats
+            .labels
+            .take()
+            .map(|l| l.select(0, valid_inds.clone()));
+        if let Some(temporal) = &mut splats.temporal {
+            map_param(
+                &mut temporal.velocities,
+                record,
+                |x| x.select(0, valid_inds.clone()),
+                |x| x.select(0, valid_inds.clone().inner()),
+            );
+            map_param(
+                &mut temporal.times,
+                record,
+                |x| x.select(0, valid_inds.clone()),
+                |x| x.select(0, valid_inds.clone().inner()),
+            );
+            map_param(
+                &mut temporal.log_durations,
+                record,
+                |x| x.select(0, valid_inds.clone()),
+                |x| x.select(0, valid_inds.clone().inner()),
+            );
+        }
     }
 }
 
@@ -704,6 +1910,7 @@ pub fn concat_splats<B: AutodiffBackend>(
     sh_coeffs: Tensor<B, 3>,
     raw_opac: Tensor<B, 1>,
     log_scales: Tensor<B, 2>,
+    inherited: Inherited<B>,
 ) {
     // Concat
     let means_shape = means.shape();
@@ -744,17 +1951,55 @@ pub fn concat_splats<B: AutodiffBackend>(
         move |x| Tensor::cat(vec![x, log_scales], 0),
         |x| Tensor::cat(vec![x, Tensor::zeros(log_scales_shape.clone(), &device)], 0),
     );
+
+    splats.labels = match (splats.labels.take(), inherited.labels) {
+        (Some(labels), Some(append)) => Some(Tensor::cat(vec![labels, append], 0)),
+        (labels, _) => labels,
+    };
+
+    if let (Some(temporal), Some(append)) = (&mut splats.temporal, inherited.temporal) {
+        let velocities = append.velocities.val();
+        let velocities_shape = velocities.shape();
+        map_param(
+            &mut temporal.velocities,
+            record,
+            move |x| Tensor::cat(vec![x, velocities], 0),
+            |x| Tensor::cat(vec![x, Tensor::zeros(velocities_shape.clone(), &device)], 0),
+        );
+        let times = append.times.val();
+        let times_shape = times.shape();
+        map_param(
+            &mut temporal.times,
+            record,
+            move |x| Tensor::cat(vec![x, times], 0),
+            |x| Tensor::cat(vec![x, Tensor::zeros(times_shape.clone(), &device)], 0),
+        );
+        let log_durations = append.log_durations.val();
+        let log_durations_shape = log_durations.shape();
+        map_param(
+            &mut temporal.log_durations,
+            record,
+            move |x| Tensor::cat(vec![x, log_durations], 0),
+            |x| {
+                Tensor::cat(
+                    vec![x, Tensor::zeros(log_durations_shape.clone(), &device)],
+                    0,
+                )
+            },
+        );
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use brush_render::gaussian_splats::Splats;
     use burn::{
-        backend::{wgpu::WgpuDevice, Wgpu},
+        backend::{wgpu::WgpuDevice, Autodiff, Wgpu},
         tensor::Tensor,
     };
     use glam::Quat;
 
-    use super::quaternion_vec_multiply;
+    use super::{least_important, quaternion_vec_multiply, SplatTrainer, TrainConfig};
 
     #[test]
     fn test_quat_multiply() {
@@ -771,4 +2016,63 @@ mod tests {
         let result = glam::vec3(result[0], result[1], result[2]);
         assert!((result_ref - result).length() < 1e-7);
     }
+
+    #[tokio::test]
+    async fn prunes_least_important_unkept_splats() {
+        type DiffBack = Autodiff<Wgpu>;
+        let device = WgpuDevice::DefaultDevice;
+        let importance =
+            Tensor::<DiffBack, 1>::from_floats([0.5, 0.1, 0.9, 0.2, 0.05, 0.7], &device);
+        // The least important splat is kept, eg. because it was just densified.
+        let keep = Tensor::<DiffBack, 1>::from_floats([0.0, 0.0, 0.0, 0.0, 1.0, 0.0], &device)
+            .greater_elem(0.5);
+
+        let (mask, count) = least_important(importance.clone(), keep.clone(), 2).await;
+        let mask: Vec<bool> = mask.into_data().to_vec().expect("Wrong type");
+        assert_eq!(count, 2);
+        assert_eq!(mask, [false, true, false, true, false, false]);
+
+        // Asking for more than there are candidates prunes every splat that isn't kept.
+        let (mask, count) = least_important(importance, keep, 10).await;
+        let mask: Vec<bool> = mask.into_data().to_vec().expect("Wrong type");
+        assert_eq!(count, 5);
+        assert_eq!(mask, [true, true, true, true, false, true]);
+    }
+
+    #[test]
+    fn grown_views_keep_their_corrections() {
+        let device = WgpuDevice::DefaultDevice;
+        let splats = Splats::<Autodiff<Wgpu>>::from_raw(
+            &[glam::Vec3::ZERO],
+            None,
+            None,
+            None,
+            None,
+            &device,
+        );
+        let config = TrainConfig::new()
+            .with_appearance_model(true)
+            .with_pose_refinement(true);
+        let mut trainer = SplatTrainer::new(&splats, &config, &device)
+            .with_appearance(2, &device)
+            .with_pose_refinement(2, &device);
+
+        let pose = trainer.pose.as_mut().expect("Pose refinement is enabled");
+        Splats::map_param(&mut pose.translations, |t| t.ones_like());
+
+        trainer.grow_views(3);
+        let appearance = trainer.appearance.as_ref().expect("Appearance is enabled");
+        assert_eq!(appearance.num_views(), 3);
+        let pose = trainer
+            .pose_refinement()
+            .expect("Pose refinement is enabled");
+        assert_eq!(pose.num_views(), 3);
+        let translations: Vec<f32> = pose
+            .translations
+            .val()
+            .into_data()
+            .to_vec()
+            .expect("Wrong type");
+        assert_eq!(translations, [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+    }
 }
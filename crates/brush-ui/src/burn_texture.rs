@@ -5,7 +5,7 @@ use burn::{
         wgpu::{JitBackend, WgpuRuntime},
         Wgpu,
     },
-    tensor::{Tensor, TensorPrimitive},
+    tensor::{ops::FloatTensor, Int, Tensor, TensorPrimitive},
 };
 use burn_fusion::client::FusionClient;
 use eframe::egui_wgpu::Renderer;
@@ -15,6 +15,15 @@ use wgpu::{CommandEncoderDescriptor, TexelCopyBufferLayout, TextureViewDescripto
 
 type InnerWgpu = JitBackend<WgpuRuntime, f32, i32, u32>;
 
+/// Number of pixels the rows of a packed u32 image need to be aligned to, to be able to
+/// copy it to a texture directly.
+pub const ROW_ALIGN: u32 = 64;
+
+/// Width to render an image at, so it can be copied to a texture without any intermediate copies.
+pub fn aligned_width(width: u32) -> u32 {
+    width.next_multiple_of(ROW_ALIGN)
+}
+
 struct TextureState {
     texture: wgpu::Texture,
     id: TextureId,
@@ -59,14 +68,56 @@ impl BurnTexture {
     }
 
     pub fn update_texture(&mut self, img: Tensor<Wgpu, 3>) -> TextureId {
+        let [h, w, _] = img.shape().dims();
+        self.update_texture_cropped(img, glam::uvec2(w as u32, h as u32))
+    }
+
+    /// Update the texture with the top left `size` pixels of a packed u32 image.
+    ///
+    /// If the width of the image is a multiple of [`ROW_ALIGN`], the render buffer is copied
+    /// straight into the texture without any intermediate copies. Use [`aligned_width`] to render
+    /// an image that's wide enough.
+    pub fn update_texture_cropped(&mut self, img: Tensor<Wgpu, 3>, size: glam::UVec2) -> TextureId {
+        let img_prim = img.into_primitive().tensor();
+        let fusion_client = img_prim.client.clone();
+        let img = fusion_client.resolve_tensor_float::<InnerWgpu>(img_prim);
+        self.copy_to_texture(img, size)
+    }
+
+    /// Update the texture with the top left `size` pixels of a float RGBA image, eg. a render
+    /// that has been post-processed.
+    pub fn update_texture_rgba_cropped(
+        &mut self,
+        img: Tensor<Wgpu, 3>,
+        size: glam::UVec2,
+    ) -> TextureId {
+        let device = img.device();
+        // Pack the channels into a u32 like the rasterizer does. Integer math wraps around, so
+        // alpha ends up in the top byte even though the ints are signed.
+        let bytes = img.clamp(0.0, 1.0).mul_scalar(255.0).int();
+        let shifts = Tensor::<Wgpu, 1, Int>::from_ints([1, 1 << 8, 1 << 16, 1 << 24], &device);
+        let packed = (bytes * shifts.reshape([1, 1, 4])).sum_dim(2);
+
+        let img_prim = packed.into_primitive();
+        let fusion_client = img_prim.client.clone();
+        let img = fusion_client.resolve_tensor_int::<InnerWgpu>(img_prim);
+        self.copy_to_texture(img, size)
+    }
+
+    // Copy a packed u32 image to the texture, recreating the texture if the size changed.
+    fn copy_to_texture(&mut self, img: FloatTensor<InnerWgpu>, size: glam::UVec2) -> TextureId {
         let mut encoder = self
             .device
             .create_command_encoder(&CommandEncoderDescriptor {
                 label: Some("viewer encoder"),
             });
 
-        let [h, w, _] = img.shape().dims();
-        let size = glam::uvec2(w as u32, h as u32);
+        let [h, w, _] = img.shape.dims();
+        assert!(
+            size.x as usize <= w && size.y as usize <= h,
+            "Texture size must fit in the image"
+        );
+        let (w, h) = (size.x as usize, size.y as usize);
 
         let dirty = if let Some(s) = self.state.as_ref() {
             s.texture.width() != size.x || s.texture.height() != size.y
@@ -100,17 +151,14 @@ impl BurnTexture {
             unreachable!("Somehow failed to initialize")
         };
 
-        let img_prim = img.into_primitive().tensor();
-        let fusion_client = img_prim.client.clone();
-        let img = fusion_client.resolve_tensor_float::<InnerWgpu>(img_prim);
         let texture: &wgpu::Texture = &s.texture;
         let [height, width, c] = img.shape.dims();
 
-        let padded_shape = vec![height, width.div_ceil(64) * 64, c];
+        let padded_shape = vec![height, aligned_width(width as u32) as usize, c];
 
         // Create padded tensor if needed. The bytes_per_row needs to be divisible
         // by 256 in WebGPU, so 4 bytes per pixel means width needs to be divisible by 64.
-        let img = if width % 64 != 0 {
+        let img = if width % ROW_ALIGN as usize != 0 {
             let padded: Tensor<InnerWgpu, 3> = Tensor::zeros(&padded_shape, &img.device);
             let img = Tensor::from_primitive(TensorPrimitive::Float(img));
             let padded = padded.slice_assign([0..height, 0..width], img);
@@ -146,8 +194,8 @@ impl BurnTexture {
                 aspect: wgpu::TextureAspect::All,
             },
             wgpu::Extent3d {
-                width: width as u32,
-                height: height as u32,
+                width: w as u32,
+                height: h as u32,
                 depth_or_array_layers: 1,
             },
         );
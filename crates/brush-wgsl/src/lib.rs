@@ -4,7 +4,7 @@ mod codewriter;
 use anyhow::Result;
 use naga::{proc::GlobalCtx, valid::Capabilities, Handle, Type};
 use naga_oil::compose::{
-    ComposableModuleDescriptor, Composer, ComposerError, NagaModuleDescriptor,
+    ComposableModuleDescriptor, Composer, ComposerError, NagaModuleDescriptor, ShaderDefValue,
 };
 use regex::Regex;
 use std::{borrow::Cow, collections::HashMap, io, sync::OnceLock};
@@ -108,6 +108,33 @@ fn alignment_of(ty: Handle<Type>, ctx: &GlobalCtx) -> usize {
     }
 }
 
+fn shader_def_if_regex() -> &'static Regex {
+    static MEM: OnceLock<Regex> = OnceLock::new();
+    MEM.get_or_init(|| Regex::new(r"^\s*#\s*(?:else\s+)?if\s+(\w+)\s*[=!<>]+\s*([-\w]+)").unwrap())
+}
+
+// Kernels are specialized on shader defs when they're compiled, see `kernel_source_gen`, but an
+// `#if` comparison fails on a def that isn't set. The generated constants and types don't depend
+// on the specialization, so every compared def is set to the value it's compared with.
+fn compared_shader_defs(source: &str) -> HashMap<String, ShaderDefValue> {
+    let mut defs = HashMap::new();
+    for cap in source
+        .lines()
+        .filter_map(|l| shader_def_if_regex().captures(l))
+    {
+        let value = &cap[2];
+        let value = if let Ok(v) = value.parse::<u32>() {
+            ShaderDefValue::UInt(v)
+        } else if let Ok(v) = value.parse::<i32>() {
+            ShaderDefValue::Int(v)
+        } else {
+            ShaderDefValue::Bool(value.parse::<bool>().unwrap_or(true))
+        };
+        defs.entry(cap[1].to_owned()).or_insert(value);
+    }
+    defs
+}
+
 #[derive(Debug, Error)]
 pub enum GenError {
     #[error("Failed to generate shader module.\n{1}")]
@@ -161,7 +188,7 @@ pub fn build_modules(
 
         code.add_lines(&[
             "composer.add_composable_module(naga_oil::compose::ComposableModuleDescriptor {",
-            &format!("source: include_str!(\"./{rel_path}\"),"),
+            &format!("source: &brush_kernel::hot_reload::read_source(env!(\"CARGO_MANIFEST_DIR\"), \"{include}\", include_str!(\"./{rel_path}\")),"),
             &format!("file_path: \"{rel_path}\","),
             &format!("as_name: Some(\"{include_name}\".to_owned()),"),
             "..Default::default()",
@@ -186,6 +213,7 @@ pub fn build_modules(
         let module = match composer.make_naga_module(NagaModuleDescriptor {
             source,
             file_path: path,
+            shader_defs: compared_shader_defs(source),
             ..Default::default()
         }) {
             Ok(m) => m,
@@ -356,7 +384,7 @@ pub fn build_modules(
                     "   shader_defs: std::collections::HashMap<String, naga_oil::compose::ShaderDefValue>",
                     ") -> wgpu::naga::Module {",
                     "super::create_composer().make_naga_module(naga_oil::compose::NagaModuleDescriptor {",
-                    &format!("source: include_str!(\"{rel_path}\"),"),
+                    &format!("source: &brush_kernel::hot_reload::read_source(env!(\"CARGO_MANIFEST_DIR\"), \"{path}\", include_str!(\"{rel_path}\")),"),
                     &format!("file_path: \"{path}\","),
                     "shader_defs,",
                     "..Default::default()",
@@ -59,6 +59,22 @@ impl CameraModel {
         }
     }
 
+    fn name(&self) -> &'static str {
+        match self {
+            Self::SimplePinhole => "SIMPLE_PINHOLE",
+            Self::Pinhole => "PINHOLE",
+            Self::SimpleRadial => "SIMPLE_RADIAL",
+            Self::Radial => "RADIAL",
+            Self::OpenCV => "OPENCV",
+            Self::OpenCvFishEye => "OPENCV_FISHEYE",
+            Self::FullOpenCV => "FULL_OPENCV",
+            Self::Fov => "FOV",
+            Self::SimpleRadialFisheye => "SIMPLE_RADIAL_FISHEYE",
+            Self::RadialFisheye => "RADIAL_FISHEYE",
+            Self::ThinPrismFisheye => "THIN_PRISM_FISHEYE",
+        }
+    }
+
     fn num_params(&self) -> usize {
         match self {
             Self::SimplePinhole => 3,
@@ -104,6 +120,18 @@ pub struct Point3D {
     pub point2d_idxs: Vec<i32>,
 }
 
+/// A frame of a camera rig: the images that were taken at the same time by the cameras of the
+/// rig. See the rig support of COLMAP 3.12.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub rig_id: u32,
+    /// Rig from world transform.
+    pub quat: glam::Quat,
+    pub tvec: glam::Vec3,
+    /// Ids of the images of the frame, taken by the cameras of the rig.
+    pub image_ids: Vec<i32>,
+}
+
 impl Camera {
     pub fn focal(&self) -> (f64, f64) {
         let x = self.params[0];
@@ -152,6 +180,19 @@ impl Camera {
         }] as f32;
         glam::vec2(x, y)
     }
+
+    /// The distortion parameters, that is all parameters after the focal and principal point.
+    pub fn distortion_params(&self) -> &[f64] {
+        let start = match self.model {
+            CameraModel::SimplePinhole
+            | CameraModel::SimpleRadial
+            | CameraModel::Radial
+            | CameraModel::SimpleRadialFisheye
+            | CameraModel::RadialFisheye => 3,
+            _ => 4,
+        };
+        &self.params[start.min(self.params.len())..]
+    }
 }
 
 fn parse<T: std::str::FromStr>(s: &str) -> io::Result<T> {
@@ -466,6 +507,113 @@ async fn read_points3d_binary<R: AsyncRead + Unpin>(
     Ok(points3d)
 }
 
+async fn read_frames_text<R: AsyncRead + Unpin>(reader: R) -> io::Result<HashMap<u32, Frame>> {
+    let mut frames = HashMap::new();
+    let mut buf_reader = tokio::io::BufReader::new(reader);
+    let mut line = String::new();
+
+    while buf_reader.read_line(&mut line).await? > 0 {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if line.starts_with('#') || parts.is_empty() {
+            line.clear();
+            continue;
+        }
+
+        if parts.len() < 10 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid frame data",
+            ));
+        }
+
+        let id: u32 = parse(parts[0])?;
+        let rig_id = parse(parts[1])?;
+        let [w, x, y, z] = [
+            parse(parts[2])?,
+            parse(parts[3])?,
+            parse(parts[4])?,
+            parse(parts[5])?,
+        ];
+        let tvec = glam::vec3(parse(parts[6])?, parse(parts[7])?, parse(parts[8])?);
+
+        // Data of a frame is (SENSOR_TYPE, SENSOR_ID, DATA_ID), the data of a camera is an image.
+        let mut image_ids = Vec::new();
+        for data in parts[10..].chunks(3) {
+            if data.len() < 3 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid frame data ids",
+                ));
+            }
+            if data[0] == "CAMERA" {
+                image_ids.push(parse(data[2])?);
+            }
+        }
+
+        frames.insert(
+            id,
+            Frame {
+                rig_id,
+                quat: glam::quat(x, y, z, w),
+                tvec,
+                image_ids,
+            },
+        );
+        line.clear();
+    }
+
+    Ok(frames)
+}
+
+async fn read_frames_binary<R: AsyncRead + Unpin>(
+    mut reader: R,
+) -> io::Result<HashMap<u32, Frame>> {
+    // Sensor type of cameras, as opposed to IMUs.
+    const CAMERA: i32 = 0;
+
+    let mut frames = HashMap::new();
+    let num_frames = reader.read_u64_le().await?;
+
+    for _ in 0..num_frames {
+        let frame_id = reader.read_u32_le().await?;
+        let rig_id = reader.read_u32_le().await?;
+        let [w, x, y, z] = [
+            reader.read_f64_le().await? as f32,
+            reader.read_f64_le().await? as f32,
+            reader.read_f64_le().await? as f32,
+            reader.read_f64_le().await? as f32,
+        ];
+        let tvec = glam::vec3(
+            reader.read_f64_le().await? as f32,
+            reader.read_f64_le().await? as f32,
+            reader.read_f64_le().await? as f32,
+        );
+
+        let num_data_ids = reader.read_u32_le().await?;
+        let mut image_ids = Vec::new();
+        for _ in 0..num_data_ids {
+            let sensor_type = reader.read_i32_le().await?;
+            let _sensor_id = reader.read_u32_le().await?;
+            let data_id = reader.read_u64_le().await?;
+            if sensor_type == CAMERA {
+                image_ids.push(data_id as i32);
+            }
+        }
+
+        frames.insert(
+            frame_id,
+            Frame {
+                rig_id,
+                quat: glam::quat(x, y, z, w),
+                tvec,
+                image_ids,
+            },
+        );
+    }
+
+    Ok(frames)
+}
+
 pub async fn read_cameras<R: AsyncRead + Unpin>(
     mut reader: R,
     binary: bool,
@@ -498,3 +646,64 @@ pub async fn read_points3d<R: AsyncRead + Unpin>(
         read_points3d_text(reader).await
     }
 }
+
+pub async fn read_frames<R: AsyncRead + Unpin>(
+    reader: R,
+    binary: bool,
+) -> io::Result<HashMap<u32, Frame>> {
+    if binary {
+        read_frames_binary(reader).await
+    } else {
+        read_frames_text(reader).await
+    }
+}
+
+/// Write cameras in the text format of cameras.txt.
+pub fn write_cameras_text(cameras: &HashMap<i32, Camera>) -> String {
+    let mut ids: Vec<_> = cameras.keys().copied().collect();
+    ids.sort_unstable();
+
+    let mut text = String::from("# Camera list with one line of data per camera:\n");
+    text.push_str("#   CAMERA_ID, MODEL, WIDTH, HEIGHT, PARAMS[]\n");
+    text.push_str(&format!("# Number of cameras: {}\n", ids.len()));
+    for id in ids {
+        let cam = &cameras[&id];
+        let params: Vec<String> = cam.params.iter().map(|p| p.to_string()).collect();
+        text.push_str(&format!(
+            "{id} {} {} {} {}\n",
+            cam.model.name(),
+            cam.width,
+            cam.height,
+            params.join(" ")
+        ));
+    }
+    text
+}
+
+/// Write images in the text format of images.txt.
+pub fn write_images_text(images: &HashMap<i32, Image>) -> String {
+    let mut ids: Vec<_> = images.keys().copied().collect();
+    ids.sort_unstable();
+
+    let mut text = String::from("# Image list with two lines of data per image:\n");
+    text.push_str("#   IMAGE_ID, QW, QX, QY, QZ, TX, TY, TZ, CAMERA_ID, NAME\n");
+    text.push_str("#   POINTS2D[] as (X, Y, POINT3D_ID)\n");
+    text.push_str(&format!("# Number of images: {}\n", ids.len()));
+    for id in ids {
+        let img = &images[&id];
+        let (q, t) = (img.quat, img.tvec);
+        text.push_str(&format!(
+            "{id} {} {} {} {} {} {} {} {} {}\n",
+            q.w, q.x, q.y, q.z, t.x, t.y, t.z, img.camera_id, img.name
+        ));
+        let points: Vec<String> = img
+            .xys
+            .iter()
+            .zip(&img.point3d_ids)
+            .map(|(xy, id)| format!("{} {} {id}", xy.x, xy.y))
+            .collect();
+        text.push_str(&points.join(" "));
+        text.push('\n');
+    }
+    text
+}
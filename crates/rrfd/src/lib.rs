@@ -14,6 +14,16 @@ pub enum FileHandle {
 }
 
 impl FileHandle {
+    /// The name of the file, if known.
+    pub fn name(&self) -> Option<String> {
+        match self {
+            #[cfg(not(target_os = "android"))]
+            Self::Rfd(file_handle) => Some(file_handle.file_name()),
+            #[cfg(target_os = "android")]
+            Self::Android(_) => None,
+        }
+    }
+
     pub async fn write(&self, data: &[u8]) -> std::io::Result<()> {
         match self {
             #[cfg(not(target_os = "android"))]
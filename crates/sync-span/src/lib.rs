@@ -1,15 +1,36 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
 
 use burn::prelude::Backend;
-use tracing::{info_span, Subscriber};
+use tracing::{info_span, span, Subscriber};
 use tracing_subscriber::{
     layer::{Context, Layer},
     registry::LookupSpan,
 };
+use web_time::Instant;
 
 // Global flag to enable/disable sync
 static SYNC_ENABLED: AtomicBool = AtomicBool::new(false);
 
+// Time spent in synced spans since the timings were last taken, summed per span name.
+static TIMINGS: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+// When a synced span was created.
+struct SpanStart(Instant);
+
+fn record_timing(name: &'static str, duration: Duration) {
+    let mut timings = TIMINGS.lock().expect("Timings lock poisoned");
+    match timings.iter_mut().find(|(n, _)| *n == name) {
+        Some((_, total)) => *total += duration,
+        None => timings.push((name, duration)),
+    }
+}
+
 // Tracing layer for sync events
 pub struct SyncLayer<B: Backend> {
     device: B::Device,
@@ -25,14 +46,31 @@ impl<B: Backend, S> Layer<S> for SyncLayer<B>
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
 {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if SYNC_ENABLED.load(Ordering::Relaxed) && attrs.fields().field("sync_burn").is_some() {
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(SpanStart(Instant::now()));
+            }
+        }
+    }
+
     fn on_close(&self, id: tracing::span::Id, ctx: Context<'_, S>) {
         if SYNC_ENABLED.load(Ordering::Relaxed) {
             let metadata = ctx.metadata(&id).expect("Span ID invalid");
 
             if metadata.is_span() && metadata.fields().field("sync_burn").is_some() {
-                let _span = info_span!("GPU Wait", name = metadata.name()).entered();
-                // TODO: Need something that works on wasm.
-                B::sync(&self.device);
+                {
+                    let _span = info_span!("GPU Wait", name = metadata.name()).entered();
+                    // TODO: Need something that works on wasm.
+                    B::sync(&self.device);
+                }
+
+                // The previous synced span waited for the GPU to finish, so this is roughly the
+                // time the kernels of this span took.
+                let span = ctx.span(&id).expect("Span ID invalid");
+                if let Some(start) = span.extensions().get::<SpanStart>() {
+                    record_timing(metadata.name(), start.0.elapsed());
+                }
             }
         }
     }
@@ -42,6 +80,13 @@ pub fn is_enabled() -> bool {
     SYNC_ENABLED.load(Ordering::Relaxed)
 }
 
+/// Take the time spent in every synced span since this was last called, summed per span name.
+///
+/// Timings are only recorded while syncing is enabled, see [`set_enabled`].
+pub fn take_timings() -> Vec<(&'static str, Duration)> {
+    std::mem::take(&mut *TIMINGS.lock().expect("Timings lock poisoned"))
+}
+
 pub fn set_enabled(enabled: bool) {
     SYNC_ENABLED.store(enabled, Ordering::Relaxed);
 }